@@ -0,0 +1,381 @@
+use std::{
+    fmt,
+    path::Path,
+    process::{Output, Stdio},
+};
+
+use serde::Serialize;
+use tokio::{
+    fs,
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    process::Command,
+};
+use tracing::{info, warn};
+
+use crate::{config::Config, outbound, progress::ProgressHandle};
+
+/// Quantos bytes finais da saída do aria2c devolvemos ao cliente em caso de erro.
+const OUTPUT_TAIL_BYTES: usize = 2048;
+
+/// Tamanho máximo do log persistido por download (mantemos o final).
+const LOG_MAX_BYTES: usize = 64 * 1024;
+
+/// Como o aria2c deve descobrir peers numa tentativa.
+#[derive(Debug, Clone, Copy)]
+enum PeerSource<'a> {
+    Trackers(&'a [String]),
+    DhtOnly,
+}
+
+impl fmt::Display for PeerSource<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerSource::Trackers(t) => write!(f, "{} trackers", t.len()),
+            PeerSource::DhtOnly => f.write_str("somente DHT"),
+        }
+    }
+}
+
+/// Código de saída do aria2c quando falta espaço em disco.
+const EXIT_DISK_FULL: i32 = 9;
+
+/// Por que o download falhou, para o cliente decidir o que mostrar sem
+/// conhecer os códigos de saída do aria2c.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    /// O `aria2c` não está no `PATH`.
+    EngineMissing,
+    Cancelled,
+    /// Sem peers, ou lento demais, até o tempo limite.
+    Timeout,
+    NotFound,
+    Network,
+    DiskFull,
+    /// Falha ao criar, abrir, gravar ou renomear os arquivos.
+    Storage,
+    /// `.torrent` ou magnet ilegível.
+    InvalidTorrent,
+    /// O arquivo baixado não tem o tamanho que o torrent diz.
+    SizeMismatch,
+    /// O download parou no meio.
+    Interrupted,
+    Unknown,
+}
+
+impl Reason {
+    /// Pelos códigos de saída documentados no manual do aria2c.
+    pub fn from_exit_code(code: i32) -> Self {
+        match code {
+            2 | 5 => Reason::Timeout,
+            3 | 4 => Reason::NotFound,
+            6 | 19 | 29 => Reason::Network,
+            EXIT_DISK_FULL => Reason::DiskFull,
+            13..=18 => Reason::Storage,
+            20 | 25..=27 => Reason::InvalidTorrent,
+            7 => Reason::Interrupted,
+            _ => Reason::Unknown,
+        }
+    }
+}
+
+/// Falha da última tentativa de download.
+#[derive(Debug)]
+pub struct DownloadFailure {
+    pub exit_code: Option<i32>,
+    pub reason: Reason,
+    pub output_tail: String,
+    pub summary: Option<Summary>,
+}
+
+impl DownloadFailure {
+    /// Falha sem código de saída (o aria2c não chegou a terminar).
+    pub fn new(reason: Reason, output_tail: impl Into<String>) -> Self {
+        DownloadFailure { exit_code: None, reason, output_tail: output_tail.into(), summary: None }
+    }
+}
+
+impl fmt::Display for DownloadFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.exit_code {
+            Some(code) => write!(f, "aria2c saiu com código {code}")?,
+            None => f.write_str("download não concluído")?,
+        }
+        if let Some(summary) = &self.summary {
+            write!(f, " ({summary})")?;
+        }
+        if !self.output_tail.is_empty() {
+            write!(f, ": {}", self.output_tail)?;
+        }
+        Ok(())
+    }
+}
+
+/// Última leitura de progresso impressa pelo aria2c, no formato
+/// `[#gid 400.0KiB/33.2MiB(1%) CN:44 SD:5 DL:115.7KiB ETA:4m49s]`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Summary {
+    pub progress: Option<String>,
+    pub percent: Option<u8>,
+    pub connections: Option<u32>,
+    pub seeders: Option<u32>,
+    pub speed: Option<String>,
+    pub eta: Option<String>,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(p) = &self.progress {
+            parts.push(p.clone());
+        }
+        if let Some(sd) = self.seeders {
+            parts.push(format!("seeders {sd}"));
+        }
+        if let Some(cn) = self.connections {
+            parts.push(format!("conexões {cn}"));
+        }
+        if let Some(dl) = &self.speed {
+            parts.push(format!("{dl}/s"));
+        }
+        f.write_str(&parts.join(", "))
+    }
+}
+
+/// Extrai a última linha de progresso de uma saída do aria2c.
+pub fn parse_summary(output: &str) -> Option<Summary> {
+    let line = output
+        .lines()
+        .rev()
+        .map(str::trim)
+        .find(|l| l.starts_with("[#") && l.ends_with(']'))?;
+    let inner = &line[2..line.len() - 1];
+
+    let mut summary = Summary::default();
+    for token in inner.split_whitespace().skip(1) {
+        if let Some(v) = token.strip_prefix("CN:") {
+            summary.connections = v.parse().ok();
+        } else if let Some(v) = token.strip_prefix("SD:") {
+            summary.seeders = v.parse().ok();
+        } else if let Some(v) = token.strip_prefix("DL:") {
+            summary.speed = Some(v.to_string());
+        } else if let Some(v) = token.strip_prefix("ETA:") {
+            summary.eta = Some(v.to_string());
+        } else if token.contains('/') && summary.progress.is_none() {
+            summary.percent = token
+                .split_once('(')
+                .and_then(|(_, p)| p.strip_suffix("%)"))
+                .and_then(|p| p.parse().ok());
+            summary.progress = Some(token.to_string());
+        }
+    }
+    Some(summary)
+}
+
+/// Baixa `filename` de `uri` (link magnet ou caminho de um `.torrent`) para
+/// `dir`. Tenta primeiro com os trackers principais, depois com a lista
+/// secundária e, por último, só com DHT.
+/// A saída de cada tentativa é acrescentada em `log_path`. Os resumos do
+/// aria2c alimentam os peers de `progress`, e o `cancel` dele mata o aria2c
+/// em execução e encerra sem novas tentativas.
+pub async fn download(
+    config: &Config,
+    dir: &Path,
+    filename: &str,
+    uri: &str,
+    log_path: &Path,
+    progress: &ProgressHandle,
+) -> Result<(), DownloadFailure> {
+    let cancel = &progress.cancel;
+    let mut attempts = Vec::with_capacity(3);
+    if !config.bt_trackers.is_empty() {
+        attempts.push(PeerSource::Trackers(&config.bt_trackers));
+    }
+    if !config.bt_trackers_fallback.is_empty() {
+        attempts.push(PeerSource::Trackers(&config.bt_trackers_fallback));
+    }
+    attempts.push(PeerSource::DhtOnly);
+
+    let total = attempts.len();
+    let mut last = None;
+    for (i, source) in attempts.into_iter().enumerate() {
+        let attempt = i + 1;
+        info!(attempt, total, %source, filename, "iniciando aria2c");
+        let result = run(config, dir, filename, uri, source, progress).await;
+        let output = match &result {
+            Ok(output) | Err((_, Some(output))) => Some(output),
+            Err((_, None)) => None,
+        };
+        let header = format!("=== tentativa {attempt}/{total} ({source}) ===");
+        if let Err(e) = append_log(log_path, &header, output).await {
+            warn!(path = %log_path.display(), "falha ao gravar log do aria2c: {e}");
+        }
+        match result {
+            Ok(_) => {
+                info!(attempt, %source, filename, "aria2c concluiu");
+                return Ok(());
+            }
+            Err((failure, _)) if cancel.is_cancelled() => {
+                info!(attempt, filename, "aria2c cancelado");
+                return Err(failure);
+            }
+            Err((failure, _)) => {
+                warn!(attempt, %source, filename, "{failure}");
+                last = Some(failure);
+            }
+        }
+    }
+
+    Err(last.expect("ao menos uma tentativa (DHT) sempre existe"))
+}
+
+async fn run(
+    config: &Config,
+    dir: &Path,
+    filename: &str,
+    uri: &str,
+    source: PeerSource<'_>,
+    progress: &ProgressHandle,
+) -> Result<Output, (DownloadFailure, Option<Output>)> {
+    let mut cmd = Command::new("aria2c");
+    cmd.kill_on_drop(true);
+    cmd.arg("--dir")
+        .arg(dir)
+        .arg("--out")
+        .arg(filename)
+        .arg("--seed-time=0")
+        .arg(uri)
+        .arg("--enable-dht=true")
+        .arg("--enable-peer-exchange=true")
+        // sem pré-alocação o tamanho em disco reflete o que já foi baixado,
+        // e o .aria2 salvo com frequência alimenta a estimativa de progresso
+        .arg(format!("--file-allocation={}", config.aria2_file_allocation))
+        .arg("--auto-save-interval=5")
+        // sem terminal o resumo (com peers e seeders) só sai a cada minuto
+        .arg("--summary-interval=5")
+        // um `.partial` sem `.aria2` não é retomável: recomeça no mesmo nome
+        // em vez de o aria2c criar `<nome>.1.partial`
+        .arg("--allow-overwrite=true");
+    if config.sequential_downloads {
+        // peças em ordem, com o começo e o fim do arquivo antes (cabeçalho e
+        // índices do MP4/MKV): o `progressive=1` começa a tocar em segundos
+        cmd.arg("--stream-piece-selector=inorder").arg("--bt-prioritize-piece=head,tail");
+    }
+    if let Some(proxy) = outbound::aria2_proxy(config) {
+        cmd.arg(format!("--all-proxy={proxy}"));
+    }
+    if let PeerSource::Trackers(trackers) = source {
+        cmd.arg(format!("--bt-tracker={}", trackers.join(",")));
+    }
+
+    cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let output = tokio::select! {
+        output = watch_output(cmd, progress) => output,
+        _ = progress.cancel.cancelled() => {
+            return Err((DownloadFailure::new(Reason::Cancelled, "download cancelado"), None));
+        }
+    };
+    let output = output.map_err(|e| {
+        let failure = match e.kind() {
+            std::io::ErrorKind::NotFound => DownloadFailure::new(Reason::EngineMissing, "aria2c não encontrado no PATH"),
+            _ => DownloadFailure::new(Reason::Unknown, e.to_string()),
+        };
+        (failure, None)
+    })?;
+
+    if output.status.success() {
+        return Ok(output);
+    }
+    let failure = DownloadFailure {
+        exit_code: output.status.code(),
+        // morto por sinal: sem código
+        reason: output.status.code().map_or(Reason::Interrupted, Reason::from_exit_code),
+        output_tail: output_tail(&output),
+        summary: parse_summary(&String::from_utf8_lossy(&output.stdout)),
+    };
+    Err((failure, Some(output)))
+}
+
+/// Roda o aria2c guardando a saída, como `Command::output`, e repassa os
+/// peers de cada linha de resumo enquanto ele roda.
+async fn watch_output(mut cmd: Command, progress: &ProgressHandle) -> std::io::Result<Output> {
+    let mut child = cmd.spawn()?;
+    let stdout = child.stdout.take().expect("stdout com pipe");
+    let mut stderr = child.stderr.take().expect("stderr com pipe");
+
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    let read_out = async {
+        let mut lines = BufReader::new(stdout).split(b'\n');
+        while let Some(line) = lines.next_segment().await? {
+            if let Some(summary) = parse_summary(&String::from_utf8_lossy(&line)) {
+                progress.set_swarm(summary.connections, summary.seeders);
+            }
+            out.extend_from_slice(&line);
+            out.push(b'\n');
+        }
+        Ok::<_, std::io::Error>(())
+    };
+    let (read_out, read_err) = tokio::join!(read_out, stderr.read_to_end(&mut err));
+    read_out?;
+    read_err?;
+    let status = child.wait().await?;
+    Ok(Output { status, stdout: out, stderr: err })
+}
+
+/// Acrescenta a saída de uma tentativa ao log, mantendo só os últimos
+/// `LOG_MAX_BYTES`.
+async fn append_log(path: &Path, header: &str, output: Option<&Output>) -> std::io::Result<()> {
+    let mut chunk = Vec::new();
+    chunk.extend_from_slice(header.as_bytes());
+    chunk.push(b'\n');
+    if let Some(output) = output {
+        chunk.extend_from_slice(&output.stdout);
+        if !output.stderr.is_empty() {
+            chunk.extend_from_slice(b"--- stderr ---\n");
+            chunk.extend_from_slice(&output.stderr);
+        }
+        if let Some(code) = output.status.code() {
+            chunk.extend_from_slice(format!("--- exit code {code} ---\n").as_bytes());
+        }
+    }
+
+    append_to_log(path, &chunk).await
+}
+
+/// Acrescenta `chunk` ao log do download, mantendo só os últimos
+/// `LOG_MAX_BYTES`.
+pub async fn append_to_log(path: &Path, chunk: &[u8]) -> std::io::Result<()> {
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path).await?;
+    file.write_all(chunk).await?;
+    let len = file.metadata().await?.len() as usize;
+    drop(file);
+
+    if len > LOG_MAX_BYTES {
+        let data = fs::read(path).await?;
+        let mut start = data.len() - LOG_MAX_BYTES;
+        // recomeça numa linha inteira
+        if let Some(nl) = data[start..].iter().position(|&b| b == b'\n') {
+            start += nl + 1;
+        }
+        fs::write(path, &data[start..]).await?;
+    }
+    Ok(())
+}
+
+/// Final do stderr, ou do stdout quando o stderr vem vazio (o aria2c
+/// costuma reportar erros de download no stdout).
+fn output_tail(output: &Output) -> String {
+    let raw = if output.stderr.iter().any(|b| !b.is_ascii_whitespace()) {
+        &output.stderr
+    } else {
+        &output.stdout
+    };
+    let text = String::from_utf8_lossy(raw);
+    let text = text.trim();
+    let mut start = text.len().saturating_sub(OUTPUT_TAIL_BYTES);
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    text[start..].to_string()
+}
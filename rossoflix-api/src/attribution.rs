@@ -0,0 +1,81 @@
+use std::collections::BTreeSet;
+
+use axum::{Json, response::IntoResponse};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Serviço de terceiros cujos dados aparecem numa resposta.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Tmdb,
+    Omdb,
+    OpenSubtitles,
+}
+
+/// Item de `attribution` nas respostas.
+#[derive(Debug, Serialize)]
+struct Credit {
+    source: Source,
+    name: &'static str,
+    url: &'static str,
+}
+
+/// Item de `GET /attribution`: o crédito com o aviso completo.
+#[derive(Debug, Serialize)]
+struct Notice {
+    #[serde(flatten)]
+    credit: Credit,
+    notice: &'static str,
+}
+
+impl Source {
+    const ALL: [Source; 3] = [Source::Tmdb, Source::Omdb, Source::OpenSubtitles];
+
+    /// A partir da etiqueta de `sources` do detalhe combinado (`metadata`).
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        match tag {
+            "tmdb" => Some(Source::Tmdb),
+            "omdb" => Some(Source::Omdb),
+            _ => None,
+        }
+    }
+
+    fn credit(self) -> Credit {
+        let (name, url) = match self {
+            Source::Tmdb => ("TMDB", "https://www.themoviedb.org"),
+            Source::Omdb => ("OMDb API", "https://www.omdbapi.com"),
+            Source::OpenSubtitles => ("OpenSubtitles", "https://www.opensubtitles.com"),
+        };
+        Credit { source: self, name, url }
+    }
+
+    fn notice(self) -> &'static str {
+        match self {
+            Source::Tmdb => "This product uses the TMDB API but is not endorsed or certified by TMDB.",
+            Source::Omdb => "Dados de filmes e séries do OMDb API, licenciados sob CC BY-NC 4.0.",
+            Source::OpenSubtitles => "Legendas fornecidas pelo OpenSubtitles.com.",
+        }
+    }
+}
+
+/// Põe em `value` (um objeto) o `attribution` com as fontes que de fato
+/// forneceram dados para ele, sem repetição e em ordem fixa. Quem monta a
+/// resposta diz o que usou; respostas em cache levam a atribuição junto.
+pub fn annotate(value: &mut Value, sources: impl IntoIterator<Item = Source>) {
+    let sources: BTreeSet<Source> = sources.into_iter().collect();
+    let credits: Vec<Credit> = sources.into_iter().map(Source::credit).collect();
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert("attribution".into(), serde_json::to_value(credits).unwrap_or_default());
+    }
+}
+
+/// `GET /attribution` — avisos completos de todas as fontes de terceiros,
+/// para a tela de créditos do cliente.
+pub async fn attribution() -> impl IntoResponse {
+    let notices: Vec<Notice> = Source::ALL
+        .into_iter()
+        .map(|source| Notice { credit: source.credit(), notice: source.notice() })
+        .collect();
+    Json(serde_json::json!({ "sources": notices }))
+}
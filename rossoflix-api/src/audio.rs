@@ -0,0 +1,235 @@
+use std::{
+    future::ready,
+    io,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::UNIX_EPOCH,
+};
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use futures_util::{StreamExt, stream};
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+    process::{Child, ChildStdout, Command},
+    sync::OwnedSemaphorePermit,
+};
+use tokio_util::io::ReaderStream;
+use tracing::{info, warn};
+
+use crate::{ApiError, AppState, find_downloaded_file, leases::ReadLease, scratch::SessionGuard};
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    #[default]
+    Aac,
+    Mp3,
+    Opus,
+}
+
+impl AudioFormat {
+    /// Encoder, muxer, extensão e Content-Type.
+    fn ffmpeg(self) -> (&'static str, &'static str, &'static str, &'static str) {
+        match self {
+            AudioFormat::Aac => ("aac", "adts", "aac", "audio/aac"),
+            AudioFormat::Mp3 => ("libmp3lame", "mp3", "mp3", "audio/mpeg"),
+            AudioFormat::Opus => ("libopus", "ogg", "opus", "audio/ogg"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AudioParams {
+    filename: String,
+    /// Índice entre as faixas de áudio do arquivo.
+    #[serde(default)]
+    track: usize,
+    #[serde(default)]
+    format: AudioFormat,
+}
+
+/// `GET /media/audio?filename=...&track=0&format=aac|mp3|opus` — só a faixa
+/// de áudio, transcodificada pelo ffmpeg enquanto é enviada (sempre 200,
+/// sem `Range`). O resultado completo fica em cache numa sessão do scratch
+/// (`audio-<chave>`); um pedido abandonado mata o ffmpeg e descarta o parcial.
+pub async fn extract_audio(
+    State(state): State<AppState>,
+    Query(params): Query<AudioParams>,
+) -> Result<Response, ApiError> {
+    let base = &state.config().downloads_dir;
+    let source = find_downloaded_file(base, &params.filename)
+        .await
+        .ok_or_else(|| ApiError::NotFound(format!("{} não encontrado", params.filename)))?;
+    let (codec, muxer, ext, content_type) = params.format.ffmpeg();
+
+    let key = cache_key(&source, params.track, ext).await?;
+    let session = state
+        .scratch
+        .session(&format!("audio-{key}"))
+        .await
+        .map_err(|e| ApiError::Storage(format!("falha ao preparar o scratch: {e}")))?;
+    let cached = session.dir().join(format!("audio.{ext}"));
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+    headers.insert(header::ACCEPT_RANGES, "none".parse().unwrap());
+
+    if let Ok(file) = fs::File::open(&cached).await {
+        if let Ok(meta) = file.metadata().await {
+            headers.insert(header::CONTENT_LENGTH, meta.len().into());
+        }
+        // a sessão fica em uso até o envio terminar
+        let body = ReaderStream::new(file).map(move |chunk| {
+            let _ = &session;
+            chunk
+        });
+        return Ok((StatusCode::OK, headers, Body::from_stream(body)).into_response());
+    }
+
+    let permit = state
+        .audio_extractions
+        .clone()
+        .try_acquire_owned()
+        .map_err(|_| ApiError::Unavailable("extrações de áudio demais em andamento".into()))?;
+    let lease = state
+        .leases
+        .read(&source)
+        .map_err(|busy| ApiError::Conflict(format!("{} está sendo removido", busy.0.display())))?;
+
+    let mut child = Command::new("ffmpeg")
+        .args(["-v", "error", "-nostdin", "-i"])
+        .arg(&source)
+        .args(["-map", &format!("0:a:{}", params.track), "-vn", "-c:a", codec, "-f", muxer, "pipe:1"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| ApiError::Unavailable(format!("ffmpeg indisponível: {e}")))?;
+    let mut stdout = ReaderStream::new(child.stdout.take().expect("stdout em pipe"));
+
+    // faixa inexistente ou arquivo ilegível: o ffmpeg sai sem escrever nada
+    let first = match stdout.next().await {
+        Some(Ok(chunk)) => chunk,
+        Some(Err(e)) => return Err(ApiError::Storage(format!("falha ao ler o ffmpeg: {e}"))),
+        None => {
+            let mut stderr = String::new();
+            if let Some(mut pipe) = child.stderr.take() {
+                let _ = pipe.read_to_string(&mut stderr).await;
+            }
+            let _ = child.wait().await;
+            return Err(ApiError::BadRequest(format!(
+                "não foi possível extrair a faixa {}: {}",
+                params.track,
+                stderr.trim()
+            )));
+        }
+    };
+
+    let tmp = cached.with_extension(format!("{ext}.part"));
+    let mut cache = fs::File::create(&tmp).await.ok();
+    if let Some(file) = &mut cache
+        && file.write_all(&first).await.is_err()
+    {
+        cache = None;
+    }
+    info!(source = %source.display(), track = params.track, format = ext, "extraindo áudio");
+
+    let extraction = Extraction {
+        stdout,
+        child,
+        cache,
+        partial: PartialFile(Some(tmp)),
+        target: cached,
+        _permit: permit,
+        _lease: lease,
+        _session: session,
+    };
+    let rest = stream::unfold(Some(extraction), |extraction| async move {
+        let mut ex = extraction?;
+        match ex.stdout.next().await {
+            Some(Ok(chunk)) => {
+                if let Some(file) = &mut ex.cache
+                    && file.write_all(&chunk).await.is_err()
+                {
+                    ex.cache = None;
+                }
+                Some((Ok(chunk), Some(ex)))
+            }
+            Some(Err(e)) => Some((Err(e), None)),
+            None => {
+                ex.finish().await;
+                None
+            }
+        }
+    });
+    let body = stream::once(ready(Ok::<_, io::Error>(first))).chain(rest);
+
+    Ok((StatusCode::OK, headers, Body::from_stream(body)).into_response())
+}
+
+/// ffmpeg em andamento. Descartado antes do fim (cliente desconectou), o
+/// processo é morto (`kill_on_drop`) e o arquivo parcial, apagado.
+struct Extraction {
+    stdout: ReaderStream<ChildStdout>,
+    child: Child,
+    cache: Option<fs::File>,
+    partial: PartialFile,
+    target: PathBuf,
+    _permit: OwnedSemaphorePermit,
+    _lease: ReadLease,
+    _session: SessionGuard,
+}
+
+impl Extraction {
+    async fn finish(&mut self) {
+        let status = self.child.wait().await;
+        if !status.as_ref().is_ok_and(|s| s.success()) {
+            warn!(target = %self.target.display(), "ffmpeg terminou com erro: {status:?}");
+            return;
+        }
+        let Some(mut file) = self.cache.take() else {
+            return;
+        };
+        let Some(tmp) = self.partial.0.take() else {
+            return;
+        };
+        if file.flush().await.is_ok() && fs::rename(&tmp, &self.target).await.is_ok() {
+            info!(target = %self.target.display(), "áudio extraído em cache");
+        } else {
+            let _ = fs::remove_file(&tmp).await;
+        }
+    }
+}
+
+struct PartialFile(Option<PathBuf>);
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        if let Some(path) = self.0.take() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Chave do cache: arquivo (caminho, tamanho, mtime), faixa e formato.
+async fn cache_key(source: &Path, track: usize, ext: &str) -> Result<String, ApiError> {
+    let meta = fs::metadata(source)
+        .await
+        .map_err(|e| ApiError::Storage(format!("falha ao ler {}: {e}", source.display())))?;
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let digest = Sha1::digest(format!("{}\n{}\n{mtime}\n{track}\n{ext}", source.display(), meta.len()));
+    Ok(digest.iter().map(|b| format!("{b:02x}")).collect())
+}
@@ -0,0 +1,191 @@
+use std::{
+    net::SocketAddr,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, Query, Request, State},
+    http::Method,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{ApiError, AppState, auth, middleware};
+
+/// Maior `limit` aceito em `/admin/audit`.
+const MAX_LIMIT: u32 = 1000;
+/// Parâmetros de query que não vão para a trilha.
+const REDACTED_PARAMS: [&str; 4] = ["sig", "token", "apikey", "api_key"];
+
+#[derive(Debug, Serialize)]
+pub struct AuditEntry {
+    id: i64,
+    /// Unix timestamp (s).
+    at: i64,
+    request_id: Option<String>,
+    /// Veja `auth::fingerprint`; `None` quando o pedido não trouxe token.
+    token_fingerprint: Option<String>,
+    /// Pedido com acesso de admin (token, grupo do proxy ou `AUTH_MODE=none`).
+    admin: bool,
+    /// Usuário do proxy reverso (`AUTH_MODE=proxy_headers`).
+    user: Option<String>,
+    client_ip: Option<String>,
+    method: String,
+    path: String,
+    params: Option<String>,
+    status: u16,
+    duration_ms: u64,
+}
+
+/// Middleware das rotas `/admin/*`: toda chamada entra na trilha.
+pub async fn audit_all(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    audited(state, req, next).await
+}
+
+/// Middleware da API pública: entram na trilha os `DELETE` e os links de
+/// convidado (`/share`: criação, cada abertura e revogação).
+pub async fn audit_public(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if req.method() != Method::DELETE && !req.uri().path().starts_with("/share") {
+        return next.run(req).await;
+    }
+    audited(state, req, next).await
+}
+
+async fn audited(state: AppState, req: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let token = auth::presented_token(req.headers());
+    let identity = req.extensions().get::<auth::Identity>().cloned().unwrap_or_default();
+    let mut entry = AuditEntry {
+        id: 0,
+        at: unix_now(),
+        request_id: middleware::current_request_id(),
+        token_fingerprint: token.map(auth::fingerprint),
+        admin: identity.admin,
+        user: identity.user,
+        client_ip: req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string()),
+        method: req.method().to_string(),
+        path: req.uri().path().to_string(),
+        params: req.uri().query().map(redact),
+        status: 0,
+        duration_ms: 0,
+    };
+
+    let resp = next.run(req).await;
+    entry.status = resp.status().as_u16();
+    entry.duration_ms = started.elapsed().as_millis() as u64;
+    write(&state, entry);
+    resp
+}
+
+/// Grava fora do caminho do pedido e apara a trilha em `AUDIT_MAX_ENTRIES`.
+fn write(state: &AppState, entry: AuditEntry) {
+    let db = state.db.clone();
+    let max_entries = state.config().audit_max_entries as i64;
+    tokio::spawn(async move {
+        let written = db
+            .call(move |conn| {
+                let id = conn.query_row(
+                    "INSERT INTO audit_log
+                     (at, request_id, token_fingerprint, admin, user, client_ip, method, path, params, status, duration_ms)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11) RETURNING id",
+                    params![
+                        entry.at,
+                        entry.request_id,
+                        entry.token_fingerprint,
+                        entry.admin,
+                        entry.user,
+                        entry.client_ip,
+                        entry.method,
+                        entry.path,
+                        entry.params,
+                        entry.status,
+                        entry.duration_ms,
+                    ],
+                    |row| row.get::<_, i64>(0),
+                )?;
+                conn.execute("DELETE FROM audit_log WHERE id <= ?1", params![id - max_entries])
+            })
+            .await;
+        if let Err(e) = written {
+            warn!("auditoria: falha ao gravar: {e}");
+        }
+    });
+}
+
+/// Query com os valores sensíveis trocados por `***`.
+fn redact(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if REDACTED_PARAMS.contains(&key.to_ascii_lowercase().as_str()) => format!("{key}=***"),
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditParams {
+    /// Só entradas a partir deste Unix timestamp (s).
+    since: Option<i64>,
+    #[serde(default = "default_limit")]
+    limit: u32,
+}
+
+fn default_limit() -> u32 {
+    100
+}
+
+/// `GET /admin/audit?since=&limit=` — trilha de auditoria, da mais recente
+/// à mais antiga.
+pub async fn list_audit(
+    State(state): State<AppState>,
+    Query(params): Query<AuditParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !(1..=MAX_LIMIT).contains(&params.limit) {
+        return Err(ApiError::BadRequest(format!("limit deve estar entre 1 e {MAX_LIMIT}")));
+    }
+    let since = params.since.unwrap_or(0);
+    let limit = params.limit;
+    let entries = state
+        .db
+        .call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, at, request_id, token_fingerprint, admin, user, client_ip, method, path, params, status, duration_ms
+                 FROM audit_log WHERE at >= ?1 ORDER BY id DESC LIMIT ?2",
+            )?;
+            stmt.query_map(params![since, limit], |row| {
+                Ok(AuditEntry {
+                    id: row.get(0)?,
+                    at: row.get(1)?,
+                    request_id: row.get(2)?,
+                    token_fingerprint: row.get(3)?,
+                    admin: row.get(4)?,
+                    user: row.get(5)?,
+                    client_ip: row.get(6)?,
+                    method: row.get(7)?,
+                    path: row.get(8)?,
+                    params: row.get(9)?,
+                    status: row.get(10)?,
+                    duration_ms: row.get(11)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await?;
+    Ok(Json(serde_json::json!({ "entries": entries })))
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
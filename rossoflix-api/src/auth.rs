@@ -0,0 +1,306 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts, Request, State},
+    http::{HeaderMap, Method, header, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+
+use crate::{ApiError, AppState, config::Config, profiles, signing};
+
+/// Como o servidor sabe quem está pedindo (`AUTH_MODE`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMode {
+    /// Só o `ADMIN_TOKEN` distingue o admin; os demais são anônimos.
+    Token,
+    /// O proxy reverso (Authelia, oauth2-proxy...) já autenticou: o usuário
+    /// e os grupos vêm dos cabeçalhos, aceitos só de `TRUSTED_PROXIES`.
+    ProxyHeaders,
+    /// Sem autenticação: todo pedido é admin. Só para redes confiáveis.
+    None,
+}
+
+impl FromStr for AuthMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('-', "_").as_str() {
+            "token" => Ok(AuthMode::Token),
+            "proxy_headers" | "proxy" => Ok(AuthMode::ProxyHeaders),
+            "none" | "off" => Ok(AuthMode::None),
+            other => Err(format!("esperado token, proxy_headers ou none, veio {other}")),
+        }
+    }
+}
+
+/// Tamanho mínimo de uma chave de API.
+const MIN_API_KEY_LEN: usize = 16;
+/// Rota aberta mesmo com `API_KEYS`: o health check do balanceador.
+const OPEN_PATH: &str = "/health";
+
+/// Chave de API (`API_KEYS` ou `[api_keys]` do `rossoflix.toml`). O nome
+/// identifica o cliente nos limites por cliente; a chave não aparece.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    pub name: String,
+    key: String,
+}
+
+impl ApiKey {
+    pub fn new(name: &str, key: &str) -> Result<Self, String> {
+        let key = key.trim();
+        if key.len() < MIN_API_KEY_LEN {
+            return Err(format!("chave curta demais (mínimo {MIN_API_KEY_LEN} caracteres)"));
+        }
+        let name = match name.trim() {
+            "" => fingerprint(key),
+            name => name.to_string(),
+        };
+        Ok(ApiKey { name, key: key.to_string() })
+    }
+}
+
+/// `nome:chave`, ou só a chave (o nome vira a impressão digital dela).
+impl FromStr for ApiKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((name, key)) => ApiKey::new(name, key),
+            None => ApiKey::new("", s),
+        }
+    }
+}
+
+/// Faixa de endereços (`10.0.0.0/8`, `::1/128`); um IP sozinho vale só
+/// para ele.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 mapeado em IPv6 (`::ffff:10.0.0.1`) conta como IPv4
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => same_prefix(u32::from(net).into(), u32::from(ip).into(), 32, self.prefix),
+            (IpAddr::V6(net), IpAddr::V6(ip)) => same_prefix(net.into(), ip.into(), 128, self.prefix),
+            _ => false,
+        }
+    }
+}
+
+fn same_prefix(a: u128, b: u128, bits: u8, prefix: u8) -> bool {
+    prefix == 0 || (a ^ b) >> (bits - prefix) == 0
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = s.split_once('/').map_or((s, None), |(a, p)| (a, Some(p)));
+        let network: IpAddr = addr.trim().parse().map_err(|_| format!("endereço inválido: {addr}"))?;
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p.trim().parse::<u8>().ok().filter(|p| *p <= bits).ok_or_else(|| format!("prefixo inválido: {p}"))?,
+            None => bits,
+        };
+        Ok(Cidr { network, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// Quem fez o pedido, resolvido uma vez por [`identify`] e lido pelos
+/// handlers como extrator.
+#[derive(Debug, Clone, Default)]
+pub struct Identity {
+    /// Usuário autenticado pelo proxy; `None` fora do modo `proxy_headers`
+    /// ou quando o pedido não veio de um proxy confiável.
+    pub user: Option<String>,
+    pub groups: Vec<String>,
+    pub admin: bool,
+    /// Nome da chave de API válida que veio no pedido.
+    pub api_key: Option<String>,
+}
+
+impl Identity {
+    /// Perfil do pedido (watchlist, calendário, marcadores). Um usuário do
+    /// proxy usa o próprio perfil sem precisar mandar `profile`; outro
+    /// perfil só com admin.
+    pub fn profile(&self, requested: &str) -> Result<String, ApiError> {
+        match &self.user {
+            None => Ok(requested.to_string()),
+            Some(user) if requested.is_empty() || requested == user => Ok(user.clone()),
+            Some(_) if self.admin => Ok(requested.to_string()),
+            Some(user) => Err(ApiError::Forbidden(format!("{user} não pode usar o perfil {requested}"))),
+        }
+    }
+
+    /// Chave dos limites por cliente: o usuário quando há um, senão a
+    /// chave de API, senão o IP.
+    pub fn client_key(&self, ip: IpAddr) -> String {
+        match (&self.user, &self.api_key) {
+            (Some(user), _) => format!("user:{user}"),
+            (None, Some(key)) => format!("key:{key}"),
+            (None, None) => format!("ip:{ip}"),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Identity {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<Identity>().cloned().unwrap_or_default())
+    }
+}
+
+/// Identidade pelo `AUTH_MODE`. Os cabeçalhos do proxy só valem quando a
+/// conexão vem de `TRUSTED_PROXIES`; de qualquer outro lugar são ignorados,
+/// para ninguém se passar por outro usuário mandando `Remote-User`. O
+/// `ADMIN_TOKEN` continua valendo em todos os modos.
+pub fn resolve(headers: &HeaderMap, peer: Option<IpAddr>, config: &Config) -> Identity {
+    let token_admin = is_admin(headers, config);
+    match config.auth_mode {
+        AuthMode::None => Identity { admin: true, ..Default::default() },
+        AuthMode::Token => Identity { admin: token_admin, ..Default::default() },
+        AuthMode::ProxyHeaders => {
+            let trusted = peer.is_some_and(|ip| config.trusted_proxies.iter().any(|c| c.contains(ip)));
+            let value = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim).filter(|v| !v.is_empty());
+            let Some(user) = value(&config.auth_user_header).filter(|_| trusted) else {
+                return Identity { admin: token_admin, ..Default::default() };
+            };
+            let groups: Vec<String> = value(&config.auth_groups_header)
+                .into_iter()
+                .flat_map(|v| v.split(','))
+                .map(str::trim)
+                .filter(|g| !g.is_empty())
+                .map(String::from)
+                .collect();
+            let admin = token_admin || groups.contains(&config.auth_admin_group);
+            Identity { user: Some(user.to_string()), groups, admin, ..Default::default() }
+        }
+    }
+}
+
+/// Resolve a [`Identity`] do pedido e a deixa nas extensões; um usuário do
+/// proxy visto pela primeira vez ganha o perfil.
+pub async fn identify(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    let peer = req.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip());
+    let config = state.config();
+    let mut identity = resolve(req.headers(), peer, &config);
+    identity.api_key = presented_api_key(&req)
+        .and_then(|given| config.api_keys.iter().find(|k| constant_time_eq(given.as_bytes(), k.key.as_bytes())))
+        .map(|k| k.name.clone());
+    if let Some(user) = &identity.user {
+        profiles::remember(&state, user, &identity.groups);
+    }
+    req.extensions_mut().insert(identity);
+    next.run(req).await
+}
+
+/// Rotas de admin: no modo `proxy_headers`, só para quem está no grupo
+/// `AUTH_ADMIN_GROUP` (ou traz o `ADMIN_TOKEN`). No modo `token`, com um
+/// `ADMIN_BIND_ADDR` a proteção é o listener separado; sem ele as rotas
+/// ficam no listener público e exigem o `ADMIN_TOKEN`.
+pub async fn require_admin(State(state): State<AppState>, identity: Identity, req: Request, next: Next) -> Response {
+    let config = state.config();
+    if identity.admin {
+        return next.run(req).await;
+    }
+    match config.auth_mode {
+        AuthMode::ProxyHeaders => {
+            let who = identity.user.as_deref().unwrap_or("anônimo");
+            ApiError::Forbidden(format!("{who} não está no grupo {}", config.auth_admin_group)).into_response()
+        }
+        AuthMode::Token if config.admin_addr.is_none() => {
+            let message = match config.admin_token {
+                Some(_) => "token de admin exigido",
+                None => "rotas de admin no listener público exigem ADMIN_TOKEN (ou use ADMIN_BIND_ADDR)",
+            };
+            ApiError::Unauthorized(message.into()).into_response()
+        }
+        _ => next.run(req).await,
+    }
+}
+
+/// Com `API_KEYS`, todo pedido precisa de uma chave válida, menos o
+/// health check, o `/stream` com assinatura válida (players que não mandam
+/// cabeçalhos), a abertura de um link de convidado e quem já é admin ou
+/// usuário do proxy.
+pub async fn require_api_key(State(state): State<AppState>, identity: Identity, req: Request, next: Next) -> Response {
+    let config = state.config();
+    let path = req.uri().path();
+    if config.api_keys.is_empty()
+        || identity.api_key.is_some()
+        || identity.admin
+        || identity.user.is_some()
+        || path == OPEN_PATH
+        || (path == "/stream" && signing::verified_query(&config, req.uri()))
+        || (req.method() == Method::GET && is_share_link(path))
+    {
+        return next.run(req).await;
+    }
+    let message = match presented_api_key(&req) {
+        Some(_) => "chave de API inválida",
+        None => "informe a chave de API em X-Api-Key",
+    };
+    ApiError::Unauthorized(message.into()).into_response()
+}
+
+/// `/share/<id>`: o id é a credencial, e `share::check_active` confere
+/// validade e revogação no handler.
+fn is_share_link(path: &str) -> bool {
+    path.strip_prefix("/share/").is_some_and(|id| !id.is_empty() && !id.contains('/'))
+}
+
+/// Chave de API mandada no pedido, em `X-Api-Key`. Nunca na query, que
+/// acaba nos logs de acesso.
+fn presented_api_key(req: &Request) -> Option<&str> {
+    req.headers().get("x-api-key").and_then(|v| v.to_str().ok()).map(str::trim)
+}
+
+/// O pedido traz o token de admin (`Authorization: Bearer <token>` ou
+/// `X-Admin-Token`)? Sem `ADMIN_TOKEN` configurado, ninguém é admin pelo token.
+fn is_admin(headers: &HeaderMap, config: &Config) -> bool {
+    let Some(expected) = config.admin_token.as_deref() else {
+        return false;
+    };
+    presented_token(headers).is_some_and(|given| constant_time_eq(given.as_bytes(), expected.as_bytes()))
+}
+
+/// Token mandado no pedido, válido ou não.
+pub fn presented_token(headers: &HeaderMap) -> Option<&str> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let explicit = headers.get("x-admin-token").and_then(|v| v.to_str().ok());
+    bearer.or(explicit).map(str::trim)
+}
+
+/// Impressão digital de um token para logs: os primeiros 12 hex do SHA-1,
+/// sem expor o token.
+pub fn fingerprint(token: &str) -> String {
+    Sha1::digest(token.as_bytes()).iter().take(6).map(|b| format!("{b:02x}")).collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,65 @@
+use serde_json::Value;
+
+use crate::{AppState, cache::CacheMode, dates, torrentio};
+
+/// Marca cada item de uma lista com `playable` e `reason`, olhando só o que
+/// já está em cache (nenhuma chamada nova ao upstream):
+///
+/// * `no_imdb_id` — sem IMDb id não há como buscar streams;
+/// * `not_released` — lançamento (TMDB) ou ano (OMDb) no futuro;
+/// * `no_streams_cached` — o torrentio já respondeu sem streams;
+/// * `unknown` — nada em cache; continua `playable`, mas sem confirmação.
+///
+/// Com streams em cache, `reason` é `null`.
+pub async fn enrich(state: &AppState, results: &mut Value) {
+    if !state.config().playable_enrichment {
+        return;
+    }
+    let Some(items) = results.as_array_mut() else {
+        return;
+    };
+    let today = dates::today();
+    for item in items {
+        let (playable, reason) = classify(state, item, &today).await;
+        if let Some(obj) = item.as_object_mut() {
+            obj.insert("playable".into(), playable.into());
+            obj.insert("reason".into(), reason.map_or(Value::Null, Into::into));
+        }
+    }
+}
+
+async fn classify(state: &AppState, item: &Value, today: &str) -> (bool, Option<&'static str>) {
+    let Some(imdb_id) = item.get("imdbID").and_then(Value::as_str) else {
+        return (false, Some("no_imdb_id"));
+    };
+
+    let release_date = item.get("release_date").and_then(Value::as_str).filter(|d| d.len() == 10);
+    let year = item
+        .get("Year")
+        .and_then(Value::as_str)
+        .and_then(|y| y.get(..4))
+        .and_then(|y| y.parse::<u32>().ok());
+    let future = match release_date {
+        Some(date) => date > today,
+        None => year.is_some_and(|y| y.to_string().as_str() > &today[..4]),
+    };
+    if future {
+        return (false, Some("not_released"));
+    }
+
+    match state.cache.get(&torrentio::movie_key(imdb_id), CacheMode::Normal).await {
+        Some(cached) => {
+            let has_streams = cached
+                .value
+                .get("streams")
+                .and_then(Value::as_array)
+                .is_some_and(|s| !s.is_empty());
+            if has_streams {
+                (true, None)
+            } else {
+                (false, Some("no_streams_cached"))
+            }
+        }
+        None => (true, Some("unknown")),
+    }
+}
@@ -0,0 +1,543 @@
+use std::collections::hash_map::RandomState;
+use std::convert::Infallible;
+use std::hash::{BuildHasher, Hasher};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use axum::{
+    Json, async_trait,
+    extract::FromRequestParts,
+    http::{HeaderValue, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use moka::{Expiry, future::Cache};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{AppState, auth, redis, shape::Shape};
+
+/// Variação aleatória do TTL de cada entrada (±15%): listas gravadas juntas
+/// (subida, aquecimento) não expiram todas no mesmo segundo.
+const TTL_JITTER: f64 = 0.15;
+/// Fração final da vida da entrada em que uma leitura pode, com
+/// probabilidade crescente, tratá-la como expirada e buscar de novo antes
+/// do prazo: chaves quentes se renovam sem uma rajada no vencimento.
+const EARLY_REFRESH_WINDOW: f64 = 0.1;
+/// Versão do [`CacheEnvelope`] das entradas gravadas no Redis.
+const REDIS_ENTRY_VERSION: u8 = 1;
+
+/// Valor em cache com o instante de inserção, para calcular o `Age`, e o
+/// TTL efetivo (já com a variação). Relógio de parede: no Redis a entrada
+/// é lida por outras instâncias.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    value: serde_json::Value,
+    inserted_at: SystemTime,
+    ttl: Duration,
+}
+
+impl Entry {
+    fn age(&self) -> Duration {
+        self.inserted_at.elapsed().unwrap_or_default()
+    }
+}
+
+/// Tipo de entrada do cache de respostas, cada um com o seu TTL
+/// (`CACHE_TTL_*`): detalhes quase não mudam, streams do torrentio
+/// envelhecem rápido.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheCategory {
+    Search,
+    /// Detalhes de títulos (OMDb e TMDB) e ids externos.
+    Detail,
+    /// Listas em alta e populares.
+    Trending,
+    /// Streams, catálogos e manifesto do torrentio.
+    Torrentio,
+}
+
+/// Onde as entradas ficam (`CACHE_BACKEND`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheBackend {
+    /// Na memória do processo: some ao reiniciar e não é dividido entre
+    /// instâncias.
+    Memory,
+    /// Num Redis (`REDIS_URL`) compartilhado pelas instâncias.
+    Redis,
+}
+
+impl FromStr for CacheBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "memory" | "moka" => Ok(CacheBackend::Memory),
+            "redis" => Ok(CacheBackend::Redis),
+            other => Err(format!("esperado memory ou redis, veio {other}")),
+        }
+    }
+}
+
+/// Armazenamento das entradas de um [`ResponseCache`]. A expiração pelo
+/// `ttl` da entrada é do armazenamento; falhas dele valem como miss (na
+/// leitura) ou como entrada não gravada, nunca como erro do pedido.
+#[async_trait]
+pub trait CacheStore: Send + Sync {
+    async fn get(&self, key: &str) -> Option<Entry>;
+    async fn insert(&self, key: String, entry: Entry);
+    async fn contains_key(&self, key: &str) -> bool;
+    /// `memory` ou `redis`, para `GET /admin/stats`.
+    fn backend(&self) -> CacheBackend;
+    /// TTL efetivo das entradas atuais, quando dá para percorrê-las.
+    fn ttls(&self) -> Option<Vec<Duration>>;
+    /// Operações que falharam no armazenamento.
+    fn errors(&self) -> u64 {
+        0
+    }
+}
+
+/// Expiração por entrada, pelo `ttl` gravado nela.
+struct EntryTtl;
+
+impl Expiry<String, Entry> for EntryTtl {
+    fn expire_after_create(&self, _key: &String, entry: &Entry, _created_at: Instant) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &String,
+        entry: &Entry,
+        _updated_at: Instant,
+        _duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+}
+
+/// Entradas na memória (moka), até `capacity`.
+pub struct MemoryStore(Cache<String, Entry>);
+
+impl MemoryStore {
+    pub fn new(capacity: u64) -> Self {
+        MemoryStore(Cache::builder().expire_after(EntryTtl).max_capacity(capacity).build())
+    }
+}
+
+#[async_trait]
+impl CacheStore for MemoryStore {
+    async fn get(&self, key: &str) -> Option<Entry> {
+        self.0.get(key).await
+    }
+
+    async fn insert(&self, key: String, entry: Entry) {
+        self.0.insert(key, entry).await;
+    }
+
+    async fn contains_key(&self, key: &str) -> bool {
+        self.0.contains_key(key)
+    }
+
+    fn backend(&self) -> CacheBackend {
+        CacheBackend::Memory
+    }
+
+    fn ttls(&self) -> Option<Vec<Duration>> {
+        Some(self.0.iter().map(|(_, entry)| entry.ttl).collect())
+    }
+}
+
+/// Entradas no Redis, em `prefix` + chave, como [`CacheEnvelope`] em JSON
+/// e com o `ttl` da entrada como expiração (`PX`).
+pub struct RedisStore {
+    client: Arc<redis::Client>,
+    prefix: String,
+    errors: AtomicU64,
+}
+
+impl RedisStore {
+    pub fn new(client: Arc<redis::Client>, prefix: String) -> Self {
+        RedisStore { client, prefix, errors: AtomicU64::new(0) }
+    }
+
+    fn failed(&self, op: &str, key: &str, e: impl std::fmt::Display) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(key, "cache no Redis: {op} falhou: {e}");
+    }
+}
+
+#[async_trait]
+impl CacheStore for RedisStore {
+    async fn get(&self, key: &str) -> Option<Entry> {
+        let bytes = match self.client.get(&format!("{}{key}", self.prefix)).await {
+            Ok(bytes) => bytes?,
+            Err(e) => {
+                self.failed("GET", key, e);
+                return None;
+            }
+        };
+        // relógios de instâncias diferentes podem discordar do `PX`
+        CacheEnvelope::<Entry>::open(&bytes, REDIS_ENTRY_VERSION).filter(|entry| entry.age() < entry.ttl)
+    }
+
+    async fn insert(&self, key: String, entry: Entry) {
+        let bytes = match CacheEnvelope::seal(REDIS_ENTRY_VERSION, &entry) {
+            Ok(bytes) => bytes,
+            Err(e) => return self.failed("serialização", &key, e),
+        };
+        if let Err(e) = self.client.set_px(&format!("{}{key}", self.prefix), &bytes, entry.ttl).await {
+            self.failed("SET", &key, e);
+        }
+    }
+
+    async fn contains_key(&self, key: &str) -> bool {
+        match self.client.exists(&format!("{}{key}", self.prefix)).await {
+            Ok(found) => found,
+            Err(e) => {
+                self.failed("EXISTS", key, e);
+                false
+            }
+        }
+    }
+
+    fn backend(&self) -> CacheBackend {
+        CacheBackend::Redis
+    }
+
+    fn ttls(&self) -> Option<Vec<Duration>> {
+        None
+    }
+
+    fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+}
+
+/// Cache de respostas JSON com TTL base e variação por entrada.
+#[derive(Clone)]
+pub struct ResponseCache {
+    store: Arc<dyn CacheStore>,
+    ttl: Duration,
+    early_refreshes: Arc<AtomicU64>,
+}
+
+/// Estado de um cache para `GET /admin/stats`.
+#[derive(Debug, Serialize)]
+pub struct CacheStats {
+    backend: CacheBackend,
+    /// Entradas atuais; `None` no Redis, que não é percorrido.
+    entries: Option<u64>,
+    ttl_secs: u64,
+    jitter: f64,
+    /// Leituras que anteciparam a renovação de uma entrada.
+    early_refreshes: u64,
+    /// Operações que falharam no armazenamento (Redis fora do ar).
+    backend_errors: u64,
+    /// TTL efetivo das entradas atuais (s): menor, maior e médio.
+    effective_ttl_secs: Option<EffectiveTtl>,
+}
+
+#[derive(Debug, Serialize)]
+struct EffectiveTtl {
+    min: f64,
+    max: f64,
+    mean: f64,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: Duration, store: Arc<dyn CacheStore>) -> Self {
+        ResponseCache {
+            store,
+            ttl,
+            early_refreshes: Default::default(),
+        }
+    }
+
+    /// Em `CacheMode::Refresh` não lê: o chamador busca no upstream e grava.
+    /// Perto do vencimento pode devolver `None` antes da hora (veja
+    /// `EARLY_REFRESH_WINDOW`).
+    pub async fn get(&self, key: &str, mode: CacheMode) -> Option<Fetched> {
+        if mode == CacheMode::Refresh {
+            return None;
+        }
+        let entry = self.store.get(key).await?;
+        let age = entry.age();
+        let window = entry.ttl.mul_f64(EARLY_REFRESH_WINDOW);
+        let into_window = age.saturating_sub(entry.ttl.saturating_sub(window));
+        if !into_window.is_zero() && random_unit() < into_window.as_secs_f64() / window.as_secs_f64() {
+            self.early_refreshes.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(Fetched {
+            value: entry.value,
+            age: Some(age),
+            shape: None,
+        })
+    }
+
+    /// O mesmo cache, gravando com outro TTL base: para passar adiante (como
+    /// ao [`upstream::cached_json`](crate::upstream::cached_json)) o TTL da
+    /// categoria das entradas.
+    pub fn with_ttl(&self, ttl: Duration) -> Self {
+        ResponseCache { ttl, ..self.clone() }
+    }
+
+    pub async fn insert(&self, key: String, value: serde_json::Value) {
+        self.insert_with_ttl(key, value, self.ttl).await;
+    }
+
+    /// Como [`insert`](Self::insert), com outro TTL base (o da categoria da
+    /// entrada). TTL zero não guarda.
+    pub async fn insert_with_ttl(&self, key: String, value: serde_json::Value, ttl: Duration) {
+        if ttl.is_zero() {
+            return;
+        }
+        let jitter = 1.0 + TTL_JITTER * (2.0 * random_unit() - 1.0);
+        let entry = Entry {
+            value,
+            inserted_at: SystemTime::now(),
+            ttl: ttl.mul_f64(jitter),
+        };
+        self.store.insert(key, entry).await;
+    }
+
+    pub async fn contains_key(&self, key: &str) -> bool {
+        self.store.contains_key(key).await
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let ttls = self.store.ttls();
+        let effective_ttl_secs = ttls.as_ref().filter(|t| !t.is_empty()).map(|ttls| {
+            let secs = ttls.iter().map(Duration::as_secs_f64);
+            EffectiveTtl {
+                min: secs.clone().fold(f64::MAX, f64::min),
+                max: secs.clone().fold(0.0, f64::max),
+                mean: secs.sum::<f64>() / ttls.len() as f64,
+            }
+        });
+        CacheStats {
+            backend: self.store.backend(),
+            entries: ttls.map(|t| t.len() as u64),
+            ttl_secs: self.ttl.as_secs(),
+            jitter: TTL_JITTER,
+            early_refreshes: self.early_refreshes.load(Ordering::Relaxed),
+            backend_errors: self.store.errors(),
+            effective_ttl_secs,
+        }
+    }
+}
+
+/// Número pseudoaleatório em `[0, 1)`, sem dependência de `rand`.
+fn random_unit() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Entrada de um cache gravado fora da memória (em disco, as playlists de
+/// `/hls/file`; no Redis, as do [`RedisStore`]). Cada cache tem a sua versão do `payload`; ao mudar o que
+/// grava, sobe a versão. Entradas de outra versão ou ilegíveis valem como
+/// miss, nunca como erro, e são trocadas na próxima gravação.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CacheEnvelope<T> {
+    pub version: u8,
+    /// Unix timestamp (s) da gravação.
+    pub inserted_at: u64,
+    pub payload: T,
+}
+
+impl<T: Serialize> CacheEnvelope<T> {
+    pub fn seal(version: u8, payload: T) -> serde_json::Result<Vec<u8>> {
+        let inserted_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        serde_json::to_vec(&CacheEnvelope { version, inserted_at, payload })
+    }
+}
+
+impl<T: DeserializeOwned> CacheEnvelope<T> {
+    /// O `payload` se `bytes` for um envelope na `version` esperada.
+    pub fn open(bytes: &[u8], version: u8) -> Option<T> {
+        // só o cabeçalho primeiro: um payload de outra versão pode nem ter
+        // o formato de `T`
+        #[derive(Deserialize)]
+        struct Header {
+            version: u8,
+        }
+        let found = match serde_json::from_slice::<Header>(bytes) {
+            Ok(header) => header.version,
+            Err(e) => {
+                tracing::warn!("entrada de cache ilegível, tratada como miss: {e}");
+                return None;
+            }
+        };
+        if found != version {
+            tracing::debug!(found, expected = version, "entrada de cache de outra versão, tratada como miss");
+            return None;
+        }
+        match serde_json::from_slice::<CacheEnvelope<T>>(bytes) {
+            Ok(envelope) => Some(envelope.payload),
+            Err(e) => {
+                tracing::warn!("entrada de cache ilegível, tratada como miss: {e}");
+                None
+            }
+        }
+    }
+}
+
+/// JSON servido com a origem: `age` é `None` quando veio do upstream agora.
+/// Como resposta, inclui `X-Cache: HIT|MISS` e `Age`.
+#[derive(Debug, Clone)]
+pub struct Fetched {
+    pub value: serde_json::Value,
+    pub age: Option<Duration>,
+    /// Forma do conteúdo, para o formato v2 (veja `shape::negotiate`).
+    pub shape: Option<Shape>,
+}
+
+impl Fetched {
+    pub fn miss(value: serde_json::Value) -> Self {
+        Fetched { value, age: None, shape: None }
+    }
+
+    pub fn shaped(self, shape: Shape) -> Self {
+        Fetched { shape: Some(shape), ..self }
+    }
+}
+
+impl IntoResponse for Fetched {
+    fn into_response(self) -> Response {
+        let status = if self.age.is_some() { "HIT" } else { "MISS" };
+        let age = self.age.unwrap_or_default().as_secs();
+        let mut resp = Json(self.value).into_response();
+        let headers = resp.headers_mut();
+        headers.insert("x-cache", HeaderValue::from_static(status));
+        headers.insert(header::AGE, HeaderValue::from(age));
+        if let Some(shape) = self.shape {
+            resp.extensions_mut().insert(shape);
+        }
+        resp
+    }
+}
+
+/// `Cache-Control: no-cache` ou `?refresh=1` forçam a releitura do upstream
+/// (o resultado novo ainda vai para o cache). Só vale com o token de admin
+/// ou com `ALLOW_CACHE_BYPASS`; fora isso o pedido é ignorado, para não
+/// virar um jeito de furar a proteção do cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CacheMode {
+    #[default]
+    Normal,
+    Refresh,
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for CacheMode {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let no_cache = parts
+            .headers
+            .get_all(header::CACHE_CONTROL)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|d| d.trim().eq_ignore_ascii_case("no-cache"));
+        let refresh = parts.uri.query().is_some_and(|q| {
+            q.split('&')
+                .any(|pair| matches!(pair, "refresh=1" | "refresh=true"))
+        });
+        if !(no_cache || refresh) {
+            return Ok(CacheMode::Normal);
+        }
+
+        let admin = parts.extensions.get::<auth::Identity>().is_some_and(|i| i.admin);
+        if state.config().allow_cache_bypass || admin {
+            Ok(CacheMode::Refresh)
+        } else {
+            tracing::debug!("bypass de cache ignorado: cliente sem permissão");
+            Ok(CacheMode::Normal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INSERTS: usize = 2000;
+
+    #[tokio::test]
+    async fn ttl_jitter_spreads_expiries() {
+        let ttl = Duration::from_secs(1000);
+        let cache = ResponseCache::new(ttl, Arc::new(MemoryStore::new(INSERTS as u64)));
+        for i in 0..INSERTS {
+            cache.insert(format!("trending:{i}"), serde_json::json!(i)).await;
+        }
+        let secs: Vec<f64> = cache.store.ttls().unwrap().iter().map(Duration::as_secs_f64).collect();
+        assert_eq!(secs.len(), INSERTS);
+
+        // limites: ±15% do TTL base
+        let (low, high) = (1000.0 * (1.0 - TTL_JITTER), 1000.0 * (1.0 + TTL_JITTER));
+        for s in &secs {
+            assert!((low..=high).contains(s), "TTL {s} fora de [{low}, {high}]");
+        }
+
+        // espalhamento: faixa quase toda usada, desvio perto do de uma
+        // uniforme (300 / √12 ≈ 87 s) e nenhum décimo da faixa vazio
+        let min = secs.iter().copied().fold(f64::MAX, f64::min);
+        let max = secs.iter().copied().fold(0.0, f64::max);
+        assert!(max - min > 270.0, "faixa de {:.1} s", max - min);
+        let mean = secs.iter().sum::<f64>() / INSERTS as f64;
+        let sd = (secs.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / INSERTS as f64).sqrt();
+        assert!((mean - 1000.0).abs() < 10.0, "média {mean:.1} s");
+        assert!((70.0..105.0).contains(&sd), "desvio {sd:.1} s");
+        let mut buckets = [0usize; 10];
+        for s in &secs {
+            buckets[(((s - low) / (high - low) * 10.0) as usize).min(9)] += 1;
+        }
+        for (i, n) in buckets.iter().enumerate() {
+            assert!(*n > INSERTS / 20, "décimo {i} com {n} entradas: {buckets:?}");
+        }
+
+        let stats = serde_json::to_value(cache.stats()).unwrap();
+        assert_eq!(stats["entries"], INSERTS);
+        assert!(stats["effective_ttl_secs"]["max"].as_f64().unwrap() - stats["effective_ttl_secs"]["min"].as_f64().unwrap() > 270.0);
+    }
+
+    /// Fração das leituras que anteciparam a renovação de uma entrada
+    /// gravada há `age` com TTL de 1000 s.
+    async fn early_refresh_rate(age: Duration) -> f64 {
+        let store = Arc::new(MemoryStore::new(10));
+        let entry = Entry {
+            value: serde_json::json!(1),
+            inserted_at: SystemTime::now() - age,
+            ttl: Duration::from_secs(1000),
+        };
+        store.insert("k".into(), entry).await;
+        let cache = ResponseCache::new(Duration::from_secs(1000), store);
+        let reads = 2000;
+        let mut misses = 0;
+        for _ in 0..reads {
+            if cache.get("k", CacheMode::Normal).await.is_none() {
+                misses += 1;
+            }
+        }
+        assert_eq!(cache.early_refreshes.load(Ordering::Relaxed), misses);
+        misses as f64 / reads as f64
+    }
+
+    #[tokio::test]
+    async fn early_refresh_grows_inside_the_window() {
+        // fora da janela (últimos 10%): nunca
+        assert_eq!(early_refresh_rate(Duration::from_secs(500)).await, 0.0);
+        assert_eq!(early_refresh_rate(Duration::from_secs(899)).await, 0.0);
+        // no meio da janela, perto de metade; no fim, quase sempre
+        let half = early_refresh_rate(Duration::from_secs(950)).await;
+        assert!((0.4..0.6).contains(&half), "no meio da janela: {half}");
+        let late = early_refresh_rate(Duration::from_secs(995)).await;
+        assert!(late > 0.9, "no fim da janela: {late}");
+    }
+}
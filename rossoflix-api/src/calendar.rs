@@ -0,0 +1,246 @@
+use std::collections::BTreeMap;
+
+use axum::extract::{Query, State};
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+
+use crate::{
+    ApiError, AppState,
+    auth::Identity,
+    cache::{CacheMode, Fetched},
+    dates, upstream, watchlist,
+};
+
+/// Maior janela aceita em `days`.
+const MAX_DAYS: i64 = 60;
+
+#[derive(Debug, Deserialize)]
+pub struct CalendarParams {
+    /// Início da janela (`YYYY-MM-DD`); padrão hoje.
+    from: Option<String>,
+    #[serde(default = "default_days")]
+    days: i64,
+    #[serde(default)]
+    profile: String,
+    /// `1`: ignora a lista e usa as séries no ar do TMDB.
+    all: Option<String>,
+}
+
+fn default_days() -> i64 {
+    7
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct CalendarEpisode {
+    show: String,
+    imdb_id: Option<String>,
+    tmdb_id: u64,
+    season: u32,
+    episode: u32,
+    title: String,
+    air_date: String,
+}
+
+/// Série a consultar: do TMDB, com o IMDb id quando veio da lista.
+struct Show {
+    tmdb_id: u64,
+    imdb_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbFind {
+    #[serde(default)]
+    tv_results: Vec<TmdbId>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbId {
+    id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbOnTheAir {
+    #[serde(default)]
+    results: Vec<TmdbId>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbShow {
+    name: String,
+    last_episode_to_air: Option<TmdbEpisodeRef>,
+    next_episode_to_air: Option<TmdbEpisodeRef>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbEpisodeRef {
+    season_number: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbSeason {
+    #[serde(default)]
+    episodes: Vec<TmdbEpisode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbEpisode {
+    season_number: u32,
+    episode_number: u32,
+    #[serde(default)]
+    name: String,
+    air_date: Option<String>,
+}
+
+/// `GET /calendar?from=&days=&profile=` — episódios que estreiam na janela,
+/// agrupados por data, para as séries da lista do perfil (`?all=1`: as
+/// séries no ar do TMDB). Séries sem episódio na janela não aparecem.
+/// Cache de algumas horas por perfil e janela.
+pub async fn calendar(
+    State(state): State<AppState>,
+    mode: CacheMode,
+    identity: Identity,
+    Query(params): Query<CalendarParams>,
+) -> Result<Fetched, ApiError> {
+    let profile = identity.profile(&params.profile)?;
+    let from = match &params.from {
+        Some(from) => from.clone(),
+        None => dates::today(),
+    };
+    let start = dates::parse(&from).ok_or_else(|| ApiError::BadRequest(format!("from inválido: {from}")))?;
+    if !(1..=MAX_DAYS).contains(&params.days) {
+        return Err(ApiError::BadRequest(format!("days deve estar entre 1 e {MAX_DAYS}")));
+    }
+    let to = dates::format(start + params.days);
+    let all = params.all.as_deref().is_some_and(|v| v == "1" || v == "true");
+
+    let (key, shows) = if all {
+        (format!("calendar:all:{from}:{}", params.days), None)
+    } else {
+        // a lista entra na chave: mexer nela não espera o cache expirar
+        let items = watchlist::items(&state, &profile).await?;
+        let ids: Vec<String> = items.into_iter().map(|i| i.imdb_id).collect();
+        let digest: String = Sha1::digest(ids.join(",")).iter().take(8).map(|b| format!("{b:02x}")).collect();
+        (format!("calendar:{profile}:{from}:{}:{digest}", params.days), Some(ids))
+    };
+    if let Some(cached) = state.calendar.get(&key, mode).await {
+        return Ok(cached);
+    }
+
+    // uma série com erro no TMDB fica de fora, mas aí o resultado não vai
+    // para o cache (senão a falha passageira duraria horas)
+    let mut complete = true;
+    let shows: Vec<Show> = match shows {
+        Some(ids) => {
+            let found = join_all(ids.iter().map(|id| find_show(&state, id, mode))).await;
+            complete &= found.iter().all(Result::is_ok);
+            found.into_iter().filter_map(|r| r.ok().flatten()).collect()
+        }
+        None => on_the_air(&state, mode).await?,
+    };
+    let episodes = join_all(shows.iter().map(|show| show_episodes(&state, show, &from, &to, mode))).await;
+    complete &= episodes.iter().all(Result::is_ok);
+
+    let mut by_date: BTreeMap<String, Vec<CalendarEpisode>> = BTreeMap::new();
+    for episode in episodes.into_iter().flatten().flatten() {
+        by_date.entry(episode.air_date.clone()).or_default().push(episode);
+    }
+    let days: Vec<Value> = by_date
+        .into_iter()
+        .map(|(date, mut episodes)| {
+            episodes.sort_by(|a, b| (&a.show, a.season, a.episode).cmp(&(&b.show, b.season, b.episode)));
+            serde_json::json!({ "date": date, "episodes": episodes })
+        })
+        .collect();
+
+    let json = serde_json::json!({
+        "from": from,
+        "to": to,
+        "profile": (!all).then_some(profile),
+        "days": days,
+    });
+    if complete {
+        state.calendar.insert(key, json.clone()).await;
+    }
+    Ok(Fetched::miss(json))
+}
+
+/// Série do TMDB a partir do IMDb id; `None` para filmes.
+async fn find_show(state: &AppState, imdb_id: &str, mode: CacheMode) -> Result<Option<Show>, ApiError> {
+    let url = format!(
+        "{}/find/{}?api_key={}&external_source=imdb_id",
+        state.config().tmdb_base_url,
+        urlencoding::encode(imdb_id),
+        state.tmdb_key
+    );
+    let found: TmdbFind = tmdb_cached(state, "/find/:imdb_id", format!("tmdb:find:{imdb_id}"), &url, mode).await?;
+    Ok(found.tv_results.first().map(|tv| Show {
+        tmdb_id: tv.id,
+        imdb_id: Some(imdb_id.to_string()),
+    }))
+}
+
+async fn on_the_air(state: &AppState, mode: CacheMode) -> Result<Vec<Show>, ApiError> {
+    let url = format!("{}/tv/on_the_air?api_key={}", state.config().tmdb_base_url, state.tmdb_key);
+    let list: TmdbOnTheAir = tmdb_cached(state, "/tv/on_the_air", "tmdb:tv:on_the_air".into(), &url, mode).await?;
+    Ok(list
+        .results
+        .into_iter()
+        .map(|tv| Show {
+            tmdb_id: tv.id,
+            imdb_id: None,
+        })
+        .collect())
+}
+
+/// Episódios da série com `air_date` em `[from, to)`. Só lê as temporadas
+/// do último e do próximo episódio, que cobrem as janelas perto de hoje.
+async fn show_episodes(
+    state: &AppState,
+    show: &Show,
+    from: &str,
+    to: &str,
+    mode: CacheMode,
+) -> Result<Vec<CalendarEpisode>, ApiError> {
+    let id = show.tmdb_id;
+    let url = format!("{}/tv/{id}?api_key={}", state.config().tmdb_base_url, state.tmdb_key);
+    let details: TmdbShow = tmdb_cached(state, "/tv/:id", format!("tmdb:tv:{id}"), &url, mode).await?;
+
+    let mut seasons: Vec<u32> = [&details.last_episode_to_air, &details.next_episode_to_air]
+        .into_iter()
+        .flatten()
+        .map(|e| e.season_number)
+        .collect();
+    seasons.dedup();
+
+    let mut out = Vec::new();
+    for season in seasons {
+        let url = format!("{}/tv/{id}/season/{season}?api_key={}", state.config().tmdb_base_url, state.tmdb_key);
+        let data: TmdbSeason = tmdb_cached(state, "/tv/:id/season/:season", format!("tmdb:season:{id}:{season}"), &url, mode).await?;
+        out.extend(data.episodes.into_iter().filter_map(|e| {
+            let air_date = e.air_date.filter(|d| d.as_str() >= from && d.as_str() < to)?;
+            Some(CalendarEpisode {
+                show: details.name.clone(),
+                imdb_id: show.imdb_id.clone(),
+                tmdb_id: id,
+                season: e.season_number,
+                episode: e.episode_number,
+                title: e.name,
+                air_date,
+            })
+        }));
+    }
+    Ok(out)
+}
+
+/// GET no TMDB guardado no cache do calendário (horas).
+async fn tmdb_cached<T: serde::de::DeserializeOwned>(
+    state: &AppState,
+    endpoint: &'static str,
+    key: String,
+    url: &str,
+    mode: CacheMode,
+) -> Result<T, ApiError> {
+    upstream::cached_json(state, &state.calendar, upstream::Service::Tmdb, endpoint, key, url, mode).await
+}
@@ -0,0 +1,254 @@
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::{
+    ApiError, AppState, attribution, availability,
+    cache::{self, CacheCategory, CacheMode},
+    posters, shape, torrentio, upstream,
+};
+
+/// Itens por página nas nossas respostas (o mesmo das páginas do TMDB).
+const PAGE_SIZE: usize = 20;
+/// Itens por página dos catálogos de addon (convenção do Stremio); o
+/// `skip` avança de 100 em 100.
+const ADDON_PAGE: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct PopularParams {
+    #[serde(rename = "type", default = "default_type")]
+    kind: String,
+    #[serde(default = "default_page")]
+    page: u32,
+}
+fn default_type() -> String {
+    "movie".into()
+}
+fn default_page() -> u32 {
+    1
+}
+
+/// Página do catálogo do addon que contém a nossa `page`, e onde ela começa
+/// dentro dela.
+fn addon_window(page: u32) -> (usize, usize) {
+    let first = (page as usize - 1) * PAGE_SIZE;
+    (first / ADDON_PAGE * ADDON_PAGE, first % ADDON_PAGE)
+}
+
+/// `GET /catalog/torrents/popular?type=movie|series&page=` — os torrents mais
+/// baixados agora, pelo catálogo do addon do torrentio, no formato curto da
+/// busca. Os campos vêm do detalhe do OMDb quando ele já está em cache (sem
+/// nenhuma chamada a mais). Sem catálogo no addon, ou com ele fora do ar,
+/// cai no "popular" do TMDB, com `source: "tmdb_popular"` e o motivo em
+/// `fallback_reason`.
+pub async fn popular(
+    State(state): State<AppState>,
+    mode: CacheMode,
+    Query(params): Query<PopularParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    if params.kind != "movie" && params.kind != "series" {
+        return Err(ApiError::BadRequest("type deve ser movie ou series".into()));
+    }
+    if params.page == 0 {
+        return Err(ApiError::BadRequest("page começa em 1".into()));
+    }
+    let mut fetched = match torrentio_page(&state, &params.kind, params.page, mode).await {
+        Ok(fetched) => fetched,
+        Err(ApiError::DeadlineExceeded) => return Err(ApiError::DeadlineExceeded),
+        Err(e) => {
+            let reason = match e {
+                ApiError::NotFound(_) => "no_catalog",
+                _ => "unavailable",
+            };
+            tracing::warn!(kind = params.kind, "catálogo do torrentio indisponível ({e}); usando o popular do TMDB");
+            tmdb_page(&state, &params.kind, params.page, reason, mode).await?
+        }
+    };
+    availability::enrich(&state, &mut fetched.value["results"]).await;
+    Ok(fetched.shaped(shape::Shape::List))
+}
+
+/// A página pelo catálogo do addon. A página do addon inteira fica em cache
+/// (`torrentio:catalog:<tipo>:<skip>`), e cada uma das nossas é uma fatia
+/// dela, também em cache.
+async fn torrentio_page(state: &AppState, kind: &str, page: u32, mode: CacheMode) -> Result<cache::Fetched, ApiError> {
+    let key = format!("catalog:torrents:popular:{kind}:{page}");
+    if let Some(cached) = state.cache.get(&key, mode).await {
+        return Ok(cached);
+    }
+    let catalog = catalog_id(state, kind).await?;
+    let (skip, offset) = addon_window(page);
+    let metas = addon_page(state, kind, &catalog, skip, mode).await?;
+
+    let mut results = Vec::new();
+    for meta in metas.iter().skip(offset).take(PAGE_SIZE) {
+        if let Some(item) = short(state, kind, meta).await {
+            results.push(item);
+        }
+    }
+    let json = json!({
+        "results": results,
+        "page": page,
+        "page_size": PAGE_SIZE,
+        // o addon não diz o total; página cheia sugere que há mais
+        "has_more": metas.len() > offset + PAGE_SIZE || metas.len() == ADDON_PAGE,
+        "type": kind,
+        "source": "torrentio",
+    });
+    state.cache.insert_with_ttl(key, json.clone(), state.config().cache_ttl(CacheCategory::Torrentio)).await;
+    Ok(cache::Fetched::miss(json))
+}
+
+/// Id do catálogo de `kind` no manifesto do addon (em cache como
+/// `torrentio:manifest`). `NotFound` se o addon não tiver um.
+async fn catalog_id(state: &AppState, kind: &str) -> Result<String, ApiError> {
+    let key = "torrentio:manifest".to_string();
+    let manifest = match state.cache.get(&key, CacheMode::Normal).await {
+        Some(cached) => cached.value,
+        None => {
+            let (manifest, _) = torrentio::fetch_any(state, "/manifest.json", "/manifest.json").await?;
+            state.cache.insert_with_ttl(key, manifest.clone(), state.config().cache_ttl(CacheCategory::Torrentio)).await;
+            manifest
+        }
+    };
+    manifest["catalogs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|c| c["type"].as_str() == Some(kind))
+        .and_then(|c| c["id"].as_str())
+        .map(str::to_string)
+        .ok_or_else(|| ApiError::NotFound(format!("o addon não tem catálogo de {kind}")))
+}
+
+async fn addon_page(
+    state: &AppState,
+    kind: &str,
+    catalog: &str,
+    skip: usize,
+    mode: CacheMode,
+) -> Result<Vec<Value>, ApiError> {
+    let key = format!("torrentio:catalog:{kind}:{skip}");
+    let body = match state.cache.get(&key, mode).await {
+        Some(cached) => cached.value,
+        None => {
+            let path = match skip {
+                0 => format!("/catalog/{kind}/{catalog}.json"),
+                skip => format!("/catalog/{kind}/{catalog}/skip={skip}.json"),
+            };
+            let (body, _) = torrentio::fetch_any(state, "/catalog/:type/:id", &path).await?;
+            if !body["metas"].is_array() {
+                return Err(ApiError::Upstream("catálogo do torrentio sem metas".into()));
+            }
+            state.cache.insert_with_ttl(key, body.clone(), state.config().cache_ttl(CacheCategory::Torrentio)).await;
+            body
+        }
+    };
+    Ok(body["metas"].as_array().cloned().unwrap_or_default())
+}
+
+/// Um meta preview do addon no formato curto. Os campos do detalhe do OMDb
+/// em cache (`detail:<id>`) têm precedência; fora do cache fica o do addon.
+/// Itens sem IMDb id ficam de fora.
+async fn short(state: &AppState, kind: &str, meta: &Value) -> Option<Value> {
+    let imdb_id = meta["id"].as_str().filter(|id| id.starts_with("tt"))?;
+    let text = |v: &Value| v.as_str().filter(|s| !s.is_empty() && *s != "N/A").map(str::to_string);
+    let mut item = json!({
+        "Title": text(&meta["name"]).unwrap_or_default(),
+        "Year": text(&meta["releaseInfo"]).or_else(|| meta["year"].as_u64().map(|y| y.to_string())).unwrap_or_default(),
+        "imdbID": imdb_id,
+        "Type": kind,
+        "Poster": text(&meta["poster"]).unwrap_or_else(|| "N/A".into()),
+    });
+    if let Some(detail) = state.cache.get(&format!("detail:{imdb_id}"), CacheMode::Normal).await {
+        for field in ["Title", "Year", "Type", "Poster"] {
+            if let Some(value) = text(&detail.value[field]) {
+                item[field] = value.into();
+            }
+        }
+    }
+    Some(item)
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbPopular {
+    results: Vec<TmdbPopularItem>,
+    #[serde(default)]
+    total_pages: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbPopularItem {
+    id: u64,
+    title: Option<String>,
+    name: Option<String>,
+    release_date: Option<String>,
+    first_air_date: Option<String>,
+    poster_path: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbExternalIds {
+    imdb_id: Option<String>,
+}
+
+/// A substituta: a página do "popular" do TMDB (também de 20 itens). O
+/// IMDb id vem do `external_ids` que já estiver em cache; sem ele fica
+/// `null`. Só o corpo do TMDB fica em cache, para o catálogo voltar a ser
+/// usado assim que responder.
+async fn tmdb_page(
+    state: &AppState,
+    kind: &str,
+    page: u32,
+    reason: &'static str,
+    mode: CacheMode,
+) -> Result<cache::Fetched, ApiError> {
+    let tmdb_kind = if kind == "series" { "tv" } else { "movie" };
+    let url = format!(
+        "{}/{tmdb_kind}/popular?api_key={}&page={page}",
+        state.config().tmdb_base_url,
+        state.tmdb_key
+    );
+    let popular: TmdbPopular = upstream::cached_json(
+        state,
+        &state.cache.with_ttl(state.config().cache_ttl(CacheCategory::Trending)),
+        upstream::Service::Tmdb,
+        "/:kind/popular",
+        format!("tmdb:{tmdb_kind}:popular:{page}"),
+        &url,
+        mode,
+    )
+    .await?;
+
+    let mut results = Vec::new();
+    for item in popular.results {
+        let external = state.cache.get(&format!("tmdb:{tmdb_kind}:{}:external_ids", item.id), CacheMode::Normal).await;
+        let imdb_id = external
+            .and_then(|cached| serde_json::from_value::<TmdbExternalIds>(cached.value).ok())
+            .and_then(|ids| ids.imdb_id)
+            .filter(|id| id.starts_with("tt"));
+        let date = item.release_date.or(item.first_air_date).unwrap_or_default();
+        results.push(json!({
+            "Title": item.title.or(item.name).unwrap_or_default(),
+            "Year": date.get(..4).unwrap_or_default(),
+            "imdbID": imdb_id,
+            "Type": kind,
+            "Poster": item.poster_path.map(|p| format!("{}{p}", posters::TMDB_IMAGE_BASE)).unwrap_or_else(|| "N/A".into()),
+            "tmdb_id": item.id,
+        }));
+    }
+    let mut json = json!({
+        "results": results,
+        "page": page,
+        "page_size": PAGE_SIZE,
+        "has_more": page < popular.total_pages,
+        "type": kind,
+        "source": "tmdb_popular",
+        "fallback_reason": reason,
+    });
+    attribution::annotate(&mut json, [attribution::Source::Tmdb]);
+    Ok(cache::Fetched::miss(json))
+}
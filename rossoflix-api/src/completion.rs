@@ -0,0 +1,349 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    response::IntoResponse,
+};
+use rusqlite::params;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{ApiError, AppState, auth::Identity, stats, stream_title::StreamTitle, watchlist::ProfileParams};
+
+/// O fim do arquivo que precisa ter sido servido: quem parou nos créditos
+/// finais ou só pulou pelo filme não terminou.
+const TAIL_BYTES: u64 = 8 * 1024 * 1024;
+/// Sessão sem nenhum pedido há esse tempo é esquecida (e o que ela cobriu).
+const SESSION_IDLE: Duration = Duration::from_secs(12 * 3600);
+
+/// União de intervalos `[início, fim)` de bytes, ordenados e sem
+/// sobreposição: trechos repetidos ou encavalados contam uma vez só.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Intervals(Vec<(u64, u64)>);
+
+impl Intervals {
+    pub fn insert(&mut self, start: u64, end: u64) {
+        if start >= end {
+            return;
+        }
+        // primeiro intervalo que encosta ou passa de `start`
+        let from = self.0.partition_point(|&(_, e)| e < start);
+        // primeiro que começa depois de `end` (encostar em `end` funde)
+        let to = self.0.partition_point(|&(s, _)| s <= end);
+        let merged = match self.0.get(from..to) {
+            Some(touched) if !touched.is_empty() => {
+                (start.min(touched[0].0), end.max(touched[touched.len() - 1].1))
+            }
+            _ => (start, end),
+        };
+        self.0.splice(from..to, [merged]);
+    }
+
+    /// Total de bytes cobertos.
+    pub fn covered(&self) -> u64 {
+        self.0.iter().map(|(s, e)| e - s).sum()
+    }
+
+    /// `[start, end)` inteiro está coberto?
+    pub fn covers(&self, start: u64, end: u64) -> bool {
+        start >= end || self.0.iter().any(|&(s, e)| s <= start && end <= e)
+    }
+}
+
+/// Quem assiste: a chave do cliente (usuário do proxy ou IP), o perfil que
+/// fica com o "assistido" e o título, quando o `/stream` traz as dicas.
+#[derive(Debug, Clone)]
+pub struct Viewer {
+    pub client: String,
+    pub profile: String,
+    pub title: Option<StreamTitle>,
+}
+
+/// Arquivo, cliente e perfil.
+type SessionKey = (PathBuf, String, String);
+
+struct Session {
+    served: Intervals,
+    total: u64,
+    title: Option<StreamTitle>,
+    last_seen: Instant,
+    completed: bool,
+}
+
+/// Um arquivo terminado por um espectador (`playback.completed`).
+#[derive(Debug, Clone)]
+pub struct Completed {
+    path: PathBuf,
+    profile: String,
+    title: Option<StreamTitle>,
+}
+
+/// Bytes servidos por arquivo e espectador, somando todos os pedidos com
+/// `Range` da mesma sessão de reprodução.
+#[derive(Clone, Default)]
+pub struct Sessions(Arc<Mutex<HashMap<SessionKey, Session>>>);
+
+impl Sessions {
+    /// Acompanha um corpo que serve os bytes `start..=end` de `path` (de
+    /// `total` bytes); `None` sem espectador ou com o recurso desligado.
+    pub fn track(&self, state: &AppState, path: &Path, total: u64, (start, end): (u64, u64), viewer: Option<Viewer>) -> Option<Served> {
+        let viewer = viewer.filter(|_| state.config().watched_threshold_percent > 0)?;
+        let path = path.to_path_buf();
+        Some(Served { state: state.clone(), path, total, start, end: end + 1, sent: 0, viewer })
+    }
+
+    /// Soma `[start, end)` à sessão; `Some` na primeira vez que ela passa
+    /// do limite e cobre o fim do arquivo.
+    fn record(&self, path: &Path, total: u64, viewer: &Viewer, start: u64, end: u64, threshold: u8) -> Option<Completed> {
+        let mut sessions = self.0.lock().unwrap();
+        sessions.retain(|_, s| s.last_seen.elapsed() < SESSION_IDLE);
+        let key = (path.to_path_buf(), viewer.client.clone(), viewer.profile.clone());
+        let session = sessions.entry(key).or_insert_with(|| Session {
+            served: Intervals::default(),
+            total,
+            title: viewer.title.clone(),
+            last_seen: Instant::now(),
+            completed: false,
+        });
+        if session.total != total {
+            // outro arquivo com o mesmo nome: começa de novo
+            session.served = Intervals::default();
+            session.total = total;
+            session.completed = false;
+        }
+        session.last_seen = Instant::now();
+        session.title = viewer.title.clone().or(session.title.take());
+        session.served.insert(start, end);
+        let enough = session.served.covered().saturating_mul(100) >= total.saturating_mul(u64::from(threshold));
+        if session.completed || !enough || !session.served.covers(total.saturating_sub(TAIL_BYTES), total) {
+            return None;
+        }
+        session.completed = true;
+        Some(Completed { path: path.to_path_buf(), profile: viewer.profile.clone(), title: session.title.clone() })
+    }
+}
+
+/// Conta os bytes de fato entregues por um corpo de `/stream`; no fim (ou
+/// quando o cliente desiste) soma o trecho à sessão. Pedido que só buscou
+/// um pedaço e pulou não cobre nada além dele.
+pub struct Served {
+    state: AppState,
+    path: PathBuf,
+    total: u64,
+    start: u64,
+    /// Fim (exclusivo) do trecho pedido; o que passar dele não foi entregue.
+    end: u64,
+    sent: u64,
+    viewer: Viewer,
+}
+
+impl Served {
+    pub fn add(&mut self, bytes: u64) {
+        self.sent += bytes;
+    }
+}
+
+impl Drop for Served {
+    fn drop(&mut self) {
+        let threshold = self.state.config().watched_threshold_percent;
+        let end = (self.start + self.sent).min(self.end);
+        let completed = self.state.completions.record(&self.path, self.total, &self.viewer, self.start, end, threshold);
+        if let Some(completed) = completed
+            && let Ok(runtime) = tokio::runtime::Handle::try_current()
+        {
+            runtime.spawn(on_completed(self.state.clone(), completed));
+        }
+    }
+}
+
+/// `playback.completed`: marca o título como assistido no perfil, avisa
+/// pelo Telegram e, com `DELETE_AFTER_WATCH`, marca o arquivo como
+/// candidato à limpeza.
+async fn on_completed(state: AppState, completed: Completed) {
+    let config = state.config();
+    let relative = completed.path.strip_prefix(&config.downloads_dir).unwrap_or(&completed.path).to_path_buf();
+    info!(event = "playback.completed", path = %relative.display(), profile = completed.profile, "reprodução concluída");
+
+    let title = match completed.title {
+        Some(title) => Some(title),
+        // sem dicas no `/stream`: o título gravado no índice para o infohash
+        None => match relative.components().next().map(|c| c.as_os_str().to_string_lossy().into_owned()) {
+            Some(info_hash) => state.downloads.by_info_hash(&info_hash).await.ok().flatten().and_then(|d| d.title),
+            None => None,
+        },
+    };
+    let Some(title) = title else {
+        info!(path = %relative.display(), "reprodução concluída sem título conhecido; nada marcado como assistido");
+        return;
+    };
+
+    stats::record(&state, stats::Event::Completion { imdb_id: title.imdb_id.clone() });
+
+    let cleanup = config.delete_after_watch;
+    let (profile, path) = (completed.profile.clone(), relative.to_string_lossy().into_owned());
+    let (imdb_id, season, episode) = (title.imdb_id.clone(), title.season.unwrap_or(0), title.episode.unwrap_or(0));
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default();
+    let saved = state
+        .db
+        .call(move |conn| {
+            conn.execute(
+                "INSERT INTO watched (profile, imdb_id, season, episode, path, completed_at, cleanup)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                 ON CONFLICT (profile, imdb_id, season, episode)
+                 DO UPDATE SET path = ?5, completed_at = ?6, cleanup = ?7",
+                params![profile, imdb_id, season, episode, path, now, cleanup],
+            )
+        })
+        .await;
+    if let Err(e) = saved {
+        warn!(imdb_id = title.imdb_id, "falha ao marcar como assistido: {e}");
+        return;
+    }
+    if state.telegram.is_some() {
+        let episode = match (title.season, title.episode) {
+            (Some(s), Some(e)) => format!(" S{s:02}E{e:02}"),
+            _ => String::new(),
+        };
+        state.outbox.enqueue("telegram", format!("Assistido até o fim: {}{episode}", title.imdb_id));
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct WatchedItem {
+    imdb_id: String,
+    season: Option<u32>,
+    episode: Option<u32>,
+    /// Arquivo assistido, relativo a `DOWNLOADS_DIR`.
+    path: Option<String>,
+    /// Unix timestamp (s).
+    completed_at: i64,
+    /// Marcado para limpeza (`DELETE_AFTER_WATCH`).
+    cleanup_candidate: bool,
+}
+
+/// `GET /watched?profile=` — títulos assistidos até o fim, do mais recente
+/// ao mais antigo.
+pub async fn get_watched(
+    State(state): State<AppState>,
+    identity: Identity,
+    Query(params): Query<ProfileParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let profile = identity.profile(&params.profile)?;
+    let query = profile.clone();
+    let items = state
+        .db
+        .call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT imdb_id, season, episode, path, completed_at, cleanup FROM watched
+                 WHERE profile = ?1 ORDER BY completed_at DESC, imdb_id, season, episode",
+            )?;
+            stmt.query_map(params![query], |row| {
+                let season: u32 = row.get(1)?;
+                let episode: u32 = row.get(2)?;
+                Ok(WatchedItem {
+                    imdb_id: row.get(0)?,
+                    season: (season > 0).then_some(season),
+                    episode: (episode > 0).then_some(episode),
+                    path: row.get(3)?,
+                    completed_at: row.get(4)?,
+                    cleanup_candidate: row.get(5)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await?;
+    Ok(Json(serde_json::json!({ "profile": profile, "items": items })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn intervals(ranges: &[(u64, u64)]) -> Intervals {
+        let mut set = Intervals::default();
+        for &(start, end) in ranges {
+            set.insert(start, end);
+        }
+        set
+    }
+
+    type Ranges = &'static [(u64, u64)];
+
+    #[test]
+    fn interval_union() {
+        let cases: &[(&str, Ranges, Ranges)] = &[
+            ("vazio", &[], &[]),
+            ("intervalo vazio", &[(10, 10), (20, 5)], &[]),
+            ("disjuntos fora de ordem", &[(50, 60), (0, 10), (20, 30)], &[(0, 10), (20, 30), (50, 60)]),
+            ("sobrepostos", &[(0, 10), (5, 15)], &[(0, 15)]),
+            ("sobrepostos à esquerda", &[(5, 15), (0, 10)], &[(0, 15)]),
+            ("encostados", &[(0, 10), (10, 20)], &[(0, 20)]),
+            ("encostados à esquerda", &[(10, 20), (0, 10)], &[(0, 20)]),
+            ("contido", &[(0, 100), (10, 20)], &[(0, 100)]),
+            ("contém", &[(10, 20), (0, 100)], &[(0, 100)]),
+            ("repetido", &[(0, 10), (0, 10)], &[(0, 10)]),
+            ("une vários", &[(0, 10), (20, 30), (40, 50), (5, 45)], &[(0, 50)]),
+            ("preenche o buraco", &[(0, 10), (20, 30), (10, 20)], &[(0, 30)]),
+            ("vazio entre dois", &[(0, 10), (20, 30), (15, 15)], &[(0, 10), (20, 30)]),
+        ];
+        for (name, ranges, want) in cases {
+            assert_eq!(intervals(ranges), Intervals(want.to_vec()), "{name}");
+        }
+    }
+
+    #[test]
+    fn covered_and_covers() {
+        let set = intervals(&[(0, 10), (5, 15), (20, 30)]);
+        assert_eq!(set.covered(), 25);
+        assert!(set.covers(0, 15));
+        assert!(set.covers(22, 30));
+        assert!(!set.covers(10, 25), "atravessa o buraco");
+        assert!(!set.covers(25, 31));
+        assert!(set.covers(40, 40), "trecho vazio está sempre coberto");
+        assert_eq!(Intervals::default().covered(), 0);
+    }
+
+    fn viewer() -> Viewer {
+        Viewer { client: "127.0.0.1".into(), profile: "default".into(), title: None }
+    }
+
+    #[test]
+    fn completes_once_after_threshold_and_tail() {
+        const MB: u64 = 1024 * 1024;
+        let total = 100 * MB;
+        let sessions = Sessions::default();
+        let path = Path::new("filme.mkv");
+        let viewer = viewer();
+
+        // 90% do começo, sem o fim: não terminou
+        assert!(sessions.record(path, total, &viewer, 0, 90 * MB, 85).is_none());
+        // o fim completa
+        assert!(sessions.record(path, total, &viewer, 90 * MB, total, 85).is_some());
+        // só uma vez por sessão
+        assert!(sessions.record(path, total, &viewer, 0, total, 85).is_none());
+    }
+
+    #[test]
+    fn seeking_through_the_file_does_not_complete() {
+        const MB: u64 = 1024 * 1024;
+        let total = 100 * MB;
+        let sessions = Sessions::default();
+        let path = Path::new("filme.mkv");
+        let viewer = viewer();
+
+        // pula pelo arquivo inteiro pedindo trechos de 1 MiB, várias vezes,
+        // e vê o fim: cobre 20% só
+        for _ in 0..5 {
+            for start in (0..total).step_by(5 * MB as usize) {
+                assert!(sessions.record(path, total, &viewer, start, start + MB, 85).is_none());
+            }
+        }
+        assert!(sessions.record(path, total, &viewer, total - 8 * MB, total, 85).is_none());
+    }
+}
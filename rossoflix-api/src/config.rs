@@ -0,0 +1,595 @@
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    auth::{ApiKey, AuthMode, Cidr},
+    cache::{CacheBackend, CacheCategory},
+    engine::Engine,
+    language::LanguageTag,
+    playback::DeviceProfile,
+    posters::PosterCheck,
+};
+
+/// Trackers usados na primeira tentativa do aria2c.
+const DEFAULT_TRACKERS: &str = "udp://tracker.opentrackr.org:1337/announce,udp://open.stealth.si:80/announce,udp://tracker.cyberia.is:6969/announce";
+
+/// Lista secundária, usada quando a primeira tentativa falha.
+const DEFAULT_TRACKERS_FALLBACK: &str = "udp://tracker.torrent.eu.org:451/announce,udp://exodus.desync.com:6969/announce,udp://tracker.openbittorrent.com:6969/announce";
+
+/// Instância pública do torrentio.
+const DEFAULT_TORRENTIO_BASE_URL: &str = "https://torrentio.strem.fun";
+
+/// APIs do OMDb e do TMDB (v3).
+const DEFAULT_OMDB_BASE_URL: &str = "https://www.omdbapi.com";
+const DEFAULT_TMDB_BASE_URL: &str = "https://api.themoviedb.org/3";
+const DEFAULT_OPENSUBTITLES_BASE_URL: &str = "https://api.opensubtitles.com/api/v1";
+
+/// Arquivo de configuração padrão, lido se existir (`CONFIG_FILE` sobrescreve).
+const DEFAULT_CONFIG_FILE: &str = "rossoflix.toml";
+
+/// Configuração do servidor, lida do ambiente (`.env`) e do arquivo
+/// `rossoflix.toml` na inicialização.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub omdb_api_key: String,
+    pub tmdb_api_key: String,
+    pub port: u16,
+    pub bind_ip: IpAddr,
+    pub admin_addr: Option<SocketAddr>,
+    pub downloads_dir: PathBuf,
+    /// Banco SQLite com os dados dos usuários (marcadores de intro/créditos).
+    pub database_path: PathBuf,
+    pub bt_trackers: Vec<String>,
+    pub bt_trackers_fallback: Vec<String>,
+    /// Espelhos do torrentio, na ordem de preferência (sem `/` no fim).
+    pub torrentio_base_urls: Vec<String>,
+    /// Segundos que uma lista do torrentio já filtrada fica guardada (0
+    /// desliga).
+    pub torrentio_view_cache_secs: u64,
+    /// Raiz das APIs do OMDb, do TMDB e do OpenSubtitles (sem `/` no fim);
+    /// trocadas por servidores de fixtures no `mock_stack`.
+    pub omdb_base_url: String,
+    pub tmdb_base_url: String,
+    pub opensubtitles_base_url: String,
+    /// Quem baixa os torrents: o `aria2c` do `PATH` (padrão) ou o motor
+    /// embutido (`DOWNLOAD_ENGINE=embedded`).
+    pub download_engine: Engine,
+    /// Valor de `--file-allocation` do aria2c (`none`, `prealloc`, `falloc`...).
+    pub aria2_file_allocation: String,
+    /// Baixar as peças em ordem, com o começo e o fim primeiro (`SEQUENTIAL_DOWNLOADS=off` desliga).
+    pub sequential_downloads: bool,
+    /// aria2c rodando ao mesmo tempo; os demais esperam na fila (`0` sem limite).
+    pub max_concurrent_downloads: usize,
+    /// Capacidades de decodificação por dispositivo (`chromecast`, `webos`...),
+    /// usadas por `?capabilities=`.
+    pub device_profiles: HashMap<String, Vec<String>>,
+    /// Restrições de container/codec/bitrate por dispositivo, usadas em `/play`.
+    pub profiles: HashMap<String, DeviceProfile>,
+    /// Pré-carregar streams do torrentio ao servir detalhes (`PREFETCH_STREAMS=off` desliga).
+    pub prefetch_streams: bool,
+    pub prefetch_concurrency: usize,
+    pub prefetch_per_client_per_min: u32,
+    /// Bytes lidos adiante depois de cada `Range` de um arquivo completo (`0` desliga).
+    pub readahead_bytes: u64,
+    /// Arquivos abertos guardados pelo `/stream` (`0` desliga).
+    pub file_handle_cache_size: usize,
+    /// Chamadas ao OMDb por minuto no aquecimento do cache (`/admin/cache/warm`).
+    pub warm_omdb_per_min: u32,
+    /// Retomar na inicialização os downloads interrompidos por um crash.
+    pub auto_resume_downloads: bool,
+    /// Idade a partir da qual parciais sem `.aria2` são apagados na recuperação.
+    pub recovery_partial_max_age_hours: u64,
+    /// Por quanto tempo arquivos removidos ficam em `<downloads>/.trash/`.
+    pub trash_retention_hours: u64,
+    /// Teto de downloads e lixeira juntos (0 sem teto) e dias sem acesso
+    /// para um download ser apagado (0 desliga).
+    pub downloads_max_bytes: u64,
+    pub downloads_max_idle_days: u64,
+    /// Hosts cujas URLs `/stream?url=` pode repassar (vazio desliga o proxy).
+    pub stream_proxy_hosts: Vec<String>,
+    /// Chave HMAC das URLs assinadas de `/stream`; a anterior continua
+    /// aceita na verificação durante a rotação.
+    pub stream_signing_key: Option<String>,
+    pub stream_signing_key_previous: Option<String>,
+    /// Tolerância de relógio (s) ao conferir a expiração.
+    pub stream_signature_skew_secs: u64,
+    /// Proxy de saída (`ALL_PROXY`, `HTTPS_PROXY` ou `HTTP_PROXY`; aceita
+    /// `socks5://`) para o upstream e, se for HTTP, para o aria2c.
+    pub outbound_proxy: Option<String>,
+    /// Só estes hosts (e subdomínios) usam o proxy; vazio: todos.
+    pub proxy_hosts: Vec<String>,
+    /// Chave da API REST do OpenSubtitles (`/subtitles/match`).
+    pub opensubtitles_api_key: Option<String>,
+    /// Timeouts (s) por upstream; o do torrentio é maior porque a busca
+    /// nos indexadores é lenta.
+    pub omdb_timeout_secs: u64,
+    pub tmdb_timeout_secs: u64,
+    pub torrentio_timeout_secs: u64,
+    pub opensubtitles_timeout_secs: u64,
+    /// Prazo padrão (ms) de um pedido para as chamadas ao upstream; `0` desliga.
+    pub request_deadline_ms: u64,
+    /// Prazos por prefixo de rota (`REQUEST_DEADLINE_ROUTES=/torrentio=30000,...`).
+    pub request_deadline_routes: Vec<(String, u64)>,
+    /// Provedores de `/title/:imdb_id`, do preferido ao último.
+    pub metadata_priority: Vec<String>,
+    /// Páginas do TMDB varridas por `/trending/all?filter=`.
+    pub trending_filter_max_pages: u32,
+    /// Títulos resolvidos ao mesmo tempo na montagem de `/movies/trending`
+    /// e o tempo (ms) de cada um por estágio (0 sem limite).
+    pub trending_concurrency: usize,
+    pub trending_title_timeout_ms: u64,
+    /// Tempo total (ms) de `/search?enrich=ratings`; o que não ficar pronto
+    /// sai com `enriched: false`.
+    pub search_enrich_budget_ms: u64,
+    /// Títulos velhos do catálogo local relidos no OMDb por passada diária.
+    pub library_refresh_max: u32,
+    /// Cota diária de chamadas ao OMDb (plano da chave); `0` desliga o aviso
+    /// de `/notices`.
+    pub omdb_daily_limit: u64,
+    /// Teto (bytes) do corpo lido de uma resposta JSON do upstream.
+    pub max_upstream_body_bytes: usize,
+    /// `playable`/`reason` nos itens das listas (`PLAYABLE_ENRICHMENT=off` desliga).
+    pub playable_enrichment: bool,
+    /// Confere com HEAD se o pôster do OMDb existe: antes de responder ou
+    /// numa fila em segundo plano.
+    pub verify_posters: PosterCheck,
+    /// Quantos ffmpeg de `/media/audio` podem rodar ao mesmo tempo.
+    pub audio_max_extractions: usize,
+    /// Quantos ffmpeg de `/stream/hls` podem rodar ao mesmo tempo.
+    pub hls_max_transcodes: usize,
+    /// Fila dos jobs curtos de mídia (miniaturas, ffprobe): workers, jobs
+    /// distintos aceitos, prazo de cada job e quanto um pedido espera antes
+    /// do `202`.
+    pub media_workers: usize,
+    pub media_queue_max: usize,
+    pub media_job_timeout_secs: u64,
+    pub media_wait_secs: u64,
+    /// Limites da recusa por sobrecarga (`shed`): pedidos em andamento, p95
+    /// da latência em ms e fila global do runtime. 0 desliga o sinal.
+    pub shed_max_in_flight: usize,
+    pub shed_p95_ms: u64,
+    pub shed_queue_depth: usize,
+    /// Teto de bytes de um `GET /speedtest` e testes por minuto por cliente
+    /// (0 sem limite).
+    pub speedtest_max_bytes: u64,
+    pub speedtest_per_minute: u32,
+    /// Pedidos por minuto de cada cliente na API pública e a rajada aceita
+    /// (`0` desliga), e downloads novos por hora que ele pode começar.
+    pub rate_limit_per_minute: u32,
+    pub rate_limit_burst: u32,
+    pub rate_limit_downloads_per_hour: u32,
+    /// Espaço temporário das transcodificações (padrão `<downloads>/.scratch`).
+    pub scratch_dir: PathBuf,
+    pub scratch_idle_ttl_minutes: u64,
+    pub scratch_budget_bytes: u64,
+    /// Minutos sem participantes nem eventos até uma sessão de `/party` expirar.
+    pub party_idle_minutes: u64,
+    /// Bot do Telegram: avisos de download e comandos (`/status`, `/downloads`,
+    /// `/cancel`). Sem o token a integração fica desligada; só o chat
+    /// configurado é atendido.
+    pub telegram_bot_token: Option<String>,
+    pub telegram_chat_id: Option<String>,
+    /// Token exigido nas operações administrativas (`Authorization: Bearer`).
+    pub admin_token: Option<String>,
+    /// Chaves aceitas em `X-Api-Key`; com alguma, todas as rotas menos os
+    /// health checks exigem uma.
+    pub api_keys: Vec<ApiKey>,
+    /// Quem é o usuário: só o token, os cabeçalhos do proxy reverso ou ninguém.
+    pub auth_mode: AuthMode,
+    /// Conexões das quais os cabeçalhos de identidade são aceitos.
+    pub trusted_proxies: Vec<Cidr>,
+    /// Cabeçalhos com o usuário e os grupos (separados por vírgula), e o
+    /// grupo que dá acesso às rotas de admin.
+    pub auth_user_header: String,
+    pub auth_groups_header: String,
+    pub auth_admin_group: String,
+    /// Entradas mantidas na trilha de auditoria; as mais antigas saem.
+    pub audit_max_entries: u64,
+    /// Permite a qualquer cliente pular a leitura do cache (sem o token de admin).
+    pub allow_cache_bypass: bool,
+    /// Onde ficam os caches de respostas; no Redis, compartilhados entre
+    /// instâncias e mantidos entre reinícios. As chaves levam o prefixo.
+    pub cache_backend: CacheBackend,
+    pub redis_url: Option<String>,
+    pub redis_key_prefix: String,
+    /// TTL base (s) de cada categoria do cache de respostas; `0` não guarda.
+    pub cache_ttl_search_secs: u64,
+    pub cache_ttl_detail_secs: u64,
+    pub cache_ttl_trending_secs: u64,
+    pub cache_ttl_torrentio_secs: u64,
+    /// Corpo de erro no formato antigo (`error` em texto), por uma versão.
+    pub legacy_error_body: bool,
+    /// Idioma de áudio preferido na escolha do release (`/play`).
+    pub preferred_audio_lang: Option<LanguageTag>,
+    /// Pasta vigiada por `.torrent`/`.magnet` e o intervalo entre as passadas.
+    pub watch_dir: Option<PathBuf>,
+    pub watch_interval_secs: u64,
+    /// Porcentagem do arquivo servida (com o fim) para contar como assistido
+    /// (0 desliga) e se o assistido vira candidato à limpeza.
+    pub watched_threshold_percent: u8,
+    pub delete_after_watch: bool,
+    /// Estatísticas de uso só locais e por quanto tempo os eventos crus
+    /// ficam depois de somados por hora.
+    pub stats_enabled: bool,
+    pub stats_raw_retention_hours: u64,
+}
+
+/// Seções do `rossoflix.toml`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    device_profiles: HashMap<String, Vec<String>>,
+    profiles: HashMap<String, DeviceProfile>,
+    /// `nome = "chave"`, somadas às de `API_KEYS`.
+    api_keys: HashMap<String, String>,
+}
+
+impl FileConfig {
+    fn load() -> io::Result<Self> {
+        let (path, explicit) = match optional("CONFIG_FILE") {
+            Some(p) => (PathBuf::from(p), true),
+            None => (PathBuf::from(DEFAULT_CONFIG_FILE), false),
+        };
+        if !explicit && !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::read(&path)
+    }
+
+    fn read(path: &Path) -> io::Result<Self> {
+        let raw = std::fs::read_to_string(path).map_err(|e| {
+            io::Error::new(e.kind(), format!("não foi possível ler {}: {e}", path.display()))
+        })?;
+        toml::from_str(&raw).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{} inválido: {e}", path.display()))
+        })
+    }
+}
+
+impl Config {
+    pub fn from_env() -> io::Result<Self> {
+        let file = FileConfig::load()?;
+        let omdb_api_key = required("OMDB_API_KEY")?;
+        let tmdb_api_key = required("TMDB_API_KEY")?;
+
+        // Interface da API pública (padrão: todas) e listener opcional só para a superfície admin
+        let bind_ip = parse_or("BIND_ADDR", IpAddr::from([0, 0, 0, 0]))?;
+        let admin_addr = match optional("ADMIN_BIND_ADDR") {
+            Some(s) => Some(parse("ADMIN_BIND_ADDR", &s)?),
+            None => None,
+        };
+
+        let downloads_dir = optional("DOWNLOADS_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("./downloads"));
+        let auth_mode = parse_or("AUTH_MODE", AuthMode::Token)?;
+        let trusted_proxies: Vec<Cidr> = list("TRUSTED_PROXIES", "127.0.0.1/32,::1/128")
+            .iter()
+            .map(|c| parse("TRUSTED_PROXIES", c))
+            .collect::<io::Result<_>>()?;
+        if auth_mode == AuthMode::ProxyHeaders && trusted_proxies.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "AUTH_MODE=proxy_headers exige TRUSTED_PROXIES",
+            ));
+        }
+
+        let cache_backend = parse_or("CACHE_BACKEND", CacheBackend::Memory)?;
+        let redis_url = optional("REDIS_URL");
+        if cache_backend == CacheBackend::Redis && redis_url.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "CACHE_BACKEND=redis exige REDIS_URL",
+            ));
+        }
+
+        let api_keys = api_keys(file.api_keys)?;
+        // com `none` todo pedido é admin, e o admin dispensa a chave
+        if auth_mode == AuthMode::None && !api_keys.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "AUTH_MODE=none não combina com API_KEYS",
+            ));
+        }
+
+        let watched_threshold_percent: u8 = parse_or("WATCHED_THRESHOLD_PERCENT", 85)?;
+        if watched_threshold_percent > 100 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "WATCHED_THRESHOLD_PERCENT vai de 0 a 100",
+            ));
+        }
+
+        let scratch_dir = optional("SCRATCH_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| downloads_dir.join(".scratch"));
+
+        Ok(Self {
+            omdb_api_key,
+            tmdb_api_key,
+            port: parse_or("PORT", 8080)?,
+            bind_ip,
+            admin_addr,
+            database_path: optional("DATABASE_PATH")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| downloads_dir.join("rossoflix.db")),
+            downloads_dir,
+            bt_trackers: list("BT_TRACKERS", DEFAULT_TRACKERS),
+            bt_trackers_fallback: list("BT_TRACKERS_FALLBACK", DEFAULT_TRACKERS_FALLBACK),
+            torrentio_base_urls: list("TORRENTIO_BASE_URL", DEFAULT_TORRENTIO_BASE_URL)
+                .into_iter()
+                .map(|u| u.trim_end_matches('/').to_string())
+                .collect(),
+            torrentio_view_cache_secs: parse_or("TORRENTIO_VIEW_CACHE_SECS", 5)?,
+            omdb_base_url: base_url("OMDB_BASE_URL", DEFAULT_OMDB_BASE_URL),
+            tmdb_base_url: base_url("TMDB_BASE_URL", DEFAULT_TMDB_BASE_URL),
+            opensubtitles_base_url: base_url("OPENSUBTITLES_BASE_URL", DEFAULT_OPENSUBTITLES_BASE_URL),
+            download_engine: parse_or("DOWNLOAD_ENGINE", Engine::Aria2c)?,
+            aria2_file_allocation: optional("ARIA2_FILE_ALLOCATION").unwrap_or_else(|| "none".into()),
+            sequential_downloads: flag("SEQUENTIAL_DOWNLOADS", true),
+            max_concurrent_downloads: parse_or("MAX_CONCURRENT_DOWNLOADS", 4)?,
+            device_profiles: device_profiles(file.device_profiles),
+            profiles: playback_profiles(file.profiles),
+            prefetch_streams: flag("PREFETCH_STREAMS", true),
+            prefetch_concurrency: parse_or("PREFETCH_CONCURRENCY", 4)?,
+            prefetch_per_client_per_min: parse_or("PREFETCH_PER_CLIENT_PER_MIN", 20)?,
+            readahead_bytes: parse_or("READAHEAD_BYTES", 8 * 1024 * 1024)?,
+            file_handle_cache_size: parse_or("FILE_HANDLE_CACHE_SIZE", 64)?,
+            warm_omdb_per_min: parse_or("WARM_OMDB_PER_MIN", 30)?,
+            auto_resume_downloads: flag("AUTO_RESUME_DOWNLOADS", false),
+            recovery_partial_max_age_hours: parse_or("RECOVERY_PARTIAL_MAX_AGE_HOURS", 24)?,
+            trash_retention_hours: parse_or("TRASH_RETENTION_HOURS", 72)?,
+            downloads_max_bytes: parse_or("DOWNLOADS_MAX_BYTES", 0)?,
+            downloads_max_idle_days: parse_or("DOWNLOADS_MAX_IDLE_DAYS", 0)?,
+            stream_proxy_hosts: list("STREAM_PROXY_HOSTS", "")
+                .into_iter()
+                .map(|h| h.to_ascii_lowercase())
+                .collect(),
+            stream_signing_key: optional("STREAM_SIGNING_KEY"),
+            stream_signing_key_previous: optional("STREAM_SIGNING_KEY_PREVIOUS"),
+            stream_signature_skew_secs: parse_or("STREAM_SIGNATURE_SKEW_SECS", 30)?,
+            outbound_proxy: optional("ALL_PROXY")
+                .or_else(|| optional("HTTPS_PROXY"))
+                .or_else(|| optional("HTTP_PROXY"))
+                .map(|p| if p.contains("://") { p } else { format!("http://{p}") }),
+            proxy_hosts: list("PROXY_HOSTS", "")
+                .into_iter()
+                .map(|h| h.to_ascii_lowercase())
+                .collect(),
+            opensubtitles_api_key: optional("OPENSUBTITLES_API_KEY"),
+            omdb_timeout_secs: parse_or("OMDB_TIMEOUT_SECS", 8)?,
+            tmdb_timeout_secs: parse_or("TMDB_TIMEOUT_SECS", 8)?,
+            torrentio_timeout_secs: parse_or("TORRENTIO_TIMEOUT_SECS", 20)?,
+            opensubtitles_timeout_secs: parse_or("OPENSUBTITLES_TIMEOUT_SECS", 10)?,
+            request_deadline_ms: parse_or("REQUEST_DEADLINE_MS", 15_000)?,
+            request_deadline_routes: list(
+                "REQUEST_DEADLINE_ROUTES",
+                "/torrentio=30000,/play=30000,/movies/trending=30000,/trending=30000",
+            )
+            .iter()
+            .map(|entry| {
+                let (prefix, ms) = entry.split_once('=').unwrap_or((entry, ""));
+                Ok((prefix.trim().to_string(), parse("REQUEST_DEADLINE_ROUTES", ms.trim())?))
+            })
+            .collect::<io::Result<_>>()?,
+            metadata_priority: metadata_priority()?,
+            trending_filter_max_pages: parse_or("TRENDING_FILTER_MAX_PAGES", 5)?,
+            trending_concurrency: parse_or("TRENDING_CONCURRENCY", 8)?,
+            trending_title_timeout_ms: parse_or("TRENDING_TITLE_TIMEOUT_MS", 5000)?,
+            search_enrich_budget_ms: parse_or("SEARCH_ENRICH_BUDGET_MS", 1500)?,
+            omdb_daily_limit: parse_or("OMDB_DAILY_LIMIT", 1000)?,
+            library_refresh_max: parse_or("LIBRARY_REFRESH_MAX", 200)?,
+            max_upstream_body_bytes: parse_or("MAX_UPSTREAM_BODY_BYTES", 8 * 1024 * 1024)?,
+            playable_enrichment: flag("PLAYABLE_ENRICHMENT", true),
+            verify_posters: parse_or("VERIFY_POSTERS", PosterCheck::Off)?,
+            audio_max_extractions: parse_or("AUDIO_MAX_EXTRACTIONS", 2)?,
+            hls_max_transcodes: parse_or("HLS_MAX_TRANSCODES", 2)?,
+            media_workers: parse_or("MEDIA_WORKERS", 2)?,
+            media_queue_max: parse_or("MEDIA_QUEUE_MAX", 32)?,
+            media_job_timeout_secs: parse_or("MEDIA_JOB_TIMEOUT_SECS", 60)?,
+            media_wait_secs: parse_or("MEDIA_WAIT_SECS", 15)?,
+            shed_max_in_flight: parse_or("SHED_MAX_IN_FLIGHT", 512)?,
+            shed_p95_ms: parse_or("SHED_P95_MS", 5000)?,
+            shed_queue_depth: parse_or("SHED_QUEUE_DEPTH", 1024)?,
+            speedtest_max_bytes: parse_or("SPEEDTEST_MAX_BYTES", 100_000_000)?,
+            speedtest_per_minute: parse_or("SPEEDTEST_PER_MINUTE", 6)?,
+            rate_limit_per_minute: parse_or("RATE_LIMIT_PER_MINUTE", 600)?,
+            rate_limit_burst: parse_or("RATE_LIMIT_BURST", 120)?,
+            rate_limit_downloads_per_hour: parse_or("RATE_LIMIT_DOWNLOADS_PER_HOUR", 30)?,
+            scratch_dir,
+            scratch_idle_ttl_minutes: parse_or("SCRATCH_IDLE_TTL_MINUTES", 60)?,
+            scratch_budget_bytes: parse_or("SCRATCH_BUDGET_BYTES", 5 * 1024 * 1024 * 1024)?,
+            party_idle_minutes: parse_or("PARTY_IDLE_MINUTES", 30)?,
+            telegram_bot_token: optional("TELEGRAM_BOT_TOKEN"),
+            telegram_chat_id: optional("TELEGRAM_CHAT_ID"),
+            admin_token: optional("ADMIN_TOKEN"),
+            api_keys,
+            auth_mode,
+            trusted_proxies,
+            auth_user_header: optional("AUTH_USER_HEADER").unwrap_or_else(|| "Remote-User".into()),
+            auth_groups_header: optional("AUTH_GROUPS_HEADER").unwrap_or_else(|| "Remote-Groups".into()),
+            auth_admin_group: optional("AUTH_ADMIN_GROUP").unwrap_or_else(|| "admins".into()),
+            audit_max_entries: parse_or("AUDIT_MAX_ENTRIES", 50_000)?,
+            allow_cache_bypass: flag("ALLOW_CACHE_BYPASS", false),
+            cache_backend,
+            redis_url,
+            redis_key_prefix: optional("REDIS_KEY_PREFIX").unwrap_or_else(|| "rossoflix:".into()),
+            cache_ttl_search_secs: parse_or("CACHE_TTL_SEARCH", 300)?,
+            cache_ttl_detail_secs: parse_or("CACHE_TTL_DETAIL", 24 * 3600)?,
+            cache_ttl_trending_secs: parse_or("CACHE_TTL_TRENDING", 600)?,
+            cache_ttl_torrentio_secs: parse_or("CACHE_TTL_TORRENTIO", 60)?,
+            legacy_error_body: flag("LEGACY_ERROR_BODY", false),
+            preferred_audio_lang: match optional("PREFERRED_AUDIO_LANG") {
+                Some(raw) => Some(parse("PREFERRED_AUDIO_LANG", &raw)?),
+                None => None,
+            },
+            watch_dir: optional("WATCH_DIR").map(PathBuf::from),
+            watch_interval_secs: parse_or("WATCH_INTERVAL_SECS", 5)?,
+            watched_threshold_percent,
+            delete_after_watch: flag("DELETE_AFTER_WATCH", false),
+            stats_enabled: flag("STATS_ENABLED", false),
+            stats_raw_retention_hours: parse_or("STATS_RAW_RETENTION_HOURS", 48)?,
+        })
+    }
+
+    /// TTL base das entradas de `category` no cache de respostas.
+    pub fn cache_ttl(&self, category: CacheCategory) -> Duration {
+        Duration::from_secs(match category {
+            CacheCategory::Search => self.cache_ttl_search_secs,
+            CacheCategory::Detail => self.cache_ttl_detail_secs,
+            CacheCategory::Trending => self.cache_ttl_trending_secs,
+            CacheCategory::Torrentio => self.cache_ttl_torrentio_secs,
+        })
+    }
+}
+
+fn required(name: &str) -> io::Result<String> {
+    optional(name).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Defina {name} no ambiente (.env)"),
+        )
+    })
+}
+
+/// Variável presente e não vazia.
+fn optional(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+fn parse<T>(name: &str, raw: &str) -> io::Result<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    raw.parse().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{name} inválido ({raw}): {e}"),
+        )
+    })
+}
+
+fn parse_or<T>(name: &str, default: T) -> io::Result<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    match optional(name) {
+        Some(raw) => parse(name, &raw),
+        None => Ok(default),
+    }
+}
+
+/// `off`/`false`/`0`/`no` desligam; qualquer outro valor liga.
+fn flag(name: &str, default: bool) -> bool {
+    match optional(name) {
+        Some(v) => !matches!(v.to_ascii_lowercase().as_str(), "off" | "false" | "0" | "no"),
+        None => default,
+    }
+}
+
+/// URL de uma API, sem `/` no fim.
+fn base_url(name: &str, default: &str) -> String {
+    optional(name).as_deref().unwrap_or(default).trim_end_matches('/').to_string()
+}
+
+/// Lista separada por vírgulas; string vazia explícita desativa a lista.
+fn list(name: &str, default: &str) -> Vec<String> {
+    std::env::var(name)
+        .unwrap_or_else(|_| default.to_string())
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// `METADATA_PRIORITY`: provedores conhecidos, sem repetição.
+fn metadata_priority() -> io::Result<Vec<String>> {
+    let priority: Vec<String> = list("METADATA_PRIORITY", "tmdb,omdb")
+        .into_iter()
+        .map(|p| p.to_ascii_lowercase())
+        .collect();
+    for (i, name) in priority.iter().enumerate() {
+        if !crate::metadata::PROVIDERS.contains(&name.as_str()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "METADATA_PRIORITY: provedor desconhecido {name} (disponíveis: {})",
+                    crate::metadata::PROVIDERS.join(", ")
+                ),
+            ));
+        }
+        if priority[..i].contains(name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("METADATA_PRIORITY: {name} repetido"),
+            ));
+        }
+    }
+    Ok(priority)
+}
+
+/// `API_KEYS` (`nome:chave` separados por vírgula) e as do arquivo de
+/// configuração, sem nome repetido. Os erros não mostram a chave.
+fn api_keys(from_file: HashMap<String, String>) -> io::Result<Vec<ApiKey>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    let mut keys: Vec<ApiKey> = list("API_KEYS", "")
+        .iter()
+        .map(|raw| raw.parse().map_err(|e| invalid(format!("API_KEYS: {e}"))))
+        .collect::<io::Result<_>>()?;
+    let mut from_file: Vec<_> = from_file.into_iter().collect();
+    from_file.sort();
+    for (name, key) in from_file {
+        keys.push(ApiKey::new(&name, &key).map_err(|e| invalid(format!("api_keys.{name}: {e}")))?);
+    }
+    for (i, key) in keys.iter().enumerate() {
+        if keys[..i].iter().any(|k| k.name == key.name) {
+            return Err(invalid(format!("chave de API {} repetida", key.name)));
+        }
+    }
+    Ok(keys)
+}
+
+/// Perfis embutidos, sobrescritos (ou estendidos) pelo arquivo de configuração.
+fn device_profiles(overrides: HashMap<String, Vec<String>>) -> HashMap<String, Vec<String>> {
+    let defaults: [(&str, &[&str]); 3] = [
+        ("browser", &["h264", "vp9", "av1"]),
+        ("chromecast", &["h264", "h265", "vp9", "hdr10", "hlg"]),
+        ("webos", &["h264", "h265", "vp9", "av1", "hdr10", "hlg", "dv", "10bit"]),
+    ];
+    let mut profiles: HashMap<String, Vec<String>> = defaults
+        .into_iter()
+        .map(|(name, caps)| (name.to_string(), caps.iter().map(|c| c.to_string()).collect()))
+        .collect();
+    for (name, caps) in overrides {
+        profiles.insert(
+            name.to_ascii_lowercase(),
+            caps.into_iter().map(|c| c.to_ascii_lowercase()).collect(),
+        );
+    }
+    profiles
+}
+
+fn playback_profiles(overrides: HashMap<String, DeviceProfile>) -> HashMap<String, DeviceProfile> {
+    let mut profiles: HashMap<String, DeviceProfile> = DeviceProfile::defaults()
+        .into_iter()
+        .map(|(name, p)| (name.to_string(), p))
+        .collect();
+    for (name, profile) in overrides {
+        profiles.insert(name.to_ascii_lowercase(), profile);
+    }
+    profiles
+}
@@ -0,0 +1,95 @@
+use std::{fs::File, io, path::Path};
+
+use serde::Serialize;
+
+/// Bytes do início do arquivo lidos para reconhecer o formato.
+const SNIFF_LEN: usize = 64;
+/// Sem pista nenhuma.
+const UNKNOWN: &str = "application/octet-stream";
+
+/// De onde veio o Content-Type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DetectedBy {
+    /// Assinatura nos bytes iniciais (`infer`).
+    Magic,
+    Extension,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Detected {
+    pub mime: &'static str,
+    pub by: DetectedBy,
+}
+
+impl Detected {
+    /// O contêiner, nos nomes de [`MediaInfo`](crate::media::MediaInfo).
+    pub fn container(&self) -> Option<&'static str> {
+        let container = match self.mime {
+            "video/x-matroska" => "mkv",
+            "video/webm" => "webm",
+            "video/mp4" | "video/x-m4v" => "mp4",
+            "video/x-msvideo" => "avi",
+            "video/quicktime" => "mov",
+            "video/x-ms-wmv" => "wmv",
+            "video/mp2t" => "ts",
+            _ => return None,
+        };
+        Some(container)
+    }
+}
+
+/// Pelos bytes iniciais, se forem de áudio ou vídeo conhecido; senão pela
+/// extensão; senão qualquer assinatura reconhecida.
+pub fn detect(path: &Path, head: &[u8]) -> Detected {
+    let magic = infer::get(head);
+    if let Some(kind) = magic
+        && matches!(kind.matcher_type(), infer::MatcherType::Video | infer::MatcherType::Audio)
+    {
+        return Detected { mime: kind.mime_type(), by: DetectedBy::Magic };
+    }
+    if let Some(mime) = from_extension(path) {
+        return Detected { mime, by: DetectedBy::Extension };
+    }
+    match magic {
+        Some(kind) => Detected { mime: kind.mime_type(), by: DetectedBy::Magic },
+        None => Detected { mime: UNKNOWN, by: DetectedBy::Unknown },
+    }
+}
+
+/// Só pela extensão, para arquivos cujo começo ainda pode não estar no
+/// disco (o `.partial` de um download em andamento).
+pub fn detect_by_name(path: &Path) -> Detected {
+    detect(path, &[])
+}
+
+/// Lê o começo de `file` e detecta. Bloqueante.
+pub fn sniff(path: &Path, file: &File) -> io::Result<Detected> {
+    let mut head = [0; SNIFF_LEN];
+    let read = read_head(file, &mut head)?;
+    Ok(detect(path, &head[..read]))
+}
+
+/// O `mime_guess` não conhece as legendas ASS e dá o `.ts` como o MPEG-TS
+/// da DLNA, que os players não reconhecem.
+fn from_extension(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+        "ts" | "m2ts" => Some("video/mp2t"),
+        "ass" | "ssa" => Some("text/x-ssa"),
+        ext => mime_guess::from_ext(ext).first_raw(),
+    }
+}
+
+#[cfg(unix)]
+fn read_head(file: &File, buf: &mut [u8]) -> io::Result<usize> {
+    use std::os::unix::fs::FileExt;
+    file.read_at(buf, 0)
+}
+
+#[cfg(windows)]
+fn read_head(file: &File, buf: &mut [u8]) -> io::Result<usize> {
+    use std::os::windows::fs::FileExt;
+    file.seek_read(buf, 0)
+}
@@ -0,0 +1,316 @@
+use std::{
+    fmt::Write,
+    io,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{Connection, OpenFlags, params};
+use tracing::info;
+
+use crate::ApiError;
+
+/// Uma migração do esquema, embutida no binário.
+pub struct Migration {
+    /// O que ela faz, para o `db migrate`.
+    pub description: &'static str,
+    sql: &'static str,
+}
+
+/// Migrações em ordem. A versão do banco é quantas já rodaram (`PRAGMA
+/// user_version`); `schema_version` guarda quando cada uma rodou.
+const MIGRATIONS: &[Migration] = &[
+    // 1
+    Migration {
+        description: "marcadores de intro/recap/créditos por título (e temporada)",
+        sql: "CREATE TABLE markers (
+            imdb_id    TEXT    NOT NULL,
+            season     INTEGER NOT NULL DEFAULT 0,
+            profile    TEXT    NOT NULL DEFAULT '',
+            kind       TEXT    NOT NULL,
+            start_secs REAL    NOT NULL,
+            end_secs   REAL    NOT NULL,
+            updated_at INTEGER NOT NULL,
+            PRIMARY KEY (imdb_id, season, profile, kind)
+        );",
+    },
+    // 2
+    Migration {
+        description: "lista de títulos acompanhados por perfil (calendário)",
+        sql: "CREATE TABLE watchlist (
+            profile  TEXT    NOT NULL DEFAULT '',
+            imdb_id  TEXT    NOT NULL,
+            added_at INTEGER NOT NULL,
+            PRIMARY KEY (profile, imdb_id)
+        );",
+    },
+    // 3
+    Migration {
+        description: "fila de avisos (Telegram) com novas tentativas",
+        sql: "CREATE TABLE notifications (
+            id              INTEGER PRIMARY KEY AUTOINCREMENT,
+            destination     TEXT    NOT NULL,
+            text            TEXT    NOT NULL,
+            created_at      INTEGER NOT NULL,
+            next_attempt_at INTEGER NOT NULL,
+            attempts        INTEGER NOT NULL DEFAULT 0,
+            last_error      TEXT,
+            delivered_at    INTEGER
+        );
+        CREATE INDEX notifications_pending ON notifications (delivered_at, next_attempt_at);",
+    },
+    // 4
+    Migration {
+        description: "trilha de auditoria das rotas de admin e dos DELETE",
+        sql: "CREATE TABLE audit_log (
+            id                INTEGER PRIMARY KEY AUTOINCREMENT,
+            at                INTEGER NOT NULL,
+            request_id        TEXT,
+            token_fingerprint TEXT,
+            admin             INTEGER NOT NULL,
+            client_ip         TEXT,
+            method            TEXT    NOT NULL,
+            path              TEXT    NOT NULL,
+            params            TEXT,
+            status            INTEGER NOT NULL,
+            duration_ms       INTEGER NOT NULL
+        );
+        CREATE INDEX audit_log_at ON audit_log (at);",
+    },
+    // 5
+    Migration {
+        description: "links de convidado para um título (/share)",
+        sql: "CREATE TABLE shares (
+            id         TEXT    PRIMARY KEY,
+            imdb_id    TEXT,
+            magnet     TEXT    NOT NULL,
+            filename   TEXT    NOT NULL,
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            max_uses   INTEGER NOT NULL,
+            uses       INTEGER NOT NULL DEFAULT 0,
+            revoked_at INTEGER
+        );",
+    },
+    // 6
+    Migration {
+        description: "catálogo local dos títulos já resolvidos no OMDb, com busca FTS5",
+        sql: "CREATE TABLE catalog (
+            imdb_id     TEXT    PRIMARY KEY,
+            title       TEXT    NOT NULL,
+            year        TEXT,
+            kind        TEXT,
+            genres      TEXT    NOT NULL DEFAULT '',
+            plot        TEXT,
+            poster      TEXT,
+            imdb_rating REAL,
+            ratings     TEXT    NOT NULL DEFAULT '[]',
+            updated_at  INTEGER NOT NULL
+        );
+        CREATE INDEX catalog_updated ON catalog (updated_at);
+        CREATE VIRTUAL TABLE catalog_fts USING fts5(
+            imdb_id UNINDEXED, title, genres, plot,
+            tokenize = 'unicode61 remove_diacritics 2'
+        );",
+    },
+    // 7
+    // `file_index` -1 é o arquivo de índice desconhecido
+    Migration {
+        description: "índice dos downloads (antes dedup-index.json e <infohash>.title.json)",
+        sql: "CREATE TABLE download_files (
+            info_hash  TEXT    NOT NULL,
+            file_index INTEGER NOT NULL DEFAULT -1,
+            path       TEXT    NOT NULL,
+            size_bytes INTEGER NOT NULL,
+            stored_at  INTEGER NOT NULL,
+            aliases    TEXT    NOT NULL DEFAULT '[]',
+            PRIMARY KEY (info_hash, file_index)
+        );
+        CREATE INDEX download_files_path ON download_files (path);
+        CREATE TABLE download_titles (
+            info_hash  TEXT    PRIMARY KEY,
+            imdb_id    TEXT    NOT NULL,
+            season     INTEGER,
+            episode    INTEGER,
+            updated_at INTEGER NOT NULL
+        );
+        CREATE INDEX download_titles_imdb ON download_titles (imdb_id);",
+    },
+    // 8
+    // altera uma tabela existente: `audit_log` ganha a coluna `user`
+    Migration {
+        description: "perfis dos usuários do proxy e o usuário na trilha de auditoria",
+        sql: "CREATE TABLE profiles (
+            name         TEXT    PRIMARY KEY,
+            groups       TEXT    NOT NULL DEFAULT '',
+            created_at   INTEGER NOT NULL,
+            last_seen_at INTEGER NOT NULL
+        );
+        ALTER TABLE audit_log ADD COLUMN user TEXT;",
+    },
+    // 9
+    Migration {
+        description: "velocidade medida por cliente (POST /speedtest/report)",
+        sql: "CREATE TABLE speedtests (
+            client      TEXT    PRIMARY KEY,
+            mbps        REAL    NOT NULL,
+            measured_at INTEGER NOT NULL
+        );",
+    },
+    // 10
+    Migration {
+        description: "títulos assistidos até o fim, por perfil (playback.completed)",
+        sql: "CREATE TABLE watched (
+            profile      TEXT    NOT NULL DEFAULT '',
+            imdb_id      TEXT    NOT NULL,
+            season       INTEGER NOT NULL DEFAULT 0,
+            episode      INTEGER NOT NULL DEFAULT 0,
+            path         TEXT,
+            completed_at INTEGER NOT NULL,
+            cleanup      INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (profile, imdb_id, season, episode)
+        );",
+    },
+    // 11
+    Migration {
+        description: "estatísticas locais: eventos crus e somas por hora",
+        sql: "CREATE TABLE stats_events (
+            id         INTEGER PRIMARY KEY,
+            at         INTEGER NOT NULL,
+            kind       TEXT    NOT NULL,
+            title      TEXT    NOT NULL DEFAULT '',
+            cause      TEXT    NOT NULL DEFAULT '',
+            aggregated INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX stats_events_pending ON stats_events (aggregated, at);
+        CREATE TABLE stats_hourly (
+            hour  INTEGER NOT NULL,
+            kind  TEXT    NOT NULL,
+            title TEXT    NOT NULL DEFAULT '',
+            cause TEXT    NOT NULL DEFAULT '',
+            count INTEGER NOT NULL,
+            PRIMARY KEY (hour, kind, title, cause)
+        );",
+    },
+];
+
+/// Banco SQLite local. Uma conexão só, usada fora das threads do runtime.
+#[derive(Clone)]
+pub struct Db {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Db {
+    /// Abre (ou cria) o banco e aplica as migrações pendentes. Recusa um
+    /// banco de uma versão mais nova que a deste binário.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        let mut conn = Connection::open(path).map_err(io::Error::other)?;
+        conn.pragma_update(None, "journal_mode", "WAL").map_err(io::Error::other)?;
+        migrate(&mut conn, path)?;
+        Ok(Db {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Roda `f` com a conexão numa thread de bloqueio.
+    pub async fn call<T, F>(&self, f: F) -> Result<T, ApiError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || f(&mut conn.lock().unwrap()))
+            .await
+            .map_err(|e| ApiError::Storage(e.to_string()))?
+            .map_err(|e| ApiError::Storage(e.to_string()))
+    }
+}
+
+/// Versão de um banco (quantas migrações já rodaram); `Err` se ela é mais
+/// nova que a deste binário, que não conhece o esquema dele.
+fn version(conn: &Connection, path: &Path) -> io::Result<usize> {
+    let current: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0)).map_err(io::Error::other)?;
+    if current > MIGRATIONS.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} está na versão {current} do esquema, mais nova que a deste binário ({}); atualize o rossoflix-api ou use outro DATABASE_PATH",
+                path.display(),
+                MIGRATIONS.len()
+            ),
+        ));
+    }
+    Ok(current)
+}
+
+/// Aplica as migrações pendentes numa transação só: ou o banco vai para a
+/// última versão, ou fica como estava.
+fn migrate(conn: &mut Connection, path: &Path) -> io::Result<()> {
+    let applied = version(conn, path)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default();
+    let run = |conn: &mut Connection| -> rusqlite::Result<()> {
+        let tx = conn.transaction()?;
+        tx.execute_batch(
+            "CREATE TABLE IF NOT EXISTS schema_version (
+                version     INTEGER PRIMARY KEY,
+                description TEXT    NOT NULL,
+                applied_at  INTEGER
+            );",
+        )?;
+        // bancos de antes do histórico: as já aplicadas entram sem data
+        for (i, migration) in MIGRATIONS[..applied].iter().enumerate() {
+            tx.execute(
+                "INSERT OR IGNORE INTO schema_version (version, description) VALUES (?1, ?2)",
+                params![i + 1, migration.description],
+            )?;
+        }
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+            tx.execute_batch(migration.sql)?;
+            tx.execute(
+                "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, ?2, ?3)",
+                params![i + 1, migration.description, now],
+            )?;
+        }
+        tx.pragma_update(None, "user_version", MIGRATIONS.len())?;
+        tx.commit()
+    };
+    run(conn).map_err(|e| io::Error::other(format!("falha ao migrar {}: {e}", path.display())))?;
+    if applied < MIGRATIONS.len() {
+        info!(path = %path.display(), from = applied, to = MIGRATIONS.len(), "banco migrado");
+    }
+    Ok(())
+}
+
+/// `rossoflix-api db migrate [--dry-run]`: mostra a versão do banco e as
+/// migrações pendentes e, sem `--dry-run`, aplica. Com `--dry-run` o banco
+/// não é criado nem alterado.
+pub fn migrate_command(path: &Path, dry_run: bool) -> io::Result<String> {
+    let current = if path.exists() {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(io::Error::other)?;
+        version(&conn, path)?
+    } else {
+        0
+    };
+    let mut out = String::new();
+    let _ = writeln!(out, "banco: {}", path.display());
+    let _ = writeln!(out, "versão: {current} (este binário: {})", MIGRATIONS.len());
+    if current == MIGRATIONS.len() {
+        out.push_str("nenhuma migração pendente\n");
+        return Ok(out);
+    }
+    out.push_str("pendentes:\n");
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+        let _ = writeln!(out, "  {:>3}  {}", i + 1, migration.description);
+    }
+    if dry_run {
+        out.push_str("nada aplicado (--dry-run)\n");
+    } else {
+        Db::open(path)?;
+        let _ = writeln!(out, "aplicadas; banco na versão {}", MIGRATIONS.len());
+    }
+    Ok(out)
+}
@@ -0,0 +1,313 @@
+use std::{path::Path, process::Stdio, time::Duration};
+
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use futures_util::future::join_all;
+use reqwest::{Client, Url};
+use serde::Serialize;
+use tokio::{fs, process::Command};
+
+use crate::{AppState, config::Config, engine::Engine, tracker};
+
+/// Abaixo disso o espaço livre em downloads vira aviso.
+pub const MIN_FREE_BYTES: u64 = 5 * 1024 * 1024 * 1024;
+/// Timeout de cada verificação HTTP.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(8);
+/// Título conhecido usado para validar as chaves.
+const PROBE_IMDB_ID: &str = "tt0133093";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    name: &'static str,
+    status: Status,
+    detail: String,
+    /// O que fazer quando não passa.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hint: Option<&'static str>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Check {
+            name,
+            status: Status::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, hint: &'static str) -> Self {
+        Check {
+            name,
+            status: Status::Warn,
+            detail: detail.into(),
+            hint: Some(hint),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, hint: &'static str) -> Self {
+        Check {
+            name,
+            status: Status::Fail,
+            detail: detail.into(),
+            hint: Some(hint),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    /// Pior resultado entre as verificações.
+    pub status: Status,
+    pub checks: Vec<Check>,
+}
+
+/// Roda todas as verificações em paralelo. `fail` só nas críticas (chaves,
+/// aria2c, downloads, DNS); o resto rebaixa para `warn`.
+pub async fn run(http: &Client, config: &Config) -> Report {
+    let (omdb, tmdb, torrentio, aria2c, ffmpeg, ffprobe, downloads, dns, udp) = tokio::join!(
+        check_omdb(http, config),
+        check_tmdb(http, config),
+        check_torrentio(http, config),
+        check_aria2c(config.download_engine),
+        check_tool("ffmpeg", "/media/audio fica indisponível: instale o ffmpeg"),
+        check_tool("ffprobe", "/play e /media/chapters ficam sem dados: instale o ffmpeg (traz o ffprobe)"),
+        check_downloads(&config.downloads_dir),
+        check_dns(config),
+        check_udp(config),
+    );
+    let checks = vec![omdb, tmdb, torrentio, aria2c, ffmpeg, ffprobe, downloads, dns, udp];
+    let status = checks.iter().map(|c| c.status).max().unwrap_or(Status::Pass);
+    Report { status, checks }
+}
+
+/// Texto para o terminal (`rossoflix-api doctor`).
+pub fn render(report: &Report) -> String {
+    let mut out = String::new();
+    for check in &report.checks {
+        let tag = match check.status {
+            Status::Pass => " OK ",
+            Status::Warn => "AVISO",
+            Status::Fail => "FALHA",
+        };
+        out.push_str(&format!("[{tag:^5}] {}: {}\n", check.name, check.detail));
+        if let Some(hint) = check.hint {
+            out.push_str(&format!("        → {hint}\n"));
+        }
+    }
+    out
+}
+
+/// `GET /admin/doctor`: o mesmo relatório em JSON; `503` se algo crítico falhar.
+pub async fn doctor(State(state): State<AppState>) -> impl IntoResponse {
+    let report = run(&state.http, &state.config()).await;
+    let status = if report.status == Status::Fail {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    (status, Json(report))
+}
+
+async fn check_omdb(http: &Client, config: &Config) -> Check {
+    const NAME: &str = "omdb";
+    let url = format!("{}/?apikey={}&i={PROBE_IMDB_ID}", config.omdb_base_url, config.omdb_api_key);
+    match get_json(http, &url).await {
+        Ok((status, body)) if status == 401 || body["Error"] == "Invalid API key!" => {
+            Check::fail(NAME, "chave recusada", "confira OMDB_API_KEY (e se ela já foi ativada pelo e-mail)")
+        }
+        Ok((_, body)) if body["Response"] == "True" => Check::pass(NAME, "chave válida"),
+        Ok((status, body)) => Check::fail(
+            NAME,
+            format!("status {status}: {}", body["Error"].as_str().unwrap_or("resposta inesperada")),
+            "o limite diário do plano gratuito pode ter estourado",
+        ),
+        Err(e) => Check::fail(NAME, e, "sem acesso a OMDB_BASE_URL: veja DNS, firewall e ALL_PROXY"),
+    }
+}
+
+async fn check_tmdb(http: &Client, config: &Config) -> Check {
+    const NAME: &str = "tmdb";
+    let url = format!("{}/configuration?api_key={}", config.tmdb_base_url, config.tmdb_api_key);
+    match get_json(http, &url).await {
+        Ok((200, _)) => Check::pass(NAME, "chave válida"),
+        Ok((401, _)) => Check::fail(NAME, "chave recusada", "confira TMDB_API_KEY (é a chave v3, não o token v4)"),
+        Ok((status, _)) => Check::fail(NAME, format!("status {status}"), "tente de novo; o TMDB pode estar instável"),
+        Err(e) => Check::fail(NAME, e, "sem acesso a TMDB_BASE_URL: veja DNS, firewall e ALL_PROXY"),
+    }
+}
+
+async fn check_torrentio(http: &Client, config: &Config) -> Check {
+    const NAME: &str = "torrentio";
+    let results = join_all(config.torrentio_base_urls.iter().map(|base| async move {
+        let ok = matches!(get_json(http, &format!("{base}/manifest.json")).await, Ok((200, _)));
+        (base.as_str(), ok)
+    }))
+    .await;
+    let down: Vec<_> = results.iter().filter(|(_, ok)| !ok).map(|(base, _)| *base).collect();
+    if down.is_empty() {
+        Check::pass(NAME, format!("{} espelho(s) no ar", results.len()))
+    } else if down.len() < results.len() {
+        Check::warn(NAME, format!("fora do ar: {}", down.join(", ")), "os outros espelhos de TORRENTIO_BASE_URL cobrem")
+    } else {
+        // sem torrentio a busca continua funcionando; só os streams somem
+        Check::warn(
+            NAME,
+            format!("nenhum espelho respondeu: {}", down.join(", ")),
+            "se o domínio é bloqueado na sua rede, use ALL_PROXY + PROXY_HOSTS ou outro espelho em TORRENTIO_BASE_URL",
+        )
+    }
+}
+
+async fn check_aria2c(engine: Engine) -> Check {
+    match version("aria2c").await {
+        Ok(v) => Check::pass("aria2c", v),
+        Err(e) if engine == Engine::Embedded => Check::pass("aria2c", format!("{e} (dispensado com DOWNLOAD_ENGINE=embedded)")),
+        Err(e) => Check::fail(
+            "aria2c",
+            e,
+            "instale o aria2 (apt install aria2) ou use DOWNLOAD_ENGINE=embedded: sem um dos dois não há downloads",
+        ),
+    }
+}
+
+async fn check_tool(name: &'static str, hint: &'static str) -> Check {
+    match version(name).await {
+        Ok(v) => Check::pass(name, v),
+        Err(e) => Check::warn(name, e, hint),
+    }
+}
+
+async fn check_downloads(dir: &Path) -> Check {
+    const NAME: &str = "downloads";
+    let probe = dir.join(".doctor-probe");
+    let writable = fs::create_dir_all(dir).await.is_ok() && fs::write(&probe, b"ok").await.is_ok();
+    let _ = fs::remove_file(&probe).await;
+    if !writable {
+        return Check::fail(
+            NAME,
+            format!("{} sem permissão de escrita", dir.display()),
+            "ajuste DOWNLOADS_DIR ou as permissões (no Docker, o dono do volume)",
+        );
+    }
+    match free_bytes(dir).await {
+        Some(free) if free < MIN_FREE_BYTES => Check::warn(
+            NAME,
+            format!("{}: só {} MiB livres", dir.display(), free / (1024 * 1024)),
+            "libere espaço ou esvazie a lixeira (GET /admin/trash)",
+        ),
+        Some(free) => Check::pass(NAME, format!("{}: {} GiB livres", dir.display(), free / (1024 * 1024 * 1024))),
+        None => Check::pass(NAME, format!("{} gravável (espaço livre desconhecido)", dir.display())),
+    }
+}
+
+async fn check_dns(config: &Config) -> Check {
+    const NAME: &str = "dns";
+    let mut hosts = Vec::new();
+    hosts.extend(
+        [&config.omdb_base_url, &config.tmdb_base_url]
+            .into_iter()
+            .chain(&config.torrentio_base_urls)
+            .filter_map(|base| Url::parse(base).ok()?.host_str().map(String::from)),
+    );
+    let resolved = join_all(hosts.iter().map(|host| async move {
+        tokio::net::lookup_host((host.as_str(), 443)).await.is_ok_and(|mut a| a.next().is_some())
+    }))
+    .await;
+    let failed: Vec<_> = hosts.iter().zip(resolved).filter(|(_, ok)| !ok).map(|(h, _)| h.as_str()).collect();
+    if failed.is_empty() {
+        Check::pass(NAME, format!("{} hosts resolvidos", hosts.len()))
+    } else if config.outbound_proxy.is_some() {
+        Check::warn(
+            NAME,
+            format!("não resolvidos: {}", failed.join(", ")),
+            "com proxy de saída a resolução pode ficar a cargo dele; confira as outras verificações",
+        )
+    } else {
+        Check::fail(
+            NAME,
+            format!("não resolvidos: {}", failed.join(", ")),
+            "confira o /etc/resolv.conf (ou o DNS do Docker); domínios bloqueados pedem ALL_PROXY",
+        )
+    }
+}
+
+async fn check_udp(config: &Config) -> Check {
+    const NAME: &str = "udp_trackers";
+    let trackers: Vec<_> = config
+        .bt_trackers
+        .iter()
+        .chain(&config.bt_trackers_fallback)
+        .filter(|t| t.starts_with("udp://"))
+        .collect();
+    if trackers.is_empty() {
+        return Check::warn(NAME, "nenhum tracker UDP configurado", "adicione trackers udp:// em BT_TRACKERS");
+    }
+    let results = join_all(trackers.iter().map(|t| tracker::probe(t))).await;
+    let answered = results.iter().filter(|r| r.is_ok()).count();
+    if answered > 0 {
+        Check::pass(NAME, format!("{answered}/{} trackers responderam", trackers.len()))
+    } else {
+        Check::warn(
+            NAME,
+            format!("nenhum dos {} trackers respondeu", trackers.len()),
+            "UDP de saída parece bloqueado: libere no firewall; os downloads ficam só com DHT e trackers HTTP",
+        )
+    }
+}
+
+async fn get_json(http: &Client, url: &str) -> Result<(u16, serde_json::Value), String> {
+    let resp = http
+        .get(url)
+        .timeout(HTTP_TIMEOUT)
+        .send()
+        .await
+        .map_err(describe)?;
+    let status = resp.status().as_u16();
+    let body = resp.json().await.unwrap_or_default();
+    Ok((status, body))
+}
+
+/// Erro de rede com a causa, sem a URL (que leva as chaves).
+fn describe(e: reqwest::Error) -> String {
+    let e = e.without_url();
+    match std::error::Error::source(&e) {
+        Some(cause) => format!("{e}: {cause}"),
+        None => e.to_string(),
+    }
+}
+
+/// Primeira linha de `<programa> --version` (`-version` no ffmpeg).
+async fn version(program: &str) -> Result<String, String> {
+    let flag = if program.starts_with("ff") { "-version" } else { "--version" };
+    let output = Command::new(program)
+        .arg(flag)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| format!("não encontrado no PATH: {e}"))?;
+    if !output.status.success() {
+        return Err(format!("{program} {flag} saiu com {}", output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string())
+}
+
+/// Espaço livre via `df` (sem dependência de libc).
+pub async fn free_bytes(dir: &Path) -> Option<u64> {
+    let output = Command::new("df").arg("-Pk").arg(dir).output().await.ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let kb: u64 = text.lines().nth(1)?.split_whitespace().nth(3)?.parse().ok()?;
+    Some(kb * 1024)
+}
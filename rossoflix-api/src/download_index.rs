@@ -0,0 +1,395 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::{info, warn};
+
+use crate::{ApiError, db::Db, magnet, stream_title::StreamTitle};
+
+/// Índice antigo em JSON, importado uma vez e renomeado para `.migrated`.
+const LEGACY_INDEX: &str = "dedup-index.json";
+/// Sufixo dos sidecars antigos `<infohash>.title.json`.
+const LEGACY_TITLE_SUFFIX: &str = ".title.json";
+/// `file_index` no banco quando o índice do arquivo no torrent é desconhecido
+/// (a chave primária não aceita NULL como valor distinto).
+const ANY_FILE: i64 = -1;
+
+/// Arquivo já baixado de um torrent: `<infohash>:<índice>` → caminho canônico.
+/// O índice do arquivo no torrent só é conhecido com o `.torrent` em mãos;
+/// vindo de magnet fica `None` e vale para torrents de um arquivo só.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredFile {
+    pub info_hash: String,
+    pub file_index: Option<usize>,
+    /// Relativo ao diretório de downloads.
+    pub path: String,
+    pub size_bytes: u64,
+    pub stored_at: u64,
+    /// Nomes pedidos depois e servidos a partir deste arquivo.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportCounts {
+    pub created: usize,
+    pub skipped: usize,
+    pub conflicting: usize,
+}
+
+/// Tudo o que o índice sabe de um infohash.
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexedDownload {
+    pub info_hash: String,
+    pub title: Option<StreamTitle>,
+    pub files: Vec<StoredFile>,
+}
+
+impl IndexedDownload {
+    /// Algum pedido deste infohash foi atendido por deduplicação?
+    pub fn is_deduplicated(&self) -> bool {
+        self.files.iter().any(|f| !f.aliases.is_empty())
+    }
+}
+
+/// Índice dos downloads no SQLite (tabelas `download_files` e
+/// `download_titles`): arquivos canônicos para a deduplicação e o título de
+/// cada infohash. Cada operação é uma instrução ou transação própria, então
+/// o fim de um download, o `DELETE`, o import e os streams podem gravar ao
+/// mesmo tempo sem perder atualizações.
+#[derive(Clone)]
+pub struct DownloadIndex {
+    db: Db,
+    base: PathBuf,
+}
+
+impl DownloadIndex {
+    /// Abre o índice e importa o `dedup-index.json` e os sidecars
+    /// `.title.json` de versões anteriores, se ainda existirem.
+    pub async fn open(db: Db, base: &Path) -> Self {
+        let index = DownloadIndex {
+            db,
+            base: base.to_path_buf(),
+        };
+        index.migrate_legacy().await;
+        index
+    }
+
+    fn relative(&self, path: &Path) -> String {
+        path.strip_prefix(&self.base).unwrap_or(path).to_string_lossy().into_owned()
+    }
+
+    /// Registra o arquivo recém-baixado como canônico (substitui um registro
+    /// cujo arquivo tenha sumido).
+    pub async fn record(&self, info_hash: &str, file_index: Option<usize>, path: &Path) -> Result<(), ApiError> {
+        let Ok(meta) = fs::metadata(path).await else {
+            return Ok(());
+        };
+        self.upsert(StoredFile {
+            info_hash: info_hash.to_string(),
+            file_index,
+            path: self.relative(path),
+            size_bytes: meta.len(),
+            stored_at: unix_now(),
+            aliases: Vec::new(),
+        })
+        .await
+    }
+
+    /// Grava `file` sob `(info_hash, file_index)`, trocando o registro que houver.
+    pub async fn upsert(&self, file: StoredFile) -> Result<(), ApiError> {
+        self.db
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT INTO download_files (info_hash, file_index, path, size_bytes, stored_at, aliases)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                     ON CONFLICT (info_hash, file_index) DO UPDATE SET
+                        path = excluded.path, size_bytes = excluded.size_bytes,
+                        stored_at = excluded.stored_at, aliases = excluded.aliases",
+                    params![
+                        file.info_hash,
+                        db_index(file.file_index),
+                        file.path,
+                        file.size_bytes as i64,
+                        file.stored_at as i64,
+                        serde_json::to_string(&file.aliases).unwrap_or_else(|_| "[]".into()),
+                    ],
+                )
+            })
+            .await
+            .map(drop)
+    }
+
+    /// Arquivo canônico já em disco para este infohash/índice. Sem índice,
+    /// só responde quando o infohash tem um único arquivo registrado.
+    pub async fn lookup(&self, info_hash: &str, file_index: Option<usize>) -> Result<Option<PathBuf>, ApiError> {
+        let info_hash = info_hash.to_string();
+        let path: Option<String> = self
+            .db
+            .call(move |conn| match file_index {
+                Some(i) => conn
+                    .query_row(
+                        "SELECT path FROM download_files WHERE info_hash = ?1 AND file_index = ?2",
+                        params![info_hash, i as i64],
+                        |row| row.get(0),
+                    )
+                    .optional(),
+                None => {
+                    let mut stmt = conn.prepare("SELECT path FROM download_files WHERE info_hash = ?1 LIMIT 2")?;
+                    let paths: Vec<String> = stmt.query_map(params![info_hash], |row| row.get(0))?.collect::<Result<_, _>>()?;
+                    Ok(match <[String; 1]>::try_from(paths) {
+                        Ok([only]) => Some(only),
+                        Err(_) => None,
+                    })
+                }
+            })
+            .await?;
+        Ok(path.map(|p| self.base.join(p)).filter(|p| p.is_file()))
+    }
+
+    /// Serve `canonical` sob `target`: hardlink quando possível (mesmo
+    /// sistema de arquivos), senão o próprio arquivo canônico.
+    pub async fn link(&self, info_hash: &str, canonical: &Path, target: &Path) -> PathBuf {
+        if let Some(parent) = target.parent() {
+            let _ = fs::create_dir_all(parent).await;
+        }
+        let served = match fs::hard_link(canonical, target).await {
+            Ok(()) => target.to_path_buf(),
+            Err(e) => {
+                warn!(canonical = %canonical.display(), "hardlink falhou, servindo o original: {e}");
+                canonical.to_path_buf()
+            }
+        };
+        info!(
+            id = info_hash,
+            canonical = %canonical.display(),
+            target = %target.display(),
+            "download deduplicado"
+        );
+        if let Err(e) = self.add_alias(&self.relative(canonical), &self.relative(target)).await {
+            warn!(canonical = %canonical.display(), "falha ao registrar o alias: {e}");
+        }
+        served
+    }
+
+    /// Acrescenta `alias` ao registro de `path`, sem repetir.
+    async fn add_alias(&self, path: &str, alias: &str) -> Result<(), ApiError> {
+        let (path, alias) = (path.to_string(), alias.to_string());
+        self.db
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE download_files SET aliases = json_insert(aliases, '$[#]', ?2)
+                     WHERE path = ?1 AND NOT EXISTS (SELECT 1 FROM json_each(aliases) WHERE value = ?2)",
+                    params![path, alias],
+                )
+            })
+            .await
+            .map(drop)
+    }
+
+    /// Título assistido a partir deste infohash (dicas de `/stream`).
+    pub async fn set_title(&self, info_hash: &str, title: &StreamTitle) -> Result<(), ApiError> {
+        let (info_hash, title) = (info_hash.to_string(), title.clone());
+        self.db
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT INTO download_titles (info_hash, imdb_id, season, episode, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5)
+                     ON CONFLICT (info_hash) DO UPDATE SET
+                        imdb_id = excluded.imdb_id, season = excluded.season,
+                        episode = excluded.episode, updated_at = excluded.updated_at",
+                    params![info_hash, title.imdb_id, title.season, title.episode, unix_now() as i64],
+                )
+            })
+            .await
+            .map(drop)
+    }
+
+    /// Esquece o infohash (arquivos e título); devolve quantos registros saíram.
+    pub async fn remove(&self, info_hash: &str) -> Result<usize, ApiError> {
+        let info_hash = info_hash.to_string();
+        self.db
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                let files = tx.execute("DELETE FROM download_files WHERE info_hash = ?1", params![info_hash])?;
+                let titles = tx.execute("DELETE FROM download_titles WHERE info_hash = ?1", params![info_hash])?;
+                tx.commit()?;
+                Ok(files + titles)
+            })
+            .await
+    }
+
+    /// Arquivos e título de um infohash; `None` se o índice não o conhece.
+    pub async fn by_info_hash(&self, info_hash: &str) -> Result<Option<IndexedDownload>, ApiError> {
+        let info_hash = info_hash.to_string();
+        self.db
+            .call(move |conn| {
+                let title = title_of(conn, &info_hash)?;
+                let files = files_of(conn, Some(&info_hash))?;
+                Ok((title.is_some() || !files.is_empty()).then_some(IndexedDownload { info_hash, title, files }))
+            })
+            .await
+    }
+
+    /// Downloads marcados com este título, por infohash.
+    pub async fn by_imdb_id(&self, imdb_id: &str) -> Result<Vec<IndexedDownload>, ApiError> {
+        let imdb_id = imdb_id.to_string();
+        self.db
+            .call(move |conn| {
+                let mut stmt =
+                    conn.prepare("SELECT info_hash FROM download_titles WHERE imdb_id = ?1 ORDER BY info_hash")?;
+                let hashes: Vec<String> = stmt.query_map(params![imdb_id], |row| row.get(0))?.collect::<Result<_, _>>()?;
+                hashes
+                    .into_iter()
+                    .map(|info_hash| {
+                        Ok(IndexedDownload {
+                            title: title_of(conn, &info_hash)?,
+                            files: files_of(conn, Some(&info_hash))?,
+                            info_hash,
+                        })
+                    })
+                    .collect()
+            })
+            .await
+    }
+
+    /// Cópia de todos os arquivos registrados, em ordem de chave.
+    pub async fn snapshot(&self) -> Result<Vec<StoredFile>, ApiError> {
+        self.db.call(|conn| files_of(conn, None)).await
+    }
+
+    /// Insere registros vindos de outra instância, sem sobrescrever: mesma
+    /// chave e mesmo caminho é ignorado, mesma chave com outro caminho é
+    /// conflito (fica o local).
+    pub async fn import(&self, incoming: Vec<StoredFile>) -> Result<ImportCounts, ApiError> {
+        self.db
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                let mut counts = ImportCounts::default();
+                for file in incoming {
+                    let inserted = tx.execute(
+                        "INSERT OR IGNORE INTO download_files (info_hash, file_index, path, size_bytes, stored_at, aliases)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                        params![
+                            file.info_hash,
+                            db_index(file.file_index),
+                            file.path,
+                            file.size_bytes as i64,
+                            file.stored_at as i64,
+                            serde_json::to_string(&file.aliases).unwrap_or_else(|_| "[]".into()),
+                        ],
+                    )?;
+                    if inserted > 0 {
+                        counts.created += 1;
+                        continue;
+                    }
+                    let existing: String = tx.query_row(
+                        "SELECT path FROM download_files WHERE info_hash = ?1 AND file_index = ?2",
+                        params![file.info_hash, db_index(file.file_index)],
+                        |row| row.get(0),
+                    )?;
+                    if existing == file.path {
+                        counts.skipped += 1;
+                    } else {
+                        counts.conflicting += 1;
+                    }
+                }
+                tx.commit()?;
+                Ok(counts)
+            })
+            .await
+    }
+
+    /// Importa o que as versões anteriores gravavam em arquivos soltos.
+    async fn migrate_legacy(&self) {
+        let legacy = self.base.join(LEGACY_INDEX);
+        if let Ok(bytes) = fs::read(&legacy).await {
+            let entries: std::collections::BTreeMap<String, StoredFile> =
+                serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                    warn!("índice de deduplicação antigo ilegível, ignorando: {e}");
+                    Default::default()
+                });
+            match self.import(entries.into_values().collect()).await {
+                Ok(counts) => {
+                    info!(?counts, "índice de deduplicação antigo importado");
+                    let _ = fs::rename(&legacy, legacy.with_extension("json.migrated")).await;
+                }
+                Err(e) => warn!("falha ao importar {}: {e}", legacy.display()),
+            }
+        }
+
+        let Ok(mut dir) = fs::read_dir(&self.base).await else {
+            return;
+        };
+        while let Ok(Some(entry)) = dir.next_entry().await {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some(info_hash) = name.strip_suffix(LEGACY_TITLE_SUFFIX).filter(|h| magnet::is_info_hash(h)) else {
+                continue;
+            };
+            let Some(title) = fs::read(entry.path())
+                .await
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<StreamTitle>(&bytes).ok())
+            else {
+                continue;
+            };
+            match self.set_title(info_hash, &title).await {
+                Ok(()) => {
+                    let _ = fs::remove_file(entry.path()).await;
+                }
+                Err(e) => warn!(id = info_hash, "falha ao importar o título: {e}"),
+            }
+        }
+    }
+}
+
+fn db_index(file_index: Option<usize>) -> i64 {
+    file_index.map_or(ANY_FILE, |i| i as i64)
+}
+
+fn title_of(conn: &Connection, info_hash: &str) -> rusqlite::Result<Option<StreamTitle>> {
+    conn.query_row(
+        "SELECT imdb_id, season, episode FROM download_titles WHERE info_hash = ?1",
+        params![info_hash],
+        |row| {
+            Ok(StreamTitle {
+                imdb_id: row.get(0)?,
+                season: row.get(1)?,
+                episode: row.get(2)?,
+            })
+        },
+    )
+    .optional()
+}
+
+/// Arquivos do infohash, ou de todos com `None`.
+fn files_of(conn: &Connection, info_hash: Option<&str>) -> rusqlite::Result<Vec<StoredFile>> {
+    let mut stmt = conn.prepare(
+        "SELECT info_hash, file_index, path, size_bytes, stored_at, aliases FROM download_files
+         WHERE ?1 IS NULL OR info_hash = ?1 ORDER BY info_hash, file_index",
+    )?;
+    stmt.query_map(params![info_hash], |row| {
+        let file_index: i64 = row.get(1)?;
+        let aliases: String = row.get(5)?;
+        Ok(StoredFile {
+            info_hash: row.get(0)?,
+            file_index: (file_index != ANY_FILE).then_some(file_index as usize),
+            path: row.get(2)?,
+            size_bytes: row.get::<_, i64>(3)? as u64,
+            stored_at: row.get::<_, i64>(4)? as u64,
+            aliases: serde_json::from_str(&aliases).unwrap_or_default(),
+        })
+    })?
+    .collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
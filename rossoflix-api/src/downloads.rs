@@ -0,0 +1,877 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    Json,
+    extract::{
+        ConnectInfo, Multipart, Path as UrlPath, Query, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{StatusCode, header},
+    response::{
+        IntoResponse,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use futures_util::{Stream, stream};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs,
+    sync::{Semaphore, broadcast, watch},
+};
+
+use crate::{
+    ApiError, AppState, aria2,
+    auth::Identity,
+    config::Config,
+    engine::{self, Engine},
+    find_downloaded_file, find_partial_file,
+    magnet::{self, Magnet},
+    markers::check_imdb_id,
+    progress, rate_limit, slug,
+    torrent::{self, TorrentFile},
+    telegram, trash,
+};
+
+/// Cada download vive em `<downloads>/<infohash>/`, com o log do aria2c em
+/// `<downloads>/<infohash>.log`.
+pub fn job_dir(downloads_dir: &Path, info_hash: &str) -> PathBuf {
+    downloads_dir.join(info_hash)
+}
+
+pub fn log_path(downloads_dir: &Path, info_hash: &str) -> PathBuf {
+    downloads_dir.join(format!("{info_hash}.log"))
+}
+
+/// Sufixo do arquivo enquanto o aria2c grava. Só o nome final conta como
+/// baixado: o `.partial` é renomeado depois de o aria2c sair com sucesso e
+/// o tamanho conferir.
+pub const PARTIAL_SUFFIX: &str = ".partial";
+
+pub fn partial_name(filename: &str) -> String {
+    format!("{filename}{PARTIAL_SUFFIX}")
+}
+
+/// Caminho final de um `.partial` (o próprio caminho, se não for um).
+pub fn final_path(path: &Path) -> PathBuf {
+    match path.to_str().and_then(|p| p.strip_suffix(PARTIAL_SUFFIX)) {
+        Some(done) => PathBuf::from(done),
+        None => path.to_path_buf(),
+    }
+}
+
+/// De onde o aria2c obtém os metadados do torrent.
+#[derive(Debug, Clone, Copy)]
+pub enum Source<'a> {
+    Magnet(&'a Magnet),
+    Torrent(&'a TorrentFile),
+}
+
+impl Source<'_> {
+    pub fn info_hash(&self) -> &str {
+        match self {
+            Source::Magnet(m) => &m.info_hash,
+            Source::Torrent(t) => &t.info_hash,
+        }
+    }
+
+    /// Índice do arquivo no torrent, só conhecido com o `.torrent`.
+    pub fn file_index(&self, filename: &str) -> Option<usize> {
+        match self {
+            Source::Magnet(_) => None,
+            Source::Torrent(t) => t.file_index(filename),
+        }
+    }
+
+    /// Tamanho do arquivo segundo o `.torrent`; de magnet não se sabe.
+    fn file_size(&self, filename: &str) -> Option<u64> {
+        match self {
+            Source::Magnet(_) => None,
+            Source::Torrent(t) => t.file_index(filename).map(|i| t.files[i].size_bytes),
+        }
+    }
+}
+
+/// [`Source`] com os metadados próprios, para o download seguir numa task.
+enum Owned {
+    Magnet(Magnet),
+    Torrent(TorrentFile),
+}
+
+impl Owned {
+    fn source(&self) -> Source<'_> {
+        match self {
+            Owned::Magnet(m) => Source::Magnet(m),
+            Owned::Torrent(t) => Source::Torrent(t),
+        }
+    }
+}
+
+/// Resultado de um download, visto por todos os pedidos que esperam por ele.
+pub type Outcome = Result<(), Arc<aria2::DownloadFailure>>;
+
+/// Downloads em andamento por infohash. Um segundo pedido para o mesmo
+/// torrent (outro `/stream`, a pasta vigiada, a recuperação) se junta ao
+/// que já roda em vez de abrir outro aria2c sobre os mesmos arquivos.
+/// Além de `MAX_CONCURRENT_DOWNLOADS` aria2c ao mesmo tempo, os novos
+/// esperam na fila. Os eventos de todos os downloads (JSON já serializado)
+/// vão para quem acompanha `/ws/downloads`.
+#[derive(Clone)]
+pub struct Jobs {
+    table: Arc<Mutex<JobTable>>,
+    slots: Arc<Semaphore>,
+    events: broadcast::Sender<String>,
+}
+
+#[derive(Default)]
+struct JobTable {
+    running: HashMap<String, watch::Receiver<Option<Outcome>>>,
+    /// Última falha de cada torrent, até ele ser baixado de novo ou removido.
+    failed: HashMap<String, Arc<aria2::DownloadFailure>>,
+}
+
+impl Jobs {
+    /// `max` aria2c ao mesmo tempo; 0 sem limite.
+    pub fn new(max: usize) -> Self {
+        let max = if max == 0 { Semaphore::MAX_PERMITS } else { max };
+        Jobs { table: Default::default(), slots: Arc::new(Semaphore::new(max)), events: broadcast::channel(256).0 }
+    }
+
+    /// Manda `event` a quem acompanha; sem ninguém, é descartado.
+    fn emit(&self, event: serde_json::Value) {
+        let _ = self.events.send(event.to_string());
+    }
+
+    pub fn is_running(&self, id: &str) -> bool {
+        self.table.lock().unwrap().running.contains_key(id)
+    }
+
+    fn failure(&self, id: &str) -> Option<Arc<aria2::DownloadFailure>> {
+        self.table.lock().unwrap().failed.get(id).cloned()
+    }
+
+    pub fn forget(&self, id: &str) {
+        self.table.lock().unwrap().failed.remove(id);
+    }
+}
+
+/// Estado de um download em `/downloads/:job_id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Esperando vaga para o aria2c.
+    Queued,
+    Downloading,
+    Complete,
+    Failed,
+    /// Nem rodando nem completo: interrompido, à espera de um novo pedido.
+    Idle,
+}
+
+/// Estado de `id` pelo que está rodando, pela última falha e pelos
+/// arquivos em disco.
+fn job_state(state: &AppState, id: &str, files: &[DownloadFile]) -> JobState {
+    if state.progress.current(id).is_some() {
+        JobState::Downloading
+    } else if state.download_jobs.is_running(id) {
+        JobState::Queued
+    } else if state.download_jobs.failure(id).is_some() {
+        JobState::Failed
+    } else if !files.is_empty() && files.iter().all(|f| f.is_complete) {
+        JobState::Complete
+    } else {
+        JobState::Idle
+    }
+}
+
+/// Um pedido ligado a um download, novo ou já em andamento.
+pub struct Job {
+    pub id: String,
+    /// `false` quando este pedido disparou o download.
+    pub joined: bool,
+    rx: watch::Receiver<Option<Outcome>>,
+}
+
+impl Job {
+    /// Corpo do `202` de quem não espera o download: o id e onde acompanhar.
+    pub fn accepted(&self, state: &AppState, filename: &str) -> serde_json::Value {
+        let status_url = format!("/downloads/{}", self.id);
+        serde_json::json!({
+            "id": self.id,
+            "state": job_state(state, &self.id, &[]),
+            "joined": self.joined,
+            "filename": filename,
+            "events_url": format!("{status_url}/events"),
+            "progress_url": format!("{status_url}/progress"),
+            "status_url": status_url,
+        })
+    }
+
+    /// Espera o download terminar.
+    pub async fn finished(mut self) -> Outcome {
+        match self.rx.wait_for(Option::is_some).await {
+            Ok(outcome) => outcome.clone().expect("esperado só com o resultado"),
+            // a task caiu sem mandar o resultado
+            Err(_) => Err(Arc::new(aria2::DownloadFailure::new(aria2::Reason::Interrupted, "download interrompido"))),
+        }
+    }
+}
+
+/// Liga o pedido ao download de `source`: junta-se ao que já roda para o
+/// infohash ou dispara um novo numa task (veja [`run`]). O download segue
+/// mesmo que quem o disparou desista; só o cancelamento o interrompe.
+pub fn start(state: &AppState, source: Source<'_>, filename: &str, size_hint: Option<u64>) -> Job {
+    let id = source.info_hash().to_string();
+    let mut jobs = state.download_jobs.table.lock().unwrap();
+    if let Some(rx) = jobs.running.get(&id) {
+        return Job { id, joined: true, rx: rx.clone() };
+    }
+    let (tx, rx) = watch::channel(None);
+    jobs.running.insert(id.clone(), rx.clone());
+    jobs.failed.remove(&id);
+    state.download_jobs.emit(serde_json::json!({ "type": "queued", "id": id, "filename": filename }));
+    let owned = match source {
+        Source::Magnet(m) => Owned::Magnet(m.clone()),
+        Source::Torrent(t) => Owned::Torrent(t.clone()),
+    };
+    let (state, filename, job_id) = (state.clone(), filename.to_string(), id.clone());
+    tokio::spawn(async move {
+        let slot = state.download_jobs.slots.clone().acquire_owned().await;
+        state.download_jobs.emit(serde_json::json!({ "type": "started", "id": job_id, "filename": filename }));
+        let outcome = execute(&state, owned.source(), &filename, size_hint).await.map_err(Arc::new);
+        drop(slot);
+        let event = match &outcome {
+            Ok(()) => serde_json::json!({ "type": "completed", "id": job_id, "filename": filename }),
+            Err(failure) => {
+                serde_json::json!({ "type": "failed", "id": job_id, "filename": filename, "error": failure_json(failure) })
+            }
+        };
+        {
+            // sai do registro junto com o resultado: quem chegar depois já acha o arquivo
+            let mut jobs = state.download_jobs.table.lock().unwrap();
+            jobs.running.remove(&job_id);
+            if let Err(failure) = &outcome {
+                jobs.failed.insert(job_id, failure.clone());
+            }
+        }
+        state.download_jobs.emit(event);
+        state.storage.wake();
+        tx.send_replace(Some(outcome));
+    });
+    Job { id, joined: false, rx }
+}
+
+/// Baixa `filename` e espera o fim, junto com quem mais já estiver
+/// baixando o mesmo torrent (veja [`start`]).
+pub async fn run(state: &AppState, source: Source<'_>, filename: &str, size_hint: Option<u64>) -> Outcome {
+    start(state, source, filename, size_hint).finished().await
+}
+
+/// Roda o aria2c para `filename` no diretório do infohash, com o progresso
+/// registrado enquanto o processo estiver vivo. O aria2c grava em
+/// `<filename>.partial`, e só um download concluído (veja [`finalize`])
+/// ganha o nome final. Um `.torrent` é gravado num arquivo temporário só
+/// durante o download.
+async fn execute(
+    state: &AppState,
+    source: Source<'_>,
+    filename: &str,
+    size_hint: Option<u64>,
+) -> Result<(), aria2::DownloadFailure> {
+    let config = state.config();
+    let id = source.info_hash();
+    let dir = job_dir(&config.downloads_dir, id);
+    let log = log_path(&config.downloads_dir, id);
+    let progress = state.progress.track(id, dir.join(partial_name(filename)), size_hint);
+    forward_progress(state, id);
+
+    let result = match config.download_engine {
+        Engine::Aria2c => with_aria2c(&config, source, &dir, filename, &log, &progress).await,
+        Engine::Embedded => engine::download(&config, &dir, filename, source, &log, &progress).await,
+    };
+    let mut size = None;
+    let result = match result {
+        Ok(()) => match find_partial_file(&dir, filename).await {
+            Some(path) => finalize(state, id, source.file_index(filename), &path, source.file_size(filename)).await,
+            // torrent de vários arquivos: o aria2c usa os nomes do torrent
+            None => Ok(find_downloaded_file(&dir, filename).await),
+        },
+        Err(failure) => Err(failure),
+    }
+    .map(|done| size = done.and_then(|path| std::fs::metadata(path).ok()).map(|m| m.len()));
+    if state.telegram.is_some() {
+        let text = telegram::download_message(filename, &result, size, progress.cancel.is_cancelled());
+        state.outbox.enqueue("telegram", text);
+    }
+    result
+}
+
+/// Roda o aria2c para `source`; o `.torrent` vai por um arquivo temporário.
+async fn with_aria2c(
+    config: &Config,
+    source: Source<'_>,
+    dir: &Path,
+    filename: &str,
+    log: &Path,
+    progress: &progress::ProgressHandle,
+) -> Result<(), aria2::DownloadFailure> {
+    let (uri, temp) = match source {
+        Source::Magnet(magnet) => (magnet.to_uri(), None),
+        Source::Torrent(torrent) => {
+            let path = std::env::temp_dir().join(format!("rossoflix-{}.torrent", torrent.info_hash));
+            fs::write(&path, &torrent.raw).await.map_err(|e| {
+                aria2::DownloadFailure::new(aria2::Reason::Storage, format!("falha ao gravar o .torrent: {e}"))
+            })?;
+            (path.to_string_lossy().into_owned(), Some(path))
+        }
+    };
+    let result = aria2::download(config, dir, &partial_name(filename), &uri, log, progress).await;
+    if let Some(path) = temp {
+        let _ = fs::remove_file(path).await;
+    }
+    result
+}
+
+/// Repassa cada amostra de progresso de `id` a `/ws/downloads`, até o
+/// download sair do registro.
+fn forward_progress(state: &AppState, id: &str) {
+    let Some(mut rx) = state.progress.subscribe(id) else {
+        return;
+    };
+    let (jobs, id) = (state.download_jobs.clone(), id.to_string());
+    tokio::spawn(async move {
+        while rx.changed().await.is_ok() {
+            let progress = rx.borrow_and_update().clone();
+            jobs.emit(serde_json::json!({ "type": "progress", "id": id, "progress": progress }));
+        }
+    });
+}
+
+/// Conclui um download: confere o tamanho esperado (quando o `.torrent`
+/// diz), renomeia o `.partial` para o nome final e registra no índice. O
+/// rename é atômico: um crash antes dele deixa só o `.partial`, que nunca é
+/// servido como completo; depois dele, a recuperação registra o que faltar.
+pub async fn finalize(
+    state: &AppState,
+    id: &str,
+    file_index: Option<usize>,
+    partial: &Path,
+    expected_size: Option<u64>,
+) -> Result<Option<PathBuf>, aria2::DownloadFailure> {
+    let failure = |reason, output_tail: String| aria2::DownloadFailure::new(reason, output_tail);
+    let len = fs::metadata(partial)
+        .await
+        .map_err(|e| failure(aria2::Reason::Storage, format!("falha ao ler {}: {e}", partial.display())))?
+        .len();
+    if let Some(expected) = expected_size
+        && len != expected
+    {
+        return Err(failure(aria2::Reason::SizeMismatch, format!("arquivo com {len} bytes, esperados {expected}")));
+    }
+    let done = final_path(partial);
+    fs::rename(partial, &done)
+        .await
+        .map_err(|e| failure(aria2::Reason::Storage, format!("falha ao renomear {}: {e}", partial.display())))?;
+    if let Err(e) = state.downloads.record(id, file_index, &done).await {
+        tracing::warn!(id, "falha ao registrar o download no índice: {e}");
+    }
+    Ok(Some(done))
+}
+
+/// Cancela o aria2c de um download ativo. O `/stream` que o disparou
+/// responde com a falha; os arquivos parciais ficam para um `DELETE`.
+pub fn cancel(state: &AppState, job_id: &str) -> Result<(), ApiError> {
+    if state.progress.cancel(job_id) {
+        tracing::info!(id = job_id, "download cancelado");
+        Ok(())
+    } else {
+        Err(ApiError::NotFound(format!("download {job_id} não está ativo")))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DownloadEntry {
+    pub id: String,
+    pub files: Vec<DownloadFile>,
+    pub size_bytes: u64,
+    /// Há arquivos e nenhum ainda em download.
+    pub is_complete: bool,
+    log_available: bool,
+    summary: Option<aria2::Summary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DownloadFile {
+    pub name: String,
+    pub size_bytes: u64,
+    /// Com o nome final e sem `.aria2` ao lado; `.partial` e arquivos de
+    /// controle ficam `false`.
+    pub is_complete: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListParams {
+    imdb_id: Option<String>,
+}
+
+/// `GET /downloads?imdb_id=` — downloads conhecidos (um por infohash); com
+/// `imdb_id`, só os marcados com esse título no índice.
+pub async fn list_downloads(
+    State(state): State<AppState>,
+    Query(params): Query<ListParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut downloads = entries(&state.config().downloads_dir).await;
+    if let Some(imdb_id) = &params.imdb_id {
+        check_imdb_id(imdb_id)?;
+        let marked: Vec<String> = state.downloads.by_imdb_id(imdb_id).await?.into_iter().map(|d| d.info_hash).collect();
+        downloads.retain(|d| marked.contains(&d.id));
+    }
+    Ok(Json(serde_json::json!({ "downloads": downloads })))
+}
+
+/// Downloads em `base`, por infohash, com arquivos e resumo do log.
+pub async fn entries(base: &Path) -> Vec<DownloadEntry> {
+    let Ok(mut entries) = fs::read_dir(base).await else {
+        return Vec::new();
+    };
+
+    let mut ids = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let id = name.strip_suffix(".log").unwrap_or(&name);
+        if magnet::is_info_hash(id) && !ids.iter().any(|i| i == id) {
+            ids.push(id.to_string());
+        }
+    }
+    ids.sort();
+
+    let mut downloads = Vec::with_capacity(ids.len());
+    for id in ids {
+        let mut files = Vec::new();
+        collect_files(&job_dir(base, &id), &mut files).await;
+        let log = fs::read_to_string(log_path(base, &id)).await.ok();
+        downloads.push(DownloadEntry {
+            size_bytes: files.iter().map(|f| f.size_bytes).sum(),
+            is_complete: !files.is_empty() && files.iter().all(|f| f.is_complete),
+            files,
+            log_available: log.is_some(),
+            summary: log.as_deref().and_then(aria2::parse_summary),
+            id,
+        });
+    }
+    downloads
+}
+
+/// `POST /downloads/torrent` — multipart com o `.torrent` (campo `torrent`) e,
+/// opcionalmente, `filename`; sem ele baixamos o maior arquivo. O download
+/// segue em segundo plano e é acompanhado por `/downloads/:job_id`.
+pub async fn upload_torrent(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    identity: Identity,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut raw = None;
+    let mut filename = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?
+    {
+        match field.name() {
+            Some("torrent") => {
+                let bytes = field.bytes().await.map_err(|e| ApiError::BadRequest(e.to_string()))?;
+                if bytes.len() > torrent::MAX_TORRENT_BYTES {
+                    return Err(ApiError::BadRequest(".torrent grande demais".into()));
+                }
+                raw = Some(bytes.to_vec());
+            }
+            Some("filename") => {
+                let text = field.text().await.map_err(|e| ApiError::BadRequest(e.to_string()))?;
+                filename = Some(text.trim().to_string()).filter(|f| !f.is_empty());
+            }
+            _ => {}
+        }
+    }
+
+    let raw = raw.ok_or_else(|| ApiError::BadRequest("campo torrent ausente".into()))?;
+    let torrent = TorrentFile::parse(raw).map_err(ApiError::BadRequest)?;
+    // o nome dentro do torrent pode ter pastas: fica só o último trecho
+    let filename = filename.unwrap_or_else(|| torrent.largest_file().name.rsplit('/').next().unwrap_or_default().to_string());
+    check_filename(&filename)?;
+    let size = torrent.file_index(&filename).map(|i| torrent.files[i].size_bytes);
+    rate_limit::check_download(&state, &identity, client.ip(), &torrent.info_hash)?;
+
+    let body = serde_json::json!({
+        "id": torrent.info_hash,
+        "name": torrent.name,
+        "filename": filename,
+        "files": torrent.files,
+        "status_url": format!("/downloads/{}", torrent.info_hash),
+    });
+
+    tokio::spawn(async move {
+        if let Err(e) = run(&state, Source::Torrent(&torrent), &filename, size).await {
+            tracing::warn!(id = %torrent.info_hash, filename, "download do .torrent falhou: {e}");
+        }
+    });
+    Ok((StatusCode::ACCEPTED, Json(body)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewDownload {
+    /// Link magnet ou só o infohash.
+    magnet: String,
+    /// Nome do arquivo a baixar; sem ele, o `dn` do magnet.
+    filename: Option<String>,
+    /// Tamanho esperado, para o progresso antes do `.aria2` existir.
+    size_bytes: Option<u64>,
+}
+
+/// `POST /downloads` — começa o download de um magnet em segundo plano e
+/// responde na hora `202` com o id do job (o infohash). O estado
+/// (`queued`, `downloading`, `complete`, `failed`) fica em
+/// `/downloads/:job_id`. Um download já em andamento do mesmo torrent é
+/// reaproveitado (`"joined": true`).
+pub async fn create_download(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    identity: Identity,
+    Json(req): Json<NewDownload>,
+) -> Result<impl IntoResponse, ApiError> {
+    let magnet = Magnet::parse(&req.magnet).ok_or_else(|| ApiError::BadRequest("magnet inválido".into()))?;
+    let filename = match req.filename.as_deref().map(str::trim).filter(|f| !f.is_empty()) {
+        Some(name) => check_filename(name)?.to_string(),
+        None => slug::from_magnet(&magnet)
+            .ok_or_else(|| ApiError::BadRequest("informe filename: o magnet não tem dn".into()))?,
+    };
+    let dir = job_dir(&state.config().downloads_dir, &magnet.info_hash);
+    fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| ApiError::Storage(format!("não foi possível criar {}: {e}", dir.display())))?;
+
+    rate_limit::check_download(&state, &identity, client.ip(), &magnet.info_hash)?;
+    let job = start(&state, Source::Magnet(&magnet), &filename, req.size_bytes);
+    tracing::info!(id = job.id, joined = job.joined, filename, "download pedido");
+    let body = job.accepted(&state, &filename);
+    let location = format!("/downloads/{}", job.id);
+    Ok((StatusCode::ACCEPTED, [(header::LOCATION, location)], Json(body)))
+}
+
+/// `GET /downloads/:job_id` — estado de um download, com progresso quando ativo.
+pub async fn download_status(
+    State(state): State<AppState>,
+    UrlPath(job_id): UrlPath<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let job_id = parse_job_id(&job_id)?;
+    let base = &state.config().downloads_dir;
+    let progress = state.progress.current(&job_id);
+
+    let mut files = Vec::new();
+    collect_files(&job_dir(base, &job_id), &mut files).await;
+    let log = fs::read_to_string(log_path(base, &job_id)).await.ok();
+    let job = job_state(&state, &job_id, &files);
+    if job == JobState::Idle && files.is_empty() && log.is_none() {
+        return Err(ApiError::NotFound(format!("download {job_id} desconhecido")));
+    }
+
+    let indexed = state.downloads.by_info_hash(&job_id).await?;
+    Ok(Json(serde_json::json!({
+        "id": job_id,
+        "state": job,
+        "error": state.download_jobs.failure(&job_id).map(|f| failure_json(&f)),
+        "deduplicated": indexed.as_ref().is_some_and(|d| d.is_deduplicated()),
+        "title": indexed.and_then(|d| d.title),
+        "progress": progress,
+        "is_complete": !files.is_empty() && files.iter().all(|f| f.is_complete),
+        "files": files,
+        "log_available": log.is_some(),
+        "summary": log.as_deref().and_then(aria2::parse_summary),
+    })))
+}
+
+/// Motivo e detalhes de um download que falhou.
+fn failure_json(failure: &aria2::DownloadFailure) -> serde_json::Value {
+    serde_json::json!({
+        "reason": failure.reason,
+        "exit_code": failure.exit_code,
+        "message": failure.to_string(),
+    })
+}
+
+/// `GET /downloads/:job_id/progress` — só o progresso, barato para uma
+/// barra de progresso consultar: bytes, total, velocidade, ETA e peers do
+/// download ativo. Na fila ou depois da falha, só o estado (e o motivo);
+/// terminado, responde `complete` com 100%, para quem consulta não perder
+/// o fim.
+pub async fn download_progress(
+    State(state): State<AppState>,
+    UrlPath(job_id): UrlPath<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let job_id = parse_job_id(&job_id)?;
+    if let Some(progress) = state.progress.current(&job_id) {
+        let mut body = serde_json::to_value(progress).unwrap_or_default();
+        body["id"] = job_id.into();
+        body["state"] = "downloading".into();
+        return Ok(Json(body));
+    }
+
+    let mut files = Vec::new();
+    collect_files(&job_dir(&state.config().downloads_dir, &job_id), &mut files).await;
+    match job_state(&state, &job_id, &files) {
+        JobState::Queued => return Ok(Json(serde_json::json!({ "id": job_id, "state": JobState::Queued }))),
+        JobState::Failed => {
+            let error = state.download_jobs.failure(&job_id).map(|f| failure_json(&f));
+            return Ok(Json(serde_json::json!({ "id": job_id, "state": JobState::Failed, "error": error })));
+        }
+        JobState::Complete => {}
+        JobState::Downloading | JobState::Idle => {
+            return Err(ApiError::NotFound(format!("download {job_id} não está ativo")));
+        }
+    }
+    let size: u64 = files.iter().map(|f| f.size_bytes).sum();
+    Ok(Json(serde_json::json!({
+        "id": job_id,
+        "state": JobState::Complete,
+        "bytes_done": size,
+        "total_bytes": size,
+        "percent": 100.0,
+        "speed_bps": 0,
+        "eta_secs": 0,
+        "peers": null,
+        "seeders": null,
+    })))
+}
+
+/// `DELETE /downloads/:job_id` — move arquivos e log para a lixeira e tira o
+/// download do índice. Recusa com 409
+/// enquanto o aria2c roda ou há stream lendo algum arquivo do job.
+pub async fn delete_download(
+    State(state): State<AppState>,
+    UrlPath(job_id): UrlPath<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let job_id = parse_job_id(&job_id)?;
+    let base = &state.config().downloads_dir;
+    let dir = job_dir(base, &job_id);
+    let log = log_path(base, &job_id);
+    if !dir.exists() && !log.exists() {
+        return Err(ApiError::NotFound(format!("download {job_id} desconhecido")));
+    }
+    if state.progress.current(&job_id).is_some() {
+        return Err(ApiError::Conflict(format!("download {job_id} em andamento")));
+    }
+    let _lease = state
+        .leases
+        .exclusive(&dir)
+        .map_err(|busy| ApiError::Conflict(format!("{} em uso por um stream", busy.0.display())))?;
+
+    let trashed = trash::discard(base, &[dir.clone(), log])
+        .await
+        .map_err(|e| ApiError::Storage(format!("falha ao remover {}: {e}", dir.display())))?;
+    state.downloads.remove(&job_id).await?;
+    state.download_jobs.forget(&job_id);
+    tracing::info!(id = %job_id, trash = trashed.as_deref(), "download removido");
+    Ok(Json(serde_json::json!({ "id": job_id, "trash": trashed })))
+}
+
+/// `POST /downloads/:job_id/cancel` — interrompe o aria2c de um download ativo.
+pub async fn cancel_download(
+    State(state): State<AppState>,
+    UrlPath(job_id): UrlPath<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let job_id = parse_job_id(&job_id)?;
+    cancel(&state, &job_id)?;
+    Ok((StatusCode::ACCEPTED, Json(serde_json::json!({ "id": job_id, "state": "cancelling" }))))
+}
+
+/// `GET /downloads/:job_id/events` — progresso via SSE enquanto o aria2c roda.
+/// O stream termina com um evento `done` quando o processo sai.
+pub async fn download_events(
+    State(state): State<AppState>,
+    UrlPath(job_id): UrlPath<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let job_id = parse_job_id(&job_id)?;
+    let rx = state
+        .progress
+        .subscribe(&job_id)
+        .ok_or_else(|| ApiError::NotFound(format!("download {job_id} não está ativo")))?;
+
+    let events = stream::unfold(Some(rx), |rx| async move {
+        let mut rx = rx?;
+        if rx.changed().await.is_err() {
+            return Some((Ok(Event::default().event("done").data("{}")), None));
+        }
+        let progress = rx.borrow_and_update().clone();
+        let event = Event::default()
+            .event("progress")
+            .json_data(&progress)
+            .unwrap_or_default();
+        Some((Ok(event), Some(rx)))
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::new().interval(Duration::from_secs(15))))
+}
+
+/// `GET /ws/downloads` — eventos de todos os downloads em JSON: `queued`,
+/// `started`, `progress` (a cada amostra, com as peças em `pieces`),
+/// `completed` e `failed`. Quem conecta recebe antes um `snapshot` com os
+/// downloads ativos, e de novo se ficar para trás.
+pub async fn downloads_ws(State(state): State<AppState>, ws: WebSocketUpgrade) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| follow(state, socket))
+}
+
+async fn follow(state: AppState, mut socket: WebSocket) {
+    let mut rx = state.download_jobs.events.subscribe();
+    if socket.send(Message::Text(snapshot(&state))).await.is_err() {
+        return;
+    }
+    loop {
+        tokio::select! {
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+            outgoing = rx.recv() => {
+                let text = match outgoing {
+                    Ok(text) => text,
+                    // ficou para trás: basta o estado atual
+                    Err(broadcast::error::RecvError::Lagged(_)) => snapshot(&state),
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Os downloads ativos, na fila ou baixando, com o progresso de cada um.
+fn snapshot(state: &AppState) -> String {
+    let mut ids: Vec<String> = state.download_jobs.table.lock().unwrap().running.keys().cloned().collect();
+    ids.sort();
+    let downloads: Vec<_> = ids
+        .into_iter()
+        .map(|id| {
+            let progress = state.progress.current(&id);
+            let job = if progress.is_some() { JobState::Downloading } else { JobState::Queued };
+            serde_json::json!({ "id": id, "state": job, "progress": progress })
+        })
+        .collect();
+    serde_json::json!({ "type": "snapshot", "downloads": downloads }).to_string()
+}
+
+/// `GET /downloads/:job_id/log` — log do aria2c em texto puro.
+pub async fn download_log(
+    State(state): State<AppState>,
+    UrlPath(job_id): UrlPath<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let job_id = parse_job_id(&job_id)?;
+    let log = fs::read(log_path(&state.config().downloads_dir, &job_id))
+        .await
+        .map_err(|_| ApiError::NotFound(format!("sem log para {job_id}")))?;
+    Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], log))
+}
+
+pub fn parse_job_id(raw: &str) -> Result<String, ApiError> {
+    let id = raw.to_ascii_lowercase();
+    if magnet::is_info_hash(&id) {
+        Ok(id)
+    } else {
+        Err(ApiError::BadRequest("job_id inválido".into()))
+    }
+}
+
+/// `name` serve de nome de arquivo dentro da pasta do download? Sem
+/// separadores nem `.`/`..`, que o levariam para fora de `DOWNLOADS_DIR`.
+pub fn check_filename(name: &str) -> Result<&str, ApiError> {
+    if name.is_empty() || name.contains(['/', '\\', '\0']) || name == "." || name == ".." {
+        return Err(ApiError::BadRequest("filename não pode ter separadores de caminho".into()));
+    }
+    Ok(name)
+}
+
+/// Um arquivo concluído do download `job_id`: `filename` ou, sem ele, o
+/// maior. `409` se ele (ou o download, sem `filename`) ainda está baixando.
+pub async fn job_file(state: &AppState, job_id: &str, filename: Option<&str>) -> Result<PathBuf, ApiError> {
+    if let Some(name) = filename {
+        check_filename(name)?;
+    }
+    let dir = job_dir(&state.config().downloads_dir, job_id);
+    let mut files = Vec::new();
+    collect_paths(&dir, &mut files).await;
+    let mut candidates = Vec::new();
+    for (path, len) in files {
+        if filename.is_some_and(|name| path.file_name().is_none_or(|n| n != name)) {
+            continue;
+        }
+        if is_finalized(&path).await {
+            candidates.push((path, len));
+        } else if filename.is_some() {
+            return Err(ApiError::Conflict(format!("{} ainda está sendo baixado", filename.unwrap_or_default())));
+        }
+    }
+    match candidates.into_iter().max_by_key(|(_, len)| *len) {
+        Some((path, _)) => Ok(path),
+        None if state.progress.current(job_id).is_some() => {
+            Err(ApiError::Conflict(format!("download {job_id} ainda em andamento")))
+        }
+        None => Err(ApiError::NotFound(match filename {
+            Some(name) => format!("{name} não encontrado no download {job_id}"),
+            None => format!("download {job_id} sem arquivos concluídos"),
+        })),
+    }
+}
+
+async fn collect_paths(dir: &Path, out: &mut Vec<(PathBuf, u64)>) {
+    let Ok(mut entries) = fs::read_dir(dir).await else {
+        return;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let Ok(meta) = entry.metadata().await else {
+            continue;
+        };
+        if meta.is_dir() {
+            Box::pin(collect_paths(&entry.path(), out)).await;
+        } else {
+            out.push((entry.path(), meta.len()));
+        }
+    }
+}
+
+async fn collect_files(dir: &Path, out: &mut Vec<DownloadFile>) {
+    let Ok(mut entries) = fs::read_dir(dir).await else {
+        return;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        let Ok(meta) = entry.metadata().await else {
+            continue;
+        };
+        if meta.is_dir() {
+            Box::pin(collect_files(&path, out)).await;
+        } else {
+            out.push(DownloadFile {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                size_bytes: meta.len(),
+                is_complete: is_finalized(&path).await,
+            });
+        }
+    }
+}
+
+/// Arquivo concluído: existe, tem o nome final, não tem `.aria2` ao lado e
+/// não é ele mesmo um arquivo de controle.
+pub async fn is_finalized(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    !name.ends_with(PARTIAL_SUFFIX)
+        && !name.ends_with(".aria2")
+        && fs::try_exists(path).await.unwrap_or(false)
+        && !fs::try_exists(progress::control_file_path(path)).await.unwrap_or(false)
+}
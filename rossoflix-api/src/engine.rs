@@ -0,0 +1,1256 @@
+use std::{
+    collections::{HashSet, VecDeque, hash_map::RandomState},
+    hash::{BuildHasher, Hasher},
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use futures_util::{StreamExt, stream};
+use serde::{Deserialize, Serialize};
+use serde_bencode::value::Value;
+use sha1::{Digest, Sha1};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::{Notify, mpsc},
+    task::JoinSet,
+    time::{Instant, sleep, sleep_until, timeout},
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    aria2::{self, DownloadFailure, Reason},
+    config::Config,
+    downloads::{PARTIAL_SUFFIX, Source},
+    peer::{self, BLOCK_LEN, Message, Peer},
+    progress::{self, ProgressHandle},
+    tracker,
+};
+
+/// Quem baixa os torrents (`DOWNLOAD_ENGINE`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Engine {
+    /// O `aria2c` do `PATH`, um processo por download.
+    Aria2c,
+    /// O motor deste módulo, dentro do processo: dispensa o `aria2c`.
+    Embedded,
+}
+
+impl FromStr for Engine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "aria2c" | "aria2" => Ok(Engine::Aria2c),
+            "embedded" | "builtin" => Ok(Engine::Embedded),
+            other => Err(format!("esperado aria2c ou embedded, veio {other}")),
+        }
+    }
+}
+
+/// Conexões com peers ao mesmo tempo, por download.
+const MAX_PEERS: usize = 30;
+/// Buscas de metadados (magnet) ao mesmo tempo.
+const METADATA_CONCURRENCY: usize = 8;
+/// Blocos pedidos a um peer ainda sem resposta.
+const PIPELINE: usize = 16;
+/// Peer calado por mais que isso cai (o keep-alive é a cada 2 min).
+const PEER_IDLE: Duration = Duration::from_secs(150);
+/// Sem nenhuma peça nova (ou sem metadados) nesse tempo, o download falha
+/// com `timeout`.
+const STALL_TIMEOUT: Duration = Duration::from_secs(300);
+/// Limites do intervalo entre announces; dentro deles vale o do tracker.
+const ANNOUNCE_MIN: Duration = Duration::from_secs(30);
+const ANNOUNCE_MAX: Duration = Duration::from_secs(30 * 60);
+/// `left` dos announces antes dos metadados: qualquer valor não nulo serve
+/// para o tracker não nos tratar como seeder.
+const UNKNOWN_LEFT: u64 = 1 << 30;
+/// Maior peça aceita: cada conexão guarda a peça inteira na memória até
+/// conferir o SHA-1.
+const MAX_PIECE_LENGTH: u64 = 64 * 1024 * 1024;
+/// Linhas guardadas para o log por download.
+const MAX_NOTES: usize = 200;
+
+/// Baixa `filename` de `source` para `dir` sem processo externo. Os peers
+/// vêm dos trackers (os configurados e os do magnet ou do `.torrent`), os
+/// metadados de um magnet vêm dos próprios peers (BEP 9) e cada peça é
+/// conferida pelo SHA-1 antes de ir para o `.partial`, com um `.aria2` ao
+/// lado no formato que [`progress`] já lê. Como o aria2c com
+/// `--seed-time=0`, só baixa: não escuta nem semeia. Sem DHT, um magnet
+/// sem trackers depende dos configurados.
+///
+/// O arquivo pedido fica em `dir/<filename>.partial` para
+/// [`crate::downloads::finalize`]; sem ele no torrent, baixa todos com os
+/// caminhos do torrent, como o aria2c faz.
+pub async fn download(
+    config: &Config,
+    dir: &Path,
+    filename: &str,
+    source: Source<'_>,
+    log_path: &Path,
+    progress: &ProgressHandle,
+) -> Result<(), DownloadFailure> {
+    let info_hash = tracker::info_hash_bytes(source.info_hash())
+        .ok_or_else(|| DownloadFailure::new(Reason::InvalidTorrent, "infohash inválido"))?;
+    let (own, info) = match source {
+        Source::Magnet(magnet) => (&magnet.trackers, None),
+        Source::Torrent(torrent) => (&torrent.trackers, Some(torrent.info.as_slice())),
+    };
+    let trackers = tracker::trackers(config, own);
+    let job = Job {
+        info_hash,
+        peer_id: peer_id(),
+        info,
+        dir,
+        filename,
+        sequential: config.sequential_downloads,
+        stall: STALL_TIMEOUT,
+        left: Arc::new(AtomicU64::new(UNKNOWN_LEFT)),
+    };
+    let notes = Arc::new(Notes::default());
+    notes.push(format!("[NOTICE] {} trackers", trackers.len()));
+    info!(info_hash = source.info_hash(), filename, "baixando com o motor embutido");
+
+    let (tx, rx) = mpsc::channel(16);
+    let announcer = tokio::spawn(announce(trackers, info_hash, job.peer_id, job.left.clone(), tx, notes.clone()));
+    let result = tokio::select! {
+        result = run(&job, rx, progress, &notes) => result,
+        _ = progress.cancel.cancelled() => Err(DownloadFailure::new(Reason::Cancelled, "download cancelado")),
+    };
+    announcer.abort();
+
+    // o rodapé `exit code` é o que a recuperação lê: 0 concluiu, 1 falhou
+    let mut chunk = format!("=== motor embutido: {filename} ===\n");
+    for line in notes.take() {
+        chunk.push_str(&line);
+        chunk.push('\n');
+    }
+    match &result {
+        Ok(()) => chunk.push_str("--- exit code 0 ---\n"),
+        Err(failure) => {
+            warn!(info_hash = source.info_hash(), "motor embutido falhou: {failure}");
+            chunk.push_str(&format!("[ERROR] {failure}\n--- exit code 1 ---\n"));
+        }
+    }
+    if let Err(e) = aria2::append_to_log(log_path, chunk.as_bytes()).await {
+        warn!(path = %log_path.display(), "falha ao gravar o log do download: {e}");
+    }
+    result
+}
+
+/// O que o laço de download precisa, já separado da origem e dos trackers.
+struct Job<'a> {
+    info_hash: [u8; 20],
+    peer_id: [u8; 20],
+    /// Dicionário `info` do `.torrent`; de magnet, vem dos peers.
+    info: Option<&'a [u8]>,
+    dir: &'a Path,
+    filename: &'a str,
+    sequential: bool,
+    /// Sem peça nova nesse tempo, o download desiste.
+    stall: Duration,
+    /// Bytes que faltam, informados nos announces.
+    left: Arc<AtomicU64>,
+}
+
+/// Linhas para o log do download; a última linha de resumo fica à parte,
+/// como a do aria2c.
+#[derive(Default)]
+struct Notes {
+    lines: Mutex<VecDeque<String>>,
+    summary: Mutex<Option<String>>,
+}
+
+impl Notes {
+    fn push(&self, line: String) {
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == MAX_NOTES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    fn set_summary(&self, line: String) {
+        *self.summary.lock().unwrap() = Some(line);
+    }
+
+    fn take(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self.lines.lock().unwrap().drain(..).collect();
+        lines.extend(self.summary.lock().unwrap().take());
+        lines
+    }
+}
+
+/// Peer id no estilo Azureus: prefixo do cliente e 12 caracteres aleatórios.
+fn peer_id() -> [u8; 20] {
+    const ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let mut id = *b"-RF0100-000000000000";
+    for byte in &mut id[8..] {
+        *byte = ALPHABET[(RandomState::new().build_hasher().finish() % ALPHABET.len() as u64) as usize];
+    }
+    id
+}
+
+/// Anuncia a todos os trackers em rodadas e manda os peers de cada uma;
+/// acaba quando o download larga o receptor.
+async fn announce(
+    trackers: Vec<(String, bool)>,
+    info_hash: [u8; 20],
+    peer_id: [u8; 20],
+    left: Arc<AtomicU64>,
+    tx: mpsc::Sender<Vec<SocketAddr>>,
+    notes: Arc<Notes>,
+) {
+    for round in 0.. {
+        let left = left.load(Ordering::Relaxed);
+        let results: Vec<_> = stream::iter(trackers.clone())
+            .map(|(tracker, trusted)| async move {
+                let result = tracker::announce(&tracker, &info_hash, &peer_id, left, trusted).await;
+                (tracker, result)
+            })
+            .buffer_unordered(tracker::TRACKER_CONCURRENCY)
+            .collect()
+            .await;
+
+        let mut peers = Vec::new();
+        let mut interval: Option<Duration> = None;
+        for (tracker, result) in results {
+            match result {
+                Ok(found) => {
+                    if round == 0 {
+                        notes.push(format!("[NOTICE] {tracker}: {} peers", found.peers.len()));
+                    }
+                    let wait = found.interval.unwrap_or(ANNOUNCE_MIN);
+                    interval = Some(interval.map_or(wait, |i| i.min(wait)));
+                    peers.extend(found.peers);
+                }
+                Err(e) if round == 0 => notes.push(format!("[WARN] {tracker}: {e}")),
+                Err(e) => debug!(tracker, "announce falhou: {e}"),
+            }
+        }
+        if tx.send(peers).await.is_err() {
+            return;
+        }
+        sleep(interval.unwrap_or(ANNOUNCE_MIN).clamp(ANNOUNCE_MIN, ANNOUNCE_MAX)).await;
+    }
+}
+
+async fn run(
+    job: &Job<'_>,
+    mut peers: mpsc::Receiver<Vec<SocketAddr>>,
+    progress: &ProgressHandle,
+    notes: &Notes,
+) -> Result<(), DownloadFailure> {
+    let mut known = Vec::new();
+    let info = match job.info {
+        Some(info) => info.to_vec(),
+        None => {
+            let info = fetch_metadata(job, &mut peers, &mut known, notes).await?;
+            notes.push(format!("[NOTICE] metadados recebidos ({} bytes)", info.len()));
+            info
+        }
+    };
+    let meta = Metainfo::parse(&info).map_err(|e| DownloadFailure::new(Reason::InvalidTorrent, e))?;
+    let (targets, all) = targets(&meta, job.dir, job.filename);
+    let storage = |e: io::Error| {
+        let (reason, message) = storage_failure(&e);
+        DownloadFailure::new(reason, message)
+    };
+    let done = prepare(&meta, &targets).await.map_err(storage)?;
+
+    let download = Arc::new(Download::new(job, meta, targets, done));
+    let resumed = download.swarm.lock().unwrap().done.iter().filter(|d| **d).count();
+    if resumed > 0 {
+        notes.push(format!("[NOTICE] {resumed} peças já no disco"));
+    }
+    download.write_controls(None).await.map_err(storage)?;
+
+    let mut queue: VecDeque<SocketAddr> = known.into_iter().collect();
+    let mut connected = HashSet::new();
+    let mut tasks = JoinSet::new();
+    loop {
+        let deadline = {
+            let swarm = download.swarm.lock().unwrap();
+            if let Some((reason, message)) = &swarm.failure {
+                return Err(DownloadFailure::new(*reason, message.clone()));
+            }
+            job.left.store(swarm.left, Ordering::Relaxed);
+            progress.set_swarm(Some(swarm.peers), Some(swarm.seeders));
+            notes.set_summary(download.summary(&swarm));
+            if swarm.remaining == 0 {
+                break;
+            }
+            swarm.last_piece + job.stall
+        };
+        while tasks.len() < MAX_PEERS
+            && let Some(addr) = queue.pop_front()
+        {
+            if connected.insert(addr) {
+                let download = download.clone();
+                tasks.spawn(async move { (addr, leech(download, addr).await) });
+            }
+        }
+
+        tokio::select! {
+            Some(batch) = peers.recv() => {
+                for addr in batch {
+                    if !connected.contains(&addr) && !queue.contains(&addr) {
+                        queue.push_back(addr);
+                    }
+                }
+            }
+            Some(joined) = tasks.join_next() => {
+                if let Ok((addr, result)) = joined {
+                    connected.remove(&addr);
+                    if let Err(e) = result {
+                        debug!(%addr, "peer caiu: {e}");
+                    }
+                }
+            }
+            _ = download.changed.notified() => {}
+            _ = sleep_until(deadline) => {
+                return Err(DownloadFailure::new(
+                    Reason::Timeout,
+                    format!("nenhuma peça nova em {}s", job.stall.as_secs()),
+                ));
+            }
+        }
+    }
+    drop(tasks);
+
+    download.finish(all).await.map_err(storage)
+}
+
+/// Metadados de um magnet: tenta os peers dos announces, alguns ao mesmo
+/// tempo, até um entregar o dicionário `info` que bate com o infohash.
+/// Os peers vistos ficam em `known` para a fase das peças.
+async fn fetch_metadata(
+    job: &Job<'_>,
+    peers: &mut mpsc::Receiver<Vec<SocketAddr>>,
+    known: &mut Vec<SocketAddr>,
+    notes: &Notes,
+) -> Result<Vec<u8>, DownloadFailure> {
+    let deadline = Instant::now() + job.stall;
+    let (info_hash, peer_id) = (job.info_hash, job.peer_id);
+    let mut queue = VecDeque::new();
+    let mut tries = JoinSet::new();
+    loop {
+        while tries.len() < METADATA_CONCURRENCY
+            && let Some(addr) = queue.pop_front()
+        {
+            tries.spawn(async move { (addr, peer::fetch_metadata(addr, &info_hash, &peer_id).await) });
+        }
+        tokio::select! {
+            Some(batch) = peers.recv() => {
+                for addr in batch {
+                    if !known.contains(&addr) {
+                        known.push(addr);
+                        queue.push_back(addr);
+                    }
+                }
+            }
+            Some(Ok((addr, result))) = tries.join_next() => match result {
+                Ok(info) => return Ok(info),
+                Err(e) => notes.push(format!("[WARN] metadados de {addr}: {e}")),
+            },
+            _ = sleep_until(deadline) => {
+                return Err(DownloadFailure::new(
+                    Reason::Timeout,
+                    format!("sem metadados em {}s: nenhum peer respondeu", job.stall.as_secs()),
+                ));
+            }
+        }
+    }
+}
+
+/// O que o motor usa do dicionário `info` (v1).
+#[derive(Debug)]
+struct Metainfo {
+    piece_length: u64,
+    pieces: Vec<[u8; 20]>,
+    /// Caminho no disco, relativo à pasta do job (com a pasta do torrent,
+    /// se houver), o nome como `TorrentEntry::name` e o tamanho, na ordem
+    /// do torrent.
+    files: Vec<MetaFile>,
+    total: u64,
+}
+
+#[derive(Debug)]
+struct MetaFile {
+    path: PathBuf,
+    name: String,
+    len: u64,
+}
+
+#[derive(Deserialize)]
+struct RawInfo {
+    name: String,
+    #[serde(rename = "piece length")]
+    piece_length: u64,
+    pieces: Value,
+    length: Option<u64>,
+    files: Option<Vec<RawFile>>,
+}
+
+#[derive(Deserialize)]
+struct RawFile {
+    length: u64,
+    path: Vec<String>,
+}
+
+impl Metainfo {
+    fn parse(info: &[u8]) -> Result<Self, String> {
+        let raw: RawInfo = serde_bencode::from_bytes(info).map_err(|e| format!("dicionário info inválido: {e}"))?;
+        let Value::Bytes(hashes) = raw.pieces else {
+            return Err("pieces inválido".into());
+        };
+        if !hashes.len().is_multiple_of(20) {
+            return Err("pieces inválido".into());
+        }
+        if !(1..=MAX_PIECE_LENGTH).contains(&raw.piece_length) {
+            return Err(format!("tamanho de peça sem suporte: {}", raw.piece_length));
+        }
+        let root = component(&raw.name)?;
+        let files = match (raw.length, raw.files) {
+            (Some(len), None) => vec![MetaFile { path: PathBuf::from(root), name: root.to_string(), len }],
+            (None, Some(files)) if !files.is_empty() => files
+                .into_iter()
+                .map(|f| {
+                    if f.path.is_empty() {
+                        return Err("arquivo sem caminho no torrent".to_string());
+                    }
+                    let mut path = PathBuf::from(root);
+                    for part in &f.path {
+                        path.push(component(part)?);
+                    }
+                    Ok(MetaFile { path, name: f.path.join("/"), len: f.length })
+                })
+                .collect::<Result<_, _>>()?,
+            _ => return Err("dicionário info sem arquivos".into()),
+        };
+        let total = files
+            .iter()
+            .try_fold(0u64, |sum, f| sum.checked_add(f.len))
+            .ok_or("tamanho total inválido")?;
+        let pieces: Vec<[u8; 20]> = hashes.chunks_exact(20).map(|h| h.try_into().unwrap()).collect();
+        if pieces.len() as u64 != total.div_ceil(raw.piece_length) {
+            return Err("número de peças não bate com o tamanho".into());
+        }
+        Ok(Metainfo { piece_length: raw.piece_length, pieces, files, total })
+    }
+
+    /// Bytes `[início, fim)` da peça no torrent inteiro.
+    fn piece_range(&self, index: usize) -> (u64, u64) {
+        let start = index as u64 * self.piece_length;
+        (start, (start + self.piece_length).min(self.total))
+    }
+}
+
+/// Nome de arquivo ou pasta vindo do torrent: nada de `..`, separadores,
+/// NUL ou nome vazio, para não sair da pasta do job.
+fn component(name: &str) -> Result<&str, String> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\', '\0']) {
+        Err(format!("nome inválido no torrent: {name:?}"))
+    } else {
+        Ok(name)
+    }
+}
+
+/// Um arquivo que o download grava.
+#[derive(Debug)]
+struct Target {
+    /// Onde o arquivo começa no torrent.
+    offset: u64,
+    len: u64,
+    /// Caminho final; grava em `<path>.partial`, com o controle ao lado.
+    path: PathBuf,
+}
+
+impl Target {
+    fn partial(&self) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(PARTIAL_SUFFIX);
+        self.path.with_file_name(name)
+    }
+
+    fn control(&self) -> PathBuf {
+        progress::control_file_path(&self.partial())
+    }
+
+    /// Trecho de `[start, end)` (bytes do torrent) dentro deste arquivo:
+    /// posição no arquivo, posição no trecho e tamanho.
+    fn overlap(&self, start: u64, end: u64) -> Option<(u64, usize, usize)> {
+        let from = start.max(self.offset);
+        let to = end.min(self.offset + self.len);
+        (from < to).then(|| (from - self.offset, (from - start) as usize, (to - from) as usize))
+    }
+}
+
+/// Os arquivos a gravar: o pedido, como `dir/filename` (o `--out` do
+/// aria2c), ou todos com os caminhos do torrent quando ele não está lá
+/// (pack de temporada, `dn` diferente). O `bool` diz se são todos.
+fn targets(meta: &Metainfo, dir: &Path, filename: &str) -> (Vec<Target>, bool) {
+    let mut offset = 0;
+    let placed: Vec<(u64, &MetaFile)> = meta
+        .files
+        .iter()
+        .map(|f| {
+            let at = offset;
+            offset += f.len;
+            (at, f)
+        })
+        .collect();
+    let chosen = match placed.len() {
+        1 => Some(0),
+        _ => placed
+            .iter()
+            .position(|(_, f)| f.name == filename || f.name.rsplit('/').next() == Some(filename)),
+    };
+    match chosen {
+        Some(i) => {
+            let (offset, file) = placed[i];
+            (vec![Target { offset, len: file.len, path: dir.join(filename) }], false)
+        }
+        None => {
+            let all = placed.into_iter().map(|(offset, f)| Target { offset, len: f.len, path: dir.join(&f.path) });
+            (all.collect(), true)
+        }
+    }
+}
+
+/// Cria as pastas e os `.partial` que faltam e confere as peças que já
+/// estão no disco (de uma tentativa anterior, com qualquer motor).
+async fn prepare(meta: &Metainfo, targets: &[Target]) -> io::Result<Vec<bool>> {
+    for target in targets {
+        if let Some(parent) = target.path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::OpenOptions::new().write(true).create(true).truncate(false).open(target.partial()).await?;
+    }
+    let mut done = vec![false; meta.pieces.len()];
+    for (index, hash) in meta.pieces.iter().enumerate() {
+        let (start, end) = meta.piece_range(index);
+        if let Some(data) = read_piece(targets, start, end).await?
+            && Sha1::digest(&data)[..] == hash[..]
+        {
+            done[index] = true;
+        }
+    }
+    Ok(done)
+}
+
+/// A peça inteira do disco, quando todos os trechos dela são de arquivos
+/// deste download e já têm bytes gravados.
+async fn read_piece(targets: &[Target], start: u64, end: u64) -> io::Result<Option<Vec<u8>>> {
+    let mut data = vec![0; (end - start) as usize];
+    let mut covered = 0;
+    for target in targets {
+        let Some((at, from, len)) = target.overlap(start, end) else {
+            continue;
+        };
+        let mut file = fs::File::open(target.partial()).await?;
+        if file.metadata().await?.len() < at + len as u64 {
+            return Ok(None);
+        }
+        file.seek(io::SeekFrom::Start(at)).await?;
+        file.read_exact(&mut data[from..from + len]).await?;
+        covered += len;
+    }
+    Ok((covered == data.len()).then_some(data))
+}
+
+async fn write_piece(targets: &[Target], start: u64, end: u64, data: &[u8]) -> io::Result<()> {
+    for target in targets {
+        let Some((at, from, len)) = target.overlap(start, end) else {
+            continue;
+        };
+        let mut file = fs::OpenOptions::new().write(true).open(target.partial()).await?;
+        file.seek(io::SeekFrom::Start(at)).await?;
+        file.write_all(&data[from..from + len]).await?;
+    }
+    Ok(())
+}
+
+/// `.aria2` de um arquivo, no formato que [`progress`] lê (versão 1,
+/// big-endian): o tamanho de peça do torrent e um bit por trecho desse
+/// tamanho do arquivo, ligado quando as peças que cobrem o trecho já estão
+/// gravadas. Num torrent de um arquivo, é o bitfield das peças.
+fn control_bytes(meta: &Metainfo, info_hash: &[u8; 20], target: &Target, done: &[bool]) -> Vec<u8> {
+    let piece_length = meta.piece_length;
+    let chunks = target.len.div_ceil(piece_length);
+    let mut bitfield = vec![0u8; chunks.div_ceil(8) as usize];
+    for chunk in 0..chunks {
+        let from = target.offset + chunk * piece_length;
+        let to = (from + piece_length).min(target.offset + target.len);
+        if (from / piece_length..=(to - 1) / piece_length).all(|p| done[p as usize]) {
+            bitfield[(chunk / 8) as usize] |= 0x80 >> (chunk % 8);
+        }
+    }
+    let mut out = vec![0, 1, 0, 0, 0, 0];
+    out.extend_from_slice(&20u32.to_be_bytes());
+    out.extend_from_slice(info_hash);
+    out.extend_from_slice(&(piece_length as u32).to_be_bytes());
+    out.extend_from_slice(&target.len.to_be_bytes());
+    out.extend_from_slice(&0u64.to_be_bytes());
+    out.extend_from_slice(&(bitfield.len() as u32).to_be_bytes());
+    out.extend_from_slice(&bitfield);
+    out.extend_from_slice(&0u32.to_be_bytes());
+    out
+}
+
+/// Grava num temporário e renomeia: quem lê o controle nunca vê metade.
+async fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_os_string();
+    tmp.push(".tmp");
+    fs::write(&tmp, data).await?;
+    fs::rename(&tmp, path).await
+}
+
+fn storage_failure(e: &io::Error) -> (Reason, String) {
+    let reason = match e.kind() {
+        io::ErrorKind::StorageFull => Reason::DiskFull,
+        _ => Reason::Storage,
+    };
+    (reason, format!("falha ao gravar o download: {e}"))
+}
+
+/// Estado das peças, dividido entre as conexões.
+struct Swarm {
+    wanted: Vec<bool>,
+    done: Vec<bool>,
+    in_flight: Vec<bool>,
+    /// Quantos peers conectados têm cada peça.
+    availability: Vec<u32>,
+    /// Ordem fixa com `SEQUENTIAL_DOWNLOADS`; sem ela, a mais rara primeiro.
+    order: Option<Vec<usize>>,
+    remaining: usize,
+    /// Bytes das peças que faltam.
+    left: u64,
+    peers: u32,
+    seeders: u32,
+    last_piece: Instant,
+    /// Falha do disco, que encerra o download inteiro.
+    failure: Option<(Reason, String)>,
+}
+
+impl Swarm {
+    fn pick(&mut self, has: &[bool]) -> Option<usize> {
+        let free = |i: usize| self.wanted[i] && !self.done[i] && !self.in_flight[i] && has[i];
+        let choice = match &self.order {
+            Some(order) => order.iter().copied().find(|&i| free(i)),
+            None => (0..self.wanted.len()).filter(|&i| free(i)).min_by_key(|&i| (self.availability[i], i)),
+        }?;
+        self.in_flight[choice] = true;
+        Some(choice)
+    }
+}
+
+/// Ordem sequencial: começo e fim de cada arquivo primeiro (onde os players
+/// procuram o índice), depois o resto em ordem.
+fn sequential_order(meta: &Metainfo, targets: &[Target], wanted: &[bool]) -> Vec<usize> {
+    let mut order = Vec::new();
+    for target in targets.iter().filter(|t| t.len > 0) {
+        order.push((target.offset / meta.piece_length) as usize);
+        order.push(((target.offset + target.len - 1) / meta.piece_length) as usize);
+    }
+    order.extend((0..wanted.len()).filter(|&i| wanted[i]));
+    let mut seen = HashSet::new();
+    order.retain(|i| seen.insert(*i));
+    order
+}
+
+/// Um download em andamento, dividido entre as conexões com peers.
+struct Download {
+    info_hash: [u8; 20],
+    peer_id: [u8; 20],
+    meta: Metainfo,
+    targets: Vec<Target>,
+    swarm: Mutex<Swarm>,
+    /// Acorda o laço principal a cada peça gravada ou falha de disco.
+    changed: Notify,
+    /// Um controle gravado por vez, sempre com o estado mais novo.
+    control: tokio::sync::Mutex<()>,
+}
+
+impl Download {
+    fn new(job: &Job<'_>, meta: Metainfo, targets: Vec<Target>, done: Vec<bool>) -> Self {
+        let wanted: Vec<bool> = (0..meta.pieces.len())
+            .map(|i| {
+                let (start, end) = meta.piece_range(i);
+                targets.iter().any(|t| t.overlap(start, end).is_some())
+            })
+            .collect();
+        let missing: Vec<usize> = (0..wanted.len()).filter(|&i| wanted[i] && !done[i]).collect();
+        let left = missing.iter().map(|&i| meta.piece_range(i)).map(|(s, e)| e - s).sum();
+        let order = job.sequential.then(|| sequential_order(&meta, &targets, &wanted));
+        let n = wanted.len();
+        let swarm = Swarm {
+            wanted,
+            done,
+            in_flight: vec![false; n],
+            availability: vec![0; n],
+            order,
+            remaining: missing.len(),
+            left,
+            peers: 0,
+            seeders: 0,
+            last_piece: Instant::now(),
+            failure: None,
+        };
+        Download {
+            info_hash: job.info_hash,
+            peer_id: job.peer_id,
+            meta,
+            targets,
+            swarm: Mutex::new(swarm),
+            changed: Notify::new(),
+            control: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    fn claim(self: &Arc<Self>, has: &[bool]) -> Option<Claim> {
+        let index = self.swarm.lock().unwrap().pick(has)?;
+        let (start, end) = self.meta.piece_range(index);
+        let len = (end - start) as usize;
+        Some(Claim {
+            download: self.clone(),
+            index,
+            data: vec![0; len],
+            received: vec![false; len.div_ceil(BLOCK_LEN as usize)],
+            next: 0,
+            pending: 0,
+            stored: false,
+        })
+    }
+
+    fn finished(&self) -> bool {
+        let swarm = self.swarm.lock().unwrap();
+        swarm.remaining == 0 || swarm.failure.is_some()
+    }
+
+    fn fail(&self, e: &io::Error) {
+        self.swarm.lock().unwrap().failure.get_or_insert_with(|| storage_failure(e));
+        self.changed.notify_one();
+    }
+
+    /// Confere e grava uma peça completa. Peça que não bate com o SHA-1
+    /// derruba o peer (e volta para a fila); erro de disco derruba o
+    /// download.
+    async fn store(&self, mut claim: Claim) -> io::Result<()> {
+        let index = claim.index;
+        if Sha1::digest(&claim.data)[..] != self.meta.pieces[index][..] {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("peça {index} não confere com o SHA-1")));
+        }
+        let (start, end) = self.meta.piece_range(index);
+        if let Err(e) = write_piece(&self.targets, start, end, &claim.data).await {
+            self.fail(&e);
+            return Err(e);
+        }
+        claim.stored = true;
+        {
+            let mut swarm = self.swarm.lock().unwrap();
+            swarm.done[index] = true;
+            swarm.in_flight[index] = false;
+            swarm.remaining -= 1;
+            swarm.left -= end - start;
+            swarm.last_piece = Instant::now();
+        }
+        drop(claim);
+        if let Err(e) = self.write_controls(Some((start, end))).await {
+            self.fail(&e);
+            return Err(e);
+        }
+        self.changed.notify_one();
+        Ok(())
+    }
+
+    /// Regrava os controles dos arquivos com bytes em `range` (todos, com
+    /// `None`).
+    async fn write_controls(&self, range: Option<(u64, u64)>) -> io::Result<()> {
+        let _guard = self.control.lock().await;
+        let done = self.swarm.lock().unwrap().done.clone();
+        for target in &self.targets {
+            if range.is_some_and(|(start, end)| target.overlap(start, end).is_none()) {
+                continue;
+            }
+            write_atomic(&target.control(), &control_bytes(&self.meta, &self.info_hash, target, &done)).await?;
+        }
+        Ok(())
+    }
+
+    /// Tira os controles; com todos os arquivos, também renomeia os
+    /// `.partial`, que ninguém mais conclui. O arquivo pedido fica como
+    /// `.partial` para `finalize` conferir e registrar.
+    async fn finish(&self, all: bool) -> io::Result<()> {
+        let _guard = self.control.lock().await;
+        for target in &self.targets {
+            fs::remove_file(target.control()).await?;
+            if all {
+                fs::rename(target.partial(), &target.path).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Linha de resumo no formato do aria2c, que `aria2::parse_summary` lê.
+    fn summary(&self, swarm: &Swarm) -> String {
+        let wanted: u64 = self.targets.iter().map(|t| t.len).sum();
+        let have = wanted.saturating_sub(swarm.left);
+        let percent = (have * 100).checked_div(wanted).unwrap_or(100);
+        format!("[#embutido {have}B/{wanted}B({percent}%) CN:{} SD:{}]", swarm.peers, swarm.seeders)
+    }
+}
+
+/// Peça reservada por uma conexão; volta para a fila se a conexão largar
+/// antes de gravá-la.
+struct Claim {
+    download: Arc<Download>,
+    index: usize,
+    data: Vec<u8>,
+    /// Blocos já recebidos.
+    received: Vec<bool>,
+    /// Próximo bloco a pedir.
+    next: usize,
+    /// Pedidos sem resposta.
+    pending: usize,
+    stored: bool,
+}
+
+impl Claim {
+    /// Próximo pedido do pipeline, se ainda cabe.
+    fn next_request(&mut self) -> Option<Message> {
+        if self.pending >= PIPELINE || self.next >= self.received.len() {
+            return None;
+        }
+        let begin = self.next as u32 * BLOCK_LEN;
+        let length = BLOCK_LEN.min(self.data.len() as u32 - begin);
+        self.next += 1;
+        self.pending += 1;
+        Some(Message::Request { index: self.index as u32, begin, length })
+    }
+
+    /// Guarda um bloco; `false` se ele não é de nenhum pedido desta peça.
+    fn receive(&mut self, begin: u32, block: &[u8]) -> bool {
+        let begin = begin as usize;
+        let block_len = BLOCK_LEN as usize;
+        let slot = begin / block_len;
+        if !begin.is_multiple_of(block_len) || slot >= self.next {
+            return false;
+        }
+        if block.len() != block_len.min(self.data.len() - begin) {
+            return false;
+        }
+        if !self.received[slot] {
+            self.data[begin..begin + block.len()].copy_from_slice(block);
+            self.received[slot] = true;
+            self.pending = self.pending.saturating_sub(1);
+        }
+        true
+    }
+
+    fn complete(&self) -> bool {
+        self.received.iter().all(|r| *r)
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if !self.stored {
+            self.download.swarm.lock().unwrap().in_flight[self.index] = false;
+        }
+    }
+}
+
+/// Uma conexão no enxame: conta como peer, e como seeder quando tem tudo,
+/// e soma as peças dela à disponibilidade enquanto durar.
+struct Seat {
+    download: Arc<Download>,
+    has: Vec<bool>,
+    seeder: bool,
+}
+
+impl Seat {
+    fn new(download: Arc<Download>) -> Self {
+        download.swarm.lock().unwrap().peers += 1;
+        let has = vec![false; download.meta.pieces.len()];
+        Seat { download, has, seeder: false }
+    }
+
+    fn have(&mut self, pieces: impl Iterator<Item = usize>) {
+        let mut swarm = self.download.swarm.lock().unwrap();
+        for index in pieces {
+            if let Some(has) = self.has.get_mut(index)
+                && !*has
+            {
+                *has = true;
+                swarm.availability[index] += 1;
+            }
+        }
+        if !self.seeder && self.has.iter().all(|h| *h) {
+            self.seeder = true;
+            swarm.seeders += 1;
+        }
+    }
+
+    fn bitfield(&mut self, bits: &[u8]) {
+        let pieces = (0..self.has.len()).filter(|&i| bits.get(i / 8).is_some_and(|b| b & (0x80 >> (i % 8)) != 0));
+        self.have(pieces);
+    }
+}
+
+impl Drop for Seat {
+    fn drop(&mut self) {
+        let mut swarm = self.download.swarm.lock().unwrap();
+        for (index, has) in self.has.iter().enumerate() {
+            if *has {
+                swarm.availability[index] -= 1;
+            }
+        }
+        swarm.peers -= 1;
+        if self.seeder {
+            swarm.seeders -= 1;
+        }
+    }
+}
+
+/// Baixa peças de um peer até o download acabar ou a conexão cair. Uma
+/// peça por vez, com até [`PIPELINE`] blocos pedidos.
+async fn leech(download: Arc<Download>, addr: SocketAddr) -> io::Result<()> {
+    let peer = Peer::connect(addr, &download.info_hash, &download.peer_id).await?;
+    exchange(download, peer).await
+}
+
+async fn exchange<S>(download: Arc<Download>, mut peer: Peer<S>) -> io::Result<()>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let mut seat = Seat::new(download.clone());
+    peer.send(&Message::Interested).await?;
+    let mut choked = true;
+    let mut current: Option<Claim> = None;
+    loop {
+        if download.finished() {
+            return Ok(());
+        }
+        if !choked {
+            if current.is_none() {
+                current = download.claim(&seat.has);
+            }
+            if let Some(claim) = &mut current {
+                while let Some(request) = claim.next_request() {
+                    peer.send(&request).await?;
+                }
+            }
+        }
+
+        let message = timeout(PEER_IDLE, peer.recv())
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "peer calado"))??;
+        match message {
+            // pedidos pendentes são descartados pelo peer no choke
+            Message::Choke => {
+                choked = true;
+                current = None;
+            }
+            Message::Unchoke => choked = false,
+            Message::Have(index) => seat.have(std::iter::once(index as usize)),
+            Message::Bitfield(bits) => seat.bitfield(&bits),
+            Message::Piece { index, begin, block } => {
+                let Some(claim) = current.as_mut().filter(|c| c.index == index as usize) else {
+                    continue;
+                };
+                if !claim.receive(begin, &block) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "bloco fora dos pedidos"));
+                }
+                if claim.complete()
+                    && let Some(claim) = current.take()
+                {
+                    download.store(claim).await?;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+
+    const PIECE: usize = 32 * 1024;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rossoflix-engine-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn dict(entries: Vec<(&str, Value)>) -> Value {
+        Value::Dict(entries.into_iter().map(|(k, v)| (k.as_bytes().to_vec(), v)).collect::<HashMap<_, _>>())
+    }
+
+    fn bytes(s: &str) -> Value {
+        Value::Bytes(s.as_bytes().to_vec())
+    }
+
+    /// Dicionário `info` de um torrent "Pack" com `files` (um só vira
+    /// torrent de arquivo único) e o conteúdo dele.
+    fn torrent(files: &[(&str, usize)]) -> (Vec<u8>, Vec<u8>) {
+        let total = files.iter().map(|(_, len)| len).sum::<usize>();
+        let data: Vec<u8> = (0..total).map(|i| (i * 31 % 251) as u8).collect();
+        let pieces: Vec<u8> = data.chunks(PIECE).flat_map(|chunk| Sha1::digest(chunk).to_vec()).collect();
+        let mut info = vec![("piece length", Value::Int(PIECE as i64)), ("pieces", Value::Bytes(pieces))];
+        match files {
+            [(name, len)] => info.extend([("name", bytes(name)), ("length", Value::Int(*len as i64))]),
+            _ => {
+                let list = files
+                    .iter()
+                    .map(|(path, len)| {
+                        let path = path.split('/').map(bytes).collect();
+                        dict(vec![("length", Value::Int(*len as i64)), ("path", Value::List(path))])
+                    })
+                    .collect();
+                info.extend([("name", bytes("Pack")), ("files", Value::List(list))]);
+            }
+        }
+        (serde_bencode::to_bytes(&dict(info)).unwrap(), data)
+    }
+
+    fn info_hash(info: &[u8]) -> [u8; 20] {
+        Sha1::digest(info).into()
+    }
+
+    /// Seeder local: metadados pelo `ut_metadata`, bitfield cheio, unchoke
+    /// e os blocos pedidos (estragados com `corrupt`). Anota as peças
+    /// pedidas.
+    struct Seed {
+        info: Vec<u8>,
+        data: Vec<u8>,
+        corrupt: bool,
+        requested: Mutex<Vec<u32>>,
+    }
+
+    async fn seeder(seed: Arc<Seed>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, seed.clone()));
+            }
+        });
+        addr
+    }
+
+    async fn serve(stream: TcpStream, seed: Arc<Seed>) -> io::Result<()> {
+        let mut peer = Peer::handshake(stream, &info_hash(&seed.info), b"-XX0000-seeder000000").await?;
+        let offer = format!("d1:md11:ut_metadatai5ee13:metadata_sizei{}ee", seed.info.len());
+        peer.send(&Message::Extended { id: 0, payload: offer.into_bytes() }).await?;
+        let pieces = seed.data.len().div_ceil(PIECE);
+        let mut bits = vec![0u8; pieces.div_ceil(8)];
+        for i in 0..pieces {
+            bits[i / 8] |= 0x80 >> (i % 8);
+        }
+        peer.send(&Message::Bitfield(bits)).await?;
+        peer.send(&Message::Unchoke).await?;
+        loop {
+            match peer.recv().await? {
+                Message::Request { index, begin, length } => {
+                    seed.requested.lock().unwrap().push(index);
+                    let start = index as usize * PIECE + begin as usize;
+                    let mut block = seed.data[start..start + length as usize].to_vec();
+                    if seed.corrupt {
+                        block[0] ^= 0xff;
+                    }
+                    peer.send(&Message::Piece { index, begin, block }).await?;
+                }
+                // os metadados dos testes cabem num pedaço só
+                Message::Extended { id: 5, .. } => {
+                    let mut reply = b"d8:msg_typei1e5:piecei0ee".to_vec();
+                    reply.extend_from_slice(&seed.info);
+                    peer.send(&Message::Extended { id: peer::UT_METADATA, payload: reply }).await?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn job<'a>(info: &'a [u8], torrent: bool, dir: &'a Path, filename: &'a str, stall: Duration) -> Job<'a> {
+        Job {
+            info_hash: info_hash(info),
+            peer_id: peer_id(),
+            info: torrent.then_some(info),
+            dir,
+            filename,
+            sequential: true,
+            stall,
+            left: Arc::new(AtomicU64::new(UNKNOWN_LEFT)),
+        }
+    }
+
+    /// Roda o download com `peers` como única rodada de announce.
+    async fn run_with(job: &Job<'_>, peers: Vec<SocketAddr>) -> Result<(), DownloadFailure> {
+        let (tx, rx) = mpsc::channel(1);
+        tx.send(peers).await.unwrap();
+        let progress = progress::ProgressRegistry::default().track("teste", job.dir.join("x.partial"), None);
+        let result = run(job, rx, &progress, &Notes::default()).await;
+        drop(tx);
+        result
+    }
+
+    fn seed(info: &[u8], data: &[u8], corrupt: bool) -> Arc<Seed> {
+        Arc::new(Seed { info: info.to_vec(), data: data.to_vec(), corrupt, requested: Mutex::default() })
+    }
+
+    fn requested(seed: &Seed) -> Vec<u32> {
+        let mut pieces = std::mem::take(&mut *seed.requested.lock().unwrap());
+        pieces.dedup();
+        pieces
+    }
+
+    #[test]
+    fn torrent_paths_stay_inside_the_job() {
+        let (info, _) = torrent(&[("a.mkv", 10), ("Extras/b.srt", 10)]);
+        let meta = Metainfo::parse(&info).unwrap();
+        let names: Vec<_> = meta.files.iter().map(|f| (f.path.clone(), f.name.as_str())).collect();
+        assert_eq!(
+            names,
+            [(PathBuf::from("Pack/a.mkv"), "a.mkv"), (PathBuf::from("Pack/Extras/b.srt"), "Extras/b.srt")]
+        );
+
+        for bad in ["../fora.mkv", "a/../../b", "/etc/passwd", "c:\\x"] {
+            let (info, _) = torrent(&[(bad, 10), ("ok.mkv", 10)]);
+            assert!(Metainfo::parse(&info).is_err(), "{bad}");
+        }
+        let (info, _) = torrent(&[("..", 10)]);
+        assert!(Metainfo::parse(&info).is_err());
+        assert!(Metainfo::parse(b"d4:name1:ae").is_err());
+    }
+
+    #[test]
+    fn targets_follow_the_requested_file() {
+        let dir = Path::new("/dl/job");
+        let (info, _) = torrent(&[("a.mkv", 50_000), ("Sub/b.mkv", 70_000)]);
+        let meta = Metainfo::parse(&info).unwrap();
+
+        let (chosen, all) = targets(&meta, dir, "b.mkv");
+        assert!(!all);
+        assert_eq!((chosen[0].offset, chosen[0].len, chosen[0].path.as_path()), (50_000, 70_000, Path::new("/dl/job/b.mkv")));
+        assert_eq!(chosen[0].partial(), Path::new("/dl/job/b.mkv.partial"));
+        assert_eq!(chosen[0].control(), Path::new("/dl/job/b.mkv.partial.aria2"));
+
+        let (every, all) = targets(&meta, dir, "Pack.S01.mkv");
+        assert!(all);
+        let paths: Vec<_> = every.iter().map(|t| t.path.as_path()).collect();
+        assert_eq!(paths, [Path::new("/dl/job/Pack/a.mkv"), Path::new("/dl/job/Pack/Sub/b.mkv")]);
+
+        // arquivo único: sempre o nome pedido, como o --out do aria2c
+        let (info, _) = torrent(&[("Nome.Do.Torrent.mkv", 10)]);
+        let (single, all) = targets(&Metainfo::parse(&info).unwrap(), dir, "filme.mkv");
+        assert!(!all);
+        assert_eq!(single[0].path, Path::new("/dl/job/filme.mkv"));
+    }
+
+    #[tokio::test]
+    async fn control_file_reads_back_in_file_chunks() {
+        let (info, _) = torrent(&[("a.mkv", 50_000), ("b.mkv", 70_000)]);
+        let meta = Metainfo::parse(&info).unwrap();
+        let dir = temp_dir("control");
+        let (chosen, _) = targets(&meta, &dir, "b.mkv");
+        let target = &chosen[0];
+
+        // b.mkv vai de 50 000 a 120 000: o primeiro trecho depende da peça 1
+        let bytes = control_bytes(&meta, &info_hash(&info), target, &[true, false, true, true]);
+        assert_eq!(bytes[50..55], [0, 0, 0, 1, 0b0110_0000]);
+
+        write_atomic(&target.control(), &bytes).await.unwrap();
+        let control = progress::partial(&target.partial()).await.unwrap().control.unwrap();
+        assert_eq!(control.total_length, 70_000);
+        assert_eq!(control.completed_bytes(), 2 * PIECE as u64);
+        assert_eq!(control.available_from(0), 0);
+        assert_eq!(control.available_from(PIECE as u64), 70_000 - PIECE as u64);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn downloads_the_requested_file_and_resumes() {
+        let (info, data) = torrent(&[("a.mkv", 50_000), ("b.mkv", 70_000)]);
+        let seed = seed(&info, &data, false);
+        let addr = seeder(seed.clone()).await;
+        let dir = temp_dir("requested");
+        let job = job(&info, true, &dir, "b.mkv", Duration::from_secs(10));
+
+        run_with(&job, vec![addr]).await.unwrap();
+        // o .partial fica para o finalize; o controle sai
+        assert_eq!(std::fs::read(dir.join("b.mkv.partial")).unwrap(), data[50_000..]);
+        assert!(!dir.join("b.mkv.partial.aria2").exists());
+        assert!(!dir.join("a.mkv.partial").exists());
+        // sequencial: começo e fim do arquivo antes do meio
+        assert_eq!(requested(&seed), [1, 3, 2]);
+
+        // de novo: só a peça dividida com a.mkv não dá para conferir no disco
+        run_with(&job, vec![addr]).await.unwrap();
+        assert_eq!(requested(&seed), [1]);
+        assert_eq!(std::fs::read(dir.join("b.mkv.partial")).unwrap(), data[50_000..]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn magnet_fetches_metadata_and_keeps_torrent_paths() {
+        let (info, data) = torrent(&[("a.mkv", 50_000), ("Sub/b.mkv", 70_000)]);
+        let addr = seeder(seed(&info, &data, false)).await;
+        let dir = temp_dir("magnet");
+
+        run_with(&job(&info, false, &dir, "Pack.S01.mkv", Duration::from_secs(10)), vec![addr]).await.unwrap();
+        assert_eq!(std::fs::read(dir.join("Pack/a.mkv")).unwrap(), data[..50_000]);
+        assert_eq!(std::fs::read(dir.join("Pack/Sub/b.mkv")).unwrap(), data[50_000..]);
+        assert!(!dir.join("Pack/a.mkv.partial").exists());
+        assert!(!dir.join("Pack/Sub/b.mkv.partial.aria2").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn corrupt_pieces_move_to_another_peer() {
+        let (info, data) = torrent(&[("filme.mkv", 100_000)]);
+        let bad = seeder(seed(&info, &data, true)).await;
+        let good = seeder(seed(&info, &data, false)).await;
+        let dir = temp_dir("corrupt");
+
+        run_with(&job(&info, true, &dir, "filme.mkv", Duration::from_secs(10)), vec![bad, good]).await.unwrap();
+        assert_eq!(std::fs::read(dir.join("filme.mkv.partial")).unwrap(), data);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn no_peers_fails_with_timeout() {
+        let (info, _) = torrent(&[("filme.mkv", 100_000)]);
+        let dir = temp_dir("stall");
+
+        let magnet = run_with(&job(&info, false, &dir, "filme.mkv", Duration::from_millis(200)), vec![]).await;
+        assert_eq!(magnet.unwrap_err().reason, Reason::Timeout);
+        let torrent = run_with(&job(&info, true, &dir, "filme.mkv", Duration::from_millis(200)), vec![]).await;
+        assert_eq!(torrent.unwrap_err().reason, Reason::Timeout);
+        // o controle fica para a próxima tentativa
+        assert!(dir.join("filme.mkv.partial.aria2").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn engine_names() {
+        assert_eq!("embedded".parse::<Engine>().unwrap(), Engine::Embedded);
+        assert_eq!("ARIA2C".parse::<Engine>().unwrap(), Engine::Aria2c);
+        assert!("librqbit".parse::<Engine>().is_err());
+    }
+}
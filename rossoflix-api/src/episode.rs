@@ -0,0 +1,348 @@
+use std::{collections::BTreeMap, fmt, path::PathBuf};
+
+use serde::Deserialize;
+use tracing::info;
+
+use crate::{
+    AppState,
+    cache::{CacheCategory, CacheMode},
+    release_name::{EpisodeRef, MatchKind, numbering},
+    upstream,
+};
+
+pub const VIDEO_EXTENSIONS: &[&str] = &["mkv", "mp4", "m4v", "avi", "webm", "mov", "ts", "wmv"];
+
+/// Episódio pedido pelo cliente (`S01E07`, `1x07` ou `E07`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpisodeHint {
+    pub season: Option<u32>,
+    pub episode: u32,
+}
+
+impl EpisodeHint {
+    pub fn parse(raw: &str) -> Option<Self> {
+        let lower = raw.trim().to_ascii_lowercase();
+        let mut refs = numbering(&lower);
+        match refs.len() {
+            1 => {
+                let r = refs.remove(0);
+                Some(EpisodeHint {
+                    season: r.season,
+                    episode: r.episode,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for EpisodeHint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.season {
+            Some(s) => write!(f, "S{s:02}E{:02}", self.episode),
+            None => write!(f, "E{:02}", self.episode),
+        }
+    }
+}
+
+/// Episódios de cada temporada (sem os especiais, a temporada 0), para
+/// levar `SxxEyy` à numeração absoluta: com 12 episódios na primeira
+/// temporada, `S02E05` é o 17.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeasonOffsets(BTreeMap<u32, u32>);
+
+impl SeasonOffsets {
+    pub fn new(counts: impl IntoIterator<Item = (u32, u32)>) -> Self {
+        SeasonOffsets(counts.into_iter().filter(|&(season, _)| season > 0).collect())
+    }
+
+    /// Número absoluto do episódio; `None` nos especiais ou sem a contagem
+    /// de alguma temporada anterior.
+    pub fn absolute(&self, season: u32, episode: u32) -> Option<u32> {
+        if season == 0 {
+            return None;
+        }
+        let before: Option<u32> = (1..season).map(|s| self.0.get(&s).copied()).sum();
+        Some(before? + episode)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbFind {
+    #[serde(default)]
+    tv_results: Vec<TmdbId>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbId {
+    id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbShow {
+    #[serde(default)]
+    seasons: Vec<TmdbSeason>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbSeason {
+    season_number: u32,
+    episode_count: u32,
+}
+
+/// Contagem de episódios por temporada da série `imdb_id`, pelo detalhe do
+/// TMDB (o mesmo cache do `/title`). `None` se não for série ou o TMDB
+/// falhar: aí a numeração absoluta só vale na primeira temporada.
+pub async fn season_offsets(state: &AppState, imdb_id: &str) -> Option<SeasonOffsets> {
+    let config = state.config();
+    let url = format!(
+        "{}/find/{}?api_key={}&external_source=imdb_id",
+        config.tmdb_base_url,
+        urlencoding::encode(imdb_id),
+        state.tmdb_key
+    );
+    let key = format!("tmdb:find:{imdb_id}");
+    let detail_cache = state.cache.with_ttl(config.cache_ttl(CacheCategory::Detail));
+    let found: Result<TmdbFind, _> =
+        upstream::cached_json(state, &detail_cache, upstream::Service::Tmdb, "/find/:imdb_id", key, &url, CacheMode::Normal).await;
+    let id = match found {
+        Ok(found) => found.tv_results.first()?.id,
+        Err(e) => {
+            tracing::debug!(imdb_id, "série no TMDB indisponível: {e}");
+            return None;
+        }
+    };
+    let url = format!("{}/tv/{id}?api_key={}", config.tmdb_base_url, state.tmdb_key);
+    let key = format!("tmdb:tv:{id}:detail");
+    let show: Result<TmdbShow, _> =
+        upstream::cached_json(state, &detail_cache, upstream::Service::Tmdb, "/tv/:id", key, &url, CacheMode::Normal).await;
+    match show {
+        Ok(show) => Some(SeasonOffsets::new(show.seasons.into_iter().map(|s| (s.season_number, s.episode_count)))),
+        Err(e) => {
+            tracing::debug!(imdb_id, tmdb_id = id, "temporadas no TMDB indisponíveis: {e}");
+            None
+        }
+    }
+}
+
+/// Onde o episódio pedido cai dentro do arquivo: `index` (de 0) entre os
+/// `count` episódios que ele cobre.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub index: u32,
+    pub count: u32,
+}
+
+impl Position {
+    fn within(r: &EpisodeRef, episode: u32) -> Option<Self> {
+        (r.episode..=r.last.max(r.episode)).contains(&episode).then(|| Position {
+            index: episode - r.episode,
+            count: r.last.max(r.episode) - r.episode + 1,
+        })
+    }
+
+    /// Segundos até o começo do episódio num arquivo de `duration_secs`,
+    /// supondo episódios de mesma duração. `None` em arquivo de um só.
+    pub fn start_offset_secs(&self, duration_secs: f64) -> Option<u64> {
+        (self.count > 1).then(|| (duration_secs * f64::from(self.index) / f64::from(self.count)) as u64)
+    }
+}
+
+/// Resultado da escolha de arquivo dentro de um pack.
+#[derive(Debug)]
+pub enum Selection {
+    Found { path: PathBuf, position: Position },
+    NoMatch,
+}
+
+/// Escolhe o arquivo de vídeo que melhor corresponde ao episódio. Em empate,
+/// fica o maior (o outro costuma ser sample ou extra). Com `offsets`, a
+/// numeração absoluta vale para qualquer temporada.
+pub fn select(files: &[(PathBuf, u64)], hint: EpisodeHint, offsets: Option<&SeasonOffsets>) -> Selection {
+    let mut candidates: Vec<(MatchKind, u64, &PathBuf, Position)> = files
+        .iter()
+        .filter(|(path, _)| is_video(path))
+        .filter_map(|(path, size)| {
+            let name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+            match_kind(&name, hint, offsets).map(|(kind, position)| (kind, *size, path, position))
+        })
+        .collect();
+
+    candidates.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)));
+    let Some(&(kind, size, best, position)) = candidates.first() else {
+        return Selection::NoMatch;
+    };
+    let tied = candidates.iter().filter(|c| c.0 == kind).count();
+    if tied > 1 {
+        info!(
+            %hint,
+            candidates = tied,
+            chosen = %best.display(),
+            size,
+            "vários arquivos casam com o episódio; usando o maior"
+        );
+    }
+    Selection::Found { path: best.clone(), position }
+}
+
+pub fn is_video(path: &std::path::Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| VIDEO_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+fn match_kind(name: &str, hint: EpisodeHint, offsets: Option<&SeasonOffsets>) -> Option<(MatchKind, Position)> {
+    let refs = numbering(name);
+    let explicit = refs
+        .iter()
+        .filter(|r| r.kind != MatchKind::Absolute)
+        .filter(|r| match (r.season, hint.season) {
+            (Some(a), Some(b)) => a == b,
+            // especial só com a temporada 0 escrita no nome
+            (None, Some(0)) => false,
+            _ => true,
+        })
+        .filter_map(|r| Some((r.kind, Position::within(r, hint.episode)?)))
+        .max_by_key(|(kind, _)| *kind);
+    if explicit.is_some() {
+        return explicit;
+    }
+    // numeração absoluta (`E17` ou número solto): sem a contagem das
+    // temporadas, só bate com a primeira
+    let absolute = match hint.season {
+        None | Some(1) => hint.episode,
+        Some(season) => offsets?.absolute(season, hint.episode)?,
+    };
+    if refs.iter().any(|r| r.kind == MatchKind::SeasonEpisode) {
+        return None;
+    }
+    // com `E17` no nome, os números soltos não contam
+    let kind = match refs.iter().any(|r| r.kind == MatchKind::EpisodeOnly) {
+        true => MatchKind::EpisodeOnly,
+        false => MatchKind::Absolute,
+    };
+    refs.iter()
+        .filter(|r| r.kind == kind)
+        .find_map(|r| Position::within(r, absolute))
+        .map(|position| (MatchKind::Absolute, position))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack(names: &[(&str, u64)]) -> Vec<(PathBuf, u64)> {
+        names.iter().map(|&(name, size)| (PathBuf::from("pack").join(name), size)).collect()
+    }
+
+    fn chosen(files: &[(PathBuf, u64)], hint: &str, offsets: Option<&SeasonOffsets>) -> Option<String> {
+        let hint = EpisodeHint::parse(hint).expect("episode_hint válido");
+        match select(files, hint, offsets) {
+            Selection::Found { path, .. } => Some(path.file_name()?.to_string_lossy().into_owned()),
+            Selection::NoMatch => None,
+        }
+    }
+
+    #[test]
+    fn parses_hints() {
+        let hint = |season, episode| Some(EpisodeHint { season, episode });
+        assert_eq!(EpisodeHint::parse("S01E07"), hint(Some(1), 7));
+        assert_eq!(EpisodeHint::parse(" 2x13 "), hint(Some(2), 13));
+        assert_eq!(EpisodeHint::parse("E07"), hint(None, 7));
+        assert_eq!(EpisodeHint::parse("ep120"), hint(None, 120));
+        assert_eq!(EpisodeHint::parse("S01E07 S01E08"), None);
+        assert_eq!(EpisodeHint::parse("temporada"), None);
+        assert_eq!(EpisodeHint::parse("s1e7").map(|h| h.to_string()).as_deref(), Some("S01E07"));
+    }
+
+    #[test]
+    fn matches_each_numbering_pattern() {
+        let cases = [
+            ("S01E07", &[("Show.S01E06.mkv", 1), ("Show.S01E07.1080p.mkv", 1), ("Show.S02E07.mkv", 1)][..], Some("Show.S01E07.1080p.mkv")),
+            ("S01E07", &[("show.1x06.avi", 1), ("show.1x07.avi", 1)][..], Some("show.1x07.avi")),
+            ("S03E02", &[("Show - E01.mkv", 1), ("Show - E02.mkv", 1)][..], Some("Show - E02.mkv")),
+            // numeração absoluta só como último recurso, e só na primeira temporada
+            ("S01E07", &[("[Grp] Show - 06 [720p].mkv", 1), ("[Grp] Show - 07 [720p].mkv", 1)][..], Some("[Grp] Show - 07 [720p].mkv")),
+            ("S02E07", &[("[Grp] Show - 07 [720p].mkv", 1)][..], None),
+            // ano e resolução não são episódio
+            ("E10", &[("Show 2010 1080p.mkv", 1)][..], None),
+        ];
+        for (hint, names, want) in cases {
+            assert_eq!(chosen(&pack(names), hint, None).as_deref(), want, "{hint} em {names:?}");
+        }
+    }
+
+    #[test]
+    fn prefers_explicit_matches_then_the_largest_file() {
+        let files = pack(&[
+            ("Show.S01E07.sample.mkv", 10),
+            ("Show.S01E07.mkv", 900),
+            ("Show.S01E07.nfo", 5000),
+            ("07.mkv", 9000),
+        ]);
+        assert_eq!(chosen(&files, "S01E07", None).as_deref(), Some("Show.S01E07.mkv"));
+        let files = pack(&[("Show.S01E07.mkv.part", 900), ("Show.S01E07.srt", 1)]);
+        assert_eq!(chosen(&files, "S01E07", None), None);
+    }
+
+    fn position(files: &[(PathBuf, u64)], hint: &str, offsets: Option<&SeasonOffsets>) -> Option<(String, Position)> {
+        match select(files, EpisodeHint::parse(hint)?, offsets) {
+            Selection::Found { path, position } => Some((path.file_name()?.to_string_lossy().into_owned(), position)),
+            Selection::NoMatch => None,
+        }
+    }
+
+    #[test]
+    fn season_offsets_map_to_absolute_numbers() {
+        // anime de uma temporada: a absoluta é o próprio episódio
+        let one_cour = SeasonOffsets::new([(1, 12)]);
+        assert_eq!(one_cour.absolute(1, 5), Some(5));
+        assert_eq!(one_cour.absolute(2, 1), Some(13));
+        assert_eq!(one_cour.absolute(3, 1), None);
+        // várias temporadas de tamanhos diferentes, com os especiais de fora
+        let show = SeasonOffsets::new([(0, 3), (1, 12), (2, 13), (3, 10)]);
+        assert_eq!(show.absolute(2, 5), Some(17));
+        assert_eq!(show.absolute(3, 1), Some(26));
+        assert_eq!(show.absolute(0, 1), None);
+        assert_eq!(show, SeasonOffsets::new([(1, 12), (2, 13), (3, 10)]));
+        // sem a contagem de uma temporada anterior não há como somar
+        assert_eq!(SeasonOffsets::new([(1, 12), (3, 10)]).absolute(3, 2), None);
+    }
+
+    #[test]
+    fn absolute_files_match_later_seasons_with_offsets() {
+        let offsets = SeasonOffsets::new([(0, 2), (1, 12), (2, 12)]);
+        let files = pack(&[("Saga.E16.1080p.mkv", 1), ("Saga.E17.1080p.mkv", 1)]);
+        assert_eq!(chosen(&files, "S02E05", Some(&offsets)).as_deref(), Some("Saga.E17.1080p.mkv"));
+        assert_eq!(chosen(&files, "S02E05", None), None);
+        let anime = pack(&[("[Grp] Kaiju - 04 [1080p].mkv", 1), ("[Grp] Kaiju - 05 [1080p].mkv", 1)]);
+        assert_eq!(chosen(&anime, "S01E05", None).as_deref(), Some("[Grp] Kaiju - 05 [1080p].mkv"));
+        assert_eq!(chosen(&anime, "E04", Some(&offsets)).as_deref(), Some("[Grp] Kaiju - 04 [1080p].mkv"));
+    }
+
+    #[test]
+    fn specials_need_season_zero_in_the_name() {
+        let offsets = SeasonOffsets::new([(0, 2), (1, 12)]);
+        let files = pack(&[("Saga.S00E01.Special.mkv", 1), ("Saga - 01.mkv", 1), ("Saga.E01.OVA.mkv", 1)]);
+        assert_eq!(chosen(&files, "S00E01", Some(&offsets)).as_deref(), Some("Saga.S00E01.Special.mkv"));
+        assert_eq!(chosen(&files, "S01E01", Some(&offsets)).as_deref(), Some("Saga.E01.OVA.mkv"));
+        assert_eq!(chosen(&pack(&[("Saga - 01.mkv", 1)]), "S00E01", Some(&offsets)), None);
+    }
+
+    #[test]
+    fn ranges_carry_the_position_inside_the_file() {
+        let files = pack(&[("Saga.S01E01-E03.1080p.mkv", 1), ("Saga.S01E04.1080p.mkv", 1)]);
+        let (name, at) = position(&files, "S01E02", None).unwrap();
+        assert_eq!((name.as_str(), at), ("Saga.S01E01-E03.1080p.mkv", Position { index: 1, count: 3 }));
+        assert_eq!(at.start_offset_secs(3600.0), Some(1200));
+        let (name, at) = position(&files, "S01E04", None).unwrap();
+        assert_eq!((name.as_str(), at.start_offset_secs(1400.0)), ("Saga.S01E04.1080p.mkv", None));
+        // intervalo em numeração absoluta, depois do deslocamento da temporada
+        let offsets = SeasonOffsets::new([(1, 12), (2, 12)]);
+        let anime = pack(&[("[Grp] Saga - 13-15 [1080p].mkv", 1)]);
+        let (_, at) = position(&anime, "S02E03", Some(&offsets)).unwrap();
+        assert_eq!(at, Position { index: 2, count: 3 });
+        assert_eq!(position(&anime, "S02E04", Some(&offsets)), None);
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    convert::Infallible,
+    path::{Component, Path},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    Json,
+    body::{Body, Bytes},
+    extract::State,
+    http::header,
+    response::IntoResponse,
+};
+use futures_util::stream;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::{ApiError, AppState, download_index::StoredFile};
+
+/// Versão do documento de `/admin/export`; o import recusa outras.
+const SCHEMA_VERSION: u32 = 1;
+
+/// Documento de migração. Hoje o único estado persistido é o índice de
+/// downloads; seções desconhecidas são ignoradas no import.
+#[derive(Debug, Deserialize)]
+pub struct ExportDocument {
+    schema_version: u32,
+    #[serde(default)]
+    downloads: Vec<StoredFile>,
+}
+
+/// `GET /admin/export` — estado persistido num único JSON versionado, gerado
+/// registro a registro em vez de montado inteiro em memória.
+pub async fn export_state(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
+    let exported_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let downloads = state.downloads.snapshot().await?;
+    info!(downloads = downloads.len(), "exportando estado");
+
+    let chunks = document(exported_at, downloads).map(|chunk| Ok::<_, Infallible>(Bytes::from(chunk)));
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"rossoflix-export.json\""),
+        ],
+        Body::from_stream(stream::iter(chunks)),
+    ))
+}
+
+/// Pedaços do JSON de `/admin/export`, um por registro.
+fn document(exported_at: u64, downloads: Vec<StoredFile>) -> impl Iterator<Item = Vec<u8>> {
+    let head = format!(r#"{{"schema_version":{SCHEMA_VERSION},"exported_at":{exported_at},"downloads":["#);
+    let entries = downloads.into_iter().enumerate().map(|(i, entry)| {
+        let mut chunk = if i == 0 { Vec::new() } else { b",".to_vec() };
+        // StoredFile só tem campos serializáveis: não falha
+        serde_json::to_writer(&mut chunk, &entry).expect("StoredFile serializa");
+        chunk
+    });
+    std::iter::once(head.into_bytes()).chain(entries).chain(std::iter::once(b"]}".to_vec()))
+}
+
+/// `POST /admin/import` — aplica um documento de `/admin/export`. Idempotente:
+/// registros já presentes (mesma chave natural) são pulados, e os que
+/// divergem do local contam como conflito sem sobrescrever nada.
+pub async fn import_state(
+    State(state): State<AppState>,
+    Json(doc): Json<ExportDocument>,
+) -> Result<impl IntoResponse, ApiError> {
+    if doc.schema_version != SCHEMA_VERSION {
+        return Err(ApiError::BadRequest(format!(
+            "schema_version {} não suportada (esperada {SCHEMA_VERSION})",
+            doc.schema_version
+        )));
+    }
+    if let Some(bad) = doc.downloads.iter().find(|e| !is_relative_inside(&e.path)) {
+        return Err(ApiError::BadRequest(format!("caminho inválido: {}", bad.path)));
+    }
+
+    let downloads = state.downloads.import(doc.downloads).await?;
+    info!(?downloads, "estado importado");
+    Ok(Json(serde_json::json!({
+        "schema_version": SCHEMA_VERSION,
+        "downloads": downloads,
+    })))
+}
+
+/// Caminho relativo que não sai do diretório de downloads.
+fn is_relative_inside(path: &str) -> bool {
+    !path.is_empty() && Path::new(path).components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db::Db, download_index::DownloadIndex};
+
+    fn stored(info_hash: &str, file_index: Option<usize>, path: &str, aliases: &[&str]) -> StoredFile {
+        StoredFile {
+            info_hash: info_hash.to_string(),
+            file_index,
+            path: path.to_string(),
+            size_bytes: 1000 + path.len() as u64,
+            stored_at: 1_700_000_000,
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+
+    async fn export(index: &DownloadIndex) -> Vec<u8> {
+        document(1_700_000_500, index.snapshot().await.unwrap()).flatten().collect()
+    }
+
+    #[tokio::test]
+    async fn export_wipe_import_round_trip() {
+        let base = std::env::temp_dir().join(format!("rossoflix-export-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        let index = DownloadIndex::open(Db::open(&base.join("rossoflix.db")).unwrap(), &base).await;
+
+        let a = "a".repeat(40);
+        let b = "b".repeat(40);
+        for file in [
+            stored(&a, None, &format!("{a}/Filme.2019.mkv"), &[]),
+            stored(&b, Some(0), &format!("{b}/Serie.S01E01.mkv"), &["Serie.S01E01.mp4"]),
+            stored(&b, Some(3), &format!("{b}/Serie.S01E02.mkv"), &[]),
+        ] {
+            index.upsert(file).await.unwrap();
+        }
+        let first = export(&index).await;
+
+        for hash in [&a, &b] {
+            index.remove(hash).await.unwrap();
+        }
+        assert!(index.snapshot().await.unwrap().is_empty(), "índice não foi limpo");
+
+        let doc: ExportDocument = serde_json::from_slice(&first).unwrap();
+        assert_eq!(doc.schema_version, SCHEMA_VERSION);
+        let counts = index.import(doc.downloads).await.unwrap();
+        assert_eq!((counts.created, counts.skipped, counts.conflicting), (3, 0, 0));
+        assert_eq!(export(&index).await, first, "exports diferentes");
+
+        // Reimportar o mesmo documento não muda nada.
+        let doc: ExportDocument = serde_json::from_slice(&first).unwrap();
+        let counts = index.import(doc.downloads).await.unwrap();
+        assert_eq!((counts.created, counts.skipped, counts.conflicting), (0, 3, 0));
+        assert_eq!(export(&index).await, first);
+
+        let _ = std::fs::remove_dir_all(&base);
+    }
+}
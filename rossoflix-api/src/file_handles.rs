@@ -0,0 +1,211 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use axum::body::Bytes;
+use futures_util::Stream;
+use serde::Serialize;
+
+use crate::content_type::{self, Detected};
+
+/// Entrada sem uso há esse tempo é fechada: limita por quanto tempo um
+/// arquivo trocado por fora da API (sem lease) ainda sairia do descritor
+/// antigo, e não segura o espaço de arquivos apagados.
+const IDLE: Duration = Duration::from_secs(60);
+/// Tamanho de cada leitura dos corpos.
+const CHUNK: usize = 64 * 1024;
+
+/// Um arquivo aberto e os metadados que o `/stream` usa. O descritor é
+/// compartilhado, mas só é lido por posição (`pread`): leitores simultâneos
+/// não dividem cursor.
+#[derive(Clone)]
+pub struct Handle {
+    pub file: Arc<File>,
+    pub size: u64,
+    pub etag: String,
+    pub content_type: Detected,
+}
+
+impl Handle {
+    fn new(path: &Path, file: File) -> io::Result<Self> {
+        let meta = file.metadata()?;
+        let content_type = content_type::sniff(path, &file)?;
+        let modified = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        Ok(Handle {
+            file: Arc::new(file),
+            size: meta.len(),
+            etag: format!("\"{:x}-{modified:x}\"", meta.len()),
+            content_type,
+        })
+    }
+}
+
+struct Entry {
+    handle: Handle,
+    /// Ordem do último uso, para achar o menos recente.
+    used: u64,
+    last_used: Instant,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<PathBuf, Entry>,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+    invalidations: u64,
+}
+
+/// LRU dos arquivos abertos pelo `/stream`, por caminho: os pedidos `Range`
+/// seguidos de um player não reabrem nem consultam o arquivo a cada vez.
+/// Remoções pelo [`FileLeaseRegistry`](crate::leases::FileLeaseRegistry)
+/// descartam as entradas do caminho.
+#[derive(Clone, Default)]
+pub struct FileHandles(Arc<Mutex<Inner>>);
+
+#[derive(Serialize)]
+pub struct Stats {
+    entries: usize,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+    invalidations: u64,
+}
+
+/// Chave das entradas: o caminho sem `.` nem barras repetidas. Os caminhos
+/// já chegam montados pelo servidor a partir de `DOWNLOADS_DIR`.
+fn key(path: &Path) -> PathBuf {
+    path.components().collect()
+}
+
+impl FileHandles {
+    /// O arquivo aberto, do cache ou aberto agora. Com `capacity` 0 nada
+    /// fica guardado.
+    pub async fn open(&self, path: &Path, capacity: usize) -> io::Result<Handle> {
+        let key = key(path);
+        if let Some(handle) = self.lookup(&key) {
+            return Ok(handle);
+        }
+        let opened = {
+            let path = key.clone();
+            tokio::task::spawn_blocking(move || Handle::new(&path, File::open(&path)?))
+                .await
+                .map_err(io::Error::other)??
+        };
+        // arquivo vazio ainda vai ser escrito; o tamanho guardado ficaria velho
+        if capacity > 0 && opened.size > 0 {
+            self.store(key, opened.clone(), capacity);
+        }
+        Ok(opened)
+    }
+
+    fn lookup(&self, key: &Path) -> Option<Handle> {
+        let mut inner = self.0.lock().unwrap();
+        inner.clock += 1;
+        let clock = inner.clock;
+        match inner.entries.get_mut(key) {
+            Some(entry) if entry.last_used.elapsed() < IDLE => {
+                entry.used = clock;
+                entry.last_used = Instant::now();
+                let handle = entry.handle.clone();
+                inner.hits += 1;
+                Some(handle)
+            }
+            Some(_) => {
+                inner.entries.remove(key);
+                inner.evictions += 1;
+                inner.misses += 1;
+                None
+            }
+            None => {
+                inner.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn store(&self, key: PathBuf, handle: Handle, capacity: usize) {
+        let mut inner = self.0.lock().unwrap();
+        inner.clock += 1;
+        let entry = Entry { handle, used: inner.clock, last_used: Instant::now() };
+        inner.entries.insert(key, entry);
+
+        let before = inner.entries.len();
+        inner.entries.retain(|_, e| e.last_used.elapsed() < IDLE);
+        while inner.entries.len() > capacity {
+            let Some(oldest) = inner.entries.iter().min_by_key(|(_, e)| e.used).map(|(k, _)| k.clone()) else {
+                break;
+            };
+            inner.entries.remove(&oldest);
+        }
+        inner.evictions += (before - inner.entries.len()) as u64;
+    }
+
+    /// Descarta `path` e tudo abaixo dele (o arquivo vai ser removido ou
+    /// trocado).
+    pub fn invalidate(&self, path: &Path) {
+        let path = key(path);
+        let mut inner = self.0.lock().unwrap();
+        let before = inner.entries.len();
+        inner.entries.retain(|k, _| !k.starts_with(&path));
+        inner.invalidations += (before - inner.entries.len()) as u64;
+    }
+
+    pub fn stats(&self) -> Stats {
+        let inner = self.0.lock().unwrap();
+        Stats {
+            entries: inner.entries.len(),
+            hits: inner.hits,
+            misses: inner.misses,
+            evictions: inner.evictions,
+            invalidations: inner.invalidations,
+        }
+    }
+}
+
+/// Corpo com os `len` bytes de `file` a partir de `start`, lidos por posição.
+pub fn read_range(file: Arc<File>, start: u64, len: u64) -> impl Stream<Item = io::Result<Bytes>> + Send + 'static {
+    futures_util::stream::try_unfold((file, start, start + len), |(file, pos, end)| async move {
+        if pos >= end {
+            return Ok(None);
+        }
+        let want = ((end - pos) as usize).min(CHUNK);
+        let (file, buf) = tokio::task::spawn_blocking(move || {
+            let mut buf = vec![0; want];
+            let read = read_at(&file, &mut buf, pos)?;
+            buf.truncate(read);
+            Ok::<_, io::Error>((file, buf))
+        })
+        .await
+        .map_err(io::Error::other)??;
+        if buf.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "o arquivo encolheu durante o stream"));
+        }
+        let next = pos + buf.len() as u64;
+        Ok(Some((Bytes::from(buf), (file, next, end))))
+    })
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], pos: u64) -> io::Result<usize> {
+    use std::os::unix::fs::FileExt;
+    file.read_at(buf, pos)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], pos: u64) -> io::Result<usize> {
+    // o `seek_read` move o cursor, mas toda leitura aqui diz a posição
+    use std::os::windows::fs::FileExt;
+    file.seek_read(buf, pos)
+}
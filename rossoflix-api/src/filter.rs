@@ -0,0 +1,55 @@
+/// Filtro de títulos das listas (`?filter=`), pensado para digitação no
+/// controle remoto: ignora caixa e acentos, e aceita tanto um trecho do
+/// título ("atrix") quanto o começo de cada palavra ("sen an" → "Senhor dos
+/// Anéis").
+pub struct TitleFilter {
+    query: String,
+    words: Vec<String>,
+}
+
+impl TitleFilter {
+    /// `None` para filtro vazio (só espaços).
+    pub fn new(raw: &str) -> Option<Self> {
+        let query = normalize(raw);
+        let words: Vec<String> = query.split_whitespace().map(String::from).collect();
+        if words.is_empty() {
+            return None;
+        }
+        Some(TitleFilter {
+            query: words.join(" "),
+            words,
+        })
+    }
+
+    pub fn matches(&self, title: &str) -> bool {
+        let title = normalize(title);
+        if title.contains(&self.query) {
+            return true;
+        }
+        let title_words: Vec<&str> = title.split_whitespace().collect();
+        self.words
+            .iter()
+            .all(|w| title_words.iter().any(|t| t.starts_with(w.as_str())))
+    }
+}
+
+/// Minúsculas, sem acentos, pontuação vira espaço.
+fn normalize(text: &str) -> String {
+    text.chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| match c {
+            'á' | 'à' | 'â' | 'ã' | 'ä' | 'å' => 'a',
+            'é' | 'è' | 'ê' | 'ë' => 'e',
+            'í' | 'ì' | 'î' | 'ï' => 'i',
+            'ó' | 'ò' | 'ô' | 'õ' | 'ö' => 'o',
+            'ú' | 'ù' | 'û' | 'ü' => 'u',
+            'ç' => 'c',
+            'ñ' => 'n',
+            c if c.is_alphanumeric() => c,
+            _ => ' ',
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
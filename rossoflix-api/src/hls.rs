@@ -0,0 +1,320 @@
+use std::{
+    io::SeekFrom,
+    net::SocketAddr,
+    path::{Path as StdPath, PathBuf},
+    sync::Arc,
+};
+
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::{HeaderMap, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt},
+    process::Command,
+};
+use tracing::{info, warn};
+
+use crate::{ApiError, AppState, cache::CacheEnvelope, find_downloaded_file, progress, serve_file};
+
+/// Duração mínima de um segmento; fragmentos curtos se juntam até ela.
+const TARGET_SEGMENT_SECS: f64 = 6.0;
+/// Versão das playlists guardadas em `.<nome>.m3u8`. Suba ao mudar a
+/// playlist gerada: as antigas passam a ser refeitas (versão 0 era o texto
+/// puro, sem envelope).
+const PLAYLIST_CACHE_VERSION: u8 = 1;
+
+/// `GET /hls/file/:filename/playlist.m3u8` — playlist HLS de um arquivo já
+/// baixado, sem reencodar: os segmentos são faixas de bytes
+/// (`EXT-X-BYTERANGE`) do próprio arquivo, servidas por
+/// `/hls/file/:filename/media` com o mesmo `Range` de `/stream`. Só serve
+/// para MP4 fragmentado; os demais recebem `409` (use a transcodificação).
+/// A playlist gerada fica em cache ao lado do arquivo (`.<nome>.m3u8`).
+pub async fn file_playlist(
+    State(state): State<AppState>,
+    Path(filename): Path<String>,
+) -> Result<Response, ApiError> {
+    let source = downloaded(&state, &filename).await?;
+    let cached = cache_path(&source);
+    let playlist = match read_cached(&cached, &source).await {
+        Some(playlist) => playlist,
+        None => {
+            let _lease = state
+                .leases
+                .read(&source)
+                .map_err(|busy| ApiError::Conflict(format!("{} está sendo removido", busy.0.display())))?;
+            let playlist = build_playlist(&state, &source).await?;
+            write_cached(&cached, &playlist).await;
+            playlist
+        }
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/vnd.apple.mpegurl"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        playlist,
+    )
+        .into_response())
+}
+
+/// `GET /hls/file/:filename/media` — o arquivo em si, com `Range`.
+pub async fn file_media(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    Path(filename): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let source = downloaded(&state, &filename).await?;
+    serve_file(&state, &source, &headers, client.ip(), None, None).await
+}
+
+/// Arquivo baixado por inteiro (sem `.aria2` ao lado).
+async fn downloaded(state: &AppState, filename: &str) -> Result<PathBuf, ApiError> {
+    let source = find_downloaded_file(&state.config().downloads_dir, filename)
+        .await
+        .ok_or_else(|| ApiError::NotFound(format!("{filename} não encontrado")))?;
+    if fs::try_exists(progress::control_file_path(&source)).await.unwrap_or(false) {
+        return Err(ApiError::Conflict(format!("{filename} ainda está sendo baixado")));
+    }
+    Ok(source)
+}
+
+fn cache_path(source: &StdPath) -> PathBuf {
+    let name = source.file_name().unwrap_or_default().to_string_lossy();
+    source.with_file_name(format!(".{name}.m3u8"))
+}
+
+/// Playlist em cache, se for mais nova que o arquivo e da versão atual.
+async fn read_cached(cached: &StdPath, source: &StdPath) -> Option<String> {
+    let built = fs::metadata(cached).await.ok()?.modified().ok()?;
+    let changed = fs::metadata(source).await.ok()?.modified().ok()?;
+    if built < changed {
+        return None;
+    }
+    CacheEnvelope::open(&fs::read(cached).await.ok()?, PLAYLIST_CACHE_VERSION)
+}
+
+async fn write_cached(cached: &StdPath, playlist: &str) {
+    let tmp = cached.with_extension("m3u8.tmp");
+    let result = match CacheEnvelope::seal(PLAYLIST_CACHE_VERSION, playlist) {
+        Ok(bytes) => match fs::write(&tmp, bytes).await {
+            Ok(()) => fs::rename(&tmp, cached).await,
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = result {
+        warn!(path = %cached.display(), "falha ao guardar a playlist HLS: {e}");
+    }
+}
+
+/// Onde ficam o cabeçalho (`ftyp` + `moov`) e cada fragmento
+/// (`moof` + `mdat`) de um MP4 fragmentado.
+#[derive(Debug)]
+struct Layout {
+    init_len: u64,
+    /// Início de cada fragmento; o último vai até `end`.
+    fragments: Vec<u64>,
+    end: u64,
+}
+
+/// Percorre as caixas de primeiro nível do MP4 (só os cabeçalhos).
+async fn scan_boxes(path: &StdPath) -> std::io::Result<Option<Layout>> {
+    let mut file = fs::File::open(path).await?;
+    let len = file.metadata().await?.len();
+    let mut init_len = None;
+    let mut fragments = Vec::new();
+    let mut end = len;
+    let mut pending_styp = None;
+    let mut offset = 0;
+    while offset + 8 <= len {
+        file.seek(SeekFrom::Start(offset)).await?;
+        let mut header = [0u8; 16];
+        let n = if offset + 16 <= len { 16 } else { 8 };
+        file.read_exact(&mut header[..n]).await?;
+        let size32 = u32::from_be_bytes(header[..4].try_into().unwrap()) as u64;
+        let kind = &header[4..8];
+        let size = match size32 {
+            0 => len - offset,
+            1 if n == 16 => u64::from_be_bytes(header[8..16].try_into().unwrap()),
+            1 => return Ok(None),
+            size => size,
+        };
+        if size < 8 {
+            return Ok(None);
+        }
+        match kind {
+            b"moov" => init_len = Some(offset + size),
+            // `styp` abre o segmento seguinte
+            b"styp" => pending_styp = Some(offset),
+            b"moof" if init_len.is_some() => fragments.push(pending_styp.take().unwrap_or(offset)),
+            b"moof" => return Ok(None),
+            b"mfra" => {
+                end = offset;
+                break;
+            }
+            _ => {}
+        }
+        offset += size;
+    }
+    match init_len {
+        Some(init_len) if !fragments.is_empty() => Ok(Some(Layout { init_len, fragments, end })),
+        _ => Ok(None),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PacketsOutput {
+    #[serde(default)]
+    packets: Vec<Packet>,
+    format: Option<PacketsFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Packet {
+    pts_time: Option<String>,
+    pos: Option<String>,
+    #[serde(default)]
+    flags: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PacketsFormat {
+    duration: Option<String>,
+}
+
+/// Pacote de vídeo: posição no arquivo, instante e se é quadro-chave.
+struct VideoPacket {
+    pos: u64,
+    pts: f64,
+    key: bool,
+}
+
+/// Índice de pacotes do vídeo pelo ffprobe (só demux, sem decodificar),
+/// pela fila de mídia.
+async fn video_packets(state: &AppState, path: &StdPath) -> Result<Arc<(Vec<VideoPacket>, f64)>, ApiError> {
+    let path = path.to_path_buf();
+    let key = format!("packets:{}", path.display());
+    state
+        .media_jobs
+        .run(&state.config(), key, async move { probe_packets(&path).await.map(Arc::new) })
+        .await
+        .map_err(|e| e.into_api(ApiError::Unavailable))
+}
+
+async fn probe_packets(path: &StdPath) -> Result<(Vec<VideoPacket>, f64), String> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "packet=pts_time,pos,flags:format=duration",
+            "-print_format",
+            "json",
+        ])
+        .arg(path)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("ffprobe indisponível: {e}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("ffprobe falhou: {}", stderr.trim()));
+    }
+    let parsed: PacketsOutput =
+        serde_json::from_slice(&output.stdout).map_err(|e| format!("saída do ffprobe inválida: {e}"))?;
+    let duration = parsed
+        .format
+        .and_then(|f| f.duration)
+        .and_then(|d| d.parse().ok())
+        .unwrap_or_default();
+    let packets = parsed
+        .packets
+        .into_iter()
+        .filter_map(|p| {
+            Some(VideoPacket {
+                pos: p.pos?.parse().ok()?,
+                pts: p.pts_time?.parse().ok()?,
+                key: p.flags.starts_with('K'),
+            })
+        })
+        .collect();
+    Ok((packets, duration))
+}
+
+/// Segmento da playlist: faixa de bytes e duração.
+struct Segment {
+    offset: u64,
+    len: u64,
+    secs: f64,
+}
+
+async fn build_playlist(state: &AppState, source: &StdPath) -> Result<String, ApiError> {
+    let not_fragmented = || {
+        ApiError::Conflict(
+            "o arquivo não é MP4 fragmentado: use a transcodificação em vez de /hls/file".into(),
+        )
+    };
+    let layout = scan_boxes(source)
+        .await
+        .map_err(|e| ApiError::Storage(format!("falha ao ler {}: {e}", source.display())))?
+        .ok_or_else(not_fragmented)?;
+    let probed = video_packets(state, source).await?;
+    let (packets, duration) = (&probed.0, probed.1);
+    if packets.is_empty() {
+        return Err(not_fragmented());
+    }
+
+    // início (menor pts) e quadro-chave inicial de cada fragmento
+    let mut starts = Vec::with_capacity(layout.fragments.len());
+    for (i, &frag) in layout.fragments.iter().enumerate() {
+        let next = layout.fragments.get(i + 1).copied().unwrap_or(layout.end);
+        let inside: Vec<&VideoPacket> = packets.iter().filter(|p| p.pos >= frag && p.pos < next).collect();
+        let start = inside.iter().map(|p| p.pts).reduce(f64::min);
+        let key = inside.iter().min_by_key(|p| p.pos).is_some_and(|p| p.key);
+        starts.push((frag, start, key));
+    }
+
+    // junta fragmentos até ~TARGET_SEGMENT_SECS, cortando só em quadro-chave
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut current: Option<(u64, f64)> = None;
+    for &(frag, start, key) in &starts {
+        let Some(start) = start else { continue };
+        match current {
+            Some((_, begin)) if !key || start - begin < TARGET_SEGMENT_SECS => {}
+            Some((offset, begin)) => {
+                segments.push(Segment { offset, len: frag - offset, secs: start - begin });
+                current = Some((frag, start));
+            }
+            None => current = Some((frag, start)),
+        }
+    }
+    let Some((offset, begin)) = current else {
+        return Err(not_fragmented());
+    };
+    segments.push(Segment {
+        offset,
+        len: layout.end - offset,
+        secs: (duration - begin).max(0.1),
+    });
+
+    let target = segments.iter().map(|s| s.secs).fold(0.0, f64::max).ceil() as u64;
+    let mut out = String::new();
+    out.push_str("#EXTM3U\n#EXT-X-VERSION:7\n");
+    out.push_str(&format!("#EXT-X-TARGETDURATION:{target}\n"));
+    out.push_str("#EXT-X-PLAYLIST-TYPE:VOD\n#EXT-X-INDEPENDENT-SEGMENTS\n");
+    out.push_str(&format!("#EXT-X-MAP:URI=\"media\",BYTERANGE=\"{}@0\"\n", layout.init_len));
+    for segment in &segments {
+        out.push_str(&format!("#EXTINF:{:.3},\n", segment.secs));
+        out.push_str(&format!("#EXT-X-BYTERANGE:{}@{}\nmedia\n", segment.len, segment.offset));
+    }
+    out.push_str("#EXT-X-ENDLIST\n");
+    info!(file = %source.display(), segments = segments.len(), "playlist HLS por faixas de bytes gerada");
+    Ok(out)
+}
@@ -0,0 +1,192 @@
+use std::str::FromStr;
+
+use serde_json::{Map, Value};
+
+use crate::dates;
+
+/// Idioma dos campos formatados (`?locale=`). Um locale desconhecido cai no
+/// inglês.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    En,
+    PtBr,
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().replace('_', "-").as_str() {
+            "en" | "en-us" | "en-gb" => Ok(Locale::En),
+            "pt" | "pt-br" => Ok(Locale::PtBr),
+            other => Err(format!("locale sem suporte: {other}")),
+        }
+    }
+}
+
+/// Duração em minutos pelo `Runtime` do OMDb: `136 min`, `1 min`,
+/// `1,440 min`, `90 min (approx.)` e `1 h 30 min`. `None` para `N/A`, zero
+/// ou texto sem número.
+pub fn runtime_minutes(raw: &str) -> Option<u32> {
+    let text = raw.to_ascii_lowercase().replace(',', "");
+    let mut total = 0u32;
+    let mut pending: Option<u32> = None;
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_ascii_digit() {
+            let mut number = String::new();
+            while let Some(d) = chars.peek().copied().filter(char::is_ascii_digit) {
+                number.push(d);
+                chars.next();
+            }
+            // um número sem unidade antes de outro número é ignorado
+            pending = number.parse().ok();
+        } else if c.is_ascii_alphabetic() {
+            let mut unit = String::new();
+            while let Some(l) = chars.peek().copied().filter(char::is_ascii_alphabetic) {
+                unit.push(l);
+                chars.next();
+            }
+            if let Some(n) = pending.take() {
+                match unit.as_str() {
+                    "h" | "hr" | "hrs" | "hour" | "hours" => total = total.saturating_add(n.saturating_mul(60)),
+                    "m" | "min" | "mins" | "minute" | "minutes" => total = total.saturating_add(n),
+                    _ => {}
+                }
+            }
+        } else {
+            chars.next();
+        }
+    }
+    (total > 0).then_some(total)
+}
+
+/// `136` → `2 hours 16 minutes` / `2 horas e 16 minutos`.
+pub fn format_runtime(minutes: u32, locale: Locale) -> String {
+    let (hours, minutes) = (minutes / 60, minutes % 60);
+    let (hour, hour_plural, minute, minute_plural, joiner) = match locale {
+        Locale::En => ("hour", "hours", "minute", "minutes", " "),
+        Locale::PtBr => ("hora", "horas", "minuto", "minutos", " e "),
+    };
+    let plural = |n: u32, one: &str, many: &str| format!("{n} {}", if n == 1 { one } else { many });
+    match (hours, minutes) {
+        (0, m) => plural(m, minute, minute_plural),
+        (h, 0) => plural(h, hour, hour_plural),
+        (h, m) => format!("{}{joiner}{}", plural(h, hour, hour_plural), plural(m, minute, minute_plural)),
+    }
+}
+
+/// `Released` do OMDb (`14 Oct 1994`) em ISO 8601 (`1994-10-14`). Aceita
+/// também o dia sem zero e o mês por extenso; `None` para `N/A`, só o ano
+/// ou uma data que não existe.
+pub fn released_iso(raw: &str) -> Option<String> {
+    let mut parts = raw.split_whitespace();
+    let (day, month, year) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+    let day: u32 = day.parse().ok()?;
+    let month = month_number(month)?;
+    let iso = format!("{year:0>4}-{month:02}-{day:02}");
+    dates::parse(&iso).map(|_| iso)
+}
+
+fn month_number(name: &str) -> Option<u32> {
+    const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+    let prefix = name.get(..3)?.to_ascii_lowercase();
+    MONTHS.iter().position(|m| *m == prefix).map(|i| i as u32 + 1)
+}
+
+/// Início e fim pelo `Year` do OMDb: `1994` → (1994, 1994), `2008–2013`
+/// (travessão ou hífen) → (2008, 2013) e `2019–` (série em exibição) →
+/// (2019, `None`).
+pub fn year_range(raw: &str) -> (Option<i32>, Option<i32>) {
+    let year = |s: &str| {
+        let s = s.trim();
+        (s.len() == 4).then(|| s.parse::<i32>().ok()).flatten()
+    };
+    match raw.split_once(['–', '—', '-']) {
+        Some((start, end)) => (year(start), year(end)),
+        None => {
+            let single = year(raw);
+            (single, single)
+        }
+    }
+}
+
+/// Acrescenta os campos calculados a um título do OMDb (ou item da busca):
+/// `runtime_minutes`, `runtime_formatted`, `released_iso`, `year_start` e
+/// `year_end`. Campos que não dá para calcular ficam `null`; só entram os
+/// ligados a campos que o título traz.
+pub fn add_fields(fields: &mut Map<String, Value>, locale: Locale) {
+    if let Some(runtime) = fields.get("Runtime").and_then(Value::as_str) {
+        let minutes = runtime_minutes(runtime);
+        fields.insert("runtime_minutes".into(), minutes.into());
+        fields.insert("runtime_formatted".into(), minutes.map(|m| format_runtime(m, locale)).into());
+    }
+    if let Some(released) = fields.get("Released").and_then(Value::as_str) {
+        fields.insert("released_iso".into(), released_iso(released).into());
+    }
+    let year = match fields.get("Year") {
+        Some(Value::String(year)) => Some(year_range(year)),
+        Some(Value::Number(n)) => n.as_i64().map(|y| (Some(y as i32), Some(y as i32))),
+        _ => None,
+    };
+    if let Some((start, end)) = year {
+        fields.insert("year_start".into(), start.into());
+        fields.insert("year_end".into(), end.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runtime_minutes_reads_omdb_runtimes() {
+        assert_eq!(runtime_minutes("136 min"), Some(136));
+        assert_eq!(runtime_minutes("1 min"), Some(1));
+        assert_eq!(runtime_minutes("1,440 min"), Some(1440));
+        assert_eq!(runtime_minutes("90 min (approx.)"), Some(90));
+        assert_eq!(runtime_minutes("1 h 30 min"), Some(90));
+        assert_eq!(runtime_minutes("N/A"), None);
+        assert_eq!(runtime_minutes("0 min"), None);
+    }
+
+    #[test]
+    fn format_runtime_plurals_per_locale() {
+        assert_eq!(format_runtime(1, Locale::En), "1 minute");
+        assert_eq!(format_runtime(45, Locale::En), "45 minutes");
+        assert_eq!(format_runtime(61, Locale::En), "1 hour 1 minute");
+        assert_eq!(format_runtime(136, Locale::En), "2 hours 16 minutes");
+        assert_eq!(format_runtime(1, Locale::PtBr), "1 minuto");
+        assert_eq!(format_runtime(45, Locale::PtBr), "45 minutos");
+        assert_eq!(format_runtime(61, Locale::PtBr), "1 hora e 1 minuto");
+        assert_eq!(format_runtime(136, Locale::PtBr), "2 horas e 16 minutos");
+        // horas exatas não ganham "0 minutos"
+        assert_eq!(format_runtime(60, Locale::En), "1 hour");
+        assert_eq!(format_runtime(120, Locale::En), "2 hours");
+        assert_eq!(format_runtime(60, Locale::PtBr), "1 hora");
+        assert_eq!(format_runtime(180, Locale::PtBr), "3 horas");
+    }
+
+    #[test]
+    fn released_iso_only_for_real_dates() {
+        assert_eq!(released_iso("14 Oct 1994").as_deref(), Some("1994-10-14"));
+        assert_eq!(released_iso("4 September 2001").as_deref(), Some("2001-09-04"));
+        assert_eq!(released_iso("N/A"), None);
+        assert_eq!(released_iso("1994"), None);
+        assert_eq!(released_iso("31 Apr 2001"), None);
+    }
+
+    #[test]
+    fn year_range_splits_on_any_dash() {
+        assert_eq!(year_range("1994"), (Some(1994), Some(1994)));
+        assert_eq!(year_range("2019–"), (Some(2019), None));
+        assert_eq!(year_range("2008–2013"), (Some(2008), Some(2013)));
+        assert_eq!(year_range("2008-2013"), (Some(2008), Some(2013)));
+        assert_eq!(year_range("2008—2013"), (Some(2008), Some(2013)));
+        assert_eq!(year_range("N/A"), (None, None));
+    }
+}
@@ -0,0 +1,200 @@
+use std::{fmt, str::FromStr};
+
+use serde::Serialize;
+
+use crate::release_name::tokenize;
+
+/// Idiomas de áudio e legenda de um release, deduzidos do título.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Languages {
+    /// Áudios anunciados (`pt-BR`, `en`...); vazio quando o título não diz.
+    pub languages: Vec<&'static str>,
+    /// Legendas embutidas anunciadas (`LEGENDADO` → `pt-BR`).
+    pub subtitles: Vec<&'static str>,
+    /// Traz dublagem, além do áudio original.
+    pub dubbed: bool,
+}
+
+/// Onde o indicador aparece no título.
+enum Pattern {
+    /// Tokens seguidos, como saem de `release_name::tokenize`.
+    Tokens(&'static [&'static str]),
+    /// Código que só vale entre colchetes ou parênteses (`[ENG]`, `(POR)`),
+    /// para `por` ou `ita` soltos no nome do filme não contarem.
+    Code(&'static str),
+    /// Emoji de bandeira, como o torrentio põe na descrição.
+    Flag(&'static str),
+}
+
+/// Um indicador e o que ele diz sobre o release.
+struct Marker {
+    pattern: Pattern,
+    audio: &'static [&'static str],
+    subtitles: &'static [&'static str],
+    dubbed: bool,
+}
+
+const fn tokens(tokens: &'static [&'static str], audio: &'static [&'static str], dubbed: bool) -> Marker {
+    Marker { pattern: Pattern::Tokens(tokens), audio, subtitles: &[], dubbed }
+}
+
+const fn code(code: &'static str, audio: &'static [&'static str]) -> Marker {
+    Marker { pattern: Pattern::Code(code), audio, subtitles: &[], dubbed: false }
+}
+
+const fn flag(flag: &'static str, audio: &'static [&'static str]) -> Marker {
+    Marker { pattern: Pattern::Flag(flag), audio, subtitles: &[], dubbed: false }
+}
+
+/// Tabela de indicadores. Para outro idioma, acrescente as linhas dele.
+/// `DUAL` segue a convenção dos releases brasileiros (português + inglês);
+/// `MULTI` sozinho não diz quais são, só as bandeiras ou códigos ao lado.
+const MARKERS: &[Marker] = &[
+    // português do Brasil
+    tokens(&["dublado"], &["pt-BR"], true),
+    tokens(&["dublagem"], &["pt-BR"], true),
+    tokens(&["dual"], &["pt-BR", "en"], true),
+    tokens(&["nacional"], &["pt-BR"], false),
+    tokens(&["ptbr"], &["pt-BR"], false),
+    tokens(&["pt", "br"], &["pt-BR"], false),
+    tokens(&["portugues"], &["pt-BR"], false),
+    tokens(&["português"], &["pt-BR"], false),
+    Marker { pattern: Pattern::Tokens(&["legendado"]), audio: &[], subtitles: &["pt-BR"], dubbed: false },
+    Marker { pattern: Pattern::Tokens(&["leg"]), audio: &[], subtitles: &["pt-BR"], dubbed: false },
+    code("por", &["pt"]),
+    code("pt", &["pt"]),
+    flag("🇧🇷", &["pt-BR"]),
+    flag("🇵🇹", &["pt-PT"]),
+    // demais
+    tokens(&["multi"], &[], true),
+    tokens(&["dubbed"], &[], true),
+    tokens(&["english"], &["en"], false),
+    code("eng", &["en"]),
+    code("en", &["en"]),
+    flag("🇬🇧", &["en"]),
+    flag("🇺🇸", &["en"]),
+    tokens(&["castellano"], &["es"], false),
+    tokens(&["latino"], &["es-419"], false),
+    code("spa", &["es"]),
+    code("esp", &["es"]),
+    flag("🇪🇸", &["es"]),
+    flag("🇲🇽", &["es-419"]),
+    tokens(&["french"], &["fr"], false),
+    tokens(&["truefrench"], &["fr"], false),
+    code("fre", &["fr"]),
+    flag("🇫🇷", &["fr"]),
+    tokens(&["italian"], &["it"], false),
+    code("ita", &["it"]),
+    flag("🇮🇹", &["it"]),
+    tokens(&["german"], &["de"], false),
+    code("ger", &["de"]),
+    flag("🇩🇪", &["de"]),
+    code("jpn", &["ja"]),
+    code("jap", &["ja"]),
+    flag("🇯🇵", &["ja"]),
+];
+
+/// Idiomas anunciados em `text` (nome e título do release). `tokens` é o
+/// `text` já separado por [`tokenize`].
+pub fn detect(text: &str, tokens: &[String]) -> Languages {
+    let bracketed = tokenize(&bracketed(text));
+    let mut found = Languages::default();
+    for marker in MARKERS {
+        let hit = match marker.pattern {
+            Pattern::Tokens(seq) => tokens.windows(seq.len()).any(|w| w.iter().zip(seq).all(|(a, b)| a == b)),
+            Pattern::Code(code) => bracketed.iter().any(|t| t == code),
+            Pattern::Flag(flag) => text.contains(flag),
+        };
+        if !hit {
+            continue;
+        }
+        found.dubbed |= marker.dubbed;
+        for &lang in marker.audio {
+            push_unique(&mut found.languages, lang);
+        }
+        for &lang in marker.subtitles {
+            push_unique(&mut found.subtitles, lang);
+        }
+    }
+    // `pt` genérico some quando o título já diz qual português é
+    if found.languages.iter().any(|l| l.starts_with("pt-")) {
+        found.languages.retain(|l| *l != "pt");
+    }
+    found
+}
+
+fn push_unique(list: &mut Vec<&'static str>, lang: &'static str) {
+    if !list.contains(&lang) {
+        list.push(lang);
+    }
+}
+
+/// Só o que está entre `[]` ou `()`, separado por espaços (o `+` também
+/// separa, como em `[ENG+POR]`).
+fn bracketed(text: &str) -> String {
+    let mut out = String::new();
+    let mut depth = 0usize;
+    for c in text.chars() {
+        match c {
+            '[' | '(' => {
+                depth += 1;
+                out.push(' ');
+            }
+            ']' | ')' => depth = depth.saturating_sub(1),
+            '+' if depth > 0 => out.push(' '),
+            c if depth > 0 => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+impl Languages {
+    /// O release tem áudio em `want`. Um `pt` sem região vale para `pt-BR`
+    /// e `pt-PT`, e `es-419` vale para quem pede `es`.
+    pub fn has_audio(&self, want: &LanguageTag) -> bool {
+        self.languages.iter().any(|have| {
+            let have_primary = have.split('-').next().unwrap_or(have);
+            have.eq_ignore_ascii_case(&want.0) || *have == want.primary() || have_primary == want.0
+        })
+    }
+}
+
+/// Tag de idioma normalizada (`pt-BR`, `en`, `es-419`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct LanguageTag(String);
+
+impl LanguageTag {
+    fn primary(&self) -> &str {
+        self.0.split('-').next().unwrap_or(&self.0)
+    }
+}
+
+impl FromStr for LanguageTag {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let mut parts = raw.trim().split(['-', '_']);
+        let primary = parts.next().unwrap_or_default();
+        let region = parts.next();
+        let valid_primary = (2..=3).contains(&primary.len()) && primary.chars().all(|c| c.is_ascii_alphabetic());
+        let valid_region = region.is_none_or(|r| {
+            (r.len() == 2 && r.chars().all(|c| c.is_ascii_alphabetic()))
+                || (r.len() == 3 && r.chars().all(|c| c.is_ascii_digit()))
+        });
+        if !valid_primary || !valid_region || parts.next().is_some() {
+            return Err(format!("tag de idioma inválida: {raw} (use pt-BR, en, es-419...)"));
+        }
+        Ok(LanguageTag(match region {
+            Some(region) => format!("{}-{}", primary.to_ascii_lowercase(), region.to_ascii_uppercase()),
+            None => primary.to_ascii_lowercase(),
+        }))
+    }
+}
+
+impl fmt::Display for LanguageTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
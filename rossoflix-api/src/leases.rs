@@ -0,0 +1,280 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{Json, extract::State, response::IntoResponse};
+use serde::Serialize;
+
+use crate::{AppState, file_handles::FileHandles, stream_title::StreamTitle};
+
+/// Quem está usando arquivos de download: streams seguram leases de leitura
+/// enquanto o corpo da resposta estiver vivo; remoções precisam de um lease
+/// exclusivo, negado se houver leitor no caminho (ou abaixo dele).
+#[derive(Clone, Default)]
+pub struct FileLeaseRegistry {
+    inner: Arc<Mutex<Leases>>,
+    /// Descritores abertos do `/stream`, descartados ao conceder e ao
+    /// liberar um lease exclusivo.
+    handles: FileHandles,
+}
+
+#[derive(Default)]
+struct Leases {
+    next_id: u64,
+    readers: HashMap<u64, LeaseInfo>,
+    exclusive: HashMap<u64, PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LeaseInfo {
+    pub id: u64,
+    pub path: PathBuf,
+    /// Unix timestamp (s) de quando o stream começou.
+    pub started_at: u64,
+    /// Título assistido, quando o cliente manda as dicas em `/stream`.
+    pub title: Option<StreamTitle>,
+}
+
+/// O caminho está ocupado por um lease incompatível.
+#[derive(Debug)]
+pub struct Busy(pub PathBuf);
+
+impl FileLeaseRegistry {
+    pub fn new(handles: FileHandles) -> Self {
+        FileLeaseRegistry { inner: Default::default(), handles }
+    }
+
+    /// Lease de leitura; falha se o arquivo (ou um diretório acima) está sendo removido.
+    pub fn read(&self, path: &Path) -> Result<ReadLease, Busy> {
+        self.read_as(path, None)
+    }
+
+    /// Como [`read`](Self::read), registrando o título assistido.
+    pub fn read_as(&self, path: &Path, title: Option<StreamTitle>) -> Result<ReadLease, Busy> {
+        let mut leases = self.inner.lock().unwrap();
+        if let Some(busy) = leases.exclusive.values().find(|p| path.starts_with(p)) {
+            return Err(Busy(busy.clone()));
+        }
+        leases.next_id += 1;
+        let id = leases.next_id;
+        let info = LeaseInfo {
+            id,
+            path: path.to_path_buf(),
+            started_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            title,
+        };
+        leases.readers.insert(id, info);
+        Ok(ReadLease {
+            registry: self.clone(),
+            id,
+        })
+    }
+
+    /// Lease exclusivo sobre `path` e tudo abaixo dele.
+    pub fn exclusive(&self, path: &Path) -> Result<ExclusiveLease, Busy> {
+        let mut leases = self.inner.lock().unwrap();
+        let conflict = leases
+            .readers
+            .values()
+            .map(|r| &r.path)
+            .chain(leases.exclusive.values())
+            .find(|p| p.starts_with(path) || path.starts_with(p));
+        if let Some(busy) = conflict {
+            return Err(Busy(busy.clone()));
+        }
+        leases.next_id += 1;
+        let id = leases.next_id;
+        leases.exclusive.insert(id, path.to_path_buf());
+        self.handles.invalidate(path);
+        Ok(ExclusiveLease {
+            registry: self.clone(),
+            id,
+        })
+    }
+
+    /// Leases de leitura ativos (streams em andamento).
+    pub fn active(&self) -> Vec<LeaseInfo> {
+        let mut active: Vec<_> = self.inner.lock().unwrap().readers.values().cloned().collect();
+        active.sort_by_key(|l| l.id);
+        active
+    }
+}
+
+/// Liberado no drop (fim ou abandono do corpo da resposta).
+pub struct ReadLease {
+    registry: FileLeaseRegistry,
+    id: u64,
+}
+
+impl Drop for ReadLease {
+    fn drop(&mut self) {
+        self.registry.inner.lock().unwrap().readers.remove(&self.id);
+    }
+}
+
+pub struct ExclusiveLease {
+    registry: FileLeaseRegistry,
+    id: u64,
+}
+
+impl Drop for ExclusiveLease {
+    fn drop(&mut self) {
+        let path = self.registry.inner.lock().unwrap().exclusive.remove(&self.id);
+        // o que foi aberto enquanto o caminho mudava também sai
+        if let Some(path) = path {
+            self.registry.handles.invalidate(&path);
+        }
+    }
+}
+
+/// `GET /admin/streams` — streams ativos (leases de leitura).
+pub async fn active_streams(State(state): State<AppState>) -> impl IntoResponse {
+    let base = &state.config().downloads_dir;
+    let streams: Vec<_> = state
+        .leases
+        .active()
+        .into_iter()
+        .map(|l| LeaseInfo {
+            path: l.path.strip_prefix(base).map(Path::to_path_buf).unwrap_or(l.path),
+            ..l
+        })
+        .collect();
+    Json(serde_json::json!({ "streams": streams }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+
+    const FILE_SIZE: usize = 256 * 1024;
+    /// Remoções que cada removedor precisa conseguir.
+    const DELETES: usize = 200;
+
+    fn contents() -> Vec<u8> {
+        (0..FILE_SIZE).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// Regrava o arquivo em pedaços, como uma remoção seguida de um novo
+    /// download: quem o lesse agora veria um arquivo faltando ou parcial.
+    fn replace(path: &Path, bytes: &[u8]) {
+        std::fs::remove_file(path).unwrap();
+        let mut file = std::fs::File::create(path).unwrap();
+        for chunk in bytes.chunks(16 * 1024) {
+            file.write_all(chunk).unwrap();
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn concurrent_streams_and_deletes() {
+        let dir = std::env::temp_dir().join(format!("rossoflix-leases-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("filme.mkv");
+        let bytes = contents();
+        std::fs::write(&file, &bytes).unwrap();
+
+        let registry = FileLeaseRegistry::default();
+        let (readers, deleters) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let (reads, busy_reads, busy_deletes) = (AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0));
+        let done = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    while !done.load(Ordering::SeqCst) {
+                        // streams começam a intervalos, não em fila
+                        thread::sleep(Duration::from_micros(300));
+                        let Ok(_lease) = registry.read(&file) else {
+                            busy_reads.fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        };
+                        readers.fetch_add(1, Ordering::SeqCst);
+                        assert_eq!(deleters.load(Ordering::SeqCst), 0, "leitura durante uma remoção");
+                        let mut read = Vec::new();
+                        let mut f = std::fs::File::open(&file).expect("arquivo sumiu durante o stream");
+                        let mut buf = [0u8; 16 * 1024];
+                        loop {
+                            let n = f.read(&mut buf).unwrap();
+                            if n == 0 {
+                                break;
+                            }
+                            read.extend_from_slice(&buf[..n]);
+                            thread::yield_now();
+                        }
+                        assert!(read == bytes, "leitura parcial: {} bytes", read.len());
+                        readers.fetch_sub(1, Ordering::SeqCst);
+                        reads.fetch_add(1, Ordering::Relaxed);
+                    }
+                });
+            }
+            // removedores num escopo próprio: os streams param quando eles terminam
+            thread::scope(|d| {
+                for target in [dir.as_path(), file.as_path()] {
+                    let (registry, readers, deleters, busy_deletes, bytes) =
+                        (&registry, &readers, &deleters, &busy_deletes, &bytes);
+                    d.spawn(move || {
+                        let mut deletes = 0;
+                        while deletes < DELETES {
+                            let Ok(lease) = registry.exclusive(target) else {
+                                busy_deletes.fetch_add(1, Ordering::Relaxed);
+                                thread::sleep(Duration::from_micros(100));
+                                continue;
+                            };
+                            assert_eq!(deleters.fetch_add(1, Ordering::SeqCst), 0, "dois leases exclusivos");
+                            assert_eq!(readers.load(Ordering::SeqCst), 0, "remoção durante um stream");
+                            replace(&file_in(target), bytes);
+                            deleters.fetch_sub(1, Ordering::SeqCst);
+                            deletes += 1;
+                            drop(lease);
+                            thread::sleep(Duration::from_micros(200));
+                        }
+                    });
+                }
+            });
+            done.store(true, Ordering::SeqCst);
+        });
+
+        assert!(registry.active().is_empty(), "lease de leitura vazado");
+        assert!(registry.exclusive(&dir).is_ok(), "lease exclusivo vazado");
+        let (reads, busy_reads, busy_deletes) = (reads.into_inner(), busy_reads.into_inner(), busy_deletes.into_inner());
+        assert!(reads > 0, "nenhum stream conseguiu o lease");
+        assert!(busy_reads > 0 && busy_deletes > 0, "nunca houve disputa");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// O arquivo do teste, para um lease sobre ele ou sobre o diretório.
+    fn file_in(target: &Path) -> PathBuf {
+        if target.is_dir() { target.join("filme.mkv") } else { target.to_path_buf() }
+    }
+
+    #[test]
+    fn exclusive_covers_paths_below() {
+        let registry = FileLeaseRegistry::default();
+        let dir = Path::new("/downloads/abc");
+        let reader = registry.read(&dir.join("filme.mkv")).unwrap();
+        assert!(registry.exclusive(dir).is_err(), "diretório com stream aberto");
+        assert!(registry.exclusive(&dir.join("outro.mkv")).is_ok());
+        assert!(registry.exclusive(Path::new("/downloads/abd")).is_ok());
+        drop(reader);
+
+        let lease = registry.exclusive(dir).unwrap();
+        assert!(registry.read(&dir.join("filme.mkv")).is_err(), "stream durante a remoção");
+        assert!(registry.exclusive(&dir.join("filme.mkv")).is_err());
+        drop(lease);
+        assert!(registry.read(&dir.join("filme.mkv")).is_ok());
+    }
+}
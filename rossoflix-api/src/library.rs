@@ -0,0 +1,226 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    response::IntoResponse,
+};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::{ApiError, AppState, cache::CacheMode, fetch_detail, upstream};
+
+/// Idade a partir da qual os metadados guardados contam como velhos.
+const STALE_AFTER_SECS: i64 = 30 * 24 * 3600;
+/// Intervalo entre as passadas de atualização.
+const REFRESH_EVERY: Duration = Duration::from_secs(24 * 3600);
+/// Espera antes da primeira passada, para não competir com a subida.
+const FIRST_REFRESH_AFTER: Duration = Duration::from_secs(15 * 60);
+/// Pausa entre duas consultas ao OMDb na atualização.
+const REFRESH_PACE: Duration = Duration::from_secs(2);
+/// A atualização para quando o uso do OMDb no dia passa desta fração da
+/// cota, deixando o resto para quem está usando o app.
+const REFRESH_QUOTA_SHARE: f64 = 0.5;
+/// Maior `limit` aceito em `/library/search`.
+const MAX_LIMIT: u32 = 100;
+
+#[derive(Debug, Serialize)]
+pub struct CatalogEntry {
+    imdb_id: String,
+    title: String,
+    year: Option<String>,
+    kind: Option<String>,
+    genres: Vec<String>,
+    plot: Option<String>,
+    poster: Option<String>,
+    imdb_rating: Option<f64>,
+    /// `Ratings` do OMDb (IMDb, Rotten Tomatoes, Metacritic).
+    ratings: Value,
+    /// Unix timestamp (s) da última resolução no OMDb.
+    updated_at: i64,
+    /// Mais de 30 dias sem atualização.
+    stale: bool,
+}
+
+/// Guarda no catálogo local os metadados de um título recém-resolvido no
+/// OMDb, sem esperar o banco.
+pub fn remember(state: &AppState, detail: &Value) {
+    let text = |field: &str| {
+        detail
+            .get(field)
+            .and_then(|v| v.as_str())
+            .filter(|v| !v.is_empty() && *v != "N/A")
+            .map(str::to_string)
+    };
+    let (Some(imdb_id), Some(title)) = (text("imdbID"), text("Title")) else {
+        return;
+    };
+    let year = text("Year");
+    let kind = text("Type");
+    let genres = text("Genre").unwrap_or_default();
+    let plot = text("Plot");
+    let poster = text("Poster");
+    let rating = text("imdbRating").and_then(|r| r.parse::<f64>().ok());
+    let ratings = detail.get("Ratings").cloned().unwrap_or(Value::Array(Vec::new())).to_string();
+    let now = unix_now();
+
+    let db = state.db.clone();
+    tokio::spawn(async move {
+        let saved = db
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                tx.execute(
+                    "INSERT INTO catalog (imdb_id, title, year, kind, genres, plot, poster, imdb_rating, ratings, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                     ON CONFLICT (imdb_id) DO UPDATE SET
+                        title = excluded.title, year = excluded.year, kind = excluded.kind,
+                        genres = excluded.genres, plot = excluded.plot, poster = excluded.poster,
+                        imdb_rating = excluded.imdb_rating, ratings = excluded.ratings,
+                        updated_at = excluded.updated_at",
+                    params![imdb_id, title, year, kind, genres, plot, poster, rating, ratings, now],
+                )?;
+                tx.execute("DELETE FROM catalog_fts WHERE imdb_id = ?1", params![imdb_id])?;
+                tx.execute(
+                    "INSERT INTO catalog_fts (imdb_id, title, genres, plot) VALUES (?1, ?2, ?3, ?4)",
+                    params![imdb_id, title, genres, plot.unwrap_or_default()],
+                )?;
+                tx.commit()
+            })
+            .await;
+        if let Err(e) = saved {
+            warn!("catálogo: falha ao guardar título: {e}");
+        }
+    });
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LibrarySearchParams {
+    #[serde(default)]
+    q: String,
+    #[serde(default = "default_limit")]
+    limit: u32,
+}
+
+fn default_limit() -> u32 {
+    20
+}
+
+/// Consulta FTS5 a partir do texto livre: cada palavra vira um prefixo
+/// entre aspas (`"matr"*`), então pontuação e operadores não quebram a busca.
+fn fts_query(q: &str) -> Option<String> {
+    let terms: Vec<String> = q
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| format!("\"{w}\"*"))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// `GET /library/search?q=&limit=` — busca no catálogo local (título,
+/// gêneros e sinopse) sem falar com o OMDb. O catálogo tem todo título já
+/// resolvido pelo servidor (detalhes abertos, lista, aquecimento, links).
+pub async fn search_library(
+    State(state): State<AppState>,
+    Query(params): Query<LibrarySearchParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Some(query) = fts_query(&params.q) else {
+        return Err(ApiError::BadRequest("q vazio".into()));
+    };
+    if !(1..=MAX_LIMIT).contains(&params.limit) {
+        return Err(ApiError::BadRequest(format!("limit deve estar entre 1 e {MAX_LIMIT}")));
+    }
+    let limit = params.limit;
+    let stale_before = unix_now() - STALE_AFTER_SECS;
+    let results = state
+        .db
+        .call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT c.imdb_id, c.title, c.year, c.kind, c.genres, c.plot, c.poster, c.imdb_rating,
+                        c.ratings, c.updated_at
+                 FROM catalog_fts f JOIN catalog c ON c.imdb_id = f.imdb_id
+                 WHERE catalog_fts MATCH ?1
+                 ORDER BY bm25(catalog_fts, 0.0, 10.0, 2.0, 1.0)
+                 LIMIT ?2",
+            )?;
+            stmt.query_map(params![query, limit], |row| {
+                let genres: String = row.get(4)?;
+                let ratings: String = row.get(8)?;
+                let updated_at: i64 = row.get(9)?;
+                Ok(CatalogEntry {
+                    imdb_id: row.get(0)?,
+                    title: row.get(1)?,
+                    year: row.get(2)?,
+                    kind: row.get(3)?,
+                    genres: genres
+                        .split(',')
+                        .map(|g| g.trim().to_string())
+                        .filter(|g| !g.is_empty())
+                        .collect(),
+                    plot: row.get(5)?,
+                    poster: row.get(6)?,
+                    imdb_rating: row.get(7)?,
+                    ratings: serde_json::from_str(&ratings).unwrap_or(Value::Array(Vec::new())),
+                    updated_at,
+                    stale: updated_at < stale_before,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await?;
+    Ok(Json(serde_json::json!({ "query": params.q, "results": results })))
+}
+
+/// Atualização diária: relê no OMDb os títulos com metadados velhos, do
+/// mais antigo ao mais novo, até `LIBRARY_REFRESH_MAX` por passada ou até
+/// gastar metade da cota do dia (`OMDB_DAILY_LIMIT`).
+pub fn spawn_refresher(state: AppState) {
+    tokio::spawn(async move {
+        tokio::time::sleep(FIRST_REFRESH_AFTER).await;
+        loop {
+            match refresh_stale(&state).await {
+                Ok(0) => {}
+                Ok(refreshed) => info!(refreshed, "catálogo: títulos atualizados"),
+                Err(e) => warn!("catálogo: atualização falhou: {e}"),
+            }
+            tokio::time::sleep(REFRESH_EVERY).await;
+        }
+    });
+}
+
+async fn refresh_stale(state: &AppState) -> Result<usize, ApiError> {
+    let stale_before = unix_now() - STALE_AFTER_SECS;
+    let max = state.config().library_refresh_max;
+    let ids: Vec<String> = state
+        .db
+        .call(move |conn| {
+            let mut stmt =
+                conn.prepare("SELECT imdb_id FROM catalog WHERE updated_at < ?1 ORDER BY updated_at LIMIT ?2")?;
+            stmt.query_map(params![stale_before, max], |row| row.get(0))?.collect()
+        })
+        .await?;
+
+    let limit = state.config().omdb_daily_limit;
+    let mut refreshed = 0;
+    for imdb_id in ids {
+        if limit > 0 && state.usage.today(upstream::Service::Omdb) as f64 >= limit as f64 * REFRESH_QUOTA_SHARE {
+            info!("catálogo: atualização pausada para poupar a cota do OMDb");
+            break;
+        }
+        // `fetch_detail` guarda o resultado de volta no catálogo
+        match fetch_detail(state, &imdb_id, CacheMode::Refresh).await {
+            Ok(_) => refreshed += 1,
+            Err(e) => warn!(imdb_id, "catálogo: falha ao atualizar: {e}"),
+        }
+        tokio::time::sleep(REFRESH_PACE).await;
+    }
+    Ok(refreshed)
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,75 @@
+/// Campos de um link magnet que nos interessam.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Magnet {
+    /// Infohash normalizado em minúsculas (hex de 40 ou base32 de 32 caracteres).
+    pub info_hash: String,
+    /// Parâmetro `dn` (nome sugerido), quando presente.
+    pub display_name: Option<String>,
+    /// Trackers embutidos (`tr`).
+    pub trackers: Vec<String>,
+}
+
+impl Magnet {
+    /// Aceita um link `magnet:?` completo ou apenas o infohash.
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        let Some(query) = input.strip_prefix("magnet:?") else {
+            return is_info_hash(input).then(|| Magnet {
+                info_hash: input.to_ascii_lowercase(),
+                display_name: None,
+                trackers: Vec::new(),
+            });
+        };
+
+        let mut info_hash = None;
+        let mut display_name = None;
+        let mut trackers = Vec::new();
+        for pair in query.split('&') {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = urlencoding::decode(&value.replace('+', " "))
+                .map(|v| v.into_owned())
+                .unwrap_or_else(|_| value.to_string());
+            match key {
+                "xt" => {
+                    if let Some(hash) = value.strip_prefix("urn:btih:")
+                        && is_info_hash(hash)
+                    {
+                        info_hash = Some(hash.to_ascii_lowercase());
+                    }
+                }
+                "dn" if !value.is_empty() => display_name = Some(value),
+                "tr" if !value.is_empty() => trackers.push(value),
+                _ => {}
+            }
+        }
+
+        Some(Magnet {
+            info_hash: info_hash?,
+            display_name,
+            trackers,
+        })
+    }
+
+    /// Link magnet completo para repassar ao aria2c.
+    pub fn to_uri(&self) -> String {
+        let mut uri = format!("magnet:?xt=urn:btih:{}", self.info_hash);
+        if let Some(dn) = &self.display_name {
+            uri.push_str("&dn=");
+            uri.push_str(&urlencoding::encode(dn));
+        }
+        for tr in &self.trackers {
+            uri.push_str("&tr=");
+            uri.push_str(&urlencoding::encode(tr));
+        }
+        uri
+    }
+}
+
+/// Infohash v1 em hex (40) ou base32 (32).
+pub fn is_info_hash(s: &str) -> bool {
+    match s.len() {
+        40 => s.bytes().all(|b| b.is_ascii_hexdigit()),
+        32 => s.bytes().all(|b| matches!(b.to_ascii_uppercase(), b'A'..=b'Z' | b'2'..=b'7')),
+        _ => false,
+    }
+}
@@ -1,16 +1,96 @@
-use std::{io, net::SocketAddr, path::{Path as StdPath, PathBuf}, time::Duration};
-use std::collections::HashSet;
+// o `json!` de `GET /admin/config` passa do limite padrão de 128
+#![recursion_limit = "256"]
+
+mod aria2;
+mod attribution;
+mod audio;
+mod audit;
+mod auth;
+mod availability;
+mod cache;
+mod calendar;
+mod catalog;
+mod completion;
+mod config;
+mod content_type;
+mod dates;
+mod db;
+mod doctor;
+mod download_index;
+mod downloads;
+mod engine;
+mod episode;
+mod export;
+mod file_handles;
+mod filter;
+mod hls;
+mod humanize;
+mod language;
+mod leases;
+mod library;
+mod magnet;
+mod markers;
+mod media;
+mod media_queue;
+mod metadata;
+mod middleware;
+mod notices;
+mod omdb;
+mod outbound;
+mod outbox;
+mod party;
+mod peer;
+mod playback;
+mod posters;
+mod prefetch;
+mod profiles;
+mod readahead;
+mod progress;
+mod proxy;
+mod rate_limit;
+mod recovery;
+mod redis;
+mod release_name;
+mod reload;
+mod scratch;
+mod shape;
+mod share;
+mod shed;
+mod signing;
+mod slug;
+mod speedtest;
+mod stats;
+mod storage;
+mod stream_title;
+mod subtitles;
+mod telegram;
+mod torrent;
+mod torrentio;
+mod tracker;
+mod transcode;
+mod trash;
+mod trending;
+mod upstream;
+mod usage;
+mod warm;
+mod watch;
+mod watchlist;
+
+use std::{io, net::{IpAddr, SocketAddr}, path::{Path as StdPath, PathBuf}, sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
 
 use axum::{
     Json, Router,
     body::Body,
-    extract::{Path, Query, State},
-    http::{StatusCode, header, HeaderMap},
-    response::{IntoResponse, Response},
-    routing::get,
+    extract::{ConnectInfo, Path, Query, State},
+    http::{HeaderName, StatusCode, Uri, header, HeaderMap},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post, put},
 };
+use config::Config;
+use episode::{EpisodeHint, Selection};
+use magnet::Magnet;
+use torrent::TorrentFile;
 use dotenvy::dotenv;
-use moka::future::Cache;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -18,63 +98,291 @@ use tokio::process::Command;
 use tokio::fs;
 use tokio::fs::File;
 use tokio::net::TcpListener;
-use tokio_util::io::ReaderStream;
-use tower_http::{compression::CompressionLayer, cors::CorsLayer, trace::TraceLayer};
-use tracing::{error, info};
-use tracing_subscriber::{EnvFilter, fmt};
+use tower_http::{
+    compression::{
+        CompressionLayer,
+        predicate::{DefaultPredicate, NotForContentType, Predicate},
+    },
+    cors::CorsLayer,
+    request_id::{PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::TraceLayer,
+};
+use tracing::{info, warn};
+use tracing_subscriber::{EnvFilter, fmt, layer::SubscriberExt, util::SubscriberInitExt};
 use futures_util::StreamExt; // <-- Adicione esta linha!
-// Linha opcional, mas recomendada para a versão melhorada:
-use tokio::io::{AsyncSeekExt, SeekFrom};
 
 
 
 #[derive(Clone)]
 struct AppState {
     http: Client,
+    /// Cliente do `/stream?url=`: sem timeout total e só seguindo
+    /// redirecionamentos para hosts de `STREAM_PROXY_HOSTS`.
+    proxy_http: Client,
     api_key: String,      // OMDb API key
-    cache: Cache<String, serde_json::Value>,
+    cache: cache::ResponseCache,
     tmdb_key: String,     // <-- add TMDB key
+    config: reload::LiveConfig,
+    progress: progress::ProgressRegistry,
+    download_jobs: downloads::Jobs,
+    prefetch: prefetch::Prefetcher,
+    readahead: readahead::ReadAhead,
+    recovery: recovery::SharedReport,
+    /// Resultados de scrape por infohash, por alguns minutos.
+    health: cache::ResponseCache,
+    /// Calendário e dados de temporadas do TMDB, por algumas horas.
+    calendar: cache::ResponseCache,
+    /// Arquivos canônicos (deduplicação) e título de cada download.
+    downloads: download_index::DownloadIndex,
+    leases: leases::FileLeaseRegistry,
+    /// Arquivos abertos pelo `/stream` (`FILE_HANDLE_CACHE_SIZE`).
+    file_handles: file_handles::FileHandles,
+    parties: party::PartyRegistry,
+    db: db::Db,
+    /// Avisos (Telegram) a entregar, com novas tentativas.
+    outbox: outbox::Outbox,
+    posters: posters::PosterCache,
+    /// Verificação dos pôsteres do OMDb em segundo plano.
+    poster_check: posters::PosterValidator,
+    scratch: scratch::ScratchSpace,
+    /// Teto e limpeza do diretório de downloads.
+    storage: storage::Storage,
+    /// Vagas para extrações de áudio simultâneas.
+    audio_extractions: Arc<tokio::sync::Semaphore>,
+    /// Jobs curtos de ffmpeg/ffprobe, com workers limitados.
+    media_jobs: media_queue::MediaQueue,
+    /// ffmpegs do HLS transcodificado (`HLS_MAX_TRANSCODES`).
+    transcodes: transcode::Transcodes,
+    /// Sinais de carga e recusa dos pedidos de baixa prioridade.
+    shedder: shed::LoadShedder,
+    /// Pedidos e downloads novos por cliente (`RATE_LIMIT_*`).
+    rate_limits: rate_limit::RateLimiter,
+    /// Usuários do proxy com perfil já gravado nesta execução.
+    known_profiles: profiles::KnownProfiles,
+    /// Testes de velocidade em andamento e recentes, por cliente.
+    speedtests: speedtest::Speedtests,
+    /// Bytes servidos por espectador, para o `playback.completed`.
+    completions: completion::Sessions,
+    /// Eventos das estatísticas locais (`STATS_ENABLED`) a gravar.
+    stats: stats::Stats,
+    torrentio_mirrors: torrentio::Mirrors,
+    torrentio_views: torrentio::Views,
+    warm: warm::WarmTasks,
+    /// Provedores do detalhe combinado, em ordem de preferência.
+    metadata: metadata::Pipeline,
+    /// Bot do Telegram, quando configurado.
+    telegram: Option<telegram::Telegram>,
+    /// Chamadas ao upstream por dia e endpoint.
+    usage: usage::UsageCounters,
 }
 
-#[derive(Debug, Deserialize)]
-struct TmdbList {
-    results: Vec<TmdbMovie>,
-}
-
-#[derive(Debug, Deserialize)]
-struct TmdbMovie {
-    id: u64,
-    title: Option<String>,
-    name: Option<String>, // fallback for TV shows
-}
-
-#[derive(Debug, Serialize)]
-struct CombinedMovie {
-    tmdb_title: String,
-    omdb: serde_json::Value,
+impl AppState {
+    /// Configuração em uso neste momento (troca na recarga).
+    fn config(&self) -> Arc<Config> {
+        self.config.load()
+    }
 }
 
-
-
-
 #[derive(Debug, Error)]
 enum ApiError {
     #[error("Upstream error: {0}")]
     Upstream(String),
+    /// O upstream não respondeu dentro do timeout dele.
+    #[error("Upstream timeout: {0}")]
+    UpstreamTimeout(String),
+    /// O upstream recusou por excesso de pedidos (`429`) ou a cota acabou.
+    #[error("Rate limited: {0}")]
+    RateLimited(String),
+    /// O upstream respondeu, mas o corpo não é o JSON esperado.
+    #[error("Upstream format error ({upstream}: {kind:?})")]
+    UpstreamFormat {
+        upstream: String,
+        kind: upstream::FormatError,
+    },
     #[error("Bad request: {0}")]
     BadRequest(String),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    /// Nenhum arquivo do torrent corresponde ao episódio pedido.
+    #[error("No matching file: {message}")]
+    NoMatchingFile {
+        message: String,
+        available_files: Vec<serde_json::Value>,
+    },
+    /// Faltou a chave de API, ou ela não vale (`API_KEYS`).
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    #[error("Gone: {0}")]
+    Gone(String),
+    #[error("Not acceptable: {0}")]
+    NotAcceptable(String),
+    #[error("Unavailable: {0}")]
+    Unavailable(String),
+    #[error("Storage error: {0}")]
+    Storage(String),
+    #[error("Storage full: {0}")]
+    StorageFull(String),
+    #[error("Download failed (exit code {exit_code:?})")]
+    DownloadFailed {
+        exit_code: Option<i32>,
+        reason: aria2::Reason,
+        stderr_excerpt: String,
+    },
+    #[error("Deadline exceeded")]
+    DeadlineExceeded,
+    /// O arquivo existe, mas o aria2c ainda está gravando (`.aria2` ao lado).
+    #[error("Download in progress")]
+    DownloadInProgress {
+        bytes_done: Option<u64>,
+        total_bytes: Option<u64>,
+    },
+    /// Job de mídia na fila: o cliente tenta de novo depois do `Retry-After`.
+    #[error("Queued")]
+    Queued { retry_after_secs: u64 },
+    /// Servidor sobrecarregado: o pedido foi recusado pela prioridade da rota.
+    #[error("Overloaded")]
+    Overloaded { retry_after_secs: u64 },
+    /// O cliente passou do limite dele (`RATE_LIMIT_*`).
+    #[error("Too many requests: {message}")]
+    TooManyRequests { message: String, retry_after_secs: u64 },
     #[error("Internal error")]
     Internal,
 }
 
+impl ApiError {
+    /// Status HTTP, código estável para o cliente decidir o que mostrar (em
+    /// vez de comparar mensagens) e se vale tentar de novo. Códigos novos
+    /// podem surgir; os existentes não mudam de significado.
+    fn classify(&self) -> (StatusCode, &'static str, bool) {
+        match self {
+            ApiError::Upstream(_) | ApiError::UpstreamFormat { .. } => (StatusCode::BAD_GATEWAY, "upstream_error", true),
+            ApiError::UpstreamTimeout(_) | ApiError::DeadlineExceeded => {
+                (StatusCode::GATEWAY_TIMEOUT, "upstream_timeout", true)
+            }
+            ApiError::RateLimited(_) | ApiError::TooManyRequests { .. } => {
+                (StatusCode::TOO_MANY_REQUESTS, "rate_limited", true)
+            }
+            ApiError::BadRequest(_) => (StatusCode::BAD_REQUEST, "bad_request", false),
+            ApiError::NotFound(_) | ApiError::NoMatchingFile { .. } => (StatusCode::NOT_FOUND, "not_found", false),
+            ApiError::Unauthorized(_) => (StatusCode::UNAUTHORIZED, "unauthorized", false),
+            ApiError::Forbidden(_) => (StatusCode::FORBIDDEN, "forbidden", false),
+            ApiError::Conflict(_) => (StatusCode::CONFLICT, "conflict", false),
+            ApiError::Gone(_) => (StatusCode::GONE, "gone", false),
+            ApiError::NotAcceptable(_) => (StatusCode::NOT_ACCEPTABLE, "not_acceptable", false),
+            ApiError::Unavailable(_) => (StatusCode::SERVICE_UNAVAILABLE, "unavailable", true),
+            ApiError::Storage(_) | ApiError::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "internal", false),
+            ApiError::StorageFull(_) => (StatusCode::INSUFFICIENT_STORAGE, "storage_full", false),
+            ApiError::DownloadFailed { .. } => (StatusCode::BAD_GATEWAY, "download_failed", true),
+            ApiError::DownloadInProgress { .. } => (StatusCode::CONFLICT, "download_in_progress", true),
+            ApiError::Queued { .. } => (StatusCode::ACCEPTED, "queued", true),
+            ApiError::Overloaded { .. } => (StatusCode::SERVICE_UNAVAILABLE, "overloaded", true),
+        }
+    }
+
+    /// Código estável do erro (o `error.code` da resposta).
+    fn code(&self) -> &'static str {
+        self.classify().1
+    }
+
+    /// Erro equivalente a uma resposta de erro montada fora dos handlers
+    /// (rejeições dos extratores do axum, rota inexistente).
+    fn from_status(status: StatusCode, message: String) -> Self {
+        match status {
+            StatusCode::NOT_FOUND => ApiError::NotFound(message),
+            StatusCode::NOT_ACCEPTABLE => ApiError::NotAcceptable(message),
+            s if s.is_client_error() => ApiError::BadRequest(message),
+            _ => ApiError::Internal,
+        }
+    }
+}
+
 impl IntoResponse for ApiError {
     fn into_response(self) -> axum::response::Response {
-        let (code, msg) = match self {
-            ApiError::Upstream(m) => (StatusCode::BAD_GATEWAY, m),
-            ApiError::BadRequest(m) => (StatusCode::BAD_REQUEST, m),
-            ApiError::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "internal error".into()),
+        let (status, code, retryable) = self.classify();
+        let mut extra = serde_json::Map::new();
+        let mut retry_after = None;
+        let message = match self {
+            ApiError::Upstream(m)
+            | ApiError::UpstreamTimeout(m)
+            | ApiError::RateLimited(m)
+            | ApiError::BadRequest(m)
+            | ApiError::NotFound(m)
+            | ApiError::Unauthorized(m)
+            | ApiError::Forbidden(m)
+            | ApiError::Conflict(m)
+            | ApiError::Gone(m)
+            | ApiError::NotAcceptable(m)
+            | ApiError::Unavailable(m)
+            | ApiError::Storage(m)
+            | ApiError::StorageFull(m) => m,
+            ApiError::DeadlineExceeded => "prazo do pedido esgotado".into(),
+            ApiError::NoMatchingFile { message, available_files } => {
+                extra.insert("available_files".into(), available_files.into());
+                message
+            }
+            ApiError::DownloadFailed {
+                exit_code,
+                reason,
+                stderr_excerpt,
+            } => {
+                extra.insert("exit_code".into(), exit_code.into());
+                extra.insert("reason".into(), serde_json::to_value(reason).unwrap_or_default());
+                extra.insert("stderr_excerpt".into(), stderr_excerpt.into());
+                "download falhou".into()
+            }
+            ApiError::DownloadInProgress { bytes_done, total_bytes } => {
+                let percent = bytes_done
+                    .zip(total_bytes.filter(|t| *t > 0))
+                    .map(|(done, total)| (done as f64 / total as f64 * 100.0).min(100.0));
+                extra.insert("bytes_done".into(), bytes_done.into());
+                extra.insert("total_bytes".into(), total_bytes.into());
+                extra.insert("percent".into(), percent.into());
+                retry_after = Some("5".to_string());
+                "download em andamento".into()
+            }
+            ApiError::UpstreamFormat { upstream, kind } => {
+                extra.insert("upstream".into(), upstream.into());
+                extra.insert("kind".into(), serde_json::to_value(kind).unwrap_or_default());
+                "resposta do upstream em formato inesperado".into()
+            }
+            ApiError::Queued { retry_after_secs } => {
+                // não é falha: o corpo é o do andamento, sem `error`
+                let body = serde_json::json!({ "status": "queued", "retry_after_secs": retry_after_secs });
+                return (status, [(header::RETRY_AFTER, retry_after_secs.to_string())], Json(body)).into_response();
+            }
+            ApiError::Overloaded { retry_after_secs } => {
+                retry_after = Some(retry_after_secs.to_string());
+                "servidor sobrecarregado, tente de novo".into()
+            }
+            ApiError::TooManyRequests { message, retry_after_secs } => {
+                retry_after = Some(retry_after_secs.to_string());
+                message
+            }
+            ApiError::Internal => "internal error".into(),
         };
-        (code, Json(serde_json::json!({"error": msg}))).into_response()
+
+        let mut legacy = extra.clone();
+        legacy.insert("error".into(), message.clone().into());
+        let mut error = serde_json::Map::new();
+        error.insert("code".into(), code.into());
+        error.insert("message".into(), message.into());
+        error.insert("request_id".into(), middleware::current_request_id().into());
+        error.insert("retryable".into(), retryable.into());
+        error.extend(extra);
+
+        let mut resp = (status, Json(serde_json::json!({ "error": error }))).into_response();
+        if let Some(secs) = retry_after
+            && let Ok(value) = header::HeaderValue::from_str(&secs)
+        {
+            resp.headers_mut().insert(header::RETRY_AFTER, value);
+        }
+        resp.extensions_mut().insert(middleware::LegacyErrorBody(legacy.into()));
+        resp.extensions_mut().insert(middleware::ErrorCode(code));
+        resp
     }
 }
 
@@ -85,7 +393,21 @@ struct SearchParams {
     page: u32,
     #[serde(default = "default_type")]
     r#type: String,
+    /// Embute os detalhes completos dos N primeiros resultados (até 10).
+    #[serde(default)]
+    include_details: usize,
+    /// `ratings`: põe `imdbRating` e `Runtime` em cada resultado.
+    enrich: Option<String>,
 }
+
+/// Limite de `include_details` em `/search`.
+const MAX_INCLUDE_DETAILS: usize = 10;
+/// Resultados de `/search?enrich=ratings` que recebem nota e duração (uma
+/// página do OMDb); os demais saem com `enriched: false`.
+const MAX_ENRICH: usize = 10;
+/// Detalhes buscados ao mesmo tempo no enriquecimento.
+const ENRICH_CONCURRENCY: usize = 4;
+
 fn default_page() -> u32 {
     1
 }
@@ -93,24 +415,11 @@ fn default_type() -> String {
     "movie".to_string()
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct OmdbSearchItem {
-    #[serde(rename = "Title")]
-    title: String,
-    #[serde(rename = "Year")]
-    year: String,
-    #[serde(rename = "imdbID")]
-    imdb_id: String,
-    #[serde(rename = "Type")]
-    kind: String,
-    #[serde(rename = "Poster")]
-    poster: String,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 struct OmdbSearchResp {
+    /// Itens crus, lidos um a um por `omdb::search_items`.
     #[serde(rename = "Search")]
-    search: Option<Vec<OmdbSearchItem>>,
+    search: Option<Vec<serde_json::Value>>,
     #[serde(rename = "totalResults")]
     total: Option<String>,
     #[serde(rename = "Response")]
@@ -119,197 +428,622 @@ struct OmdbSearchResp {
     error: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct OmdbMovieDetail {
-    #[serde(rename = "Title")]
-    title: String,
-    #[serde(rename = "Year")]
-    year: String,
-    #[serde(rename = "imdbID")]
-    imdb_id: String,
-    #[serde(rename = "Type")]
-    kind: String,
-    #[serde(rename = "Genre")]
-    genre: String,
-    #[serde(rename = "Director")]
-    director: String,
-    #[serde(rename = "Actors")]
-    actors: String,
-    #[serde(rename = "Plot")]
-    plot: String,
-    #[serde(rename = "Poster")]
-    poster: String,
-    #[serde(rename = "imdbRating")]
-    imdb_rating: String,
-}
-
 #[tokio::main]
 async fn main() -> io::Result<()> {
     dotenv().ok();
 
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-    fmt().with_env_filter(filter).init();
-
-    let api_key = std::env::var("OMDB_API_KEY").expect("Defina OMDB_API_KEY no ambiente (.env)");
-    let tmdb_key = std::env::var("TMDB_API_KEY").expect("Defina TMDB_API_KEY no ambiente (.env)");
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(reload::DEFAULT_LOG_FILTER));
+    // filtro trocável: `RUST_LOG` muda na recarga da configuração
+    let (filter, log_handle) = tracing_subscriber::reload::Layer::new(filter);
+    tracing_subscriber::registry().with(filter).with(fmt::layer()).init();
 
-    let port: u16 = std::env::var("PORT")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(8080);
-
-    // Cliente HTTP com pooling, gzip/brotli, timeout e retry simples (manual ao chamar)
+    let config = Config::from_env()?;
+    // Cliente HTTP com pooling, timeout e retry simples (manual ao chamar)
     let http = Client::builder()
+        .user_agent(upstream::USER_AGENT)
         .connect_timeout(Duration::from_secs(3))
         .timeout(Duration::from_secs(8))
-        .pool_max_idle_per_host(8)
+        .pool_max_idle_per_host(8);
+    let http = outbound::apply(http, &config)?
         .build()
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        .map_err(io::Error::other)?;
+
+    // `rossoflix-api doctor [--json]`: diagnóstico das integrações, sem subir o servidor
+    let mut args = std::env::args().skip(1);
+    let command = args.next();
+    if command.as_deref() == Some("doctor") {
+        let report = doctor::run(&http, &config).await;
+        if args.any(|a| a == "--json") {
+            println!("{}", serde_json::to_string_pretty(&report).map_err(io::Error::other)?);
+        } else {
+            print!("{}", doctor::render(&report));
+        }
+        std::process::exit(if report.status == doctor::Status::Fail { 1 } else { 0 });
+    }
+    // `rossoflix-api db migrate [--dry-run]`: versão do banco e migrações pendentes
+    if command.as_deref() == Some("db") {
+        let rest: Vec<String> = args.collect();
+        if rest.first().map(String::as_str) != Some("migrate") {
+            eprintln!("uso: rossoflix-api db migrate [--dry-run]");
+            std::process::exit(2);
+        }
+        match db::migrate_command(&config.database_path, rest.iter().any(|a| a == "--dry-run")) {
+            Ok(text) => print!("{text}"),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
 
-    // Cache TTL curto para reduzir latência e chamadas externas
-    let cache: Cache<String, serde_json::Value> = Cache::builder()
-        .time_to_live(Duration::from_secs(60))
-        .max_capacity(10_000)
-        .build();
+    // Na memória ou no Redis (`CACHE_BACKEND`); cada cache com o seu prefixo
+    let redis = match (config.cache_backend, &config.redis_url) {
+        (cache::CacheBackend::Redis, Some(url)) => Some(Arc::new(
+            redis::Client::from_url(url).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        )),
+        _ => None,
+    };
+    let response_cache = |name: &str, ttl: Duration, capacity: u64| {
+        let store: Arc<dyn cache::CacheStore> = match &redis {
+            Some(client) => Arc::new(cache::RedisStore::new(
+                client.clone(),
+                format!("{}{name}:", config.redis_key_prefix),
+            )),
+            None => Arc::new(cache::MemoryStore::new(capacity)),
+        };
+        cache::ResponseCache::new(ttl, store)
+    };
+    // Reduz latência e chamadas externas; o TTL de cada entrada vem de `CACHE_TTL_*`
+    let cache = response_cache("responses", Duration::from_secs(60), 10_000);
         
+    let telegram = telegram::Telegram::from_config(&http, &config);
+    let poster_check = posters::PosterValidator::spawn(http.clone());
+    let db = db::Db::open(&config.database_path)?;
+    let file_handles = file_handles::FileHandles::default();
+    let live_config = reload::LiveConfig::new(config.clone(), Some(log_handle));
     let state = AppState {
         http,
-        api_key,
+        proxy_http: proxy::client(&live_config)?,
+        api_key: config.omdb_api_key.clone(),
         cache,
-        tmdb_key,
+        tmdb_key: config.tmdb_api_key.clone(),
+        progress: progress::ProgressRegistry::default(),
+        download_jobs: downloads::Jobs::new(config.max_concurrent_downloads),
+        prefetch: prefetch::Prefetcher::new(&config),
+        readahead: readahead::ReadAhead::new(),
+        recovery: Default::default(),
+        health: response_cache("health", Duration::from_secs(300), 5_000),
+        calendar: response_cache("calendar", Duration::from_secs(3 * 3600), 5_000),
+        downloads: download_index::DownloadIndex::open(db.clone(), &config.downloads_dir).await,
+        usage: usage::UsageCounters::load(&config.downloads_dir).await,
+        leases: leases::FileLeaseRegistry::new(file_handles.clone()),
+        file_handles,
+        parties: Default::default(),
+        outbox: outbox::Outbox::new(db.clone()),
+        db,
+        posters: Default::default(),
+        poster_check,
+        scratch: scratch::ScratchSpace::new(
+            config.scratch_dir.clone(),
+            Duration::from_secs(config.scratch_idle_ttl_minutes * 60),
+            config.scratch_budget_bytes,
+        ),
+        storage: storage::Storage::default(),
+        audio_extractions: Arc::new(tokio::sync::Semaphore::new(config.audio_max_extractions)),
+        media_jobs: media_queue::MediaQueue::new(config.media_workers),
+        transcodes: transcode::Transcodes::new(config.hls_max_transcodes),
+        shedder: shed::LoadShedder::default(),
+        rate_limits: rate_limit::RateLimiter::default(),
+        known_profiles: profiles::KnownProfiles::default(),
+        speedtests: speedtest::Speedtests::default(),
+        completions: completion::Sessions::default(),
+        stats: stats::Stats::default(),
+        torrentio_mirrors: torrentio::Mirrors::new(&config.torrentio_base_urls),
+        torrentio_views: torrentio::Views::new(config.torrentio_view_cache_secs),
+        warm: warm::WarmTasks::new(config.warm_omdb_per_min),
+        metadata: metadata::Pipeline::new(&config.metadata_priority),
+        telegram,
+        config: live_config,
     };
 
-    // let app = Router::new()
-    //     .route("/health", get(health))
-    //     .route("/search", get(search_movies))
-    //     .route("/movie/:imdb_id", get(movie_detail))
-    //     .with_state(state)
-    //     // Apply each layer individually
-    //     .layer(CompressionLayer::new())
-    //     //.layer(TimeoutLayer::new(Duration::from_secs(10)))
-    //     .layer(TraceLayer::new_for_http())
-    //     .layer(CorsLayer::permissive());
-    let app = Router::new()
+    recovery::run(&state).await;
+    trash::spawn_purger(
+        state.config().downloads_dir.clone(),
+        Duration::from_secs(state.config().trash_retention_hours * 3600),
+    );
+    scratch::spawn_sweeper(state.scratch.clone());
+    storage::spawn_cleaner(state.clone());
+    party::spawn_sweeper(
+        state.parties.clone(),
+        Duration::from_secs(state.config().party_idle_minutes * 60),
+    );
+    telegram::spawn_poller(state.clone());
+    outbox::spawn_dispatcher(state.clone());
+    stats::spawn_recorder(state.clone());
+    library::spawn_refresher(state.clone());
+    watch::spawn_watcher(state.clone());
+    usage::spawn_flusher(state.usage.clone());
+    shed::spawn_sampler(state.clone());
+    reload::spawn_sighup_listener(state.config.clone());
+
+    let public = public_router(&state);
+    let admin = admin_router(&state);
+
+    let addr = SocketAddr::new(state.config().bind_ip, state.config().port);
+    match state.config().admin_addr {
+        Some(admin_addr) => {
+            let listener = bind(addr).await?;
+            let admin_listener = bind(admin_addr).await?;
+            info!("listening on {} (admin on {})", listener.local_addr()?, admin_listener.local_addr()?);
+
+            let public = with_layers(public.with_state(state.clone()), &state);
+            let admin = with_layers(admin.with_state(state.clone()), &state);
+            let (public_res, admin_res) = tokio::join!(
+                axum::serve(listener, public.into_make_service_with_connect_info::<SocketAddr>()),
+                axum::serve(admin_listener, admin.into_make_service_with_connect_info::<SocketAddr>()),
+            );
+            public_res?;
+            admin_res?;
+        }
+        None => {
+            let listener = bind(addr).await?;
+            info!("listening on {}", listener.local_addr()?);
+            if state.config().auth_mode == auth::AuthMode::Token && state.config().admin_token.is_none() {
+                warn!("sem ADMIN_TOKEN nem ADMIN_BIND_ADDR: as rotas de admin ficam fechadas");
+            }
+            let app = with_layers(public.merge(admin).with_state(state.clone()), &state);
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Rotas da API pública (busca, detalhes, torrentio, streaming).
+fn public_router(state: &AppState) -> Router<AppState> {
+    Router::new()
         .route("/health", get(health))
+        .route("/health/ready", get(shed::ready))
+        .route("/notices", get(notices::get_notices))
+        .route("/attribution", get(attribution::attribution))
         .route("/search", get(search_movies))
         .route("/movie/:imdb_id", get(movie_detail))
-        .route("/torrentio/movie/:imdb_id", get(torrentio_movie))
+        .route("/torrentio/movie/:imdb_id", get(torrentio::torrentio_movie))
         .route(
             "/torrentio/show/:imdb_id/:season/:episode",
-            get(torrentio_episode),
+            get(torrentio::torrentio_episode),
         )
-        // .route("/stream", axum::routing::get(download_and_stream))
-        .route("/stream", axum::routing::get(download_and_stream))
+        .route("/stream", get(download_and_stream))
+        .route("/stream/sign", post(signing::sign_stream))
+        .route("/stream/hls/:job_id/master.m3u8", get(transcode::master_playlist))
+        .route("/stream/hls/:job_id/:key/:name", get(transcode::variant_file))
+        .route("/downloads", get(downloads::list_downloads).post(downloads::create_download))
+        .route("/downloads/torrent", post(downloads::upload_torrent))
+        .route(
+            "/downloads/:job_id",
+            get(downloads::download_status).delete(downloads::delete_download),
+        )
+        .route("/downloads/:job_id/cancel", post(downloads::cancel_download))
+        .route("/downloads/:job_id/events", get(downloads::download_events))
+        .route("/downloads/:job_id/progress", get(downloads::download_progress))
+        .route("/downloads/:job_id/log", get(downloads::download_log))
+        .route("/ws/downloads", get(downloads::downloads_ws))
         .route("/movies/trending", get(movies_trending))
-        .with_state(state)
-        .layer(CompressionLayer::new())
+        .route("/trending/all", get(trending_all))
+        .route("/catalog/torrents/popular", get(catalog::popular))
+        .route("/calendar", get(calendar::calendar))
+        .route("/library/search", get(library::search_library))
+        .route("/watchlist", get(watchlist::get_watchlist))
+        .route("/watched", get(completion::get_watched))
+        .route(
+            "/watchlist/:imdb_id",
+            put(watchlist::add_to_watchlist).delete(watchlist::remove_from_watchlist),
+        )
+        .route("/play/:imdb_id", get(playback::play_decision))
+        .route("/speedtest", get(speedtest::speedtest))
+        .route("/speedtest/report", post(speedtest::report))
+        .route("/torrent/health", get(tracker::torrent_health))
+        .route("/subtitles/match", get(subtitles::match_subtitles))
+        .route("/subtitles/:imdb_id", get(subtitles::subtitle_vtt))
+        .route("/media/chapters", get(markers::media_chapters))
+        .route("/media/audio", get(audio::extract_audio))
+        .route("/media/thumbnail", get(media::thumbnail))
+        .route("/media/:id/info", get(media::media_info))
+        .route("/hls/file/:filename/playlist.m3u8", get(hls::file_playlist))
+        .route("/hls/file/:filename/media", get(hls::file_media))
+        .route("/title/:imdb_id", get(metadata::title_detail))
+        .route("/title/:imdb_id/filename", get(slug::title_filename))
+        .route("/parse/release", get(release_name::parse_release))
+        .route(
+            "/title/:imdb_id/markers",
+            get(markers::get_markers).put(markers::put_markers),
+        )
+        .route("/share", post(share::create_share))
+        .route("/share/:id", get(share::open_share).delete(share::revoke_share))
+        .route("/party", post(party::create_party))
+        .route("/party/:id", get(party::party_info))
+        .route("/party/:id/ws", get(party::party_ws))
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), audit::audit_public))
+        // só a API pública: as rotas de admin continuam respondendo sob carga
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), shed::shed_requests))
+        // antes da recusa por carga: quem passou do limite nem conta nela
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), rate_limit::limit_requests))
+}
+
+/// Rotas operacionais (admin, métricas, health profundo). Servidas no
+/// `ADMIN_BIND_ADDR` quando configurado, senão junto com a API pública.
+fn admin_router(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/admin/recovery", get(recovery::last_report))
+        .route("/admin/upstream", get(torrentio::upstream_status))
+        .route("/admin/upstream-usage", get(usage::upstream_usage))
+        .route("/admin/upstream-failures", get(upstream::upstream_failures))
+        .route("/admin/stats", get(admin_stats))
+        .route("/admin/insights", get(stats::insights))
+        .route("/admin/config", get(reload::effective_config))
+        .route("/admin/config/reload", post(reload::reload_config))
+        .route("/admin/doctor", get(doctor::doctor))
+        .route("/admin/scratch/purge", post(scratch::purge_scratch))
+        .route("/admin/notifications/pending", get(outbox::pending_notifications))
+        .route("/admin/notifications/retry", post(outbox::retry_notifications))
+        .route("/admin/streams", get(leases::active_streams))
+        .route("/admin/trash", get(trash::list_trash))
+        .route("/admin/trash/restore", post(trash::restore_trash))
+        .route("/admin/storage", get(storage::storage_status))
+        .route("/admin/storage/cleanup", post(storage::run_cleanup))
+        .route("/admin/cache/warm", post(warm::start_warm))
+        .route("/admin/cache/posters", get(posters::poster_cache))
+        .route("/admin/cache/warm/:id", get(warm::warm_status))
+        .route("/admin/cache/warm/:id/cancel", post(warm::cancel_warm))
+        .route("/admin/export", get(export::export_state))
+        .route("/admin/import", post(export::import_state))
+        .route("/admin/audit", get(audit::list_audit))
+        .route("/admin/profiles", get(profiles::list_profiles))
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), auth::require_admin))
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), audit::audit_all))
+        // fora da auditoria: é consultado o tempo todo pelo monitoramento
+        .route("/health/deep", get(deep_health))
+}
+
+fn with_layers(router: Router, state: &AppState) -> Router {
+    let request_id = HeaderName::from_static(middleware::REQUEST_ID_HEADER);
+    let config = &state.config;
+    router
+        .layer(axum::middleware::from_fn_with_state(state.clone(), stats::observe))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), auth::require_api_key))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), auth::identify))
+        .layer(axum::middleware::from_fn(shape::negotiate))
+        .layer(axum::middleware::from_fn(middleware::catch_panic))
+        .layer(axum::middleware::from_fn_with_state(
+            config.clone(),
+            middleware::scope_deadline,
+        ))
+        .layer(axum::middleware::from_fn_with_state(config.clone(), middleware::error_body))
+        .layer(axum::middleware::from_fn(middleware::scope_request_id))
+        // o corpo do teste de velocidade é medido como sai
+        .layer(CompressionLayer::new().compress_when(
+            DefaultPredicate::new().and(NotForContentType::const_new(speedtest::CONTENT_TYPE)),
+        ))
         .layer(TraceLayer::new_for_http())
-        .layer(CorsLayer::permissive());
+        .layer(CorsLayer::permissive())
+        .layer(PropagateRequestIdLayer::new(request_id.clone()))
+        .layer(SetRequestIdLayer::new(request_id, middleware::RequestIds::default()))
+}
 
-    let addr: SocketAddr = ([0, 0, 0, 0], port).into();
-    let listener = TcpListener::bind(addr).await?;
-    info!("listening on {}", listener.local_addr()?);
-    axum::serve(listener, app).await?;
-    Ok(())
+async fn bind(addr: SocketAddr) -> io::Result<TcpListener> {
+    TcpListener::bind(addr)
+        .await
+        .map_err(|e| io::Error::new(e.kind(), format!("não foi possível escutar em {addr}: {e}")))
+}
+
+/// Uso de recursos do servidor: o scratch das transcodificações e os caches
+/// de respostas (com o TTL efetivo das entradas).
+async fn admin_stats(State(state): State<AppState>) -> impl IntoResponse {
+    Json(serde_json::json!({
+        "scratch": state.scratch.usage().await,
+        "storage": state.storage.metrics(),
+        "caches": {
+            "responses": state.cache.stats(),
+            "health": state.health.stats(),
+            "calendar": state.calendar.stats(),
+            "posters": posters::stats(&state),
+            "file_handles": state.file_handles.stats(),
+        },
+        "readahead": state.readahead.stats(),
+        "rate_limit": state.rate_limits.stats(),
+        "media_jobs": state.media_jobs.stats(),
+        "load": state.shedder.snapshot(&state.config()),
+    }))
 }
 
 async fn health() -> impl IntoResponse {
     Json(serde_json::json!({ "status": "ok" }))
 }
 
+/// Health profundo: além do processo, verifica as dependências locais do
+/// streaming (diretório de downloads gravável e `aria2c` disponível, se é
+/// ele quem baixa).
+async fn deep_health(State(state): State<AppState>) -> impl IntoResponse {
+    let config = state.config();
+    let downloads_ok = downloads_writable(&config.downloads_dir).await;
+    let aria2c_ok = aria2c_available().await;
+    let engine_ok = aria2c_ok || config.download_engine == engine::Engine::Embedded;
+
+    if !downloads_ok || !engine_ok {
+        warn!(downloads_ok, aria2c_ok, "deep health degradado");
+    }
+    let status = if downloads_ok && engine_ok { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (
+        status,
+        Json(serde_json::json!({
+            "status": if status == StatusCode::OK { "ok" } else { "degraded" },
+            "checks": {
+                "downloads_dir": downloads_ok,
+                "aria2c": aria2c_ok,
+                "download_engine": config.download_engine,
+            }
+        })),
+    )
+}
+
+/// Cria o diretório de downloads se preciso e confere que dá para gravar nele.
+async fn downloads_writable(downloads_dir: &StdPath) -> bool {
+    match fs::create_dir_all(downloads_dir).await {
+        Ok(()) => {
+            let probe = downloads_dir.join(".health-probe");
+            let ok = fs::write(&probe, b"ok").await.is_ok();
+            let _ = fs::remove_file(&probe).await;
+            ok
+        }
+        Err(_) => false,
+    }
+}
+
+async fn aria2c_available() -> bool {
+    Command::new("aria2c")
+        .arg("--version")
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .await
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
 async fn search_movies(
     State(state): State<AppState>,
+    mode: cache::CacheMode,
     Query(params): Query<SearchParams>,
 ) -> Result<impl IntoResponse, ApiError> {
+    let deadline = tokio::time::Instant::now() + Duration::from_millis(state.config().search_enrich_budget_ms);
     if params.q.trim().is_empty() {
         return Err(ApiError::BadRequest("q vazio".into()));
     }
+    match params.enrich.as_deref() {
+        None | Some("") | Some("ratings") => {}
+        Some(other) => return Err(ApiError::BadRequest(format!("enrich desconhecido: {other}"))),
+    }
 
+    let mut fetched = fetch_search(&state, &params, mode).await?;
+    stats::record(&state, stats::Event::Search);
+    let n = params.include_details.min(MAX_INCLUDE_DETAILS);
+    if n > 0 {
+        embed_details(&state, &mut fetched.value, n).await;
+    }
+    if params.enrich.as_deref() == Some("ratings") {
+        enrich_ratings(&state, &mut fetched.value, deadline).await;
+    }
+    availability::enrich(&state, &mut fetched.value["results"]).await;
+    Ok(fetched.shaped(shape::Shape::List))
+}
+
+/// Busca no OMDb via cache. A chave não inclui `include_details`: os
+/// detalhes entram só na resposta, vindos das entradas `detail:`.
+async fn fetch_search(
+    state: &AppState,
+    params: &SearchParams,
+    mode: cache::CacheMode,
+) -> Result<cache::Fetched, ApiError> {
     let key = format!(
         "search:q={}:page={}:type={}",
         params.q, params.page, params.r#type
     );
 
-    if let Some(cached) = state.cache.get(&key).await {
-        return Ok(Json(cached));
+    if let Some(cached) = state.cache.get(&key, mode).await {
+        return Ok(cached);
     }
 
     let url = format!(
-        "https://www.omdbapi.com/?apikey={}&s={}&page={}&type={}&r=json",
+        "{}/?apikey={}&s={}&page={}&type={}&r=json",
+        state.config().omdb_base_url,
         state.api_key,
         urlencoding::encode(&params.q),
         params.page,
         urlencoding::encode(&params.r#type),
     );
 
-    let resp = state
-        .http
-        .get(&url)
+    let resp = upstream::get(state, upstream::Service::Omdb, "search", &url)
         .send()
         .await
-        .map_err(|e| ApiError::Upstream(e.to_string()))?;
+        .map_err(upstream::send_error)?;
 
     if !resp.status().is_success() {
         return Err(ApiError::Upstream(format!("status {}", resp.status())));
     }
 
-    let body: OmdbSearchResp = resp
-        .json()
-        .await
-        .map_err(|e| ApiError::Upstream(e.to_string()))?;
+    let body: OmdbSearchResp = upstream::json(state, resp).await?;
 
     if body.ok != "True" {
         let msg = body.error.unwrap_or_else(|| "unknown".into());
         return Err(ApiError::Upstream(msg));
     }
 
-    let json = serde_json::json!({
+    let (mut results, skipped) = omdb::search_items(body.search.unwrap_or_default(), &params.r#type);
+    omdb::backfill_years(state, &mut results).await;
+    let mut json = serde_json::json!({
         "query": params.q,
         "page": params.page,
         "type": params.r#type,
         "total": body.total,
-        "results": body.search.unwrap_or_default(),
+        "results": results,
+        // itens malformados que o OMDb mandou e ficaram de fora
+        "skipped": skipped,
     });
+    let tmdb_posters = posters::fix_posters(state, &mut json["results"]).await;
+    attribution::annotate(
+        &mut json,
+        [Some(attribution::Source::Omdb), tmdb_posters.then_some(attribution::Source::Tmdb)].into_iter().flatten(),
+    );
+
+    state.cache.insert_with_ttl(key, json.clone(), state.config().cache_ttl(cache::CacheCategory::Search)).await;
+    Ok(cache::Fetched::miss(json))
+}
+
+/// Busca em paralelo os detalhes dos `n` primeiros resultados e os põe em
+/// `details`; um detalhe que falhar só deixa o item na forma curta.
+async fn embed_details(state: &AppState, search: &mut serde_json::Value, n: usize) {
+    let Some(results) = search.get_mut("results").and_then(|r| r.as_array_mut()) else {
+        return;
+    };
+    let ids: Vec<String> = results
+        .iter()
+        .take(n)
+        .filter_map(|r| r.get("imdbID").and_then(|v| v.as_str()).map(str::to_string))
+        .collect();
+    let details = futures_util::future::join_all(
+        ids.iter().map(|id| fetch_detail(state, id, cache::CacheMode::Normal)),
+    )
+    .await;
+
+    for (id, detail) in ids.iter().zip(details) {
+        match detail {
+            Ok(detail) => {
+                if let Some(item) = results
+                    .iter_mut()
+                    .find(|r| r.get("imdbID").and_then(|v| v.as_str()) == Some(id))
+                {
+                    item["details"] = detail.value;
+                }
+            }
+            Err(e) => warn!(imdb_id = %id, "detalhe para a busca falhou: {e}"),
+        }
+    }
+}
+
+/// `?enrich=ratings`: copia `imdbRating` e `Runtime` dos detalhes para os
+/// primeiros resultados. O que já está no cache `detail:` entra direto; o
+/// resto é buscado em segundo plano (concorrência limitada) e aproveitado
+/// até `deadline`. O que não chegar a tempo sai com `enriched: false`, mas
+/// a busca continua e deixa o cache pronto para a próxima vez.
+async fn enrich_ratings(state: &AppState, search: &mut serde_json::Value, deadline: tokio::time::Instant) {
+    let Some(results) = search.get_mut("results").and_then(|r| r.as_array_mut()) else {
+        return;
+    };
+    let ids: Vec<String> = results
+        .iter()
+        .take(MAX_ENRICH)
+        .filter_map(|r| r.get("imdbID").and_then(|v| v.as_str()).map(str::to_string))
+        .collect();
+
+    let mut details = std::collections::HashMap::new();
+    let mut missing = Vec::new();
+    for id in ids {
+        match state.cache.get(&format!("detail:{id}"), cache::CacheMode::Normal).await {
+            Some(cached) => {
+                details.insert(id, cached.value);
+            }
+            None => missing.push(id),
+        }
+    }
 
-    state.cache.insert(key, json.clone()).await;
-    Ok(Json(json))
+    if !missing.is_empty() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let bg = state.clone();
+        tokio::spawn(async move {
+            let mut fetches = futures_util::stream::iter(missing)
+                .map(|id| {
+                    let state = bg.clone();
+                    async move {
+                        let detail = fetch_detail(&state, &id, cache::CacheMode::Normal).await;
+                        (id, detail)
+                    }
+                })
+                .buffer_unordered(ENRICH_CONCURRENCY);
+            while let Some((id, detail)) = fetches.next().await {
+                match detail {
+                    // o handler pode já ter respondido; o cache fica pronto mesmo assim
+                    Ok(detail) => {
+                        let _ = tx.send((id, detail.value));
+                    }
+                    Err(e) => warn!(imdb_id = %id, "detalhe para enriquecer a busca falhou: {e}"),
+                }
+            }
+        });
+        while let Ok(Some((id, detail))) = tokio::time::timeout_at(deadline, rx.recv()).await {
+            details.insert(id, detail);
+        }
+    }
+
+    for item in results.iter_mut() {
+        let detail = item.get("imdbID").and_then(|v| v.as_str()).and_then(|id| details.get(id));
+        let Some(detail) = detail else {
+            item["enriched"] = false.into();
+            continue;
+        };
+        for field in ["imdbRating", "Runtime"] {
+            if let Some(value) = detail.get(field) {
+                item[field] = value.clone();
+            }
+        }
+        item["enriched"] = true.into();
+    }
 }
 
 async fn movie_detail(
     State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    identity: auth::Identity,
+    mode: cache::CacheMode,
     Path(imdb_id): Path<String>,
 ) -> Result<impl IntoResponse, ApiError> {
     if imdb_id.trim().is_empty() {
         return Err(ApiError::BadRequest("imdb_id vazio".into()));
     }
 
+    let mut detail = fetch_detail(&state, &imdb_id, mode).await?;
+    if detail.value.get("Type").and_then(|t| t.as_str()) == Some("movie") {
+        state.prefetch.movie_streams(&state, identity.client_key(client.ip()), &imdb_id).await;
+    }
+    // o corpo é o do OMDb, sem nada de outra fonte
+    attribution::annotate(&mut detail.value, [attribution::Source::Omdb]);
+    Ok(detail.shaped(shape::Shape::Detail))
+}
+
+/// Detalhes do OMDb por IMDb ID, via cache (`detail:<id>`).
+async fn fetch_detail(state: &AppState, imdb_id: &str, mode: cache::CacheMode) -> Result<cache::Fetched, ApiError> {
     let key = format!("detail:{}", imdb_id);
-    if let Some(cached) = state.cache.get(&key).await {
-        return Ok(Json(cached));
+    if let Some(cached) = state.cache.get(&key, mode).await {
+        return Ok(cached);
     }
 
     let url = format!(
-        "https://www.omdbapi.com/?apikey={}&i={}&plot=full&r=json",
+        "{}/?apikey={}&i={}&plot=full&r=json",
+        state.config().omdb_base_url,
         state.api_key,
-        urlencoding::encode(&imdb_id),
+        urlencoding::encode(imdb_id),
     );
 
-    let resp = state
-        .http
-        .get(&url)
+    let resp = upstream::get(state, upstream::Service::Omdb, "detail", &url)
         .send()
         .await
-        .map_err(|e| ApiError::Upstream(e.to_string()))?;
+        .map_err(upstream::send_error)?;
 
     if !resp.status().is_success() {
         return Err(ApiError::Upstream(format!("status {}", resp.status())));
     }
 
     // Não mapeamos tudo: retornamos JSON cru para flexibilidade
-    let body: serde_json::Value = resp
-        .json()
-        .await
-        .map_err(|e| ApiError::Upstream(e.to_string()))?;
+    let body: serde_json::Value = upstream::json(state, resp).await?;
 
     if body.get("Response") == Some(&serde_json::Value::String("False".into())) {
         let msg = body
@@ -319,90 +1053,85 @@ async fn movie_detail(
         return Err(ApiError::Upstream(msg.into()));
     }
 
-    state.cache.insert(key, body.clone()).await;
-    Ok(Json(body))
+    state.cache.insert_with_ttl(key, body.clone(), state.config().cache_ttl(cache::CacheCategory::Detail)).await;
+    library::remember(state, &body);
+    Ok(cache::Fetched::miss(body))
 }
 
-async fn torrentio_movie(
-    State(state): State<AppState>,
-    Path(imdb_id): Path<String>,
-) -> Result<impl IntoResponse, ApiError> {
-    if imdb_id.trim().is_empty() {
-        return Err(ApiError::BadRequest("imdb_id vazio".into()));
-    }
-
-    let key = format!("torrentio:movie:{}", imdb_id);
-    if let Some(cached) = state.cache.get(&key).await {
-        return Ok(Json(cached));
-    }
-
-    let url = format!("https://torrentio.strem.fun/stream/movie/{}.json", imdb_id);
-
-    let resp = state
-        .http
-        .get(&url)
-        .send()
-        .await
-        .map_err(|e| ApiError::Upstream(e.to_string()))?;
-
-    if !resp.status().is_success() {
-        return Err(ApiError::Upstream(format!("status {}", resp.status())));
-    }
-
-    let body: serde_json::Value = resp
-        .json()
-        .await
-        .map_err(|e| ApiError::Upstream(e.to_string()))?;
-
-    state.cache.insert(key, body.clone()).await;
-    Ok(Json(body))
+#[derive(Deserialize)]
+struct TorrentParams {
+    /// Link magnet, infohash ou URL `http(s)` de um `.torrent`.
+    #[serde(default)]
+    magnet: String,
+    /// Nome do arquivo a servir. Sem ele, sai do `dn` do magnet ou do
+    /// título de `imdb_id`, pelas regras de `slug::filename`.
+    #[serde(default)]
+    filename: String,
+    /// Título assistido: nomeia o arquivo quando não há `filename` nem `dn`
+    /// e, com `season`/`episode`, vai para o stream ativo e para o índice
+    /// dos downloads (veja `stream_title`).
+    imdb_id: Option<String>,
+    season: Option<u32>,
+    episode: Option<u32>,
+    /// Perfil que fica com o "assistido" quando o arquivo é servido até o
+    /// fim (veja `completion`).
+    #[serde(default)]
+    profile: String,
+    /// Fonte HTTP remota (debrid, storage) a repassar no lugar de um torrent.
+    url: Option<String>,
+    /// URL assinada por `/stream/sign`: HMAC hex e expiração (unix, s).
+    sig: Option<String>,
+    exp: Option<u64>,
+    /// Link de convidado da URL assinada (`/share/:id`).
+    share: Option<String>,
+    /// Tamanho esperado, usado na estimativa de progresso quando não há `.aria2`.
+    size_bytes: Option<u64>,
+    /// Episódio a servir de dentro de um pack de temporada (`S01E07`, `1x07`, `E07`).
+    episode_hint: Option<String>,
+    /// `1`: com o arquivo ainda em download, espera o trecho pedido ser
+    /// gravado e serve só o que já existe; sem ele vale `wait`.
+    #[serde(default, deserialize_with = "flag_param")]
+    progressive: bool,
+    /// O que fazer enquanto o arquivo é baixado (por este pedido ou por
+    /// outro, ao qual este se junta).
+    #[serde(default)]
+    wait: Wait,
 }
 
-async fn torrentio_episode(
-    State(state): State<AppState>,
-    Path((imdb_id, season, episode)): Path<(String, String, String)>,
-) -> Result<impl IntoResponse, ApiError> {
-    if imdb_id.trim().is_empty() {
-        return Err(ApiError::BadRequest("imdb_id vazio".into()));
-    }
-
-    let key = format!("torrentio:show:{}:S{}E{}", imdb_id, season, episode);
-    if let Some(cached) = state.cache.get(&key).await {
-        return Ok(Json(cached));
-    }
-
-    let url = format!(
-        "https://torrentio.strem.fun/stream/series/{}/{}-{}/.json",
-        imdb_id, season, episode
-    );
+/// `?wait=` do `/stream` com o arquivo ainda não baixado.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Wait {
+    /// Espera o download e serve o arquivo.
+    #[default]
+    Block,
+    /// `202` na hora, com o id do download e onde acompanhar o progresso.
+    Progress,
+    /// `303` para o progresso do download.
+    Redirect,
+}
 
-    let resp = state
-        .http
-        .get(&url)
-        .send()
-        .await
-        .map_err(|e| ApiError::Upstream(e.to_string()))?;
+/// `1`/`true` em query strings.
+fn flag_param<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    let raw = String::deserialize(deserializer)?;
+    Ok(matches!(raw.as_str(), "1" | "true"))
+}
 
-    if !resp.status().is_success() {
-        return Err(ApiError::Upstream(format!("status {}", resp.status())));
+/// Arquivo concluído `filename` sob `base_dir`. Um `.partial` nunca conta
+/// como baixado; quem precisa dele usa [`find_partial_file`].
+async fn find_downloaded_file(base_dir: &StdPath, filename: &str) -> Option<PathBuf> {
+    if filename.ends_with(downloads::PARTIAL_SUFFIX) {
+        return None;
     }
-
-    let body: serde_json::Value = resp
-        .json()
-        .await
-        .map_err(|e| ApiError::Upstream(e.to_string()))?;
-
-    state.cache.insert(key, body.clone()).await;
-    Ok(Json(body))
+    find_file(base_dir, filename).await
 }
 
-#[derive(Deserialize)]
-struct TorrentParams {
-    magnet: String,
-    filename: String, // nome do arquivo a ser servido
+/// O `.partial` de `filename`, enquanto o aria2c grava (ou se ficou para trás).
+async fn find_partial_file(base_dir: &StdPath, filename: &str) -> Option<PathBuf> {
+    find_file(base_dir, &downloads::partial_name(filename)).await
 }
 
-async fn find_downloaded_file(base_dir: &StdPath, filename: &str) -> Option<PathBuf> {
+async fn find_file(base_dir: &StdPath, filename: &str) -> Option<PathBuf> {
     let mut entries = match fs::read_dir(base_dir).await {
         Ok(rd) => rd,
         Err(_) => return None,
@@ -412,9 +1141,10 @@ async fn find_downloaded_file(base_dir: &StdPath, filename: &str) -> Option<Path
         let path = entry.path();
         if path.is_file() && path.file_name().map(|n| n == filename).unwrap_or(false) {
             return Some(path);
-        } else if path.is_dir() {
+        } else if path.is_dir() && !entry.file_name().to_string_lossy().starts_with('.') {
+            // diretórios ocultos (.trash, .audio-cache) não são downloads
             // Aqui criamos uma future "boxed" para a chamada recursiva
-            if let Some(found) = Box::pin(find_downloaded_file(&path, filename)).await {
+            if let Some(found) = Box::pin(find_file(&path, filename)).await {
                 return Some(found);
             }
         }
@@ -422,64 +1152,371 @@ async fn find_downloaded_file(base_dir: &StdPath, filename: &str) -> Option<Path
 
     None
 }
-async fn download_and_stream(Query(params): Query<TorrentParams>, headers: HeaderMap) -> Result<Response, (StatusCode, String)>  {
-    let download_dir = PathBuf::from("./downloads");
-    tokio::fs::create_dir_all(&download_dir).await.unwrap();
+/// Todos os arquivos sob `dir`, com tamanho.
+async fn list_files(dir: &StdPath) -> Vec<(PathBuf, u64)> {
+    let mut out = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(mut entries) = fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let Ok(meta) = entry.metadata().await else {
+                continue;
+            };
+            if meta.is_dir() {
+                pending.push(entry.path());
+            } else {
+                out.push((entry.path(), meta.len()));
+            }
+        }
+    }
+    out
+}
 
-    let filepath = match find_downloaded_file(&download_dir, &params.filename).await {
-        Some(p) => p,
-        None => {
-            println!("File not found, starting aria2c download...");
+/// 404 com a lista de arquivos do torrent, para o cliente escolher manualmente.
+fn episode_not_found(base: &StdPath, files: &[(PathBuf, u64)], hint: EpisodeHint) -> ApiError {
+    let available: Vec<_> = files
+        .iter()
+        .filter(|(p, _)| episode::is_video(p))
+        .map(|(p, size)| {
+            serde_json::json!({
+                "filename": p.strip_prefix(base).unwrap_or(p).to_string_lossy(),
+                "size_bytes": size,
+            })
+        })
+        .collect();
+    ApiError::NoMatchingFile {
+        message: format!("nenhum arquivo corresponde a {hint}"),
+        available_files: available,
+    }
+}
 
-            let magnet_link = if params.magnet.starts_with("magnet:?") {
-                params.magnet.clone()
-            } else {
-                format!("magnet:?xt=urn:btih:{}", params.magnet)
-            };
+/// `Title.Year.mkv` de `imdb_id`, para `/stream` sem `filename` nem `dn`.
+async fn filename_from_title(state: &AppState, imdb_id: Option<&str>) -> Result<String, ApiError> {
+    let imdb_id = imdb_id.ok_or_else(|| ApiError::BadRequest("informe filename (ou imdb_id)".into()))?;
+    markers::check_imdb_id(imdb_id)?;
+    let detail = fetch_detail(state, imdb_id, cache::CacheMode::Normal).await?.value;
+    let text = |field: &str| detail.get(field).and_then(|v| v.as_str()).filter(|v| *v != "N/A");
+    let title = text("Title").ok_or_else(|| ApiError::NotFound(format!("{imdb_id} sem título")))?;
+    Ok(slug::from_title(title, text("Year"), None, slug::DEFAULT_EXTENSION))
+}
 
-            let status = Command::new("aria2c")
-                .arg("--dir")
-                .arg(&download_dir)
-                .arg("--out")
-                .arg(&params.filename)
-                .arg("--seed-time=0")
-                .arg(magnet_link)
-                .arg("--enable-dht=true")
-                .arg("--enable-peer-exchange=true")
-                .arg("--bt-tracker=udp://tracker.opentrackr.org:1337/announce,udp://open.stealth.si:80/announce,udp://tracker.cyberia.is:6969/announce")
-                .status()
-                .await;
+async fn download_and_stream(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    identity: auth::Identity,
+    Query(params): Query<TorrentParams>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let signed = match (&params.sig, params.exp) {
+        (Some(_), Some(_)) => {
+            // confere a query crua: `profile`, `wait` e cia. também são assinados
+            signing::verify_query(&state.config(), &uri)?;
+            if let Some(share) = &params.share {
+                share::check_active(&state, share).await?;
+            }
+            true
+        }
+        (None, None) => false,
+        _ => return Err(ApiError::Forbidden("sig e exp devem vir juntos".into())),
+    };
 
+    let title = stream_title::StreamTitle::from_hints(params.imdb_id.as_deref(), params.season, params.episode)?;
+    // convidados de um link compartilhado não marcam nada como assistido
+    let viewer = match &params.share {
+        Some(_) => None,
+        None => Some(completion::Viewer {
+            client: identity.client_key(client.ip()),
+            profile: identity.profile(&params.profile)?,
+            title: title.clone(),
+        }),
+    };
 
-            println!("aria2c finished: {:?}", status);
+    if let Some(url) = &params.url {
+        // URLs assinadas pelo servidor dispensam a lista de hosts
+        let url = if signed {
+            reqwest::Url::parse(url).map_err(|_| ApiError::BadRequest("url inválida".into()))?
+        } else {
+            proxy::allowed_url(&state.config(), url)?
+        };
+        return proxy::stream_remote(&state, url, &headers).await;
+    }
+    if params.magnet.trim().is_empty() {
+        return Err(ApiError::BadRequest("informe magnet (ou url)".into()));
+    }
 
-            if !matches!(status, Ok(s) if s.success()) {
-                return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Download failed")));
-            }
+    let (torrent, magnet);
+    let source = if torrent::is_torrent_url(&params.magnet) {
+        torrent = TorrentFile::fetch(params.magnet.trim()).await?;
+        downloads::Source::Torrent(&torrent)
+    } else {
+        magnet = Magnet::parse(&params.magnet).ok_or_else(|| ApiError::BadRequest("magnet inválido".into()))?;
+        downloads::Source::Magnet(&magnet)
+    };
+    let from_dn = match &source {
+        downloads::Source::Magnet(magnet) => slug::from_magnet(magnet),
+        downloads::Source::Torrent(_) => None,
+    };
+    let filename = match (params.filename.as_str(), from_dn) {
+        ("", Some(name)) => name,
+        ("", None) => filename_from_title(&state, params.imdb_id.as_deref()).await?,
+        (name, _) => name.to_string(),
+    };
+    downloads::check_filename(&filename)?;
+    let download_dir = downloads::job_dir(&state.config().downloads_dir, source.info_hash());
+    tokio::fs::create_dir_all(&download_dir)
+        .await
+        .map_err(|e| ApiError::Storage(format!("não foi possível criar {}: {e}", download_dir.display())))?;
+    if let Some(title) = &title
+        && let Err(e) = state.downloads.set_title(source.info_hash(), title).await
+    {
+        // falhar aqui não impede o stream
+        warn!(id = source.info_hash(), "falha ao gravar o título do stream: {e}");
+    }
 
-            find_downloaded_file(&download_dir, &params.filename).await
-                .ok_or_else(|| (StatusCode::INTERNAL_SERVER_ERROR, "File not found after download".to_string()))?
-            
+    let hint = match params.episode_hint.as_deref() {
+        Some(raw) => Some(
+            EpisodeHint::parse(raw).ok_or_else(|| ApiError::BadRequest(format!("episode_hint inválido: {raw}")))?,
+        ),
+        None => None,
+    };
+
+    // numeração absoluta além da primeira temporada: pelas contagens do TMDB
+    let offsets = match (hint, params.imdb_id.as_deref()) {
+        (Some(EpisodeHint { season: Some(season), .. }), Some(imdb_id)) if season > 1 => {
+            episode::season_offsets(&state, imdb_id).await
         }
+        _ => None,
+    };
+    // onde o episódio começa num arquivo com vários
+    let mut position = None;
+
+    let existing = match hint {
+        Some(hint) => match episode::select(&list_files(&download_dir).await, hint, offsets.as_ref()) {
+            Selection::Found { path, position: at } => {
+                position = Some(at);
+                Some(path)
+            }
+            Selection::NoMatch => None,
+        },
+        None => find_downloaded_file(&download_dir, &filename).await,
     };
 
-    println!("Checking file at {:?}", filepath);
+    // mesmo arquivo já baixado sob outro nome: reaproveita em vez de baixar
+    let existing = match existing {
+        None if hint.is_none() => match state.downloads.lookup(source.info_hash(), source.file_index(&filename)).await? {
+            Some(canonical) => Some(
+                state
+                    .downloads
+                    .link(source.info_hash(), &canonical, &download_dir.join(&filename))
+                    .await,
+            ),
+            None => None,
+        },
+        other => other,
+    };
 
-    // Stream the file
-    if !filepath.exists() {
-        return Err((StatusCode::NOT_FOUND, "Video not found".to_string()));
+    // `progressive`: começa (ou se junta a) o download e serve os bytes do
+    // `.partial` assim que o trecho pedido estiver gravado; o `.partial`
+    // nunca é servido como completo
+    if params.progressive && existing.is_none() && hint.is_none() {
+        rate_limit::check_download(&state, &identity, client.ip(), source.info_hash())?;
+        let job = downloads::start(&state, source, &filename, params.size_bytes);
+        info!(id = job.id, joined = job.joined, "stream progressivo");
+        let partial = match find_partial_file(&download_dir, &filename).await {
+            Some(partial) => partial,
+            None => download_dir.join(downloads::partial_name(&filename)),
+        };
+        // terminar bem não encerra a espera: o `serve_progressive` vê o
+        // arquivo final e serve ele
+        return tokio::select! {
+            served = serve_progressive(&state, &partial, &headers, client.ip(), title, viewer) => served,
+            Err(failure) = job.finished() => Err(download_error(&failure)),
+        };
     }
 
-    let mut file = match File::open(&filepath).await {
-        Ok(file) => file,
-        Err(err) => return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to open video file: {}", err))),
+    let filepath = match existing {
+        Some(p) => p,
+        None => {
+            rate_limit::check_download(&state, &identity, client.ip(), source.info_hash())?;
+            let job = downloads::start(&state, source, &filename, params.size_bytes);
+            info!(id = job.id, joined = job.joined, wait = ?params.wait, "arquivo ainda não baixado");
+            let status_url = format!("/downloads/{}", job.id);
+            match params.wait {
+                Wait::Block => {}
+                Wait::Progress => {
+                    let body = job.accepted(&state, &filename);
+                    return Ok((StatusCode::ACCEPTED, [(header::LOCATION, status_url)], Json(body)).into_response());
+                }
+                Wait::Redirect => return Ok(Redirect::to(&status_url).into_response()),
+            }
+
+            job.finished().await.map_err(|failure| download_error(&failure))?;
+
+            match hint {
+                Some(hint) => {
+                    let files = list_files(&download_dir).await;
+                    match episode::select(&files, hint, offsets.as_ref()) {
+                        Selection::Found { path, position: at } => {
+                            position = Some(at);
+                            path
+                        }
+                        Selection::NoMatch => return Err(episode_not_found(&download_dir, &files, hint)),
+                    }
+                }
+                None => find_downloaded_file(&download_dir, &filename)
+                    .await
+                    .ok_or_else(|| {
+                        ApiError::NotFound(format!("{} não encontrado após o download", filename))
+                    })?,
+            }
+        }
     };
 
-    let meta = match file.metadata().await {
-        Ok(meta) => meta,
-        Err(err) => return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to get video metadata: {}", err))),
+    tracing::debug!(path = %filepath.display(), "servindo arquivo do download");
+    if let Some(partial) = progress::partial(&filepath).await {
+        if params.progressive {
+            return serve_progressive(&state, &filepath, &headers, client.ip(), title, viewer).await;
+        }
+        let control = partial.control.as_ref();
+        return Err(ApiError::DownloadInProgress {
+            bytes_done: control.map(|c| c.completed_bytes()),
+            total_bytes: control.map(|c| c.total_length),
+        });
+    }
+    let offset = match position.filter(|p| p.count > 1) {
+        Some(position) => match media::probe(&state, &filepath).await {
+            Ok(info) => info.duration_secs.and_then(|d| position.start_offset_secs(d)),
+            Err(e) => {
+                warn!(path = %filepath.display(), "sem a duração para o início do episódio: {e}");
+                None
+            }
+        },
+        None => None,
     };
-    let file_size = meta.len();
+    let mut response = serve_file(&state, &filepath, &headers, client.ip(), title, viewer).await?;
+    if let Some(offset) = offset {
+        response.headers_mut().insert(START_OFFSET_HEADER, offset.into());
+    }
+    Ok(response)
+}
+
+fn download_error(failure: &aria2::DownloadFailure) -> ApiError {
+    match failure.reason {
+        aria2::Reason::DiskFull => ApiError::StorageFull(failure.to_string()),
+        reason => ApiError::DownloadFailed {
+            exit_code: failure.exit_code,
+            reason,
+            stderr_excerpt: failure.output_tail.clone(),
+        },
+    }
+}
+
+/// Segundos até o episódio pedido num arquivo com vários (`E01-E03`),
+/// estimados pela posição dele e pela duração do arquivo.
+const START_OFFSET_HEADER: &str = "x-start-offset-hint-seconds";
+
+/// Quanto `/stream?progressive=1` espera o trecho pedido começar a existir.
+const PROGRESSIVE_WAIT: Duration = Duration::from_secs(60);
+const PROGRESSIVE_POLL: Duration = Duration::from_millis(500);
+
+/// Arquivo ainda em download (em geral o `.partial`): espera a peça do
+/// início do `Range` (ou do começo, sem `Range`) e responde `206` só com os
+/// bytes contíguos já gravados; o player pede o resto depois. Com
+/// pré-alocação o arquivo já tem o tamanho final, então o tamanho em disco
+/// não diz nada: vale o `.aria2`.
+async fn serve_progressive(
+    state: &AppState,
+    filepath: &StdPath,
+    headers: &HeaderMap,
+    client: IpAddr,
+    title: Option<stream_title::StreamTitle>,
+    viewer: Option<completion::Viewer>,
+) -> Result<Response, ApiError> {
+    let requested = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|s| s.strip_prefix("bytes="));
+    let give_up = tokio::time::Instant::now() + PROGRESSIVE_WAIT;
+    let done = downloads::final_path(filepath);
+    loop {
+        if downloads::is_finalized(&done).await {
+            // terminou enquanto esperávamos
+            return serve_file(state, &done, headers, client, title, viewer).await;
+        }
+        // sem controle legível: o aria2c está começando ou finalizando
+        let control = progress::partial(filepath).await.and_then(|p| p.control);
+        if let Some(control) = &control {
+            let total = control.total_length;
+            let (start, end) = match requested {
+                Some(range) => parse_range(range, total)
+                    .ok_or_else(|| ApiError::BadRequest(format!("Range inválido: {range}")))?,
+                None => (0, total.saturating_sub(1)),
+            };
+            let available = control.available_from(start);
+            if available > 0 {
+                let end = end.min(start + available - 1);
+                let lease = state
+                    .leases
+                    .read_as(filepath, title.clone())
+                    .map_err(|busy| ApiError::Conflict(format!("{} está sendo removido", busy.0.display())))?;
+                // o parcial cresce: fica fora do cache de descritores
+                let file = File::open(filepath)
+                    .await
+                    .map_err(|e| ApiError::Storage(format!("falha ao abrir o vídeo: {e}")))?
+                    .into_std()
+                    .await;
+                // o começo pode ainda não estar gravado: vale o nome final
+                let content_type = content_type::detect_by_name(&done);
+                return Ok(range_response(Arc::new(file), content_type, lease, (start, end, total), None, None).await);
+            }
+        }
+        if tokio::time::Instant::now() >= give_up {
+            return Err(ApiError::DownloadInProgress {
+                bytes_done: control.as_ref().map(|c| c.completed_bytes()),
+                total_bytes: control.as_ref().map(|c| c.total_length),
+            });
+        }
+        tokio::time::sleep(PROGRESSIVE_POLL).await;
+    }
+}
+
+/// Envia um arquivo baixado respeitando `Range` (também usado pelas
+/// playlists HLS de `/hls/file`). Com `viewer`, os bytes entregues contam
+/// para o `playback.completed`.
+async fn serve_file(
+    state: &AppState,
+    filepath: &StdPath,
+    headers: &HeaderMap,
+    client: IpAddr,
+    title: Option<stream_title::StreamTitle>,
+    viewer: Option<completion::Viewer>,
+) -> Result<Response, ApiError> {
+    // Stream the file
+    if !filepath.exists() {
+        return Err(ApiError::NotFound("vídeo não encontrado".into()));
+    }
+    // segurado até o corpo terminar, para que o arquivo não seja removido no meio do stream
+    let lease = state
+        .leases
+        .read_as(filepath, title)
+        .map_err(|busy| ApiError::Conflict(format!("{} está sendo removido", busy.0.display())))?;
+    state.storage.touch(&state.config().downloads_dir, filepath);
+
+    // pedidos seguidos do mesmo arquivo reaproveitam o descritor e os metadados
+    let handle = state
+        .file_handles
+        .open(filepath, state.config().file_handle_cache_size)
+        .await
+        .map_err(|e| ApiError::Storage(format!("falha ao abrir o vídeo: {e}")))?;
+    let file_size = handle.size;
+    if file_size == 0 {
+        // arquivo criado mas nunca gravado: servir `200` vazio faria o player
+        // desistir achando que o vídeo acabou
+        return Err(ApiError::Conflict("o arquivo ainda está vazio".into()));
+    }
 
     let range = headers
         .get(header::RANGE)
@@ -488,47 +1525,104 @@ async fn download_and_stream(Query(params): Query<TorrentParams>, headers: Heade
 
     if let Some(range) = range {
         let (start, end) = parse_range(range, file_size).unwrap_or((0, file_size - 1));
-        let chunk_size = (end - start) + 1;
+        let served = state.completions.track(state, filepath, file_size, (start, end), viewer);
+        let reader = (filepath.to_path_buf(), client);
+        let mut response = range_response(
+            handle.file,
+            handle.content_type,
+            lease,
+            (start, end, file_size),
+            Some((state, reader)),
+            served,
+        )
+        .await;
+        response.headers_mut().insert(header::ETAG, handle.etag.parse().unwrap());
+        return Ok(response);
+    }
 
-        // Mover o cursor do arquivo para o 'start' do range
-        if let Err(err) = file.seek(SeekFrom::Start(start)).await {
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to seek file: {}", err)));
+    // Se não houver 'Range', transmite o arquivo inteiro
+    let mut served = state.completions.track(state, filepath, file_size, (0, file_size - 1), viewer);
+    let stream = file_handles::read_range(handle.file, 0, file_size).map(move |chunk| {
+        let _ = &lease;
+        if let (Ok(bytes), Some(served)) = (&chunk, served.as_mut()) {
+            served.add(bytes.len() as u64);
         }
+        chunk
+    });
+    let body = Body::from_stream(stream);
 
-        // Criar um stream que lê apenas o 'chunk_size' necessário
-        let stream = ReaderStream::new(file).take(chunk_size as usize);
-
-        let body = Body::from_stream(stream);
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::CONTENT_TYPE, handle.content_type.mime.parse().unwrap());
+    response_headers.insert(header::CONTENT_LENGTH, file_size.to_string().parse().unwrap());
+    response_headers.insert(header::ACCEPT_RANGES, "bytes".parse().unwrap());
+    response_headers.insert(header::ETAG, handle.etag.parse().unwrap());
 
-        let mut response_headers = HeaderMap::new();
-        response_headers.insert(
-            header::CONTENT_RANGE,
-            format!("bytes {}-{}/{}", start, end, file_size).parse().unwrap(),
-        );
-        response_headers.insert(header::ACCEPT_RANGES, "bytes".parse().unwrap());
-        response_headers.insert(header::CONTENT_LENGTH, chunk_size.to_string().parse().unwrap());
-        response_headers.insert(header::CONTENT_TYPE, "video/mp4".parse().unwrap());
+    Ok((StatusCode::OK, response_headers, body).into_response())
+}
 
-        return Ok((StatusCode::PARTIAL_CONTENT, response_headers, body).into_response());
-    }
+/// `206` com os bytes `start..=end` de um arquivo de `total` bytes. Com
+/// `readahead`, o trecho seguinte é aquecido quando o corpo termina; com
+/// `served`, os bytes entregues são contados na sessão do espectador.
+async fn range_response(
+    file: Arc<std::fs::File>,
+    content_type: content_type::Detected,
+    lease: leases::ReadLease,
+    (start, end, total): (u64, u64, u64),
+    readahead: Option<(&AppState, readahead::Viewer)>,
+    mut served: Option<completion::Served>,
+) -> Response {
+    let chunk_size = (end - start) + 1;
+
+    // terminado o corpo, o trecho seguinte é lido adiante para o mesmo
+    // espectador; cliente que desistiu no meio não dispara nada
+    let mut after = match readahead {
+        Some((state, viewer)) => {
+            state.readahead.observe(&viewer, start, end).await;
+            let (readahead, bytes) = (state.readahead.clone(), state.config().readahead_bytes);
+            Some(move || {
+                tokio::spawn(async move { readahead.after(viewer, end + 1, total, bytes).await });
+            })
+        }
+        None => None,
+    };
+    let mut sent = 0u64;
+
+    // lido por posição: o descritor pode estar servindo outros pedidos
+    let stream = file_handles::read_range(file, start, chunk_size).map(move |chunk| {
+        let _ = &lease;
+        if let Ok(bytes) = &chunk {
+            sent += bytes.len() as u64;
+            if let Some(served) = served.as_mut() {
+                served.add(bytes.len() as u64);
+            }
+            if sent >= chunk_size
+                && let Some(after) = after.take()
+            {
+                after();
+            }
+        }
+        chunk
+    });
 
-    // Se não houver 'Range', transmite o arquivo inteiro
-    let stream = ReaderStream::new(file);
     let body = Body::from_stream(stream);
 
     let mut response_headers = HeaderMap::new();
-    response_headers.insert(header::CONTENT_TYPE, "video/mp4".parse().unwrap());
-    response_headers.insert(header::CONTENT_LENGTH, file_size.to_string().parse().unwrap());
+    response_headers.insert(
+        header::CONTENT_RANGE,
+        format!("bytes {}-{}/{}", start, end, total).parse().unwrap(),
+    );
     response_headers.insert(header::ACCEPT_RANGES, "bytes".parse().unwrap());
+    response_headers.insert(header::CONTENT_LENGTH, chunk_size.to_string().parse().unwrap());
+    response_headers.insert(header::CONTENT_TYPE, content_type.mime.parse().unwrap());
 
-    Ok((StatusCode::OK, response_headers, body).into_response())
+    (StatusCode::PARTIAL_CONTENT, response_headers, body).into_response()
 }
 
 fn parse_range(range_str: &str, file_size: u64) -> Option<(u64, u64)> {
     let mut parts = range_str.split('-');
     let start = parts.next()?.parse::<u64>().ok()?;
     let end = match parts.next() {
-        Some("") | None => file_size - 1,
+        Some("") | None => file_size.checked_sub(1)?,
         Some(end_str) => end_str.parse::<u64>().ok()?,
     };
 
@@ -539,99 +1633,444 @@ fn parse_range(range_str: &str, file_size: u64) -> Option<(u64, u64)> {
     Some((start, end))
 }
 
+/// Teto de `page_size` em `/movies/trending`.
+const MAX_TRENDING_PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Deserialize)]
+struct TrendingParams {
+    filter: Option<String>,
+    #[serde(default = "default_page")]
+    page: u32,
+    /// Sem ele, a lista inteira numa resposta só.
+    page_size: Option<usize>,
+    /// `1`: tempo de cada estágio da reconstrução em `debug` (só admin).
+    debug: Option<String>,
+}
+
+/// `GET /movies/trending?filter=&page=&page_size=` — com filtro, só os
+/// títulos que batem. Cada item traz o `rank` da lista completa, e as
+/// páginas são fatias da mesma lista em cache: enquanto `generation` não
+/// muda, a página 2 continua exatamente de onde a 1 parou. `debug=1` com o
+/// token de admin mostra o tempo de cada estágio da última reconstrução
+/// (veja `trending::fetch`); para os demais é ignorado.
+async fn movies_trending(
+    State(state): State<AppState>,
+    identity: auth::Identity,
+    mode: cache::CacheMode,
+    Query(params): Query<TrendingParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    if params.page == 0 {
+        return Err(ApiError::BadRequest("page começa em 1".into()));
+    }
+    if params.page_size.is_some_and(|size| !(1..=MAX_TRENDING_PAGE_SIZE).contains(&size)) {
+        return Err(ApiError::BadRequest(format!("page_size deve estar entre 1 e {MAX_TRENDING_PAGE_SIZE}")));
+    }
+    let mut fetched = trending::fetch(&state, mode).await?;
+    let debug = fetched.value.as_object_mut().and_then(|obj| obj.remove("debug"));
+    if let Some(mut debug) = debug.filter(|_| identity.admin && params.debug.as_deref().is_some_and(|v| v == "1" || v == "true")) {
+        debug["cache"] = if fetched.age.is_some() { "hit" } else { "miss" }.into();
+        fetched.value["debug"] = debug;
+    }
+    let mut items = match fetched.value["results"].take() {
+        serde_json::Value::Array(items) => items,
+        _ => Vec::new(),
+    };
+    if let Some(filter) = params.filter.as_deref().and_then(filter::TitleFilter::new) {
+        items.retain(|item| item["Title"].as_str().is_some_and(|t| filter.matches(t)));
+    }
+    let total = items.len();
+    fetched.value["total"] = total.to_string().into();
+    if let Some(size) = params.page_size {
+        let start = (params.page as usize - 1).saturating_mul(size).min(total);
+        items = items.drain(start..).take(size).collect();
+        fetched.value["page"] = params.page.into();
+        fetched.value["page_size"] = size.into();
+        fetched.value["total_pages"] = total.div_ceil(size).into();
+    }
+    fetched.value["results"] = items.into();
+    availability::enrich(&state, &mut fetched.value["results"]).await;
+    Ok(fetched.shaped(shape::Shape::List))
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Deserialize)]
+struct TrendingAllParams {
+    /// `day` ou `week`.
+    #[serde(default = "default_window")]
+    window: String,
+    #[serde(default = "default_page")]
+    page: u32,
+    /// Filtra por título nas primeiras `TRENDING_FILTER_MAX_PAGES` páginas.
+    filter: Option<String>,
+}
+fn default_window() -> String {
+    "week".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbTrendingPage {
+    results: Vec<TmdbTrendingItem>,
+    #[serde(default)]
+    total_pages: u32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TmdbTrendingItem {
+    id: u64,
+    media_type: String,
+    title: Option<String>,
+    name: Option<String>,
+    release_date: Option<String>,
+    first_air_date: Option<String>,
+    poster_path: Option<String>,
+}
+
 #[derive(Serialize)]
-struct OmdbMovieShort {
-    Poster: String,
-    Title: String,
-    Type: String,
-    Year: String,
-    imdbID: String,
+struct TrendingEntry {
+    #[serde(rename = "Poster")]
+    poster: String,
+    #[serde(rename = "Title")]
+    title: String,
+    #[serde(rename = "Year")]
+    year: String,
+    #[serde(rename = "imdbID")]
+    imdb_id: Option<String>,
+    tmdb_id: u64,
+    /// `movie` ou `series`.
+    media_type: &'static str,
+    /// Sem IMDb id não há como buscar streams, mas o item ainda pode ser exibido.
+    playable: bool,
+    release_date: Option<String>,
+    /// Posição na lista completa (só nas respostas com `filter`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rank: Option<usize>,
+}
+
+/// Máximo de resultados de um `filter` (cada um custa uma busca no OMDb).
+const MAX_FILTER_RESULTS: usize = 40;
+
+/// `GET /trending/all?window=day|week&page=` — filmes e séries em alta juntos
+/// (pessoas ficam de fora), com o IMDb id resolvido pelo OMDb. Cache por
+/// janela e página. Com `filter`, a busca é por título em várias páginas
+/// (veja `fetch_trending_filtered`) e `page` é ignorado.
+async fn trending_all(
+    State(state): State<AppState>,
+    mode: cache::CacheMode,
+    Query(params): Query<TrendingAllParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !matches!(params.window.as_str(), "day" | "week") {
+        return Err(ApiError::BadRequest("window deve ser day ou week".into()));
+    }
+    if !(1..=500).contains(&params.page) {
+        return Err(ApiError::BadRequest("page deve estar entre 1 e 500".into()));
+    }
+    let mut fetched = match params.filter.as_deref().and_then(filter::TitleFilter::new) {
+        Some(filter) => {
+            let raw = params.filter.as_deref().unwrap_or_default();
+            fetch_trending_filtered(&state, &params.window, raw, &filter, mode).await?
+        }
+        None => fetch_trending_all(&state, &params, mode).await?,
+    };
+    availability::enrich(&state, &mut fetched.value["results"]).await;
+    Ok(fetched)
 }
 
-async fn movies_trending(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
-    let key = "movies:trending".to_string();
-    if let Some(cached) = state.cache.get(&key).await {
-        return Ok(Json(cached));
+async fn fetch_trending_all(
+    state: &AppState,
+    params: &TrendingAllParams,
+    mode: cache::CacheMode,
+) -> Result<cache::Fetched, ApiError> {
+    let key = format!("trending:all:{}:{}", params.window, params.page);
+    if let Some(cached) = state.cache.get(&key, mode).await {
+        return Ok(cached);
+    }
+
+    let trending = fetch_trending_page(state, &params.window, params.page).await?;
+    let mut results = Vec::with_capacity(trending.results.len());
+    for item in trending.results {
+        middleware::check_deadline()?;
+        if let Some(entry) = trending_entry(state, item).await {
+            results.push(entry);
+        }
     }
 
-    let client = &state.http;
+    let mut json = serde_json::json!({
+        "results": results,
+        "page": params.page,
+        "total_pages": trending.total_pages,
+        "window": params.window,
+    });
+    let omdb = results.iter().any(|e| e.imdb_id.is_some());
+    posters::fix_posters(state, &mut json["results"]).await;
+    annotate_trending(&mut json, omdb);
+    state.cache.insert_with_ttl(key, json.clone(), state.config().cache_ttl(cache::CacheCategory::Trending)).await;
+    Ok(cache::Fetched::miss(json))
+}
 
-    // Get trending
-    let trending_url = format!(
-        "https://api.themoviedb.org/3/trending/movie/week?api_key={}",
-        state.tmdb_key
+/// Filtro por título nas primeiras páginas da lista em alta. A lista crua
+/// do TMDB (várias páginas) fica em cache à parte, então filtros seguidos
+/// não refazem as buscas; só os itens que batem passam pelo OMDb.
+async fn fetch_trending_filtered(
+    state: &AppState,
+    window: &str,
+    raw_filter: &str,
+    filter: &filter::TitleFilter,
+    mode: cache::CacheMode,
+) -> Result<cache::Fetched, ApiError> {
+    let key = format!("trending:all:{window}:filter:{}", raw_filter.trim().to_lowercase());
+    if let Some(cached) = state.cache.get(&key, mode).await {
+        return Ok(cached);
+    }
+
+    let (items, pages_searched) = fetch_trending_expanded(state, window, mode).await?;
+    let matched: Vec<_> = items
+        .into_iter()
+        .filter(|item| matches!(item.media_type.as_str(), "movie" | "tv"))
+        .enumerate()
+        .filter(|(_, item)| {
+            item.title.as_deref().or(item.name.as_deref()).is_some_and(|t| filter.matches(t))
+        })
+        .take(MAX_FILTER_RESULTS)
+        .collect();
+    let entries = futures_util::future::join_all(matched.into_iter().map(|(i, item)| async move {
+        let mut entry = trending_entry(state, item).await?;
+        entry.rank = Some(i + 1);
+        Some(entry)
+    }))
+    .await;
+    let results: Vec<_> = entries.into_iter().flatten().collect();
+
+    let mut json = serde_json::json!({
+        "results": results,
+        "window": window,
+        "filter": raw_filter,
+        "pages_searched": pages_searched,
+    });
+    let omdb = results.iter().any(|e| e.imdb_id.is_some());
+    posters::fix_posters(state, &mut json["results"]).await;
+    annotate_trending(&mut json, omdb);
+    state.cache.insert_with_ttl(key, json.clone(), state.config().cache_ttl(cache::CacheCategory::Trending)).await;
+    Ok(cache::Fetched::miss(json))
+}
+
+/// Atribuição de `/trending/all`: a lista é do TMDB, e o OMDb entra quando
+/// resolveu o IMDb id de algum item.
+fn annotate_trending(json: &mut serde_json::Value, omdb: bool) {
+    attribution::annotate(
+        json,
+        [Some(attribution::Source::Tmdb), omdb.then_some(attribution::Source::Omdb)].into_iter().flatten(),
     );
-    let trending: TmdbList = client
-        .get(&trending_url)
-        .send()
-        .await
-        .map_err(|e| ApiError::Upstream(e.to_string()))?
-        .json()
-        .await
-        .map_err(|e| ApiError::Upstream(e.to_string()))?;
+}
+
+/// Itens crus das primeiras páginas (até `TRENDING_FILTER_MAX_PAGES`), na
+/// ordem do TMDB, e quantas páginas foram lidas.
+async fn fetch_trending_expanded(
+    state: &AppState,
+    window: &str,
+    mode: cache::CacheMode,
+) -> Result<(Vec<TmdbTrendingItem>, u32), ApiError> {
+    let key = format!("trending:all:{window}:expanded");
+    if let Some(cached) = state.cache.get(&key, mode).await {
+        let items = serde_json::from_value(cached.value["items"].clone()).unwrap_or_default();
+        let pages = cached.value["pages"].as_u64().unwrap_or_default() as u32;
+        return Ok((items, pages));
+    }
+
+    let first = fetch_trending_page(state, window, 1).await?;
+    let pages = first.total_pages.clamp(1, state.config().trending_filter_max_pages.max(1));
+    let rest = futures_util::future::join_all((2..=pages).map(|page| fetch_trending_page(state, window, page))).await;
+    let mut items = first.results;
+    for page in rest {
+        items.extend(page?.results);
+    }
+
+    let json = serde_json::json!({ "items": items, "pages": pages });
+    state.cache.insert_with_ttl(key, json, state.config().cache_ttl(cache::CacheCategory::Trending)).await;
+    Ok((items, pages))
+}
 
-    // Get now playing
-    let releases_url = format!(
-        "https://api.themoviedb.org/3/movie/now_playing?api_key={}&language=en-US&page=1",
-        state.tmdb_key
+async fn fetch_trending_page(state: &AppState, window: &str, page: u32) -> Result<TmdbTrendingPage, ApiError> {
+    let url = format!(
+        "{}/trending/all/{}?api_key={}&page={}",
+        state.config().tmdb_base_url,
+        window, state.tmdb_key, page
     );
-    let releases: TmdbList = client
-        .get(&releases_url)
+    let resp = upstream::get(state, upstream::Service::Tmdb, "/trending/all/:window", &url)
         .send()
         .await
-        .map_err(|e| ApiError::Upstream(e.to_string()))?
-        .json()
-        .await
-        .map_err(|e| ApiError::Upstream(e.to_string()))?;
+        .map_err(upstream::send_error)?;
+    upstream::json(state, resp).await
+}
 
-    // Merge lists
-    let all = trending.results.into_iter().chain(releases.results);
+/// Item do TMDB como entrada da lista, com o IMDb id resolvido pelo OMDb.
+/// Pessoas e itens sem título ficam de fora.
+async fn trending_entry(state: &AppState, item: TmdbTrendingItem) -> Option<TrendingEntry> {
+    let (kind, media_type) = match item.media_type.as_str() {
+        "movie" => (omdb::Kind::Movie, "movie"),
+        "tv" => (omdb::Kind::Series, "series"),
+        _ => return None, // person
+    };
+    let title = item.title.or(item.name).unwrap_or_default();
+    if title.is_empty() {
+        return None;
+    }
+    let date = item.release_date.or(item.first_air_date).unwrap_or_default();
+    let release_date = Some(date.clone()).filter(|d| !d.is_empty());
+    let year = date.get(..4).and_then(|y| y.parse().ok());
+
+    let omdb = omdb::resolve_by_title(state, &title, year, kind).await.ok().flatten();
+    let field = |name: &str| {
+        omdb.as_ref()
+            .and_then(|o| o.get(name))
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+    };
+    let imdb_id = field("imdbID");
+    Some(TrendingEntry {
+        poster: field("Poster")
+            .filter(|p| p != "N/A")
+            .or_else(|| item.poster_path.map(|p| format!("https://image.tmdb.org/t/p/w500{p}")))
+            .unwrap_or_default(),
+        title: field("Title").unwrap_or(title),
+        year: field("Year").unwrap_or_else(|| date.get(..4).unwrap_or_default().to_string()),
+        playable: imdb_id.is_some(),
+        imdb_id,
+        tmdb_id: item.id,
+        media_type,
+        release_date,
+        rank: None,
+    })
+}
 
-    let mut seen_ids = HashSet::new();
-    let mut combined: Vec<OmdbMovieShort> = Vec::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    for m in all {
-        let title = m.title.or(m.name).unwrap_or_default();
-        if title.is_empty() {
-            continue;
-        }
+    async fn body(resp: Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
 
-        let omdb_url = format!(
-            "https://www.omdbapi.com/?apikey={}&t={}&r=json",
-            state.api_key,
-            urlencoding::encode(&title)
-        );
-
-        if let Ok(resp) = client.get(&omdb_url).send().await {
-            if resp.status().is_success() {
-                if let Ok(omdb_data) = resp.json::<serde_json::Value>().await {
-                    if omdb_data.get("Response") != Some(&serde_json::Value::String("False".into())) {
-                        if let Some(imdb_id) = omdb_data.get("imdbID").and_then(|v| v.as_str()) {
-                            if seen_ids.contains(imdb_id) {
-                                continue; // skip duplicates
-                            }
-                            seen_ids.insert(imdb_id.to_string());
-
-                            combined.push(OmdbMovieShort {
-                                Poster: omdb_data.get("Poster").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
-                                Title: omdb_data.get("Title").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
-                                Type: omdb_data.get("Type").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
-                                Year: omdb_data.get("Year").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
-                                imdbID: imdb_id.to_string(),
-                            });
-                        }
-                    }
-                }
-            }
+    #[tokio::test]
+    async fn error_code_and_status_per_variant() {
+        let m = || "mensagem".to_string();
+        let cases = [
+            (ApiError::BadRequest(m()), StatusCode::BAD_REQUEST, "bad_request", false, None),
+            (ApiError::NotFound(m()), StatusCode::NOT_FOUND, "not_found", false, None),
+            (
+                ApiError::NoMatchingFile { message: m(), available_files: vec!["S01E01.mkv".into()] },
+                StatusCode::NOT_FOUND,
+                "not_found",
+                false,
+                None,
+            ),
+            (ApiError::Unauthorized(m()), StatusCode::UNAUTHORIZED, "unauthorized", false, None),
+            (ApiError::Forbidden(m()), StatusCode::FORBIDDEN, "forbidden", false, None),
+            (ApiError::Conflict(m()), StatusCode::CONFLICT, "conflict", false, None),
+            (ApiError::Gone(m()), StatusCode::GONE, "gone", false, None),
+            (ApiError::NotAcceptable(m()), StatusCode::NOT_ACCEPTABLE, "not_acceptable", false, None),
+            (ApiError::Upstream(m()), StatusCode::BAD_GATEWAY, "upstream_error", true, None),
+            (
+                ApiError::UpstreamFormat { upstream: "www.omdbapi.com".into(), kind: upstream::FormatError::Html },
+                StatusCode::BAD_GATEWAY,
+                "upstream_error",
+                true,
+                None,
+            ),
+            (ApiError::UpstreamTimeout(m()), StatusCode::GATEWAY_TIMEOUT, "upstream_timeout", true, None),
+            (ApiError::DeadlineExceeded, StatusCode::GATEWAY_TIMEOUT, "upstream_timeout", true, None),
+            (ApiError::RateLimited(m()), StatusCode::TOO_MANY_REQUESTS, "rate_limited", true, None),
+            (
+                ApiError::TooManyRequests { message: m(), retry_after_secs: 30 },
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limited",
+                true,
+                Some("30"),
+            ),
+            (
+                ApiError::DownloadFailed { exit_code: Some(9), reason: aria2::Reason::DiskFull, stderr_excerpt: m() },
+                StatusCode::BAD_GATEWAY,
+                "download_failed",
+                true,
+                None,
+            ),
+            (
+                ApiError::DownloadInProgress { bytes_done: Some(25), total_bytes: Some(100) },
+                StatusCode::CONFLICT,
+                "download_in_progress",
+                true,
+                Some("5"),
+            ),
+            (ApiError::StorageFull(m()), StatusCode::INSUFFICIENT_STORAGE, "storage_full", false, None),
+            (ApiError::Unavailable(m()), StatusCode::SERVICE_UNAVAILABLE, "unavailable", true, None),
+            (
+                ApiError::Overloaded { retry_after_secs: 2 },
+                StatusCode::SERVICE_UNAVAILABLE,
+                "overloaded",
+                true,
+                Some("2"),
+            ),
+            (ApiError::Storage(m()), StatusCode::INTERNAL_SERVER_ERROR, "internal", false, None),
+            (ApiError::Internal, StatusCode::INTERNAL_SERVER_ERROR, "internal", false, None),
+        ];
+        for (error, status, code, retryable, retry_after) in cases {
+            let name = format!("{error:?}");
+            assert_eq!(error.code(), code, "{name}");
+            let resp = error.into_response();
+            assert_eq!(resp.status(), status, "{name}");
+            assert_eq!(resp.headers().get(header::RETRY_AFTER).map(|v| v.to_str().unwrap()), retry_after, "{name}");
+            assert_eq!(resp.extensions().get::<middleware::ErrorCode>().map(|c| c.0), Some(code), "{name}");
+            let legacy = resp.extensions().get::<middleware::LegacyErrorBody>().unwrap().0.clone();
+            let body = body(resp).await;
+            let error = &body["error"];
+            assert_eq!((&error["code"], &error["retryable"]), (&code.into(), &retryable.into()), "{name}: {body}");
+            assert!(error["message"].as_str().is_some_and(|m| !m.is_empty()), "{name}: {body}");
+            assert!(error.get("request_id").is_some(), "{name}: {body}");
+            // o corpo antigo: a mensagem em `error`, os extras no topo
+            assert_eq!(legacy["error"], error["message"], "{name}");
         }
     }
 
-    let json = serde_json::json!({
-        "results": combined,
-        "total": combined.len().to_string(),
-        "type": "movie"
-    });
+    #[tokio::test]
+    async fn variant_details_ride_along() {
+        let resp = ApiError::DownloadInProgress { bytes_done: Some(25), total_bytes: Some(100) }.into_response();
+        let error = body(resp).await["error"].clone();
+        assert_eq!((&error["bytes_done"], &error["total_bytes"], &error["percent"]), (&25.into(), &100.into(), &25.0.into()));
+
+        let resp = ApiError::UpstreamFormat { upstream: "www.omdbapi.com".into(), kind: upstream::FormatError::Truncated }
+            .into_response();
+        let error = body(resp).await["error"].clone();
+        assert_eq!((&error["upstream"], &error["kind"]), (&"www.omdbapi.com".into(), &"truncated".into()));
+
+        let resp = ApiError::NoMatchingFile { message: "S03E07".into(), available_files: vec!["S01E01.mkv".into()] }
+            .into_response();
+        let legacy = resp.extensions().get::<middleware::LegacyErrorBody>().unwrap().0.clone();
+        assert_eq!(legacy, serde_json::json!({ "error": "S03E07", "available_files": ["S01E01.mkv"] }));
+        assert_eq!(body(resp).await["error"]["available_files"], serde_json::json!(["S01E01.mkv"]));
+
+        // na fila não é falha: só o andamento, sem `error`
+        let resp = ApiError::Queued { retry_after_secs: 3 }.into_response();
+        assert_eq!((resp.status(), resp.headers()[header::RETRY_AFTER].to_str().unwrap()), (StatusCode::ACCEPTED, "3"));
+        assert_eq!(body(resp).await, serde_json::json!({ "status": "queued", "retry_after_secs": 3 }));
+    }
 
-    state.cache.insert(key, json.clone()).await;
-    Ok(Json(json))
+    #[test]
+    fn rejections_map_to_variants() {
+        for (status, code) in [
+            (StatusCode::NOT_FOUND, "not_found"),
+            (StatusCode::NOT_ACCEPTABLE, "not_acceptable"),
+            (StatusCode::METHOD_NOT_ALLOWED, "bad_request"),
+            (StatusCode::UNPROCESSABLE_ENTITY, "bad_request"),
+            (StatusCode::PAYLOAD_TOO_LARGE, "bad_request"),
+            (StatusCode::INTERNAL_SERVER_ERROR, "internal"),
+        ] {
+            assert_eq!(ApiError::from_status(status, "rejeitado".into()).code(), code, "{status}");
+        }
+    }
 }
@@ -0,0 +1,236 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    response::IntoResponse,
+};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ApiError, AppState,
+    auth::Identity,
+    find_downloaded_file,
+    media::{self, Chapter},
+};
+
+/// Trechos que o player pode oferecer para pular.
+const KINDS: [&str; 3] = ["intro", "recap", "credits"];
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Marker {
+    kind: String,
+    start_secs: f64,
+    end_secs: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PutMarkers {
+    /// Temporada (séries); ausente ou 0 vale para o título inteiro.
+    #[serde(default)]
+    season: u32,
+    /// Perfil do usuário; ausente grava os marcadores globais (ou os do
+    /// usuário do proxy, veja `Identity::profile`).
+    #[serde(default)]
+    profile: String,
+    /// Duração do arquivo, para validar `end_secs`.
+    duration_secs: Option<f64>,
+    markers: Vec<Marker>,
+}
+
+/// `PUT /title/:imdb_id/markers` — substitui os marcadores do escopo
+/// (título/temporada, perfil ou global).
+pub async fn put_markers(
+    State(state): State<AppState>,
+    Path(imdb_id): Path<String>,
+    identity: Identity,
+    Json(req): Json<PutMarkers>,
+) -> Result<impl IntoResponse, ApiError> {
+    check_imdb_id(&imdb_id)?;
+    for (i, m) in req.markers.iter().enumerate() {
+        if !KINDS.contains(&m.kind.as_str()) {
+            return Err(ApiError::BadRequest(format!("kind inválido: {} (use intro, recap ou credits)", m.kind)));
+        }
+        if req.markers[..i].iter().any(|other| other.kind == m.kind) {
+            return Err(ApiError::BadRequest(format!("{} repetido", m.kind)));
+        }
+        if !(m.start_secs.is_finite() && m.end_secs.is_finite() && m.start_secs >= 0.0 && m.start_secs < m.end_secs) {
+            return Err(ApiError::BadRequest(format!("{}: exige 0 <= start_secs < end_secs", m.kind)));
+        }
+        if let Some(duration) = req.duration_secs
+            && m.end_secs > duration
+        {
+            return Err(ApiError::BadRequest(format!("{}: end_secs além da duração ({duration}s)", m.kind)));
+        }
+    }
+
+    let profile = identity.profile(&req.profile)?;
+    let (season, markers) = (req.season, req.markers.clone());
+    let (id, stored_profile) = (imdb_id.clone(), profile.clone());
+    state
+        .db
+        .call(move |conn| {
+            let profile = stored_profile;
+            let tx = conn.transaction()?;
+            tx.execute(
+                "DELETE FROM markers WHERE imdb_id = ?1 AND season = ?2 AND profile = ?3",
+                params![id, season, profile],
+            )?;
+            let now = unix_now();
+            for m in &markers {
+                tx.execute(
+                    "INSERT INTO markers (imdb_id, season, profile, kind, start_secs, end_secs, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![id, season, profile, m.kind, m.start_secs, m.end_secs, now],
+                )?;
+            }
+            tx.commit()
+        })
+        .await?;
+
+    Ok(Json(serde_json::json!({
+        "imdb_id": imdb_id,
+        "season": req.season,
+        "profile": Some(profile).filter(|p| !p.is_empty()),
+        "markers": req.markers,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetMarkers {
+    #[serde(default)]
+    season: u32,
+    #[serde(default)]
+    profile: String,
+    /// Arquivo baixado cujos capítulos entram na resposta.
+    filename: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ResolvedMarker {
+    kind: String,
+    start_secs: f64,
+    end_secs: f64,
+    /// `profile`, `global` ou `chapter`.
+    source: &'static str,
+}
+
+/// `GET /title/:imdb_id/markers` — um marcador por tipo, do mais específico
+/// para o mais geral: perfil antes de global, temporada antes do título.
+/// Com `filename`, inclui os capítulos do arquivo e usa os que têm nome de
+/// intro/recap/créditos onde não há marcador salvo.
+pub async fn get_markers(
+    State(state): State<AppState>,
+    Path(imdb_id): Path<String>,
+    identity: Identity,
+    Query(params): Query<GetMarkers>,
+) -> Result<impl IntoResponse, ApiError> {
+    check_imdb_id(&imdb_id)?;
+    let (id, season, profile) = (imdb_id.clone(), params.season, identity.profile(&params.profile)?);
+    let rows: Vec<(u32, String, String, f64, f64)> = state
+        .db
+        .call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT season, profile, kind, start_secs, end_secs FROM markers
+                 WHERE imdb_id = ?1 AND season IN (0, ?2) AND profile IN ('', ?3)",
+            )?;
+            stmt.query_map(params![id, season, profile], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?))
+            })?
+            .collect()
+        })
+        .await?;
+
+    let chapters = match &params.filename {
+        Some(filename) => {
+            let path = find_downloaded_file(&state.config().downloads_dir, filename)
+                .await
+                .ok_or_else(|| ApiError::NotFound(format!("{filename} não encontrado")))?;
+            media::chapters(&state, &path).await.map_err(|e| e.into_api(ApiError::Upstream))?
+        }
+        None => Vec::new(),
+    };
+
+    let mut markers = Vec::new();
+    for kind in KINDS {
+        // perfil vale mais que temporada: (perfil, temporada) > (perfil, título) > (global, temporada) > (global, título)
+        let stored = rows
+            .iter()
+            .filter(|r| r.2 == kind)
+            .max_by_key(|r| (!r.1.is_empty(), r.0 != 0));
+        let resolved = match stored {
+            Some((_, profile, _, start, end)) => Some(ResolvedMarker {
+                kind: kind.to_string(),
+                start_secs: *start,
+                end_secs: *end,
+                source: if profile.is_empty() { "global" } else { "profile" },
+            }),
+            None => chapter_marker(kind, &chapters),
+        };
+        markers.extend(resolved);
+    }
+
+    Ok(Json(serde_json::json!({
+        "imdb_id": imdb_id,
+        "season": params.season,
+        "markers": markers,
+        "chapters": chapters,
+    })))
+}
+
+/// `GET /media/chapters?filename=...` — capítulos de um arquivo baixado.
+pub async fn media_chapters(
+    State(state): State<AppState>,
+    Query(params): Query<ChaptersParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let path = find_downloaded_file(&state.config().downloads_dir, &params.filename)
+        .await
+        .ok_or_else(|| ApiError::NotFound(format!("{} não encontrado", params.filename)))?;
+    let chapters = media::chapters(&state, &path).await.map_err(|e| e.into_api(ApiError::Upstream))?;
+    Ok(Json(serde_json::json!({ "filename": params.filename, "chapters": chapters })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChaptersParams {
+    filename: String,
+}
+
+/// Capítulo cujo título indica o trecho (`Opening`, `Previously on`, `Créditos`...).
+fn chapter_marker(kind: &str, chapters: &[Chapter]) -> Option<ResolvedMarker> {
+    let names: &[&str] = match kind {
+        "intro" => &["intro", "opening", "abertura"],
+        "recap" => &["recap", "previously", "anteriormente"],
+        _ => &["credits", "ending", "créditos", "encerramento"],
+    };
+    let chapter = chapters.iter().find(|c| {
+        c.title
+            .as_deref()
+            .map(str::to_lowercase)
+            .is_some_and(|t| names.iter().any(|n| t.contains(n)))
+    })?;
+    Some(ResolvedMarker {
+        kind: kind.to_string(),
+        start_secs: chapter.start_secs,
+        end_secs: chapter.end_secs,
+        source: "chapter",
+    })
+}
+
+pub fn check_imdb_id(imdb_id: &str) -> Result<(), ApiError> {
+    let valid = imdb_id
+        .strip_prefix("tt")
+        .is_some_and(|digits| !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()));
+    if valid {
+        Ok(())
+    } else {
+        Err(ApiError::BadRequest(format!("imdb_id inválido: {imdb_id}")))
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,367 @@
+use std::{collections::HashMap, path::Path};
+
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{Path as UrlPath, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use tokio::{fs, process::Command};
+use tracing::warn;
+
+use crate::{ApiError, AppState, downloads, find_downloaded_file, media_queue::JobError};
+
+/// Largura aceita em `/media/thumbnail`.
+const THUMBNAIL_MIN_WIDTH: u32 = 64;
+const THUMBNAIL_MAX_WIDTH: u32 = 1280;
+
+/// O que o ffprobe revela sobre um arquivo, já normalizado para os nomes
+/// usados nos perfis (`mkv`, `h265`, `hdr10`...).
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MediaInfo {
+    pub container: Option<String>,
+    pub video_codec: Option<String>,
+    pub audio_codecs: Vec<String>,
+    pub height: Option<u32>,
+    pub bitrate_kbps: Option<u64>,
+    pub hdr: Vec<String>,
+    pub duration_secs: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    height: Option<u32>,
+    color_transfer: Option<String>,
+    #[serde(default)]
+    side_data_list: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeFormat {
+    format_name: Option<String>,
+    bit_rate: Option<String>,
+    duration: Option<String>,
+}
+
+/// Roda o ffprobe no arquivo, pela fila de mídia.
+pub async fn probe(state: &AppState, path: &Path) -> Result<MediaInfo, JobError> {
+    let path = path.to_path_buf();
+    let key = format!("probe:{}", path.display());
+    state.media_jobs.run(&state.config(), key, async move { run_probe(&path).await }).await
+}
+
+async fn run_probe(path: &Path) -> Result<MediaInfo, String> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-print_format", "json", "-show_format", "-show_streams"])
+        .arg(path)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("ffprobe indisponível: {e}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("ffprobe falhou: {}", stderr.trim()));
+    }
+    let parsed: ProbeOutput = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("saída do ffprobe inválida: {e}"))?;
+    Ok(MediaInfo::from_probe(path, parsed))
+}
+
+/// Capítulo do arquivo, como o ffprobe reporta.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Chapter {
+    pub start_secs: f64,
+    pub end_secs: f64,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChaptersOutput {
+    #[serde(default)]
+    chapters: Vec<ProbeChapter>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeChapter {
+    start_time: Option<String>,
+    end_time: Option<String>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+/// Capítulos do arquivo (vazio quando o release não traz nenhum), pela
+/// fila de mídia.
+pub async fn chapters(state: &AppState, path: &Path) -> Result<Vec<Chapter>, JobError> {
+    let path = path.to_path_buf();
+    let key = format!("chapters:{}", path.display());
+    state.media_jobs.run(&state.config(), key, async move { run_chapters(&path).await }).await
+}
+
+async fn run_chapters(path: &Path) -> Result<Vec<Chapter>, String> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-print_format", "json", "-show_chapters"])
+        .arg(path)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("ffprobe indisponível: {e}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("ffprobe falhou: {}", stderr.trim()));
+    }
+    let parsed: ChaptersOutput = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("saída do ffprobe inválida: {e}"))?;
+    Ok(parsed
+        .chapters
+        .into_iter()
+        .filter_map(|c| {
+            Some(Chapter {
+                start_secs: c.start_time?.parse().ok()?,
+                end_secs: c.end_time?.parse().ok()?,
+                title: c.tags.get("title").cloned(),
+            })
+        })
+        .collect())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InfoParams {
+    /// Arquivo dentro do download; sem ele, o maior já concluído.
+    filename: Option<String>,
+}
+
+/// `GET /media/:id/info?filename=` — o que o `/stream` sabe de um arquivo
+/// do download `id` (o infohash): o Content-Type com que ele é servido
+/// (`detected_by`: `magic`, `extension` ou `unknown`) e contêiner e codecs
+/// pelo ffprobe. Sem o ffprobe, o contêiner vem do Content-Type e o erro,
+/// em `probe_error`.
+pub async fn media_info(
+    State(state): State<AppState>,
+    UrlPath(id): UrlPath<String>,
+    Query(params): Query<InfoParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let job_id = downloads::parse_job_id(&id)?;
+    let path = downloads::job_file(&state, &job_id, params.filename.as_deref()).await?;
+    let handle = state
+        .file_handles
+        .open(&path, state.config().file_handle_cache_size)
+        .await
+        .map_err(|e| ApiError::Storage(format!("falha ao abrir {}: {e}", path.display())))?;
+    let detected = handle.content_type;
+    let (info, probe_error) = match probe(&state, &path).await {
+        Ok(info) => (info, None),
+        Err(JobError::Failed(e)) => (MediaInfo::default(), Some(e)),
+        Err(queued) => return Err(queued.into_api(ApiError::Unavailable)),
+    };
+    let container = info.container.clone().or_else(|| detected.container().map(str::to_string));
+
+    let mut body = serde_json::to_value(&info).map_err(|_| ApiError::Internal)?;
+    body["id"] = job_id.into();
+    body["filename"] = path.file_name().unwrap_or_default().to_string_lossy().into();
+    body["size_bytes"] = handle.size.into();
+    body["content_type"] = detected.mime.into();
+    body["detected_by"] = serde_json::to_value(detected.by).map_err(|_| ApiError::Internal)?;
+    body["container"] = container.into();
+    body["probe_error"] = probe_error.into();
+    Ok(Json(body))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ThumbnailParams {
+    filename: String,
+    /// Segundo do quadro; se o vídeo for mais curto, vale o primeiro quadro.
+    #[serde(default = "default_thumbnail_at")]
+    at: u32,
+    #[serde(default = "default_thumbnail_width")]
+    width: u32,
+}
+
+fn default_thumbnail_at() -> u32 {
+    60
+}
+
+fn default_thumbnail_width() -> u32 {
+    320
+}
+
+/// `GET /media/thumbnail?filename=&at=&width=` — quadro JPEG de um arquivo
+/// baixado, pelo ffmpeg na fila de mídia. Fica em cache ao lado do arquivo
+/// (`.<nome>.<at>s.<width>.jpg`); pedidos iguais ao mesmo tempo dividem um
+/// só ffmpeg, e com a fila cheia a resposta é `202` com `Retry-After`.
+pub async fn thumbnail(
+    State(state): State<AppState>,
+    Query(params): Query<ThumbnailParams>,
+) -> Result<Response, ApiError> {
+    if !(THUMBNAIL_MIN_WIDTH..=THUMBNAIL_MAX_WIDTH).contains(&params.width) {
+        return Err(ApiError::BadRequest(format!(
+            "width deve estar entre {THUMBNAIL_MIN_WIDTH} e {THUMBNAIL_MAX_WIDTH}"
+        )));
+    }
+    let source = find_downloaded_file(&state.config().downloads_dir, &params.filename)
+        .await
+        .ok_or_else(|| ApiError::NotFound(format!("{} não encontrado", params.filename)))?;
+    let name = source.file_name().unwrap_or_default().to_string_lossy();
+    let cached = source.with_file_name(format!(".{name}.{}s.{}.jpg", params.at, params.width));
+
+    let jpeg = match read_thumbnail(&cached, &source).await {
+        Some(jpeg) => jpeg,
+        None => {
+            let key = format!("thumbnail:{}", cached.display());
+            let (at, width) = (params.at, params.width);
+            state
+                .media_jobs
+                .run(&state.config(), key, async move {
+                    // um job igual pode ter terminado entre a leitura acima e a fila
+                    if let Some(jpeg) = read_thumbnail(&cached, &source).await {
+                        return Ok(jpeg);
+                    }
+                    let mut jpeg = extract_frame(&source, at, width).await?;
+                    if jpeg.is_empty() && at > 0 {
+                        jpeg = extract_frame(&source, 0, width).await?;
+                    }
+                    if jpeg.is_empty() {
+                        return Err("o ffmpeg não extraiu nenhum quadro".into());
+                    }
+                    let tmp = cached.with_extension("jpg.tmp");
+                    let saved = async {
+                        fs::write(&tmp, &jpeg).await?;
+                        fs::rename(&tmp, &cached).await
+                    };
+                    if let Err(e) = saved.await {
+                        warn!(path = %cached.display(), "falha ao guardar a miniatura: {e}");
+                    }
+                    Ok(Bytes::from(jpeg))
+                })
+                .await
+                .map_err(|e| e.into_api(ApiError::Unavailable))?
+        }
+    };
+    Ok((
+        [(header::CONTENT_TYPE, "image/jpeg"), (header::CACHE_CONTROL, "max-age=86400")],
+        jpeg,
+    )
+        .into_response())
+}
+
+/// Miniatura em cache, se for mais nova que o arquivo.
+async fn read_thumbnail(cached: &Path, source: &Path) -> Option<Bytes> {
+    let built = fs::metadata(cached).await.ok()?.modified().ok()?;
+    let changed = fs::metadata(source).await.ok()?.modified().ok()?;
+    if built < changed {
+        return None;
+    }
+    fs::read(cached).await.ok().map(Bytes::from)
+}
+
+/// Um quadro em `at` segundos, reduzido a `width` de largura (vazio se o
+/// vídeo acabar antes).
+async fn extract_frame(source: &Path, at: u32, width: u32) -> Result<Vec<u8>, String> {
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-nostdin", "-ss", &at.to_string(), "-i"])
+        .arg(source)
+        .args(["-frames:v", "1", "-vf", &format!("scale={width}:-2"), "-f", "image2", "-c:v", "mjpeg", "pipe:1"])
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("ffmpeg indisponível: {e}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("ffmpeg falhou: {}", stderr.trim()));
+    }
+    Ok(output.stdout)
+}
+
+impl MediaInfo {
+    fn from_probe(path: &Path, probe: ProbeOutput) -> Self {
+        let mut info = MediaInfo {
+            container: container_from_extension(path),
+            ..Default::default()
+        };
+        if let Some(format) = &probe.format {
+            if info.container.is_none() {
+                info.container = format.format_name.as_deref().and_then(container_from_format);
+            }
+            info.bitrate_kbps = format.bit_rate.as_deref().and_then(|b| b.parse::<u64>().ok()).map(|b| b / 1000);
+            info.duration_secs = format.duration.as_deref().and_then(|d| d.parse().ok());
+        }
+
+        for stream in probe.streams {
+            match stream.codec_type.as_deref() {
+                Some("video") if info.video_codec.is_none() => {
+                    info.video_codec = stream.codec_name.as_deref().map(normalize_codec);
+                    info.height = stream.height;
+                    match stream.color_transfer.as_deref() {
+                        Some("smpte2084") => info.hdr.push("hdr10".into()),
+                        Some("arib-std-b67") => info.hdr.push("hlg".into()),
+                        _ => {}
+                    }
+                    let dovi = stream.side_data_list.iter().any(|sd| {
+                        sd.get("side_data_type")
+                            .and_then(|t| t.as_str())
+                            .is_some_and(|t| t.contains("DOVI"))
+                    });
+                    if dovi {
+                        info.hdr.push("dv".into());
+                    }
+                }
+                Some("audio") => {
+                    if let Some(codec) = stream.codec_name.as_deref().map(normalize_codec)
+                        && !info.audio_codecs.contains(&codec)
+                    {
+                        info.audio_codecs.push(codec);
+                    }
+                }
+                _ => {}
+            }
+        }
+        info
+    }
+}
+
+pub fn container_from_extension(path: &Path) -> Option<String> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    let container = match ext.as_str() {
+        "mkv" => "mkv",
+        "mp4" | "m4v" => "mp4",
+        "webm" => "webm",
+        "avi" => "avi",
+        "ts" | "m2ts" => "ts",
+        "mov" => "mov",
+        "wmv" => "wmv",
+        _ => return None,
+    };
+    Some(container.to_string())
+}
+
+fn container_from_format(format: &str) -> Option<String> {
+    let container = if format.contains("matroska") {
+        "mkv"
+    } else if format.contains("mp4") {
+        "mp4"
+    } else if format.contains("avi") {
+        "avi"
+    } else if format.contains("mpegts") {
+        "ts"
+    } else {
+        return None;
+    };
+    Some(container.to_string())
+}
+
+fn normalize_codec(codec: &str) -> String {
+    match codec {
+        "hevc" => "h265".into(),
+        other => other.to_ascii_lowercase(),
+    }
+}
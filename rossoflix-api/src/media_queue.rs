@@ -0,0 +1,243 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    future::Future,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use serde::Serialize;
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
+
+use crate::{ApiError, config::Config};
+
+/// Teto do `Retry-After` sugerido quando a fila está cheia ou o job demora.
+const MAX_RETRY_AFTER_SECS: u64 = 30;
+
+type Output = Result<Arc<dyn Any + Send + Sync>, String>;
+type Job = Shared<BoxFuture<'static, Output>>;
+
+/// Fila dos subprocessos de mídia (ffmpeg/ffprobe: miniaturas, probes,
+/// capítulos, índice de pacotes). No máximo `MEDIA_WORKERS` rodam ao mesmo
+/// tempo; pedidos iguais (mesma chave) enquanto o job está na fila ou
+/// rodando esperam o mesmo resultado em vez de abrir outro processo.
+#[derive(Clone)]
+pub struct MediaQueue {
+    workers: Arc<Semaphore>,
+    worker_count: usize,
+    inflight: Arc<Mutex<HashMap<String, Job>>>,
+    counters: Arc<Counters>,
+}
+
+#[derive(Default)]
+struct Counters {
+    submitted: AtomicU64,
+    /// Pedidos que pegaram carona num job igual já na fila.
+    coalesced: AtomicU64,
+    completed: AtomicU64,
+    failed: AtomicU64,
+    timed_out: AtomicU64,
+    /// Recusados com a fila cheia.
+    rejected: AtomicU64,
+    /// Pedidos que desistiram de esperar (o job segue em segundo plano).
+    gave_up: AtomicU64,
+    running: AtomicU64,
+    wait_ms_total: AtomicU64,
+    wait_ms_max: AtomicU64,
+    run_ms_total: AtomicU64,
+    run_ms_max: AtomicU64,
+}
+
+/// Por que o pedido não tem o resultado do job.
+#[derive(Debug)]
+pub enum JobError {
+    /// Fila cheia, ou o job não terminou dentro de `MEDIA_WAIT_SECS`: tente
+    /// de novo em tantos segundos.
+    Queued(u64),
+    /// O job rodou e falhou (ou estourou `MEDIA_JOB_TIMEOUT_SECS`).
+    Failed(String),
+}
+
+impl JobError {
+    /// `202` para `Queued`; a falha vira o erro que o chamador escolher.
+    pub fn into_api(self, failed: impl FnOnce(String) -> ApiError) -> ApiError {
+        match self {
+            JobError::Queued(retry_after_secs) => ApiError::Queued { retry_after_secs },
+            JobError::Failed(e) => failed(e),
+        }
+    }
+}
+
+impl std::fmt::Display for JobError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobError::Queued(secs) => write!(f, "fila de mídia ocupada, tente em {secs}s"),
+            JobError::Failed(e) => f.write_str(e),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Stats {
+    workers: usize,
+    /// Jobs distintos na fila ou rodando.
+    depth: usize,
+    running: u64,
+    submitted: u64,
+    coalesced: u64,
+    completed: u64,
+    failed: u64,
+    timed_out: u64,
+    rejected: u64,
+    gave_up: u64,
+    /// Espera por um worker e duração dos jobs (ms): média e maior.
+    wait_ms: Latency,
+    run_ms: Latency,
+}
+
+#[derive(Serialize)]
+struct Latency {
+    mean: u64,
+    max: u64,
+}
+
+impl MediaQueue {
+    pub fn new(workers: usize) -> Self {
+        MediaQueue {
+            workers: Arc::new(Semaphore::new(workers.max(1))),
+            worker_count: workers.max(1),
+            inflight: Default::default(),
+            counters: Default::default(),
+        }
+    }
+
+    /// Roda `job` na fila sob `key` e espera o resultado por até
+    /// `MEDIA_WAIT_SECS`. Um job com a mesma chave já na fila é reaproveitado.
+    /// O job roda numa task própria: quem desiste de esperar não o cancela.
+    pub async fn run<T, F>(&self, config: &Config, key: String, job: F) -> Result<T, JobError>
+    where
+        T: Clone + Send + Sync + 'static,
+        F: Future<Output = Result<T, String>> + Send + 'static,
+    {
+        let shared = {
+            let mut inflight = self.inflight.lock().unwrap();
+            match inflight.get(&key) {
+                Some(shared) => {
+                    self.counters.coalesced.fetch_add(1, Ordering::Relaxed);
+                    debug!(key, "job de mídia reaproveitado");
+                    shared.clone()
+                }
+                None => {
+                    if inflight.len() >= config.media_queue_max {
+                        self.counters.rejected.fetch_add(1, Ordering::Relaxed);
+                        return Err(JobError::Queued(self.retry_after(inflight.len())));
+                    }
+                    self.counters.submitted.fetch_add(1, Ordering::Relaxed);
+                    let shared = self.spawn(key.clone(), Duration::from_secs(config.media_job_timeout_secs), job);
+                    inflight.insert(key, shared.clone());
+                    shared
+                }
+            }
+        };
+
+        let depth = self.inflight.lock().unwrap().len();
+        match tokio::time::timeout(Duration::from_secs(config.media_wait_secs), shared).await {
+            Ok(Ok(value)) => value
+                .downcast::<T>()
+                .map(|value| (*value).clone())
+                .map_err(|_| JobError::Failed("job de mídia com resultado de outro tipo".into())),
+            Ok(Err(e)) => Err(JobError::Failed(e)),
+            Err(_) => {
+                self.counters.gave_up.fetch_add(1, Ordering::Relaxed);
+                Err(JobError::Queued(self.retry_after(depth)))
+            }
+        }
+    }
+
+    fn spawn<T, F>(&self, key: String, timeout: Duration, job: F) -> Job
+    where
+        T: Send + Sync + 'static,
+        F: Future<Output = Result<T, String>> + Send + 'static,
+    {
+        let queue = self.clone();
+        let queued_at = Instant::now();
+        let handle = tokio::spawn(async move {
+            let _permit = queue.workers.acquire().await;
+            let counters = &queue.counters;
+            record(&counters.wait_ms_total, &counters.wait_ms_max, queued_at.elapsed());
+            counters.running.fetch_add(1, Ordering::Relaxed);
+            let started = Instant::now();
+            // estourar o prazo solta o futuro: os comandos usam `kill_on_drop`
+            let result = match tokio::time::timeout(timeout, job).await {
+                Ok(Ok(value)) => {
+                    counters.completed.fetch_add(1, Ordering::Relaxed);
+                    Ok(Arc::new(value) as Arc<dyn Any + Send + Sync>)
+                }
+                Ok(Err(e)) => {
+                    counters.failed.fetch_add(1, Ordering::Relaxed);
+                    Err(e)
+                }
+                Err(_) => {
+                    counters.timed_out.fetch_add(1, Ordering::Relaxed);
+                    warn!(key, secs = timeout.as_secs(), "job de mídia estourou o prazo");
+                    Err(format!("job de mídia passou de {}s", timeout.as_secs()))
+                }
+            };
+            record(&counters.run_ms_total, &counters.run_ms_max, started.elapsed());
+            counters.running.fetch_sub(1, Ordering::Relaxed);
+            queue.inflight.lock().unwrap().remove(&key);
+            result
+        });
+        async move { handle.await.unwrap_or_else(|e| Err(format!("job de mídia abortou: {e}"))) }
+            .boxed()
+            .shared()
+    }
+
+    /// Estimativa de quando a fila terá vaga: os jobs à frente divididos
+    /// pelos workers, pela duração média até agora.
+    fn retry_after(&self, depth: usize) -> u64 {
+        let c = &self.counters;
+        let done = c.completed.load(Ordering::Relaxed) + c.failed.load(Ordering::Relaxed) + c.timed_out.load(Ordering::Relaxed);
+        let mean_ms = self.counters.run_ms_total.load(Ordering::Relaxed) / done.max(1);
+        let rounds = depth.div_ceil(self.worker_count) as u64;
+        (rounds * mean_ms / 1000).clamp(1, MAX_RETRY_AFTER_SECS)
+    }
+
+    pub fn stats(&self) -> Stats {
+        let c = &self.counters;
+        let load = |a: &AtomicU64| a.load(Ordering::Relaxed);
+        let started = load(&c.completed) + load(&c.failed) + load(&c.timed_out) + load(&c.running);
+        let finished = load(&c.completed) + load(&c.failed) + load(&c.timed_out);
+        Stats {
+            workers: self.worker_count,
+            depth: self.inflight.lock().unwrap().len(),
+            running: load(&c.running),
+            submitted: load(&c.submitted),
+            coalesced: load(&c.coalesced),
+            completed: load(&c.completed),
+            failed: load(&c.failed),
+            timed_out: load(&c.timed_out),
+            rejected: load(&c.rejected),
+            gave_up: load(&c.gave_up),
+            wait_ms: Latency {
+                mean: load(&c.wait_ms_total) / started.max(1),
+                max: load(&c.wait_ms_max),
+            },
+            run_ms: Latency {
+                mean: load(&c.run_ms_total) / finished.max(1),
+                max: load(&c.run_ms_max),
+            },
+        }
+    }
+}
+
+fn record(total: &AtomicU64, max: &AtomicU64, elapsed: Duration) {
+    let ms = elapsed.as_millis() as u64;
+    total.fetch_add(ms, Ordering::Relaxed);
+    max.fetch_max(ms, Ordering::Relaxed);
+}
@@ -0,0 +1,388 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use futures_util::future::{BoxFuture, join_all};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{
+    ApiError, AppState,
+    attribution::{self, Source},
+    cache::{CacheCategory, CacheMode, Fetched},
+    fetch_detail,
+    markers::check_imdb_id,
+    upstream,
+};
+
+/// Provedores conhecidos, para validar `METADATA_PRIORITY`.
+pub const PROVIDERS: [&str; 2] = ["tmdb", "omdb"];
+
+/// Identificadores do título que os provedores recebem.
+#[derive(Debug, Clone)]
+pub struct TitleIds {
+    pub imdb_id: String,
+}
+
+/// Campos que um provedor conseguiu preencher, já com os nomes do detalhe
+/// combinado (`title`, `year`, `plot`, `runtime_minutes`, `genres`,
+/// `rating`, `poster`, `release_date`, `kind`...).
+#[derive(Debug, Default)]
+pub struct Patch {
+    pub fields: Map<String, Value>,
+}
+
+impl Patch {
+    /// Ignora valores vazios: quem não sabe não disputa o campo.
+    fn set(&mut self, field: &str, value: impl Into<Value>) {
+        let value = value.into();
+        let empty = match &value {
+            Value::Null => true,
+            Value::String(s) => s.is_empty(),
+            Value::Array(a) => a.is_empty(),
+            _ => false,
+        };
+        if !empty {
+            self.fields.insert(field.to_string(), value);
+        }
+    }
+}
+
+/// Fonte de metadados do detalhe combinado (`GET /title/:imdb_id`).
+pub trait Enricher: Send + Sync {
+    /// Nome usado em `METADATA_PRIORITY` e na atribuição de cada campo.
+    fn source(&self) -> &'static str;
+
+    fn enrich<'a>(
+        &'a self,
+        state: &'a AppState,
+        ids: &'a TitleIds,
+        mode: CacheMode,
+    ) -> BoxFuture<'a, Result<Patch, ApiError>>;
+}
+
+/// Provedores em ordem de preferência: em cada campo vence o primeiro da
+/// lista que tiver valor.
+#[derive(Clone)]
+pub struct Pipeline(Arc<[Box<dyn Enricher>]>);
+
+impl Pipeline {
+    /// A partir de `METADATA_PRIORITY` (nomes já validados pela config).
+    pub fn new(priority: &[String]) -> Self {
+        let providers: Vec<Box<dyn Enricher>> = priority
+            .iter()
+            .filter_map(|name| -> Option<Box<dyn Enricher>> {
+                match name.as_str() {
+                    "tmdb" => Some(Box::new(Tmdb)),
+                    "omdb" => Some(Box::new(Omdb)),
+                    _ => None,
+                }
+            })
+            .collect();
+        Pipeline(providers.into())
+    }
+
+    /// Consulta todos os provedores em paralelo e junta os campos pela
+    /// prioridade, registrando em `sources` de onde veio cada um. Provedor
+    /// com erro só fica de fora (aparece em `providers`); `502` se nenhum
+    /// responder.
+    pub async fn detail(&self, state: &AppState, ids: &TitleIds, mode: CacheMode) -> Result<Value, ApiError> {
+        let patches = join_all(self.0.iter().map(|p| p.enrich(state, ids, mode))).await;
+        merge(ids, self.0.iter().map(|p| p.source()).zip(patches))
+    }
+}
+
+/// Junta as respostas dos provedores, já na ordem de prioridade: em cada
+/// campo fica o primeiro valor, e `sources` guarda quem o deu.
+fn merge(
+    ids: &TitleIds,
+    patches: impl IntoIterator<Item = (&'static str, Result<Patch, ApiError>)>,
+) -> Result<Value, ApiError> {
+    let mut detail = Map::new();
+    let mut sources = Map::new();
+    let mut providers = Vec::new();
+    let mut last_error = None;
+    for (source, patch) in patches {
+        match patch {
+            Ok(patch) => {
+                providers.push(serde_json::json!({
+                    "source": source,
+                    "ok": true,
+                    "fields": patch.fields.len(),
+                }));
+                for (field, value) in patch.fields {
+                    if !detail.contains_key(&field) {
+                        sources.insert(field.clone(), source.into());
+                        detail.insert(field, value);
+                    }
+                }
+            }
+            Err(e) => {
+                providers.push(serde_json::json!({
+                    "source": source,
+                    "ok": false,
+                    "code": e.code(),
+                    "error": e.to_string(),
+                }));
+                last_error = Some(e);
+            }
+        }
+    }
+    if detail.is_empty() {
+        return Err(last_error.unwrap_or_else(|| ApiError::NotFound(format!("{} sem metadados", ids.imdb_id))));
+    }
+    let used: Vec<Source> = sources.values().filter_map(|tag| tag.as_str().and_then(Source::from_tag)).collect();
+    let mut value = serde_json::json!({
+        "imdb_id": ids.imdb_id,
+        "detail": detail,
+        "sources": sources,
+        "providers": providers,
+    });
+    attribution::annotate(&mut value, used);
+    Ok(value)
+}
+
+/// `GET /title/:imdb_id` — detalhe combinado de todos os provedores, com a
+/// fonte de cada campo em `sources` (ordem em `METADATA_PRIORITY`) e, em
+/// `attribution`, só os provedores que ganharam algum campo.
+pub async fn title_detail(
+    State(state): State<AppState>,
+    mode: CacheMode,
+    Path(imdb_id): Path<String>,
+) -> Result<Fetched, ApiError> {
+    check_imdb_id(&imdb_id)?;
+    let ids = TitleIds { imdb_id };
+    let value = state.metadata.detail(&state, &ids, mode).await?;
+    Ok(Fetched::miss(value))
+}
+
+struct Omdb;
+
+impl Enricher for Omdb {
+    fn source(&self) -> &'static str {
+        "omdb"
+    }
+
+    fn enrich<'a>(
+        &'a self,
+        state: &'a AppState,
+        ids: &'a TitleIds,
+        mode: CacheMode,
+    ) -> BoxFuture<'a, Result<Patch, ApiError>> {
+        Box::pin(async move {
+            let body = fetch_detail(state, &ids.imdb_id, mode).await?.value;
+            let text = |field: &str| {
+                body.get(field)
+                    .and_then(|v| v.as_str())
+                    .filter(|v| *v != "N/A")
+                    .map(str::to_string)
+            };
+            let mut patch = Patch::default();
+            patch.set("title", text("Title"));
+            patch.set("year", text("Year").and_then(|y| y.get(..4)?.parse::<i32>().ok()));
+            patch.set("plot", text("Plot"));
+            patch.set(
+                "runtime_minutes",
+                text("Runtime").and_then(|r| r.trim_end_matches(" min").parse::<u32>().ok()),
+            );
+            patch.set(
+                "genres",
+                text("Genre")
+                    .map(|g| g.split(',').map(|s| s.trim().to_string()).collect::<Vec<_>>())
+                    .unwrap_or_default(),
+            );
+            patch.set("rating", text("imdbRating").and_then(|r| r.parse::<f64>().ok()));
+            patch.set("poster", text("Poster"));
+            patch.set("kind", text("Type"));
+            patch.set("director", text("Director"));
+            patch.set("actors", text("Actors"));
+            Ok(patch)
+        })
+    }
+}
+
+struct Tmdb;
+
+#[derive(Debug, Deserialize)]
+struct TmdbFind {
+    #[serde(default)]
+    movie_results: Vec<TmdbId>,
+    #[serde(default)]
+    tv_results: Vec<TmdbId>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbId {
+    id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbDetail {
+    title: Option<String>,
+    name: Option<String>,
+    overview: Option<String>,
+    release_date: Option<String>,
+    first_air_date: Option<String>,
+    runtime: Option<u32>,
+    #[serde(default)]
+    episode_run_time: Vec<u32>,
+    #[serde(default)]
+    genres: Vec<TmdbGenre>,
+    vote_average: Option<f64>,
+    poster_path: Option<String>,
+    tagline: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbGenre {
+    name: String,
+}
+
+impl Enricher for Tmdb {
+    fn source(&self) -> &'static str {
+        "tmdb"
+    }
+
+    fn enrich<'a>(
+        &'a self,
+        state: &'a AppState,
+        ids: &'a TitleIds,
+        mode: CacheMode,
+    ) -> BoxFuture<'a, Result<Patch, ApiError>> {
+        Box::pin(async move {
+            let url = format!(
+                "{}/find/{}?api_key={}&external_source=imdb_id",
+                state.config().tmdb_base_url,
+                urlencoding::encode(&ids.imdb_id),
+                state.tmdb_key
+            );
+            let key = format!("tmdb:find:{}", ids.imdb_id);
+            let detail_cache = state.cache.with_ttl(state.config().cache_ttl(CacheCategory::Detail));
+            let found: TmdbFind =
+                upstream::cached_json(state, &detail_cache, upstream::Service::Tmdb, "/find/:imdb_id", key, &url, mode)
+                    .await?;
+            let (kind, id) = match (found.movie_results.first(), found.tv_results.first()) {
+                (Some(movie), _) => ("movie", movie.id),
+                (None, Some(tv)) => ("tv", tv.id),
+                (None, None) => return Ok(Patch::default()),
+            };
+
+            let url = format!("{}/{kind}/{id}?api_key={}", state.config().tmdb_base_url, state.tmdb_key);
+            let key = format!("tmdb:{kind}:{id}:detail");
+            let detail: TmdbDetail =
+                upstream::cached_json(state, &detail_cache, upstream::Service::Tmdb, "/:kind/:id", key, &url, mode).await?;
+
+            let date = detail.release_date.or(detail.first_air_date).filter(|d| !d.is_empty());
+            let mut patch = Patch::default();
+            patch.set("tmdb_id", id);
+            patch.set("title", detail.title.or(detail.name));
+            patch.set("year", date.as_deref().and_then(|d| d.get(..4)?.parse::<i32>().ok()));
+            patch.set("release_date", date);
+            patch.set("plot", detail.overview);
+            patch.set("tagline", detail.tagline);
+            patch.set("runtime_minutes", detail.runtime.or(detail.episode_run_time.first().copied()).filter(|r| *r > 0));
+            patch.set("genres", detail.genres.into_iter().map(|g| g.name).collect::<Vec<_>>());
+            // sem votos o TMDB devolve 0, que não é nota
+            patch.set("rating", detail.vote_average.filter(|r| *r > 0.0));
+            patch.set("poster", detail.poster_path.map(|p| format!("https://image.tmdb.org/t/p/w500{p}")));
+            patch.set("kind", if kind == "movie" { "movie" } else { "series" });
+            Ok(patch)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn ids() -> TitleIds {
+        TitleIds { imdb_id: "tt1160419".into() }
+    }
+
+    fn patch(fields: Value) -> Result<Patch, ApiError> {
+        let mut patch = Patch::default();
+        for (field, value) in fields.as_object().unwrap() {
+            patch.set(field, value.clone());
+        }
+        Ok(patch)
+    }
+
+    fn tmdb() -> Result<Patch, ApiError> {
+        patch(json!({ "title": "Duna", "year": 2021, "rating": 7.8, "tagline": "", "genres": ["Ficção científica"] }))
+    }
+
+    fn omdb() -> Result<Patch, ApiError> {
+        patch(json!({ "title": "Dune", "year": 2021, "rating": 8.0, "genres": [], "director": "Denis Villeneuve" }))
+    }
+
+    fn credited(value: &Value) -> Vec<&str> {
+        value["attribution"].as_array().unwrap().iter().map(|c| c["source"].as_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn priority_decides_conflicting_fields() {
+        let merged = merge(&ids(), [("tmdb", tmdb()), ("omdb", omdb())]).unwrap();
+        assert_eq!(
+            merged["detail"],
+            json!({
+                "title": "Duna", "year": 2021, "rating": 7.8,
+                "genres": ["Ficção científica"], "director": "Denis Villeneuve",
+            })
+        );
+        assert_eq!(
+            merged["sources"],
+            json!({ "title": "tmdb", "year": "tmdb", "rating": "tmdb", "genres": "tmdb", "director": "omdb" })
+        );
+        assert_eq!(credited(&merged), ["tmdb", "omdb"]);
+
+        // a ordem inversa troca quem vence; valores vazios nunca disputam
+        let merged = merge(&ids(), [("omdb", omdb()), ("tmdb", tmdb())]).unwrap();
+        assert_eq!((&merged["detail"]["title"], &merged["sources"]["title"]), (&json!("Dune"), &json!("omdb")));
+        assert_eq!((&merged["detail"]["rating"], &merged["sources"]["rating"]), (&json!(8.0), &json!("omdb")));
+        assert_eq!(merged["sources"]["genres"], "tmdb");
+        assert!(merged["detail"].get("tagline").is_none());
+        assert_eq!(merged["providers"], json!([
+            { "source": "omdb", "ok": true, "fields": 4 },
+            { "source": "tmdb", "ok": true, "fields": 4 },
+        ]));
+    }
+
+    #[test]
+    fn attribution_only_credits_winning_sources() {
+        let merged = merge(&ids(), [("tmdb", tmdb()), ("omdb", patch(json!({ "title": "Dune", "year": 2021 })))]).unwrap();
+        assert_eq!(credited(&merged), ["tmdb"]);
+        assert_eq!(merged["providers"][1], json!({ "source": "omdb", "ok": true, "fields": 2 }));
+    }
+
+    #[test]
+    fn missing_providers_fall_through() {
+        let timeout = || Err(ApiError::UpstreamTimeout("api.themoviedb.org".into()));
+        let merged = merge(&ids(), [("tmdb", timeout()), ("omdb", omdb())]).unwrap();
+        assert_eq!(merged["detail"]["title"], "Dune");
+        assert!(merged["sources"].as_object().unwrap().values().all(|s| s == "omdb"));
+        assert_eq!(credited(&merged), ["omdb"]);
+        assert_eq!((&merged["providers"][0]["ok"], &merged["providers"][0]["code"]), (&json!(false), &json!("upstream_timeout")));
+
+        // sem o título no TMDB: resposta vazia, não erro
+        let merged = merge(&ids(), [("tmdb", Ok(Patch::default())), ("omdb", omdb())]).unwrap();
+        assert_eq!(merged["providers"][0], json!({ "source": "tmdb", "ok": true, "fields": 0 }));
+
+        // ninguém responde: o último erro; ninguém sabe nada: 404
+        let failed = merge(&ids(), [("tmdb", timeout()), ("omdb", Err(ApiError::Upstream("omdb".into())))]);
+        assert!(matches!(failed, Err(ApiError::Upstream(_))));
+        let empty = merge(&ids(), [("tmdb", Ok(Patch::default())), ("omdb", patch(json!({ "title": "" })))]);
+        assert!(matches!(empty, Err(ApiError::NotFound(_))));
+    }
+
+    #[test]
+    fn pipeline_follows_the_configured_order() {
+        let names = |priority: &[&str]| {
+            let priority: Vec<String> = priority.iter().map(|p| p.to_string()).collect();
+            Pipeline::new(&priority).0.iter().map(|p| p.source()).collect::<Vec<_>>()
+        };
+        assert_eq!(names(&["tmdb", "omdb"]), ["tmdb", "omdb"]);
+        assert_eq!(names(&["omdb", "tmdb"]), ["omdb", "tmdb"]);
+        assert_eq!(names(&["omdb"]), ["omdb"]);
+    }
+}
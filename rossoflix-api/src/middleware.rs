@@ -0,0 +1,272 @@
+use std::{
+    any::Any,
+    panic::AssertUnwindSafe,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    body::{Body, to_bytes},
+    extract::{Request, State},
+    http::{HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::FutureExt;
+use tokio::time::Instant;
+use tower_http::request_id::{MakeRequestId, RequestId};
+use tracing::error;
+
+use crate::{ApiError, config::Config, reload::LiveConfig};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// Prazo que o cliente ainda vai esperar pela resposta, em ms.
+pub const DEADLINE_HEADER: &str = "x-request-deadline-ms";
+/// Teto aceito em `X-Request-Deadline-Ms`.
+const MAX_DEADLINE: Duration = Duration::from_secs(120);
+
+/// Ids `<início do processo em hex>-<sequencial>`: únicos por processo e
+/// fáceis de achar nos logs.
+#[derive(Clone)]
+pub struct RequestIds {
+    prefix: Arc<str>,
+    next: Arc<AtomicU64>,
+}
+
+impl Default for RequestIds {
+    fn default() -> Self {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        RequestIds {
+            prefix: format!("{started:x}").into(),
+            next: Default::default(),
+        }
+    }
+}
+
+impl MakeRequestId for RequestIds {
+    fn make_request_id<B>(&mut self, _: &axum::http::Request<B>) -> Option<RequestId> {
+        let n = self.next.fetch_add(1, Ordering::Relaxed);
+        HeaderValue::from_str(&format!("{}-{n}", self.prefix))
+            .ok()
+            .map(RequestId::new)
+    }
+}
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Deixa o id do pedido visível às chamadas ao upstream feitas pelo handler.
+pub async fn scope_request_id(req: Request, next: Next) -> Response {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+    match request_id {
+        Some(id) => REQUEST_ID.scope(id, next.run(req)).await,
+        None => next.run(req).await,
+    }
+}
+
+/// Id do pedido em andamento; `None` fora de um handler (tarefas de fundo).
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Momento em que o cliente deixa de esperar pelo pedido (também fica nas
+/// extensões do pedido).
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline(pub Instant);
+
+/// Prazos padrão: o geral e os por prefixo de rota (vence o mais longo).
+#[derive(Clone)]
+pub struct Deadlines {
+    default_ms: u64,
+    routes: Arc<[(String, u64)]>,
+}
+
+impl Deadlines {
+    pub fn new(config: &Config) -> Self {
+        let mut routes = config.request_deadline_routes.clone();
+        routes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        Deadlines {
+            default_ms: config.request_deadline_ms,
+            routes: routes.into(),
+        }
+    }
+
+    fn for_path(&self, path: &str) -> u64 {
+        self.routes
+            .iter()
+            .find(|(prefix, _)| path.starts_with(prefix.as_str()))
+            .map_or(self.default_ms, |(_, ms)| *ms)
+    }
+}
+
+tokio::task_local! {
+    static DEADLINE: Deadline;
+}
+
+/// Fixa o prazo do pedido: `X-Request-Deadline-Ms` do cliente ou o padrão
+/// da rota (da configuração em uso, que a recarga troca). As chamadas ao
+/// upstream encurtam o timeout para caber nele.
+pub async fn scope_deadline(State(config): State<LiveConfig>, mut req: Request, next: Next) -> Response {
+    let deadlines = Deadlines::new(&config.load());
+    let requested = req
+        .headers()
+        .get(DEADLINE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|ms| *ms > 0)
+        .map(|ms| Duration::from_millis(ms).min(MAX_DEADLINE));
+    let budget = requested.or_else(|| {
+        let ms = deadlines.for_path(req.uri().path());
+        (ms > 0).then(|| Duration::from_millis(ms))
+    });
+    let Some(budget) = budget else {
+        return next.run(req).await;
+    };
+
+    let deadline = Deadline(Instant::now() + budget);
+    req.extensions_mut().insert(deadline);
+    DEADLINE.scope(deadline, next.run(req)).await
+}
+
+/// Tempo que resta até o prazo do pedido em andamento; `None` sem prazo
+/// (ou fora de um handler).
+pub fn remaining() -> Option<Duration> {
+    DEADLINE
+        .try_with(|d| d.0.saturating_duration_since(Instant::now()))
+        .ok()
+}
+
+/// Para handlers com várias chamadas em sequência: `504` se o prazo já
+/// passou, em vez de seguir gastando cota do upstream.
+pub fn check_deadline() -> Result<(), ApiError> {
+    match remaining() {
+        Some(left) if left.is_zero() => Err(ApiError::DeadlineExceeded),
+        _ => Ok(()),
+    }
+}
+
+/// Converte um panic no handler no 500 JSON padrão (com o id do pedido).
+pub async fn catch_panic(req: Request, next: Next) -> Response {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+    let path = req.uri().path().to_string();
+
+    match AssertUnwindSafe(next.run(req)).catch_unwind().await {
+        Ok(resp) => resp,
+        Err(panic) => {
+            error!(request_id, path, "panic no handler: {}", panic_message(&*panic));
+            ApiError::Internal.into_response()
+        }
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("(sem mensagem)")
+}
+
+/// Corpo de erro no formato antigo (`{"error": "<mensagem>", ...}`), que
+/// [`ApiError`] deixa nas extensões da resposta para [`error_body`].
+#[derive(Clone)]
+pub struct LegacyErrorBody(pub serde_json::Value);
+
+/// Código estável de um erro de [`ApiError`], nas extensões da resposta
+/// para quem observa o resultado (as estatísticas do `/stream`).
+#[derive(Debug, Clone, Copy)]
+pub struct ErrorCode(pub &'static str);
+
+/// Teto lido do corpo de uma rejeição do axum para virar a mensagem.
+const MAX_REJECTION_BODY: usize = 16 * 1024;
+
+/// Padroniza as respostas de erro: as rejeições do axum (query ou JSON
+/// inválido, rota inexistente, método errado) ganham o corpo JSON de
+/// [`ApiError`], mantendo o status; com `LEGACY_ERROR_BODY=on` os erros
+/// voltam ao formato antigo, com `error` em texto.
+pub async fn error_body(State(config): State<LiveConfig>, req: Request, next: Next) -> Response {
+    let mut resp = next.run(req).await;
+    let status = resp.status();
+    let is_json = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"));
+    if status.is_client_error()
+        && status != StatusCode::RANGE_NOT_SATISFIABLE
+        && !is_json
+        && resp.extensions().get::<LegacyErrorBody>().is_none()
+    {
+        let text = to_bytes(resp.into_body(), MAX_REJECTION_BODY)
+            .await
+            .map(|b| String::from_utf8_lossy(&b).trim().to_string())
+            .unwrap_or_default();
+        let message = match text.is_empty() {
+            true => status.canonical_reason().unwrap_or("erro no pedido").to_lowercase(),
+            false => text,
+        };
+        resp = ApiError::from_status(status, message).into_response();
+        *resp.status_mut() = status;
+    }
+
+    if !config.load().legacy_error_body {
+        return resp;
+    }
+    match resp.extensions().get::<LegacyErrorBody>().cloned() {
+        Some(LegacyErrorBody(legacy)) => {
+            let (mut parts, _) = resp.into_parts();
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Response::from_parts(parts, Body::from(serde_json::to_vec(&legacy).unwrap_or_default()))
+        }
+        None => resp,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn injected_panic() -> &'static str {
+        panic!("falha injetada")
+    }
+
+    #[tokio::test]
+    async fn panic_becomes_the_json_500() {
+        let app = Router::new()
+            .route("/panic", get(injected_panic))
+            .route("/ok", get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn(catch_panic))
+            .layer(axum::middleware::from_fn(scope_request_id));
+        let req = |path: &str| Request::builder().uri(path).header(REQUEST_ID_HEADER, "teste-1").body(Body::empty()).unwrap();
+
+        let resp = app.clone().oneshot(req("/panic")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(resp.headers()[header::CONTENT_TYPE].to_str().unwrap().starts_with("application/json"));
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(resp.into_body(), usize::MAX).await.unwrap()).unwrap();
+        let error = &body["error"];
+        assert_eq!(error["code"], "internal", "{body}");
+        assert_eq!(error["request_id"], "teste-1", "{body}");
+        assert!(error["message"].is_string() && error["retryable"].is_boolean(), "{body}");
+
+        // o panic não derruba o servidor
+        let resp = app.oneshot(req("/ok")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
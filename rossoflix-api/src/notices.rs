@@ -0,0 +1,287 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+
+use crate::{AppState, aria2c_available, doctor, downloads_writable, engine::Engine, upstream};
+
+/// Abaixo disso o disco cheio vira crítico (o aviso começa em
+/// `doctor::MIN_FREE_BYTES`).
+const CRITICAL_FREE_BYTES: u64 = 1024 * 1024 * 1024;
+/// Fração da cota do OMDb a partir da qual o aviso aparece.
+const OMDB_WARN_RATIO: f64 = 0.9;
+/// Validade das verificações locais (disco, `aria2c`), que rodam processos:
+/// o polling dos clientes não dispara um `df` por pedido.
+const LOCAL_PROBE_TTL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+/// Aviso para o cliente mostrar num banner.
+#[derive(Debug, Clone, Serialize)]
+pub struct Notice {
+    pub severity: Severity,
+    /// Identificador estável (`disk_low`, `omdb_quota`, ...).
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// Estado atual das dependências, o mesmo que o health profundo olha.
+#[derive(Debug, Clone, Default)]
+pub struct Signals {
+    /// Espaço livre em downloads; `None` se o `df` falhou.
+    pub free_bytes: Option<u64>,
+    pub downloads_writable: bool,
+    pub aria2c: bool,
+    pub omdb_calls_today: u64,
+    /// `0`: sem cota configurada.
+    pub omdb_daily_limit: u64,
+    pub torrentio_down: usize,
+    pub torrentio_mirrors: usize,
+}
+
+/// Avisos para os sinais dados, do mais grave ao mais leve. Sem estado:
+/// quando o sinal volta ao normal o aviso some no pedido seguinte.
+pub fn evaluate(signals: &Signals) -> Vec<Notice> {
+    let mut notices = Vec::new();
+    let mut push = |severity, code, message: String| notices.push(Notice { severity, code, message });
+
+    if !signals.downloads_writable {
+        push(
+            Severity::Critical,
+            "downloads_unwritable",
+            "Não é possível gravar na pasta de downloads: novos downloads vão falhar.".into(),
+        );
+    }
+    if let Some(free) = signals.free_bytes.filter(|free| *free < doctor::MIN_FREE_BYTES) {
+        let severity = if free < CRITICAL_FREE_BYTES { Severity::Critical } else { Severity::Warning };
+        push(
+            severity,
+            "disk_low",
+            format!("Pouco espaço em disco ({:.1} GiB livres): downloads grandes podem falhar.", gib(free)),
+        );
+    }
+    if !signals.aria2c {
+        push(
+            Severity::Critical,
+            "aria2c_missing",
+            "O aria2c não está disponível: não dá para baixar nem assistir torrents.".into(),
+        );
+    }
+    if signals.torrentio_mirrors > 0 && signals.torrentio_down > 0 {
+        if signals.torrentio_down == signals.torrentio_mirrors {
+            push(
+                Severity::Critical,
+                "torrentio_down",
+                "A busca de fontes está fora do ar: novos títulos podem ficar sem opções de reprodução.".into(),
+            );
+        } else {
+            push(
+                Severity::Info,
+                "torrentio_degraded",
+                "A busca de fontes está instável e pode demorar mais.".into(),
+            );
+        }
+    }
+    let limit = signals.omdb_daily_limit;
+    if limit > 0 && signals.omdb_calls_today as f64 >= limit as f64 * OMDB_WARN_RATIO {
+        let (severity, message) = if signals.omdb_calls_today >= limit {
+            (Severity::Critical, "A cota diária de buscas acabou: buscas e detalhes podem falhar até amanhã.")
+        } else {
+            (Severity::Warning, "A cota diária de buscas está quase no fim.")
+        };
+        push(severity, "omdb_quota", message.into());
+    }
+
+    notices.sort_by_key(|n| std::cmp::Reverse(n.severity));
+    notices
+}
+
+/// Resultado das verificações locais, reaproveitado por `LOCAL_PROBE_TTL`.
+#[derive(Clone)]
+struct LocalProbe {
+    at: Instant,
+    free_bytes: Option<u64>,
+    downloads_writable: bool,
+    aria2c: bool,
+}
+
+static LOCAL_PROBE: Mutex<Option<LocalProbe>> = Mutex::new(None);
+
+async fn local_probe(state: &AppState) -> LocalProbe {
+    if let Some(probe) = LOCAL_PROBE.lock().unwrap().clone()
+        && probe.at.elapsed() < LOCAL_PROBE_TTL
+    {
+        return probe;
+    }
+    let dir = &state.config().downloads_dir;
+    let (downloads_writable, aria2c) = tokio::join!(downloads_writable(dir), aria2c_available());
+    let probe = LocalProbe {
+        at: Instant::now(),
+        free_bytes: doctor::free_bytes(dir).await,
+        downloads_writable,
+        aria2c,
+    };
+    *LOCAL_PROBE.lock().unwrap() = Some(probe.clone());
+    probe
+}
+
+async fn signals(state: &AppState) -> Signals {
+    let local = local_probe(state).await;
+    let (torrentio_down, torrentio_mirrors) = state.torrentio_mirrors.down_count();
+    Signals {
+        free_bytes: local.free_bytes,
+        downloads_writable: local.downloads_writable,
+        // o motor embutido dispensa o aria2c
+        aria2c: local.aria2c || state.config().download_engine == Engine::Embedded,
+        omdb_calls_today: state.usage.today(upstream::Service::Omdb),
+        omdb_daily_limit: state.config().omdb_daily_limit,
+        torrentio_down,
+        torrentio_mirrors,
+    }
+}
+
+/// `GET /notices` — condições que o cliente deve mostrar ao usuário (disco
+/// cheio, cota do OMDb, torrentio fora do ar, `aria2c` ausente). Lista vazia
+/// quando está tudo bem. Responde `304` quando o `If-None-Match` bate com o
+/// `ETag` atual, para o polling sair barato.
+pub async fn get_notices(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    respond(&evaluate(&signals(&state).await), &headers)
+}
+
+/// Lista de avisos com `ETag`, ou `304` se o cliente já tem essa versão.
+fn respond(notices: &[Notice], headers: &HeaderMap) -> Response {
+    let body = serde_json::json!({ "notices": notices }).to_string();
+    let digest: String = Sha1::digest(&body).iter().take(10).map(|b| format!("{b:02x}")).collect();
+    let etag = format!("\"{digest}\"");
+
+    let matches = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|tag| tag.trim().trim_start_matches("W/") == etag || tag.trim() == "*"));
+    if matches {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+    (
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (header::CACHE_CONTROL, "no-cache".to_string()),
+            (header::ETAG, etag),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+fn gib(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0 * 1024.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn healthy() -> Signals {
+        Signals {
+            free_bytes: Some(500 * 1024 * 1024 * 1024),
+            downloads_writable: true,
+            aria2c: true,
+            omdb_calls_today: 10,
+            omdb_daily_limit: 1000,
+            torrentio_down: 0,
+            torrentio_mirrors: 2,
+        }
+    }
+
+    fn codes(signals: &Signals) -> Vec<(Severity, &'static str)> {
+        evaluate(signals).into_iter().map(|n| (n.severity, n.code)).collect()
+    }
+
+    #[test]
+    fn each_condition_turns_on_and_off() {
+        assert!(codes(&healthy()).is_empty());
+        type Break = fn(&mut Signals);
+        let cases: [(Break, Severity, &str); 8] = [
+            (|s| s.downloads_writable = false, Severity::Critical, "downloads_unwritable"),
+            (|s| s.free_bytes = Some(doctor::MIN_FREE_BYTES - 1), Severity::Warning, "disk_low"),
+            (|s| s.free_bytes = Some(CRITICAL_FREE_BYTES - 1), Severity::Critical, "disk_low"),
+            (|s| s.aria2c = false, Severity::Critical, "aria2c_missing"),
+            (|s| s.torrentio_down = 1, Severity::Info, "torrentio_degraded"),
+            (|s| s.torrentio_down = 2, Severity::Critical, "torrentio_down"),
+            (|s| s.omdb_calls_today = 900, Severity::Warning, "omdb_quota"),
+            (|s| s.omdb_calls_today = 1000, Severity::Critical, "omdb_quota"),
+        ];
+        for (break_it, severity, code) in cases {
+            let mut signals = healthy();
+            break_it(&mut signals);
+            assert_eq!(codes(&signals), [(severity, code)], "{signals:?}");
+            // o sinal volta ao normal: o aviso some sem limpeza nenhuma
+            assert!(codes(&healthy()).is_empty());
+        }
+    }
+
+    #[test]
+    fn unknown_signals_stay_quiet() {
+        // `df` falhou, sem cota configurada, sem espelhos: nada a avisar
+        let signals = Signals { free_bytes: None, omdb_daily_limit: 0, omdb_calls_today: 5000, torrentio_mirrors: 0, ..healthy() };
+        assert!(codes(&signals).is_empty());
+        assert_eq!(codes(&Signals { omdb_calls_today: 899, ..healthy() }), []);
+    }
+
+    #[test]
+    fn most_severe_first() {
+        let signals = Signals { torrentio_down: 1, omdb_calls_today: 950, aria2c: false, ..healthy() };
+        assert_eq!(
+            codes(&signals),
+            [(Severity::Critical, "aria2c_missing"), (Severity::Warning, "omdb_quota"), (Severity::Info, "torrentio_degraded")]
+        );
+    }
+
+    #[tokio::test]
+    async fn etag_follows_the_conditions() {
+        let fetch = |signals: &Signals, etag: Option<&str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(etag) = etag {
+                headers.insert(header::IF_NONE_MATCH, etag.parse().unwrap());
+            }
+            respond(&evaluate(signals), &headers)
+        };
+        let etag = |resp: &Response| resp.headers()[header::ETAG].to_str().unwrap().to_string();
+        let body = |resp: Response| async {
+            let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()
+        };
+
+        let quiet = fetch(&healthy(), None);
+        let quiet_tag = etag(&quiet);
+        assert_eq!(body(quiet).await, serde_json::json!({ "notices": [] }));
+        assert_eq!(fetch(&healthy(), Some(&quiet_tag)).status(), StatusCode::NOT_MODIFIED);
+
+        // o disco enche: a versão do cliente ficou velha
+        let full = Signals { free_bytes: Some(CRITICAL_FREE_BYTES / 2), ..healthy() };
+        let resp = fetch(&full, Some(&quiet_tag));
+        assert_eq!(resp.status(), StatusCode::OK);
+        let full_tag = etag(&resp);
+        assert_ne!(full_tag, quiet_tag);
+        let notices = body(resp).await;
+        assert_eq!((&notices["notices"][0]["code"], &notices["notices"][0]["severity"]), (&"disk_low".into(), &"critical".into()));
+        assert_eq!(fetch(&full, Some(&format!("W/{full_tag}"))).status(), StatusCode::NOT_MODIFIED);
+
+        // liberou espaço: de volta à lista vazia, com o mesmo `ETag` de antes
+        let resp = fetch(&healthy(), Some(&full_tag));
+        assert_eq!((resp.status(), etag(&resp)), (StatusCode::OK, quiet_tag));
+    }
+}
@@ -0,0 +1,246 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::{ApiError, AppState, cache::CacheMode, upstream};
+
+/// Tipo esperado no campo `Type` do OMDb.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Movie,
+    Series,
+}
+
+impl Kind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kind::Movie => "movie",
+            Kind::Series => "series",
+        }
+    }
+}
+
+/// Busca por título (`t=`). Com ano conhecido, manda sempre `y=` e, se não
+/// achar, tenta o ano anterior e o seguinte (estreia em festival × lançamento
+/// comercial). Só aceita resultados do tipo esperado; `None` se nada bater.
+pub async fn resolve_by_title(
+    state: &AppState,
+    title: &str,
+    year: Option<i32>,
+    kind: Kind,
+) -> Result<Option<Value>, ApiError> {
+    resolve(year, kind, |year| fetch_title(state, title, year, kind)).await
+}
+
+/// As tentativas de [`resolve_by_title`], com `fetch` fazendo cada `t=`.
+async fn resolve<F, Fut>(year: Option<i32>, kind: Kind, mut fetch: F) -> Result<Option<Value>, ApiError>
+where
+    F: FnMut(Option<i32>) -> Fut,
+    Fut: Future<Output = Result<Value, ApiError>>,
+{
+    for year in candidate_years(year) {
+        let value = fetch(year).await?;
+        if is_match(&value, kind) {
+            return Ok(Some(value));
+        }
+    }
+    Ok(None)
+}
+
+async fn fetch_title(state: &AppState, title: &str, year: Option<i32>, kind: Kind) -> Result<Value, ApiError> {
+    let mut url = format!(
+        "{}/?apikey={}&t={}&type={}&r=json",
+        state.config().omdb_base_url,
+        state.api_key,
+        urlencoding::encode(title),
+        kind.as_str()
+    );
+    if let Some(year) = year {
+        url.push_str(&format!("&y={year}"));
+    }
+
+    let resp = upstream::get(state, upstream::Service::Omdb, "title", &url)
+        .send()
+        .await
+        .map_err(upstream::send_error)?;
+    if !resp.status().is_success() {
+        return Err(ApiError::Upstream(format!("OMDb: status {}", resp.status())));
+    }
+    upstream::json(state, resp).await
+}
+
+/// Anos a tentar, em ordem: o informado, depois ±1. Sem ano, uma busca só.
+fn candidate_years(year: Option<i32>) -> Vec<Option<i32>> {
+    match year {
+        Some(y) => vec![Some(y), Some(y - 1), Some(y + 1)],
+        None => vec![None],
+    }
+}
+
+fn is_match(value: &Value, kind: Kind) -> bool {
+    value.get("Response").and_then(Value::as_str) != Some("False")
+        && value.get("imdbID").and_then(Value::as_str).is_some()
+        && value.get("Type").and_then(Value::as_str) == Some(kind.as_str())
+}
+
+/// Item da busca (`s=`). Nas buscas de episódios o OMDb omite `Year`,
+/// `Poster` e às vezes `Type`, ou manda `Year` como número: esses campos
+/// são tolerados. Sem `imdbID` o item não serve para nada e é descartado.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchItem {
+    #[serde(rename = "Title", default, deserialize_with = "lenient_text")]
+    title: Option<String>,
+    #[serde(rename = "Year", default, deserialize_with = "lenient_text")]
+    year: Option<String>,
+    #[serde(rename = "imdbID")]
+    imdb_id: String,
+    #[serde(rename = "Type", default, deserialize_with = "lenient_text")]
+    kind: Option<String>,
+    #[serde(rename = "Poster", default = "no_poster", deserialize_with = "poster")]
+    poster: String,
+}
+
+fn no_poster() -> String {
+    "N/A".into()
+}
+
+/// Texto, número ou nada; `""` e `"N/A"` viram `None`.
+fn lenient_text<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(match Option::<Value>::deserialize(deserializer)? {
+        Some(Value::String(s)) => Some(s.trim().to_string()).filter(|s| !s.is_empty() && s != "N/A"),
+        Some(Value::Number(n)) => Some(n.to_string()),
+        _ => None,
+    })
+}
+
+/// Pôster ausente ou malformado fica `"N/A"`, como o OMDb manda quando não
+/// tem, para `posters::fix_posters` trocar pelo do TMDB.
+fn poster<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(lenient_text(deserializer)?.unwrap_or_else(no_poster))
+}
+
+/// Itens de uma página da busca, um a um: um item malformado é descartado
+/// (e contado) em vez de derrubar a página inteira. Sem `Type`, vale o tipo
+/// pedido na busca.
+pub fn search_items(raw: Vec<Value>, requested_type: &str) -> (Vec<SearchItem>, usize) {
+    let mut items = Vec::with_capacity(raw.len());
+    let mut skipped = 0;
+    for value in raw {
+        match serde_json::from_value::<SearchItem>(value) {
+            Ok(mut item) => {
+                if item.kind.is_none() && !requested_type.is_empty() {
+                    item.kind = Some(requested_type.to_string());
+                }
+                items.push(item);
+            }
+            Err(e) => {
+                warn!("OMDb: item da busca descartado: {e}");
+                skipped += 1;
+            }
+        }
+    }
+    (items, skipped)
+}
+
+/// Completa o `Year` que faltou com o que já está em cache, sem chamar o
+/// OMDb: o detalhe do próprio item ou, num episódio, o ano de estreia da
+/// série (`seriesID`).
+pub async fn backfill_years(state: &AppState, items: &mut [SearchItem]) {
+    for item in items.iter_mut().filter(|i| i.year.is_none()) {
+        let Some(detail) = state.cache.get(&format!("detail:{}", item.imdb_id), CacheMode::Normal).await else {
+            continue;
+        };
+        item.year = match leading_year(&detail.value) {
+            Some(year) => Some(year),
+            None => match detail.value.get("seriesID").and_then(Value::as_str) {
+                Some(series) => match state.cache.get(&format!("detail:{series}"), CacheMode::Normal).await {
+                    Some(series) => leading_year(&series.value),
+                    None => None,
+                },
+                None => None,
+            },
+        };
+    }
+}
+
+/// Primeiro ano do `Year` de um detalhe (`2008–2013` → `2008`).
+fn leading_year(detail: &Value) -> Option<String> {
+    let year = detail.get("Year")?.as_str()?;
+    let digits: String = year.chars().take_while(char::is_ascii_digit).collect();
+    (digits.len() == 4).then_some(digits)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Títulos homônimos em décadas diferentes, como o `t=` do OMDb os
+    /// devolve. Sem `y=` vem o mais popular; o `type=` não é confiável.
+    fn fixtures() -> Vec<Value> {
+        vec![
+            json!({ "Title": "Dune", "Year": "2021", "imdbID": "tt1160419", "Type": "movie", "Response": "True" }),
+            json!({ "Title": "Dune", "Year": "1984", "imdbID": "tt0087182", "Type": "movie", "Response": "True" }),
+            json!({ "Title": "Dune", "Year": "2000", "imdbID": "tt0142032", "Type": "series", "Response": "True" }),
+            json!({ "Title": "Solaris", "Year": "1972", "imdbID": "tt0069293", "Type": "movie", "Response": "True" }),
+            json!({ "Title": "Solaris", "Year": "2002", "imdbID": "tt0307479", "Type": "movie", "Response": "True" }),
+        ]
+    }
+
+    fn omdb_title(title: &str, year: Option<i32>) -> Value {
+        fixtures()
+            .into_iter()
+            .find(|t| t["Title"] == title && year.is_none_or(|y| t["Year"].as_str() == Some(&y.to_string())))
+            .unwrap_or_else(|| json!({ "Response": "False", "Error": "Movie not found!" }))
+    }
+
+    /// O imdbID resolvido e os anos pedidos ao OMDb.
+    async fn resolved(title: &str, year: Option<i32>, kind: Kind) -> (Option<String>, Vec<Option<i32>>) {
+        let mut asked = Vec::new();
+        let found = resolve(year, kind, |year| {
+            asked.push(year);
+            std::future::ready(Ok(omdb_title(title, year)))
+        })
+        .await
+        .unwrap();
+        (found.map(|v| v["imdbID"].as_str().unwrap().to_string()), asked)
+    }
+
+    #[tokio::test]
+    async fn same_name_titles_across_decades() {
+        let cases = [
+            ("Dune", Some(1984), Kind::Movie, Some("tt0087182"), vec![Some(1984)]),
+            ("Dune", Some(2021), Kind::Movie, Some("tt1160419"), vec![Some(2021)]),
+            // lançamento um ano depois da estreia, e o contrário
+            ("Dune", Some(1985), Kind::Movie, Some("tt0087182"), vec![Some(1985), Some(1984)]),
+            ("Dune", Some(2020), Kind::Movie, Some("tt1160419"), vec![Some(2020), Some(2019), Some(2021)]),
+            ("Dune", Some(2000), Kind::Series, Some("tt0142032"), vec![Some(2000)]),
+            // o ano bate, o tipo não: não é o filme
+            ("Dune", Some(2000), Kind::Movie, None, vec![Some(2000), Some(1999), Some(2001)]),
+            // sem ano, o que o OMDb escolher (se o tipo bater)
+            ("Dune", None, Kind::Movie, Some("tt1160419"), vec![None]),
+            ("Dune", None, Kind::Series, None, vec![None]),
+            ("Solaris", Some(1972), Kind::Movie, Some("tt0069293"), vec![Some(1972)]),
+            ("Solaris", Some(2003), Kind::Movie, Some("tt0307479"), vec![Some(2003), Some(2002)]),
+            ("Solaris", Some(1990), Kind::Movie, None, vec![Some(1990), Some(1989), Some(1991)]),
+        ];
+        for (title, year, kind, want, want_asked) in cases {
+            let (found, asked) = resolved(title, year, kind).await;
+            assert_eq!(found.as_deref(), want, "{title} {year:?} {kind:?}");
+            assert_eq!(asked, want_asked, "{title} {year:?} {kind:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn upstream_errors_stop_the_retries() {
+        let mut calls = 0;
+        let result = resolve(Some(1999), Kind::Movie, |_| {
+            calls += 1;
+            std::future::ready(Err(ApiError::Upstream("OMDb: status 503".into())))
+        })
+        .await;
+        assert!(matches!(result, Err(ApiError::Upstream(_))));
+        assert_eq!(calls, 1);
+    }
+}
@@ -0,0 +1,107 @@
+use std::io;
+
+use reqwest::{ClientBuilder, Proxy, Url};
+use tracing::{info, warn};
+
+use crate::config::Config;
+
+/// Aplica o proxy de saída (`ALL_PROXY`/`HTTPS_PROXY`/`HTTP_PROXY`) ao
+/// cliente HTTP. Com `PROXY_HOSTS`, só esses hosts (e subdomínios) passam
+/// pelo proxy; o resto vai direto. Sem proxy configurado, nada muda.
+pub fn apply(builder: ClientBuilder, config: &Config) -> io::Result<ClientBuilder> {
+    let Some(raw) = &config.outbound_proxy else {
+        return Ok(builder);
+    };
+    let proxy_url = Url::parse(raw)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("proxy de saída inválido: {e}")))?;
+    log_setup(config, &proxy_url);
+
+    let proxy = if config.proxy_hosts.is_empty() {
+        Proxy::all(proxy_url).map_err(io::Error::other)?
+    } else {
+        let hosts = config.proxy_hosts.clone();
+        Proxy::custom(move |url| {
+            url.host_str()
+                .filter(|host| should_proxy(&hosts, host))
+                .map(|_| proxy_url.clone())
+        })
+    };
+    Ok(builder.proxy(proxy))
+}
+
+/// `host` passa pelo proxy? Lista vazia: todos. Senão, o host exato ou um
+/// subdomínio de algum da lista (`strem.fun` cobre `torrentio.strem.fun`).
+fn should_proxy(hosts: &[String], host: &str) -> bool {
+    if hosts.is_empty() {
+        return true;
+    }
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    hosts.iter().any(|h| {
+        host == *h
+            || host
+                .strip_suffix(h.as_str())
+                .is_some_and(|rest| rest.ends_with('.'))
+    })
+}
+
+/// Valor de `--all-proxy` do aria2c. O aria2 só fala com proxy HTTP, então
+/// um proxy SOCKS fica de fora (os torrents vão direto).
+pub fn aria2_proxy(config: &Config) -> Option<&str> {
+    config
+        .outbound_proxy
+        .as_deref()
+        .filter(|raw| raw.starts_with("http://"))
+}
+
+fn log_setup(config: &Config, proxy_url: &Url) {
+    // sem usuário/senha no log
+    let shown = format!(
+        "{}://{}:{}",
+        proxy_url.scheme(),
+        proxy_url.host_str().unwrap_or_default(),
+        proxy_url.port_or_known_default().unwrap_or_default()
+    );
+    if config.proxy_hosts.is_empty() {
+        info!("proxy de saída {shown} para todos os hosts");
+    } else {
+        info!("proxy de saída {shown} só para: {}", config.proxy_hosts.join(", "));
+    }
+    if aria2_proxy(config).is_none() {
+        warn!("aria2c não suporta o proxy {shown}: o tráfego dos torrents vai direto");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn per_host_proxy_selection() {
+        let hosts = vec!["strem.fun".to_string(), "tracker.example".to_string(), "10.0.0.5".to_string()];
+        let cases = [
+            ("torrentio.strem.fun", true),
+            ("strem.fun", true),
+            ("a.b.strem.fun", true),
+            ("TORRENTIO.Strem.Fun", true),
+            ("torrentio.strem.fun.", true),
+            ("tracker.example", true),
+            ("10.0.0.5", true),
+            // só no limite de um rótulo
+            ("evilstrem.fun", false),
+            ("strem.fun.evil.example", false),
+            ("api.themoviedb.org", false),
+            ("www.omdbapi.com", false),
+            ("110.0.0.5", false),
+        ];
+        for (host, want) in cases {
+            assert_eq!(should_proxy(&hosts, host), want, "{host}");
+        }
+    }
+
+    #[test]
+    fn empty_list_proxies_everything() {
+        for host in ["api.themoviedb.org", "torrentio.strem.fun", "127.0.0.1"] {
+            assert!(should_proxy(&[], host), "{host}");
+        }
+    }
+}
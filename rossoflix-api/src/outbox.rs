@@ -0,0 +1,312 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{Json, extract::State, response::IntoResponse};
+use rusqlite::params;
+use serde::Serialize;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+use crate::{ApiError, AppState, db::Db};
+
+/// Tentativas até um aviso ser descartado.
+const MAX_ATTEMPTS: u32 = 12;
+/// Idade a partir da qual um aviso não entregue perde o sentido.
+const MAX_AGE_SECS: i64 = 24 * 3600;
+/// Entregues ficam esse tempo no banco (para inspeção) antes da limpeza.
+const KEEP_DELIVERED_SECS: i64 = 3600;
+/// Espera da primeira nova tentativa; dobra a cada falha, até `MAX_BACKOFF_SECS`.
+const BASE_BACKOFF_SECS: i64 = 5;
+const MAX_BACKOFF_SECS: i64 = 10 * 60;
+/// Falhas seguidas que abrem o disjuntor de um destino, e por quanto tempo.
+const BREAKER_THRESHOLD: u32 = 3;
+const BREAKER_COOLDOWN: Duration = Duration::from_secs(60);
+/// Maior intervalo entre duas passadas do despachante.
+const IDLE_POLL: Duration = Duration::from_secs(30);
+/// Avisos tentados por passada.
+const BATCH: usize = 20;
+
+/// Fila persistente de avisos (tabela `notifications`): gravar é um INSERT
+/// e a entrega fica com o despachante em segundo plano, com backoff
+/// exponencial por aviso e disjuntor por destino. Assim uma queda de
+/// alguns minutos do Telegram atrasa os avisos em vez de perdê-los.
+#[derive(Clone)]
+pub struct Outbox {
+    db: Db,
+    wake: Arc<Notify>,
+    breakers: Arc<Mutex<HashMap<String, Breaker>>>,
+}
+
+#[derive(Default)]
+struct Breaker {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Pending {
+    id: i64,
+    destination: String,
+    text: String,
+    /// Unix timestamps (s).
+    created_at: i64,
+    next_attempt_at: i64,
+    attempts: u32,
+    last_error: Option<String>,
+}
+
+/// Aviso a entregar nesta passada.
+struct Due {
+    id: i64,
+    destination: String,
+    text: String,
+    attempts: u32,
+}
+
+impl Outbox {
+    pub fn new(db: Db) -> Self {
+        Outbox {
+            db,
+            wake: Default::default(),
+            breakers: Default::default(),
+        }
+    }
+
+    /// Põe o aviso na fila sem esperar o banco: quem chama (o fim de um
+    /// download) segue na hora.
+    pub fn enqueue(&self, destination: &str, text: String) {
+        let outbox = self.clone();
+        let destination = destination.to_string();
+        tokio::spawn(async move {
+            let now = unix_now();
+            let inserted = outbox
+                .db
+                .call(move |conn| {
+                    conn.execute(
+                        "INSERT INTO notifications (destination, text, created_at, next_attempt_at, attempts)
+                         VALUES (?1, ?2, ?3, ?3, 0)",
+                        params![destination, text, now],
+                    )
+                })
+                .await;
+            match inserted {
+                Ok(_) => outbox.wake.notify_one(),
+                Err(e) => warn!("outbox: falha ao gravar aviso: {e}"),
+            }
+        });
+    }
+
+    /// Avisos ainda não entregues, do mais antigo ao mais novo.
+    pub async fn pending(&self) -> Result<Vec<Pending>, ApiError> {
+        self.db
+            .call(|conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT id, destination, text, created_at, next_attempt_at, attempts, last_error
+                     FROM notifications WHERE delivered_at IS NULL ORDER BY id",
+                )?;
+                stmt.query_map([], |row| {
+                    Ok(Pending {
+                        id: row.get(0)?,
+                        destination: row.get(1)?,
+                        text: row.get(2)?,
+                        created_at: row.get(3)?,
+                        next_attempt_at: row.get(4)?,
+                        attempts: row.get(5)?,
+                        last_error: row.get(6)?,
+                    })
+                })?
+                .collect()
+            })
+            .await
+    }
+
+    /// Antecipa todas as pendentes e fecha os disjuntores; devolve quantas.
+    pub async fn retry_now(&self) -> Result<usize, ApiError> {
+        let now = unix_now();
+        let count = self
+            .db
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE notifications SET next_attempt_at = ?1 WHERE delivered_at IS NULL",
+                    params![now],
+                )
+            })
+            .await?;
+        self.breakers.lock().unwrap().clear();
+        self.wake.notify_one();
+        Ok(count)
+    }
+
+    /// Disjuntor aberto: o destino falhou seguidamente há pouco.
+    fn is_open(&self, destination: &str) -> bool {
+        let breakers = self.breakers.lock().unwrap();
+        breakers
+            .get(destination)
+            .and_then(|b| b.open_until)
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    fn record(&self, destination: &str, ok: bool) {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers.entry(destination.to_string()).or_default();
+        if ok {
+            *breaker = Breaker::default();
+            return;
+        }
+        breaker.failures += 1;
+        if breaker.failures >= BREAKER_THRESHOLD {
+            breaker.open_until = Some(Instant::now() + BREAKER_COOLDOWN);
+            warn!(destination, failures = breaker.failures, "outbox: destino em pausa por {}s", BREAKER_COOLDOWN.as_secs());
+        }
+    }
+
+    /// Uma passada: limpa entregues e vencidos e tenta as pendentes cujo
+    /// horário chegou.
+    async fn dispatch(&self, state: &AppState) -> Result<(), ApiError> {
+        let now = unix_now();
+        let (expired, _) = self
+            .db
+            .call(move |conn| {
+                let expired = conn.execute(
+                    "DELETE FROM notifications WHERE delivered_at IS NULL AND (attempts >= ?1 OR created_at < ?2)",
+                    params![MAX_ATTEMPTS, now - MAX_AGE_SECS],
+                )?;
+                let delivered = conn.execute(
+                    "DELETE FROM notifications WHERE delivered_at IS NOT NULL AND delivered_at < ?1",
+                    params![now - KEEP_DELIVERED_SECS],
+                )?;
+                Ok((expired, delivered))
+            })
+            .await?;
+        if expired > 0 {
+            warn!(expired, "outbox: avisos descartados sem entrega");
+        }
+
+        let due = self
+            .db
+            .call(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT id, destination, text, attempts FROM notifications
+                     WHERE delivered_at IS NULL AND next_attempt_at <= ?1 ORDER BY id LIMIT ?2",
+                )?;
+                stmt.query_map(params![now, BATCH], |row| {
+                    Ok(Due {
+                        id: row.get(0)?,
+                        destination: row.get(1)?,
+                        text: row.get(2)?,
+                        attempts: row.get(3)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()
+            })
+            .await?;
+
+        for item in due {
+            if self.is_open(&item.destination) {
+                continue;
+            }
+            let result = deliver(state, &item.destination, &item.text).await;
+            self.record(&item.destination, result.is_ok());
+            let now = unix_now();
+            let (id, attempts) = (item.id, item.attempts + 1);
+            match result {
+                Ok(()) => {
+                    self.db
+                        .call(move |conn| {
+                            conn.execute(
+                                "UPDATE notifications SET delivered_at = ?2, attempts = ?3 WHERE id = ?1",
+                                params![id, now, attempts],
+                            )
+                        })
+                        .await?;
+                }
+                Err(error) => {
+                    let backoff = (BASE_BACKOFF_SECS << attempts.min(10)).min(MAX_BACKOFF_SECS);
+                    warn!(id, destination = item.destination, attempts, "outbox: entrega falhou: {error}");
+                    self.db
+                        .call(move |conn| {
+                            conn.execute(
+                                "UPDATE notifications SET attempts = ?2, next_attempt_at = ?3, last_error = ?4
+                                 WHERE id = ?1",
+                                params![id, attempts, now + backoff, error],
+                            )
+                        })
+                        .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Segundos até o próximo aviso pendente vencer (limitado a `IDLE_POLL`).
+    async fn next_wait(&self) -> Duration {
+        let next: Option<i64> = self
+            .db
+            .call(|conn| {
+                conn.query_row(
+                    "SELECT MIN(next_attempt_at) FROM notifications WHERE delivered_at IS NULL",
+                    [],
+                    |row| row.get(0),
+                )
+            })
+            .await
+            .ok()
+            .flatten();
+        match next {
+            Some(at) => Duration::from_secs((at - unix_now()).clamp(1, IDLE_POLL.as_secs() as i64) as u64),
+            None => IDLE_POLL,
+        }
+    }
+}
+
+/// Entrega num destino; por enquanto só o Telegram.
+async fn deliver(state: &AppState, destination: &str, text: &str) -> Result<(), String> {
+    match destination {
+        "telegram" => match &state.telegram {
+            Some(bot) => bot.deliver(text).await,
+            None => Err("Telegram desligado".into()),
+        },
+        other => Err(format!("destino desconhecido: {other}")),
+    }
+}
+
+/// Despachante em segundo plano: acorda com avisos novos ou quando o
+/// próximo backoff vence.
+pub fn spawn_dispatcher(state: AppState) {
+    tokio::spawn(async move {
+        let outbox = state.outbox.clone();
+        info!("outbox: despachante de avisos ativo");
+        loop {
+            if let Err(e) = outbox.dispatch(&state).await {
+                warn!("outbox: passada falhou: {e}");
+            }
+            let wait = outbox.next_wait().await;
+            tokio::select! {
+                _ = outbox.wake.notified() => {}
+                _ = tokio::time::sleep(wait) => {}
+            }
+        }
+    });
+}
+
+/// `GET /admin/notifications/pending`
+pub async fn pending_notifications(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
+    let pending = state.outbox.pending().await?;
+    Ok(Json(serde_json::json!({ "pending": pending })))
+}
+
+/// `POST /admin/notifications/retry` — tenta já todas as pendentes.
+pub async fn retry_notifications(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
+    let scheduled = state.outbox.retry_now().await?;
+    Ok(Json(serde_json::json!({ "scheduled": scheduled })))
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
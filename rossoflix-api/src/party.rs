@@ -0,0 +1,312 @@
+use std::{
+    collections::{HashMap, hash_map::RandomState},
+    hash::{BuildHasher, Hasher},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    Json,
+    extract::{
+        Path as UrlPath, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::{debug, info};
+
+use crate::{ApiError, AppState};
+
+/// Limite de sessões simultâneas em memória.
+const MAX_PARTIES: usize = 1_000;
+
+/// Intervalo entre as passadas que removem sessões ociosas.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Sessões de "assistir junto", só em memória: cada uma guarda o estado de
+/// reprodução e retransmite os eventos aos participantes via WebSocket.
+#[derive(Clone, Default)]
+pub struct PartyRegistry {
+    parties: Arc<Mutex<HashMap<String, Arc<Party>>>>,
+}
+
+struct Party {
+    id: String,
+    title: String,
+    stream_url: String,
+    created_at: u64,
+    playback: Mutex<Playback>,
+    tx: broadcast::Sender<String>,
+}
+
+struct Playback {
+    playing: bool,
+    /// Posição (s) no instante `at`.
+    position: f64,
+    at: Instant,
+    participants: usize,
+    last_activity: Instant,
+}
+
+impl Playback {
+    /// Posição atual, avançando o relógio se estiver tocando.
+    fn position_now(&self) -> f64 {
+        if self.playing {
+            self.position + self.at.elapsed().as_secs_f64()
+        } else {
+            self.position
+        }
+    }
+}
+
+/// Estado autoritativo enviado a todos: `position` vale para `server_time`
+/// (ms unix); o cliente corrige pelo seu offset de relógio (ver `pong`).
+#[derive(Debug, Serialize)]
+struct StateMessage<'a> {
+    r#type: &'static str,
+    event: &'a str,
+    playing: bool,
+    position: f64,
+    server_time: u64,
+    participants: usize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ClientEvent {
+    Play { position: Option<f64> },
+    Pause { position: Option<f64> },
+    Seek { position: f64 },
+    /// Medição de relógio: o servidor responde `pong` só para quem pediu.
+    Ping { client_time: u64 },
+}
+
+impl Party {
+    fn snapshot(&self, event: &str) -> String {
+        let playback = self.playback.lock().unwrap();
+        let message = StateMessage {
+            r#type: "state",
+            event,
+            playing: playback.playing,
+            position: playback.position_now(),
+            server_time: unix_millis(),
+            participants: playback.participants,
+        };
+        serde_json::to_string(&message).unwrap_or_default()
+    }
+
+    /// Aplica play/pause/seek e devolve o nome do evento para o broadcast.
+    fn apply(&self, event: &ClientEvent) -> Option<&'static str> {
+        let mut playback = self.playback.lock().unwrap();
+        let current = playback.position_now();
+        let (name, playing, position) = match *event {
+            ClientEvent::Play { position } => ("play", true, position.unwrap_or(current)),
+            ClientEvent::Pause { position } => ("pause", false, position.unwrap_or(current)),
+            ClientEvent::Seek { position } => ("seek", playback.playing, position),
+            ClientEvent::Ping { .. } => return None,
+        };
+        if !position.is_finite() || position < 0.0 {
+            return None;
+        }
+        playback.playing = playing;
+        playback.position = position;
+        playback.at = Instant::now();
+        playback.last_activity = Instant::now();
+        Some(name)
+    }
+
+    fn join(&self) {
+        let mut playback = self.playback.lock().unwrap();
+        playback.participants += 1;
+        playback.last_activity = Instant::now();
+    }
+
+    fn leave(&self) {
+        let mut playback = self.playback.lock().unwrap();
+        playback.participants = playback.participants.saturating_sub(1);
+        playback.last_activity = Instant::now();
+    }
+
+    fn info(&self) -> serde_json::Value {
+        let playback = self.playback.lock().unwrap();
+        serde_json::json!({
+            "id": self.id,
+            "title": self.title,
+            "stream_url": self.stream_url,
+            "created_at": self.created_at,
+            "participants": playback.participants,
+            "playing": playback.playing,
+            "position": playback.position_now(),
+            "ws_url": format!("/party/{}/ws", self.id),
+        })
+    }
+}
+
+impl PartyRegistry {
+    fn get(&self, id: &str) -> Result<Arc<Party>, ApiError> {
+        self.parties
+            .lock()
+            .unwrap()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| ApiError::NotFound(format!("sessão {id} não existe")))
+    }
+
+    /// Remove sessões sem participantes e sem eventos há mais de `idle`.
+    fn sweep(&self, idle: Duration) {
+        self.parties.lock().unwrap().retain(|id, party| {
+            let playback = party.playback.lock().unwrap();
+            let keep = playback.participants > 0 || playback.last_activity.elapsed() < idle;
+            if !keep {
+                info!(party = id, "sessão expirada por inatividade");
+            }
+            keep
+        });
+    }
+}
+
+/// Limpeza periódica das sessões ociosas, em segundo plano.
+pub fn spawn_sweeper(registry: PartyRegistry, idle: Duration) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            tick.tick().await;
+            registry.sweep(idle);
+        }
+    });
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateParty {
+    #[serde(default)]
+    title: String,
+    stream_url: String,
+}
+
+/// `POST /party` — cria uma sessão para `stream_url` e devolve o id.
+pub async fn create_party(
+    State(state): State<AppState>,
+    Json(req): Json<CreateParty>,
+) -> Result<impl IntoResponse, ApiError> {
+    if req.stream_url.trim().is_empty() {
+        return Err(ApiError::BadRequest("stream_url é obrigatório".into()));
+    }
+    let mut parties = state.parties.parties.lock().unwrap();
+    if parties.len() >= MAX_PARTIES {
+        return Err(ApiError::Unavailable("limite de sessões atingido".into()));
+    }
+
+    let id = new_id();
+    let (tx, _) = broadcast::channel(64);
+    let party = Arc::new(Party {
+        id: id.clone(),
+        title: req.title,
+        stream_url: req.stream_url,
+        created_at: unix_millis() / 1000,
+        playback: Mutex::new(Playback {
+            playing: false,
+            position: 0.0,
+            at: Instant::now(),
+            participants: 0,
+            last_activity: Instant::now(),
+        }),
+        tx,
+    });
+    let body = party.info();
+    parties.insert(id.clone(), party);
+    info!(party = id, "sessão criada");
+    Ok((StatusCode::CREATED, Json(body)))
+}
+
+/// `GET /party/:id` — dados da sessão, participantes e posição atual.
+pub async fn party_info(
+    State(state): State<AppState>,
+    UrlPath(id): UrlPath<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    Ok(Json(state.parties.get(&id)?.info()))
+}
+
+/// `GET /party/:id/ws` — canal da sessão. Quem entra recebe o estado atual;
+/// play/pause/seek de qualquer participante vão para todos com o
+/// `server_time` autoritativo.
+pub async fn party_ws(
+    State(state): State<AppState>,
+    UrlPath(id): UrlPath<String>,
+    ws: WebSocketUpgrade,
+) -> Result<impl IntoResponse, ApiError> {
+    let party = state.parties.get(&id)?;
+    Ok(ws.on_upgrade(move |socket| participate(party, socket)))
+}
+
+async fn participate(party: Arc<Party>, mut socket: WebSocket) {
+    let mut rx = party.tx.subscribe();
+    party.join();
+    let _ = party.tx.send(party.snapshot("join"));
+    debug!(party = party.id, "participante entrou");
+
+    loop {
+        tokio::select! {
+            incoming = socket.recv() => {
+                let text = match incoming {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                let Ok(event) = serde_json::from_str::<ClientEvent>(&text) else {
+                    let error = serde_json::json!({ "type": "error", "error": "evento inválido" });
+                    if socket.send(Message::Text(error.to_string())).await.is_err() {
+                        break;
+                    }
+                    continue;
+                };
+                if let ClientEvent::Ping { client_time } = event {
+                    // offset ingênuo (inclui a latência de ida); o cliente
+                    // pode refinar com o RTT medido
+                    let server_time = unix_millis();
+                    let pong = serde_json::json!({
+                        "type": "pong",
+                        "client_time": client_time,
+                        "server_time": server_time,
+                        "offset_ms": server_time as i64 - client_time as i64,
+                    });
+                    if socket.send(Message::Text(pong.to_string())).await.is_err() {
+                        break;
+                    }
+                } else if let Some(name) = party.apply(&event) {
+                    let _ = party.tx.send(party.snapshot(name));
+                }
+            }
+            outgoing = rx.recv() => {
+                let text = match outgoing {
+                    Ok(text) => text,
+                    // ficou para trás: basta o estado atual
+                    Err(broadcast::error::RecvError::Lagged(_)) => party.snapshot("sync"),
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    party.leave();
+    let _ = party.tx.send(party.snapshot("leave"));
+    debug!(party = party.id, "participante saiu");
+}
+
+/// Id aleatório de 128 bits (chaves aleatórias do SipHash, sem dependência extra).
+pub fn new_id() -> String {
+    let random = || RandomState::new().build_hasher().finish();
+    format!("{:016x}{:016x}", random(), random())
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
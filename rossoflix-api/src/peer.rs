@@ -0,0 +1,387 @@
+use std::{collections::HashMap, io, net::SocketAddr, time::Duration};
+
+use serde::Deserialize;
+use serde_bencode::value::Value;
+use sha1::{Digest, Sha1};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    time::timeout,
+};
+
+use crate::torrent;
+
+const PROTOCOL: &[u8; 19] = b"BitTorrent protocol";
+/// Bit reservado do protocolo de extensões (BEP 10), no sexto byte.
+const EXTENSION_BIT: u8 = 0x10;
+/// Tamanho dos pedidos de bloco, o que todo cliente aceita.
+pub const BLOCK_LEN: u32 = 16 * 1024;
+/// Conexão e handshake com um peer.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Troca inteira dos metadados com um peer.
+const METADATA_TIMEOUT: Duration = Duration::from_secs(30);
+/// Maior mensagem aceita: um bloco, o bitfield de um torrent enorme ou um
+/// pedaço dos metadados cabem com folga.
+const MAX_MESSAGE_LEN: usize = 1024 * 1024;
+/// Id local da extensão `ut_metadata` (BEP 9), anunciado no handshake estendido.
+pub const UT_METADATA: u8 = 1;
+/// Pedaços em que os metadados trafegam.
+const METADATA_PIECE_LEN: usize = 16 * 1024;
+
+/// Mensagens do protocolo de peers (BEP 3), sem o prefixo de tamanho.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    KeepAlive,
+    Choke,
+    Unchoke,
+    Interested,
+    NotInterested,
+    Have(u32),
+    Bitfield(Vec<u8>),
+    Request { index: u32, begin: u32, length: u32 },
+    Piece { index: u32, begin: u32, block: Vec<u8> },
+    Cancel { index: u32, begin: u32, length: u32 },
+    /// Protocolo de extensões (BEP 10); o `id` 0 é o handshake estendido.
+    Extended { id: u8, payload: Vec<u8> },
+    /// Mensagens que o motor não usa (`port` da DHT e afins).
+    Other(u8),
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        let words = |values: &[u32]| values.iter().flat_map(|v| v.to_be_bytes()).collect::<Vec<u8>>();
+        let (id, body) = match self {
+            Message::KeepAlive => return vec![0; 4],
+            Message::Choke => (0, Vec::new()),
+            Message::Unchoke => (1, Vec::new()),
+            Message::Interested => (2, Vec::new()),
+            Message::NotInterested => (3, Vec::new()),
+            Message::Have(index) => (4, words(&[*index])),
+            Message::Bitfield(bits) => (5, bits.clone()),
+            Message::Request { index, begin, length } => (6, words(&[*index, *begin, *length])),
+            Message::Piece { index, begin, block } => (7, [words(&[*index, *begin]), block.clone()].concat()),
+            Message::Cancel { index, begin, length } => (8, words(&[*index, *begin, *length])),
+            Message::Extended { id, payload } => (20, [&[*id][..], payload].concat()),
+            Message::Other(id) => (*id, Vec::new()),
+        };
+        let mut out = Vec::with_capacity(5 + body.len());
+        out.extend_from_slice(&(body.len() as u32 + 1).to_be_bytes());
+        out.push(id);
+        out.extend_from_slice(&body);
+        out
+    }
+
+    /// Mensagem a partir do quadro já sem o prefixo de tamanho.
+    pub fn decode(frame: &[u8]) -> io::Result<Self> {
+        let Some((&id, body)) = frame.split_first() else {
+            return Ok(Message::KeepAlive);
+        };
+        let short = || io::Error::new(io::ErrorKind::InvalidData, format!("mensagem {id} curta demais"));
+        let word = |at: usize| {
+            body.get(at..at + 4)
+                .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+                .ok_or_else(short)
+        };
+        Ok(match id {
+            0 => Message::Choke,
+            1 => Message::Unchoke,
+            2 => Message::Interested,
+            3 => Message::NotInterested,
+            4 => Message::Have(word(0)?),
+            5 => Message::Bitfield(body.to_vec()),
+            6 => Message::Request { index: word(0)?, begin: word(4)?, length: word(8)? },
+            7 => Message::Piece { index: word(0)?, begin: word(4)?, block: body.get(8..).ok_or_else(short)?.to_vec() },
+            8 => Message::Cancel { index: word(0)?, begin: word(4)?, length: word(8)? },
+            20 => {
+                let (&id, payload) = body.split_first().ok_or_else(short)?;
+                Message::Extended { id, payload: payload.to_vec() }
+            }
+            other => Message::Other(other),
+        })
+    }
+}
+
+/// Handshake: protocolo, bits reservados (com o de extensões), infohash e peer id.
+pub fn handshake(info_hash: &[u8; 20], peer_id: &[u8; 20]) -> [u8; 68] {
+    let mut out = [0u8; 68];
+    out[0] = PROTOCOL.len() as u8;
+    out[1..20].copy_from_slice(PROTOCOL);
+    out[25] = EXTENSION_BIT;
+    out[28..48].copy_from_slice(info_hash);
+    out[48..].copy_from_slice(peer_id);
+    out
+}
+
+/// Confere o handshake do outro lado; `true` se ele fala o protocolo de
+/// extensões.
+fn check_handshake(resp: &[u8; 68], info_hash: &[u8; 20]) -> io::Result<bool> {
+    if resp[0] as usize != PROTOCOL.len() || resp[1..20] != PROTOCOL[..] {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "handshake inválido"));
+    }
+    if resp[28..48] != info_hash[..] {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "peer de outro torrent"));
+    }
+    Ok(resp[25] & EXTENSION_BIT != 0)
+}
+
+/// Conexão com um peer, já depois do handshake.
+pub struct Peer<S = TcpStream> {
+    stream: BufReader<S>,
+    /// O outro lado fala o protocolo de extensões (BEP 10).
+    pub extensions: bool,
+}
+
+impl Peer {
+    pub async fn connect(addr: SocketAddr, info_hash: &[u8; 20], peer_id: &[u8; 20]) -> io::Result<Self> {
+        let connecting = async {
+            let stream = TcpStream::connect(addr).await?;
+            Peer::handshake(stream, info_hash, peer_id).await
+        };
+        timeout(CONNECT_TIMEOUT, connecting)
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "peer sem resposta"))?
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Peer<S> {
+    /// Manda o nosso handshake e confere o do outro lado, que pode vir
+    /// antes ou depois.
+    pub async fn handshake(stream: S, info_hash: &[u8; 20], peer_id: &[u8; 20]) -> io::Result<Self> {
+        let mut stream = BufReader::new(stream);
+        stream.write_all(&handshake(info_hash, peer_id)).await?;
+        let mut resp = [0u8; 68];
+        stream.read_exact(&mut resp).await?;
+        let extensions = check_handshake(&resp, info_hash)?;
+        Ok(Peer { stream, extensions })
+    }
+
+    pub async fn send(&mut self, message: &Message) -> io::Result<()> {
+        self.stream.write_all(&message.encode()).await
+    }
+
+    pub async fn recv(&mut self) -> io::Result<Message> {
+        let len = self.stream.read_u32().await? as usize;
+        if len > MAX_MESSAGE_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("mensagem de {len} bytes")));
+        }
+        let mut frame = vec![0; len];
+        self.stream.read_exact(&mut frame).await?;
+        Message::decode(&frame)
+    }
+
+    /// Dicionário `info` pelo `ut_metadata` (BEP 9), pedaço a pedaço,
+    /// conferido contra o infohash.
+    pub async fn metadata(&mut self, info_hash: &[u8; 20]) -> io::Result<Vec<u8>> {
+        if !self.extensions {
+            return Err(io::Error::other("peer sem o protocolo de extensões"));
+        }
+        self.send(&extended_handshake()).await?;
+        let (remote_id, size) = loop {
+            if let Message::Extended { id: 0, payload } = self.recv().await? {
+                break metadata_offer(&payload)?;
+            }
+        };
+
+        let mut info = Vec::with_capacity(size);
+        for piece in 0..size.div_ceil(METADATA_PIECE_LEN) {
+            self.send(&metadata_request(remote_id, piece)).await?;
+            loop {
+                let Message::Extended { id: UT_METADATA, payload } = self.recv().await? else {
+                    continue;
+                };
+                let end = bencode_end(&payload).ok_or_else(|| invalid("mensagem ut_metadata inválida"))?;
+                let message: MetadataMessage =
+                    serde_bencode::from_bytes(&payload[..end]).map_err(|e| invalid(&e.to_string()))?;
+                match message.msg_type {
+                    1 if message.piece == piece => {
+                        info.extend_from_slice(&payload[end..]);
+                        break;
+                    }
+                    2 => return Err(io::Error::other("peer recusou os metadados")),
+                    _ => {}
+                }
+            }
+        }
+        if info.len() != size || Sha1::digest(&info)[..] != info_hash[..] {
+            return Err(invalid("metadados não batem com o infohash"));
+        }
+        Ok(info)
+    }
+}
+
+/// Conecta e busca os metadados de um magnet com `addr`.
+pub async fn fetch_metadata(addr: SocketAddr, info_hash: &[u8; 20], peer_id: &[u8; 20]) -> io::Result<Vec<u8>> {
+    let mut peer = Peer::connect(addr, info_hash, peer_id).await?;
+    timeout(METADATA_TIMEOUT, peer.metadata(info_hash))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "metadados sem resposta"))?
+}
+
+#[derive(Deserialize)]
+struct ExtendedHandshake {
+    #[serde(default)]
+    m: HashMap<String, Value>,
+    metadata_size: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct MetadataMessage {
+    msg_type: u8,
+    piece: usize,
+}
+
+/// Handshake estendido: só anuncia o `ut_metadata`.
+pub fn extended_handshake() -> Message {
+    Message::Extended { id: 0, payload: format!("d1:md11:ut_metadatai{UT_METADATA}eee").into_bytes() }
+}
+
+fn metadata_request(remote_id: u8, piece: usize) -> Message {
+    Message::Extended { id: remote_id, payload: format!("d8:msg_typei0e5:piecei{piece}ee").into_bytes() }
+}
+
+/// Id do `ut_metadata` do outro lado e o tamanho dos metadados, pelo
+/// handshake estendido dele.
+fn metadata_offer(payload: &[u8]) -> io::Result<(u8, usize)> {
+    let offer: ExtendedHandshake = serde_bencode::from_bytes(payload).map_err(|e| invalid(&e.to_string()))?;
+    let id = match offer.m.get("ut_metadata") {
+        Some(Value::Int(id)) => u8::try_from(*id).ok().filter(|id| *id != 0),
+        _ => None,
+    };
+    let id = id.ok_or_else(|| io::Error::other("peer sem ut_metadata"))?;
+    let size = offer
+        .metadata_size
+        .filter(|size| (1..=torrent::MAX_TORRENT_BYTES as u64).contains(size))
+        .ok_or_else(|| invalid("metadata_size ausente ou grande demais"))?;
+    Ok((id, size as usize))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Fim do primeiro valor bencodado de `data`: no `ut_metadata` o pedaço
+/// dos metadados vem logo depois do dicionário.
+fn bencode_end(data: &[u8]) -> Option<usize> {
+    fn value(data: &[u8], pos: usize, depth: usize) -> Option<usize> {
+        if depth > 32 {
+            return None;
+        }
+        match *data.get(pos)? {
+            b'i' => Some(pos + data[pos..].iter().position(|&b| b == b'e')? + 1),
+            b'l' | b'd' => {
+                let mut pos = pos + 1;
+                while *data.get(pos)? != b'e' {
+                    pos = value(data, pos, depth + 1)?;
+                }
+                Some(pos + 1)
+            }
+            b'0'..=b'9' => {
+                let colon = pos + data[pos..].iter().position(|&b| b == b':')?;
+                let len: usize = std::str::from_utf8(&data[pos..colon]).ok()?.parse().ok()?;
+                let end = colon.checked_add(1)?.checked_add(len)?;
+                (end <= data.len()).then_some(end)
+            }
+            _ => None,
+        }
+    }
+    value(data, 0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: [u8; 20] = [7; 20];
+
+    #[test]
+    fn message_round_trip() {
+        let messages = [
+            Message::KeepAlive,
+            Message::Choke,
+            Message::Unchoke,
+            Message::Interested,
+            Message::NotInterested,
+            Message::Have(42),
+            Message::Bitfield(vec![0b1010_0000, 0xff]),
+            Message::Request { index: 1, begin: BLOCK_LEN, length: BLOCK_LEN },
+            Message::Piece { index: 3, begin: 0, block: b"dados".to_vec() },
+            Message::Cancel { index: 1, begin: 0, length: 5 },
+            Message::Extended { id: 0, payload: b"de".to_vec() },
+            Message::Other(9),
+        ];
+        for message in messages {
+            let frame = message.encode();
+            let len = u32::from_be_bytes(frame[..4].try_into().unwrap()) as usize;
+            assert_eq!(len, frame.len() - 4, "{message:?}");
+            assert_eq!(Message::decode(&frame[4..]).unwrap(), message);
+        }
+        assert_eq!(Message::Have(1).encode(), [0, 0, 0, 5, 4, 0, 0, 0, 1]);
+        assert!(Message::decode(&[6, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn handshake_checks_protocol_and_hash() {
+        let ours = handshake(&HASH, b"-RF0100-abcdefghijkl");
+        assert_eq!(ours[1..20], *b"BitTorrent protocol");
+        assert!(check_handshake(&ours, &HASH).unwrap());
+
+        let mut plain = ours;
+        plain[25] = 0;
+        assert!(!check_handshake(&plain, &HASH).unwrap());
+        assert!(check_handshake(&ours, &[8; 20]).is_err());
+        let mut garbage = ours;
+        garbage[1] = b'X';
+        assert!(check_handshake(&garbage, &HASH).is_err());
+    }
+
+    #[test]
+    fn bencode_end_finds_the_first_value() {
+        let message = b"d8:msg_typei1e5:piecei0e10:total_sizei3ee\x00\x01\x02";
+        assert_eq!(bencode_end(message), Some(message.len() - 3));
+        assert_eq!(bencode_end(b"l4:spami-3ee!"), Some(12));
+        assert_eq!(bencode_end(b"d3:abc"), None);
+        assert_eq!(bencode_end(b"5:ab"), None);
+        assert_eq!(bencode_end(b"x"), None);
+    }
+
+    /// Lado do seeder: responde o handshake estendido e entrega os
+    /// metadados em pedaços, como um cliente de verdade.
+    async fn serve_metadata(stream: tokio::io::DuplexStream, hash: [u8; 20], info: Vec<u8>, remote_id: u8) {
+        let mut peer = Peer::handshake(stream, &hash, b"-XX0000-seeder000000").await.unwrap();
+        let offer = format!("d1:md11:ut_metadatai{remote_id}ee13:metadata_sizei{}ee", info.len());
+        peer.send(&Message::Extended { id: 0, payload: offer.into_bytes() }).await.unwrap();
+        while let Ok(message) = peer.recv().await {
+            // o nosso handshake estendido chega antes dos pedidos
+            let Message::Extended { id, payload } = message else { continue };
+            if id == 0 {
+                continue;
+            }
+            assert_eq!(id, remote_id);
+            let request: MetadataMessage = serde_bencode::from_bytes(&payload).unwrap();
+            let start = request.piece * METADATA_PIECE_LEN;
+            let mut reply = format!("d8:msg_typei1e5:piecei{}e10:total_sizei{}ee", request.piece, info.len()).into_bytes();
+            reply.extend_from_slice(&info[start..(start + METADATA_PIECE_LEN).min(info.len())]);
+            if peer.send(&Message::Extended { id: UT_METADATA, payload: reply }).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn metadata_in_pieces_checked_against_the_hash() {
+        // maior que um pedaço, para exigir dois pedidos
+        let info = format!("d4:name20000:{}e", "x".repeat(20_000)).into_bytes();
+        let hash: [u8; 20] = Sha1::digest(&info).into();
+        let (ours, theirs) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(serve_metadata(theirs, hash, info.clone(), 3));
+        let mut peer = Peer::handshake(ours, &hash, b"-RF0100-abcdefghijkl").await.unwrap();
+        assert_eq!(peer.metadata(&hash).await.unwrap(), info);
+
+        // metadados de outro torrent não passam
+        let (ours, theirs) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(serve_metadata(theirs, HASH, b"d4:name3:abce".to_vec(), 2));
+        let mut peer = Peer::handshake(ours, &HASH, b"-RF0100-abcdefghijkl").await.unwrap();
+        let err = peer.metadata(&HASH).await.unwrap_err();
+        assert!(err.to_string().contains("infohash"), "{err}");
+    }
+}
@@ -0,0 +1,411 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, Path, Query, State},
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ApiError, AppState,
+    auth::Identity,
+    cache::CacheMode,
+    downloads, find_downloaded_file,
+    language::LanguageTag,
+    magnet::Magnet,
+    media::{self, MediaInfo},
+    speedtest,
+    torrentio::{self, Capabilities, StreamInfo},
+};
+
+/// Restrições de reprodução de um dispositivo (seção `[profiles.<nome>]`).
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DeviceProfile {
+    pub containers: Vec<String>,
+    pub video_codecs: Vec<String>,
+    pub audio_codecs: Vec<String>,
+    pub hdr: Vec<String>,
+    pub max_height: Option<u32>,
+    pub max_bitrate_kbps: Option<u64>,
+}
+
+impl DeviceProfile {
+    fn new(
+        containers: &[&str],
+        video_codecs: &[&str],
+        audio_codecs: &[&str],
+        hdr: &[&str],
+        max_height: u32,
+        max_bitrate_kbps: Option<u64>,
+    ) -> Self {
+        let owned = |v: &[&str]| v.iter().map(|s| s.to_string()).collect();
+        DeviceProfile {
+            containers: owned(containers),
+            video_codecs: owned(video_codecs),
+            audio_codecs: owned(audio_codecs),
+            hdr: owned(hdr),
+            max_height: Some(max_height),
+            max_bitrate_kbps,
+        }
+    }
+
+    /// Perfis embutidos; o arquivo de configuração pode sobrescrevê-los.
+    pub fn defaults() -> Vec<(&'static str, DeviceProfile)> {
+        vec![
+            (
+                "browser",
+                DeviceProfile::new(
+                    &["mp4", "webm"],
+                    &["h264", "vp9", "av1"],
+                    &["aac", "mp3", "opus", "vorbis", "flac"],
+                    &[],
+                    2160,
+                    None,
+                ),
+            ),
+            (
+                "chromecast-gen3",
+                DeviceProfile::new(
+                    &["mp4", "webm"],
+                    &["h264", "vp8"],
+                    &["aac", "mp3", "opus", "vorbis", "flac"],
+                    &[],
+                    1080,
+                    Some(20_000),
+                ),
+            ),
+            (
+                "chromecast-ultra",
+                DeviceProfile::new(
+                    &["mp4", "webm"],
+                    &["h264", "h265", "vp9"],
+                    &["aac", "mp3", "opus", "vorbis", "flac", "ac3", "eac3"],
+                    &["hdr10", "hlg", "dv"],
+                    2160,
+                    Some(40_000),
+                ),
+            ),
+            (
+                "webos",
+                DeviceProfile::new(
+                    &["mp4", "mkv", "webm", "ts"],
+                    &["h264", "h265", "vp9", "av1"],
+                    &["aac", "mp3", "opus", "flac", "ac3", "eac3"],
+                    &["hdr10", "hlg", "dv"],
+                    2160,
+                    None,
+                ),
+            ),
+        ]
+    }
+
+    fn allows(list: &[String], value: &str) -> bool {
+        list.iter().any(|v| v.eq_ignore_ascii_case(value))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Method {
+    Direct,
+    Remux,
+    Transcode,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Decision {
+    pub method: Method,
+    pub reasons: Vec<String>,
+}
+
+/// Decide como entregar o arquivo ao dispositivo. Vídeo incompatível (codec,
+/// resolução, bitrate ou HDR) exige transcode; container ou áudio
+/// incompatíveis só exigem remux (o vídeo é copiado). Propriedades
+/// desconhecidas não pesam contra o arquivo.
+pub fn decide(info: &MediaInfo, profile: &DeviceProfile) -> Decision {
+    let mut transcode = Vec::new();
+    let mut remux = Vec::new();
+
+    if let Some(codec) = &info.video_codec
+        && !DeviceProfile::allows(&profile.video_codecs, codec)
+    {
+        transcode.push(format!("codec de vídeo {codec} não suportado"));
+    }
+    if let (Some(h), Some(max)) = (info.height, profile.max_height)
+        && h > max
+    {
+        transcode.push(format!("resolução {h}p acima de {max}p"));
+    }
+    if let (Some(b), Some(max)) = (info.bitrate_kbps, profile.max_bitrate_kbps)
+        && b > max
+    {
+        transcode.push(format!("bitrate {b} kbps acima de {max} kbps"));
+    }
+    for fmt in &info.hdr {
+        let hdr10 = DeviceProfile::allows(&profile.hdr, "hdr10");
+        let ok = DeviceProfile::allows(&profile.hdr, fmt)
+            || (fmt == "hdr10plus" && hdr10)
+            // DV com camada base HDR10 (perfil 8) toca como HDR10
+            || (fmt == "dv" && hdr10 && info.hdr.iter().any(|h| h == "hdr10"));
+        if !ok {
+            transcode.push(format!("{fmt} não suportado"));
+        }
+    }
+
+    if let Some(container) = &info.container
+        && !DeviceProfile::allows(&profile.containers, container)
+    {
+        remux.push(format!("container {container} não suportado"));
+    }
+    if !info.audio_codecs.is_empty()
+        && !info
+            .audio_codecs
+            .iter()
+            .any(|a| DeviceProfile::allows(&profile.audio_codecs, a))
+    {
+        remux.push(format!("nenhuma faixa de áudio suportada ({})", info.audio_codecs.join(", ")));
+    }
+
+    let method = if !transcode.is_empty() {
+        Method::Transcode
+    } else if !remux.is_empty() {
+        Method::Remux
+    } else {
+        Method::Direct
+    };
+    transcode.extend(remux);
+    Decision {
+        method,
+        reasons: transcode,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlayParams {
+    device: String,
+    /// Release específico; sem ele escolhemos o primeiro stream compatível do torrentio.
+    magnet: Option<String>,
+    filename: Option<String>,
+    season: Option<String>,
+    episode: Option<String>,
+    /// Idioma de áudio preferido na escolha do release (padrão
+    /// `PREFERRED_AUDIO_LANG`).
+    audio_lang: Option<String>,
+    /// Banda até este servidor em Mbps; sem ela, a última de
+    /// `POST /speedtest/report` do cliente.
+    bandwidth_mbps: Option<f64>,
+}
+
+/// Banda considerada na escolha do release e de onde ela veio.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Bandwidth {
+    mbps: f64,
+    source: &'static str,
+    max_height: u32,
+}
+
+/// `GET /play/:imdb_id?device=...` — decide entre direct, remux e transcode.
+pub async fn play_decision(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    identity: Identity,
+    Path(imdb_id): Path<String>,
+    Query(params): Query<PlayParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let config = state.config();
+    let profile = config
+        .profiles
+        .get(&params.device.to_ascii_lowercase())
+        .ok_or_else(|| ApiError::BadRequest(format!("perfil de dispositivo desconhecido: {}", params.device)))?;
+
+    let bandwidth = match params.bandwidth_mbps {
+        Some(mbps) if mbps.is_finite() && mbps > 0.0 => Some((mbps, "param")),
+        Some(_) => return Err(ApiError::BadRequest("bandwidth_mbps precisa ser maior que zero".into())),
+        None => speedtest::measured(&state, identity.client_key(client.ip())).await?.map(|mbps| (mbps, "speedtest")),
+    }
+    .map(|(mbps, source)| Bandwidth { mbps, source, max_height: speedtest::max_height(mbps) });
+
+    let (magnet, filename, title_info) = match (&params.magnet, &params.filename) {
+        (Some(m), Some(f)) => {
+            let magnet = Magnet::parse(m).ok_or_else(|| ApiError::BadRequest("magnet inválido".into()))?;
+            (magnet, f.clone(), StreamInfo::parse(f))
+        }
+        (None, None) => pick_stream(&state, &imdb_id, &params, profile, bandwidth).await?,
+        _ => return Err(ApiError::BadRequest("informe magnet e filename juntos".into())),
+    };
+
+    let dir = downloads::job_dir(&state.config().downloads_dir, &magnet.info_hash);
+    let local = find_downloaded_file(&dir, &filename).await;
+    let (info, probed) = match &local {
+        Some(path) => match media::probe(&state, path).await {
+            Ok(info) => (info, true),
+            Err(e) => {
+                tracing::warn!(path = %path.display(), "{e}");
+                (info_from_title(&filename, &title_info), false)
+            }
+        },
+        None => (info_from_title(&filename, &title_info), false),
+    };
+
+    let decision = decide(&info, profile);
+    let url = match decision.method {
+        Method::Direct => Some(format!(
+            "/stream?magnet={}&filename={}",
+            magnet.info_hash,
+            urlencoding::encode(&filename)
+        )),
+        // ainda não há pipeline de HLS para remux/transcode
+        Method::Remux | Method::Transcode => None,
+    };
+
+    Ok(Json(serde_json::json!({
+        "imdb_id": imdb_id,
+        "device": params.device,
+        "method": decision.method,
+        "reasons": decision.reasons,
+        "url": url,
+        "info_hash": magnet.info_hash,
+        "filename": filename,
+        "probed": probed,
+        "release_languages": title_info.languages,
+        "bandwidth": bandwidth,
+        "media": info,
+    })))
+}
+
+/// Primeiro stream do torrentio compatível com o perfil (o torrentio já
+/// ordena por qualidade), de preferência com áudio no idioma preferido e
+/// numa resolução que a banda do cliente comporta; sem compatível, o
+/// primeiro da lista.
+async fn pick_stream(
+    state: &AppState,
+    imdb_id: &str,
+    params: &PlayParams,
+    profile: &DeviceProfile,
+    bandwidth: Option<Bandwidth>,
+) -> Result<(Magnet, String, StreamInfo), ApiError> {
+    let body = match (&params.season, &params.episode) {
+        (Some(s), Some(e)) => torrentio::episode_streams(state, imdb_id, s, e, CacheMode::Normal).await?,
+        _ => torrentio::movie_streams(state, imdb_id, CacheMode::Normal).await?,
+    };
+    let streams = torrentio::parse_streams(&body.value);
+
+    let audio_lang: Option<LanguageTag> = match &params.audio_lang {
+        Some(raw) => Some(raw.parse().map_err(ApiError::BadRequest)?),
+        None => state.config().preferred_audio_lang.clone(),
+    };
+    let caps = Capabilities::from_tokens(profile.video_codecs.iter().chain(&profile.hdr));
+    let candidates: Vec<_> = streams
+        .iter()
+        .filter_map(|s| {
+            let magnet = Magnet::parse(s.info_hash.as_deref()?)?;
+            let filename = s.filename()?;
+            Some((magnet, filename, StreamInfo::from_stream(s)))
+        })
+        .collect();
+
+    let spoken = |info: &StreamInfo| audio_lang.as_ref().is_some_and(|lang| info.languages.has_audio(lang));
+    // resolução desconhecida não pesa contra o release
+    let fits = |info: &StreamInfo| {
+        let height = info.resolution.and_then(|r| r.trim_end_matches('p').parse::<u32>().ok());
+        bandwidth.zip(height).is_none_or(|(b, h)| h <= b.max_height)
+    };
+    let chosen = candidates
+        .iter()
+        .position(|(_, _, info)| caps.supports(info) && fits(info) && spoken(info))
+        .or_else(|| candidates.iter().position(|(_, _, info)| caps.supports(info) && fits(info)))
+        .or_else(|| candidates.iter().position(|(_, _, info)| caps.supports(info) && spoken(info)))
+        .or_else(|| candidates.iter().position(|(_, _, info)| caps.supports(info)))
+        .unwrap_or(0);
+    candidates
+        .into_iter()
+        .nth(chosen)
+        .ok_or_else(|| ApiError::NotFound(format!("nenhum stream para {imdb_id}")))
+}
+
+/// Sem o arquivo em disco, estimamos pelo nome do release.
+fn info_from_title(filename: &str, title: &StreamInfo) -> MediaInfo {
+    MediaInfo {
+        container: media::container_from_extension(&PathBuf::from(filename)),
+        video_codec: title.codec.map(String::from),
+        height: title.resolution.and_then(|r| r.trim_end_matches('p').parse().ok()),
+        hdr: title.hdr.iter().map(|h| h.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(container: &str, video: &str, audio: &[&str], height: u32, bitrate_kbps: u64, hdr: &[&str]) -> MediaInfo {
+        MediaInfo {
+            container: Some(container.into()),
+            video_codec: Some(video.into()),
+            audio_codecs: audio.iter().map(|a| a.to_string()).collect(),
+            height: Some(height),
+            bitrate_kbps: Some(bitrate_kbps),
+            hdr: hdr.iter().map(|h| h.to_string()).collect(),
+            duration_secs: None,
+        }
+    }
+
+    fn profile(name: &str) -> DeviceProfile {
+        DeviceProfile::defaults().into_iter().find(|(n, _)| *n == name).map(|(_, p)| p).unwrap()
+    }
+
+    #[test]
+    fn decision_matrix_for_builtin_profiles() {
+        use Method::{Direct as D, Remux as R, Transcode as T};
+        let profiles = ["browser", "chromecast-gen3", "chromecast-ultra", "webos"];
+        let cases = [
+            ("mp4 h264 aac 1080p", file("mp4", "h264", &["aac"], 1080, 8_000, &[]), [D, D, D, D]),
+            ("mkv h264 aac 1080p", file("mkv", "h264", &["aac"], 1080, 8_000, &[]), [R, R, R, D]),
+            ("mp4 h264 ac3 1080p", file("mp4", "h264", &["ac3"], 1080, 8_000, &[]), [R, R, D, D]),
+            ("mkv h265 eac3 2160p hdr10", file("mkv", "h265", &["eac3"], 2160, 30_000, &["hdr10"]), [T, T, R, D]),
+            ("mp4 h264 aac 2160p 50 Mbps", file("mp4", "h264", &["aac"], 2160, 50_000, &[]), [D, T, T, D]),
+            ("mp4 h265 aac 2160p dv+hdr10", file("mp4", "h265", &["aac"], 2160, 25_000, &["dv", "hdr10"]), [T, T, D, D]),
+            ("mp4 h265 aac 2160p hdr10plus", file("mp4", "h265", &["aac"], 2160, 25_000, &["hdr10plus"]), [T, T, D, D]),
+            ("webm vp9 opus 1080p", file("webm", "vp9", &["opus"], 1080, 5_000, &[]), [D, T, D, D]),
+            ("ts h264 dts+aac 720p", file("ts", "h264", &["dts", "aac"], 720, 4_000, &[]), [R, R, R, D]),
+            ("sem ffprobe", MediaInfo::default(), [D, D, D, D]),
+        ];
+        for (name, info, want) in cases {
+            for (device, want) in profiles.into_iter().zip(want) {
+                let decision = decide(&info, &profile(device));
+                assert_eq!(decision.method, want, "{name} em {device}: {:?}", decision.reasons);
+                assert_eq!(decision.reasons.is_empty(), want == D, "{name} em {device}: {:?}", decision.reasons);
+            }
+        }
+    }
+
+    #[test]
+    fn dolby_vision_plays_as_hdr10_only_with_the_base_layer() {
+        let hdr10_only = DeviceProfile { hdr: vec!["hdr10".into()], ..profile("webos") };
+        let profile8 = file("mkv", "h265", &["aac"], 2160, 20_000, &["dv", "hdr10"]);
+        let profile5 = file("mkv", "h265", &["aac"], 2160, 20_000, &["dv"]);
+        assert_eq!(decide(&profile8, &hdr10_only).method, Method::Direct);
+        let decision = decide(&profile5, &hdr10_only);
+        assert_eq!((decision.method, decision.reasons), (Method::Transcode, vec!["dv não suportado".to_string()]));
+    }
+
+    #[test]
+    fn transcode_reasons_come_before_remux_reasons() {
+        let info = file("avi", "mpeg4", &["dts"], 1080, 8_000, &[]);
+        let decision = decide(&info, &profile("browser"));
+        assert_eq!(decision.method, Method::Transcode);
+        assert_eq!(
+            decision.reasons,
+            [
+                "codec de vídeo mpeg4 não suportado",
+                "container avi não suportado",
+                "nenhuma faixa de áudio suportada (dts)",
+            ]
+        );
+        // o que o perfil não limita não pesa
+        let unlimited = DeviceProfile { max_height: None, max_bitrate_kbps: None, ..profile("chromecast-gen3") };
+        assert_eq!(decide(&file("mp4", "h264", &["aac"], 4320, 900_000, &[]), &unlimited).method, Method::Direct);
+    }
+}
@@ -0,0 +1,302 @@
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    response::IntoResponse,
+};
+use futures_util::future::join_all;
+use moka::future::Cache;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::{Semaphore, mpsc};
+
+use crate::{ApiError, AppState, upstream};
+
+pub const TMDB_IMAGE_BASE: &str = "https://image.tmdb.org/t/p/w500";
+/// URLs esperando verificação; com a fila cheia, as novas são descartadas
+/// (voltam na próxima lista).
+const QUEUE_CAPACITY: usize = 1000;
+/// HEADs simultâneos no total e por host.
+const MAX_CHECKS: usize = 8;
+const MAX_CHECKS_PER_HOST: usize = 2;
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Como conferir os pôsteres do OMDb (`VERIFY_POSTERS`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PosterCheck {
+    /// Usa a URL do OMDb como vem.
+    Off,
+    /// HEAD antes de responder (atrasa a primeira lista).
+    Inline,
+    /// HEAD numa fila em segundo plano; as respostas seguintes usam o resultado.
+    Background,
+}
+
+impl FromStr for PosterCheck {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" | "false" | "0" | "no" => Ok(PosterCheck::Off),
+            "on" | "true" | "1" | "yes" | "inline" => Ok(PosterCheck::Inline),
+            "background" => Ok(PosterCheck::Background),
+            other => Err(format!("esperado off, on ou background, veio {other}")),
+        }
+    }
+}
+
+/// Pôster resolvido por IMDb id (`None`: não há pôster em lugar nenhum).
+/// Guardado por um dia para não repetir o HEAD e a busca no TMDB.
+#[derive(Clone)]
+pub struct PosterCache(Cache<String, Option<String>>);
+
+impl Default for PosterCache {
+    fn default() -> Self {
+        PosterCache(
+            Cache::builder()
+                .max_capacity(20_000)
+                .time_to_live(Duration::from_secs(24 * 3600))
+                .build(),
+        )
+    }
+}
+
+/// Verificação em segundo plano das URLs de pôster: fila limitada, HEADs
+/// com limite por host e o resultado (vivo/morto) por URL durante um dia.
+#[derive(Clone)]
+pub struct PosterValidator {
+    queue: mpsc::Sender<String>,
+    /// `true`: a URL respondeu; `false`: 404/410.
+    status: Cache<String, bool>,
+    /// Na fila ou sendo verificadas, para não enfileirar duas vezes.
+    pending: Arc<Mutex<HashSet<String>>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl PosterValidator {
+    pub fn spawn(http: Client) -> Self {
+        let (queue, rx) = mpsc::channel(QUEUE_CAPACITY);
+        let validator = PosterValidator {
+            queue,
+            status: Cache::builder()
+                .max_capacity(50_000)
+                .time_to_live(Duration::from_secs(24 * 3600))
+                .build(),
+            pending: Default::default(),
+            dropped: Default::default(),
+        };
+        tokio::spawn(validator.clone().run(http, rx));
+        validator
+    }
+
+    /// Resultado já conhecido da URL; `None` se ainda não foi verificada.
+    async fn status(&self, url: &str) -> Option<bool> {
+        self.status.get(url).await
+    }
+
+    /// Põe a URL na fila sem esperar; fila cheia descarta.
+    fn enqueue(&self, url: &str) {
+        if !self.pending.lock().unwrap().insert(url.to_string()) {
+            return;
+        }
+        if self.queue.try_send(url.to_string()).is_err() {
+            self.pending.lock().unwrap().remove(url);
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    async fn run(self, http: Client, mut rx: mpsc::Receiver<String>) {
+        let slots = Arc::new(Semaphore::new(MAX_CHECKS));
+        let mut hosts: HashMap<String, Arc<Semaphore>> = HashMap::new();
+        while let Some(url) = rx.recv().await {
+            let host = reqwest::Url::parse(&url)
+                .ok()
+                .and_then(|u| u.host_str().map(str::to_string))
+                .unwrap_or_default();
+            let per_host = hosts
+                .entry(host)
+                .or_insert_with(|| Arc::new(Semaphore::new(MAX_CHECKS_PER_HOST)))
+                .clone();
+            let Ok(slot) = slots.clone().acquire_owned().await else { break };
+            let validator = self.clone();
+            let http = http.clone();
+            tokio::spawn(async move {
+                let _slot = slot;
+                let Ok(_host_slot) = per_host.acquire_owned().await else { return };
+                // erro de rede não diz nada sobre o pôster: tenta de novo na próxima lista
+                if let Ok(resp) = http.head(&url).timeout(CHECK_TIMEOUT).send().await {
+                    let alive = !matches!(resp.status().as_u16(), 404 | 410);
+                    validator.status.insert(url.clone(), alive).await;
+                }
+                validator.pending.lock().unwrap().remove(&url);
+            });
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbFind {
+    #[serde(default)]
+    movie_results: Vec<TmdbPoster>,
+    #[serde(default)]
+    tv_results: Vec<TmdbPoster>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbPoster {
+    poster_path: Option<String>,
+}
+
+/// Corrige o `Poster` de cada item (`imdbID` + `Poster`) de uma lista: "N/A"
+/// (ou, com `VERIFY_POSTERS`, uma URL que dá 404) vira o pôster do TMDB, e
+/// sem pôster nenhum fica `null`. Com a verificação em segundo plano, os
+/// itens já verificados levam `poster_valid`. Devolve se algum pôster veio
+/// do TMDB (para a atribuição da resposta).
+pub async fn fix_posters(state: &AppState, results: &mut Value) -> bool {
+    let Some(items) = results.as_array_mut() else {
+        return false;
+    };
+    let resolved = join_all(items.iter().map(|item| {
+        let imdb_id = item.get("imdbID").and_then(Value::as_str).map(str::to_string);
+        let poster = item.get("Poster").and_then(Value::as_str).map(str::to_string);
+        async move { resolve(state, imdb_id, poster).await }
+    }))
+    .await;
+    let mut from_tmdb = false;
+    for (item, (poster, valid)) in items.iter_mut().zip(resolved) {
+        if let Some(obj) = item.as_object_mut() {
+            from_tmdb |= poster.is_some() && obj.get("Poster").and_then(Value::as_str) != poster.as_deref();
+            obj.insert("Poster".into(), poster.map_or(Value::Null, Value::String));
+            if let Some(valid) = valid {
+                obj.insert("poster_valid".into(), Value::Bool(valid));
+            }
+        }
+    }
+    from_tmdb
+}
+
+/// Pôster a usar e, na verificação em segundo plano, se o do OMDb está vivo.
+async fn resolve(state: &AppState, imdb_id: Option<String>, poster: Option<String>) -> (Option<String>, Option<bool>) {
+    let usable = poster.filter(|p| !p.is_empty() && p != "N/A");
+    let Some(imdb_id) = imdb_id else {
+        return (usable, None);
+    };
+    let valid = match (&usable, state.config().verify_posters) {
+        (Some(_), PosterCheck::Off) => return (usable, None),
+        (Some(url), PosterCheck::Background) => match state.poster_check.status(url).await {
+            Some(true) => return (usable, Some(true)),
+            Some(false) => Some(false),
+            None => {
+                state.poster_check.enqueue(url);
+                return (usable, None);
+            }
+        },
+        _ => None,
+    };
+    if let Some(cached) = state.posters.0.get(&imdb_id).await {
+        return (cached, valid);
+    }
+
+    let resolved = match usable {
+        // na verificação em segundo plano, chegar aqui com URL é pôster morto
+        Some(url) if valid.is_none() && !is_missing(state, &url).await => Some(url),
+        _ => match tmdb_poster(state, &imdb_id).await {
+            Ok(found) => found,
+            // falha passageira do TMDB: não fica em cache
+            Err(e) => {
+                tracing::debug!(imdb_id, "pôster do TMDB indisponível: {e}");
+                return (None, valid);
+            }
+        },
+    };
+    state.posters.0.insert(imdb_id, resolved.clone()).await;
+    (resolved, valid)
+}
+
+/// HEAD na URL do pôster: só 404/410 contam como ausente.
+async fn is_missing(state: &AppState, url: &str) -> bool {
+    match state.http.head(url).send().await {
+        Ok(resp) => matches!(resp.status().as_u16(), 404 | 410),
+        Err(_) => false,
+    }
+}
+
+async fn tmdb_poster(state: &AppState, imdb_id: &str) -> Result<Option<String>, ApiError> {
+    let url = format!(
+        "{}/find/{}?api_key={}&external_source=imdb_id",
+        state.config().tmdb_base_url,
+        urlencoding::encode(imdb_id),
+        state.tmdb_key
+    );
+    let resp = upstream::get(state, upstream::Service::Tmdb, "/find/:imdb_id", &url)
+        .send()
+        .await
+        .map_err(upstream::send_error)?;
+    if !resp.status().is_success() {
+        return Err(ApiError::Upstream(format!("TMDB: status {}", resp.status())));
+    }
+    let found: TmdbFind = upstream::json(state, resp).await?;
+    Ok(found
+        .movie_results
+        .into_iter()
+        .chain(found.tv_results)
+        .find_map(|r| r.poster_path)
+        .map(|path| format!("{TMDB_IMAGE_BASE}{path}")))
+}
+
+/// Contagens para `GET /admin/stats`.
+pub fn stats(state: &AppState) -> Value {
+    let checker = &state.poster_check;
+    let dead = checker.status.iter().filter(|(_, alive)| !alive).count();
+    serde_json::json!({
+        "resolved": state.posters.0.entry_count(),
+        "checked": checker.status.entry_count(),
+        "dead": dead,
+        "pending": checker.pending.lock().unwrap().len(),
+        "dropped": checker.dropped.load(Ordering::Relaxed),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PosterCacheParams {
+    /// `dead` (padrão) ou `alive`.
+    #[serde(default = "default_status")]
+    status: String,
+}
+
+fn default_status() -> String {
+    "dead".into()
+}
+
+/// `GET /admin/cache/posters?status=dead|alive` — URLs de pôster já
+/// verificadas em segundo plano.
+pub async fn poster_cache(
+    State(state): State<AppState>,
+    Query(params): Query<PosterCacheParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let alive = match params.status.as_str() {
+        "dead" => false,
+        "alive" => true,
+        other => return Err(ApiError::BadRequest(format!("status deve ser dead ou alive, veio {other}"))),
+    };
+    let mut urls: Vec<String> = state
+        .poster_check
+        .status
+        .iter()
+        .filter(|(_, status)| *status == alive)
+        .map(|(url, _)| url.to_string())
+        .collect();
+    urls.sort();
+    Ok(Json(serde_json::json!({ "status": params.status, "urls": urls })))
+}
@@ -0,0 +1,71 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+
+use moka::future::Cache;
+use tokio::sync::Semaphore;
+use tracing::debug;
+
+use crate::{AppState, cache::CacheMode, config::Config, torrentio};
+
+/// Pré-carrega no cache os streams do torrentio de um título assim que a
+/// página de detalhes é aberta, já que o próximo passo do usuário costuma
+/// ser pedir os streams.
+#[derive(Clone)]
+pub struct Prefetcher {
+    enabled: bool,
+    permits: Arc<Semaphore>,
+    per_client_limit: u32,
+    /// Prefetches disparados por cliente (usuário do proxy ou IP, veja
+    /// `Identity::client_key`) no último minuto.
+    per_client: Cache<String, Arc<AtomicU32>>,
+}
+
+impl Prefetcher {
+    pub fn new(config: &Config) -> Self {
+        Prefetcher {
+            enabled: config.prefetch_streams,
+            permits: Arc::new(Semaphore::new(config.prefetch_concurrency)),
+            per_client_limit: config.prefetch_per_client_per_min,
+            per_client: Cache::builder()
+                .time_to_live(Duration::from_secs(60))
+                .max_capacity(10_000)
+                .build(),
+        }
+    }
+
+    /// Dispara em segundo plano, sem bloquear a resposta. Se o limite global
+    /// de concorrência ou o do cliente estiver esgotado, simplesmente não
+    /// pré-carrega.
+    pub async fn movie_streams(&self, state: &AppState, client: String, imdb_id: &str) {
+        if !self.enabled || state.cache.contains_key(&torrentio::movie_key(imdb_id)).await {
+            return;
+        }
+
+        let counter = self
+            .per_client
+            .get_with(client.clone(), async { Arc::new(AtomicU32::new(0)) })
+            .await;
+        if counter.fetch_add(1, Ordering::Relaxed) >= self.per_client_limit {
+            debug!(%client, imdb_id, "prefetch ignorado: limite do cliente");
+            return;
+        }
+        let Ok(permit) = self.permits.clone().try_acquire_owned() else {
+            debug!(imdb_id, "prefetch ignorado: concorrência esgotada");
+            return;
+        };
+
+        let state = state.clone();
+        let imdb_id = imdb_id.to_string();
+        tokio::spawn(async move {
+            let _permit = permit;
+            if let Err(e) = torrentio::movie_streams(&state, &imdb_id, CacheMode::Normal).await {
+                debug!(imdb_id, "prefetch do torrentio falhou: {e}");
+            }
+        });
+    }
+}
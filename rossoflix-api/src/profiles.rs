@@ -0,0 +1,94 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{Json, extract::State, response::IntoResponse};
+use rusqlite::params;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{ApiError, AppState};
+
+/// De quanto em quanto tempo o `last_seen_at` de um usuário ativo é gravado.
+const TOUCH_EVERY: Duration = Duration::from_secs(3600);
+
+/// Usuários do proxy já gravados nesta execução, para não escrever no banco
+/// a cada pedido.
+#[derive(Clone, Default)]
+pub struct KnownProfiles(Arc<Mutex<HashMap<String, Instant>>>);
+
+#[derive(Debug, Serialize)]
+pub struct Profile {
+    name: String,
+    /// Grupos vistos no último pedido gravado.
+    groups: Vec<String>,
+    /// Unix timestamps (s).
+    created_at: i64,
+    last_seen_at: i64,
+}
+
+/// Cria o perfil de `user` na primeira vez que ele aparece e atualiza os
+/// grupos e o `last_seen_at` de tempos em tempos. Fora do caminho do pedido.
+pub fn remember(state: &AppState, user: &str, groups: &[String]) {
+    {
+        let mut known = state.known_profiles.0.lock().unwrap();
+        if known.get(user).is_some_and(|at| at.elapsed() < TOUCH_EVERY) {
+            return;
+        }
+        known.insert(user.to_string(), Instant::now());
+    }
+    let (db, user, groups) = (state.db.clone(), user.to_string(), groups.join(","));
+    tokio::spawn(async move {
+        let now = unix_now();
+        let name = user.clone();
+        let created = db
+            .call(move |conn| {
+                let created = conn.execute(
+                    "INSERT OR IGNORE INTO profiles (name, groups, created_at, last_seen_at) VALUES (?1, ?2, ?3, ?3)",
+                    params![name, groups, now],
+                )?;
+                conn.execute(
+                    "UPDATE profiles SET groups = ?2, last_seen_at = ?3 WHERE name = ?1",
+                    params![name, groups, now],
+                )?;
+                Ok(created == 1)
+            })
+            .await;
+        match created {
+            Ok(true) => info!(user, "perfil criado para o usuário do proxy"),
+            Ok(false) => {}
+            Err(e) => warn!(user, "falha ao gravar o perfil: {e}"),
+        }
+    });
+}
+
+/// `GET /admin/profiles` — perfis criados pelo modo `proxy_headers`.
+pub async fn list_profiles(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
+    let profiles = state
+        .db
+        .call(|conn| {
+            let mut stmt =
+                conn.prepare("SELECT name, groups, created_at, last_seen_at FROM profiles ORDER BY last_seen_at DESC, name")?;
+            stmt.query_map([], |row| {
+                let groups: String = row.get(1)?;
+                Ok(Profile {
+                    name: row.get(0)?,
+                    groups: groups.split(',').filter(|g| !g.is_empty()).map(String::from).collect(),
+                    created_at: row.get(2)?,
+                    last_seen_at: row.get(3)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await?;
+    Ok(Json(serde_json::json!({ "profiles": profiles })))
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,336 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tokio::{fs, sync::watch};
+use tokio_util::sync::CancellationToken;
+
+/// Intervalo entre amostras de progresso.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(3);
+/// Faixas em que o arquivo é dividido em [`Pieces::availability`].
+const AVAILABILITY_BUCKETS: u64 = 100;
+
+/// Progresso estimado de um download ativo.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Progress {
+    pub bytes_done: u64,
+    pub total_bytes: Option<u64>,
+    pub percent: Option<f64>,
+    pub speed_bps: u64,
+    /// Segundos até o fim na velocidade atual; `None` parado ou sem o total.
+    pub eta_secs: Option<u64>,
+    /// Conexões e seeders da última linha de resumo do aria2c.
+    pub peers: Option<u32>,
+    pub seeders: Option<u32>,
+    /// Só com o `.aria2` legível.
+    pub pieces: Option<Pieces>,
+    /// `control_file` (bitfield do `.aria2`) ou `file_size` (tamanho gravado em disco).
+    pub source: &'static str,
+}
+
+/// Peças já baixadas, pelo bitfield do `.aria2`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Pieces {
+    pub done: u64,
+    pub total: u64,
+    /// Percentual baixado de cada faixa igual do arquivo, do começo ao fim
+    /// (até 100 faixas): a barra de progresso com os buracos.
+    pub availability: Vec<u8>,
+}
+
+/// Downloads com aria2c em execução, por infohash.
+#[derive(Clone, Default)]
+pub struct ProgressRegistry {
+    active: Arc<Mutex<HashMap<String, Active>>>,
+}
+
+struct Active {
+    rx: watch::Receiver<Progress>,
+    cancel: CancellationToken,
+}
+
+impl ProgressRegistry {
+    pub fn subscribe(&self, id: &str) -> Option<watch::Receiver<Progress>> {
+        self.active.lock().unwrap().get(id).map(|a| a.rx.clone())
+    }
+
+    pub fn current(&self, id: &str) -> Option<Progress> {
+        self.subscribe(id).map(|rx| rx.borrow().clone())
+    }
+
+    /// Ids e progresso de todos os downloads ativos.
+    pub fn all(&self) -> Vec<(String, Progress)> {
+        let mut all: Vec<_> = self
+            .active
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, a)| (id.clone(), a.rx.borrow().clone()))
+            .collect();
+        all.sort_by(|a, b| a.0.cmp(&b.0));
+        all
+    }
+
+    /// Pede o cancelamento de um download ativo; `false` se não há nenhum.
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.active.lock().unwrap().get(id) {
+            Some(a) => {
+                a.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Começa a amostrar o arquivo de saída de um download. O amostrador
+    /// para (e o download sai do registro) quando o handle é descartado,
+    /// isto é, quando o processo do aria2c termina.
+    pub fn track(&self, id: &str, file: PathBuf, expected_size: Option<u64>) -> ProgressHandle {
+        let (tx, rx) = watch::channel(Progress {
+            total_bytes: expected_size,
+            source: "file_size",
+            ..Default::default()
+        });
+        let cancel = CancellationToken::new();
+        self.active.lock().unwrap().insert(
+            id.to_string(),
+            Active {
+                rx,
+                cancel: cancel.clone(),
+            },
+        );
+
+        let stop = CancellationToken::new();
+        let tx = Arc::new(tx);
+        tokio::spawn(sample(file, expected_size, tx.clone(), stop.clone()));
+        ProgressHandle {
+            registry: self.clone(),
+            id: id.to_string(),
+            tx,
+            stop,
+            cancel,
+        }
+    }
+}
+
+pub struct ProgressHandle {
+    registry: ProgressRegistry,
+    id: String,
+    tx: Arc<watch::Sender<Progress>>,
+    stop: CancellationToken,
+    /// Disparado por [`ProgressRegistry::cancel`].
+    pub cancel: CancellationToken,
+}
+
+impl ProgressHandle {
+    /// Conexões e seeders vistos agora na saída do aria2c; valem até o
+    /// próximo resumo.
+    pub fn set_swarm(&self, peers: Option<u32>, seeders: Option<u32>) {
+        self.tx.send_modify(|p| {
+            p.peers = peers;
+            p.seeders = seeders;
+        });
+    }
+}
+
+impl Drop for ProgressHandle {
+    fn drop(&mut self) {
+        self.stop.cancel();
+        self.registry.active.lock().unwrap().remove(&self.id);
+    }
+}
+
+async fn sample(
+    file: PathBuf,
+    expected_size: Option<u64>,
+    tx: Arc<watch::Sender<Progress>>,
+    stop: CancellationToken,
+) {
+    let control = control_file_path(&file);
+    let mut last: Option<(Instant, u64)> = None;
+    loop {
+        tokio::select! {
+            _ = stop.cancelled() => return,
+            _ = tokio::time::sleep(SAMPLE_INTERVAL) => {}
+        }
+
+        let (bytes_done, total_bytes, pieces, source) = match read_control_file(&control).await {
+            Some(c) => (c.completed_bytes(), Some(c.total_length), Some(c.pieces()), "control_file"),
+            None => (written_bytes(&file).await, expected_size, None, "file_size"),
+        };
+
+        let now = Instant::now();
+        let speed_bps = match last {
+            Some((at, prev)) => {
+                let secs = now.duration_since(at).as_secs_f64();
+                (bytes_done.saturating_sub(prev) as f64 / secs) as u64
+            }
+            None => 0,
+        };
+        last = Some((now, bytes_done));
+
+        let percent = total_bytes
+            .filter(|&t| t > 0)
+            .map(|t| (bytes_done as f64 / t as f64 * 100.0).min(100.0));
+        let eta_secs = total_bytes
+            .filter(|_| speed_bps > 0)
+            .map(|t| t.saturating_sub(bytes_done) / speed_bps);
+        let (peers, seeders) = {
+            let current = tx.borrow();
+            (current.peers, current.seeders)
+        };
+        tx.send_replace(Progress {
+            bytes_done,
+            total_bytes,
+            percent,
+            speed_bps,
+            eta_secs,
+            peers,
+            seeders,
+            pieces,
+            source,
+        });
+    }
+}
+
+/// Bytes efetivamente gravados. Com pré-alocação o tamanho lógico já é o
+/// final, então usamos os blocos alocados quando eles forem menores.
+async fn written_bytes(file: &Path) -> u64 {
+    let Ok(meta) = fs::metadata(file).await else {
+        return 0;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        meta.len().min(meta.blocks() * 512)
+    }
+    #[cfg(not(unix))]
+    {
+        meta.len()
+    }
+}
+
+pub fn control_file_path(file: &Path) -> PathBuf {
+    let mut name = file.file_name().unwrap_or_default().to_os_string();
+    name.push(".aria2");
+    file.with_file_name(name)
+}
+
+/// Campos do arquivo de controle `.aria2` usados para estimar o progresso.
+pub struct ControlFile {
+    piece_length: u64,
+    pub total_length: u64,
+    bitfield: Vec<u8>,
+}
+
+impl ControlFile {
+    pub fn completed_bytes(&self) -> u64 {
+        let pieces: u64 = self.bitfield.iter().map(|b| b.count_ones() as u64).sum();
+        (pieces * self.piece_length).min(self.total_length)
+    }
+
+    pub fn pieces(&self) -> Pieces {
+        let total = match self.piece_length {
+            0 => 0,
+            len => self.total_length.div_ceil(len),
+        };
+        let buckets = total.min(AVAILABILITY_BUCKETS);
+        let availability = (0..buckets)
+            .map(|b| {
+                let (from, to) = (b * total / buckets, (b + 1) * total / buckets);
+                let have = (from..to).filter(|&i| self.has_piece(i)).count() as u64;
+                (have * 100 / (to - from).max(1)) as u8
+            })
+            .collect();
+        let done = (0..total).filter(|&i| self.has_piece(i)).count() as u64;
+        Pieces { done, total, availability }
+    }
+
+    /// O bitfield do aria2 começa pelo bit mais alto do primeiro byte.
+    fn has_piece(&self, index: u64) -> bool {
+        let Some(byte) = self.bitfield.get((index / 8) as usize) else {
+            return false;
+        };
+        byte & (0x80 >> (index % 8)) != 0
+    }
+
+    /// Bytes contíguos já gravados a partir de `offset` (`0` se a peça de
+    /// `offset` ainda falta).
+    pub fn available_from(&self, offset: u64) -> u64 {
+        if self.piece_length == 0 || offset >= self.total_length {
+            return 0;
+        }
+        let mut piece = offset / self.piece_length;
+        while piece * self.piece_length < self.total_length && self.has_piece(piece) {
+            piece += 1;
+        }
+        (piece * self.piece_length).min(self.total_length).saturating_sub(offset)
+    }
+}
+
+/// Arquivo ainda em download: existe o `.aria2` ao lado. `control` fica
+/// `None` quando o controle não pôde ser lido (o aria2c acabou de criá-lo).
+pub struct Partial {
+    pub control: Option<ControlFile>,
+}
+
+/// `None` quando o arquivo está completo (sem `.aria2` ao lado). Vale para
+/// torrents de um arquivo só, em que o controle fica junto do arquivo e as
+/// peças cobrem só ele.
+pub async fn partial(file: &Path) -> Option<Partial> {
+    let control = control_file_path(file);
+    if !fs::try_exists(&control).await.unwrap_or(false) {
+        return None;
+    }
+    Some(Partial {
+        control: read_control_file(&control).await,
+    })
+}
+
+async fn read_control_file(path: &Path) -> Option<ControlFile> {
+    let data = fs::read(path).await.ok()?;
+    parse_control_file(&data)
+}
+
+/// Formato: VER(2) EXT(4) INFOHASH_LEN(4) INFOHASH PIECE_LEN(4) TOTAL_LEN(8)
+/// UPLOAD_LEN(8) BITFIELD_LEN(4) BITFIELD ... A versão 1 usa big-endian; a 0
+/// usa a ordem do host.
+fn parse_control_file(data: &[u8]) -> Option<ControlFile> {
+    let big_endian = match data.get(0..2)? {
+        [0, 1] => true,
+        [0, 0] => cfg!(target_endian = "big"),
+        _ => return None,
+    };
+    let mut pos = 6;
+    let mut take = |n: usize| -> Option<&[u8]> {
+        let slice = data.get(pos..pos + n)?;
+        pos += n;
+        Some(slice)
+    };
+    let u32_at = |b: &[u8]| -> u64 {
+        let b: [u8; 4] = b.try_into().unwrap();
+        if big_endian { u32::from_be_bytes(b) as u64 } else { u32::from_le_bytes(b) as u64 }
+    };
+    let u64_at = |b: &[u8]| -> u64 {
+        let b: [u8; 8] = b.try_into().unwrap();
+        if big_endian { u64::from_be_bytes(b) } else { u64::from_le_bytes(b) }
+    };
+
+    let hash_len = u32_at(take(4)?) as usize;
+    take(hash_len)?;
+    let piece_length = u32_at(take(4)?);
+    let total_length = u64_at(take(8)?);
+    take(8)?;
+    let bitfield_len = u32_at(take(4)?) as usize;
+    let bitfield = take(bitfield_len)?.to_vec();
+
+    Some(ControlFile {
+        piece_length,
+        total_length,
+        bitfield,
+    })
+}
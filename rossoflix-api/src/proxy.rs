@@ -0,0 +1,120 @@
+use std::{future::ready, io, time::Duration};
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use futures_util::StreamExt;
+use reqwest::{Client, Url, redirect};
+use tracing::warn;
+
+use crate::{ApiError, AppState, config::Config, outbound, reload::LiveConfig, upstream};
+
+/// Streams remotos podem durar horas; o timeout padrão do cliente (8s)
+/// cortaria o corpo no meio.
+const PROXY_TIMEOUT: Duration = Duration::from_secs(12 * 3600);
+
+/// Redirecionamentos seguidos por stream, todos dentro da lista.
+const MAX_REDIRECTS: usize = 5;
+
+/// Cabeçalhos do pedido repassados ao upstream.
+const FORWARD_REQUEST: [header::HeaderName; 2] = [header::RANGE, header::IF_RANGE];
+
+/// Cabeçalhos da resposta do upstream devolvidos ao cliente.
+const FORWARD_RESPONSE: [header::HeaderName; 6] = [
+    header::CONTENT_TYPE,
+    header::CONTENT_LENGTH,
+    header::CONTENT_RANGE,
+    header::ACCEPT_RANGES,
+    header::ETAG,
+    header::LAST_MODIFIED,
+];
+
+/// Só hosts de `STREAM_PROXY_HOSTS` (ou subdomínios deles) podem ser
+/// repassados; nunca uma URL arbitrária do cliente.
+pub fn allowed_url(config: &Config, raw: &str) -> Result<Url, ApiError> {
+    let url = Url::parse(raw).map_err(|_| ApiError::BadRequest("url inválida".into()))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(ApiError::BadRequest("url deve ser http(s)".into()));
+    }
+    let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+    let allowed = config
+        .stream_proxy_hosts
+        .iter()
+        .any(|h| host == *h || host.ends_with(&format!(".{h}")));
+    if !allowed {
+        return Err(ApiError::BadRequest(format!("host {host} não permitido para proxy")));
+    }
+    Ok(url)
+}
+
+/// Cliente próprio do proxy: um host permitido não pode mandar o stream
+/// para outro lugar (`127.0.0.1`, metadados de nuvem) com um `30x`, então
+/// cada salto passa de novo por [`allowed_url`], com a lista em vigor. Vale
+/// também para URLs assinadas: a assinatura cobre só o primeiro endereço.
+pub fn client(config: &LiveConfig) -> io::Result<Client> {
+    let live = config.clone();
+    let policy = redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            return attempt.error("redirecionamentos demais");
+        }
+        match allowed_url(&live.load(), attempt.url().as_str()) {
+            Ok(_) => attempt.follow(),
+            Err(_) => {
+                let host = attempt.url().host_str().unwrap_or_default().to_string();
+                attempt.error(format!("redirecionamento para {host}, fora de STREAM_PROXY_HOSTS"))
+            }
+        }
+    });
+    let builder = Client::builder()
+        .user_agent(upstream::USER_AGENT)
+        .connect_timeout(Duration::from_secs(3))
+        .redirect(policy);
+    outbound::apply(builder, &config.load())?.build().map_err(io::Error::other)
+}
+
+/// Repassa o conteúdo remoto sem bufferizar, respeitando `Range`. 200, 206 e
+/// 416 do upstream são devolvidos como vieram; outros status viram 502.
+pub async fn stream_remote(state: &AppState, url: Url, headers: &HeaderMap) -> Result<Response, ApiError> {
+    // identity: sem descompressão automática, para que tamanhos e ranges batam
+    let mut req = state
+        .proxy_http
+        .get(url.clone())
+        .timeout(PROXY_TIMEOUT)
+        .header(header::ACCEPT_ENCODING, "identity");
+    for name in FORWARD_REQUEST {
+        if let Some(value) = headers.get(&name) {
+            req = req.header(name, value);
+        }
+    }
+
+    let upstream = req.send().await.map_err(|e| {
+        // o motivo do redirecionamento recusado fica na fonte do erro
+        let reason = std::error::Error::source(&e).map(|s| format!(": {s}")).unwrap_or_default();
+        ApiError::Upstream(format!("{e}{reason}"))
+    })?;
+    let status = upstream.status();
+    if !matches!(
+        status,
+        StatusCode::OK | StatusCode::PARTIAL_CONTENT | StatusCode::RANGE_NOT_SATISFIABLE
+    ) {
+        return Err(ApiError::Upstream(format!("status {status} de {}", url.host_str().unwrap_or_default())));
+    }
+
+    let mut out = HeaderMap::new();
+    for name in FORWARD_RESPONSE {
+        if let Some(value) = upstream.headers().get(&name) {
+            out.insert(name, value.clone());
+        }
+    }
+
+    // upstream caiu no meio: encerra o corpo em vez de propagar o erro
+    let body = upstream.bytes_stream().take_while(move |chunk| {
+        if let Err(e) = chunk {
+            warn!(%url, "upstream interrompeu o stream: {e}");
+        }
+        ready(chunk.is_ok())
+    });
+    Ok((status, out, Body::from_stream(body)).into_response())
+}
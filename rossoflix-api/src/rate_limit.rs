@@ -0,0 +1,152 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{IpAddr, SocketAddr},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::{
+    ApiError, AppState,
+    auth::{AuthMode, Identity},
+    config::Config,
+};
+
+/// Janela do limite de downloads (`RATE_LIMIT_DOWNLOADS_PER_HOUR`).
+const DOWNLOAD_WINDOW: Duration = Duration::from_secs(3600);
+/// Acima de tantos clientes guardados, os baldes cheios (clientes parados)
+/// são descartados.
+const PRUNE_ABOVE: usize = 10_000;
+/// Rotas fora do limite: os health checks do balanceador.
+const EXEMPT: &[&str] = &["/health"];
+
+/// Balde de fichas de um cliente: enche `RATE_LIMIT_PER_MINUTE` por minuto
+/// até `RATE_LIMIT_BURST`, e cada pedido gasta uma.
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Default)]
+struct Inner {
+    buckets: Mutex<HashMap<String, Bucket>>,
+    /// Downloads começados na última hora, por cliente.
+    downloads: Mutex<HashMap<String, VecDeque<Instant>>>,
+    rejected_requests: AtomicU64,
+    rejected_downloads: AtomicU64,
+}
+
+/// Limites por cliente ([`Identity::client_key`]: o usuário quando há um,
+/// senão o IP) da API pública. O admin não é limitado, a não ser com
+/// `AUTH_MODE=none`, em que todo pedido é admin.
+#[derive(Clone, Default)]
+pub struct RateLimiter(Arc<Inner>);
+
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    clients: usize,
+    /// Recusados com `429` desde a subida.
+    rejected_requests: u64,
+    rejected_downloads: u64,
+}
+
+impl RateLimiter {
+    /// Gasta uma ficha de `client`; sem ficha, o erro diz quando haverá.
+    fn take(&self, client: &str, per_minute: u32, burst: u32) -> Result<(), ApiError> {
+        let rate = per_minute as f64 / 60.0;
+        let burst = burst.max(1) as f64;
+        let now = Instant::now();
+        let mut buckets = self.0.buckets.lock().unwrap();
+        if buckets.len() > PRUNE_ABOVE {
+            buckets.retain(|_, b| b.tokens + now.duration_since(b.updated).as_secs_f64() * rate < burst);
+        }
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket { tokens: burst, updated: now });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate).min(burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        self.0.rejected_requests.fetch_add(1, Ordering::Relaxed);
+        let wait = ((1.0 - bucket.tokens) / rate).ceil().max(1.0) as u64;
+        Err(ApiError::TooManyRequests {
+            message: format!("no máximo {per_minute} pedidos por minuto"),
+            retry_after_secs: wait,
+        })
+    }
+
+    /// Conta um download novo de `client`, se couber nos `per_hour`.
+    fn take_download(&self, client: &str, per_hour: u32) -> Result<(), ApiError> {
+        let mut downloads = self.0.downloads.lock().unwrap();
+        downloads.retain(|_, d| d.back().is_some_and(|at| at.elapsed() < DOWNLOAD_WINDOW));
+        let started = downloads.entry(client.to_string()).or_default();
+        while started.front().is_some_and(|at| at.elapsed() >= DOWNLOAD_WINDOW) {
+            started.pop_front();
+        }
+        if let Some(oldest) = started.front()
+            && started.len() >= per_hour as usize
+        {
+            self.0.rejected_downloads.fetch_add(1, Ordering::Relaxed);
+            let wait = DOWNLOAD_WINDOW.saturating_sub(oldest.elapsed()).as_secs().max(1);
+            return Err(ApiError::TooManyRequests {
+                message: format!("no máximo {per_hour} downloads novos por hora"),
+                retry_after_secs: wait,
+            });
+        }
+        started.push_back(Instant::now());
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            clients: self.0.buckets.lock().unwrap().len(),
+            rejected_requests: self.0.rejected_requests.load(Ordering::Relaxed),
+            rejected_downloads: self.0.rejected_downloads.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Recusa com `429` e `Retry-After` o cliente que passou de
+/// `RATE_LIMIT_PER_MINUTE` (com rajadas de até `RATE_LIMIT_BURST`).
+pub async fn limit_requests(State(state): State<AppState>, identity: Identity, req: Request, next: Next) -> Response {
+    let config = state.config();
+    let path = req.uri().path();
+    if config.rate_limit_per_minute == 0 || exempt_admin(&config, &identity) || EXEMPT.iter().any(|p| path.starts_with(p)) {
+        return next.run(req).await;
+    }
+    let Some(ConnectInfo(peer)) = req.extensions().get::<ConnectInfo<SocketAddr>>().copied() else {
+        return next.run(req).await;
+    };
+    let client = identity.client_key(peer.ip());
+    if let Err(e) = state.rate_limits.take(&client, config.rate_limit_per_minute, config.rate_limit_burst) {
+        tracing::debug!(client, path, "pedido recusado pelo limite do cliente");
+        return e.into_response();
+    }
+    next.run(req).await
+}
+
+/// Antes de começar o download de `info_hash` a pedido de um cliente: conta
+/// em `RATE_LIMIT_DOWNLOADS_PER_HOUR`. Juntar-se a um download que já roda
+/// não conta.
+pub fn check_download(state: &AppState, identity: &Identity, ip: IpAddr, info_hash: &str) -> Result<(), ApiError> {
+    let config = state.config();
+    let per_hour = config.rate_limit_downloads_per_hour;
+    if per_hour == 0 || exempt_admin(&config, identity) || state.download_jobs.is_running(info_hash) {
+        return Ok(());
+    }
+    state.rate_limits.take_download(&identity.client_key(ip), per_hour)
+}
+
+/// Admin de verdade, fora dos limites: com `AUTH_MODE=none` todo mundo é.
+fn exempt_admin(config: &Config, identity: &Identity) -> bool {
+    identity.admin && config.auth_mode != AuthMode::None
+}
@@ -0,0 +1,270 @@
+use std::{
+    io::SeekFrom,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use futures_util::future::BoxFuture;
+use moka::future::Cache;
+use serde::Serialize;
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+    sync::Semaphore,
+};
+use tracing::debug;
+
+/// Leituras antecipadas rodando ao mesmo tempo; além disso a vez é pulada.
+const MAX_CONCURRENT: usize = 4;
+/// Tamanho de cada leitura do aquecimento (o buffer é descartado).
+const CHUNK: usize = 256 * 1024;
+
+/// Quem está lendo o quê: espectadores no mesmo arquivo têm janelas
+/// separadas e não se atrapalham.
+pub type Viewer = (PathBuf, IpAddr);
+
+/// Quem de fato aquece o trecho `start..end` de um arquivo, devolvendo
+/// quantos bytes passaram pelo page cache.
+pub trait Advisor: Send + Sync {
+    fn advise<'a>(&'a self, path: &'a Path, start: u64, end: u64) -> BoxFuture<'a, std::io::Result<u64>>;
+}
+
+/// Leitura num buffer descartável: funciona em qualquer sistema de
+/// arquivos, sem depender de `posix_fadvise`.
+struct BufferedRead;
+
+impl Advisor for BufferedRead {
+    fn advise<'a>(&'a self, path: &'a Path, start: u64, end: u64) -> BoxFuture<'a, std::io::Result<u64>> {
+        Box::pin(warm(path, start, end))
+    }
+}
+
+/// Depois de cada `Range` servido de um arquivo completo, lê em segundo
+/// plano os `READAHEAD_BYTES` seguintes, para que o próximo pedido
+/// sequencial do player ache os dados no page cache do sistema.
+#[derive(Clone)]
+pub struct ReadAhead {
+    /// Última janela aquecida por espectador: `start..end`.
+    windows: Cache<Viewer, (u64, u64)>,
+    permits: Arc<Semaphore>,
+    counters: Arc<Counters>,
+    advisor: Arc<dyn Advisor>,
+}
+
+#[derive(Default)]
+struct Counters {
+    warmed: AtomicU64,
+    warmed_bytes: AtomicU64,
+    /// Leituras puladas por falta de vaga.
+    skipped: AtomicU64,
+    /// Pedidos que começaram dentro de uma janela aquecida, e quantos dos
+    /// bytes servidos já estavam nela.
+    hits: AtomicU64,
+    hit_bytes: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Serialize)]
+pub struct Stats {
+    warmed: u64,
+    warmed_bytes: u64,
+    skipped: u64,
+    hits: u64,
+    hit_bytes: u64,
+    misses: u64,
+}
+
+impl ReadAhead {
+    pub fn new() -> Self {
+        Self::with_advisor(Arc::new(BufferedRead))
+    }
+
+    fn with_advisor(advisor: Arc<dyn Advisor>) -> Self {
+        ReadAhead {
+            windows: Cache::builder()
+                .time_to_idle(Duration::from_secs(600))
+                .max_capacity(10_000)
+                .build(),
+            permits: Arc::new(Semaphore::new(MAX_CONCURRENT)),
+            counters: Default::default(),
+            advisor,
+        }
+    }
+
+    /// Conta se o pedido `start..=end` caiu na janela aquecida do espectador.
+    pub async fn observe(&self, viewer: &Viewer, start: u64, end: u64) {
+        let Some((from, to)) = self.windows.get(viewer).await else {
+            return;
+        };
+        if (from..to).contains(&start) {
+            self.counters.hits.fetch_add(1, Ordering::Relaxed);
+            self.counters.hit_bytes.fetch_add(to.min(end + 1) - start, Ordering::Relaxed);
+        } else {
+            self.counters.misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Chamado quando o corpo de um `Range` terminou em `next - 1`: aquece
+    /// `next..next + bytes` (sem passar de `total`) se ainda não estiver.
+    pub async fn after(&self, viewer: Viewer, next: u64, total: u64, bytes: u64) {
+        let end = next.saturating_add(bytes).min(total);
+        if bytes == 0 || next >= end {
+            return;
+        }
+        if self.windows.get(&viewer).await.is_some_and(|(from, to)| from <= next && end <= to) {
+            return;
+        }
+        let Ok(permit) = self.permits.clone().try_acquire_owned() else {
+            self.counters.skipped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        self.windows.insert(viewer.clone(), (next, end)).await;
+        let (counters, advisor) = (self.counters.clone(), self.advisor.clone());
+        tokio::spawn(async move {
+            let _permit = permit;
+            match advisor.advise(&viewer.0, next, end).await {
+                Ok(read) => {
+                    counters.warmed.fetch_add(1, Ordering::Relaxed);
+                    counters.warmed_bytes.fetch_add(read, Ordering::Relaxed);
+                }
+                Err(e) => debug!(path = %viewer.0.display(), "leitura antecipada falhou: {e}"),
+            }
+        });
+    }
+
+    pub fn stats(&self) -> Stats {
+        let c = &self.counters;
+        Stats {
+            warmed: c.warmed.load(Ordering::Relaxed),
+            warmed_bytes: c.warmed_bytes.load(Ordering::Relaxed),
+            skipped: c.skipped.load(Ordering::Relaxed),
+            hits: c.hits.load(Ordering::Relaxed),
+            hit_bytes: c.hit_bytes.load(Ordering::Relaxed),
+            misses: c.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Lê `start..end` num buffer descartável; o que importa é o page cache.
+async fn warm(path: &Path, start: u64, end: u64) -> std::io::Result<u64> {
+    let mut file = File::open(path).await?;
+    file.seek(SeekFrom::Start(start)).await?;
+    let mut buf = vec![0; CHUNK];
+    let mut read = 0;
+    while read < end - start {
+        let want = CHUNK.min((end - start - read) as usize);
+        match file.read(&mut buf[..want]).await? {
+            0 => break,
+            n => read += n as u64,
+        }
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    type Calls = mpsc::UnboundedReceiver<(PathBuf, u64, u64)>;
+
+    /// Anota cada aviso; com `hold`, cada leitura espera uma vaga dele.
+    struct Recorder {
+        calls: mpsc::UnboundedSender<(PathBuf, u64, u64)>,
+        hold: Option<Arc<Semaphore>>,
+    }
+
+    impl Advisor for Recorder {
+        fn advise<'a>(&'a self, path: &'a Path, start: u64, end: u64) -> BoxFuture<'a, std::io::Result<u64>> {
+            Box::pin(async move {
+                self.calls.send((path.to_path_buf(), start, end)).unwrap();
+                if let Some(hold) = &self.hold {
+                    hold.acquire().await.unwrap().forget();
+                }
+                Ok(end - start)
+            })
+        }
+    }
+
+    fn recorder(hold: Option<Arc<Semaphore>>) -> (ReadAhead, Calls) {
+        let (calls, received) = mpsc::unbounded_channel();
+        (ReadAhead::with_advisor(Arc::new(Recorder { calls, hold })), received)
+    }
+
+    async fn next_call(calls: &mut Calls) -> (PathBuf, u64, u64) {
+        tokio::time::timeout(Duration::from_secs(5), calls.recv()).await.unwrap().unwrap()
+    }
+
+    fn viewer(file: &str, client: u8) -> Viewer {
+        (PathBuf::from(file), IpAddr::from([10, 0, 0, client]))
+    }
+
+    const MB: u64 = 1024 * 1024;
+
+    #[tokio::test]
+    async fn warms_the_next_chunk_of_each_viewer() {
+        let (readahead, mut calls) = recorder(None);
+        let (total, bytes) = (20 * MB, 8 * MB);
+        let alice = viewer("/downloads/a/filme.mkv", 1);
+
+        // o player pediu 0..=2 MB: aquece 2..10 MB
+        readahead.after(alice.clone(), 2 * MB, total, bytes).await;
+        assert_eq!(next_call(&mut calls).await, (alice.0.clone(), 2 * MB, 10 * MB));
+
+        // o pedido seguinte cai na janela: acerto, e nada a aquecer enquanto
+        // o trecho pedido já está coberto
+        readahead.observe(&alice, 2 * MB, 4 * MB - 1).await;
+        readahead.after(alice.clone(), 4 * MB, total, 6 * MB).await;
+
+        // fora da janela: começa onde o pedido terminou e para no fim do arquivo
+        readahead.after(alice.clone(), 14 * MB, total, bytes).await;
+        assert_eq!(next_call(&mut calls).await, (alice.0.clone(), 14 * MB, total));
+        readahead.observe(&alice, 3 * MB, 4 * MB).await;
+
+        // outro cliente no mesmo arquivo tem a janela dele
+        let bob = viewer("/downloads/a/filme.mkv", 2);
+        readahead.after(bob.clone(), 14 * MB, total, bytes).await;
+        assert_eq!(next_call(&mut calls).await, (bob.0, 14 * MB, total));
+
+        // `READAHEAD_BYTES=0` desliga; no fim do arquivo não há o que ler.
+        // Nenhum dos dois avisa: o próximo da fila já é o do outro arquivo
+        readahead.after(alice.clone(), 4 * MB, total, 0).await;
+        readahead.after(alice.clone(), total, total, bytes).await;
+        let other = viewer("/downloads/b/outro.mkv", 1);
+        readahead.after(other.clone(), 0, MB, bytes).await;
+        assert_eq!(next_call(&mut calls).await, (other.0, 0, MB));
+
+        let stats = readahead.stats();
+        assert_eq!((stats.hits, stats.hit_bytes, stats.misses, stats.skipped), (1, 2 * MB, 1, 0));
+    }
+
+    #[tokio::test]
+    async fn busy_slots_skip_instead_of_queueing() {
+        let hold = Arc::new(Semaphore::new(0));
+        let (readahead, mut calls) = recorder(Some(hold.clone()));
+        for client in 0..MAX_CONCURRENT as u8 {
+            readahead.after(viewer("/downloads/a/filme.mkv", client), 0, 100 * MB, 8 * MB).await;
+            next_call(&mut calls).await;
+        }
+        let late = viewer("/downloads/a/filme.mkv", 99);
+        readahead.after(late.clone(), 0, 100 * MB, 8 * MB).await;
+        assert_eq!(readahead.stats().skipped, 1);
+
+        // as leituras terminam, as vagas voltam e a vez pulada não ficou
+        // marcada como aquecida
+        hold.add_permits(MAX_CONCURRENT);
+        while readahead.stats().warmed < MAX_CONCURRENT as u64 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(readahead.stats().warmed_bytes, 4 * 8 * MB);
+        hold.add_permits(1);
+        readahead.after(late.clone(), 0, 100 * MB, 8 * MB).await;
+        assert_eq!(next_call(&mut calls).await, (late.0, 0, 8 * MB));
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{Json, extract::State, response::IntoResponse};
+use serde::Serialize;
+use tokio::fs;
+use tracing::{info, warn};
+
+use crate::{ApiError, AppState, downloads, episode, list_files, magnet::{self, Magnet}, trash};
+
+/// Resultado da última passada de recuperação.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RecoveryReport {
+    /// Unix timestamp (s) da execução.
+    pub ran_at: u64,
+    /// Downloads interrompidos reiniciados em segundo plano.
+    pub resumed: Vec<Interrupted>,
+    /// Downloads interrompidos deixados para o próximo `/stream` (auto-resume desligado).
+    pub interrupted: Vec<Interrupted>,
+    /// Parciais e arquivos de controle órfãos movidos para a lixeira.
+    pub deleted: Vec<String>,
+    /// Downloads concluídos pelo aria2c mas não finalizados antes do crash:
+    /// `.partial` renomeado ou arquivo final que faltava no índice.
+    pub finalized: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Interrupted {
+    pub id: String,
+    pub filename: String,
+}
+
+pub type SharedReport = Arc<RwLock<Option<RecoveryReport>>>;
+
+/// Reconciliação após um crash: um `.aria2` ao lado do arquivo indica
+/// download interrompido (retomável); `.aria2` sem arquivo é lixo; e
+/// arquivos de um download cuja última tentativa falhou, sem `.aria2`,
+/// são parciais irrecuperáveis, levados para a lixeira depois de `max_age`.
+/// Quando a última tentativa terminou com sucesso, o crash foi durante a
+/// finalização: o `.partial` ganha o nome final, e o arquivo final que
+/// faltar no índice é registrado.
+pub async fn run(state: &AppState) {
+    let base = state.config().downloads_dir.clone();
+    let max_age = Duration::from_secs(state.config().recovery_partial_max_age_hours * 3600);
+    let mut report = RecoveryReport {
+        ran_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        ..Default::default()
+    };
+
+    let mut ids = Vec::new();
+    if let Ok(mut entries) = fs::read_dir(&base).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if magnet::is_info_hash(&name) && entry.path().is_dir() {
+                ids.push(name);
+            }
+        }
+    }
+
+    for id in ids {
+        let dir = downloads::job_dir(&base, &id);
+        let files = list_files(&dir).await;
+        let mut has_control = false;
+        let mut discard = Vec::new();
+
+        for (path, _) in &files {
+            if path.extension().is_none_or(|e| e != "aria2") {
+                continue;
+            }
+            has_control = true;
+            let payload = path.with_extension("");
+            if !payload.exists() {
+                discard.push(path.clone());
+                continue;
+            }
+            let filename = relative(&dir, &downloads::final_path(&payload));
+            let entry = Interrupted {
+                id: id.clone(),
+                filename,
+            };
+            if state.config().auto_resume_downloads {
+                resume(state, &entry);
+                report.resumed.push(entry);
+            } else {
+                report.interrupted.push(entry);
+            }
+        }
+
+        let last_exit = last_exit_code(&downloads::log_path(&base, &id)).await;
+        if !has_control && last_exit.as_deref() == Some("0") {
+            finalize(state, &id, &files, &mut report).await;
+        } else if !has_control && last_exit.is_some() {
+            for (path, _) in &files {
+                if older_than(path, max_age).await {
+                    discard.push(path.clone());
+                }
+            }
+        }
+        match trash::discard(&base, &discard).await {
+            Ok(_) => report
+                .deleted
+                .extend(discard.iter().map(|p| p.display().to_string())),
+            Err(e) => warn!(id, "não foi possível mover parciais para a lixeira: {e}"),
+        }
+        // diretórios que ficaram vazios
+        if list_files(&dir).await.is_empty() {
+            let _ = fs::remove_dir_all(&dir).await;
+        }
+    }
+
+    info!(
+        "recuperação: {} retomados, {} interrompidos aguardando, {} removidos, {} finalizados",
+        report.resumed.len(),
+        report.interrupted.len(),
+        report.deleted.len(),
+        report.finalized.len()
+    );
+    *state.recovery.write().unwrap() = Some(report);
+}
+
+/// `GET /admin/recovery` — relatório da última recuperação.
+pub async fn last_report(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
+    let report = state.recovery.read().unwrap().clone();
+    report
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound("recuperação ainda não executada".into()))
+}
+
+fn resume(state: &AppState, entry: &Interrupted) {
+    let Some(magnet) = Magnet::parse(&entry.id) else {
+        return;
+    };
+    let state = state.clone();
+    let filename = entry.filename.clone();
+    tokio::spawn(async move {
+        info!(id = %magnet.info_hash, filename, "retomando download interrompido");
+        if let Err(e) = downloads::run(&state, downloads::Source::Magnet(&magnet), &filename, None).await {
+            warn!(id = %magnet.info_hash, filename, "falha ao retomar: {e}");
+        }
+    });
+}
+
+/// Conclui um download cujo aria2c saiu com sucesso: renomeia os
+/// `.partial` (crash antes do rename) e registra no índice os arquivos
+/// finais que ele não conhece (crash depois do rename).
+async fn finalize(state: &AppState, id: &str, files: &[(PathBuf, u64)], report: &mut RecoveryReport) {
+    let indexed = match state.downloads.by_info_hash(id).await {
+        Ok(found) => found.is_some_and(|d| !d.files.is_empty()),
+        Err(e) => {
+            warn!(id, "falha ao consultar o índice: {e}");
+            return;
+        }
+    };
+    for (path, _) in files {
+        let is_partial = path.to_string_lossy().ends_with(downloads::PARTIAL_SUFFIX);
+        let done = if is_partial {
+            match downloads::finalize(state, id, None, path, None).await {
+                Ok(done) => done,
+                Err(e) => {
+                    warn!(id, path = %path.display(), "falha ao finalizar: {e}");
+                    continue;
+                }
+            }
+        } else if !indexed && episode::is_video(path) && downloads::is_finalized(path).await {
+            if let Err(e) = state.downloads.record(id, None, path).await {
+                warn!(id, path = %path.display(), "falha ao registrar o download no índice: {e}");
+                continue;
+            }
+            Some(path.clone())
+        } else {
+            None
+        };
+        if let Some(done) = done {
+            info!(id, path = %done.display(), "download finalizado na recuperação");
+            report.finalized.push(done.display().to_string());
+        }
+    }
+}
+
+/// Código de saída da última tentativa registrada no log.
+async fn last_exit_code(log: &Path) -> Option<String> {
+    let text = fs::read_to_string(log).await.ok()?;
+    text.lines()
+        .rev()
+        .find_map(|l| Some(l.strip_prefix("--- exit code ")?.strip_suffix(" ---")?.to_string()))
+}
+
+async fn older_than(path: &Path, age: Duration) -> bool {
+    fs::metadata(path)
+        .await
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.elapsed().ok())
+        .is_some_and(|elapsed| elapsed > age)
+}
+
+fn relative(base: &Path, path: &Path) -> String {
+    path.strip_prefix(base).unwrap_or(path).to_string_lossy().into_owned()
+}
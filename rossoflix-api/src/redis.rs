@@ -0,0 +1,168 @@
+use std::{io, sync::Mutex, time::Duration};
+
+use reqwest::Url;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream},
+    net::TcpStream,
+};
+
+/// Prazo de cada comando, conexão incluída: um Redis fora do ar vira miss
+/// rápido em vez de segurar o pedido.
+const TIMEOUT: Duration = Duration::from_millis(500);
+/// Conexões ociosas guardadas para os próximos comandos.
+const MAX_IDLE: usize = 16;
+/// Teto de um valor lido (as maiores entradas são listas de poucos MB).
+const MAX_BULK: usize = 64 * 1024 * 1024;
+
+/// Cliente Redis mínimo (RESP2 sobre TCP, sem TLS): só o que o cache usa.
+/// Cada comando pega uma conexão ociosa ou abre outra; conexões com erro
+/// são descartadas.
+pub struct Client {
+    addr: String,
+    /// `AUTH` e `SELECT` enviados em cada conexão nova.
+    handshake: Vec<Vec<Vec<u8>>>,
+    idle: Mutex<Vec<BufStream<TcpStream>>>,
+}
+
+enum Reply {
+    Status,
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array,
+}
+
+impl Client {
+    /// `redis://[usuário:senha@]host[:porta][/banco]`.
+    pub fn from_url(raw: &str) -> Result<Self, String> {
+        let url = Url::parse(raw).map_err(|e| format!("REDIS_URL inválida: {e}"))?;
+        if url.scheme() != "redis" {
+            return Err(format!("REDIS_URL: esquema {} não suportado (só redis://)", url.scheme()));
+        }
+        let host = url.host_str().ok_or("REDIS_URL sem host")?;
+        let addr = format!("{host}:{}", url.port().unwrap_or(6379));
+        let mut handshake = Vec::new();
+        if let Some(password) = url.password() {
+            let password = urlencoding::decode(password).map_err(|e| e.to_string())?.into_owned();
+            let mut auth = vec![b"AUTH".to_vec()];
+            if !url.username().is_empty() {
+                auth.push(url.username().as_bytes().to_vec());
+            }
+            auth.push(password.into_bytes());
+            handshake.push(auth);
+        }
+        match url.path().trim_start_matches('/') {
+            "" | "0" => {}
+            db => {
+                let db: u32 = db.parse().map_err(|_| format!("REDIS_URL: banco inválido ({db})"))?;
+                handshake.push(vec![b"SELECT".to_vec(), db.to_string().into_bytes()]);
+            }
+        }
+        Ok(Client { addr, handshake, idle: Mutex::default() })
+    }
+
+    pub async fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match self.command(&[b"GET", key.as_bytes()]).await? {
+            Reply::Bulk(value) => Ok(value),
+            _ => Err(unexpected("GET")),
+        }
+    }
+
+    /// `SET` com expiração em milissegundos.
+    pub async fn set_px(&self, key: &str, value: &[u8], ttl: Duration) -> io::Result<()> {
+        let ttl = ttl.as_millis().max(1).to_string();
+        match self.command(&[b"SET", key.as_bytes(), value, b"PX", ttl.as_bytes()]).await? {
+            Reply::Status => Ok(()),
+            _ => Err(unexpected("SET")),
+        }
+    }
+
+    pub async fn exists(&self, key: &str) -> io::Result<bool> {
+        match self.command(&[b"EXISTS", key.as_bytes()]).await? {
+            Reply::Integer(n) => Ok(n > 0),
+            _ => Err(unexpected("EXISTS")),
+        }
+    }
+
+    async fn command(&self, args: &[&[u8]]) -> io::Result<Reply> {
+        tokio::time::timeout(TIMEOUT, async {
+            let idle = self.idle.lock().unwrap().pop();
+            let mut conn = match idle {
+                Some(conn) => conn,
+                None => self.connect().await?,
+            };
+            let reply = roundtrip(&mut conn, args).await?;
+            let mut idle = self.idle.lock().unwrap();
+            if idle.len() < MAX_IDLE {
+                idle.push(conn);
+            }
+            Ok(reply)
+        })
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Redis não respondeu a tempo"))?
+    }
+
+    async fn connect(&self) -> io::Result<BufStream<TcpStream>> {
+        let stream = TcpStream::connect(&self.addr).await?;
+        stream.set_nodelay(true)?;
+        let mut conn = BufStream::new(stream);
+        for command in &self.handshake {
+            let args: Vec<&[u8]> = command.iter().map(Vec::as_slice).collect();
+            roundtrip(&mut conn, &args).await?;
+        }
+        Ok(conn)
+    }
+}
+
+async fn roundtrip(conn: &mut BufStream<TcpStream>, args: &[&[u8]]) -> io::Result<Reply> {
+    let mut out = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg);
+        out.extend_from_slice(b"\r\n");
+    }
+    conn.write_all(&out).await?;
+    conn.flush().await?;
+    read_reply(conn).await
+}
+
+/// Uma resposta. Os arrays (nenhum comando usado os devolve) são lidos só
+/// para não dessincronizar a conexão.
+async fn read_reply(conn: &mut BufStream<TcpStream>) -> io::Result<Reply> {
+    let mut pending = 1;
+    let mut first = None;
+    while pending > 0 {
+        pending -= 1;
+        let mut line = String::new();
+        if conn.read_line(&mut line).await? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Redis fechou a conexão"));
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        let (kind, rest) = line.split_at_checked(1).ok_or_else(|| unexpected("resposta vazia"))?;
+        let reply = match kind {
+            "+" => Reply::Status,
+            "-" => return Err(io::Error::other(format!("Redis: {rest}"))),
+            ":" => Reply::Integer(rest.parse().map_err(|_| unexpected(line))?),
+            "$" => match rest.parse::<i64>().map_err(|_| unexpected(line))? {
+                len if len < 0 => Reply::Bulk(None),
+                len if len as usize > MAX_BULK => return Err(unexpected("valor grande demais")),
+                len => {
+                    let mut buf = vec![0; len as usize + 2];
+                    conn.read_exact(&mut buf).await?;
+                    buf.truncate(len as usize);
+                    Reply::Bulk(Some(buf))
+                }
+            },
+            "*" => {
+                pending += rest.parse::<i64>().map_err(|_| unexpected(line))?.max(0) as usize;
+                Reply::Array
+            }
+            _ => return Err(unexpected(line)),
+        };
+        first.get_or_insert(reply);
+    }
+    first.ok_or_else(|| unexpected("sem resposta"))
+}
+
+fn unexpected(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("resposta inesperada do Redis: {what}"))
+}
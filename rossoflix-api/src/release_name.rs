@@ -0,0 +1,531 @@
+use axum::{Json, extract::Query, response::IntoResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::{ApiError, episode::VIDEO_EXTENSIONS, language};
+
+/// O que dá para tirar do nome de um release ou arquivo
+/// (`The.Matrix.1999.1080p.BluRay.x264-GROUP.mkv`).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ReleaseName {
+    /// Título, com as palavras separadas por espaço.
+    pub title: Option<String>,
+    pub year: Option<u16>,
+    /// Temporada de um episódio ou de um pack (`S02`, `Season 2`).
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+    pub resolution: Option<&'static str>,
+    pub source: Option<&'static str>,
+    pub codec: Option<&'static str>,
+    /// Grupo do release (`-GROUP` no fim ou `[Grupo]` no começo).
+    pub group: Option<String>,
+    /// Idiomas de áudio anunciados, como em `language::detect`.
+    pub language_tags: Vec<&'static str>,
+}
+
+/// Palavras que encerram o título mesmo sem ano, resolução ou episódio.
+const TITLE_STOPWORDS: &[&str] = &[
+    "proper", "repack", "rerip", "internal", "limited", "extended", "remastered", "unrated", "uncut",
+    "directors", "complete", "imax", "hybrid", "remux", "dublado", "dual", "legendado", "nacional", "multi",
+    "subbed", "dubbed", "10bit", "8bit", "hdr", "hdr10", "dv", "atmos", "ddp5", "dd5", "aac", "ac3", "dts",
+];
+
+/// Separa o nome em partes. Tolera nomes sem pontuação, com pontos, com
+/// `_` e com espaços, e uma extensão de vídeo no fim.
+pub fn parse(name: &str) -> ReleaseName {
+    let (stem, _) = split_extension(name.trim());
+    let (stem, group) = split_group(stem);
+    let lower = stem.to_lowercase();
+    let tokens = tokenize(&lower);
+
+    let words: Vec<&str> = stem
+        .split(|c: char| !(c.is_alphanumeric() || matches!(c, '\'' | '-' | '&' | '+')))
+        .filter(|w| !w.is_empty())
+        .collect();
+    let marker = words.iter().position(|w| is_marker(w)).unwrap_or(words.len());
+    let year_at = words[..marker]
+        .iter()
+        .enumerate()
+        .skip(1)
+        .filter(|(_, w)| year(w).is_some())
+        .map(|(i, _)| i)
+        .next_back();
+    let title_end = year_at.unwrap_or(marker);
+    let title = words[..title_end].join(" ");
+
+    let anime = group.is_some() && name.trim_start().starts_with('[');
+    let (season, episode) = episode(&lower, anime);
+
+    ReleaseName {
+        title: (!title.is_empty()).then_some(title),
+        year: year_at.and_then(|i| year(words[i])),
+        season,
+        episode,
+        resolution: resolution(&tokens),
+        source: source(&tokens),
+        codec: codec(&tokens),
+        group,
+        language_tags: language::detect(stem, &tokens).languages,
+    }
+}
+
+/// Tira a extensão quando ela é de vídeo; devolve o resto e a extensão em
+/// minúsculas.
+pub fn split_extension(name: &str) -> (&str, Option<String>) {
+    match name.rsplit_once('.') {
+        Some((stem, ext)) if VIDEO_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()) => {
+            (stem, Some(ext.to_ascii_lowercase()))
+        }
+        _ => (name, None),
+    }
+}
+
+/// Grupo do release: `[Grupo] Nome` (anime) ou `Nome-GRUPO`, ignorando
+/// etiquetas de site no fim (`[rarbg]`).
+fn split_group(stem: &str) -> (&str, Option<String>) {
+    let stem = stem.trim();
+    if let Some(rest) = stem.strip_prefix('[')
+        && let Some((group, rest)) = rest.split_once(']')
+        && !group.trim().is_empty()
+    {
+        return (rest.trim_start(), Some(group.trim().to_string()));
+    }
+    let mut body = stem;
+    while body.ends_with(']')
+        && let Some(open) = body.rfind('[')
+    {
+        body = body[..open].trim_end();
+    }
+    if let Some((rest, group)) = body.rsplit_once('-')
+        && !rest.ends_with(' ')
+        && (2..=24).contains(&group.len())
+        && group.chars().all(|c| c.is_ascii_alphanumeric())
+        && !group.chars().all(|c| c.is_ascii_digit())
+        && !is_marker(group)
+        && !matches!(group.to_ascii_lowercase().as_str(), "dl" | "rip" | "ray")
+    {
+        return (rest, Some(group.to_string()));
+    }
+    (stem, None)
+}
+
+/// Temporada e episódio. Número solto (`Show - 07`) só conta em nomes de
+/// anime, com o grupo entre colchetes no começo.
+fn episode(lower: &str, anime: bool) -> (Option<u32>, Option<u32>) {
+    let refs = numbering(lower);
+    if let Some(r) = refs.iter().filter(|r| r.kind != MatchKind::Absolute).max_by_key(|r| r.kind) {
+        return (r.season, Some(r.episode));
+    }
+    let tokens = tokenize(lower);
+    let season = tokens.iter().enumerate().find_map(|(i, t)| {
+        if matches!(t.as_str(), "season" | "temporada") {
+            return tokens.get(i + 1)?.parse().ok();
+        }
+        let digits = t.strip_prefix('s')?;
+        (!digits.is_empty() && digits.len() <= 2 && digits.bytes().all(|b| b.is_ascii_digit()))
+            .then(|| digits.parse().ok())
+            .flatten()
+    });
+    if season.is_some() {
+        return (season, None);
+    }
+    let absolute = refs.iter().rev().find(|r| r.kind == MatchKind::Absolute).filter(|_| anime);
+    (None, absolute.map(|r| r.episode))
+}
+
+/// Palavra que marca o fim do título: resolução, fonte, codec, episódio,
+/// temporada ou um dos `TITLE_STOPWORDS`.
+fn is_marker(word: &str) -> bool {
+    let lower = word.to_lowercase();
+    let tokens = tokenize(&lower);
+    let season_word = lower
+        .strip_prefix('s')
+        .is_some_and(|d| (1..=2).contains(&d.len()) && d.bytes().all(|b| b.is_ascii_digit()));
+    word == "-"
+        || season_word
+        || matches!(lower.as_str(), "season" | "temporada")
+        || TITLE_STOPWORDS.contains(&lower.as_str())
+        || resolution(&tokens).is_some()
+        || source(&tokens).is_some()
+        || codec(&tokens).is_some()
+        || numbering(&lower).iter().any(|r| r.kind != MatchKind::Absolute)
+}
+
+fn year(word: &str) -> Option<u16> {
+    let year: u16 = word.parse().ok().filter(|_| word.len() == 4)?;
+    (1900..=2099).contains(&year).then_some(year)
+}
+
+/// Separa o título em tokens minúsculos. Pontos, traços, colchetes e quebras
+/// de linha separam; `+` fica para reconhecer `HDR10+`.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !(c.is_alphanumeric() || c == '+'))
+        .filter(|t| !t.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn has(tokens: &[String], token: &str) -> bool {
+    tokens.iter().any(|t| t == token)
+}
+
+fn has_seq(tokens: &[String], a: &str, b: &str) -> bool {
+    tokens.windows(2).any(|w| w[0] == a && w[1] == b)
+}
+
+pub fn resolution(tokens: &[String]) -> Option<&'static str> {
+    let has = |t| has(tokens, t);
+    if has("2160p") || has("4k") || has("uhd") {
+        Some("2160p")
+    } else if has("1080p") {
+        Some("1080p")
+    } else if has("720p") {
+        Some("720p")
+    } else if has("480p") || has("sd") {
+        Some("480p")
+    } else {
+        None
+    }
+}
+
+pub fn codec(tokens: &[String]) -> Option<&'static str> {
+    let has = |t| has(tokens, t);
+    if has("av1") {
+        Some("av1")
+    } else if has("x265") || has("h265") || has("hevc") {
+        Some("h265")
+    } else if has("vp9") {
+        Some("vp9")
+    } else if has("x264") || has("h264") || has("avc") {
+        Some("h264")
+    } else {
+        None
+    }
+}
+
+/// Origem do vídeo, da melhor para a pior quando o nome traz mais de uma.
+pub fn source(tokens: &[String]) -> Option<&'static str> {
+    let has = |t| has(tokens, t);
+    if has("bluray") || has("bdrip") || has("brrip") || has("bdremux") || has_seq(tokens, "blu", "ray") {
+        Some("bluray")
+    } else if has("webdl") || has_seq(tokens, "web", "dl") {
+        Some("web-dl")
+    } else if has("webrip") {
+        Some("webrip")
+    } else if has("web") {
+        Some("web")
+    } else if has("hdtv") || has("pdtv") {
+        Some("hdtv")
+    } else if has("dvdrip") || has("dvd") || has("dvdr") || has("dvd5") || has("dvd9") {
+        Some("dvd")
+    } else if has("hdrip") {
+        Some("hdrip")
+    } else if has("dvdscr") || has("screener") || has("scr") {
+        Some("screener")
+    } else if has("telesync") || has("hdts") || has("ts") {
+        Some("telesync")
+    } else if has("cam") || has("hdcam") || has("camrip") {
+        Some("cam")
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ParseReleaseParams {
+    #[serde(default)]
+    name: String,
+}
+
+/// `GET /parse/release?name=` — o mesmo parse que o servidor usa nos nomes
+/// de release e de arquivo, para o cliente não precisar de outro.
+pub async fn parse_release(Query(params): Query<ParseReleaseParams>) -> Result<impl IntoResponse, ApiError> {
+    if params.name.trim().is_empty() {
+        return Err(ApiError::BadRequest("name vazio".into()));
+    }
+    Ok(Json(serde_json::json!({ "name": params.name, "parsed": parse(&params.name) })))
+}
+
+/// Força do casamento entre um nome de arquivo e o episódio pedido.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MatchKind {
+    /// Número solto no nome (`Show - 07.mkv`), comum em anime.
+    Absolute,
+    /// `E07` sem temporada.
+    EpisodeOnly,
+    /// `S01E07` ou `1x07`.
+    SeasonEpisode,
+}
+
+/// Numeração encontrada num nome de arquivo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpisodeRef {
+    pub season: Option<u32>,
+    pub episode: u32,
+    /// Último episódio de um arquivo com vários (`E01-E03`); o próprio
+    /// `episode` nos demais.
+    pub last: u32,
+    pub kind: MatchKind,
+}
+
+/// Procura padrões `sNNeNN`, `NxNN`, `eNN`/`epNN` e números soltos em
+/// `name` (já em minúsculas), cada um com o intervalo que o segue, se
+/// houver (`s01e01-e03`, `s01e01e02`, `e01-03`, `01-03`).
+pub fn numbering(name: &str) -> Vec<EpisodeRef> {
+    let b = name.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < b.len() {
+        let boundary = i == 0 || !b[i - 1].is_ascii_alphanumeric();
+        let c = b[i];
+
+        // s01e07 / s01.e07
+        if boundary
+            && c == b's'
+            && let Some((season, j)) = digits(b, i + 1, 2)
+        {
+            let mut k = j;
+            if k < b.len() && matches!(b[k], b'.' | b'_' | b'-' | b' ') {
+                k += 1;
+            }
+            if k < b.len()
+                && b[k] == b'e'
+                && let Some((episode, end)) = digits(b, k + 1, 3)
+            {
+                let (last, end) = range_end(b, end, episode).unwrap_or((episode, end));
+                out.push(EpisodeRef {
+                    season: Some(season),
+                    episode,
+                    last,
+                    kind: MatchKind::SeasonEpisode,
+                });
+                i = end;
+                continue;
+            }
+        }
+
+        // e07 / ep07
+        if boundary && c == b'e' {
+            let start = if b.get(i + 1) == Some(&b'p') { i + 2 } else { i + 1 };
+            if let Some((episode, end)) = digits(b, start, 3) {
+                let (last, end) = range_end(b, end, episode).unwrap_or((episode, end));
+                if !b.get(end).is_some_and(|c| c.is_ascii_alphanumeric()) {
+                    out.push(EpisodeRef {
+                        season: None,
+                        episode,
+                        last,
+                        kind: MatchKind::EpisodeOnly,
+                    });
+                    i = end;
+                    continue;
+                }
+            }
+        }
+
+        if boundary && c.is_ascii_digit() {
+            // 1x07
+            if let Some((season, j)) = digits(b, i, 2)
+                && b.get(j) == Some(&b'x')
+                && let Some((episode, end)) = digits(b, j + 1, 3)
+                && !b.get(end).is_some_and(|c| c.is_ascii_digit())
+            {
+                let (last, end) = range_end(b, end, episode).unwrap_or((episode, end));
+                out.push(EpisodeRef {
+                    season: Some(season),
+                    episode,
+                    last,
+                    kind: MatchKind::SeasonEpisode,
+                });
+                i = end;
+                continue;
+            }
+
+            // número solto de 1 a 3 dígitos (anos e resoluções ficam de fora)
+            let end = b[i..].iter().position(|c| !c.is_ascii_digit()).map_or(b.len(), |p| i + p);
+            let next = b.get(end).copied();
+            let standalone = !next.is_some_and(|c| c.is_ascii_alphanumeric());
+            if standalone && end - i <= 3 {
+                let n: u32 = name[i..end].parse().unwrap_or(0);
+                if n > 0 {
+                    // `01-03`: só com o traço, sem o `e`
+                    let (last, end) = match b.get(end) {
+                        Some(b'-') => range_end(b, end, n).unwrap_or((n, end)),
+                        _ => (n, end),
+                    };
+                    out.push(EpisodeRef {
+                        season: None,
+                        episode: n,
+                        last,
+                        kind: MatchKind::Absolute,
+                    });
+                    i = end;
+                    continue;
+                }
+            }
+            i = end;
+            continue;
+        }
+
+        i += 1;
+    }
+    out
+}
+
+/// Fim de um intervalo que começa em `first` e segue em `at`: `-e03`,
+/// `-03` ou `e03`. Só vale crescente e sem letra ou número colado depois
+/// (`-720p` não é intervalo).
+fn range_end(b: &[u8], at: usize, first: u32) -> Option<(u32, usize)> {
+    let mut k = at;
+    let dash = b.get(k) == Some(&b'-');
+    if dash {
+        k += 1;
+    }
+    if b.get(k) == Some(&b'e') {
+        k += 1;
+    } else if !dash {
+        return None;
+    }
+    let (last, end) = digits(b, k, 3)?;
+    if last <= first || b.get(end).is_some_and(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    Some((last, end))
+}
+
+/// Lê de 1 a `max` dígitos a partir de `start`.
+fn digits(b: &[u8], start: usize, max: usize) -> Option<(u32, usize)> {
+    let len = b
+        .get(start..)?
+        .iter()
+        .take(max + 1)
+        .take_while(|c| c.is_ascii_digit())
+        .count();
+    if len == 0 || len > max {
+        return None;
+    }
+    let n = std::str::from_utf8(&b[start..start + len]).ok()?.parse().ok()?;
+    Some((n, start + len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(title: &str, year: Option<u16>, season: Option<u32>, episode: Option<u32>) -> ReleaseName {
+        ReleaseName { title: Some(title.into()), year, season, episode, ..Default::default() }
+    }
+
+    #[test]
+    fn parses_release_names() {
+        let cases = [
+            (
+                "The.Matrix.1999.1080p.BluRay.x264-SPARKS.mkv",
+                ReleaseName {
+                    resolution: Some("1080p"),
+                    source: Some("bluray"),
+                    codec: Some("h264"),
+                    group: Some("SPARKS".into()),
+                    ..release("The Matrix", Some(1999), None, None)
+                },
+            ),
+            (
+                "Blade Runner 2049 (2017) [2160p] [WEB-DL] [x265]",
+                ReleaseName {
+                    resolution: Some("2160p"),
+                    source: Some("web-dl"),
+                    codec: Some("h265"),
+                    ..release("Blade Runner 2049", Some(2017), None, None)
+                },
+            ),
+            (
+                "1917.2019.720p.WEBRip.x264-YTS",
+                ReleaseName {
+                    resolution: Some("720p"),
+                    source: Some("webrip"),
+                    codec: Some("h264"),
+                    group: Some("YTS".into()),
+                    ..release("1917", Some(2019), None, None)
+                },
+            ),
+            (
+                "2001.A.Space.Odyssey.1968.REMASTERED.1080p.BluRay",
+                ReleaseName {
+                    resolution: Some("1080p"),
+                    source: Some("bluray"),
+                    ..release("2001 A Space Odyssey", Some(1968), None, None)
+                },
+            ),
+            (
+                "Breaking.Bad.S01E02.720p.HDTV.x264-CTU",
+                ReleaseName {
+                    resolution: Some("720p"),
+                    source: Some("hdtv"),
+                    codec: Some("h264"),
+                    group: Some("CTU".into()),
+                    ..release("Breaking Bad", None, Some(1), Some(2))
+                },
+            ),
+            (
+                "Dark.1x05.Dublado.WEB-DL.1080p",
+                ReleaseName {
+                    resolution: Some("1080p"),
+                    source: Some("web-dl"),
+                    language_tags: vec!["pt-BR"],
+                    ..release("Dark", None, Some(1), Some(5))
+                },
+            ),
+            (
+                "[SubsPlease] Jujutsu Kaisen - 07 (1080p) [ABCD1234].mkv",
+                ReleaseName {
+                    resolution: Some("1080p"),
+                    group: Some("SubsPlease".into()),
+                    ..release("Jujutsu Kaisen", None, None, Some(7))
+                },
+            ),
+            (
+                "The Office US Season 3 Complete DVDRip",
+                ReleaseName { source: Some("dvd"), ..release("The Office US", None, Some(3), None) },
+            ),
+            (
+                "Game.of.Thrones.S08.2160p.WEB-DL.DUAL.5.1",
+                ReleaseName {
+                    resolution: Some("2160p"),
+                    source: Some("web-dl"),
+                    language_tags: vec!["pt-BR", "en"],
+                    ..release("Game of Thrones", None, Some(8), None)
+                },
+            ),
+            (
+                "Tropa de Elite 2 2010 Nacional 1080p BluRay",
+                ReleaseName {
+                    resolution: Some("1080p"),
+                    source: Some("bluray"),
+                    language_tags: vec!["pt-BR"],
+                    ..release("Tropa de Elite 2", Some(2010), None, None)
+                },
+            ),
+            (
+                "Spider-Man.No.Way.Home.2021.HDCAM-XYZ",
+                ReleaseName {
+                    source: Some("cam"),
+                    group: Some("XYZ".into()),
+                    ..release("Spider-Man No Way Home", Some(2021), None, None)
+                },
+            ),
+            (
+                "Inception.2010.[ENG+POR].1080p.BluRay.H265-Grupo[rarbg]",
+                ReleaseName {
+                    resolution: Some("1080p"),
+                    source: Some("bluray"),
+                    codec: Some("h265"),
+                    group: Some("Grupo".into()),
+                    language_tags: vec!["pt", "en"],
+                    ..release("Inception", Some(2010), None, None)
+                },
+            ),
+        ];
+        for (name, want) in cases {
+            assert_eq!(parse(name), want, "{name}");
+        }
+    }
+}
@@ -0,0 +1,406 @@
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{Json, extract::State, response::IntoResponse};
+use serde::Serialize;
+use tracing::{info, warn};
+use tracing_subscriber::{EnvFilter, Registry, reload};
+
+use crate::{ApiError, AppState, config::Config};
+
+/// Filtro de log padrão quando `RUST_LOG` não está definido.
+pub const DEFAULT_LOG_FILTER: &str = "info";
+
+/// Troca o filtro do `tracing` em tempo de execução.
+pub type LogHandle = reload::Handle<EnvFilter, Registry>;
+
+/// Configuração em uso, trocada inteira a cada recarga: quem chama
+/// [`LiveConfig::load`] recebe a antiga ou a nova, nunca uma mistura.
+#[derive(Clone)]
+pub struct LiveConfig {
+    current: Arc<RwLock<Arc<Config>>>,
+    status: Arc<Mutex<ReloadStatus>>,
+    log: Option<LogHandle>,
+}
+
+/// Resultado da última recarga, mostrado em `GET /admin/config`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReloadStatus {
+    /// Unix timestamp (s) da última recarga; `None` se nunca houve.
+    pub last_reload_at: Option<u64>,
+    /// Campos aplicados na última recarga.
+    pub applied: Vec<&'static str>,
+    /// Campos alterados que só valem reiniciando; o valor antigo continua.
+    pub rejected: Vec<&'static str>,
+    /// Erro da última tentativa, quando a configuração nova não pôde ser lida.
+    pub error: Option<String>,
+    /// Filtro de log em uso.
+    pub log_filter: String,
+}
+
+impl LiveConfig {
+    pub fn new(config: Config, log: Option<LogHandle>) -> Self {
+        let log_filter = std::env::var("RUST_LOG").unwrap_or_else(|_| DEFAULT_LOG_FILTER.into());
+        LiveConfig {
+            current: Arc::new(RwLock::new(Arc::new(config))),
+            status: Arc::new(Mutex::new(ReloadStatus { log_filter, ..Default::default() })),
+            log,
+        }
+    }
+
+    pub fn load(&self) -> Arc<Config> {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn status(&self) -> ReloadStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Relê o `.env` e o `rossoflix.toml` e aplica os campos recarregáveis.
+    /// Uma configuração inválida não muda nada.
+    pub fn reload(&self) -> Result<ReloadStatus, ApiError> {
+        // uma recarga por vez: a segunda espera e compara com o resultado da primeira
+        let mut status = self.status.lock().unwrap();
+        status.last_reload_at = Some(unix_now());
+
+        let _ = dotenvy::dotenv_override();
+        let new = match Config::from_env() {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("recarga da configuração falhou, nada mudou: {e}");
+                status.error = Some(e.to_string());
+                return Err(ApiError::BadRequest(format!("configuração inválida: {e}")));
+            }
+        };
+        let old = self.load();
+        let Merge { config, mut applied, rejected } = merge(&old, new);
+        if !rejected.is_empty() {
+            warn!(fields = rejected.join(","), "configuração: campos que só mudam reiniciando foram ignorados");
+        }
+
+        let log_filter = std::env::var("RUST_LOG").unwrap_or_else(|_| DEFAULT_LOG_FILTER.into());
+        if log_filter != status.log_filter {
+            match (&self.log, EnvFilter::try_new(&log_filter)) {
+                (Some(handle), Ok(filter)) => match handle.reload(filter) {
+                    Ok(()) => {
+                        status.log_filter = log_filter;
+                        applied.push("RUST_LOG");
+                    }
+                    Err(e) => warn!("configuração: falha ao trocar o filtro de log: {e}"),
+                },
+                (None, _) => {}
+                (_, Err(e)) => warn!("configuração: RUST_LOG inválido, filtro mantido: {e}"),
+            }
+        }
+
+        *self.current.write().unwrap() = Arc::new(config);
+        info!(applied = applied.join(","), "configuração recarregada");
+        status.applied = applied;
+        status.rejected = rejected;
+        status.error = None;
+        Ok(status.clone())
+    }
+}
+
+struct Merge {
+    config: Config,
+    applied: Vec<&'static str>,
+    rejected: Vec<&'static str>,
+}
+
+/// Divide os campos de [`Config`] entre os lidos a cada uso (trocam na
+/// hora) e os consumidos na subida (sockets, banco, clientes HTTP, tarefas
+/// de fundo). Sem `..` no padrão: um campo novo não compila até entrar numa
+/// das listas.
+macro_rules! classify {
+    (reloadable: [$($r:ident),* $(,)?], restart: [$($f:ident),* $(,)?] $(,)?) => {
+        fn merge(old: &Config, new: Config) -> Merge {
+            let Config { $($r: _,)* $($f: _,)* } = old;
+            let mut config = new;
+            let mut applied = Vec::new();
+            let mut rejected = Vec::new();
+            $(
+                if old.$r != config.$r {
+                    applied.push(stringify!($r));
+                }
+            )*
+            $(
+                if old.$f != config.$f {
+                    rejected.push(stringify!($f));
+                    config.$f = old.$f.clone();
+                }
+            )*
+            Merge { config, applied, rejected }
+        }
+    };
+}
+
+classify! {
+    reloadable: [
+        bt_trackers,
+        bt_trackers_fallback,
+        omdb_base_url,
+        tmdb_base_url,
+        opensubtitles_base_url,
+        download_engine,
+        aria2_file_allocation,
+        sequential_downloads,
+        device_profiles,
+        profiles,
+        stream_proxy_hosts,
+        stream_signing_key,
+        stream_signing_key_previous,
+        stream_signature_skew_secs,
+        opensubtitles_api_key,
+        omdb_timeout_secs,
+        tmdb_timeout_secs,
+        torrentio_timeout_secs,
+        opensubtitles_timeout_secs,
+        request_deadline_ms,
+        request_deadline_routes,
+        trending_filter_max_pages,
+        trending_concurrency,
+        trending_title_timeout_ms,
+        search_enrich_budget_ms,
+        readahead_bytes,
+        file_handle_cache_size,
+        media_queue_max,
+        media_job_timeout_secs,
+        media_wait_secs,
+        shed_max_in_flight,
+        shed_p95_ms,
+        shed_queue_depth,
+        speedtest_max_bytes,
+        speedtest_per_minute,
+        rate_limit_per_minute,
+        rate_limit_burst,
+        rate_limit_downloads_per_hour,
+        library_refresh_max,
+        omdb_daily_limit,
+        max_upstream_body_bytes,
+        playable_enrichment,
+        verify_posters,
+        admin_token,
+        api_keys,
+        auth_mode,
+        trusted_proxies,
+        auth_user_header,
+        auth_groups_header,
+        auth_admin_group,
+        audit_max_entries,
+        allow_cache_bypass,
+        legacy_error_body,
+        watch_interval_secs,
+        preferred_audio_lang,
+        watched_threshold_percent,
+        delete_after_watch,
+        downloads_max_bytes,
+        downloads_max_idle_days,
+        stats_enabled,
+        stats_raw_retention_hours,
+        cache_ttl_search_secs,
+        cache_ttl_detail_secs,
+        cache_ttl_trending_secs,
+        cache_ttl_torrentio_secs,
+    ],
+    restart: [
+        omdb_api_key,
+        tmdb_api_key,
+        port,
+        bind_ip,
+        admin_addr,
+        downloads_dir,
+        database_path,
+        torrentio_base_urls,
+        torrentio_view_cache_secs,
+        prefetch_streams,
+        prefetch_concurrency,
+        prefetch_per_client_per_min,
+        warm_omdb_per_min,
+        auto_resume_downloads,
+        recovery_partial_max_age_hours,
+        trash_retention_hours,
+        outbound_proxy,
+        proxy_hosts,
+        metadata_priority,
+        audio_max_extractions,
+        hls_max_transcodes,
+        media_workers,
+        max_concurrent_downloads,
+        watch_dir,
+        scratch_dir,
+        scratch_idle_ttl_minutes,
+        scratch_budget_bytes,
+        party_idle_minutes,
+        telegram_bot_token,
+        telegram_chat_id,
+        cache_backend,
+        redis_url,
+        redis_key_prefix,
+    ],
+}
+
+/// Recarrega a cada `SIGHUP`.
+#[cfg(unix)]
+pub fn spawn_sighup_listener(live: LiveConfig) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(stream) => stream,
+        Err(e) => {
+            warn!("configuração: não foi possível escutar SIGHUP: {e}");
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            info!("SIGHUP recebido, recarregando a configuração");
+            let live = live.clone();
+            // lê arquivos: fora das threads do runtime
+            let _ = tokio::task::spawn_blocking(move || live.reload()).await;
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn spawn_sighup_listener(_live: LiveConfig) {}
+
+/// `POST /admin/config/reload` — mesma recarga do `SIGHUP`.
+pub async fn reload_config(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
+    let live = state.config.clone();
+    let status = tokio::task::spawn_blocking(move || live.reload())
+        .await
+        .map_err(|_| ApiError::Internal)??;
+    Ok(Json(status))
+}
+
+/// `GET /admin/config` — configuração efetiva (segredos omitidos) e o
+/// resultado da última recarga.
+pub async fn effective_config(State(state): State<AppState>) -> impl IntoResponse {
+    let config = state.config();
+    let set = |secret: &Option<String>| secret.is_some();
+    let secrets_set = serde_json::json!({
+        "stream_signing_key": set(&config.stream_signing_key),
+        "stream_signing_key_previous": set(&config.stream_signing_key_previous),
+        "opensubtitles_api_key": set(&config.opensubtitles_api_key),
+        "admin_token": set(&config.admin_token),
+        "outbound_proxy": set(&config.outbound_proxy),
+        "telegram_bot_token": set(&config.telegram_bot_token),
+        // pode levar a senha
+        "redis_url": set(&config.redis_url),
+    });
+    let auth = serde_json::json!({
+        "mode": config.auth_mode,
+        "api_keys": config.api_keys.iter().map(|k| &k.name).collect::<Vec<_>>(),
+        "trusted_proxies": config.trusted_proxies.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        "user_header": config.auth_user_header,
+        "groups_header": config.auth_groups_header,
+        "admin_group": config.auth_admin_group,
+    });
+    Json(serde_json::json!({
+        "config": {
+            "port": config.port,
+            "bind_ip": config.bind_ip,
+            "admin_addr": config.admin_addr,
+            "downloads_dir": config.downloads_dir,
+            "database_path": config.database_path,
+            "scratch_dir": config.scratch_dir,
+            "torrentio_base_urls": config.torrentio_base_urls,
+            "torrentio_view_cache_secs": config.torrentio_view_cache_secs,
+            "omdb_base_url": config.omdb_base_url,
+            "tmdb_base_url": config.tmdb_base_url,
+            "opensubtitles_base_url": config.opensubtitles_base_url,
+            "bt_trackers": config.bt_trackers,
+            "bt_trackers_fallback": config.bt_trackers_fallback,
+            "download_engine": config.download_engine,
+            "aria2_file_allocation": config.aria2_file_allocation,
+            "sequential_downloads": config.sequential_downloads,
+            "max_concurrent_downloads": config.max_concurrent_downloads,
+            "device_profiles": config.device_profiles,
+            "profiles": config.profiles,
+            "stream_proxy_hosts": config.stream_proxy_hosts,
+            "stream_signature_skew_secs": config.stream_signature_skew_secs,
+            "proxy_hosts": config.proxy_hosts,
+            "timeouts_secs": {
+                "omdb": config.omdb_timeout_secs,
+                "tmdb": config.tmdb_timeout_secs,
+                "torrentio": config.torrentio_timeout_secs,
+                "opensubtitles": config.opensubtitles_timeout_secs,
+            },
+            "request_deadline_ms": config.request_deadline_ms,
+            "request_deadline_routes": config.request_deadline_routes,
+            "metadata_priority": config.metadata_priority,
+            "trending_filter_max_pages": config.trending_filter_max_pages,
+            "trending_concurrency": config.trending_concurrency,
+            "trending_title_timeout_ms": config.trending_title_timeout_ms,
+            "search_enrich_budget_ms": config.search_enrich_budget_ms,
+            "library_refresh_max": config.library_refresh_max,
+            "omdb_daily_limit": config.omdb_daily_limit,
+            "max_upstream_body_bytes": config.max_upstream_body_bytes,
+            "playable_enrichment": config.playable_enrichment,
+            "verify_posters": config.verify_posters,
+            "prefetch_streams": config.prefetch_streams,
+            "readahead_bytes": config.readahead_bytes,
+            "file_handle_cache_size": config.file_handle_cache_size,
+            "hls_max_transcodes": config.hls_max_transcodes,
+            "media_queue": {
+                "workers": config.media_workers,
+                "max": config.media_queue_max,
+                "job_timeout_secs": config.media_job_timeout_secs,
+                "wait_secs": config.media_wait_secs,
+            },
+            "shed": {
+                "max_in_flight": config.shed_max_in_flight,
+                "p95_ms": config.shed_p95_ms,
+                "queue_depth": config.shed_queue_depth,
+            },
+            "speedtest": {
+                "max_bytes": config.speedtest_max_bytes,
+                "per_minute": config.speedtest_per_minute,
+            },
+            "rate_limit": {
+                "per_minute": config.rate_limit_per_minute,
+                "burst": config.rate_limit_burst,
+                "downloads_per_hour": config.rate_limit_downloads_per_hour,
+            },
+            "auth": auth,
+            "audit_max_entries": config.audit_max_entries,
+            "allow_cache_bypass": config.allow_cache_bypass,
+            "cache": {
+                "backend": config.cache_backend,
+                "redis_key_prefix": config.redis_key_prefix,
+                "ttl_secs": {
+                    "search": config.cache_ttl_search_secs,
+                    "detail": config.cache_ttl_detail_secs,
+                    "trending": config.cache_ttl_trending_secs,
+                    "torrentio": config.cache_ttl_torrentio_secs,
+                },
+            },
+            "legacy_error_body": config.legacy_error_body,
+            "preferred_audio_lang": config.preferred_audio_lang,
+            "watch_dir": config.watch_dir,
+            "watch_interval_secs": config.watch_interval_secs,
+            "watched_threshold_percent": config.watched_threshold_percent,
+            "delete_after_watch": config.delete_after_watch,
+            "storage": {
+                "max_bytes": config.downloads_max_bytes,
+                "max_idle_days": config.downloads_max_idle_days,
+            },
+            "stats": {
+                "enabled": config.stats_enabled,
+                "raw_retention_hours": config.stats_raw_retention_hours,
+            },
+            "secrets_set": secrets_set,
+        },
+        "reload": state.config.status(),
+    }))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
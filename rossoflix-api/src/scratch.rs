@@ -0,0 +1,373 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use axum::{Json, extract::State, response::IntoResponse};
+use serde::Serialize;
+use tokio::fs;
+use tracing::{info, warn};
+
+use crate::{AppState, list_files};
+
+/// Intervalo entre as varreduras de sessões expiradas e do orçamento.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Espaço temporário das saídas de transcodificação (áudio extraído,
+/// segmentos HLS...), uma pasta por sessão em `SCRATCH_DIR/<sessão>/`.
+/// Sessões ociosas há mais de `SCRATCH_IDLE_TTL_MINUTES` são apagadas, e
+/// passando de `SCRATCH_BUDGET_BYTES` as ociosas mais antigas saem primeiro.
+/// Sessões em uso nunca são removidas.
+#[derive(Clone)]
+pub struct ScratchSpace(Arc<Inner>);
+
+struct Inner {
+    root: PathBuf,
+    idle_ttl: Duration,
+    budget_bytes: u64,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+struct Session {
+    /// Guardas vivas (processos ou respostas usando a pasta).
+    active: usize,
+    last_used: SystemTime,
+}
+
+/// Uso de uma sessão; enquanto existir, a pasta não é removida.
+pub struct SessionGuard {
+    space: ScratchSpace,
+    id: String,
+    dir: PathBuf,
+}
+
+impl SessionGuard {
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        let mut sessions = self.space.0.sessions.lock().unwrap();
+        if let Some(session) = sessions.get_mut(&self.id) {
+            session.active = session.active.saturating_sub(1);
+            session.last_used = SystemTime::now();
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PurgeReport {
+    pub removed: usize,
+    pub freed_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScratchUsage {
+    pub dir: PathBuf,
+    pub budget_bytes: u64,
+    pub used_bytes: u64,
+    pub sessions: usize,
+    pub active_sessions: usize,
+}
+
+impl ScratchSpace {
+    pub fn new(root: PathBuf, idle_ttl: Duration, budget_bytes: u64) -> Self {
+        ScratchSpace(Arc::new(Inner {
+            root,
+            idle_ttl,
+            budget_bytes,
+            sessions: Default::default(),
+        }))
+    }
+
+    /// Adota as pastas deixadas pela execução anterior como sessões ociosas
+    /// (pela data de modificação), para que expirem normalmente.
+    pub async fn adopt_existing(&self) {
+        let Ok(mut dirs) = fs::read_dir(&self.0.root).await else {
+            return;
+        };
+        let mut adopted = Vec::new();
+        while let Ok(Some(dir)) = dirs.next_entry().await {
+            let Ok(meta) = dir.metadata().await else {
+                continue;
+            };
+            let name = dir.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') {
+                // remoção interrompida pela parada anterior
+                let _ = fs::remove_dir_all(dir.path()).await;
+            } else if meta.is_dir() {
+                let last_used = meta.modified().unwrap_or_else(|_| SystemTime::now());
+                adopted.push((name, last_used));
+            }
+        }
+        let mut sessions = self.0.sessions.lock().unwrap();
+        for (id, last_used) in adopted {
+            sessions.entry(id).or_insert(Session { active: 0, last_used });
+        }
+    }
+
+    /// Abre (ou cria) a sessão `id` e marca o uso até a guarda cair.
+    pub async fn session(&self, id: &str) -> io::Result<SessionGuard> {
+        if id.is_empty() || id.contains(['/', '\\']) || id.starts_with('.') {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("sessão inválida: {id}")));
+        }
+        let dir = self.0.root.join(id);
+        {
+            let mut sessions = self.0.sessions.lock().unwrap();
+            let session = sessions.entry(id.to_string()).or_insert(Session {
+                active: 0,
+                last_used: SystemTime::now(),
+            });
+            session.active += 1;
+            session.last_used = SystemTime::now();
+        }
+        let guard = SessionGuard {
+            space: self.clone(),
+            id: id.to_string(),
+            dir,
+        };
+        fs::create_dir_all(&guard.dir).await?;
+        Ok(guard)
+    }
+
+    /// Remove as sessões ociosas expiradas e, se o total ainda passar do
+    /// orçamento, as ociosas mais antigas até caber.
+    pub async fn sweep(&self) -> PurgeReport {
+        let now = SystemTime::now();
+        let mut idle = self.idle_sessions();
+        idle.sort_by_key(|(_, last_used)| *last_used);
+
+        let mut sizes = HashMap::new();
+        let mut used = 0;
+        for (id, _) in self.all_sessions() {
+            let size = dir_size(&self.0.root.join(&id)).await;
+            used += size;
+            sizes.insert(id, size);
+        }
+
+        let mut report = PurgeReport::default();
+        for (id, last_used) in idle {
+            let expired = now.duration_since(last_used).unwrap_or_default() >= self.0.idle_ttl;
+            if !expired && used <= self.0.budget_bytes {
+                continue;
+            }
+            let size = sizes.get(&id).copied().unwrap_or_default();
+            if self.remove_if_idle(&id).await {
+                used = used.saturating_sub(size);
+                report.removed += 1;
+                report.freed_bytes += size;
+                let why = if expired { "ociosa" } else { "orçamento" };
+                info!(session = id, size_bytes = size, "scratch: sessão removida ({why})");
+            }
+        }
+        if used > self.0.budget_bytes {
+            warn!(used, budget = self.0.budget_bytes, "scratch acima do orçamento, só com sessões em uso");
+        }
+        report
+    }
+
+    /// Remove todas as sessões ociosas (`POST /admin/scratch/purge`).
+    pub async fn purge_idle(&self) -> PurgeReport {
+        let mut report = PurgeReport::default();
+        for (id, _) in self.idle_sessions() {
+            let size = dir_size(&self.0.root.join(&id)).await;
+            if self.remove_if_idle(&id).await {
+                report.removed += 1;
+                report.freed_bytes += size;
+            }
+        }
+        info!(removed = report.removed, freed_bytes = report.freed_bytes, "scratch: limpeza manual");
+        report
+    }
+
+    pub async fn usage(&self) -> ScratchUsage {
+        let sessions = self.all_sessions();
+        let mut used_bytes = 0;
+        for (id, _) in &sessions {
+            used_bytes += dir_size(&self.0.root.join(id)).await;
+        }
+        ScratchUsage {
+            dir: self.0.root.clone(),
+            budget_bytes: self.0.budget_bytes,
+            used_bytes,
+            sessions: sessions.len(),
+            active_sessions: sessions.iter().filter(|(_, active)| *active).count(),
+        }
+    }
+
+    fn all_sessions(&self) -> Vec<(String, bool)> {
+        let sessions = self.0.sessions.lock().unwrap();
+        sessions.iter().map(|(id, s)| (id.clone(), s.active > 0)).collect()
+    }
+
+    fn idle_sessions(&self) -> Vec<(String, SystemTime)> {
+        let sessions = self.0.sessions.lock().unwrap();
+        sessions
+            .iter()
+            .filter(|(_, s)| s.active == 0)
+            .map(|(id, s)| (id.clone(), s.last_used))
+            .collect()
+    }
+
+    /// Tira a sessão do registro só se ninguém a abriu nesse meio-tempo. A
+    /// pasta é renomeada ainda sob o lock, então uma reabertura logo depois
+    /// começa numa pasta nova em vez de perder arquivos para a remoção.
+    async fn remove_if_idle(&self, id: &str) -> bool {
+        let doomed = self.0.root.join(format!(".{id}.removing"));
+        {
+            let mut sessions = self.0.sessions.lock().unwrap();
+            if sessions.get(id).is_none_or(|s| s.active > 0) {
+                return false;
+            }
+            match std::fs::rename(self.0.root.join(id), &doomed) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    sessions.remove(id);
+                    return true;
+                }
+                Err(e) => {
+                    warn!(session = id, "falha ao limpar o scratch: {e}");
+                    return false;
+                }
+            }
+            sessions.remove(id);
+        }
+        if let Err(e) = fs::remove_dir_all(&doomed).await {
+            warn!(dir = %doomed.display(), "falha ao limpar o scratch: {e}");
+        }
+        true
+    }
+}
+
+async fn dir_size(dir: &Path) -> u64 {
+    list_files(dir).await.iter().map(|(_, size)| size).sum()
+}
+
+/// Varredura periódica do scratch, em segundo plano.
+pub fn spawn_sweeper(space: ScratchSpace) {
+    tokio::spawn(async move {
+        space.adopt_existing().await;
+        let mut tick = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            tick.tick().await;
+            space.sweep().await;
+        }
+    });
+}
+
+/// `POST /admin/scratch/purge` — apaga já todas as sessões ociosas.
+pub async fn purge_scratch(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.scratch.purge_idle().await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KB: u64 = 1024;
+
+    fn root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("rossoflix-scratch-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        root
+    }
+
+    /// Abre a sessão, grava `size` bytes nela e devolve a guarda.
+    async fn fill(space: &ScratchSpace, id: &str, size: u64) -> SessionGuard {
+        let guard = space.session(id).await.unwrap();
+        fs::write(guard.dir().join("seg00000.ts"), vec![0; size as usize]).await.unwrap();
+        // `last_used` distintos, para a ordem das ociosas
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        guard
+    }
+
+    fn remaining(root: &Path) -> Vec<String> {
+        let mut names: Vec<_> = std::fs::read_dir(root)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn budget_pressure_evicts_the_oldest_idle_sessions() {
+        let root = root("budget");
+        let space = ScratchSpace::new(root.clone(), Duration::from_secs(3600), 300 * KB);
+
+        drop(fill(&space, "a", 100 * KB).await);
+        let busy = fill(&space, "b", 100 * KB).await;
+        drop(fill(&space, "c", 100 * KB).await);
+        drop(fill(&space, "d", 100 * KB).await);
+        assert_eq!(space.usage().await.used_bytes, 400 * KB);
+
+        // 400 KB para um orçamento de 300: sai só a ociosa mais antiga
+        let report = space.sweep().await;
+        assert_eq!((report.removed, report.freed_bytes), (1, 100 * KB));
+        assert_eq!(remaining(&root), ["b", "c", "d"]);
+
+        // `b` é mais antiga que `c`, mas está em uso
+        drop(fill(&space, "e", 150 * KB).await);
+        let report = space.sweep().await;
+        assert_eq!((report.removed, report.freed_bytes), (2, 200 * KB));
+        assert_eq!(remaining(&root), ["b", "e"]);
+
+        // dentro do orçamento, nada sai
+        assert_eq!(space.sweep().await.removed, 0);
+        let usage = space.usage().await;
+        assert_eq!((usage.used_bytes, usage.sessions, usage.active_sessions), (250 * KB, 2, 1));
+
+        // só sessões em uso acima do orçamento: ficam todas
+        let big = fill(&space, "f", 400 * KB).await;
+        assert_eq!(space.sweep().await.removed, 1);
+        assert_eq!(remaining(&root), ["b", "f"]);
+        drop((busy, big));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[tokio::test]
+    async fn idle_ttl_and_manual_purge() {
+        let root = root("ttl");
+        let space = ScratchSpace::new(root.clone(), Duration::from_millis(50), u64::MAX);
+        drop(fill(&space, "old", KB).await);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        drop(fill(&space, "new", KB).await);
+        let busy = fill(&space, "busy", KB).await;
+
+        let report = space.sweep().await;
+        assert_eq!(report.removed, 1);
+        assert_eq!(remaining(&root), ["busy", "new"]);
+
+        let report = space.purge_idle().await;
+        assert_eq!((report.removed, report.freed_bytes), (1, KB));
+        assert_eq!(remaining(&root), ["busy"]);
+
+        // reaberta depois da limpeza, começa vazia
+        drop(busy);
+        space.purge_idle().await;
+        let again = space.session("busy").await.unwrap();
+        assert_eq!(space.usage().await.used_bytes, 0);
+        drop(again);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[tokio::test]
+    async fn adopts_leftovers_from_the_previous_run() {
+        let root = root("adopt");
+        std::fs::create_dir_all(root.join("job-1")).unwrap();
+        std::fs::write(root.join("job-1").join("seg00000.ts"), vec![0; KB as usize]).unwrap();
+        std::fs::create_dir_all(root.join(".job-0.removing")).unwrap();
+        let space = ScratchSpace::new(root.clone(), Duration::from_secs(3600), u64::MAX);
+        space.adopt_existing().await;
+        assert_eq!(remaining(&root), ["job-1"]);
+        let usage = space.usage().await;
+        assert_eq!((usage.sessions, usage.active_sessions, usage.used_bytes), (1, 0, KB));
+        assert_eq!(space.purge_idle().await.removed, 1);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,359 @@
+use axum::{
+    body::{Body, to_bytes},
+    extract::Request,
+    http::{HeaderValue, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::{Map, Value};
+use tracing::{debug, warn};
+
+use crate::{
+    ApiError,
+    humanize::{self, Locale},
+    middleware,
+};
+
+/// Tipo de mídia que escolhe a versão: `application/vnd.rossoflix.v<N>+json`.
+const VENDOR_PREFIX: &str = "application/vnd.rossoflix.v";
+/// Teto do corpo reformatado para a v2 (as respostas JSON são pequenas).
+const MAX_SHAPED_BODY: usize = 16 * 1024 * 1024;
+
+/// Formato das respostas. A v1 é o repasse do OMDb (chaves `Title`,
+/// `imdbID`, `"N/A"`...); a v2 é o envelope normalizado
+/// `{"data": ..., "meta": {...}}` com chaves em camelCase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    V1,
+    V2,
+}
+
+impl ApiVersion {
+    fn as_str(self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "v1",
+            ApiVersion::V2 => "v2",
+        }
+    }
+}
+
+/// O que a resposta contém, marcado pelo handler para a camada saber
+/// como montar a v2. Respostas sem marca saem iguais nas duas versões.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    /// `{"results": [...], <metadados>}` (busca, em alta).
+    List,
+    /// Um título do OMDb.
+    Detail,
+}
+
+/// Versão pedida no `Accept`; v1 sem pedido explícito. `Err` com a versão
+/// desconhecida.
+fn negotiate_version(accept: Option<&str>) -> Result<ApiVersion, String> {
+    let Some(accept) = accept else {
+        return Ok(ApiVersion::V1);
+    };
+    for media in accept.split(',') {
+        let media = media.split(';').next().unwrap_or_default().trim();
+        if let Some(rest) = media.strip_prefix(VENDOR_PREFIX) {
+            return match rest {
+                "1+json" => Ok(ApiVersion::V1),
+                "2+json" => Ok(ApiVersion::V2),
+                other => Err(format!("v{}", other.trim_end_matches("+json"))),
+            };
+        }
+    }
+    Ok(ApiVersion::V1)
+}
+
+/// Negociação do formato: escolhe a versão pelo `Accept` (`406` para uma
+/// versão que não existe), reformata as respostas marcadas com [`Shape`]
+/// na v2 e põe a versão no `Content-Type` (`profile`) de toda resposta JSON.
+/// O `?locale=` escolhe o idioma dos campos formatados da v2.
+pub async fn negotiate(req: Request, next: Next) -> Response {
+    let accept = req.headers().get(header::ACCEPT).and_then(|v| v.to_str().ok());
+    let version = match negotiate_version(accept) {
+        Ok(version) => version,
+        Err(unknown) => {
+            return ApiError::NotAcceptable(format!("versão da API desconhecida: {unknown}; use v1 ou v2")).into_response();
+        }
+    };
+    debug!(request_id = middleware::current_request_id(), api_version = version.as_str(), "formato negociado");
+    let locale = requested_locale(req.uri().query());
+
+    let mut resp = next.run(req).await;
+    let is_json = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"));
+    if !is_json {
+        return resp;
+    }
+    if version == ApiVersion::V2
+        && let Some(shape) = resp.extensions().get::<Shape>().copied()
+    {
+        resp = reshape(resp, shape, locale).await;
+    }
+    let headers = resp.headers_mut();
+    let content_type = format!("application/json; profile=\"vnd.rossoflix.{}\"", version.as_str());
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_str(&content_type).unwrap());
+    headers.append(header::VARY, HeaderValue::from_static("accept"));
+    resp
+}
+
+async fn reshape(resp: Response, shape: Shape, locale: Locale) -> Response {
+    let (mut parts, body) = resp.into_parts();
+    let value = match to_bytes(body, MAX_SHAPED_BODY).await {
+        Ok(bytes) => serde_json::from_slice::<Value>(&bytes).map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    let value = match value {
+        Ok(value) => value,
+        Err(e) => {
+            warn!(request_id = middleware::current_request_id(), "falha ao reformatar resposta para a v2: {e}");
+            return ApiError::Internal.into_response();
+        }
+    };
+    let body = serde_json::to_vec(&v2(value, shape, locale)).unwrap_or_default();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// `locale` da query string; inglês sem ele ou com um que não existe.
+fn requested_locale(query: Option<&str>) -> Locale {
+    query
+        .into_iter()
+        .flat_map(|q| q.split('&'))
+        .find_map(|pair| pair.strip_prefix("locale="))
+        .and_then(|locale| locale.parse().ok())
+        .unwrap_or_default()
+}
+
+/// Monta o envelope da v2, com os campos calculados de
+/// [`humanize::add_fields`] em cada título.
+pub fn v2(value: Value, shape: Shape, locale: Locale) -> Value {
+    match (shape, value) {
+        (Shape::List, Value::Object(mut fields)) => {
+            let mut results = fields.remove("results").unwrap_or(Value::Array(Vec::new()));
+            for item in results.as_array_mut().into_iter().flatten() {
+                if let Some(item) = item.as_object_mut() {
+                    humanize::add_fields(item, locale);
+                }
+            }
+            let mut meta = normalize(Value::Object(fields));
+            // o OMDb manda o total como texto
+            if let Some(total) = meta.get("total").and_then(Value::as_str).and_then(|t| t.parse::<u64>().ok()) {
+                meta["total"] = total.into();
+            }
+            serde_json::json!({ "data": normalize(results), "meta": meta })
+        }
+        (Shape::Detail, Value::Object(mut fields)) => {
+            fields.remove("Response");
+            humanize::add_fields(&mut fields, locale);
+            let meta = match fields.remove("attribution") {
+                Some(attribution) => serde_json::json!({ "attribution": attribution }),
+                None => serde_json::json!({}),
+            };
+            serde_json::json!({ "data": normalize(Value::Object(fields)), "meta": meta })
+        }
+        (_, other) => serde_json::json!({ "data": normalize(other), "meta": {} }),
+    }
+}
+
+/// Chaves em camelCase (`imdbID` → `imdbId`, `release_date` →
+/// `releaseDate`, `Title` → `title`) e `"N/A"` do OMDb como `null`, em
+/// toda a árvore.
+fn normalize(value: Value) -> Value {
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (camel_case(&key), normalize(value)))
+                .collect::<Map<_, _>>(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(normalize).collect()),
+        Value::String(s) if s == "N/A" => Value::Null,
+        other => other,
+    }
+}
+
+fn camel_case(key: &str) -> String {
+    // siglas no fim (`imdbID`, `DVD`) viram palavra comum
+    let key = match key.strip_suffix("ID") {
+        Some(head) => format!("{head}Id"),
+        None if key.len() > 1 && key.chars().all(|c| c.is_ascii_uppercase()) => key.to_ascii_lowercase(),
+        None => key.to_string(),
+    };
+    let mut out = String::with_capacity(key.len());
+    let mut upper_next = false;
+    for (i, c) in key.chars().enumerate() {
+        if c == '_' {
+            upper_next = true;
+        } else if i == 0 {
+            out.push(c.to_ascii_lowercase());
+        } else if upper_next {
+            out.push(c.to_ascii_uppercase());
+            upper_next = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, http::StatusCode, routing::get};
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::cache::Fetched;
+
+    const OMDB: &str = "https://www.omdbapi.com";
+
+    /// Resposta v1 de cada endpoint marcado com [`Shape`] (o que o handler
+    /// produz, como no mock stack) e o envelope v2 esperado.
+    fn snapshots() -> Vec<(&'static str, Shape, Value, Value)> {
+        let omdb = json!([{ "name": "OMDb API", "source": "omdb", "url": OMDB }]);
+        let tmdb_omdb = json!([
+            { "name": "TMDB", "source": "tmdb", "url": "https://www.themoviedb.org" },
+            { "name": "OMDb API", "source": "omdb", "url": OMDB },
+        ]);
+        vec![
+            (
+                "/search",
+                Shape::List,
+                json!({
+                    "attribution": omdb, "page": 1, "query": "matrix", "skipped": 0, "total": "2", "type": "movie",
+                    "results": [
+                        { "Poster": null, "Title": "The Matrix", "Type": "movie", "Year": "1999", "imdbID": "tt0133093", "playable": true, "reason": "unknown" },
+                        { "Poster": "N/A", "Title": "The Matrix Reloaded", "Type": "movie", "Year": "2003", "imdbID": "tt0234215", "playable": false, "reason": "no_seeders" },
+                    ],
+                }),
+                json!({
+                    "data": [
+                        { "imdbId": "tt0133093", "playable": true, "poster": null, "reason": "unknown", "title": "The Matrix", "type": "movie", "year": "1999", "yearEnd": 1999, "yearStart": 1999 },
+                        { "imdbId": "tt0234215", "playable": false, "poster": null, "reason": "no_seeders", "title": "The Matrix Reloaded", "type": "movie", "year": "2003", "yearEnd": 2003, "yearStart": 2003 },
+                    ],
+                    "meta": { "attribution": omdb, "page": 1, "query": "matrix", "skipped": 0, "total": 2, "type": "movie" },
+                }),
+            ),
+            (
+                "/movie/tt0133093",
+                Shape::Detail,
+                json!({
+                    "Genre": "Action, Sci-Fi", "Plot": "Fixture.", "Poster": "N/A", "Rated": "R", "Released": "31 Mar 1999",
+                    "Response": "True", "Runtime": "136 min", "Title": "The Matrix", "Type": "movie", "Year": "1999",
+                    "attribution": omdb, "imdbID": "tt0133093", "imdbRating": "8.7",
+                }),
+                json!({
+                    "data": {
+                        "genre": "Action, Sci-Fi", "imdbId": "tt0133093", "imdbRating": "8.7", "plot": "Fixture.", "poster": null,
+                        "rated": "R", "released": "31 Mar 1999", "releasedIso": "1999-03-31", "runtime": "136 min",
+                        "runtimeFormatted": "2 hours 16 minutes", "runtimeMinutes": 136, "title": "The Matrix", "type": "movie",
+                        "year": "1999", "yearEnd": 1999, "yearStart": 1999,
+                    },
+                    "meta": { "attribution": omdb },
+                }),
+            ),
+            (
+                "/movies/trending",
+                Shape::List,
+                json!({
+                    "attribution": tmdb_omdb, "generation": 1792176109518u64, "total": "2", "type": "movie",
+                    "results": [
+                        { "Poster": null, "Title": "The Matrix", "Type": "movie", "Year": "1999", "imdbID": "tt0133093", "playable": true, "rank": 1, "reason": null, "release_date": "1999-03-31", "tmdb_id": 603 },
+                        { "Poster": null, "Title": "Dark", "Type": "series", "Year": "2017–2020", "imdbID": "tt5753856", "playable": true, "rank": 2, "reason": "unknown", "release_date": "2017-12-01", "tmdb_id": 70523 },
+                    ],
+                }),
+                json!({
+                    "data": [
+                        { "imdbId": "tt0133093", "playable": true, "poster": null, "rank": 1, "reason": null, "releaseDate": "1999-03-31", "title": "The Matrix", "tmdbId": 603, "type": "movie", "year": "1999", "yearEnd": 1999, "yearStart": 1999 },
+                        { "imdbId": "tt5753856", "playable": true, "poster": null, "rank": 2, "reason": "unknown", "releaseDate": "2017-12-01", "title": "Dark", "tmdbId": 70523, "type": "series", "year": "2017–2020", "yearEnd": 2020, "yearStart": 2017 },
+                    ],
+                    "meta": { "attribution": tmdb_omdb, "generation": 1792176109518u64, "total": 2, "type": "movie" },
+                }),
+            ),
+            (
+                "/catalog/torrents/popular",
+                Shape::List,
+                json!({
+                    "has_more": true, "page": 1, "page_size": 20,
+                    "results": [
+                        { "Poster": "N/A", "Title": "The Matrix", "Type": "movie", "Year": "1999", "imdbID": "tt0133093", "playable": true, "reason": null },
+                        { "Poster": "https://posters.invalid/2.jpg", "Title": "Popular 2", "Type": "movie", "Year": "", "imdbID": "tt8000002", "playable": true, "reason": "unknown" },
+                    ],
+                }),
+                json!({
+                    "data": [
+                        { "imdbId": "tt0133093", "playable": true, "poster": null, "reason": null, "title": "The Matrix", "type": "movie", "year": "1999", "yearEnd": 1999, "yearStart": 1999 },
+                        { "imdbId": "tt8000002", "playable": true, "poster": "https://posters.invalid/2.jpg", "reason": "unknown", "title": "Popular 2", "type": "movie", "year": "", "yearEnd": null, "yearStart": null },
+                    ],
+                    "meta": { "hasMore": true, "page": 1, "pageSize": 20 },
+                }),
+            ),
+        ]
+    }
+
+    fn app() -> Router {
+        let mut router = Router::new();
+        for (path, shape, v1, _) in snapshots() {
+            router = router.route(path, get(move || async move { Fetched::miss(v1).shaped(shape) }));
+        }
+        router
+            .route("/unshaped", get(|| async { axum::Json(json!({ "imdbID": "tt0133093", "Poster": "N/A" })) }))
+            .route("/text", get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn(negotiate))
+    }
+
+    async fn call(path: &str, accept: Option<&str>) -> (StatusCode, Option<String>, Value) {
+        let mut req = Request::builder().uri(path);
+        if let Some(accept) = accept {
+            req = req.header(header::ACCEPT, accept);
+        }
+        let resp = app().oneshot(req.body(Body::empty()).unwrap()).await.unwrap();
+        let status = resp.status();
+        let content_type = resp.headers().get(header::CONTENT_TYPE).map(|v| v.to_str().unwrap().to_string());
+        let bytes = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let body = serde_json::from_slice(&bytes).unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into()));
+        (status, content_type, body)
+    }
+
+    #[tokio::test]
+    async fn v1_snapshots_per_endpoint() {
+        for (path, _, v1, _) in snapshots() {
+            for accept in [None, Some("application/json"), Some("application/vnd.rossoflix.v1+json")] {
+                let (status, content_type, body) = call(path, accept).await;
+                assert_eq!(status, StatusCode::OK, "{path} {accept:?}");
+                assert_eq!(content_type.as_deref(), Some("application/json; profile=\"vnd.rossoflix.v1\""), "{path}");
+                assert_eq!(body, v1, "{path} {accept:?}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn v2_snapshots_per_endpoint() {
+        for (path, _, _, v2) in snapshots() {
+            let (status, content_type, body) = call(path, Some("text/html, application/vnd.rossoflix.v2+json;q=0.9")).await;
+            assert_eq!(status, StatusCode::OK, "{path}");
+            assert_eq!(content_type.as_deref(), Some("application/json; profile=\"vnd.rossoflix.v2\""), "{path}");
+            assert_eq!(body, v2, "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn unmarked_and_unknown_versions() {
+        // sem marca, a v2 só muda o Content-Type
+        let (_, content_type, body) = call("/unshaped", Some("application/vnd.rossoflix.v2+json")).await;
+        assert_eq!(content_type.as_deref(), Some("application/json; profile=\"vnd.rossoflix.v2\""));
+        assert_eq!(body, json!({ "imdbID": "tt0133093", "Poster": "N/A" }));
+
+        let (_, content_type, body) = call("/text", Some("application/vnd.rossoflix.v2+json")).await;
+        assert_eq!(content_type.as_deref(), Some("text/plain; charset=utf-8"));
+        assert_eq!(body, json!("ok"));
+
+        let (status, _, _) = call("/search", Some("application/vnd.rossoflix.v3+json")).await;
+        assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+    }
+}
@@ -0,0 +1,343 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{Html, IntoResponse, Response},
+};
+use rusqlite::{OptionalExtension, params};
+use serde::Deserialize;
+
+use crate::{
+    ApiError, AppState,
+    auth::Identity,
+    cache::CacheMode,
+    fetch_detail, find_downloaded_file,
+    magnet::Magnet,
+    markers::check_imdb_id,
+    party, signing, torrentio,
+};
+
+/// Validade padrão e máxima de um link de convidado.
+const DEFAULT_HOURS: u64 = 48;
+const MAX_HOURS: u64 = 7 * 24;
+/// Aberturas permitidas quando `max_uses` não vem.
+const DEFAULT_MAX_USES: u32 = 10;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateShare {
+    /// Título a compartilhar; sem `filename`, o primeiro stream do torrentio.
+    imdb_id: Option<String>,
+    /// Arquivo já baixado (ou, com `magnet`, o arquivo dentro do torrent).
+    filename: Option<String>,
+    magnet: Option<String>,
+    #[serde(default = "default_hours")]
+    expires_in_hours: u64,
+    #[serde(default = "default_max_uses")]
+    max_uses: u32,
+}
+
+fn default_hours() -> u64 {
+    DEFAULT_HOURS
+}
+
+fn default_max_uses() -> u32 {
+    DEFAULT_MAX_USES
+}
+
+/// Link ativo, como guardado na tabela `shares`.
+struct Share {
+    imdb_id: Option<String>,
+    magnet: String,
+    filename: String,
+    expires_at: i64,
+    max_uses: u32,
+    uses: u32,
+}
+
+/// `POST /share` (token de admin) — cria um link de convidado para um
+/// título, com validade e número de aberturas, sem expor o token.
+pub async fn create_share(
+    State(state): State<AppState>,
+    identity: Identity,
+    Json(req): Json<CreateShare>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !identity.admin {
+        return Err(ApiError::Forbidden("acesso de admin exigido".into()));
+    }
+    if state.config().stream_signing_key.is_none() {
+        return Err(ApiError::Forbidden("STREAM_SIGNING_KEY não configurada".into()));
+    }
+    if !(1..=MAX_HOURS).contains(&req.expires_in_hours) {
+        return Err(ApiError::BadRequest(format!("expires_in_hours deve estar entre 1 e {MAX_HOURS}")));
+    }
+    if req.max_uses == 0 {
+        return Err(ApiError::BadRequest("max_uses deve ser ao menos 1".into()));
+    }
+    if let Some(imdb_id) = &req.imdb_id {
+        check_imdb_id(imdb_id)?;
+    }
+
+    let (magnet, filename) = target(&state, &req).await?;
+    let id = party::new_id();
+    let now = unix_now();
+    let expires_at = now + (req.expires_in_hours * 3600) as i64;
+    let row = (id.clone(), req.imdb_id.clone(), magnet.clone(), filename.clone(), req.max_uses);
+    state
+        .db
+        .call(move |conn| {
+            let (id, imdb_id, magnet, filename, max_uses) = row;
+            conn.execute(
+                "INSERT INTO shares (id, imdb_id, magnet, filename, created_at, expires_at, max_uses, uses)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0)",
+                params![id, imdb_id, magnet, filename, now, expires_at, max_uses],
+            )
+        })
+        .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(serde_json::json!({
+            "id": id,
+            "url": format!("/share/{id}"),
+            "imdb_id": req.imdb_id,
+            "filename": filename,
+            "expires_at": expires_at,
+            "max_uses": req.max_uses,
+        })),
+    ))
+}
+
+/// Torrent (infohash) e arquivo servidos pelo link.
+async fn target(state: &AppState, req: &CreateShare) -> Result<(String, String), ApiError> {
+    match (&req.magnet, &req.filename, &req.imdb_id) {
+        (Some(magnet), Some(filename), _) => {
+            let magnet = Magnet::parse(magnet).ok_or_else(|| ApiError::BadRequest("magnet inválido".into()))?;
+            Ok((magnet.info_hash, filename.clone()))
+        }
+        (None, Some(filename), _) => {
+            let base = &state.config().downloads_dir;
+            let path = find_downloaded_file(base, filename)
+                .await
+                .ok_or_else(|| ApiError::NotFound(format!("{filename} não encontrado")))?;
+            // downloads ficam em `<downloads>/<infohash>/...`
+            let info_hash = path
+                .strip_prefix(base)
+                .ok()
+                .and_then(|rel| rel.components().next())
+                .and_then(|c| Magnet::parse(&c.as_os_str().to_string_lossy()))
+                .map(|m| m.info_hash)
+                .ok_or_else(|| ApiError::BadRequest(format!("{filename} não pertence a um download conhecido")))?;
+            Ok((info_hash, filename.clone()))
+        }
+        (None, None, Some(imdb_id)) => {
+            let body = torrentio::movie_streams(state, imdb_id, CacheMode::Normal).await?;
+            torrentio::parse_streams(&body.value)
+                .iter()
+                .find_map(|s| Some((Magnet::parse(s.info_hash.as_deref()?)?.info_hash, s.filename()?)))
+                .ok_or_else(|| ApiError::NotFound(format!("nenhum stream para {imdb_id}")))
+        }
+        (Some(_), None, _) => Err(ApiError::BadRequest("magnet exige filename".into())),
+        (None, None, None) => Err(ApiError::BadRequest("informe imdb_id ou filename".into())),
+    }
+}
+
+/// `GET /share/:id` — abre o link: conta um uso e devolve os metadados do
+/// título e uma URL de `/stream` assinada e presa ao link (vale até ele
+/// expirar ou ser revogado). HTML quando o `Accept` pede, senão JSON.
+/// Vencido, esgotado ou revogado: `410`.
+pub async fn open_share(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let now = unix_now();
+    let share_id = id.clone();
+    let share = state
+        .db
+        .call(move |conn| {
+            // conta e confere no mesmo UPDATE: duas aberturas simultâneas
+            // não passam do limite
+            conn.query_row(
+                "UPDATE shares SET uses = uses + 1
+                 WHERE id = ?1 AND revoked_at IS NULL AND expires_at > ?2 AND uses < max_uses
+                 RETURNING imdb_id, magnet, filename, expires_at, max_uses, uses",
+                params![share_id, now],
+                |row| {
+                    Ok(Share {
+                        imdb_id: row.get(0)?,
+                        magnet: row.get(1)?,
+                        filename: row.get(2)?,
+                        expires_at: row.get(3)?,
+                        max_uses: row.get(4)?,
+                        uses: row.get(5)?,
+                    })
+                },
+            )
+            .optional()
+        })
+        .await?;
+    let Some(share) = share else {
+        return Err(inactive(&state, &id).await);
+    };
+
+    let config = state.config();
+    let key = config
+        .stream_signing_key
+        .as_deref()
+        .ok_or_else(|| ApiError::Unavailable("STREAM_SIGNING_KEY não configurada".into()))?;
+    let exp = share.expires_at as u64;
+    let signed = signing::Signed {
+        magnet: &share.magnet,
+        filename: &share.filename,
+        episode_hint: None,
+        url: None,
+        share: Some(&id),
+        ..Default::default()
+    };
+    let stream_url = format!(
+        "/stream?magnet={}&filename={}&share={id}&exp={exp}&sig={}",
+        share.magnet,
+        urlencoding::encode(&share.filename),
+        signing::sign(key, &signed, exp),
+    );
+
+    let detail = match &share.imdb_id {
+        Some(imdb_id) => fetch_detail(&state, imdb_id, CacheMode::Normal).await.ok().map(|d| d.value),
+        None => None,
+    };
+    let text = |field: &str| {
+        detail
+            .as_ref()
+            .and_then(|d| d.get(field))
+            .and_then(|v| v.as_str())
+            .filter(|v| *v != "N/A")
+            .map(str::to_string)
+    };
+    let title = text("Title").unwrap_or_else(|| share.filename.clone());
+
+    let wants_html = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/html"));
+    if wants_html {
+        return Ok(Html(landing_page(&title, text("Year"), text("Plot"), text("Poster"), &stream_url)).into_response());
+    }
+    Ok(Json(serde_json::json!({
+        "id": id,
+        "title": title,
+        "year": text("Year"),
+        "plot": text("Plot"),
+        "poster": text("Poster"),
+        "imdb_id": share.imdb_id,
+        "filename": share.filename,
+        "stream_url": stream_url,
+        "expires_at": share.expires_at,
+        "uses_left": share.max_uses.saturating_sub(share.uses),
+    }))
+    .into_response())
+}
+
+/// Por que o link não abriu: inexistente é `404`, o resto `410`.
+async fn inactive(state: &AppState, id: &str) -> ApiError {
+    let id = id.to_string();
+    let now = unix_now();
+    let reason = state
+        .db
+        .call(move |conn| {
+            conn.query_row(
+                "SELECT CASE
+                    WHEN revoked_at IS NOT NULL THEN 'revogado'
+                    WHEN expires_at <= ?2 THEN 'expirado'
+                    ELSE 'esgotado'
+                 END FROM shares WHERE id = ?1",
+                params![id, now],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+        })
+        .await;
+    match reason {
+        Ok(Some(reason)) => ApiError::Gone(format!("link {reason}")),
+        Ok(None) => ApiError::NotFound("link não encontrado".into()),
+        Err(e) => e,
+    }
+}
+
+/// Confere, a cada pedido de `/stream` com `share=`, que o link continua
+/// valendo: revogar corta o acesso mesmo com a URL assinada em mãos.
+pub async fn check_active(state: &AppState, id: &str) -> Result<(), ApiError> {
+    let share_id = id.to_string();
+    let now = unix_now();
+    let active = state
+        .db
+        .call(move |conn| {
+            conn.query_row(
+                "SELECT 1 FROM shares WHERE id = ?1 AND revoked_at IS NULL AND expires_at > ?2",
+                params![share_id, now],
+                |_| Ok(()),
+            )
+            .optional()
+        })
+        .await?;
+    match active {
+        Some(()) => Ok(()),
+        None => Err(inactive(state, id).await),
+    }
+}
+
+/// `DELETE /share/:id` (token de admin) — revoga o link.
+pub async fn revoke_share(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    identity: Identity,
+) -> Result<impl IntoResponse, ApiError> {
+    if !identity.admin {
+        return Err(ApiError::Forbidden("acesso de admin exigido".into()));
+    }
+    let now = unix_now();
+    let revoked = state
+        .db
+        .call(move |conn| {
+            conn.execute(
+                "UPDATE shares SET revoked_at = COALESCE(revoked_at, ?2) WHERE id = ?1",
+                params![id, now],
+            )
+        })
+        .await?;
+    if revoked == 0 {
+        return Err(ApiError::NotFound("link não encontrado".into()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn landing_page(title: &str, year: Option<String>, plot: Option<String>, poster: Option<String>, url: &str) -> String {
+    let title = escape(title);
+    let heading = match year {
+        Some(year) => format!("{title} ({})", escape(&year)),
+        None => title.clone(),
+    };
+    let poster = poster.map(|p| format!("<img src=\"{}\" alt=\"\" width=\"200\">", escape(&p))).unwrap_or_default();
+    let plot = plot.map(|p| format!("<p>{}</p>", escape(&p))).unwrap_or_default();
+    format!(
+        "<!doctype html>\n<html lang=\"pt-BR\"><head><meta charset=\"utf-8\"><title>{title}</title></head>\n\
+         <body><h1>{heading}</h1>{poster}{plot}\n\
+         <video controls width=\"100%\" src=\"{}\"></video></body></html>\n",
+        escape(url)
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
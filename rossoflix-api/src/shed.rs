@@ -0,0 +1,250 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU8, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Json,
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{ApiError, AppState, config::Config};
+
+/// Amostras de latência guardadas para o p95.
+const LATENCY_SAMPLES: usize = 512;
+/// Amostras mais velhas que isso não contam: o p95 é da carga de agora.
+const LATENCY_WINDOW: Duration = Duration::from_secs(60);
+/// Intervalo entre as leituras do p95 e da fila do runtime.
+const SAMPLE_EVERY: Duration = Duration::from_secs(1);
+/// `Retry-After` das recusas.
+const RETRY_AFTER_SECS: u64 = 5;
+
+/// Quem pode ser recusado quando o servidor está sobrecarregado. A ordem
+/// importa: com carga passando do limite, saem os `Low`; passando do dobro,
+/// também os `Normal`. `Critical` nunca é recusado.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl Priority {
+    /// Prioridade pela rota: a reprodução (`/stream`, HLS, eventos do
+    /// download, watch party, `/ws/`) e os health checks passam sempre; busca,
+    /// enriquecimento e miniaturas são os primeiros a sair.
+    pub fn of(path: &str) -> Self {
+        const CRITICAL: &[&str] = &["/stream", "/hls/", "/health", "/party/", "/ws/"];
+        const LOW: &[&str] = &[
+            "/search",
+            "/library/search",
+            "/movies/trending",
+            "/trending/",
+            "/catalog/",
+            "/calendar",
+            "/title/",
+            "/attribution",
+            "/notices",
+            "/media/thumbnail",
+            "/media/chapters",
+            "/torrent/health",
+            "/subtitles/",
+            "/parse/",
+            "/speedtest",
+        ];
+        if CRITICAL.iter().any(|p| path.starts_with(p)) || (path.starts_with("/downloads/") && path.ends_with("/events"))
+        {
+            Priority::Critical
+        } else if LOW.iter().any(|p| path.starts_with(p)) {
+            Priority::Low
+        } else {
+            Priority::Normal
+        }
+    }
+}
+
+/// Sinais de carga e o que está sendo recusado. Os pedidos em andamento são
+/// contados na hora; o p95 e a fila do runtime, a cada `SAMPLE_EVERY`.
+#[derive(Clone, Default)]
+pub struct LoadShedder {
+    in_flight: Arc<AtomicUsize>,
+    latencies: Arc<Mutex<VecDeque<(Instant, Duration)>>>,
+    /// Nível pelos sinais amostrados (0, 1 ou 2; veja [`level`]).
+    sampled_level: Arc<AtomicU8>,
+    p95_ms: Arc<AtomicU64>,
+    queue_depth: Arc<AtomicUsize>,
+    shed_low: Arc<AtomicU64>,
+    shed_normal: Arc<AtomicU64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Snapshot {
+    /// `true` quando algo está sendo recusado.
+    pub shedding: bool,
+    /// Prioridades recusadas agora (`low`, `normal`).
+    pub rejecting: Vec<&'static str>,
+    pub in_flight: usize,
+    pub p95_ms: u64,
+    pub runtime_queue_depth: usize,
+    pub thresholds: Thresholds,
+    /// Recusados desde a subida, por prioridade.
+    pub shed_total: ShedTotals,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Thresholds {
+    pub max_in_flight: usize,
+    pub p95_ms: u64,
+    pub queue_depth: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShedTotals {
+    pub low: u64,
+    pub normal: u64,
+}
+
+/// 0 dentro do limite, 1 acima dele, 2 acima do dobro. Limite 0 desliga o
+/// sinal.
+fn level(value: u64, threshold: u64) -> u8 {
+    match threshold {
+        0 => 0,
+        t if value > t.saturating_mul(2) => 2,
+        t if value > t => 1,
+        _ => 0,
+    }
+}
+
+impl LoadShedder {
+    /// Nível de agora: o pior entre os pedidos em andamento e os sinais
+    /// amostrados.
+    fn current_level(&self, config: &Config, in_flight: usize) -> u8 {
+        level(in_flight as u64, config.shed_max_in_flight as u64).max(self.sampled_level.load(Ordering::Relaxed))
+    }
+
+    fn record_latency(&self, elapsed: Duration) {
+        let mut samples = self.latencies.lock().unwrap();
+        if samples.len() == LATENCY_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back((Instant::now(), elapsed));
+    }
+
+    /// Relê o p95 e a fila do runtime e recalcula o nível amostrado.
+    fn sample(&self, config: &Config) {
+        let p95 = {
+            let mut samples = self.latencies.lock().unwrap();
+            while samples.front().is_some_and(|(at, _)| at.elapsed() > LATENCY_WINDOW) {
+                samples.pop_front();
+            }
+            let mut sorted: Vec<Duration> = samples.iter().map(|(_, d)| *d).collect();
+            sorted.sort_unstable();
+            sorted.get((sorted.len() * 95 / 100).min(sorted.len().saturating_sub(1))).copied().unwrap_or_default()
+        };
+        let queue_depth = tokio::runtime::Handle::current().metrics().global_queue_depth();
+        let p95_ms = p95.as_millis() as u64;
+        self.p95_ms.store(p95_ms, Ordering::Relaxed);
+        self.queue_depth.store(queue_depth, Ordering::Relaxed);
+
+        let new = level(p95_ms, config.shed_p95_ms).max(level(queue_depth as u64, config.shed_queue_depth as u64));
+        let old = self.sampled_level.swap(new, Ordering::Relaxed);
+        if new > old {
+            warn!(level = new, p95_ms, queue_depth, "sobrecarga: recusando pedidos de baixa prioridade");
+        } else if new < old {
+            info!(level = new, p95_ms, queue_depth, "carga caiu");
+        }
+    }
+
+    pub fn snapshot(&self, config: &Config) -> Snapshot {
+        let in_flight = self.in_flight.load(Ordering::Relaxed);
+        let level = self.current_level(config, in_flight);
+        let rejecting = [(1, "low"), (2, "normal")].into_iter().filter(|(min, _)| level >= *min).map(|(_, p)| p).collect();
+        Snapshot {
+            shedding: level > 0,
+            rejecting,
+            in_flight,
+            p95_ms: self.p95_ms.load(Ordering::Relaxed),
+            runtime_queue_depth: self.queue_depth.load(Ordering::Relaxed),
+            thresholds: Thresholds {
+                max_in_flight: config.shed_max_in_flight,
+                p95_ms: config.shed_p95_ms,
+                queue_depth: config.shed_queue_depth,
+            },
+            shed_total: ShedTotals {
+                low: self.shed_low.load(Ordering::Relaxed),
+                normal: self.shed_normal.load(Ordering::Relaxed),
+            },
+        }
+    }
+}
+
+/// Amostra os sinais de carga em segundo plano.
+pub fn spawn_sampler(state: AppState) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(SAMPLE_EVERY);
+        loop {
+            tick.tick().await;
+            state.shedder.sample(&state.config());
+        }
+    });
+}
+
+/// Desconta o pedido dos em andamento mesmo se o handler for cancelado.
+struct InFlight(Arc<AtomicUsize>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Recusa com `503` e `Retry-After` os pedidos que a carga de agora não
+/// comporta, pela [`Priority`] da rota. A latência medida (até o começo da
+/// resposta) é a dos pedidos que não são de reprodução.
+pub async fn shed_requests(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let shedder = &state.shedder;
+    let in_flight = shedder.in_flight.fetch_add(1, Ordering::Relaxed) + 1;
+    let _guard = InFlight(shedder.in_flight.clone());
+    let priority = Priority::of(req.uri().path());
+    if priority != Priority::Critical {
+        let level = shedder.current_level(&state.config(), in_flight);
+        let shed = match priority {
+            Priority::Low => (level >= 1).then_some(&shedder.shed_low),
+            Priority::Normal => (level >= 2).then_some(&shedder.shed_normal),
+            Priority::Critical => None,
+        };
+        if let Some(counter) = shed {
+            counter.fetch_add(1, Ordering::Relaxed);
+            return ApiError::Overloaded { retry_after_secs: RETRY_AFTER_SECS }.into_response();
+        }
+    }
+
+    let started = Instant::now();
+    let resp = next.run(req).await;
+    if priority != Priority::Critical {
+        shedder.record_latency(started.elapsed());
+    }
+    resp
+}
+
+/// `GET /health/ready` — `200` enquanto o servidor aceita tudo; `503` com
+/// o estado da recusa quando está sobrecarregado, para o balanceador mandar
+/// menos tráfego.
+pub async fn ready(State(state): State<AppState>) -> impl IntoResponse {
+    let snapshot = state.shedder.snapshot(&state.config());
+    let status = if snapshot.shedding { StatusCode::SERVICE_UNAVAILABLE } else { StatusCode::OK };
+    let body = serde_json::json!({
+        "status": if snapshot.shedding { "shedding" } else { "ready" },
+        "load": snapshot,
+    });
+    (status, Json(body))
+}
@@ -0,0 +1,256 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::Uri,
+    response::IntoResponse,
+};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::{ApiError, AppState, auth::Identity, config::Config};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Validade padrão e máxima de uma URL assinada.
+const DEFAULT_TTL_SECS: u64 = 3600;
+const MAX_TTL_SECS: u64 = 24 * 3600;
+
+/// Parâmetros de `/stream` cobertos pela assinatura.
+#[derive(Debug, Default)]
+pub struct Signed<'a> {
+    pub magnet: &'a str,
+    pub filename: &'a str,
+    pub episode_hint: Option<&'a str>,
+    pub url: Option<&'a str>,
+    /// Link de convidado (`/share/:id`) ao qual a URL fica presa.
+    pub share: Option<&'a str>,
+    /// Os demais parâmetros que mudam o que o pedido faz (perfil que fica
+    /// com o "assistido", título, espera), como vêm na query.
+    pub profile: Option<&'a str>,
+    pub imdb_id: Option<&'a str>,
+    pub season: Option<&'a str>,
+    pub episode: Option<&'a str>,
+    pub progressive: Option<&'a str>,
+    pub wait: Option<&'a str>,
+}
+
+impl Signed<'_> {
+    /// Parâmetros opcionais, na ordem em que entram na mensagem e na URL.
+    fn scope(&self) -> [(&'static str, Option<&str>); 7] {
+        [
+            ("share", self.share),
+            ("profile", self.profile),
+            ("imdb_id", self.imdb_id),
+            ("season", self.season),
+            ("episode", self.episode),
+            ("progressive", self.progressive),
+            ("wait", self.wait),
+        ]
+    }
+
+    fn message(&self, exp: u64) -> String {
+        let mut message = format!(
+            "{}\n{}\n{}\n{}\n{exp}",
+            self.magnet,
+            self.filename,
+            self.episode_hint.unwrap_or_default(),
+            self.url.unwrap_or_default()
+        );
+        // só entram quando existem, para as URLs já assinadas continuarem
+        // valendo; acrescentar um deles a uma URL assinada a invalida
+        for (name, value) in self.scope() {
+            if let Some(value) = value {
+                message.push_str(&format!("\n{name}={value}"));
+            }
+        }
+        message
+    }
+}
+
+fn mac(key: &str, message: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC aceita chaves de qualquer tamanho");
+    mac.update(message.as_bytes());
+    mac
+}
+
+pub fn sign(key: &str, params: &Signed<'_>, exp: u64) -> String {
+    mac(key, &params.message(exp))
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Confere `sig`/`exp` contra a chave atual e, durante a rotação, a anterior.
+/// Expirada (além da tolerância de relógio) ou adulterada: 403.
+pub fn verify(config: &Config, params: &Signed<'_>, sig: &str, exp: u64) -> Result<(), ApiError> {
+    if now() > exp.saturating_add(config.stream_signature_skew_secs) {
+        return Err(ApiError::Forbidden("assinatura expirada".into()));
+    }
+    let Some(bytes) = decode_hex(sig) else {
+        return Err(ApiError::Forbidden("assinatura inválida".into()));
+    };
+    let message = params.message(exp);
+    let valid = [&config.stream_signing_key, &config.stream_signing_key_previous]
+        .into_iter()
+        .flatten()
+        .any(|key| mac(key, &message).verify_slice(&bytes).is_ok());
+    if valid {
+        Ok(())
+    } else {
+        Err(ApiError::Forbidden("assinatura inválida".into()))
+    }
+}
+
+/// Os parâmetros assinados de um pedido a `/stream`, como vêm na query.
+#[derive(Debug, Deserialize)]
+struct SignedQuery {
+    #[serde(default)]
+    magnet: String,
+    #[serde(default)]
+    filename: String,
+    episode_hint: Option<String>,
+    url: Option<String>,
+    share: Option<String>,
+    profile: Option<String>,
+    imdb_id: Option<String>,
+    season: Option<String>,
+    episode: Option<String>,
+    progressive: Option<String>,
+    wait: Option<String>,
+    sig: String,
+    exp: u64,
+}
+
+/// Confere `sig`/`exp` da query de `uri`, com os parâmetros como vieram
+/// (antes de qualquer normalização do handler).
+pub fn verify_query(config: &Config, uri: &Uri) -> Result<(), ApiError> {
+    let Ok(Query(query)) = Query::<SignedQuery>::try_from_uri(uri) else {
+        return Err(ApiError::Forbidden("assinatura inválida".into()));
+    };
+    let params = Signed {
+        magnet: &query.magnet,
+        filename: &query.filename,
+        episode_hint: query.episode_hint.as_deref(),
+        url: query.url.as_deref(),
+        share: query.share.as_deref(),
+        profile: query.profile.as_deref(),
+        imdb_id: query.imdb_id.as_deref(),
+        season: query.season.as_deref(),
+        episode: query.episode.as_deref(),
+        progressive: query.progressive.as_deref(),
+        wait: query.wait.as_deref(),
+    };
+    verify(config, &params, &query.sig, query.exp)
+}
+
+/// A query de `uri` traz `sig`/`exp` válidos? Para quem decide antes do
+/// handler (a chave de API); o handler confere de novo, com o link de
+/// convidado.
+pub fn verified_query(config: &Config, uri: &Uri) -> bool {
+    verify_query(config, uri).is_ok()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Vazios não vão para a URL, então também não entram na assinatura.
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().filter(|v| !v.is_empty())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SignRequest {
+    #[serde(default)]
+    magnet: String,
+    #[serde(default)]
+    filename: String,
+    episode_hint: Option<String>,
+    url: Option<String>,
+    profile: Option<String>,
+    imdb_id: Option<String>,
+    season: Option<u32>,
+    episode: Option<u32>,
+    #[serde(default)]
+    progressive: bool,
+    wait: Option<String>,
+    ttl_secs: Option<u64>,
+}
+
+/// `POST /stream/sign` (token de admin) — URL de `/stream` com `exp` e `sig`,
+/// para players que não conseguem mandar `Authorization`.
+pub async fn sign_stream(
+    State(state): State<AppState>,
+    identity: Identity,
+    Json(req): Json<SignRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !identity.admin {
+        return Err(ApiError::Forbidden("acesso de admin exigido".into()));
+    }
+    let Some(key) = &state.config().stream_signing_key else {
+        return Err(ApiError::Forbidden("STREAM_SIGNING_KEY não configurada".into()));
+    };
+    if req.url.is_none() && (req.magnet.trim().is_empty() || req.filename.is_empty()) {
+        return Err(ApiError::BadRequest("informe magnet e filename (ou url)".into()));
+    }
+
+    let exp = now() + req.ttl_secs.unwrap_or(DEFAULT_TTL_SECS).min(MAX_TTL_SECS);
+    let (season, episode) = (req.season.map(|s| s.to_string()), req.episode.map(|e| e.to_string()));
+    let params = Signed {
+        magnet: &req.magnet,
+        filename: &req.filename,
+        episode_hint: req.episode_hint.as_deref(),
+        url: req.url.as_deref(),
+        share: None,
+        profile: non_empty(&req.profile),
+        imdb_id: non_empty(&req.imdb_id),
+        season: season.as_deref(),
+        episode: episode.as_deref(),
+        progressive: req.progressive.then_some("1"),
+        wait: non_empty(&req.wait),
+    };
+    let sig = sign(key, &params, exp);
+
+    let mut query = vec![
+        ("magnet", req.magnet.as_str()),
+        ("filename", req.filename.as_str()),
+    ];
+    if let Some(hint) = &req.episode_hint {
+        query.push(("episode_hint", hint));
+    }
+    if let Some(url) = &req.url {
+        query.push(("url", url));
+    }
+    query.extend(params.scope().into_iter().filter_map(|(name, value)| Some((name, value?))));
+    let exp_str = exp.to_string();
+    query.push(("exp", &exp_str));
+    query.push(("sig", &sig));
+    let query: Vec<String> = query
+        .into_iter()
+        .filter(|(_, v)| !v.is_empty())
+        .map(|(k, v)| format!("{k}={}", urlencoding::encode(v)))
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "url": format!("/stream?{}", query.join("&")),
+        "expires_at": exp,
+    })))
+}
@@ -0,0 +1,173 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+
+use crate::{
+    ApiError, AppState,
+    cache::CacheMode,
+    fetch_detail,
+    magnet::Magnet,
+    markers::check_imdb_id,
+    release_name,
+    torrentio::{self, StreamInfo},
+};
+
+/// Tamanho máximo do nome, com a extensão (folga sob o limite de 255 dos
+/// sistemas de arquivos e dos diálogos de salvar).
+const MAX_LEN: usize = 120;
+/// Extensão quando o nome vem só do título.
+pub const DEFAULT_EXTENSION: &str = "mkv";
+/// Nome quando não sobra nada do original.
+const FALLBACK_STEM: &str = "video";
+/// Qualidades aceitas em `?quality=`.
+const QUALITIES: [&str; 4] = ["2160p", "1080p", "720p", "480p"];
+
+/// Troca letras acentuadas pela base ASCII (`Amélie` → `Amelie`, `Æ` →
+/// `AE`), mantendo a caixa. O que não tem equivalente some.
+pub fn ascii_fold(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii() {
+            out.push(c);
+            continue;
+        }
+        let folded = match c {
+            'á' | 'à' | 'â' | 'ã' | 'ä' | 'å' | 'ā' => "a",
+            'Á' | 'À' | 'Â' | 'Ã' | 'Ä' | 'Å' | 'Ā' => "A",
+            'é' | 'è' | 'ê' | 'ë' | 'ē' | 'ę' | 'ě' => "e",
+            'É' | 'È' | 'Ê' | 'Ë' | 'Ē' | 'Ę' | 'Ě' => "E",
+            'í' | 'ì' | 'î' | 'ï' | 'ī' | 'ı' => "i",
+            'Í' | 'Ì' | 'Î' | 'Ï' | 'Ī' | 'İ' => "I",
+            'ó' | 'ò' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => "o",
+            'Ó' | 'Ò' | 'Ô' | 'Õ' | 'Ö' | 'Ø' | 'Ō' | 'Ő' => "O",
+            'ú' | 'ù' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' => "u",
+            'Ú' | 'Ù' | 'Û' | 'Ü' | 'Ū' | 'Ů' | 'Ű' => "U",
+            'ý' | 'ÿ' => "y",
+            'Ý' | 'Ÿ' => "Y",
+            'ç' | 'ć' | 'č' => "c",
+            'Ç' | 'Ć' | 'Č' => "C",
+            'ñ' | 'ń' | 'ň' => "n",
+            'Ñ' | 'Ń' | 'Ň' => "N",
+            'š' | 'ś' | 'ş' => "s",
+            'Š' | 'Ś' | 'Ş' => "S",
+            'ž' | 'ź' | 'ż' => "z",
+            'Ž' | 'Ź' | 'Ż' => "Z",
+            'ł' => "l",
+            'Ł' => "L",
+            'đ' | 'ð' => "d",
+            'Đ' | 'Ð' => "D",
+            'ř' => "r",
+            'Ř' => "R",
+            'ğ' => "g",
+            'Ğ' => "G",
+            'æ' => "ae",
+            'Æ' => "AE",
+            'œ' => "oe",
+            'Œ' => "OE",
+            'ß' => "ss",
+            'þ' => "th",
+            'Þ' => "TH",
+            _ => "",
+        };
+        out.push_str(folded);
+    }
+    out
+}
+
+/// Palavras (letras e números ASCII) unidas por `.`; apóstrofos somem
+/// (`Schindler's` → `Schindlers`) e o resto vira separador.
+fn words(text: &str) -> String {
+    ascii_fold(text)
+        .replace('\'', "")
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Nome de arquivo seguro para `name` (um nome de release ou de arquivo).
+/// Uma extensão de vídeo no fim é mantida, em minúsculas; o resto do nome
+/// é cortado numa fronteira de palavra para caber em `MAX_LEN`.
+pub fn filename(name: &str) -> String {
+    let (stem, extension) = release_name::split_extension(name.trim());
+    let suffix = extension.map(|ext| format!(".{ext}")).unwrap_or_default();
+    let mut stem = words(stem);
+    if stem.len() + suffix.len() > MAX_LEN {
+        let cut = MAX_LEN - suffix.len();
+        stem.truncate(stem[..cut].rfind('.').unwrap_or(cut));
+    }
+    if stem.is_empty() {
+        stem = FALLBACK_STEM.into();
+    }
+    stem + &suffix
+}
+
+/// `Title.Year.Quality.ext` para quando não há nome de arquivo de origem.
+pub fn from_title(title: &str, year: Option<&str>, quality: Option<&str>, extension: &str) -> String {
+    let parts: Vec<&str> = [Some(title), year, quality].into_iter().flatten().collect();
+    filename(&format!("{}.{extension}", parts.join(" ")))
+}
+
+/// Nome a usar em `/stream` quando o cliente não manda `filename`: o `dn`
+/// do magnet, com as mesmas regras.
+pub fn from_magnet(magnet: &Magnet) -> Option<String> {
+    magnet.display_name.as_deref().filter(|dn| !dn.trim().is_empty()).map(filename)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FilenameParams {
+    quality: Option<String>,
+}
+
+/// `GET /title/:imdb_id/filename?quality=1080p` — nome de arquivo canônico
+/// do título: o `behaviorHints.filename` do primeiro stream do torrentio na
+/// qualidade pedida ou, sem ele, `Title.Year.Quality.mkv`, pelas mesmas
+/// regras que `/stream` usa.
+pub async fn title_filename(
+    State(state): State<AppState>,
+    Path(imdb_id): Path<String>,
+    Query(params): Query<FilenameParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    check_imdb_id(&imdb_id)?;
+    let quality = match params.quality.as_deref().map(str::to_ascii_lowercase) {
+        Some(q) if q == "4k" => Some("2160p"),
+        Some(q) => Some(
+            QUALITIES
+                .into_iter()
+                .find(|known| *known == q)
+                .ok_or_else(|| ApiError::BadRequest(format!("quality deve ser uma de {}", QUALITIES.join(", "))))?,
+        ),
+        None => None,
+    };
+
+    // torrentio fora do ar não impede a resposta: cai para o título
+    if let Ok(body) = torrentio::movie_streams(&state, &imdb_id, CacheMode::Normal).await {
+        let stream = torrentio::parse_streams(&body.value).into_iter().find(|s| {
+            quality.is_none_or(|q| StreamInfo::from_stream(s).resolution == Some(q))
+                && s.behavior_hints.as_ref().is_some_and(|h| h.filename.is_some())
+        });
+        if let Some(stream) = stream
+            && let Some(name) = stream.filename()
+        {
+            return Ok(Json(serde_json::json!({
+                "imdb_id": imdb_id,
+                "filename": filename(&name),
+                "source": "torrentio",
+                "info_hash": stream.info_hash,
+            })));
+        }
+    }
+
+    let detail = fetch_detail(&state, &imdb_id, CacheMode::Normal).await?.value;
+    let text = |field: &str| detail.get(field).and_then(|v| v.as_str()).filter(|v| *v != "N/A");
+    let title = text("Title").ok_or_else(|| ApiError::NotFound(format!("{imdb_id} sem título")))?;
+    Ok(Json(serde_json::json!({
+        "imdb_id": imdb_id,
+        "filename": from_title(title, text("Year"), quality, DEFAULT_EXTENSION),
+        "source": "title",
+        "info_hash": null,
+    })))
+}
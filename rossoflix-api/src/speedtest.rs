@@ -0,0 +1,182 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    Json,
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Query, State},
+    http::header,
+    response::IntoResponse,
+};
+use rusqlite::{OptionalExtension, params};
+use serde::Deserialize;
+
+use crate::{ApiError, AppState, auth::Identity};
+
+/// `Content-Type` do corpo do teste; fora da compressão (veja `with_layers`).
+pub const CONTENT_TYPE: &str = "application/octet-stream";
+/// Tamanho padrão do teste, sem `bytes`.
+const DEFAULT_BYTES: u64 = 10_000_000;
+const CHUNK: usize = 64 * 1024;
+/// Janela do limite de testes por cliente (`SPEEDTEST_PER_MINUTE`).
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+/// Teto do que o cliente pode informar; acima disso é erro de medida.
+const MAX_REPORTED_MBPS: f64 = 100_000.0;
+
+/// Testes em andamento e os começados no último minuto, por cliente.
+#[derive(Clone, Default)]
+pub struct Speedtests(Arc<Mutex<HashMap<String, ClientTests>>>);
+
+#[derive(Default)]
+struct ClientTests {
+    running: bool,
+    started: VecDeque<Instant>,
+}
+
+impl Speedtests {
+    /// Reserva o teste de `client`: um por vez e no máximo `per_minute` por
+    /// minuto (0 sem limite).
+    fn start(&self, client: &str, per_minute: u32) -> Result<Running, ApiError> {
+        let mut clients = self.0.lock().unwrap();
+        clients.retain(|_, c| c.running || c.started.back().is_some_and(|at| at.elapsed() < RATE_WINDOW));
+        let tests = clients.entry(client.to_string()).or_default();
+        if tests.running {
+            return Err(ApiError::Conflict("já há um teste de velocidade em andamento".into()));
+        }
+        while tests.started.front().is_some_and(|at| at.elapsed() >= RATE_WINDOW) {
+            tests.started.pop_front();
+        }
+        if per_minute > 0 && tests.started.len() >= per_minute as usize {
+            return Err(ApiError::RateLimited(format!("no máximo {per_minute} testes de velocidade por minuto")));
+        }
+        tests.running = true;
+        tests.started.push_back(Instant::now());
+        Ok(Running { tests: self.clone(), client: client.to_string() })
+    }
+}
+
+/// Libera o teste quando o corpo termina ou o cliente desconecta.
+struct Running {
+    tests: Speedtests,
+    client: String,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if let Some(tests) = self.tests.0.lock().unwrap().get_mut(&self.client) {
+            tests.running = false;
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SpeedtestParams {
+    bytes: Option<u64>,
+}
+
+/// `GET /speedtest?bytes=N` — N bytes pseudoaleatórios (incompressíveis),
+/// limitados a `SPEEDTEST_MAX_BYTES`, para o cliente cronometrar o caminho
+/// até este servidor.
+pub async fn speedtest(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    identity: Identity,
+    Query(params): Query<SpeedtestParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let config = state.config();
+    let bytes = params.bytes.unwrap_or(DEFAULT_BYTES);
+    if bytes == 0 {
+        return Err(ApiError::BadRequest("bytes precisa ser maior que zero".into()));
+    }
+    let bytes = bytes.min(config.speedtest_max_bytes);
+    let running = state.speedtests.start(&identity.client_key(client.ip()), config.speedtest_per_minute)?;
+
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(1) | 1;
+    let body = futures_util::stream::unfold((bytes, seed, running), |(left, mut rng, running)| async move {
+        if left == 0 {
+            return None;
+        }
+        let len = (left as usize).min(CHUNK);
+        let mut chunk = Vec::with_capacity(len + 8);
+        while chunk.len() < len {
+            // xorshift64: rápido e sem padrão que a compressão aproveite
+            rng ^= rng << 13;
+            rng ^= rng >> 7;
+            rng ^= rng << 17;
+            chunk.extend_from_slice(&rng.to_le_bytes());
+        }
+        chunk.truncate(len);
+        Some((Ok::<_, std::io::Error>(Bytes::from(chunk)), (left - len as u64, rng, running)))
+    });
+    Ok((
+        [
+            (header::CONTENT_TYPE, CONTENT_TYPE.to_string()),
+            (header::CONTENT_LENGTH, bytes.to_string()),
+            (header::CACHE_CONTROL, "no-store".to_string()),
+        ],
+        Body::from_stream(body),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SpeedtestReport {
+    mbps: f64,
+}
+
+/// `POST /speedtest/report` — guarda a velocidade medida pelo cliente (por
+/// usuário do proxy ou IP), usada como padrão em `/play`.
+pub async fn report(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    identity: Identity,
+    Json(report): Json<SpeedtestReport>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !report.mbps.is_finite() || report.mbps <= 0.0 || report.mbps > MAX_REPORTED_MBPS {
+        return Err(ApiError::BadRequest(format!("mbps fora do intervalo (0, {MAX_REPORTED_MBPS}]")));
+    }
+    let key = identity.client_key(client.ip());
+    let (client_key, mbps) = (key.clone(), report.mbps);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default();
+    state
+        .db
+        .call(move |conn| {
+            conn.execute(
+                "INSERT INTO speedtests (client, mbps, measured_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (client) DO UPDATE SET mbps = ?2, measured_at = ?3",
+                params![client_key, mbps, now],
+            )
+        })
+        .await?;
+    Ok(Json(serde_json::json!({
+        "client": key,
+        "mbps": report.mbps,
+        "max_height": max_height(report.mbps),
+    })))
+}
+
+/// Última velocidade informada por `client` (chave de [`Identity::client_key`]).
+pub async fn measured(state: &AppState, client: String) -> Result<Option<f64>, ApiError> {
+    let mbps = state
+        .db
+        .call(move |conn| {
+            conn.query_row("SELECT mbps FROM speedtests WHERE client = ?1", [client], |row| row.get(0))
+                .optional()
+        })
+        .await?;
+    Ok(mbps)
+}
+
+/// Maior resolução que a banda comporta com folga, pelas taxas usuais de
+/// cada uma (4K ~25 Mbps, 1080p ~8, 720p ~5).
+pub fn max_height(mbps: f64) -> u32 {
+    match mbps {
+        m if m >= 25.0 => 2160,
+        m if m >= 8.0 => 1080,
+        m if m >= 5.0 => 720,
+        _ => 480,
+    }
+}
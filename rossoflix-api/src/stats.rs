@@ -0,0 +1,326 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    Json,
+    extract::{Query, Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use rusqlite::{Connection, params};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::{ApiError, AppState, middleware::ErrorCode};
+
+/// Eventos esperando a gravação; com a fila cheia, o evento é descartado
+/// (e contado em `dropped`) em vez de segurar o pedido.
+const QUEUE: usize = 4096;
+/// Eventos gravados por transação.
+const BATCH: usize = 256;
+/// Intervalo entre as agregações em baldes por hora.
+const AGGREGATE_EVERY: Duration = Duration::from_secs(600);
+/// Baldes por hora mais velhos que isso são apagados; é também o teto de
+/// `days` em `/admin/insights`.
+const MAX_DAYS: u32 = 90;
+const TOP_TITLES: usize = 10;
+
+/// Um acontecimento contado nas estatísticas. Nada identifica quem fez o
+/// pedido: só o tipo, o título (IMDb id) e a causa da falha.
+#[derive(Debug)]
+pub enum Event {
+    Search,
+    Play { imdb_id: Option<String> },
+    Completion { imdb_id: String },
+    /// `/stream` que terminou em erro, pelo código do erro.
+    Failure { cause: &'static str },
+    /// Resposta servida do cache ou não (`X-Cache`).
+    Cache { hit: bool },
+}
+
+impl Event {
+    fn row(self) -> Row {
+        let (kind, title, cause) = match self {
+            Event::Search => ("search", None, ""),
+            Event::Play { imdb_id } => ("play", imdb_id, ""),
+            Event::Completion { imdb_id } => ("completion", Some(imdb_id), ""),
+            Event::Failure { cause } => ("failure", None, cause),
+            Event::Cache { hit: true } => ("cache_hit", None, ""),
+            Event::Cache { hit: false } => ("cache_miss", None, ""),
+        };
+        Row { at: unix_now(), kind, title: title.unwrap_or_default(), cause }
+    }
+}
+
+struct Row {
+    at: i64,
+    kind: &'static str,
+    title: String,
+    cause: &'static str,
+}
+
+/// Estatísticas de uso, só locais (`STATS_ENABLED`): os eventos vão para
+/// `stats_events` e, de tempos em tempos, são somados em `stats_hourly`.
+#[derive(Clone)]
+pub struct Stats {
+    tx: mpsc::Sender<Row>,
+    rx: Arc<Mutex<Option<mpsc::Receiver<Row>>>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        let (tx, rx) = mpsc::channel(QUEUE);
+        Stats { tx, rx: Arc::new(Mutex::new(Some(rx))), dropped: Default::default() }
+    }
+}
+
+/// Conta `event` sem esperar o banco; não faz nada com `STATS_ENABLED` off.
+pub fn record(state: &AppState, event: Event) {
+    if !state.config().stats_enabled {
+        return;
+    }
+    if state.stats.tx.try_send(event.row()).is_err() {
+        state.stats.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Grava os eventos em lotes e os agrega a cada [`AGGREGATE_EVERY`]. Roda
+/// sempre: ligar o `STATS_ENABLED` na recarga não precisa de reinício.
+pub fn spawn_recorder(state: AppState) {
+    let Some(mut rx) = state.stats.rx.lock().unwrap().take() else {
+        return;
+    };
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(AGGREGATE_EVERY);
+        tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                received = rx.recv() => {
+                    let Some(first) = received else { return };
+                    let mut batch = vec![first];
+                    while batch.len() < BATCH && let Ok(row) = rx.try_recv() {
+                        batch.push(row);
+                    }
+                    if let Err(e) = state.db.call(move |conn| insert(conn, batch)).await {
+                        warn!("stats: falha ao gravar eventos: {e}");
+                    }
+                }
+                _ = tick.tick() => {
+                    let retention = state.config().stats_raw_retention_hours;
+                    match state.db.call(move |conn| aggregate(conn, retention)).await {
+                        Ok(0) => {}
+                        Ok(n) => info!(events = n, "stats: eventos agregados por hora"),
+                        Err(e) => warn!("stats: falha ao agregar: {e}"),
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn insert(conn: &mut Connection, batch: Vec<Row>) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare_cached("INSERT INTO stats_events (at, kind, title, cause) VALUES (?1, ?2, ?3, ?4)")?;
+        for row in batch {
+            stmt.execute(params![row.at, row.kind, row.title, row.cause])?;
+        }
+    }
+    tx.commit()
+}
+
+/// Soma os eventos ainda não agregados nos baldes da hora deles, apaga os
+/// agregados há mais de `retention_hours` e os baldes de mais de
+/// [`MAX_DAYS`]. Devolve quantos eventos entraram nos baldes.
+fn aggregate(conn: &mut Connection, retention_hours: u64) -> rusqlite::Result<usize> {
+    let now = unix_now();
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO stats_hourly (hour, kind, title, cause, count)
+         SELECT at - at % 3600, kind, title, cause, COUNT(*) FROM stats_events
+         WHERE aggregated = 0 GROUP BY 1, 2, 3, 4
+         ON CONFLICT (hour, kind, title, cause) DO UPDATE SET count = count + excluded.count",
+        [],
+    )?;
+    let aggregated = tx.execute("UPDATE stats_events SET aggregated = 1 WHERE aggregated = 0", [])?;
+    tx.execute(
+        "DELETE FROM stats_events WHERE aggregated = 1 AND at < ?1",
+        [now - retention_hours as i64 * 3600],
+    )?;
+    tx.execute("DELETE FROM stats_hourly WHERE hour < ?1", [now - i64::from(MAX_DAYS) * 86_400])?;
+    tx.commit()?;
+    Ok(aggregated)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InsightsParams {
+    #[serde(default = "default_days")]
+    days: u32,
+}
+
+fn default_days() -> u32 {
+    7
+}
+
+#[derive(Debug, Serialize)]
+struct TopTitle {
+    imdb_id: String,
+    /// Do catálogo local, quando o título já passou pelo OMDb.
+    title: Option<String>,
+    plays: u64,
+    completions: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct FailureCause {
+    cause: String,
+    count: u64,
+    /// Sobre todas as tentativas de `/stream` (reproduções + falhas).
+    rate: f64,
+}
+
+/// `GET /admin/insights?days=7` — top títulos, reproduções por hora do dia
+/// (UTC), falhas do `/stream` por causa e eficiência do cache, somados
+/// pelo SQLite sobre os baldes por hora. Agrega antes os eventos pendentes.
+pub async fn insights(State(state): State<AppState>, Query(params): Query<InsightsParams>) -> Result<impl IntoResponse, ApiError> {
+    if !(1..=MAX_DAYS).contains(&params.days) {
+        return Err(ApiError::BadRequest(format!("days vai de 1 a {MAX_DAYS}")));
+    }
+    let config = state.config();
+    let retention = config.stats_raw_retention_hours;
+    let since = unix_now() - i64::from(params.days) * 86_400;
+    let since = since - since % 3600;
+    let insights = state
+        .db
+        .call(move |conn| {
+            aggregate(conn, retention)?;
+            let total = |kind: &str| -> rusqlite::Result<u64> {
+                conn.query_row(
+                    "SELECT COALESCE(SUM(count), 0) FROM stats_hourly WHERE hour >= ?1 AND kind = ?2",
+                    params![since, kind],
+                    |row| row.get(0),
+                )
+            };
+            let (searches, plays, completions, failures) =
+                (total("search")?, total("play")?, total("completion")?, total("failure")?);
+            let (hits, misses) = (total("cache_hit")?, total("cache_miss")?);
+
+            let mut stmt = conn.prepare(
+                "SELECT s.title, c.title,
+                        SUM(CASE WHEN s.kind = 'play' THEN s.count ELSE 0 END) AS plays,
+                        SUM(CASE WHEN s.kind = 'completion' THEN s.count ELSE 0 END) AS completions
+                 FROM stats_hourly s LEFT JOIN catalog c ON c.imdb_id = s.title
+                 WHERE s.hour >= ?1 AND s.title != '' AND s.kind IN ('play', 'completion')
+                 GROUP BY s.title ORDER BY plays DESC, completions DESC, s.title LIMIT ?2",
+            )?;
+            let top: Vec<TopTitle> = stmt
+                .query_map(params![since, TOP_TITLES], |row| {
+                    Ok(TopTitle { imdb_id: row.get(0)?, title: row.get(1)?, plays: row.get(2)?, completions: row.get(3)? })
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+
+            let mut by_hour = [0u64; 24];
+            let mut stmt = conn.prepare(
+                "SELECT (hour / 3600) % 24, SUM(count) FROM stats_hourly
+                 WHERE hour >= ?1 AND kind = 'play' GROUP BY 1",
+            )?;
+            for row in stmt.query_map([since], |row| Ok((row.get::<_, usize>(0)?, row.get::<_, u64>(1)?)))? {
+                let (hour, count) = row?;
+                by_hour[hour % 24] = count;
+            }
+
+            let attempts = plays + failures;
+            let mut stmt = conn.prepare(
+                "SELECT cause, SUM(count) FROM stats_hourly WHERE hour >= ?1 AND kind = 'failure'
+                 GROUP BY cause ORDER BY 2 DESC, cause",
+            )?;
+            let causes: Vec<FailureCause> = stmt
+                .query_map([since], |row| {
+                    let count: u64 = row.get(1)?;
+                    Ok(FailureCause { cause: row.get(0)?, count, rate: ratio(count, attempts) })
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+
+            Ok(serde_json::json!({
+                "searches": searches,
+                "plays": plays,
+                "completions": completions,
+                "top_titles": top,
+                "plays_by_hour_utc": by_hour,
+                "failures": {
+                    "total": failures,
+                    "rate": ratio(failures, attempts),
+                    "by_cause": causes,
+                },
+                "cache": {
+                    "hits": hits,
+                    "misses": misses,
+                    "hit_rate": ratio(hits, hits + misses),
+                },
+            }))
+        })
+        .await?;
+    let mut body = insights;
+    body["enabled"] = config.stats_enabled.into();
+    body["days"] = params.days.into();
+    body["dropped_events"] = state.stats.dropped.load(Ordering::Relaxed).into();
+    Ok(Json(body))
+}
+
+/// Conta as respostas do cache (`X-Cache`) e o resultado de cada `/stream`:
+/// reprodução quando serve o começo do arquivo, falha pelo código do erro.
+pub async fn observe(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if !state.config().stats_enabled {
+        return next.run(req).await;
+    }
+    let stream = req.uri().path() == "/stream";
+    let from_start = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .is_none_or(|range| range.trim().starts_with("bytes=0-"));
+    let imdb_id = stream
+        .then(|| Query::<StreamHints>::try_from_uri(req.uri()).ok())
+        .flatten()
+        .and_then(|Query(hints)| hints.imdb_id);
+
+    let resp = next.run(req).await;
+    match resp.headers().get("x-cache").map(|v| v.as_bytes()) {
+        Some(b"HIT") => record(&state, Event::Cache { hit: true }),
+        Some(b"MISS") => record(&state, Event::Cache { hit: false }),
+        _ => {}
+    }
+    if stream {
+        let status = resp.status();
+        if let Some(ErrorCode(code)) = resp.extensions().get::<ErrorCode>() {
+            record(&state, Event::Failure { cause: code });
+        } else if status.is_server_error() || status.is_client_error() {
+            record(&state, Event::Failure { cause: "other" });
+        } else if from_start && (status == StatusCode::OK || status == StatusCode::PARTIAL_CONTENT) {
+            record(&state, Event::Play { imdb_id });
+        }
+    }
+    resp
+}
+
+/// O que `observe` lê da query do `/stream`.
+#[derive(Debug, Deserialize)]
+struct StreamHints {
+    imdb_id: Option<String>,
+}
+
+fn ratio(part: u64, whole: u64) -> f64 {
+    if whole == 0 { 0.0 } else { part as f64 / whole as f64 }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default()
+}
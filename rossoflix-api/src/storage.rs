@@ -0,0 +1,357 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{Json, extract::State, response::IntoResponse};
+use serde::Serialize;
+use tokio::{fs, sync::Notify};
+use tracing::{info, warn};
+
+use crate::{AppState, downloads, magnet, trash};
+
+/// Intervalo entre as passadas de limpeza (um download concluído adianta a
+/// próxima).
+const SWEEP_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Teto e limpeza do diretório de downloads. Contam os downloads (pasta do
+/// infohash e log) e a lixeira; o scratch tem orçamento próprio. Downloads
+/// sem acesso há `DOWNLOADS_MAX_IDLE_DAYS` são apagados; passando de
+/// `DOWNLOADS_MAX_BYTES`, sai primeiro a lixeira, depois os marcados pelo
+/// `DELETE_AFTER_WATCH` e então os de acesso mais antigo. Downloads em
+/// andamento ou com stream aberto nunca são removidos.
+#[derive(Clone, Default)]
+pub struct Storage(Arc<Inner>);
+
+#[derive(Default)]
+struct Inner {
+    /// Último `/stream` de cada download nesta execução, por infohash; o
+    /// acesso de antes vem do atime/mtime dos arquivos.
+    accessed: Mutex<HashMap<String, SystemTime>>,
+    metrics: Mutex<Metrics>,
+    /// Uma passada por vez (a periódica e a de `/admin/storage/cleanup`).
+    running: tokio::sync::Mutex<()>,
+    wake: Notify,
+}
+
+/// Por que algo foi apagado.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    /// Sem acesso há `DOWNLOADS_MAX_IDLE_DAYS`.
+    Idle,
+    /// Entrada da lixeira, para caber no teto.
+    Trash,
+    /// Download, para caber no teto.
+    Quota,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PerReason {
+    pub idle: u64,
+    pub trash: u64,
+    pub quota: u64,
+}
+
+impl PerReason {
+    fn add(&mut self, reason: Reason, n: u64) {
+        match reason {
+            Reason::Idle => self.idle += n,
+            Reason::Trash => self.trash += n,
+            Reason::Quota => self.quota += n,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Removed {
+    /// Infohash do download ou id da entrada da lixeira.
+    pub id: String,
+    pub reason: Reason,
+    pub size_bytes: u64,
+}
+
+/// Resultado de uma passada.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    /// Unix timestamp (s).
+    pub at: u64,
+    pub used_bytes_before: u64,
+    pub used_bytes_after: u64,
+    pub reclaimed_bytes: u64,
+    pub removed: Vec<Removed>,
+    /// Ainda acima do teto, só com downloads em uso.
+    pub over_quota: bool,
+}
+
+/// Totais desde a subida.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Metrics {
+    pub runs: u64,
+    pub removed: PerReason,
+    pub reclaimed_bytes: PerReason,
+    pub last_run: Option<Report>,
+}
+
+/// Um download em disco.
+struct Candidate {
+    id: String,
+    size: u64,
+    last_access: SystemTime,
+    /// Assistido e marcado pelo `DELETE_AFTER_WATCH`.
+    watched: bool,
+}
+
+impl Storage {
+    /// Marca o acesso ao download dono de `path` (um arquivo sob
+    /// `downloads_dir`).
+    pub fn touch(&self, downloads_dir: &Path, path: &Path) {
+        if let Some(id) = info_hash_of(downloads_dir, path) {
+            self.0.accessed.lock().unwrap().insert(id, SystemTime::now());
+        }
+    }
+
+    /// Adianta a próxima passada (um download acabou de ocupar espaço).
+    pub fn wake(&self) {
+        self.0.wake.notify_one();
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        self.0.metrics.lock().unwrap().clone()
+    }
+
+    /// Apaga os downloads ociosos e, acima do teto, o que for preciso para
+    /// caber.
+    pub async fn sweep(&self, state: &AppState) -> Report {
+        let _running = self.0.running.lock().await;
+        let config = state.config();
+        let base = &config.downloads_dir;
+        let mut candidates = self.candidates(state).await;
+        let mut trash = trash::entries(base).await;
+        let used = candidates.iter().map(|c| c.size).sum::<u64>() + trash.iter().map(|e| e.size_bytes).sum::<u64>();
+        let mut report = Report { at: unix_now(), used_bytes_before: used, ..Default::default() };
+        let mut used = used;
+
+        if config.downloads_max_idle_days > 0 {
+            let cutoff = SystemTime::now() - Duration::from_secs(config.downloads_max_idle_days * 24 * 3600);
+            let mut kept = Vec::with_capacity(candidates.len());
+            for candidate in candidates {
+                if candidate.last_access < cutoff && self.remove_download(state, &candidate, Reason::Idle, &mut report).await {
+                    used = used.saturating_sub(candidate.size);
+                } else {
+                    kept.push(candidate);
+                }
+            }
+            candidates = kept;
+        }
+
+        let max = config.downloads_max_bytes;
+        if max > 0 && used > max {
+            // `entries` vem da mais antiga para a mais nova
+            for entry in trash.drain(..) {
+                if used <= max {
+                    break;
+                }
+                let path = trash::trash_dir(base).join(&entry.id);
+                match fs::remove_dir_all(&path).await {
+                    Ok(()) => {
+                        used = used.saturating_sub(entry.size_bytes);
+                        record(&mut report, entry.id, Reason::Trash, entry.size_bytes);
+                    }
+                    Err(e) => warn!(path = %path.display(), "armazenamento: falha ao limpar a lixeira: {e}"),
+                }
+            }
+            candidates.sort_by_key(|c| (!c.watched, c.last_access));
+            for candidate in &candidates {
+                if used <= max {
+                    break;
+                }
+                if self.remove_download(state, candidate, Reason::Quota, &mut report).await {
+                    used = used.saturating_sub(candidate.size);
+                }
+            }
+            report.over_quota = used > max;
+            if report.over_quota {
+                warn!(used, max, "downloads acima do teto, só com downloads em uso");
+            }
+        }
+
+        report.used_bytes_after = used;
+        let mut metrics = self.0.metrics.lock().unwrap();
+        metrics.runs += 1;
+        for removed in &report.removed {
+            metrics.removed.add(removed.reason, 1);
+            metrics.reclaimed_bytes.add(removed.reason, removed.size_bytes);
+        }
+        metrics.last_run = Some(report.clone());
+        report
+    }
+
+    /// Os downloads em disco, com tamanho e último acesso.
+    async fn candidates(&self, state: &AppState) -> Vec<Candidate> {
+        let base = &state.config().downloads_dir;
+        let Ok(mut dir) = fs::read_dir(base).await else {
+            return Vec::new();
+        };
+        let mut ids = HashSet::new();
+        while let Ok(Some(entry)) = dir.next_entry().await {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let id = name.strip_suffix(".log").unwrap_or(&name);
+            if magnet::is_info_hash(id) {
+                ids.insert(id.to_string());
+            }
+        }
+        let watched = watched_downloads(state).await;
+        let accessed = self.0.accessed.lock().unwrap().clone();
+
+        let mut out = Vec::with_capacity(ids.len());
+        for id in ids {
+            let (mut size, mut last_access) = usage(&downloads::job_dir(base, &id)).await;
+            if let Ok(meta) = fs::metadata(downloads::log_path(base, &id)).await {
+                size += meta.len();
+                last_access = last_access.max(accessed_at(&meta));
+            }
+            if let Some(at) = accessed.get(&id) {
+                last_access = last_access.max(*at);
+            }
+            out.push(Candidate { watched: watched.contains(&id), id, size, last_access });
+        }
+        out
+    }
+
+    /// Apaga o download de vez, a menos que esteja em andamento ou com
+    /// stream aberto.
+    async fn remove_download(&self, state: &AppState, candidate: &Candidate, reason: Reason, report: &mut Report) -> bool {
+        let id = &candidate.id;
+        if state.download_jobs.is_running(id) {
+            return false;
+        }
+        let base = &state.config().downloads_dir;
+        let dir = downloads::job_dir(base, id);
+        let Ok(_lease) = state.leases.exclusive(&dir) else {
+            return false;
+        };
+        if let Err(e) = fs::remove_dir_all(&dir).await
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            warn!(id, "armazenamento: falha ao apagar o download: {e}");
+            return false;
+        }
+        let _ = fs::remove_file(downloads::log_path(base, id)).await;
+        if let Err(e) = state.downloads.remove(id).await {
+            warn!(id, "armazenamento: download apagado, mas continua no índice: {e}");
+        }
+        state.download_jobs.forget(id);
+        self.0.accessed.lock().unwrap().remove(id);
+        info!(id, size_bytes = candidate.size, ?reason, "armazenamento: download apagado");
+        record(report, id.clone(), reason, candidate.size);
+        true
+    }
+}
+
+fn record(report: &mut Report, id: String, reason: Reason, size_bytes: u64) {
+    report.reclaimed_bytes += size_bytes;
+    report.removed.push(Removed { id, reason, size_bytes });
+}
+
+/// Infohash do primeiro componente de `path` dentro de `downloads_dir`.
+fn info_hash_of(downloads_dir: &Path, path: &Path) -> Option<String> {
+    let first = path.strip_prefix(downloads_dir).ok()?.components().next()?;
+    let name = first.as_os_str().to_str()?;
+    magnet::is_info_hash(name).then(|| name.to_string())
+}
+
+/// Downloads com algum arquivo assistido e marcado para limpeza.
+async fn watched_downloads(state: &AppState) -> HashSet<String> {
+    let paths = state
+        .db
+        .call(|conn| {
+            let mut stmt = conn.prepare("SELECT path FROM watched WHERE cleanup = 1 AND path IS NOT NULL")?;
+            stmt.query_map([], |row| row.get::<_, String>(0))?.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
+        .unwrap_or_default();
+    let base = &state.config().downloads_dir;
+    paths.iter().filter_map(|p| info_hash_of(base, &base.join(p))).collect()
+}
+
+/// Tamanho e acesso mais recente (atime ou mtime) dos arquivos em `dir`.
+async fn usage(dir: &Path) -> (u64, SystemTime) {
+    let (mut size, mut last) = (0, UNIX_EPOCH);
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(mut entries) = fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let Ok(meta) = entry.metadata().await else {
+                continue;
+            };
+            if meta.is_dir() {
+                pending.push(entry.path());
+            } else {
+                size += meta.len();
+                last = last.max(accessed_at(&meta));
+            }
+        }
+    }
+    (size, last)
+}
+
+/// Com `noatime` (ou `relatime`, que atualiza no máximo uma vez por dia)
+/// vale a modificação.
+fn accessed_at(meta: &std::fs::Metadata) -> SystemTime {
+    let modified = meta.modified().unwrap_or(UNIX_EPOCH);
+    meta.accessed().map_or(modified, |accessed| accessed.max(modified))
+}
+
+/// Limpeza periódica, em segundo plano.
+pub fn spawn_cleaner(state: AppState) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = tick.tick() => {}
+                _ = state.storage.0.wake.notified() => {}
+            }
+            let config = state.config();
+            if config.downloads_max_bytes == 0 && config.downloads_max_idle_days == 0 {
+                continue;
+            }
+            let report = state.storage.sweep(&state).await;
+            if !report.removed.is_empty() {
+                info!(removed = report.removed.len(), reclaimed_bytes = report.reclaimed_bytes, "armazenamento: limpeza concluída");
+            }
+        }
+    });
+}
+
+/// `GET /admin/storage` — uso do diretório de downloads, limites e o que a
+/// limpeza já apagou.
+pub async fn storage_status(State(state): State<AppState>) -> impl IntoResponse {
+    let config = state.config();
+    let downloads: u64 = state.storage.candidates(&state).await.iter().map(|c| c.size).sum();
+    let trash: u64 = trash::entries(&config.downloads_dir).await.iter().map(|e| e.size_bytes).sum();
+    Json(serde_json::json!({
+        "max_bytes": config.downloads_max_bytes,
+        "max_idle_days": config.downloads_max_idle_days,
+        "used_bytes": downloads + trash,
+        "downloads_bytes": downloads,
+        "trash_bytes": trash,
+        "metrics": state.storage.metrics(),
+    }))
+}
+
+/// `POST /admin/storage/cleanup` — roda uma passada agora.
+pub async fn run_cleanup(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.storage.sweep(&state).await)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
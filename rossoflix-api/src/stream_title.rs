@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{ApiError, markers::check_imdb_id};
+
+/// O que está sendo assistido, segundo as dicas `imdb_id`, `season` e
+/// `episode` de `/stream`. Vai para o stream ativo (`/admin/streams`) e
+/// para o índice dos downloads, de onde `/subtitles/match` tira o título
+/// quando o cliente não manda.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamTitle {
+    pub imdb_id: String,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+}
+
+impl StreamTitle {
+    /// `None` sem dicas. `season` e `episode` exigem `imdb_id`, e `episode`
+    /// exige `season`; `season` sozinho é a temporada inteira.
+    pub fn from_hints(imdb_id: Option<&str>, season: Option<u32>, episode: Option<u32>) -> Result<Option<Self>, ApiError> {
+        let Some(imdb_id) = imdb_id else {
+            return match (season, episode) {
+                (None, None) => Ok(None),
+                _ => Err(ApiError::BadRequest("season e episode exigem imdb_id".into())),
+            };
+        };
+        check_imdb_id(imdb_id)?;
+        if episode.is_some() && season.is_none() {
+            return Err(ApiError::BadRequest("episode exige season".into()));
+        }
+        Ok(Some(StreamTitle {
+            imdb_id: imdb_id.to_string(),
+            season,
+            episode,
+        }))
+    }
+}
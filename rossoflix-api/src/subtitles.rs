@@ -0,0 +1,501 @@
+use std::{io::SeekFrom, path::Path};
+
+use axum::{
+    Json,
+    extract::{Path as UrlPath, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use tracing::{info, warn};
+
+use crate::{
+    ApiError, AppState,
+    attribution::{self, Source},
+    cache::CacheEnvelope,
+    downloads, find_downloaded_file,
+    language::LanguageTag,
+    magnet,
+    markers::check_imdb_id,
+    stream_title::StreamTitle,
+    upstream,
+};
+
+/// Legendas convertidas, em `<DOWNLOADS_DIR>/.subtitles/`: cada download
+/// gasta da cota diária do OpenSubtitles.
+const CACHE_DIR: &str = ".subtitles";
+/// Versão das entradas do cache. Suba ao mudar a conversão: as antigas
+/// passam a ser baixadas de novo.
+const CACHE_VERSION: u8 = 1;
+
+/// Tamanho de cada janela lida pelo moviehash (início e fim do arquivo).
+const HASH_CHUNK: u64 = 64 * 1024;
+
+/// "moviehash" do OpenSubtitles: tamanho do arquivo somado (com overflow)
+/// às palavras u64 little-endian dos primeiros e dos últimos 64 KiB. Lê só
+/// as duas janelas; em arquivos menores que 64 KiB as janelas cobrem o
+/// arquivo inteiro (e se sobrepõem), com a última palavra completada com
+/// zeros.
+pub async fn moviehash(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path).await?;
+    let size = file.metadata().await?.len();
+    let window = size.min(HASH_CHUNK);
+
+    let mut hash = size;
+    let mut buf = vec![0u8; window as usize];
+    for offset in [0, size - window] {
+        file.seek(SeekFrom::Start(offset)).await?;
+        file.read_exact(&mut buf).await?;
+        for word in buf.chunks(8) {
+            let mut bytes = [0u8; 8];
+            bytes[..word.len()].copy_from_slice(word);
+            hash = hash.wrapping_add(u64::from_le_bytes(bytes));
+        }
+    }
+    Ok(format!("{hash:016x}"))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MatchParams {
+    filename: String,
+    /// Infohash do download, para localizar o arquivo mais rápido.
+    id: Option<String>,
+    /// Códigos separados por vírgula (`pt-br,en`).
+    languages: Option<String>,
+    /// Título do arquivo; sem ele vale o que `/stream` gravou para o `id`.
+    imdb_id: Option<String>,
+    season: Option<u32>,
+    episode: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum MatchedBy {
+    Hash,
+    Title,
+}
+
+#[derive(Debug, Serialize)]
+struct Candidate {
+    file_id: u64,
+    file_name: Option<String>,
+    language: Option<String>,
+    release: Option<String>,
+    download_count: u64,
+    matched_by: MatchedBy,
+}
+
+#[derive(Debug, Deserialize)]
+struct OsResponse {
+    #[serde(default)]
+    data: Vec<OsSubtitle>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OsSubtitle {
+    attributes: OsAttributes,
+}
+
+#[derive(Debug, Deserialize)]
+struct OsAttributes {
+    language: Option<String>,
+    release: Option<String>,
+    #[serde(default)]
+    download_count: u64,
+    #[serde(default)]
+    moviehash_match: bool,
+    #[serde(default)]
+    files: Vec<OsFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OsFile {
+    file_id: u64,
+    file_name: Option<String>,
+}
+
+/// `GET /subtitles/match?filename=...` — legendas do OpenSubtitles para um
+/// arquivo baixado: primeiro pelo moviehash e, sem resultado, pelo IMDb id
+/// (com temporada e episódio) do título assistido ou, sem ele, pelo título
+/// extraído do nome. Resultados por hash vêm antes, depois por downloads.
+pub async fn match_subtitles(
+    State(state): State<AppState>,
+    Query(params): Query<MatchParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let config = state.config();
+    let Some(api_key) = config.opensubtitles_api_key.as_deref() else {
+        return Err(ApiError::Unavailable("OPENSUBTITLES_API_KEY não configurada".into()));
+    };
+
+    let base = &config.downloads_dir;
+    let info_hash = match params.id.as_deref() {
+        Some(id) if magnet::is_info_hash(id) => Some(id.to_ascii_lowercase()),
+        Some(_) => return Err(ApiError::BadRequest("id inválido".into())),
+        None => None,
+    };
+    let search_dir = match &info_hash {
+        Some(id) => downloads::job_dir(base, id),
+        None => base.clone(),
+    };
+    let watched = match StreamTitle::from_hints(params.imdb_id.as_deref(), params.season, params.episode)? {
+        Some(title) => Some(title),
+        None => match &info_hash {
+            Some(id) => state.downloads.by_info_hash(id).await?.and_then(|d| d.title),
+            None => None,
+        },
+    };
+    let path = find_downloaded_file(&search_dir, &params.filename)
+        .await
+        .ok_or_else(|| ApiError::NotFound(format!("{} não encontrado", params.filename)))?;
+    let hash = moviehash(&path)
+        .await
+        .map_err(|e| ApiError::BadRequest(format!("não foi possível calcular o moviehash: {e}")))?;
+
+    let languages = params.languages.as_deref().unwrap_or("pt-br,en");
+    let mut candidates = query(&state, api_key, &[("moviehash", &hash), ("languages", languages)])
+        .await?
+        .into_iter()
+        .filter(|c| c.matched_by == MatchedBy::Hash)
+        .collect::<Vec<_>>();
+
+    let title = title_from_filename(&params.filename);
+    if candidates.is_empty() {
+        if let Some(watched) = &watched {
+            // o OpenSubtitles quer o id sem o `tt`
+            let imdb_id = watched.imdb_id.trim_start_matches("tt").to_string();
+            let mut by_id = vec![("imdb_id", imdb_id), ("languages", languages.to_string())];
+            by_id.extend(watched.season.map(|s| ("season_number", s.to_string())));
+            by_id.extend(watched.episode.map(|e| ("episode_number", e.to_string())));
+            let by_id: Vec<(&str, &str)> = by_id.iter().map(|(k, v)| (*k, v.as_str())).collect();
+            candidates = query(&state, api_key, &by_id).await?;
+        } else if !title.is_empty() {
+            candidates = query(&state, api_key, &[("query", &title), ("languages", languages)]).await?;
+        }
+    }
+    candidates.sort_by(|a, b| {
+        (b.matched_by == MatchedBy::Hash)
+            .cmp(&(a.matched_by == MatchedBy::Hash))
+            .then(b.download_count.cmp(&a.download_count))
+    });
+
+    let mut body = serde_json::json!({
+        "filename": params.filename,
+        "moviehash": hash,
+        "title": title,
+        "watched": watched,
+        "candidates": candidates,
+    });
+    attribution::annotate(&mut body, [Source::OpenSubtitles]);
+    Ok(Json(body))
+}
+
+async fn query(state: &AppState, api_key: &str, params: &[(&str, &str)]) -> Result<Vec<Candidate>, ApiError> {
+    let url = format!("{}/subtitles", state.config().opensubtitles_base_url);
+    let resp = upstream::get(state, upstream::Service::OpenSubtitles, "/subtitles", &url)
+        .header("Api-Key", api_key)
+        .query(params)
+        .send()
+        .await
+        .map_err(upstream::send_error)?;
+    if !resp.status().is_success() {
+        return Err(ApiError::Upstream(format!("OpenSubtitles: status {}", resp.status())));
+    }
+    let body: OsResponse = upstream::json(state, resp).await?;
+
+    Ok(body
+        .data
+        .into_iter()
+        .filter_map(|s| {
+            let attrs = s.attributes;
+            let file = attrs.files.into_iter().next()?;
+            Some(Candidate {
+                file_id: file.file_id,
+                file_name: file.file_name,
+                language: attrs.language,
+                release: attrs.release,
+                download_count: attrs.download_count,
+                matched_by: if attrs.moviehash_match {
+                    MatchedBy::Hash
+                } else {
+                    MatchedBy::Title
+                },
+            })
+        })
+        .collect())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubtitleParams {
+    /// Idioma da legenda (`pt-BR`, `en`...).
+    #[serde(default = "default_lang")]
+    lang: String,
+    season: Option<u32>,
+    episode: Option<u32>,
+}
+
+fn default_lang() -> String {
+    "pt-BR".into()
+}
+
+#[derive(Debug, Deserialize)]
+struct OsDownload {
+    link: String,
+}
+
+/// Legenda guardada: o WebVTT e de onde ele veio.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedSubtitle {
+    file_id: u64,
+    release: Option<String>,
+    vtt: String,
+}
+
+/// `GET /subtitles/:imdb_id?lang=pt-BR&season=&episode=` — a legenda mais
+/// baixada do título no OpenSubtitles, convertida para WebVTT e servida
+/// como `text/vtt` (direto no `<track>` do player). Fica em cache em disco
+/// (`X-Cache: HIT|MISS`); sem legenda no idioma, `404`.
+pub async fn subtitle_vtt(
+    State(state): State<AppState>,
+    UrlPath(imdb_id): UrlPath<String>,
+    Query(params): Query<SubtitleParams>,
+) -> Result<Response, ApiError> {
+    check_imdb_id(&imdb_id)?;
+    let lang: LanguageTag = params.lang.parse().map_err(ApiError::BadRequest)?;
+    if params.episode.is_some() && params.season.is_none() {
+        return Err(ApiError::BadRequest("episode exige season".into()));
+    }
+    let config = state.config();
+    let mut key = imdb_id.clone();
+    if let Some(season) = params.season {
+        key.push_str(&format!(".s{season:02}"));
+    }
+    if let Some(episode) = params.episode {
+        key.push_str(&format!("e{episode:02}"));
+    }
+    let cached_path = config.downloads_dir.join(CACHE_DIR).join(format!("{key}.{lang}.json"));
+
+    let (subtitle, cache) = match read_cached(&cached_path).await {
+        Some(subtitle) => (subtitle, "HIT"),
+        None => {
+            let Some(api_key) = config.opensubtitles_api_key.as_deref() else {
+                return Err(ApiError::Unavailable("OPENSUBTITLES_API_KEY não configurada".into()));
+            };
+            // o OpenSubtitles usa o id sem o `tt` e os idiomas em minúsculas
+            let os_lang = lang.to_string().to_ascii_lowercase();
+            let mut search = vec![("imdb_id", imdb_id.trim_start_matches("tt").to_string()), ("languages", os_lang.clone())];
+            search.extend(params.season.map(|s| ("season_number", s.to_string())));
+            search.extend(params.episode.map(|e| ("episode_number", e.to_string())));
+            let search: Vec<(&str, &str)> = search.iter().map(|(k, v)| (*k, v.as_str())).collect();
+            let best = query(&state, api_key, &search)
+                .await?
+                .into_iter()
+                .filter(|c| c.language.as_deref().is_none_or(|l| l.eq_ignore_ascii_case(&os_lang)))
+                .max_by_key(|c| c.download_count)
+                .ok_or_else(|| ApiError::NotFound(format!("nenhuma legenda em {lang} para {key}")))?;
+            let raw = download(&state, api_key, best.file_id).await?;
+            let vtt = to_webvtt(&decode(&raw))
+                .ok_or_else(|| ApiError::Upstream("OpenSubtitles: legenda em formato desconhecido".into()))?;
+            info!(imdb_id, lang = %lang, file_id = best.file_id, "legenda baixada do OpenSubtitles");
+            let subtitle = CachedSubtitle { file_id: best.file_id, release: best.release, vtt };
+            write_cached(&cached_path, &subtitle).await;
+            (subtitle, "MISS")
+        }
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/vtt; charset=utf-8"),
+            (header::CACHE_CONTROL, "public, max-age=86400"),
+            (header::HeaderName::from_static("x-cache"), cache),
+        ],
+        subtitle.vtt,
+    )
+        .into_response())
+}
+
+/// Pede o link do arquivo (`POST /download`) e baixa a legenda.
+async fn download(state: &AppState, api_key: &str, file_id: u64) -> Result<Vec<u8>, ApiError> {
+    let url = format!("{}/download", state.config().opensubtitles_base_url);
+    let resp = upstream::post(state, upstream::Service::OpenSubtitles, "/download", &url)
+        .header("Api-Key", api_key)
+        .json(&serde_json::json!({ "file_id": file_id }))
+        .send()
+        .await
+        .map_err(upstream::send_error)?;
+    match resp.status() {
+        // a cota diária de downloads acabou
+        status if status.as_u16() == 406 || status.as_u16() == 429 => {
+            return Err(ApiError::RateLimited("cota de downloads do OpenSubtitles esgotada".into()));
+        }
+        status if !status.is_success() => {
+            return Err(ApiError::Upstream(format!("OpenSubtitles: status {status}")));
+        }
+        _ => {}
+    }
+    let link: OsDownload = upstream::json(state, resp).await?;
+    let resp = upstream::get(state, upstream::Service::OpenSubtitles, "/download/file", &link.link)
+        .send()
+        .await
+        .map_err(upstream::send_error)?;
+    if !resp.status().is_success() {
+        return Err(ApiError::Upstream(format!("OpenSubtitles: arquivo com status {}", resp.status())));
+    }
+    upstream::read_body(resp, state.config().max_upstream_body_bytes).await
+}
+
+async fn read_cached(path: &Path) -> Option<CachedSubtitle> {
+    CacheEnvelope::open(&fs::read(path).await.ok()?, CACHE_VERSION)
+}
+
+async fn write_cached(path: &Path, subtitle: &CachedSubtitle) {
+    let tmp = path.with_extension("json.tmp");
+    let result = async {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        fs::write(&tmp, CacheEnvelope::seal(CACHE_VERSION, subtitle)?).await?;
+        fs::rename(&tmp, path).await
+    };
+    if let Err(e) = result.await {
+        warn!(path = %path.display(), "falha ao guardar a legenda: {e}");
+    }
+}
+
+/// UTF-8 (sem BOM) ou, se não for, Latin-1, comum nas legendas antigas.
+fn decode(raw: &[u8]) -> String {
+    match std::str::from_utf8(raw) {
+        Ok(text) => text.trim_start_matches('\u{feff}').to_string(),
+        Err(_) => raw.iter().map(|&b| b as char).collect(),
+    }
+}
+
+/// SRT para WebVTT: cabeçalho, quebras `\n` e vírgula decimal trocada por
+/// ponto nas linhas de tempo. Um WebVTT passa como está; sem nenhuma linha
+/// de tempo, `None`.
+fn to_webvtt(text: &str) -> Option<String> {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    if text.starts_with("WEBVTT") {
+        return Some(text);
+    }
+    if !text.contains("-->") {
+        return None;
+    }
+    let mut vtt = String::from("WEBVTT\n\nNOTE Legenda do OpenSubtitles (opensubtitles.com)\n\n");
+    for line in text.trim_start().lines() {
+        if line.contains("-->") {
+            vtt.push_str(&line.replace(',', "."));
+        } else {
+            vtt.push_str(line);
+        }
+        vtt.push('\n');
+    }
+    Some(vtt)
+}
+
+/// Título a partir do nome do release: tokens até o ano, a resolução ou o
+/// marcador de episódio (`Duna.Parte.Dois.2024.1080p.mkv` → `Duna Parte Dois`).
+fn title_from_filename(filename: &str) -> String {
+    let stem = Path::new(filename)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut words = Vec::new();
+    for token in stem.split(['.', '_', ' ', '-']).filter(|t| !t.is_empty()) {
+        let lower = token.to_ascii_lowercase();
+        let trimmed = lower.trim_matches(|c| c == '(' || c == ')' || c == '[' || c == ']');
+        let is_year = trimmed.len() == 4
+            && trimmed.parse::<u32>().is_ok_and(|y| (1900..=2100).contains(&y))
+            && !words.is_empty();
+        let is_resolution = trimmed.ends_with('p') && trimmed[..trimmed.len() - 1].parse::<u32>().is_ok();
+        let is_episode = trimmed.len() >= 4
+            && trimmed.starts_with('s')
+            && trimmed[1..].split('e').count() == 2
+            && trimmed[1..].replace('e', "").chars().all(|c| c.is_ascii_digit());
+        if is_year || is_resolution || is_episode {
+            break;
+        }
+        words.push(token.to_string());
+    }
+    words.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Grava `bytes` num arquivo temporário e devolve o moviehash.
+    async fn hash_of(name: &str, bytes: &[u8]) -> String {
+        let path = std::env::temp_dir()
+            .join(format!("rossoflix-moviehash-{}-{name}", std::process::id()));
+        fs::write(&path, bytes).await.unwrap();
+        let hash = moviehash(&path).await.unwrap();
+        fs::remove_file(&path).await.unwrap();
+        hash
+    }
+
+    /// Implementação de referência, lendo o arquivo inteiro da memória.
+    fn reference(bytes: &[u8]) -> String {
+        let window = bytes.len().min(HASH_CHUNK as usize);
+        let mut hash = bytes.len() as u64;
+        for part in [&bytes[..window], &bytes[bytes.len() - window..]] {
+            for word in part.chunks(8) {
+                let mut padded = [0u8; 8];
+                padded[..word.len()].copy_from_slice(word);
+                hash = hash.wrapping_add(u64::from_le_bytes(padded));
+            }
+        }
+        format!("{hash:016x}")
+    }
+
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    #[tokio::test]
+    async fn zero_filled_files_hash_to_their_size() {
+        assert_eq!(hash_of("empty", &[]).await, "0000000000000000");
+        assert_eq!(hash_of("zeros-1k", &[0; 1000]).await, "00000000000003e8");
+        assert_eq!(hash_of("zeros-200k", &vec![0; 200_000]).await, "0000000000030d40");
+    }
+
+    #[tokio::test]
+    async fn known_vectors() {
+        // Uma palavra 0x01 no início e outra 0x02 no fim: 3 + 128 KiB.
+        let mut bytes = vec![0u8; 2 * HASH_CHUNK as usize];
+        bytes[0] = 1;
+        let last = bytes.len() - 8;
+        bytes[last] = 2;
+        assert_eq!(hash_of("words", &bytes).await, "0000000000020003");
+
+        // Menor que uma janela: as duas janelas são o arquivo todo.
+        assert_eq!(hash_of("small", &[1, 0, 0, 0, 0, 0, 0, 0, 2]).await, "000000000000000f");
+
+        // Soma com overflow.
+        let mut bytes = vec![0xffu8; 2 * HASH_CHUNK as usize];
+        bytes[..8].copy_from_slice(&0u64.to_le_bytes());
+        let words = 2 * HASH_CHUNK / 8 - 1;
+        let want = (2 * HASH_CHUNK).wrapping_add(u64::MAX.wrapping_mul(words));
+        assert_eq!(hash_of("overflow", &bytes).await, format!("{want:016x}"));
+    }
+
+    #[tokio::test]
+    async fn matches_the_reference_across_sizes() {
+        for len in [1, 7, 8, 9, 4096, 65_535, 65_536, 65_537, 100_003, 131_072, 300_000] {
+            let bytes = pattern(len);
+            assert_eq!(hash_of(&format!("len-{len}"), &bytes).await, reference(&bytes), "{len} bytes");
+        }
+    }
+
+    #[tokio::test]
+    async fn ignores_the_middle_of_large_files() {
+        let mut bytes = pattern(3 * HASH_CHUNK as usize);
+        let before = hash_of("middle-a", &bytes).await;
+        bytes[HASH_CHUNK as usize + 10] ^= 0xff;
+        assert_eq!(hash_of("middle-b", &bytes).await, before);
+        bytes[HASH_CHUNK as usize - 1] ^= 0xff;
+        assert_ne!(hash_of("middle-c", &bytes).await, before);
+    }
+}
@@ -0,0 +1,259 @@
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::{AppState, aria2::DownloadFailure, config::Config, downloads};
+
+const API: &str = "https://api.telegram.org";
+
+/// Long-polling: o Telegram segura o `getUpdates` por até esse tempo.
+const POLL_TIMEOUT_SECS: u64 = 30;
+
+/// Espera depois de uma falha da API antes de tentar de novo.
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Máximo de downloads listados por `/downloads` (mensagens têm até 4096 caracteres).
+const MAX_LISTED: usize = 30;
+
+/// Bot do Telegram: avisos de fim de download e comandos por long-polling.
+/// Erros da API só vão para o log; nunca derrubam o servidor.
+#[derive(Clone)]
+pub struct Telegram {
+    http: Client,
+    token: String,
+    chat_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Updates {
+    #[serde(default)]
+    result: Vec<Update>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<Message>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Message {
+    chat: Chat,
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+impl Telegram {
+    /// `None` sem `TELEGRAM_BOT_TOKEN` (ou sem `TELEGRAM_CHAT_ID`, com aviso).
+    pub fn from_config(http: &Client, config: &Config) -> Option<Self> {
+        let token = config.telegram_bot_token.clone()?;
+        let Some(chat_id) = config.telegram_chat_id.clone() else {
+            warn!("TELEGRAM_BOT_TOKEN definido sem TELEGRAM_CHAT_ID; Telegram desligado");
+            return None;
+        };
+        Some(Self {
+            http: http.clone(),
+            token,
+            chat_id,
+        })
+    }
+
+    fn url(&self, method: &str) -> String {
+        format!("{API}/bot{}/{method}", self.token)
+    }
+
+    /// Envia em segundo plano para o chat configurado.
+    pub fn notify(&self, text: String) {
+        let bot = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = bot.send(&text).await {
+                // sem a URL: ela contém o token
+                warn!("telegram: falha ao enviar mensagem: {}", e.without_url());
+            }
+        });
+    }
+
+    /// Entrega pela fila de avisos (`outbox`); o erro volta sem a URL, que
+    /// contém o token.
+    pub async fn deliver(&self, text: &str) -> Result<(), String> {
+        self.send(text).await.map_err(|e| e.without_url().to_string())
+    }
+
+    async fn send(&self, text: &str) -> Result<(), reqwest::Error> {
+        self.http
+            .post(self.url("sendMessage"))
+            .json(&serde_json::json!({ "chat_id": self.chat_id, "text": text }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn updates(&self, offset: i64) -> Result<Vec<Update>, reqwest::Error> {
+        let updates: Updates = self
+            .http
+            .get(self.url("getUpdates"))
+            .query(&[("offset", offset.to_string()), ("timeout", POLL_TIMEOUT_SECS.to_string())])
+            .timeout(Duration::from_secs(POLL_TIMEOUT_SECS + 10))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(updates.result)
+    }
+}
+
+/// Atende comandos do chat configurado em segundo plano. Não faz nada com
+/// o Telegram desligado.
+pub fn spawn_poller(state: AppState) {
+    let Some(bot) = state.telegram.clone() else {
+        return;
+    };
+    tokio::spawn(async move {
+        info!("telegram: aguardando comandos");
+        let mut offset = 0;
+        loop {
+            let updates = match bot.updates(offset).await {
+                Ok(updates) => updates,
+                Err(e) => {
+                    warn!("telegram: getUpdates falhou: {}", e.without_url());
+                    tokio::time::sleep(RETRY_DELAY).await;
+                    continue;
+                }
+            };
+            for update in updates {
+                offset = offset.max(update.update_id + 1);
+                let Some(Message { chat, text: Some(text) }) = update.message else {
+                    continue;
+                };
+                if chat.id.to_string() != bot.chat_id {
+                    warn!(chat = chat.id, "telegram: comando de chat não autorizado ignorado");
+                    continue;
+                }
+                if let Some(reply) = handle_command(&state, &text).await {
+                    bot.notify(reply);
+                }
+            }
+        }
+    });
+}
+
+/// Aviso de fim de download (concluído, cancelado ou falho).
+pub fn download_message(
+    filename: &str,
+    result: &Result<(), DownloadFailure>,
+    size_bytes: Option<u64>,
+    cancelled: bool,
+) -> String {
+    match result {
+        Ok(()) => match size_bytes {
+            Some(size) => format!("Download concluído: {filename} ({})", format_size(size)),
+            None => format!("Download concluído: {filename}"),
+        },
+        Err(_) if cancelled => format!("Download cancelado: {filename}"),
+        Err(failure) => match failure.exit_code {
+            Some(code) => format!("Download falhou: {filename} (aria2c saiu com código {code})"),
+            None => format!("Download falhou: {filename} ({})", failure.output_tail),
+        },
+    }
+}
+
+async fn handle_command(state: &AppState, text: &str) -> Option<String> {
+    let mut parts = text.split_whitespace();
+    // `/status@meubot` em grupos
+    let command = parts.next()?.split('@').next()?;
+    let reply = match command {
+        "/status" => status(state),
+        "/downloads" => list_downloads(state).await,
+        "/cancel" => match parts.next() {
+            Some(job) => cancel(state, job),
+            None => "uso: /cancel <job>".into(),
+        },
+        "/start" | "/help" => HELP.into(),
+        _ => return None,
+    };
+    Some(reply)
+}
+
+const HELP: &str = "/status — downloads e streams ativos\n/downloads — downloads conhecidos\n/cancel <job> — cancela um download ativo (o id pode ser abreviado)";
+
+fn status(state: &AppState) -> String {
+    let active = state.progress.all();
+    let streams = state.leases.active();
+    let mut out = format!("Downloads ativos: {}", active.len());
+    for (id, p) in &active {
+        let percent = p.percent.map(|p| format!("{p:.1}%")).unwrap_or_else(|| "?".into());
+        out.push_str(&format!(
+            "\n• {} {percent} — {} ({}/s)",
+            &id[..8],
+            format_size(p.bytes_done),
+            format_size(p.speed_bps)
+        ));
+    }
+    out.push_str(&format!("\nStreams ativos: {}", streams.len()));
+    let base = &state.config().downloads_dir;
+    for lease in &streams {
+        let path = lease.path.strip_prefix(base).unwrap_or(&lease.path);
+        out.push_str(&format!("\n• {}", path.display()));
+    }
+    out
+}
+
+async fn list_downloads(state: &AppState) -> String {
+    let entries = downloads::entries(&state.config().downloads_dir).await;
+    if entries.is_empty() {
+        return "Nenhum download.".into();
+    }
+    let mut out = format!("Downloads: {}", entries.len());
+    for entry in entries.iter().take(MAX_LISTED) {
+        let name = entry.files.first().map(|f| f.name.as_str()).unwrap_or("(sem arquivos)");
+        out.push_str(&format!("\n• {} {name} ({})", &entry.id[..8], format_size(entry.size_bytes)));
+    }
+    if entries.len() > MAX_LISTED {
+        out.push_str(&format!("\n… e mais {}", entries.len() - MAX_LISTED));
+    }
+    out
+}
+
+/// Aceita o infohash completo ou um prefixo único entre os downloads ativos.
+fn cancel(state: &AppState, job: &str) -> String {
+    let job = job.to_ascii_lowercase();
+    let matches: Vec<_> = state
+        .progress
+        .all()
+        .into_iter()
+        .map(|(id, _)| id)
+        .filter(|id| id.starts_with(&job))
+        .collect();
+    let id = match matches.as_slice() {
+        [id] => id.clone(),
+        [] => return format!("Nenhum download ativo com id {job}."),
+        _ => return format!("Id {job} ambíguo ({} downloads).", matches.len()),
+    };
+    match downloads::cancel(state, &id) {
+        Ok(()) => format!("Cancelando {id}."),
+        Err(e) => e.to_string(),
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
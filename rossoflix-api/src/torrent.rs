@@ -0,0 +1,215 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::Duration,
+};
+
+use reqwest::{Client, Url, redirect};
+use serde::{Deserialize, Serialize};
+use serde_bencode::value::Value;
+use sha1::{Digest, Sha1};
+
+use crate::{ApiError, upstream};
+
+/// Tamanho máximo aceito para um `.torrent` (upload ou URL).
+pub const MAX_TORRENT_BYTES: usize = 10 * 1024 * 1024;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(8);
+
+/// Metadados de um arquivo `.torrent` (v1).
+#[derive(Debug, Clone)]
+pub struct TorrentFile {
+    /// SHA-1 do dicionário `info` bencodado, em hex minúsculo.
+    pub info_hash: String,
+    pub name: String,
+    pub files: Vec<TorrentEntry>,
+    /// Trackers do próprio `.torrent` (`announce` e `announce-list`).
+    pub trackers: Vec<String>,
+    /// Dicionário `info` bencodado (o do infohash), lido pelo motor embutido.
+    pub info: Vec<u8>,
+    /// Conteúdo original, repassado ao aria2c.
+    pub raw: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TorrentEntry {
+    pub name: String,
+    pub size_bytes: u64,
+}
+
+#[derive(Deserialize)]
+struct RawTorrent {
+    info: Value,
+    // brutos: um tracker malformado não invalida o `.torrent`
+    announce: Option<Value>,
+    #[serde(rename = "announce-list")]
+    announce_list: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct RawInfo {
+    name: String,
+    length: Option<u64>,
+    files: Option<Vec<RawFile>>,
+}
+
+#[derive(Deserialize)]
+struct RawFile {
+    length: u64,
+    path: Vec<String>,
+}
+
+impl TorrentFile {
+    pub fn parse(raw: Vec<u8>) -> Result<Self, String> {
+        let torrent: RawTorrent =
+            serde_bencode::from_bytes(&raw).map_err(|e| format!(".torrent inválido: {e}"))?;
+        // o serializador ordena as chaves, então o reencode é o bencode canônico
+        let info_bytes = serde_bencode::to_bytes(&torrent.info).map_err(|e| e.to_string())?;
+        let info: RawInfo =
+            serde_bencode::from_bytes(&info_bytes).map_err(|e| format!("dicionário info inválido: {e}"))?;
+
+        let files = match (info.length, info.files) {
+            (Some(length), None) => vec![TorrentEntry {
+                name: info.name.clone(),
+                size_bytes: length,
+            }],
+            (None, Some(files)) if !files.is_empty() => files
+                .into_iter()
+                .map(|f| TorrentEntry {
+                    name: f.path.join("/"),
+                    size_bytes: f.length,
+                })
+                .collect(),
+            _ => return Err("dicionário info sem arquivos".into()),
+        };
+
+        let info_hash = Sha1::digest(&info_bytes)
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        let mut trackers: Vec<String> = Vec::new();
+        let tiers = match torrent.announce_list {
+            Some(Value::List(tiers)) => tiers,
+            _ => Vec::new(),
+        };
+        let listed = tiers.into_iter().flat_map(|tier| match tier {
+            Value::List(tier) => tier,
+            _ => Vec::new(),
+        });
+        for tracker in torrent.announce.into_iter().chain(listed) {
+            if let Value::Bytes(url) = tracker
+                && let Ok(url) = String::from_utf8(url)
+                && !trackers.contains(&url)
+            {
+                trackers.push(url);
+            }
+        }
+        Ok(TorrentFile {
+            info_hash,
+            name: info.name,
+            files,
+            trackers,
+            info: info_bytes,
+            raw,
+        })
+    }
+
+    /// Maior arquivo do torrent: o vídeo principal, na prática.
+    pub fn largest_file(&self) -> &TorrentEntry {
+        self.files
+            .iter()
+            .max_by_key(|f| f.size_bytes)
+            .expect("parse garante ao menos um arquivo")
+    }
+
+    /// Posição de `filename` na lista de arquivos (caminho completo ou só o nome).
+    pub fn file_index(&self, filename: &str) -> Option<usize> {
+        self.files
+            .iter()
+            .position(|f| f.name == filename || f.name.rsplit('/').next() == Some(filename))
+    }
+
+    /// Baixa e valida um `.torrent` de uma URL `http(s)` pública. A URL vem
+    /// do cliente: o host tem que resolver só para endereços públicos, a
+    /// conexão vai para o endereço conferido (nada de trocar o DNS no meio)
+    /// e redirecionamentos não são seguidos. Por isso o cliente é próprio,
+    /// sem o proxy de saída.
+    pub async fn fetch(raw: &str) -> Result<Self, ApiError> {
+        let url = Url::parse(raw).map_err(|_| ApiError::BadRequest("url do .torrent inválida".into()))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(ApiError::BadRequest("url do .torrent deve ser http(s)".into()));
+        }
+        let host = url.host_str().ok_or_else(|| ApiError::BadRequest("url do .torrent sem host".into()))?;
+        let port = url.port_or_known_default().unwrap_or(80);
+        let addrs: Vec<_> = tokio::net::lookup_host((host.trim_matches(['[', ']']), port))
+            .await
+            .map_err(|e| ApiError::Upstream(format!("não foi possível resolver {host}: {e}")))?
+            .collect();
+        let addr = match addrs.first() {
+            Some(_) if addrs.iter().any(|a| !is_public(a.ip())) => {
+                return Err(ApiError::BadRequest(format!("host {host} não é público")));
+            }
+            Some(addr) => *addr,
+            None => return Err(ApiError::Upstream(format!("{host} não resolveu"))),
+        };
+        let client = Client::builder()
+            .user_agent(upstream::USER_AGENT)
+            .redirect(redirect::Policy::none())
+            .timeout(FETCH_TIMEOUT)
+            .resolve(host, addr)
+            .build()
+            .map_err(|_| ApiError::Internal)?;
+        let resp = client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| ApiError::Upstream(e.to_string()))?;
+        if !resp.status().is_success() {
+            return Err(ApiError::Upstream(format!("status {} ao baixar o .torrent", resp.status())));
+        }
+        if resp.content_length().is_some_and(|len| len as usize > MAX_TORRENT_BYTES) {
+            return Err(ApiError::BadRequest(".torrent grande demais".into()));
+        }
+        let bytes = upstream::read_body(resp, MAX_TORRENT_BYTES).await?;
+        Self::parse(bytes).map_err(ApiError::BadRequest)
+    }
+}
+
+/// Endereço da internet pública? Fora loopback, redes privadas, CGNAT,
+/// link-local (metadados de nuvem), multicast e afins.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => is_public_v4(v4),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80)
+}
+
+/// A origem aceita por `/stream` é uma URL de `.torrent`?
+pub fn is_torrent_url(input: &str) -> bool {
+    let input = input.trim();
+    input.starts_with("http://") || input.starts_with("https://")
+}
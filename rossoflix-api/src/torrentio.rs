@@ -0,0 +1,760 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    response::IntoResponse,
+};
+use moka::future::Cache;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    ApiError, AppState,
+    cache::{CacheCategory, CacheMode, Fetched},
+    language::{self, LanguageTag, Languages},
+    release_name::{self, tokenize},
+    upstream,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct StreamFilterParams {
+    /// Tokens separados por vírgula (`h264,h265,hdr10`) ou o nome de um perfil
+    /// de dispositivo (`chromecast`).
+    capabilities: Option<String>,
+    /// Só releases com áudio nesse idioma (`pt-BR`, `en`...).
+    audio_lang: Option<String>,
+    /// Máximo de streams na resposta, depois do filtro (padrão 100).
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+fn default_limit() -> usize {
+    100
+}
+
+/// Teto de `?limit=`.
+const MAX_LIMIT: usize = 1000;
+
+pub async fn torrentio_movie(
+    State(state): State<AppState>,
+    mode: CacheMode,
+    Path(imdb_id): Path<String>,
+    Query(filter): Query<StreamFilterParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    if imdb_id.trim().is_empty() {
+        return Err(ApiError::BadRequest("imdb_id vazio".into()));
+    }
+
+    let filter = ViewFilter::resolve(&state, &filter)?;
+    let key = movie_key(&imdb_id);
+    filtered(&state, &key, &filter, mode, movie_streams(&state, &imdb_id, mode)).await
+}
+
+pub async fn torrentio_episode(
+    State(state): State<AppState>,
+    mode: CacheMode,
+    Path((imdb_id, season, episode)): Path<(String, String, String)>,
+    Query(filter): Query<StreamFilterParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    if imdb_id.trim().is_empty() {
+        return Err(ApiError::BadRequest("imdb_id vazio".into()));
+    }
+
+    let filter = ViewFilter::resolve(&state, &filter)?;
+    let key = episode_key(&imdb_id, &season, &episode);
+    filtered(&state, &key, &filter, mode, episode_streams(&state, &imdb_id, &season, &episode, mode)).await
+}
+
+/// Id do IMDb como vai na chave e na URL do torrentio (`TT0133093 ` e
+/// `tt0133093` são o mesmo título).
+fn normalize_id(imdb_id: &str) -> String {
+    imdb_id.trim().to_ascii_lowercase()
+}
+
+/// Temporada ou episódio sem zeros à esquerda (`01` e `1` são o mesmo).
+fn normalize_number(raw: &str) -> String {
+    let raw = raw.trim();
+    raw.parse::<u32>().map_or_else(|_| raw.to_string(), |n| n.to_string())
+}
+
+pub fn movie_key(imdb_id: &str) -> String {
+    format!("torrentio:movie:{}", normalize_id(imdb_id))
+}
+
+fn episode_key(imdb_id: &str, season: &str, episode: &str) -> String {
+    format!(
+        "torrentio:show:{}:S{}E{}",
+        normalize_id(imdb_id),
+        normalize_number(season),
+        normalize_number(episode)
+    )
+}
+
+pub async fn movie_streams(state: &AppState, imdb_id: &str, mode: CacheMode) -> Result<Fetched, ApiError> {
+    let key = movie_key(imdb_id);
+    let path = format!("/stream/movie/{}.json", normalize_id(imdb_id));
+    fetch_streams(state, key, "/stream/movie/:imdb_id", &path, mode).await
+}
+
+pub async fn episode_streams(
+    state: &AppState,
+    imdb_id: &str,
+    season: &str,
+    episode: &str,
+    mode: CacheMode,
+) -> Result<Fetched, ApiError> {
+    let key = episode_key(imdb_id, season, episode);
+    let path = format!(
+        "/stream/series/{}/{}-{}/.json",
+        normalize_id(imdb_id),
+        normalize_number(season),
+        normalize_number(episode)
+    );
+    fetch_streams(state, key, "/stream/series/:imdb_id/:episode", &path, mode).await
+}
+
+/// Capacidade do cache de listas já filtradas.
+const VIEW_CAPACITY: u64 = 256;
+
+/// Listas já filtradas e cortadas, por alguns segundos
+/// (`TORRENTIO_VIEW_CACHE_SECS`), para um título popular não ser refiltrado
+/// a cada pedido. O cache de verdade é o do corpo cru, por id; este só
+/// poupa o pós-processamento.
+#[derive(Clone)]
+pub struct Views {
+    enabled: bool,
+    cache: Cache<String, (serde_json::Value, Option<Duration>, Instant)>,
+}
+
+impl Views {
+    pub fn new(ttl_secs: u64) -> Self {
+        Views {
+            enabled: ttl_secs > 0,
+            cache: Cache::builder()
+                .time_to_live(Duration::from_secs(ttl_secs.max(1)))
+                .max_capacity(VIEW_CAPACITY)
+                .build(),
+        }
+    }
+}
+
+/// Aplica o filtro ao corpo cru (do cache ou do upstream), passando antes
+/// pelo cache de listas filtradas. Mudar o filtro nunca busca no upstream
+/// enquanto o corpo cru estiver no cache.
+async fn filtered(
+    state: &AppState,
+    key: &str,
+    filter: &ViewFilter,
+    mode: CacheMode,
+    fetch: impl Future<Output = Result<Fetched, ApiError>>,
+) -> Result<Fetched, ApiError> {
+    let view_key = filter.key(key);
+    if state.torrentio_views.enabled
+        && mode == CacheMode::Normal
+        && let Some((value, age, cached_at)) = state.torrentio_views.cache.get(&view_key).await
+    {
+        let age = age.unwrap_or_default() + cached_at.elapsed();
+        return Ok(Fetched { value, age: Some(age), shape: None });
+    }
+    let fetched = fetch.await?;
+    let value = post_process(fetched.value, filter);
+    if state.torrentio_views.enabled {
+        state.torrentio_views.cache.insert(view_key, (value.clone(), fetched.age, Instant::now())).await;
+    }
+    Ok(Fetched { value, ..fetched })
+}
+
+/// Resposta crua do torrentio, via cache. A chave não inclui o espelho:
+/// o que qualquer um deles respondeu serve para todos.
+async fn fetch_streams(
+    state: &AppState,
+    key: String,
+    endpoint: &'static str,
+    path: &str,
+    mode: CacheMode,
+) -> Result<Fetched, ApiError> {
+    if let Some(cached) = state.cache.get(&key, mode).await {
+        return Ok(cached);
+    }
+
+    let (mut body, base) = fetch_any(state, endpoint, path).await?;
+    check_schema(&body, &base);
+    if let Some(obj) = body.as_object_mut() {
+        obj.insert("source_mirror".into(), base.into());
+    }
+    state.cache.insert_with_ttl(key, body.clone(), state.config().cache_ttl(CacheCategory::Torrentio)).await;
+    Ok(Fetched::miss(body))
+}
+
+/// `path` no primeiro espelho que responder, na ordem de [`Mirrors`], com o
+/// espelho que respondeu. Sem cache: fica com quem chama.
+pub async fn fetch_any(
+    state: &AppState,
+    endpoint: &'static str,
+    path: &str,
+) -> Result<(serde_json::Value, String), ApiError> {
+    let mut last = None;
+    for base in state.torrentio_mirrors.order() {
+        match fetch_from(state, &base, endpoint, path).await {
+            Ok(body) => {
+                state.torrentio_mirrors.record_success(&base);
+                return Ok((body, base));
+            }
+            // o prazo é do cliente, não culpa do espelho
+            Err(ApiError::DeadlineExceeded) => return Err(ApiError::DeadlineExceeded),
+            Err(e) => {
+                warn!(mirror = base, "torrentio falhou: {e}");
+                state.torrentio_mirrors.record_failure(&base, &e.to_string());
+                last = Some(e);
+            }
+        }
+    }
+    Err(last.unwrap_or_else(|| ApiError::Upstream("nenhum espelho do torrentio configurado".into())))
+}
+
+async fn fetch_from(
+    state: &AppState,
+    base: &str,
+    endpoint: &'static str,
+    path: &str,
+) -> Result<serde_json::Value, ApiError> {
+    let resp = upstream::get(state, upstream::Service::Torrentio, endpoint, &format!("{base}{path}"))
+        .send()
+        .await
+        .map_err(upstream::send_error)?;
+
+    if !resp.status().is_success() {
+        return Err(ApiError::Upstream(format!("status {}", resp.status())));
+    }
+
+    upstream::json(state, resp).await
+}
+
+/// Falhas seguidas até um espelho ser deixado de lado.
+const MIRROR_FAILURE_THRESHOLD: u32 = 3;
+/// Por quanto tempo um espelho fora do ar vai para o fim da fila.
+const MIRROR_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Saúde dos espelhos do torrentio (`TORRENTIO_BASE_URL`). Cada requisição
+/// tenta na ordem configurada; espelhos com falhas seguidas ficam por último
+/// até o fim do cooldown (nunca são descartados: se todos caírem, todos são
+/// tentados).
+#[derive(Clone)]
+pub struct Mirrors(Arc<Mutex<Vec<Mirror>>>);
+
+struct Mirror {
+    base_url: String,
+    consecutive_failures: u32,
+    down_until: Option<Instant>,
+    served: u64,
+    failed: u64,
+    last_error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct MirrorStatus {
+    base_url: String,
+    healthy: bool,
+    consecutive_failures: u32,
+    /// Segundos até voltar ao topo da fila (só quando fora do ar).
+    retry_in_secs: Option<u64>,
+    served: u64,
+    failed: u64,
+    last_error: Option<String>,
+}
+
+impl Mirrors {
+    pub fn new(base_urls: &[String]) -> Self {
+        let mirrors = base_urls
+            .iter()
+            .map(|base_url| Mirror {
+                base_url: base_url.clone(),
+                consecutive_failures: 0,
+                down_until: None,
+                served: 0,
+                failed: 0,
+                last_error: None,
+            })
+            .collect();
+        Mirrors(Arc::new(Mutex::new(mirrors)))
+    }
+
+    /// Espelhos na ordem de tentativa: os saudáveis primeiro, na ordem da config.
+    fn order(&self) -> Vec<String> {
+        let now = Instant::now();
+        let mirrors = self.0.lock().unwrap();
+        let (up, down): (Vec<&Mirror>, Vec<&Mirror>) =
+            mirrors.iter().partition(|m| m.down_until.is_none_or(|t| t <= now));
+        up.into_iter().chain(down).map(|m| m.base_url.clone()).collect()
+    }
+
+    fn record_success(&self, base_url: &str) {
+        let mut mirrors = self.0.lock().unwrap();
+        if let Some(m) = mirrors.iter_mut().find(|m| m.base_url == base_url) {
+            m.consecutive_failures = 0;
+            m.down_until = None;
+            m.served += 1;
+        }
+    }
+
+    fn record_failure(&self, base_url: &str, error: &str) {
+        let mut mirrors = self.0.lock().unwrap();
+        if let Some(m) = mirrors.iter_mut().find(|m| m.base_url == base_url) {
+            m.consecutive_failures += 1;
+            m.failed += 1;
+            m.last_error = Some(error.to_string());
+            if m.consecutive_failures >= MIRROR_FAILURE_THRESHOLD {
+                m.down_until = Some(Instant::now() + MIRROR_COOLDOWN);
+            }
+        }
+    }
+
+    /// Espelhos fora do ar agora e o total.
+    pub fn down_count(&self) -> (usize, usize) {
+        let now = Instant::now();
+        let mirrors = self.0.lock().unwrap();
+        let down = mirrors.iter().filter(|m| m.down_until.is_some_and(|t| t > now)).count();
+        (down, mirrors.len())
+    }
+
+    pub fn status(&self) -> Vec<MirrorStatus> {
+        let now = Instant::now();
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|m| {
+                let retry_in = m.down_until.filter(|t| *t > now).map(|t| (t - now).as_secs());
+                MirrorStatus {
+                    base_url: m.base_url.clone(),
+                    healthy: retry_in.is_none(),
+                    consecutive_failures: m.consecutive_failures,
+                    retry_in_secs: retry_in,
+                    served: m.served,
+                    failed: m.failed,
+                    last_error: m.last_error.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// `GET /admin/upstream`: saúde de cada espelho do torrentio e tráfego por
+/// host upstream.
+pub async fn upstream_status(State(state): State<AppState>) -> impl IntoResponse {
+    Json(serde_json::json!({
+        "torrentio": state.torrentio_mirrors.status(),
+        "torrentio_schema_warnings": SCHEMA_WARNINGS.load(Ordering::Relaxed),
+        "bandwidth": upstream::bandwidth(),
+        "omdb_calls_today": state.usage.today(upstream::Service::Omdb),
+    }))
+}
+
+/// Filtro de `StreamFilterParams` já validado e normalizado.
+struct ViewFilter {
+    caps: Option<Capabilities>,
+    audio_lang: Option<LanguageTag>,
+    limit: usize,
+}
+
+impl ViewFilter {
+    fn resolve(state: &AppState, params: &StreamFilterParams) -> Result<Self, ApiError> {
+        if !(1..=MAX_LIMIT).contains(&params.limit) {
+            return Err(ApiError::BadRequest(format!("limit deve estar entre 1 e {MAX_LIMIT}")));
+        }
+        let caps = params
+            .capabilities
+            .as_deref()
+            .map(|raw| Capabilities::resolve(raw, &state.config().device_profiles))
+            .transpose()?;
+        let audio_lang = params.audio_lang.as_deref().map(str::parse).transpose().map_err(ApiError::BadRequest)?;
+        Ok(ViewFilter { caps, audio_lang, limit: params.limit })
+    }
+
+    /// Chave no cache de listas filtradas: um perfil e a lista de tokens
+    /// equivalente caem na mesma entrada.
+    fn key(&self, raw_key: &str) -> String {
+        let caps = self.caps.as_ref().map(|c| {
+            let mut tokens: Vec<&str> = c.0.iter().map(String::as_str).collect();
+            tokens.sort_unstable();
+            tokens.join(",")
+        });
+        let lang = self.audio_lang.as_ref().map(LanguageTag::to_string);
+        format!(
+            "{raw_key}|caps={}|lang={}|limit={}",
+            caps.unwrap_or_default(),
+            lang.unwrap_or_default(),
+            self.limit
+        )
+    }
+}
+
+/// Anota cada stream com o que o título revela (resolução, codec, HDR),
+/// remove os incompatíveis com as capacidades pedidas e corta em `limit`,
+/// mantendo a ordem do torrentio (melhor qualidade e mais seeders antes).
+/// Títulos populares passam de 400 streams: a lista é desserializada uma
+/// vez só, tirada de dentro do corpo, e filtrada no lugar. Não consulta
+/// nada além do corpo e do filtro.
+fn post_process(mut body: serde_json::Value, filter: &ViewFilter) -> serde_json::Value {
+    let mut streams = take_streams(&mut body);
+    let total_before_filter = streams.len();
+    streams.retain_mut(|stream| {
+        let info = StreamInfo::from_stream(stream);
+        let keep = filter.caps.as_ref().is_none_or(|c| c.supports(&info))
+            && filter.audio_lang.as_ref().is_none_or(|lang| info.languages.has_audio(lang));
+        stream.parsed = Some(info);
+        keep
+    });
+    let total = streams.len();
+    streams.truncate(filter.limit);
+    if let Some(obj) = body.as_object_mut() {
+        obj.insert("streams".into(), serde_json::to_value(&streams).unwrap_or_default());
+        obj.insert("total".into(), total.into());
+        obj.insert("total_before_filter".into(), total_before_filter.into());
+    }
+    body
+}
+
+/// Stream do torrentio com os campos de que dependemos tipados. Tudo é
+/// opcional para uma mudança no upstream não derrubar a lista inteira, e o
+/// que não conhecemos segue em `extras` (reenviado como veio).
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct TorrentioStream {
+    #[serde(rename = "infoHash", default, skip_serializing_if = "Option::is_none")]
+    pub info_hash: Option<String>,
+    #[serde(rename = "fileIdx", default, skip_serializing_if = "Option::is_none")]
+    pub file_idx: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Versões novas do torrentio trocaram `title` por `description`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(rename = "behaviorHints", default, skip_serializing_if = "Option::is_none")]
+    pub behavior_hints: Option<BehaviorHints>,
+    /// Nossa anotação (resolução, codec, HDR), ausente no upstream.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub parsed: Option<StreamInfo>,
+    #[serde(flatten)]
+    pub extras: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct BehaviorHints {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    #[serde(flatten)]
+    pub extras: serde_json::Map<String, serde_json::Value>,
+}
+
+impl TorrentioStream {
+    /// Título do release: `title` ou, nas versões novas, `description`.
+    pub fn release_title(&self) -> Option<&str> {
+        self.title.as_deref().or(self.description.as_deref())
+    }
+
+    /// Nome do arquivo: `behaviorHints.filename` ou a primeira linha do título.
+    pub fn filename(&self) -> Option<String> {
+        self.behavior_hints
+            .as_ref()
+            .and_then(|h| h.filename.as_deref())
+            .or_else(|| self.release_title()?.lines().next())
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    }
+}
+
+/// `streams` de uma resposta do torrentio; entradas que nem são objetos
+/// ficam de fora.
+pub fn parse_streams(body: &serde_json::Value) -> Vec<TorrentioStream> {
+    body.get("streams")
+        .and_then(|s| s.as_array())
+        .map(|streams| {
+            streams
+                .iter()
+                .filter_map(|s| serde_json::from_value(s.clone()).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Como `parse_streams`, mas movendo os streams para fora do corpo em vez
+/// de copiá-los.
+fn take_streams(body: &mut serde_json::Value) -> Vec<TorrentioStream> {
+    match body.get_mut("streams").map(serde_json::Value::take) {
+        Some(serde_json::Value::Array(streams)) => streams
+            .into_iter()
+            .filter_map(|s| serde_json::from_value(s).ok())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Respostas do torrentio fora do formato esperado (também em `/admin/upstream`).
+static SCHEMA_WARNINGS: AtomicU64 = AtomicU64::new(0);
+
+/// Confere, uma vez por resposta nova do upstream, se os campos de que
+/// dependemos continuam lá; mudança de formato vira aviso no log em vez de
+/// quebrar em silêncio o parser de títulos e os clientes.
+fn check_schema(body: &serde_json::Value, mirror: &str) {
+    let Some(raw) = body.get("streams").and_then(|s| s.as_array()) else {
+        SCHEMA_WARNINGS.fetch_add(1, Ordering::Relaxed);
+        warn!(mirror, "torrentio: resposta sem a lista `streams`");
+        return;
+    };
+    let streams = parse_streams(body);
+    let unparsed = raw.len() - streams.len();
+    let no_source = streams.iter().filter(|s| s.info_hash.is_none() && s.url.is_none()).count();
+    let no_title = streams.iter().filter(|s| s.release_title().is_none() && s.name.is_none()).count();
+    if unparsed + no_source + no_title > 0 {
+        SCHEMA_WARNINGS.fetch_add(1, Ordering::Relaxed);
+        let sample: Vec<&String> = raw.iter().find_map(|s| s.as_object()).map(|o| o.keys().collect()).unwrap_or_default();
+        warn!(
+            mirror,
+            total = raw.len(),
+            unparsed,
+            no_source,
+            no_title,
+            ?sample,
+            "torrentio: streams fora do formato esperado (mudança no upstream?)"
+        );
+    }
+}
+
+/// Características do release extraídas do `name`/`title` do torrentio.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StreamInfo {
+    pub resolution: Option<&'static str>,
+    pub codec: Option<&'static str>,
+    pub hdr: Vec<&'static str>,
+    pub bit_depth: Option<u8>,
+    #[serde(flatten)]
+    pub languages: Languages,
+}
+
+impl StreamInfo {
+    pub fn from_stream(stream: &TorrentioStream) -> Self {
+        let text = [&stream.name, &stream.title, &stream.description]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" ");
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let tokens = tokenize(text);
+        let has = |t: &str| tokens.iter().any(|x| x == t);
+        let has_seq = |a: &str, b: &str| tokens.windows(2).any(|w| w[0] == a && w[1] == b);
+
+        let mut hdr = Vec::new();
+        if has("dv") || has("dovi") || has_seq("dolby", "vision") {
+            hdr.push("dv");
+        }
+        if has("hdr10+") || has("hdr10plus") {
+            hdr.push("hdr10plus");
+        }
+        if has("hdr10") || has("hdr") {
+            hdr.push("hdr10");
+        }
+        if has("hlg") {
+            hdr.push("hlg");
+        }
+
+        let bit_depth = if has("10bit") || has_seq("10", "bit") || has("hi10p") {
+            Some(10)
+        } else if has("8bit") || has_seq("8", "bit") {
+            Some(8)
+        } else {
+            None
+        };
+
+        StreamInfo {
+            resolution: release_name::resolution(&tokens),
+            codec: release_name::codec(&tokens),
+            hdr,
+            bit_depth,
+            languages: language::detect(text, &tokens),
+        }
+    }
+}
+
+/// O que o dispositivo do cliente consegue decodificar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities(HashSet<String>);
+
+impl Capabilities {
+    /// Um nome de perfil (`chromecast`) ou uma lista de tokens.
+    pub fn resolve(
+        raw: &str,
+        profiles: &std::collections::HashMap<String, Vec<String>>,
+    ) -> Result<Self, ApiError> {
+        let raw = raw.trim().to_ascii_lowercase();
+        if let Some(caps) = profiles.get(&raw) {
+            return Ok(Capabilities(caps.iter().cloned().collect()));
+        }
+        let tokens: HashSet<String> = raw
+            .split(',')
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect();
+        if tokens.is_empty() {
+            return Err(ApiError::BadRequest("capabilities vazio".into()));
+        }
+        Ok(Capabilities(tokens))
+    }
+
+    pub fn from_tokens<'a>(tokens: impl IntoIterator<Item = &'a String>) -> Self {
+        Capabilities(tokens.into_iter().map(|t| t.to_ascii_lowercase()).collect())
+    }
+
+    fn has(&self, token: &str) -> bool {
+        self.0.contains(token)
+    }
+
+    /// Releases sem informação de codec/HDR passam: não temos como julgar.
+    pub fn supports(&self, info: &StreamInfo) -> bool {
+        if let Some(codec) = info.codec
+            && !self.has(codec)
+        {
+            return false;
+        }
+        for &fmt in &info.hdr {
+            let ok = match fmt {
+                // DV com camada base HDR10 (perfil 8) toca como HDR10
+                "dv" => self.has("dv") || (info.hdr.contains(&"hdr10") && self.has("hdr10")),
+                "hdr10plus" => self.has("hdr10plus") || self.has("hdr10"),
+                other => self.has(other),
+            };
+            if !ok {
+                return false;
+            }
+        }
+        // Hi10P em H.264 quase nunca tem decodificação por hardware
+        if info.bit_depth == Some(10) && info.codec == Some("h264") && !self.has("10bit") {
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+    };
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// Conta os bytes alocados pela thread corrente (os testes rodam em
+    /// paralelo, cada um na sua thread).
+    struct Counting;
+
+    thread_local! {
+        static ALLOCATED: Cell<u64> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATED.try_with(|n| n.set(n.get() + layout.size() as u64));
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let _ = ALLOCATED.try_with(|n| n.set(n.get() + new_size.saturating_sub(layout.size()) as u64));
+            unsafe { System.realloc(ptr, layout, new_size) }
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: Counting = Counting;
+
+    fn allocated_by<T>(f: impl FnOnce() -> T) -> (T, u64) {
+        let before = ALLOCATED.with(Cell::get);
+        let out = f();
+        (out, ALLOCATED.with(Cell::get) - before)
+    }
+
+    /// Resposta de um título popular: 500 streams, como o torrentio manda.
+    fn popular(count: usize) -> serde_json::Value {
+        let qualities = ["2160p.HDR.x265", "1080p.x265", "1080p.x264", "720p.x264"];
+        let streams: Vec<_> = (0..count)
+            .map(|i| {
+                let release = format!("Movie.2021.{}.WEB-DL.DDP5.1-GRP{i}", qualities[i % qualities.len()]);
+                json!({
+                    "name": "Torrentio\n1080p",
+                    "title": format!("{release}\n👤 {} 💾 2.1 GB ⚙️ ThePirateBay", 1000 - i),
+                    "infoHash": format!("{i:040x}"),
+                    "fileIdx": i % 3,
+                    "behaviorHints": { "bingeGroup": format!("torrentio|{i}"), "filename": format!("{release}.mkv") },
+                })
+            })
+            .collect();
+        json!({ "streams": streams, "cacheMaxAge": 3600 })
+    }
+
+    /// O caminho de antes: `parse_streams` clonando cada entrada e a lista
+    /// inteira de volta na resposta.
+    fn passthrough_and_filter(mut body: serde_json::Value) -> serde_json::Value {
+        let mut streams = parse_streams(&body);
+        streams.retain_mut(|stream| {
+            stream.parsed = Some(StreamInfo::from_stream(stream));
+            true
+        });
+        body["streams"] = serde_json::to_value(&streams).unwrap();
+        body
+    }
+
+    #[test]
+    fn popular_title_allocates_less_than_passthrough() {
+        let cached = popular(500);
+        let filter = ViewFilter { caps: None, audio_lang: None, limit: default_limit() };
+
+        // os dois recebem o corpo já fora do cache, como o handler
+        let (owned, moved) = (cached.clone(), cached.clone());
+        let (old, old_bytes) = allocated_by(|| passthrough_and_filter(owned));
+        let (new, new_bytes) = allocated_by(|| post_process(moved, &filter));
+        assert!(
+            new_bytes * 2 < old_bytes,
+            "post_process alocou {new_bytes} bytes, o caminho antigo {old_bytes}"
+        );
+
+        let streams = new["streams"].as_array().unwrap();
+        assert_eq!((streams.len(), &new["total"], &new["total_before_filter"]), (100, &json!(500), &json!(500)));
+        assert_eq!(old["streams"].as_array().unwrap()[..100], streams[..]);
+        assert_eq!(new["cacheMaxAge"], 3600);
+        assert_eq!(streams[0]["parsed"]["resolution"], old["streams"][0]["parsed"]["resolution"]);
+    }
+
+    #[test]
+    fn limit_applies_after_the_filter() {
+        let cached = popular(500);
+        let caps = Capabilities::from_tokens(&["h264".to_string()]);
+        let filter = ViewFilter { caps: Some(caps), audio_lang: None, limit: 10 };
+        let body = post_process(cached, &filter);
+        let streams = body["streams"].as_array().unwrap();
+        assert_eq!((streams.len(), &body["total"], &body["total_before_filter"]), (10, &json!(250), &json!(500)));
+        let hashes: Vec<_> = streams.iter().map(|s| s["infoHash"].as_str().unwrap()).collect();
+        // a ordem do torrentio fica: as entradas x264 são as de índice 2 e 3 (mod 4)
+        assert_eq!(hashes[..4], [format!("{:040x}", 2), format!("{:040x}", 3), format!("{:040x}", 6), format!("{:040x}", 7)]);
+    }
+}
@@ -0,0 +1,613 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+};
+use futures_util::{StreamExt, stream};
+use reqwest::{Client, Url, redirect};
+use serde::{Deserialize, Serialize};
+use serde_bencode::value::Value;
+use tokio::{net::UdpSocket, time::timeout};
+
+use crate::{ApiError, AppState, cache::{CacheMode, Fetched}, config::Config, magnet::Magnet, torrent, upstream};
+
+/// Identificador fixo do protocolo UDP de trackers (BEP 15).
+const PROTOCOL_ID: u64 = 0x0417_2710_1980;
+const ACTION_CONNECT: u32 = 0;
+const ACTION_ANNOUNCE: u32 = 1;
+const ACTION_SCRAPE: u32 = 2;
+const ACTION_ERROR: u32 = 3;
+/// `event=started` do announce UDP.
+const EVENT_STARTED: u32 = 2;
+
+/// Tempo máximo de um announce do motor embutido, HTTP ou UDP.
+const ANNOUNCE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Porta informada no announce: o motor embutido só baixa, não escuta.
+const ANNOUNCE_PORT: u16 = 6881;
+/// Peers pedidos a cada announce.
+const NUM_WANT: u32 = 50;
+/// Teto da resposta HTTP de um announce.
+const MAX_ANNOUNCE_BYTES: usize = 256 * 1024;
+
+/// Tempo máximo por tracker (connect + scrape).
+const TRACKER_TIMEOUT: Duration = Duration::from_secs(4);
+/// Trackers consultados por pedido, somando os configurados e os do magnet.
+const MAX_TRACKERS: usize = 20;
+/// Scrapes (ou announces) em andamento ao mesmo tempo.
+pub const TRACKER_CONCURRENCY: usize = 8;
+
+#[derive(Debug, Deserialize)]
+pub struct HealthParams {
+    magnet: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum TrackerResult {
+    Ok {
+        seeders: u32,
+        leechers: u32,
+        completed: u32,
+    },
+    Timeout,
+    Error {
+        message: String,
+    },
+    Unsupported,
+}
+
+impl TrackerResult {
+    /// Texto de um resultado que não é a contagem esperada.
+    fn message(self) -> String {
+        match self {
+            TrackerResult::Error { message } => message,
+            TrackerResult::Timeout => "sem resposta".into(),
+            TrackerResult::Unsupported => "tracker sem suporte".into(),
+            TrackerResult::Ok { .. } => "resposta inesperada".into(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct TrackerReport {
+    tracker: String,
+    #[serde(flatten)]
+    result: TrackerResult,
+}
+
+/// `GET /torrent/health?magnet=...` — scrape UDP nos trackers do magnet e nos
+/// configurados. Trackers que não respondem entram como `timeout`.
+pub async fn torrent_health(
+    State(state): State<AppState>,
+    mode: CacheMode,
+    Query(params): Query<HealthParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let magnet = Magnet::parse(&params.magnet)
+        .ok_or_else(|| ApiError::BadRequest("magnet inválido".into()))?;
+    if let Some(cached) = state.health.get(&magnet.info_hash, mode).await {
+        return Ok(cached);
+    }
+    let hash = info_hash_bytes(&magnet.info_hash)
+        .ok_or_else(|| ApiError::BadRequest("infohash inválido".into()))?;
+
+    let trackers = trackers(&state.config(), &magnet.trackers);
+    let mut reports: Vec<(usize, TrackerReport)> = stream::iter(trackers.into_iter().enumerate())
+        .map(|(i, (tracker, trusted))| async move {
+            let result = scrape(&tracker, &hash, trusted).await;
+            (i, TrackerReport { tracker, result })
+        })
+        .buffer_unordered(TRACKER_CONCURRENCY)
+        .collect()
+        .await;
+    reports.sort_by_key(|(i, _)| *i);
+    let reports: Vec<TrackerReport> = reports.into_iter().map(|(_, r)| r).collect();
+
+    let mut best: Option<(u32, u32)> = None;
+    for r in &reports {
+        if let TrackerResult::Ok {
+            seeders, leechers, ..
+        } = r.result
+        {
+            let (s, l) = best.unwrap_or_default();
+            best = Some((s.max(seeders), l.max(leechers)));
+        }
+    }
+    let verdict = match best {
+        None => "unknown",
+        Some((0, _)) => "dead",
+        Some((s, _)) if s < 5 => "weak",
+        Some(_) => "healthy",
+    };
+
+    let json = serde_json::json!({
+        "info_hash": magnet.info_hash,
+        "verdict": verdict,
+        "seeders": best.map(|b| b.0),
+        "leechers": best.map(|b| b.1),
+        "trackers": reports,
+    });
+    state.health.insert(magnet.info_hash, json.clone()).await;
+    Ok(Fetched::miss(json))
+}
+
+async fn scrape(tracker: &str, hash: &[u8; 20], trusted: bool) -> TrackerResult {
+    let Some(rest) = tracker.strip_prefix("udp://") else {
+        return TrackerResult::Unsupported;
+    };
+    let host = rest.split('/').next().unwrap_or(rest);
+    let roundtrip = async {
+        let addr = resolve(host, !trusted).await?;
+        udp_scrape(addr, hash).await
+    };
+    match timeout(TRACKER_TIMEOUT, roundtrip).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => TrackerResult::Error {
+            message: e.to_string(),
+        },
+        Err(_) => TrackerResult::Timeout,
+    }
+}
+
+/// Primeiro endereço de `host`; com `public_only`, os de loopback, rede
+/// local e afins ficam de fora (o mesmo critério do `.torrent` por URL).
+async fn resolve(host: &str, public_only: bool) -> std::io::Result<SocketAddr> {
+    tokio::net::lookup_host(host)
+        .await?
+        .find(|addr| !public_only || torrent::is_public(addr.ip()))
+        .ok_or_else(|| std::io::Error::other(if public_only { "host sem endereço público" } else { "host sem endereço" }))
+}
+
+async fn udp_scrape(addr: SocketAddr, hash: &[u8; 20]) -> std::io::Result<TrackerResult> {
+    let bind = if addr.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(addr).await?;
+    let mut buf = [0u8; 1024];
+
+    let tx = transaction_id();
+    socket.send(&connect_request(tx)).await?;
+    let n = socket.recv(&mut buf).await?;
+    let connection_id = match parse_connect(&buf[..n], tx) {
+        Ok(id) => id,
+        Err(result) => return Ok(result),
+    };
+
+    let tx = transaction_id();
+    socket.send(&scrape_request(connection_id, tx, hash)).await?;
+    let n = socket.recv(&mut buf).await?;
+    Ok(parse_scrape(&buf[..n], tx))
+}
+
+/// `connect` do BEP 15: id do protocolo, ação e transação.
+fn connect_request(tx: u32) -> [u8; 16] {
+    let mut req = [0u8; 16];
+    req[..8].copy_from_slice(&PROTOCOL_ID.to_be_bytes());
+    req[8..12].copy_from_slice(&ACTION_CONNECT.to_be_bytes());
+    req[12..].copy_from_slice(&tx.to_be_bytes());
+    req
+}
+
+/// `connection_id` da resposta ao `connect`, ou o resultado a reportar.
+fn parse_connect(resp: &[u8], tx: u32) -> Result<[u8; 8], TrackerResult> {
+    if let Some(err) = tracker_error(resp) {
+        return Err(err);
+    }
+    if resp.len() < 16 || be_u32(&resp[0..4]) != ACTION_CONNECT || be_u32(&resp[4..8]) != tx {
+        return Err(TrackerResult::Error {
+            message: "resposta de connect inválida".into(),
+        });
+    }
+    Ok(resp[8..16].try_into().unwrap())
+}
+
+/// `scrape` de um infohash só.
+fn scrape_request(connection_id: [u8; 8], tx: u32, hash: &[u8; 20]) -> [u8; 36] {
+    let mut req = [0u8; 36];
+    req[..8].copy_from_slice(&connection_id);
+    req[8..12].copy_from_slice(&ACTION_SCRAPE.to_be_bytes());
+    req[12..16].copy_from_slice(&tx.to_be_bytes());
+    req[16..].copy_from_slice(hash);
+    req
+}
+
+fn parse_scrape(resp: &[u8], tx: u32) -> TrackerResult {
+    if let Some(err) = tracker_error(resp) {
+        return err;
+    }
+    if resp.len() < 20 || be_u32(&resp[0..4]) != ACTION_SCRAPE || be_u32(&resp[4..8]) != tx {
+        return TrackerResult::Error {
+            message: "resposta de scrape inválida".into(),
+        };
+    }
+    TrackerResult::Ok {
+        seeders: be_u32(&resp[8..12]),
+        completed: be_u32(&resp[12..16]),
+        leechers: be_u32(&resp[16..20]),
+    }
+}
+
+/// Trackers a consultar, com `true` para os de confiança: os configurados
+/// vêm primeiro e valem como estão; os do magnet ou do `.torrent` são do
+/// cliente e só podem apontar para endereços públicos.
+pub fn trackers(config: &Config, own: &[String]) -> Vec<(String, bool)> {
+    let mut trackers: Vec<(String, bool)> = Vec::new();
+    let configured = config.bt_trackers.iter().chain(&config.bt_trackers_fallback).map(|t| (t, true));
+    for (t, trusted) in configured.chain(own.iter().map(|t| (t, false))) {
+        if !trackers.iter().any(|(known, _)| known == t) {
+            trackers.push((t.clone(), trusted));
+        }
+    }
+    trackers.truncate(MAX_TRACKERS);
+    trackers
+}
+
+/// Peers de um announce e o intervalo pedido pelo tracker até o próximo.
+#[derive(Debug, Default, PartialEq)]
+pub struct Announce {
+    pub peers: Vec<SocketAddr>,
+    pub interval: Option<Duration>,
+}
+
+/// Announce do motor embutido: HTTP (BEP 3, com a lista compacta do BEP
+/// 23) ou UDP (BEP 15). Como no `/torrent/health`, trackers que não são
+/// de confiança (os do magnet e do `.torrent`) só alcançam endereços
+/// públicos. Vai direto, sem o proxy de saída.
+pub async fn announce(tracker: &str, hash: &[u8; 20], peer_id: &[u8; 20], left: u64, trusted: bool) -> Result<Announce, String> {
+    let roundtrip = async {
+        if let Some(rest) = tracker.strip_prefix("udp://") {
+            let host = rest.split('/').next().unwrap_or(rest);
+            let addr = resolve(host, !trusted).await.map_err(|e| e.to_string())?;
+            udp_announce(addr, hash, peer_id, left).await
+        } else if tracker.starts_with("http://") || tracker.starts_with("https://") {
+            http_announce(tracker, hash, peer_id, left, trusted).await
+        } else {
+            Err("tracker sem suporte".into())
+        }
+    };
+    timeout(ANNOUNCE_TIMEOUT, roundtrip).await.unwrap_or_else(|_| Err("sem resposta".into()))
+}
+
+async fn http_announce(tracker: &str, hash: &[u8; 20], peer_id: &[u8; 20], left: u64, trusted: bool) -> Result<Announce, String> {
+    let mut url = Url::parse(tracker).map_err(|e| format!("url inválida: {e}"))?;
+    let host = url.host_str().ok_or("url sem host")?.to_string();
+    let mut client = Client::builder()
+        .user_agent(upstream::USER_AGENT)
+        .redirect(redirect::Policy::none())
+        .timeout(ANNOUNCE_TIMEOUT);
+    if !trusted {
+        // conecta no endereço conferido, sem trocar o DNS no meio
+        let port = url.port_or_known_default().unwrap_or(80);
+        let addr = resolve(&format!("{host}:{port}"), true).await.map_err(|e| e.to_string())?;
+        client = client.resolve(host.trim_matches(['[', ']']), addr);
+    }
+    let client = client.build().map_err(|e| e.to_string())?;
+
+    let query = format!(
+        "info_hash={}&peer_id={}&port={ANNOUNCE_PORT}&uploaded=0&downloaded=0&left={left}&compact=1&numwant={NUM_WANT}&event=started",
+        urlencoding::encode_binary(hash),
+        urlencoding::encode_binary(peer_id),
+    );
+    let query = match url.query() {
+        Some(existing) if !existing.is_empty() => format!("{existing}&{query}"),
+        _ => query,
+    };
+    url.set_query(Some(&query));
+
+    let resp = client.get(url).send().await.map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("status {}", resp.status()));
+    }
+    let body = upstream::read_body(resp, MAX_ANNOUNCE_BYTES).await.map_err(|e| e.to_string())?;
+    parse_http_announce(&body)
+}
+
+#[derive(Deserialize)]
+struct HttpAnnounce {
+    #[serde(rename = "failure reason")]
+    failure: Option<String>,
+    interval: Option<u64>,
+    peers: Option<Value>,
+    peers6: Option<Value>,
+}
+
+/// Resposta bencodada do announce HTTP: lista compacta (6 bytes por peer,
+/// 18 em `peers6`) ou a antiga, de dicionários com `ip` e `port`.
+fn parse_http_announce(body: &[u8]) -> Result<Announce, String> {
+    let resp: HttpAnnounce = serde_bencode::from_bytes(body).map_err(|e| format!("resposta inválida: {e}"))?;
+    if let Some(reason) = resp.failure {
+        return Err(reason);
+    }
+    let mut peers = Vec::new();
+    match resp.peers {
+        Some(Value::Bytes(compact)) => peers.extend(compact_peers(&compact, 4)),
+        Some(Value::List(list)) => {
+            for peer in list {
+                let Value::Dict(peer) = peer else { continue };
+                let ip = match peer.get(&b"ip"[..]) {
+                    Some(Value::Bytes(ip)) => std::str::from_utf8(ip).ok().and_then(|ip| ip.parse::<IpAddr>().ok()),
+                    _ => None,
+                };
+                let port = match peer.get(&b"port"[..]) {
+                    Some(Value::Int(port)) => u16::try_from(*port).ok(),
+                    _ => None,
+                };
+                if let (Some(ip), Some(port)) = (ip, port) {
+                    peers.push(SocketAddr::new(ip, port));
+                }
+            }
+        }
+        _ => {}
+    }
+    if let Some(Value::Bytes(compact)) = resp.peers6 {
+        peers.extend(compact_peers(&compact, 16));
+    }
+    Ok(Announce { peers, interval: resp.interval.map(Duration::from_secs) })
+}
+
+/// Peers em formato compacto: o IP (4 ou 16 bytes) e a porta, big-endian.
+fn compact_peers(data: &[u8], ip_len: usize) -> impl Iterator<Item = SocketAddr> + '_ {
+    data.chunks_exact(ip_len + 2).filter_map(move |peer| {
+        let ip = match ip_len {
+            4 => IpAddr::from(<[u8; 4]>::try_from(&peer[..4]).ok()?),
+            _ => IpAddr::from(<[u8; 16]>::try_from(&peer[..16]).ok()?),
+        };
+        let port = u16::from_be_bytes([peer[ip_len], peer[ip_len + 1]]);
+        (port != 0).then(|| SocketAddr::new(ip, port))
+    })
+}
+
+async fn udp_announce(addr: SocketAddr, hash: &[u8; 20], peer_id: &[u8; 20], left: u64) -> Result<Announce, String> {
+    let roundtrip = async {
+        let socket = UdpSocket::bind(if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }).await?;
+        socket.connect(addr).await?;
+        let mut buf = [0u8; 2048];
+
+        let tx = transaction_id();
+        socket.send(&connect_request(tx)).await?;
+        let n = socket.recv(&mut buf).await?;
+        let connection_id = match parse_connect(&buf[..n], tx) {
+            Ok(id) => id,
+            Err(result) => return Ok(Err(result.message())),
+        };
+
+        let tx = transaction_id();
+        socket.send(&announce_request(connection_id, tx, hash, peer_id, left)).await?;
+        let n = socket.recv(&mut buf).await?;
+        Ok::<_, std::io::Error>(parse_announce(&buf[..n], tx, addr.is_ipv6()))
+    };
+    roundtrip.await.map_err(|e| e.to_string())?
+}
+
+/// `announce` do BEP 15, com `event=started` e a porta fixa.
+fn announce_request(connection_id: [u8; 8], tx: u32, hash: &[u8; 20], peer_id: &[u8; 20], left: u64) -> [u8; 98] {
+    let mut req = [0u8; 98];
+    req[..8].copy_from_slice(&connection_id);
+    req[8..12].copy_from_slice(&ACTION_ANNOUNCE.to_be_bytes());
+    req[12..16].copy_from_slice(&tx.to_be_bytes());
+    req[16..36].copy_from_slice(hash);
+    req[36..56].copy_from_slice(peer_id);
+    // downloaded (56..64) e uploaded (72..80) ficam zerados
+    req[64..72].copy_from_slice(&left.to_be_bytes());
+    req[80..84].copy_from_slice(&EVENT_STARTED.to_be_bytes());
+    // ip (84..88) zero: o do remetente
+    req[88..92].copy_from_slice(&tx.to_be_bytes());
+    req[92..96].copy_from_slice(&NUM_WANT.to_be_bytes());
+    req[96..].copy_from_slice(&ANNOUNCE_PORT.to_be_bytes());
+    req
+}
+
+/// Resposta ao `announce`: intervalo, contagens e os peers compactos (de
+/// 18 bytes quando o tracker é IPv6).
+fn parse_announce(resp: &[u8], tx: u32, ipv6: bool) -> Result<Announce, String> {
+    if let Some(err) = tracker_error(resp) {
+        return Err(err.message());
+    }
+    if resp.len() < 20 || be_u32(&resp[0..4]) != ACTION_ANNOUNCE || be_u32(&resp[4..8]) != tx {
+        return Err("resposta de announce inválida".into());
+    }
+    Ok(Announce {
+        peers: compact_peers(&resp[20..], if ipv6 { 16 } else { 4 }).collect(),
+        interval: Some(Duration::from_secs(be_u32(&resp[8..12]) as u64)),
+    })
+}
+
+/// Só o `connect` do BEP 15: qualquer resposta prova que UDP de saída
+/// funciona até o tracker (usado pelo `doctor`).
+pub async fn probe(tracker: &str) -> Result<(), String> {
+    let host = tracker
+        .strip_prefix("udp://")
+        .map(|rest| rest.split('/').next().unwrap_or(rest))
+        .ok_or_else(|| format!("{tracker} não é UDP"))?;
+    let roundtrip = async {
+        let addr = resolve(host, false).await?;
+        let socket = UdpSocket::bind(if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }).await?;
+        socket.connect(addr).await?;
+        socket.send(&connect_request(transaction_id())).await?;
+        let mut buf = [0u8; 64];
+        socket.recv(&mut buf).await.map(|_| ())
+    };
+    match timeout(TRACKER_TIMEOUT, roundtrip).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err("sem resposta".into()),
+    }
+}
+
+fn tracker_error(resp: &[u8]) -> Option<TrackerResult> {
+    (resp.len() >= 8 && be_u32(&resp[0..4]) == ACTION_ERROR).then(|| TrackerResult::Error {
+        message: String::from_utf8_lossy(&resp[8..]).into_owned(),
+    })
+}
+
+fn be_u32(b: &[u8]) -> u32 {
+    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn transaction_id() -> u32 {
+    RandomState::new().build_hasher().finish() as u32
+}
+
+/// Infohash em bytes, a partir do hex (40) ou base32 (32).
+pub fn info_hash_bytes(hash: &str) -> Option<[u8; 20]> {
+    let mut out = [0u8; 20];
+    match hash.len() {
+        40 => {
+            for (i, chunk) in hash.as_bytes().chunks(2).enumerate() {
+                out[i] = u8::from_str_radix(std::str::from_utf8(chunk).ok()?, 16).ok()?;
+            }
+        }
+        32 => {
+            let mut bits: u64 = 0;
+            let mut nbits = 0;
+            let mut i = 0;
+            for c in hash.bytes() {
+                let v = match c.to_ascii_uppercase() {
+                    c @ b'A'..=b'Z' => c - b'A',
+                    c @ b'2'..=b'7' => c - b'2' + 26,
+                    _ => return None,
+                };
+                bits = (bits << 5) | v as u64;
+                nbits += 5;
+                if nbits >= 8 {
+                    nbits -= 8;
+                    out[i] = (bits >> nbits) as u8;
+                    i += 1;
+                }
+            }
+        }
+        _ => return None,
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: [u8; 20] = *b"\x01\x23\x45\x67\x89\xab\xcd\xef\x01\x23\x45\x67\x89\xab\xcd\xef\x01\x23\x45\x67";
+
+    fn words(parts: &[&[u8]]) -> Vec<u8> {
+        parts.concat()
+    }
+
+    #[test]
+    fn connect_round_trip() {
+        let req = connect_request(0xdead_beef);
+        assert_eq!(req, *b"\x00\x00\x04\x17\x27\x10\x19\x80\x00\x00\x00\x00\xde\xad\xbe\xef");
+
+        let resp = words(&[&0u32.to_be_bytes(), &0xdead_beefu32.to_be_bytes(), b"conn-id!"]);
+        assert_eq!(parse_connect(&resp, 0xdead_beef).unwrap(), *b"conn-id!");
+
+        // transação de outro pedido, ação errada, resposta curta
+        for resp in [
+            words(&[&0u32.to_be_bytes(), &1u32.to_be_bytes(), b"conn-id!"]),
+            words(&[&2u32.to_be_bytes(), &0xdead_beefu32.to_be_bytes(), b"conn-id!"]),
+            words(&[&0u32.to_be_bytes(), &0xdead_beefu32.to_be_bytes(), b"conn"]),
+        ] {
+            assert!(matches!(parse_connect(&resp, 0xdead_beef), Err(TrackerResult::Error { .. })), "{resp:?}");
+        }
+    }
+
+    #[test]
+    fn scrape_round_trip() {
+        let req = scrape_request(*b"conn-id!", 7, &HASH);
+        assert_eq!(req[..8], *b"conn-id!");
+        assert_eq!(req[8..16], *b"\x00\x00\x00\x02\x00\x00\x00\x07");
+        assert_eq!(req[16..], HASH);
+
+        let counts = |seeders: u32, completed: u32, leechers: u32| {
+            words(&[&2u32.to_be_bytes(), &7u32.to_be_bytes(), &seeders.to_be_bytes(), &completed.to_be_bytes(), &leechers.to_be_bytes()])
+        };
+        assert!(matches!(
+            parse_scrape(&counts(12, 340, 5), 7),
+            TrackerResult::Ok { seeders: 12, completed: 340, leechers: 5 }
+        ));
+        assert!(matches!(parse_scrape(&counts(12, 340, 5), 8), TrackerResult::Error { .. }));
+        assert!(matches!(parse_scrape(&counts(12, 340, 5)[..16], 7), TrackerResult::Error { .. }));
+    }
+
+    #[test]
+    fn tracker_errors_carry_the_message() {
+        let resp = words(&[&ACTION_ERROR.to_be_bytes(), &7u32.to_be_bytes(), b"torrent desconhecido"]);
+        for result in [parse_scrape(&resp, 7), parse_connect(&resp, 7).unwrap_err()] {
+            match result {
+                TrackerResult::Error { message } => assert_eq!(message, "torrent desconhecido"),
+                other => panic!("{other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn announce_round_trip() {
+        let peer_id = *b"-RF0100-abcdefghijkl";
+        let req = announce_request(*b"conn-id!", 9, &HASH, &peer_id, 1234);
+        assert_eq!(req[..8], *b"conn-id!");
+        assert_eq!(req[8..16], *b"\x00\x00\x00\x01\x00\x00\x00\x09");
+        assert_eq!(req[16..36], HASH);
+        assert_eq!(req[36..56], peer_id);
+        assert_eq!(req[64..72], 1234u64.to_be_bytes());
+        assert_eq!(req[80..84], EVENT_STARTED.to_be_bytes());
+        assert_eq!(req[96..], ANNOUNCE_PORT.to_be_bytes());
+
+        let header = words(&[&ACTION_ANNOUNCE.to_be_bytes(), &9u32.to_be_bytes(), &1800u32.to_be_bytes(), &[0; 8]]);
+        // a porta zero não é peer
+        let resp = words(&[&header, &[10, 0, 0, 1, 0x1a, 0xe1], &[10, 0, 0, 2, 0, 0]]);
+        assert_eq!(
+            parse_announce(&resp, 9, false).unwrap(),
+            Announce { peers: vec!["10.0.0.1:6881".parse().unwrap()], interval: Some(Duration::from_secs(1800)) }
+        );
+        let v6 = words(&[&header, &[0x20, 0x01, 0x0d, 0xb8], &[0; 11], &[1, 0x1a, 0xe1]]);
+        assert_eq!(parse_announce(&v6, 9, true).unwrap().peers, vec!["[2001:db8::1]:6881".parse().unwrap()]);
+        assert!(parse_announce(&resp, 8, false).is_err());
+    }
+
+    #[test]
+    fn http_announce_forms() {
+        let compact = b"d8:intervali900e5:peers12:\x0a\x00\x00\x01\x1a\xe1\x0a\x00\x00\x02\x1a\xe2e";
+        assert_eq!(
+            parse_http_announce(compact).unwrap(),
+            Announce {
+                peers: vec!["10.0.0.1:6881".parse().unwrap(), "10.0.0.2:6882".parse().unwrap()],
+                interval: Some(Duration::from_secs(900)),
+            }
+        );
+
+        let dicts = b"d5:peersld2:ip8:10.0.0.34:porti6883eed2:ip3:???4:porti1eeee";
+        assert_eq!(parse_http_announce(dicts).unwrap().peers, vec!["10.0.0.3:6883".parse().unwrap()]);
+
+        let failure = b"d14:failure reason20:torrent desconhecidoe";
+        assert_eq!(parse_http_announce(failure).unwrap_err(), "torrent desconhecido");
+        assert!(parse_http_announce(b"<html>").is_err());
+    }
+
+    #[test]
+    fn info_hash_in_hex_and_base32() {
+        let hex = "0123456789abcdef0123456789abcdef01234567";
+        assert_eq!(info_hash_bytes(hex), Some(HASH));
+        assert_eq!(info_hash_bytes(&hex.to_uppercase()), Some(HASH));
+        assert_eq!(info_hash_bytes("AERUKZ4JVPG66AJDIVTYTK6N54ASGRLH"), Some(HASH));
+        assert_eq!(info_hash_bytes("0123"), None);
+        assert_eq!(info_hash_bytes(&hex.replace("a", "g")), None);
+    }
+
+    #[tokio::test]
+    async fn magnet_trackers_only_reach_public_addresses() {
+        let err = resolve("127.0.0.1:6969", true).await.unwrap_err();
+        assert_eq!(err.to_string(), "host sem endereço público");
+        assert!(resolve("10.0.0.1:6969", true).await.is_err());
+        assert!(resolve("[::1]:6969", true).await.is_err());
+        assert!(resolve("169.254.169.254:80", true).await.is_err());
+        assert_eq!(resolve("1.1.1.1:6969", true).await.unwrap(), "1.1.1.1:6969".parse().unwrap());
+        // os configurados pelo operador podem estar na rede local
+        assert_eq!(resolve("127.0.0.1:6969", false).await.unwrap(), "127.0.0.1:6969".parse().unwrap());
+    }
+}
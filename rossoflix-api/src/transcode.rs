@@ -0,0 +1,373 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    process::Stdio,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use axum::{
+    body::Body,
+    extract::{Path as UrlPath, Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use tokio::{
+    fs,
+    io::AsyncReadExt,
+    process::{Child, Command},
+    sync::{OwnedSemaphorePermit, Semaphore},
+};
+use tokio_util::io::ReaderStream;
+use tracing::{info, warn};
+
+use crate::{
+    ApiError, AppState,
+    downloads::{self, parse_job_id},
+    leases::ReadLease,
+    media::{self, MediaInfo},
+    scratch::SessionGuard,
+};
+
+/// Playlist de mídia que o ffmpeg grava na pasta da sessão.
+const PLAYLIST: &str = "index.m3u8";
+/// Duração alvo de cada segmento.
+const SEGMENT_SECS: u32 = 6;
+/// Sem nenhum pedido de playlist ou segmento há esse tempo, o ffmpeg é
+/// parado (o player foi fechado).
+const IDLE: Duration = Duration::from_secs(120);
+/// Quanto um pedido espera pela playlist ou por um segmento que o ffmpeg
+/// ainda não gravou.
+const WAIT: Duration = Duration::from_secs(30);
+/// Dígitos hexadecimais da chave da variante.
+const KEY_LEN: usize = 16;
+/// Banda anunciada quando o vídeo é reencodado ou o ffprobe não diz.
+const DEFAULT_BANDWIDTH: u64 = 6_000_000;
+
+enum Status {
+    Running,
+    Failed(String),
+    Absent,
+}
+
+struct Entry {
+    touched: Instant,
+    failure: Option<String>,
+}
+
+/// ffmpegs de `/stream/hls`, um por sessão do scratch
+/// (`hls-<infohash>-<chave>`), com no máximo `HLS_MAX_TRANSCODES` ao mesmo
+/// tempo. Uma transcodificação que falhou fica registrada até o próximo
+/// `master.m3u8`, que tenta de novo.
+#[derive(Clone)]
+pub struct Transcodes {
+    entries: Arc<Mutex<HashMap<String, Entry>>>,
+    slots: Arc<Semaphore>,
+}
+
+impl Transcodes {
+    pub fn new(max: usize) -> Self {
+        Transcodes { entries: Default::default(), slots: Arc::new(Semaphore::new(max)) }
+    }
+
+    /// Marca o uso da sessão e diz como está o ffmpeg dela.
+    fn touch(&self, id: &str) -> Status {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get_mut(id) {
+            Some(Entry { failure: Some(e), .. }) => Status::Failed(e.clone()),
+            Some(entry) => {
+                entry.touched = Instant::now();
+                Status::Running
+            }
+            None => Status::Absent,
+        }
+    }
+
+    /// Garante um ffmpeg para a sessão, a menos que ele já rode ou a
+    /// playlist já esteja completa.
+    async fn ensure(&self, state: &AppState, session: SessionGuard, id: &str, source: &Path, plan: &Plan) -> Result<(), ApiError> {
+        if matches!(self.touch(id), Status::Running) || is_complete(&session.dir().join(PLAYLIST)).await {
+            return Ok(());
+        }
+        let permit = self
+            .slots
+            .clone()
+            .try_acquire_owned()
+            .map_err(|_| ApiError::Unavailable("transcodificações demais em andamento".into()))?;
+        let lease = state
+            .leases
+            .read(source)
+            .map_err(|busy| ApiError::Conflict(format!("{} está sendo removido", busy.0.display())))?;
+        {
+            let mut entries = self.entries.lock().unwrap();
+            if entries.get(id).is_some_and(|e| e.failure.is_none()) {
+                // outro pedido começou entre a checagem e aqui
+                return Ok(());
+            }
+            entries.insert(id.to_string(), Entry { touched: Instant::now(), failure: None });
+        }
+        let child = match spawn_ffmpeg(source, session.dir(), plan) {
+            Ok(child) => child,
+            Err(e) => {
+                self.entries.lock().unwrap().remove(id);
+                return Err(ApiError::Unavailable(format!("ffmpeg indisponível: {e}")));
+            }
+        };
+        info!(session = id, source = %source.display(), copy_video = plan.copy_video, "transcodificando para HLS");
+        let running = Running { child, _session: session, _lease: lease, _permit: permit };
+        tokio::spawn(self.clone().supervise(id.to_string(), running));
+        Ok(())
+    }
+
+    /// Espera o ffmpeg terminar, ou o mata quando a sessão fica ociosa.
+    async fn supervise(self, id: String, mut running: Running) {
+        let stderr = running.child.stderr.take().map(|mut pipe| {
+            tokio::spawn(async move {
+                let mut out = String::new();
+                let _ = pipe.read_to_string(&mut out).await;
+                out
+            })
+        });
+        let mut tick = tokio::time::interval(Duration::from_secs(5));
+        let status = loop {
+            tokio::select! {
+                status = running.child.wait() => break Some(status),
+                _ = tick.tick() => {
+                    let idle = self.entries.lock().unwrap().get(&id).is_none_or(|e| e.touched.elapsed() >= IDLE);
+                    if idle {
+                        let _ = running.child.kill().await;
+                        break None;
+                    }
+                }
+            }
+        };
+        let stderr = match stderr {
+            Some(task) => task.await.unwrap_or_default(),
+            None => String::new(),
+        };
+        let mut entries = self.entries.lock().unwrap();
+        match status {
+            Some(Ok(exit)) if exit.success() => {
+                info!(session = id, "transcodificação HLS concluída");
+                entries.remove(&id);
+            }
+            None => {
+                info!(session = id, "transcodificação HLS ociosa; ffmpeg parado");
+                entries.remove(&id);
+            }
+            Some(exit) => {
+                let tail = stderr.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or_default().trim().to_string();
+                warn!(session = id, "ffmpeg do HLS terminou com erro ({exit:?}): {tail}");
+                if let Some(entry) = entries.get_mut(&id) {
+                    entry.failure = Some(if tail.is_empty() { format!("{exit:?}") } else { tail });
+                }
+            }
+        }
+    }
+}
+
+/// ffmpeg em andamento e o que ele segura: a sessão do scratch, o arquivo
+/// de origem e a vaga.
+struct Running {
+    child: Child,
+    _session: SessionGuard,
+    _lease: ReadLease,
+    _permit: OwnedSemaphorePermit,
+}
+
+/// O que dá para copiar sem reencodar: H.264 e AAC tocam em qualquer
+/// navegador com HLS.
+struct Plan {
+    copy_video: bool,
+    copy_audio: bool,
+    bandwidth: u64,
+}
+
+impl Plan {
+    fn for_media(info: Option<&MediaInfo>) -> Self {
+        let copy_video = info.is_some_and(|i| i.video_codec.as_deref() == Some("h264"));
+        let copy_audio = info.is_some_and(|i| i.audio_codecs.first().is_some_and(|c| c == "aac"));
+        let bandwidth = info
+            .and_then(|i| i.bitrate_kbps)
+            .filter(|_| copy_video)
+            .map(|kbps| kbps * 1000)
+            .unwrap_or(DEFAULT_BANDWIDTH);
+        Plan { copy_video, copy_audio, bandwidth }
+    }
+}
+
+fn spawn_ffmpeg(source: &Path, dir: &Path, plan: &Plan) -> std::io::Result<Child> {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args(["-v", "error", "-nostdin", "-i"])
+        .arg(source)
+        .args(["-map", "0:v:0", "-map", "0:a:0?", "-sn"]);
+    if plan.copy_video {
+        cmd.args(["-c:v", "copy"]);
+    } else {
+        cmd.args(["-c:v", "libx264", "-preset", "veryfast", "-crf", "21", "-pix_fmt", "yuv420p"]);
+    }
+    if plan.copy_audio {
+        cmd.args(["-c:a", "copy"]);
+    } else {
+        cmd.args(["-c:a", "aac", "-ac", "2", "-b:a", "160k"]);
+    }
+    cmd.args(["-f", "hls", "-hls_time", &SEGMENT_SECS.to_string(), "-hls_playlist_type", "event"])
+        .args(["-hls_flags", "temp_file", "-hls_segment_filename"])
+        .arg(dir.join("seg%05d.ts"))
+        .arg(dir.join(PLAYLIST))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MasterParams {
+    /// Arquivo dentro do download; sem ele, o maior já concluído.
+    filename: Option<String>,
+}
+
+/// `GET /stream/hls/:job_id/master.m3u8?filename=` — HLS de um arquivo já
+/// baixado do download `job_id` (o infohash), para os navegadores que não
+/// tocam MKV ou HEVC. O ffmpeg copia o que já é H.264/AAC e reencoda o
+/// resto, gravando os segmentos numa sessão do scratch; a playlist
+/// (`<chave>/index.m3u8`) cresce enquanto ele trabalha.
+pub async fn master_playlist(
+    State(state): State<AppState>,
+    UrlPath(job_id): UrlPath<String>,
+    Query(params): Query<MasterParams>,
+) -> Result<Response, ApiError> {
+    let job_id = parse_job_id(&job_id)?;
+    let source = downloads::job_file(&state, &job_id, params.filename.as_deref()).await?;
+    let key = variant_key(&source).await?;
+    let id = session_id(&job_id, &key);
+    let session = state
+        .scratch
+        .session(&id)
+        .await
+        .map_err(|e| ApiError::Storage(format!("falha ao preparar o scratch: {e}")))?;
+
+    let info = match media::probe(&state, &source).await {
+        Ok(info) => Some(info),
+        Err(e) => {
+            warn!(source = %source.display(), "ffprobe sem resposta para o HLS ({e}); reencodando tudo");
+            None
+        }
+    };
+    let plan = Plan::for_media(info.as_ref());
+    state.transcodes.ensure(&state, session, &id, &source, &plan).await?;
+
+    let master = format!("#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-STREAM-INF:BANDWIDTH={}\n{key}/{PLAYLIST}\n", plan.bandwidth);
+    Ok(playlist_response(master))
+}
+
+/// `GET /stream/hls/:job_id/:key/:name` — a playlist de mídia
+/// (`index.m3u8`) ou um segmento (`seg00000.ts`) da transcodificação.
+/// Espera até `WAIT` pelo que o ffmpeg ainda não gravou.
+pub async fn variant_file(
+    State(state): State<AppState>,
+    UrlPath((job_id, key, name)): UrlPath<(String, String, String)>,
+) -> Result<Response, ApiError> {
+    let job_id = parse_job_id(&job_id)?;
+    if key.len() != KEY_LEN || !key.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ApiError::BadRequest("chave de variante inválida".into()));
+    }
+    if name != PLAYLIST && !is_segment_name(&name) {
+        return Err(ApiError::NotFound(format!("{name} não existe nesta transcodificação")));
+    }
+    let id = session_id(&job_id, &key);
+    let known = !matches!(state.transcodes.touch(&id), Status::Absent);
+    if !known && !fs::try_exists(state.config().scratch_dir.join(&id)).await.unwrap_or(false) {
+        return Err(ApiError::NotFound("transcodificação desconhecida; abra o master.m3u8".into()));
+    }
+    let session = state
+        .scratch
+        .session(&id)
+        .await
+        .map_err(|e| ApiError::Storage(format!("falha ao abrir o scratch: {e}")))?;
+    let path = session.dir().join(&name);
+
+    let deadline = Instant::now() + WAIT;
+    loop {
+        if fs::try_exists(&path).await.unwrap_or(false) {
+            break;
+        }
+        match state.transcodes.touch(&id) {
+            Status::Failed(e) => return Err(ApiError::Unavailable(format!("a transcodificação falhou: {e}"))),
+            // o ffmpeg pode ter acabado logo depois da checagem
+            Status::Absent if !fs::try_exists(&path).await.unwrap_or(false) => {
+                return Err(ApiError::NotFound(format!("{name} não existe nesta transcodificação")));
+            }
+            Status::Absent => break,
+            Status::Running if Instant::now() >= deadline => return Err(ApiError::Queued { retry_after_secs: 2 }),
+            Status::Running => tokio::time::sleep(Duration::from_millis(200)).await,
+        }
+    }
+
+    if name == PLAYLIST {
+        let playlist = fs::read_to_string(&path)
+            .await
+            .map_err(|e| ApiError::Storage(format!("falha ao ler a playlist: {e}")))?;
+        return Ok(playlist_response(playlist));
+    }
+    let file = fs::File::open(&path)
+        .await
+        .map_err(|e| ApiError::Storage(format!("falha ao ler {name}: {e}")))?;
+    let len = file.metadata().await.map(|m| m.len()).ok();
+    // a sessão fica em uso até o envio terminar
+    let body = ReaderStream::new(file).map(move |chunk| {
+        let _ = &session;
+        chunk
+    });
+    let mut resp = (StatusCode::OK, [(header::CONTENT_TYPE, "video/mp2t")], Body::from_stream(body)).into_response();
+    if let Some(len) = len {
+        resp.headers_mut().insert(header::CONTENT_LENGTH, len.into());
+    }
+    Ok(resp)
+}
+
+fn playlist_response(body: String) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, "application/vnd.apple.mpegurl"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+fn session_id(job_id: &str, key: &str) -> String {
+    format!("hls-{job_id}-{key}")
+}
+
+fn is_segment_name(name: &str) -> bool {
+    name.strip_prefix("seg")
+        .and_then(|n| n.strip_suffix(".ts"))
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Playlist que o ffmpeg já fechou (`#EXT-X-ENDLIST`).
+async fn is_complete(playlist: &Path) -> bool {
+    fs::read_to_string(playlist).await.is_ok_and(|p| p.contains("#EXT-X-ENDLIST"))
+}
+
+/// Chave da variante: arquivo (caminho, tamanho, mtime). Um arquivo trocado
+/// ganha outra sessão em vez de misturar segmentos.
+async fn variant_key(source: &Path) -> Result<String, ApiError> {
+    let meta = fs::metadata(source)
+        .await
+        .map_err(|e| ApiError::Storage(format!("falha ao ler {}: {e}", source.display())))?;
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let digest = Sha1::digest(format!("{}\n{}\n{mtime}", source.display(), meta.len()));
+    Ok(digest.iter().take(KEY_LEN / 2).map(|b| format!("{b:02x}")).collect())
+}
@@ -0,0 +1,219 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{Json, extract::State, response::IntoResponse};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::{info, warn};
+
+use crate::{ApiError, AppState, list_files};
+
+/// Lixeira em `<downloads>/.trash/<timestamp>/`, espelhando os caminhos
+/// relativos ao diretório de downloads.
+const TRASH_DIR: &str = ".trash";
+
+/// Intervalo entre as passadas de limpeza definitiva.
+const PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
+pub fn trash_dir(downloads_dir: &Path) -> PathBuf {
+    downloads_dir.join(TRASH_DIR)
+}
+
+/// Move `paths` (arquivos ou diretórios sob `downloads_dir`) para uma nova
+/// entrada da lixeira e devolve o id dela. Se a lixeira estiver em outro
+/// sistema de arquivos, apaga de verdade (com aviso) e devolve `None`.
+pub async fn discard(downloads_dir: &Path, paths: &[PathBuf]) -> io::Result<Option<String>> {
+    if paths.is_empty() {
+        return Ok(None);
+    }
+    let (id, entry) = new_entry(downloads_dir).await?;
+    let mut moved_any = false;
+
+    for path in paths {
+        let rel = path.strip_prefix(downloads_dir).unwrap_or(path);
+        let target = entry.join(rel);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        match fs::rename(path, &target).await {
+            Ok(()) => moved_any = true,
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                warn!(path = %path.display(), "lixeira em outro sistema de arquivos, apagando definitivamente");
+                remove_any(path).await?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    if !moved_any {
+        let _ = fs::remove_dir_all(&entry).await;
+        return Ok(None);
+    }
+    info!(entry = id, count = paths.len(), "movido para a lixeira");
+    Ok(Some(id))
+}
+
+async fn new_entry(downloads_dir: &Path) -> io::Result<(String, PathBuf)> {
+    let root = trash_dir(downloads_dir);
+    fs::create_dir_all(&root).await?;
+    let now = unix_now();
+    for n in 0.. {
+        let id = if n == 0 { now.to_string() } else { format!("{now}-{n}") };
+        let path = root.join(&id);
+        match fs::create_dir(&path).await {
+            Ok(()) => return Ok((id, path)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+async fn remove_any(path: &Path) -> io::Result<()> {
+    if fs::metadata(path).await?.is_dir() {
+        fs::remove_dir_all(path).await
+    } else {
+        fs::remove_file(path).await
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrashEntry {
+    pub id: String,
+    /// Unix timestamp (s) da remoção.
+    pub deleted_at: u64,
+    pub files: Vec<TrashFile>,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrashFile {
+    pub name: String,
+    pub size_bytes: u64,
+}
+
+pub async fn entries(downloads_dir: &Path) -> Vec<TrashEntry> {
+    let root = trash_dir(downloads_dir);
+    let mut out = Vec::new();
+    let Ok(mut dirs) = fs::read_dir(&root).await else {
+        return out;
+    };
+    while let Ok(Some(dir)) = dirs.next_entry().await {
+        let id = dir.file_name().to_string_lossy().into_owned();
+        let Some(deleted_at) = id.split('-').next().and_then(|t| t.parse().ok()) else {
+            continue;
+        };
+        let path = dir.path();
+        let files: Vec<_> = list_files(&path)
+            .await
+            .into_iter()
+            .map(|(p, size)| TrashFile {
+                name: p.strip_prefix(&path).unwrap_or(&p).to_string_lossy().into_owned(),
+                size_bytes: size,
+            })
+            .collect();
+        out.push(TrashEntry {
+            id,
+            deleted_at,
+            size_bytes: files.iter().map(|f| f.size_bytes).sum(),
+            files,
+        });
+    }
+    out.sort_by_key(|e| e.deleted_at);
+    out
+}
+
+/// Apaga definitivamente as entradas mais velhas que `retention`.
+pub async fn purge_expired(downloads_dir: &Path, retention: Duration) {
+    let cutoff = unix_now().saturating_sub(retention.as_secs());
+    for entry in entries(downloads_dir).await {
+        if entry.deleted_at > cutoff {
+            continue;
+        }
+        let path = trash_dir(downloads_dir).join(&entry.id);
+        match fs::remove_dir_all(&path).await {
+            Ok(()) => info!(entry = entry.id, size_bytes = entry.size_bytes, "lixeira: entrada expirada removida"),
+            Err(e) => warn!(path = %path.display(), "falha ao limpar a lixeira: {e}"),
+        }
+    }
+}
+
+/// Limpeza periódica da lixeira, em segundo plano.
+pub fn spawn_purger(downloads_dir: PathBuf, retention: Duration) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            tick.tick().await;
+            purge_expired(&downloads_dir, retention).await;
+        }
+    });
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// `GET /admin/trash` — entradas da lixeira.
+pub async fn list_trash(State(state): State<AppState>) -> impl IntoResponse {
+    let entries = entries(&state.config().downloads_dir).await;
+    let total: u64 = entries.iter().map(|e| e.size_bytes).sum();
+    Json(serde_json::json!({
+        "retention_hours": state.config().trash_retention_hours,
+        "size_bytes": total,
+        "entries": entries,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RestoreRequest {
+    id: String,
+}
+
+/// `POST /admin/trash/restore` — devolve uma entrada (`{"id": "..."}`) aos
+/// caminhos originais. Nada é sobrescrito: se algum destino já existe, 409.
+pub async fn restore_trash(
+    State(state): State<AppState>,
+    Json(req): Json<RestoreRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let base = &state.config().downloads_dir;
+    if req.id.is_empty() || req.id.contains(['/', '\\']) || req.id.starts_with('.') {
+        return Err(ApiError::BadRequest("id inválido".into()));
+    }
+    let entry = trash_dir(base).join(&req.id);
+    let files = list_files(&entry).await;
+    if files.is_empty() {
+        return Err(ApiError::NotFound(format!("entrada {} não está na lixeira", req.id)));
+    }
+
+    let moves: Vec<_> = files
+        .iter()
+        .map(|(p, _)| (p.clone(), base.join(p.strip_prefix(&entry).unwrap_or(p))))
+        .collect();
+    if let Some((_, target)) = moves.iter().find(|(_, target)| target.exists()) {
+        return Err(ApiError::Conflict(format!("{} já existe", target.display())));
+    }
+
+    let mut restored = Vec::with_capacity(moves.len());
+    for (from, to) in moves {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|e| ApiError::Storage(e.to_string()))?;
+        }
+        fs::rename(&from, &to)
+            .await
+            .map_err(|e| ApiError::Storage(format!("falha ao restaurar {}: {e}", from.display())))?;
+        restored.push(to.strip_prefix(base).unwrap_or(&to).to_string_lossy().into_owned());
+    }
+    let _ = fs::remove_dir_all(&entry).await;
+    info!(entry = req.id, count = restored.len(), "lixeira: entrada restaurada");
+
+    Ok(Json(serde_json::json!({ "id": req.id, "restored": restored })))
+}
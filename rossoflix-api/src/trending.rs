@@ -0,0 +1,343 @@
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+use futures_util::{FutureExt, StreamExt, future::BoxFuture};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::{
+    ApiError, AppState, attribution,
+    cache::{self, CacheCategory, CacheMode},
+    fetch_detail, middleware, omdb, posters, unix_millis, upstream,
+};
+
+#[derive(Debug, Deserialize)]
+struct TmdbList {
+    results: Vec<TmdbMovie>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbMovie {
+    id: u64,
+    title: Option<String>,
+    name: Option<String>, // fallback for TV shows
+    /// `YYYY-MM-DD`; `first_air_date` nas séries.
+    release_date: Option<String>,
+    first_air_date: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbExternalIds {
+    imdb_id: Option<String>,
+}
+
+/// O que o detalhe do TMDB (`tmdb:<tipo>:<id>:detail`, gravado pelo
+/// `/title`) tem de útil para a lista.
+#[derive(Debug, Deserialize)]
+struct TmdbDetail {
+    title: Option<String>,
+    name: Option<String>,
+    release_date: Option<String>,
+    first_air_date: Option<String>,
+    poster_path: Option<String>,
+}
+
+#[derive(Serialize)]
+struct OmdbMovieShort {
+    #[serde(rename = "Poster")]
+    poster: String,
+    #[serde(rename = "Title")]
+    title: String,
+    #[serde(rename = "Type")]
+    kind: String,
+    #[serde(rename = "Year")]
+    year: String,
+    #[serde(rename = "imdbID")]
+    imdb_id: String,
+    tmdb_id: u64,
+    /// Posição (1..) na lista completa; filtros e páginas não a alteram.
+    rank: usize,
+    /// Data de lançamento segundo o TMDB (`YYYY-MM-DD`).
+    release_date: Option<String>,
+}
+
+/// Um item das listas do TMDB, na ordem final.
+struct Candidate {
+    tmdb_id: u64,
+    kind: omdb::Kind,
+    title: String,
+    year: Option<i32>,
+    release_date: Option<String>,
+}
+
+impl Candidate {
+    /// Tipo no caminho da API do TMDB.
+    fn tmdb_kind(&self) -> &'static str {
+        match self.kind {
+            omdb::Kind::Movie => "movie",
+            omdb::Kind::Series => "tv",
+        }
+    }
+}
+
+/// Como o IMDb id foi achado.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Via {
+    /// `external_ids` do TMDB, sem gastar OMDb.
+    Tmdb,
+    /// Busca por título e ano no OMDb, quando o TMDB não tem o id.
+    Omdb,
+}
+
+struct Resolved {
+    imdb_id: String,
+    via: Via,
+    /// O corpo do OMDb quando a resolução já o trouxe.
+    omdb: Option<Value>,
+}
+
+/// De onde vieram os campos de um título no estágio de enriquecimento.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Enriched {
+    /// O corpo da resolução por título.
+    Resolution,
+    OmdbCache,
+    TmdbCache,
+    /// Detalhe do OMDb buscado agora (a lacuna).
+    Fetched,
+    /// Nenhum detalhe: só o que a lista do TMDB traz.
+    ListOnly,
+}
+
+/// Tempo de cada estágio, para o `?debug=1`.
+#[derive(Default)]
+struct Stages(Vec<(&'static str, Duration)>);
+
+impl Stages {
+    fn done(&mut self, stage: &'static str, started: Instant) -> Instant {
+        self.0.push((stage, started.elapsed()));
+        Instant::now()
+    }
+}
+
+/// `movies:trending`: trending da semana e em cartaz do TMDB, com o IMDb id
+/// e os campos do OMDb. Reconstrução em estágios, cada um com os títulos em
+/// paralelo: (1) as duas listas ao mesmo tempo, (2) o IMDb id pelo
+/// `external_ids` do TMDB (busca no OMDb só se faltar), (3) os campos pelo
+/// detalhe que já estiver em cache, OMDb ou TMDB, buscando só o que falta,
+/// e (4) a montagem, com os pôsteres. Cada título tem até
+/// `TRENDING_TITLE_TIMEOUT_MS` por estágio: sem id a tempo, fica de fora;
+/// sem campos, sai com os da lista. O tempo de cada estágio fica em
+/// `debug`, que o handler só mostra ao admin.
+pub async fn fetch(state: &AppState, mode: CacheMode) -> Result<cache::Fetched, ApiError> {
+    let key = "movies:trending".to_string();
+    if let Some(cached) = state.cache.get(&key, mode).await {
+        return Ok(cached);
+    }
+    let config = state.config();
+    let (concurrency, timeout) = (config.trending_concurrency.max(1), Duration::from_millis(config.trending_title_timeout_ms));
+    let mut stages = Stages::default();
+    let started = Instant::now();
+    let mut at = started;
+
+    let (trending, releases) = tokio::try_join!(
+        tmdb_list(state, "/trending/movie/week", "trending/movie/week?"),
+        tmdb_list(state, "/movie/now_playing", "movie/now_playing?language=en-US&page=1&"),
+    )?;
+    // Ordem estável: a posição no trending do TMDB; depois os que só estão
+    // em cartaz, por id do TMDB (a ordem do now_playing oscila entre leituras)
+    let in_trending: HashSet<u64> = trending.results.iter().map(|m| m.id).collect();
+    let mut now_playing: Vec<TmdbMovie> =
+        releases.results.into_iter().filter(|m| !in_trending.contains(&m.id)).collect();
+    now_playing.sort_by_key(|m| m.id);
+    let candidates: Vec<Candidate> = trending.results.into_iter().chain(now_playing).filter_map(candidate).collect();
+    at = stages.done("lists", at);
+
+    middleware::check_deadline()?;
+    let resolved = in_order(candidates.iter().map(|c| resolve(state, c).boxed()).collect(), concurrency, timeout).await;
+    at = stages.done("resolve", at);
+
+    middleware::check_deadline()?;
+    let resolve_timeouts = resolved.iter().filter(|r| r.is_none()).count();
+    let resolved: Vec<Option<Resolved>> = resolved.into_iter().map(Option::flatten).collect();
+    let missing = resolved.iter().filter(|r| r.is_none()).count() - resolve_timeouts;
+    let mut seen_ids = HashSet::new();
+    let unique: Vec<(&Candidate, Resolved)> = candidates
+        .iter()
+        .zip(resolved)
+        .filter_map(|(c, r)| Some((c, r?)))
+        .filter(|(_, r)| seen_ids.insert(r.imdb_id.clone())) // skip duplicates
+        .collect();
+    let enriched = in_order(unique.iter().map(|(c, r)| enrich(state, c, r).boxed()).collect(), concurrency, timeout).await;
+    let enrich_timeouts = enriched.iter().filter(|e| e.is_none()).count();
+    let enriched: Vec<(Value, Enriched)> =
+        enriched.into_iter().map(|e| e.unwrap_or((Value::Null, Enriched::ListOnly))).collect();
+    at = stages.done("enrich", at);
+
+    let combined: Vec<OmdbMovieShort> = unique
+        .iter()
+        .zip(&enriched)
+        .enumerate()
+        .map(|(i, ((c, r), (item, _)))| OmdbMovieShort {
+            poster: text(item, "Poster").unwrap_or_default(),
+            title: text(item, "Title").unwrap_or_else(|| c.title.clone()),
+            kind: text(item, "Type").unwrap_or_else(|| c.kind.as_str().to_string()),
+            year: text(item, "Year").or_else(|| c.year.map(|y| y.to_string())).unwrap_or_default(),
+            imdb_id: r.imdb_id.clone(),
+            tmdb_id: c.tmdb_id,
+            rank: i + 1,
+            release_date: c.release_date.clone(),
+        })
+        .collect();
+    let omdb_used = enriched.iter().any(|(_, from)| matches!(from, Enriched::Resolution | Enriched::OmdbCache | Enriched::Fetched));
+
+    let mut json = json!({
+        "results": combined,
+        "total": combined.len().to_string(),
+        "type": "movie",
+        // muda a cada reconstrução da lista; páginas com `generation`
+        // diferente vieram de listas diferentes
+        "generation": unix_millis(),
+    });
+    posters::fix_posters(state, &mut json["results"]).await;
+    // a lista é do TMDB; o OMDb entra quando algum campo veio dele
+    attribution::annotate(
+        &mut json,
+        [Some(attribution::Source::Tmdb), omdb_used.then_some(attribution::Source::Omdb)]
+            .into_iter()
+            .flatten(),
+    );
+    stages.done("assemble", at);
+
+    let count = |via: Via| unique.iter().filter(|(_, r)| r.via == via).count();
+    let from = |kind: Enriched| enriched.iter().filter(|(_, e)| *e == kind).count();
+    json["debug"] = json!({
+        "stages": stages.0.iter().map(|(stage, took)| json!({ "stage": stage, "ms": took.as_millis() as u64 })).collect::<Vec<_>>(),
+        "total_ms": started.elapsed().as_millis() as u64,
+        "candidates": candidates.len(),
+        "concurrency": concurrency,
+        "title_timeout_ms": timeout.as_millis() as u64,
+        "timed_out": { "resolve": resolve_timeouts, "enrich": enrich_timeouts },
+        "resolved": { "tmdb": count(Via::Tmdb), "omdb": count(Via::Omdb), "missing": missing },
+        "enriched": {
+            "resolution": from(Enriched::Resolution),
+            "omdb_cache": from(Enriched::OmdbCache),
+            "tmdb_cache": from(Enriched::TmdbCache),
+            "fetched": from(Enriched::Fetched),
+            "list_only": from(Enriched::ListOnly),
+        },
+    });
+
+    state.cache.insert_with_ttl(key, json.clone(), state.config().cache_ttl(CacheCategory::Trending)).await;
+    Ok(cache::Fetched::miss(json))
+}
+
+/// Roda `futures` com até `concurrency` ao mesmo tempo, cada um com até
+/// `timeout` (0 sem limite), e devolve os resultados na ordem de entrada;
+/// `None` no que passou do tempo.
+async fn in_order<'a, T: Send + 'a>(futures: Vec<BoxFuture<'a, T>>, concurrency: usize, timeout: Duration) -> Vec<Option<T>> {
+    let indexed: Vec<BoxFuture<'a, (usize, Option<T>)>> = futures
+        .into_iter()
+        .enumerate()
+        .map(|(i, f)| {
+            async move {
+                let value = if timeout.is_zero() { Some(f.await) } else { tokio::time::timeout(timeout, f).await.ok() };
+                (i, value)
+            }
+            .boxed()
+        })
+        .collect();
+    let mut done: Vec<(usize, Option<T>)> =
+        futures_util::stream::iter(indexed).buffer_unordered(concurrency).collect().await;
+    done.sort_by_key(|(i, _)| *i);
+    done.into_iter().map(|(_, value)| value).collect()
+}
+
+async fn tmdb_list(state: &AppState, endpoint: &'static str, path: &str) -> Result<TmdbList, ApiError> {
+    let url = format!("{}/{path}api_key={}", state.config().tmdb_base_url, state.tmdb_key);
+    let resp = upstream::get(state, upstream::Service::Tmdb, endpoint, &url)
+        .send()
+        .await
+        .map_err(upstream::send_error)?;
+    upstream::json(state, resp).await
+}
+
+fn candidate(m: TmdbMovie) -> Option<Candidate> {
+    // `name` sem `title` só aparece em séries
+    let kind = if m.title.is_some() { omdb::Kind::Movie } else { omdb::Kind::Series };
+    let title = m.title.or(m.name).filter(|t| !t.is_empty())?;
+    let release_date = m.release_date.or(m.first_air_date).filter(|d| !d.is_empty());
+    let year = release_date.as_deref().and_then(|d| d.get(..4)?.parse().ok());
+    Some(Candidate { tmdb_id: m.id, kind, title, year, release_date })
+}
+
+/// IMDb id pelo `external_ids` do TMDB (em cache por título); sem ele, pela
+/// busca por título e ano no OMDb. `None` se nenhum dos dois achar.
+async fn resolve(state: &AppState, c: &Candidate) -> Option<Resolved> {
+    let url = format!(
+        "{}/{}/{}/external_ids?api_key={}",
+        state.config().tmdb_base_url,
+        c.tmdb_kind(),
+        c.tmdb_id,
+        state.tmdb_key
+    );
+    let key = format!("tmdb:{}:{}:external_ids", c.tmdb_kind(), c.tmdb_id);
+    let external = upstream::cached_json::<TmdbExternalIds>(
+        state,
+        &state.cache.with_ttl(state.config().cache_ttl(CacheCategory::Detail)),
+        upstream::Service::Tmdb,
+        "/:kind/:id/external_ids",
+        key,
+        &url,
+        CacheMode::Normal,
+    )
+    .await;
+    match external {
+        Ok(TmdbExternalIds { imdb_id: Some(imdb_id) }) if imdb_id.starts_with("tt") => {
+            return Some(Resolved { imdb_id, via: Via::Tmdb, omdb: None });
+        }
+        Ok(_) => {}
+        Err(e) => tracing::debug!(tmdb_id = c.tmdb_id, "external_ids do TMDB indisponível: {e}"),
+    }
+    let found = omdb::resolve_by_title(state, &c.title, c.year, c.kind).await.ok().flatten()?;
+    let imdb_id = found.get("imdbID")?.as_str()?.to_string();
+    Some(Resolved { imdb_id, via: Via::Omdb, omdb: Some(found) })
+}
+
+/// Campos do título (`Title`, `Type`, `Year`, `Poster`) pelo que já houver:
+/// o corpo da resolução, o detalhe do OMDb ou o do TMDB em cache. Só sem
+/// nenhum deles busca o detalhe no OMDb; se falhar, fica o da lista.
+async fn enrich(state: &AppState, c: &Candidate, r: &Resolved) -> (Value, Enriched) {
+    if let Some(body) = &r.omdb {
+        return (body.clone(), Enriched::Resolution);
+    }
+    if let Some(cached) = state.cache.get(&format!("detail:{}", r.imdb_id), CacheMode::Normal).await {
+        return (cached.value, Enriched::OmdbCache);
+    }
+    let tmdb_key = format!("tmdb:{}:{}:detail", c.tmdb_kind(), c.tmdb_id);
+    if let Some(cached) = state.cache.get(&tmdb_key, CacheMode::Normal).await
+        && let Ok(detail) = serde_json::from_value::<TmdbDetail>(cached.value)
+    {
+        let year = detail.release_date.or(detail.first_air_date).and_then(|d| d.get(..4).map(str::to_string));
+        let fields = json!({
+            "Title": detail.title.or(detail.name),
+            "Type": c.kind.as_str(),
+            "Year": year,
+            "Poster": detail.poster_path.map(|p| format!("{}{p}", posters::TMDB_IMAGE_BASE)).unwrap_or_else(|| "N/A".into()),
+        });
+        return (fields, Enriched::TmdbCache);
+    }
+    match fetch_detail(state, &r.imdb_id, CacheMode::Normal).await {
+        Ok(detail) => (detail.value, Enriched::Fetched),
+        Err(e) => {
+            tracing::debug!(imdb_id = r.imdb_id, "detalhe para o trending indisponível: {e}");
+            (Value::Null, Enriched::ListOnly)
+        }
+    }
+}
+
+fn text(item: &Value, field: &str) -> Option<String> {
+    item.get(field).and_then(Value::as_str).filter(|v| !v.is_empty()).map(str::to_string)
+}
@@ -0,0 +1,352 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZlibDecoder};
+use axum::{Json, response::IntoResponse};
+use futures_util::TryStreamExt;
+use reqwest::{Method, RequestBuilder, Response, StatusCode, header};
+use serde::{Serialize, de::DeserializeOwned};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_util::io::StreamReader;
+use tracing::warn;
+
+use crate::{
+    ApiError, AppState,
+    cache::{CacheMode, ResponseCache},
+    middleware,
+};
+
+/// `User-Agent` de todas as chamadas de saída (o TMDB pede um identificável).
+pub const USER_AGENT: &str = concat!("rossoflix-api/", env!("CARGO_PKG_VERSION"));
+
+/// Serviços externos com política própria de requisição.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Service {
+    Omdb,
+    Tmdb,
+    Torrentio,
+    OpenSubtitles,
+}
+
+impl Service {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Service::Omdb => "omdb",
+            Service::Tmdb => "tmdb",
+            Service::Torrentio => "torrentio",
+            Service::OpenSubtitles => "opensubtitles",
+        }
+    }
+}
+
+/// Codificações que `read_body` sabe abrir.
+const ACCEPT_ENCODING: &str = "gzip, br, deflate";
+
+/// `GET` para um upstream JSON: timeout do serviço (`*_TIMEOUT_SECS`),
+/// encurtado para caber no prazo do pedido, `Accept: application/json`, `Accept-Encoding` com o que `read_body`
+/// descomprime e o `x-request-id` do pedido em andamento. `endpoint` é o
+/// molde da rota (`/find/:imdb_id`, `detail`), sob o qual a chamada entra
+/// nos contadores de `/admin/upstream-usage`.
+pub fn get(state: &AppState, service: Service, endpoint: &'static str, url: &str) -> RequestBuilder {
+    request(state, Method::GET, service, endpoint, url)
+}
+
+/// `POST` com a mesma política do [`get`] (o `/download` do OpenSubtitles).
+pub fn post(state: &AppState, service: Service, endpoint: &'static str, url: &str) -> RequestBuilder {
+    request(state, Method::POST, service, endpoint, url)
+}
+
+fn request(state: &AppState, method: Method, service: Service, endpoint: &'static str, url: &str) -> RequestBuilder {
+    let config = state.config();
+    let secs = match service {
+        Service::Omdb => config.omdb_timeout_secs,
+        Service::Tmdb => config.tmdb_timeout_secs,
+        Service::Torrentio => config.torrentio_timeout_secs,
+        Service::OpenSubtitles => config.opensubtitles_timeout_secs,
+    };
+    state.usage.record(service, endpoint);
+    let mut timeout = Duration::from_secs(secs);
+    if let Some(left) = middleware::remaining() {
+        timeout = timeout.min(left.max(Duration::from_millis(1)));
+    }
+    let req = state
+        .http
+        .request(method, url)
+        .timeout(timeout)
+        .header(header::ACCEPT, "application/json")
+        .header(header::ACCEPT_ENCODING, ACCEPT_ENCODING);
+    match middleware::current_request_id() {
+        Some(id) => req.header(middleware::REQUEST_ID_HEADER, id),
+        None => req,
+    }
+}
+
+/// Erro de envio ao upstream; o timeout causado pelo prazo do pedido vira
+/// `504` em vez de `502`.
+pub fn send_error(e: reqwest::Error) -> ApiError {
+    if e.is_timeout() && middleware::remaining().is_some_and(|left| left.is_zero()) {
+        warn!(request_id = middleware::current_request_id(), "prazo do pedido esgotado no upstream");
+        return ApiError::DeadlineExceeded;
+    }
+    if e.is_timeout() {
+        return ApiError::UpstreamTimeout(e.to_string());
+    }
+    ApiError::Upstream(e.to_string())
+}
+
+/// Lê o corpo de uma resposta do upstream até `limit` bytes, descomprimindo
+/// conforme o `Content-Encoding` (gzip, br, deflate). Recusa antes de ler
+/// quando o `Content-Length` já passa do limite, e aborta no meio quando o
+/// corpo descomprimido passa dele, sem bufferizar o resto (nem abrir uma
+/// bomba de compressão inteira).
+pub async fn read_body(resp: Response, limit: usize) -> Result<Vec<u8>, ApiError> {
+    if resp.content_length().is_some_and(|len| len > limit as u64) {
+        return Err(too_large());
+    }
+    let host = resp.url().host_str().unwrap_or_default().to_string();
+    let encoding = resp
+        .headers()
+        .get(header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_ascii_lowercase())
+        .filter(|v| !v.is_empty() && v != "identity");
+
+    let wire = AtomicU64::new(0);
+    let chunks = resp
+        .bytes_stream()
+        .inspect_ok(|chunk| {
+            wire.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        })
+        .map_err(io::Error::other);
+    let raw = StreamReader::new(chunks);
+    let reader: Box<dyn AsyncRead + Send + Unpin + '_> = match encoding.as_deref() {
+        None => Box::new(raw),
+        Some("gzip" | "x-gzip") => Box::new(GzipDecoder::new(raw)),
+        Some("br") => Box::new(BrotliDecoder::new(raw)),
+        Some("deflate") => Box::new(ZlibDecoder::new(raw)),
+        Some(other) => {
+            return Err(ApiError::Upstream(format!("{host}: Content-Encoding não suportado: {other}")));
+        }
+    };
+
+    let mut body = Vec::new();
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut body)
+        .await
+        .map_err(|e| ApiError::Upstream(format!("{host}: corpo ilegível ({}): {e}", encoding.as_deref().unwrap_or("identity"))))?;
+    if body.len() > limit {
+        return Err(too_large());
+    }
+    record(&host, encoding.is_some(), wire.load(Ordering::Relaxed), body.len() as u64);
+    Ok(body)
+}
+
+/// Tráfego de um host upstream: bytes no fio (comprimidos) e depois de
+/// descomprimir.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Bandwidth {
+    pub responses: u64,
+    pub compressed_responses: u64,
+    pub wire_bytes: u64,
+    pub decoded_bytes: u64,
+}
+
+static BANDWIDTH: Mutex<BTreeMap<String, Bandwidth>> = Mutex::new(BTreeMap::new());
+
+fn record(host: &str, compressed: bool, wire: u64, decoded: u64) {
+    let mut stats = BANDWIDTH.lock().unwrap();
+    let entry = stats.entry(host.to_string()).or_default();
+    entry.responses += 1;
+    if compressed {
+        entry.compressed_responses += 1;
+    }
+    entry.wire_bytes += wire;
+    entry.decoded_bytes += decoded;
+}
+
+/// Tráfego por host desde a subida (`GET /admin/upstream`).
+pub fn bandwidth() -> BTreeMap<String, Bandwidth> {
+    BANDWIDTH.lock().unwrap().clone()
+}
+
+/// JSON do upstream com o teto de `MAX_UPSTREAM_BODY_BYTES`. Um corpo que
+/// não desserializa vira `ApiError::UpstreamFormat`, com uma amostra
+/// guardada para `/admin/upstream-failures`.
+pub async fn json<T: DeserializeOwned>(state: &AppState, resp: Response) -> Result<T, ApiError> {
+    let host = resp.url().host_str().unwrap_or_default().to_string();
+    let path = resp.url().path().to_string();
+    let status = resp.status().as_u16();
+    let content_type = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+    let body = read_body(resp, state.config().max_upstream_body_bytes).await?;
+    serde_json::from_slice(&body).map_err(|e| {
+        let kind = FormatError::classify(content_type.as_deref(), &body, &e);
+        let failure = FormatFailure {
+            at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+            request_id: middleware::current_request_id(),
+            path,
+            status,
+            content_type,
+            kind,
+            error: e.to_string(),
+            body_bytes: body.len(),
+            sample: sample(&body),
+        };
+        warn!(
+            upstream = host,
+            path = failure.path,
+            content_type = failure.content_type,
+            kind = ?kind,
+            sample = failure.sample,
+            "resposta do upstream não é o JSON esperado: {e}"
+        );
+        record_failure(&host, failure);
+        ApiError::UpstreamFormat { upstream: host, kind }
+    })
+}
+
+/// Por que o corpo não desserializou.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FormatError {
+    /// Página HTML (desafio do Cloudflare, página de erro do proxy).
+    Html,
+    /// JSON cortado no meio.
+    Truncated,
+    /// Nem JSON.
+    Invalid,
+    /// JSON válido, mas fora do formato esperado.
+    Schema,
+}
+
+impl FormatError {
+    fn classify(content_type: Option<&str>, body: &[u8], e: &serde_json::Error) -> Self {
+        let html_type = content_type.is_some_and(|ct| ct.to_ascii_lowercase().contains("html"));
+        if html_type || body.trim_ascii_start().starts_with(b"<") {
+            FormatError::Html
+        } else if e.is_eof() {
+            FormatError::Truncated
+        } else if e.is_data() {
+            FormatError::Schema
+        } else {
+            FormatError::Invalid
+        }
+    }
+}
+
+/// Amostras guardadas por host.
+const FAILURES_PER_HOST: usize = 5;
+/// Tamanho da amostra do corpo.
+const SAMPLE_BYTES: usize = 512;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FormatFailure {
+    /// Unix timestamp (s).
+    at: u64,
+    request_id: Option<String>,
+    /// Só o caminho: a query leva as chaves da API.
+    path: String,
+    status: u16,
+    content_type: Option<String>,
+    kind: FormatError,
+    error: String,
+    body_bytes: usize,
+    /// Começo do corpo: texto, ou hex quando não é UTF-8.
+    sample: String,
+}
+
+fn sample(body: &[u8]) -> String {
+    let head = &body[..body.len().min(SAMPLE_BYTES)];
+    match std::str::from_utf8(head) {
+        Ok(text) => text.to_string(),
+        // o corte pode ter partido um caractere no fim
+        Err(e) if e.error_len().is_none() => String::from_utf8_lossy(&head[..e.valid_up_to()]).into_owned(),
+        Err(_) => head.iter().map(|b| format!("{b:02x}")).collect(),
+    }
+}
+
+static FAILURES: Mutex<BTreeMap<String, VecDeque<FormatFailure>>> = Mutex::new(BTreeMap::new());
+
+fn record_failure(host: &str, failure: FormatFailure) {
+    let mut failures = FAILURES.lock().unwrap();
+    let recent = failures.entry(host.to_string()).or_default();
+    if recent.len() == FAILURES_PER_HOST {
+        recent.pop_front();
+    }
+    recent.push_back(failure);
+}
+
+/// `GET /admin/upstream-failures` — as últimas respostas de cada host que
+/// não desserializaram, da mais recente para a mais antiga.
+pub async fn upstream_failures() -> impl IntoResponse {
+    let failures: BTreeMap<String, Vec<FormatFailure>> = FAILURES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(host, recent)| (host.clone(), recent.iter().rev().cloned().collect()))
+        .collect();
+    Json(serde_json::json!({ "failures": failures }))
+}
+
+/// `GET` JSON guardado em `cache` sob `key`, já convertido para `T`.
+pub async fn cached_json<T: DeserializeOwned>(
+    state: &AppState,
+    cache: &ResponseCache,
+    service: Service,
+    endpoint: &'static str,
+    key: String,
+    url: &str,
+    mode: CacheMode,
+) -> Result<T, ApiError> {
+    let value = match cache.get(&key, mode).await {
+        Some(cached) => cached.value,
+        None => {
+            // um corpo cortado no meio costuma ser da conexão, não do
+            // upstream: vale uma segunda tentativa
+            let value = match fetch_value(state, service, endpoint, url).await {
+                Err(ApiError::UpstreamFormat {
+                    kind: FormatError::Truncated,
+                    ..
+                }) => fetch_value(state, service, endpoint, url).await?,
+                other => other?,
+            };
+            cache.insert(key, value.clone()).await;
+            value
+        }
+    };
+    serde_json::from_value(value).map_err(|e| ApiError::UpstreamFormat {
+        upstream: service.as_str().into(),
+        kind: if e.is_data() { FormatError::Schema } else { FormatError::Invalid },
+    })
+}
+
+async fn fetch_value(
+    state: &AppState,
+    service: Service,
+    endpoint: &'static str,
+    url: &str,
+) -> Result<serde_json::Value, ApiError> {
+    let resp = get(state, service, endpoint, url).send().await.map_err(send_error)?;
+    if resp.status() == StatusCode::TOO_MANY_REQUESTS {
+        return Err(ApiError::RateLimited(format!("{service:?}: status {}", resp.status())));
+    }
+    if !resp.status().is_success() {
+        return Err(ApiError::Upstream(format!("{service:?}: status {}", resp.status())));
+    }
+    json(state, resp).await
+}
+
+fn too_large() -> ApiError {
+    ApiError::Upstream("response too large".into())
+}
@@ -0,0 +1,195 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{Json, extract::State, response::IntoResponse};
+use serde::Serialize;
+use tokio::fs;
+use tracing::warn;
+
+use crate::{AppState, dates, upstream::Service};
+
+const USAGE_FILE: &str = "upstream-usage.json";
+/// Dias guardados além de hoje.
+const HISTORY_DAYS: i64 = 7;
+/// Intervalo de gravação do arquivo; uma queda perde no máximo isso.
+const FLUSH_EVERY: Duration = Duration::from_secs(60);
+
+/// Chamadas por dia (UTC) → `"<serviço>:<endpoint>"` → total.
+type Days = BTreeMap<i64, BTreeMap<String, u64>>;
+/// Contadores de um dia, por serviço e endpoint.
+type Counters = HashMap<(Service, &'static str), AtomicU64>;
+
+/// Contadores diários de chamadas ao upstream por endpoint, persistidos em
+/// `<downloads>/upstream-usage.json`. Cada chamada soma no contador do dia
+/// em que saiu, então a virada da meia-noite não perde nem mistura
+/// contagens, mesmo com chamadas em andamento.
+#[derive(Clone)]
+pub struct UsageCounters {
+    path: PathBuf,
+    live: Arc<RwLock<HashMap<i64, Counters>>>,
+    /// O que estava no arquivo na subida.
+    loaded: Arc<Mutex<Days>>,
+    /// Serializa as gravações do arquivo.
+    writer: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl UsageCounters {
+    pub async fn load(base: &Path) -> Self {
+        let path = base.join(USAGE_FILE);
+        let loaded = match fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice::<BTreeMap<String, BTreeMap<String, u64>>>(&bytes)
+                .map(|days| days.into_iter().filter_map(|(date, counts)| Some((dates::parse(&date)?, counts))).collect())
+                .unwrap_or_else(|e| {
+                    warn!("contadores do upstream ilegíveis, recomeçando: {e}");
+                    Days::new()
+                }),
+            Err(_) => Days::new(),
+        };
+        UsageCounters {
+            path,
+            live: Default::default(),
+            loaded: Arc::new(Mutex::new(loaded)),
+            writer: Default::default(),
+        }
+    }
+
+    /// Conta uma chamada. No caminho comum é só um incremento atômico; o
+    /// lock de escrita só entra no primeiro uso de um endpoint no dia.
+    pub fn record(&self, service: Service, endpoint: &'static str) {
+        let day = unix_day();
+        if let Some(counter) = self.live.read().unwrap().get(&day).and_then(|d| d.get(&(service, endpoint))) {
+            counter.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let mut live = self.live.write().unwrap();
+        live.retain(|d, _| *d >= day - HISTORY_DAYS);
+        live.entry(day)
+            .or_default()
+            .entry((service, endpoint))
+            .or_default()
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Todas as contagens guardadas (arquivo + desde a subida), por dia.
+    fn snapshot(&self) -> Days {
+        let oldest = unix_day() - HISTORY_DAYS;
+        let mut days: Days = self.loaded.lock().unwrap().clone();
+        for (day, counters) in self.live.read().unwrap().iter() {
+            let counts = days.entry(*day).or_default();
+            for ((service, endpoint), counter) in counters {
+                *counts.entry(format!("{}:{endpoint}", service.as_str())).or_default() += counter.load(Ordering::Relaxed);
+            }
+        }
+        days.retain(|day, _| *day >= oldest);
+        days
+    }
+
+    /// Chamadas de hoje a um serviço, somando os endpoints.
+    pub fn today(&self, service: Service) -> u64 {
+        let day = unix_day();
+        let prefix = format!("{}:", service.as_str());
+        let loaded: u64 = self
+            .loaded
+            .lock()
+            .unwrap()
+            .get(&day)
+            .map(|counts| counts.iter().filter(|(k, _)| k.starts_with(&prefix)).map(|(_, n)| n).sum())
+            .unwrap_or_default();
+        let live: u64 = self
+            .live
+            .read()
+            .unwrap()
+            .get(&day)
+            .map(|counters| {
+                counters
+                    .iter()
+                    .filter(|((s, _), _)| *s == service)
+                    .map(|(_, n)| n.load(Ordering::Relaxed))
+                    .sum()
+            })
+            .unwrap_or_default();
+        loaded + live
+    }
+
+    async fn save(&self) {
+        let _guard = self.writer.lock().await;
+        let days: BTreeMap<String, BTreeMap<String, u64>> =
+            self.snapshot().into_iter().map(|(day, counts)| (dates::format(day), counts)).collect();
+        let json = match serde_json::to_vec_pretty(&days) {
+            Ok(json) => json,
+            Err(e) => return warn!("falha ao serializar contadores do upstream: {e}"),
+        };
+        let tmp = self.path.with_extension("json.tmp");
+        let result = match fs::write(&tmp, json).await {
+            Ok(()) => fs::rename(&tmp, &self.path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!(path = %self.path.display(), "falha ao gravar contadores do upstream: {e}");
+        }
+    }
+}
+
+/// Grava os contadores a cada `FLUSH_EVERY`.
+pub fn spawn_flusher(usage: UsageCounters) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(FLUSH_EVERY);
+        tick.tick().await;
+        loop {
+            tick.tick().await;
+            usage.save().await;
+        }
+    });
+}
+
+#[derive(Debug, Serialize)]
+struct DayUsage {
+    date: String,
+    total: u64,
+    /// Total por serviço (`omdb`, `tmdb`, `torrentio`, `opensubtitles`).
+    upstreams: BTreeMap<String, u64>,
+    /// Total por endpoint (`tmdb:/trending/all/:window`, `omdb:detail`...).
+    endpoints: BTreeMap<String, u64>,
+}
+
+fn day_usage(day: i64, endpoints: BTreeMap<String, u64>) -> DayUsage {
+    let mut upstreams = BTreeMap::new();
+    for (endpoint, n) in &endpoints {
+        let service = endpoint.split_once(':').map_or(endpoint.as_str(), |(s, _)| s);
+        *upstreams.entry(service.to_string()).or_default() += n;
+    }
+    DayUsage {
+        date: dates::format(day),
+        total: endpoints.values().sum(),
+        upstreams,
+        endpoints,
+    }
+}
+
+/// `GET /admin/upstream-usage` — chamadas ao upstream de hoje, de ontem e
+/// dos 7 dias anteriores a hoje, por serviço e por endpoint (dia UTC).
+pub async fn upstream_usage(State(state): State<AppState>) -> impl IntoResponse {
+    let days = state.usage.snapshot();
+    let today = unix_day();
+    let at = |day: i64| day_usage(day, days.get(&day).cloned().unwrap_or_default());
+    Json(serde_json::json!({
+        "today": at(today),
+        "yesterday": at(today - 1),
+        "history": (1..=HISTORY_DAYS).map(|ago| at(today - ago)).collect::<Vec<_>>(),
+    }))
+}
+
+/// Dias desde 1970-01-01 (UTC).
+fn unix_day() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / 86_400)
+        .unwrap_or_default() as i64
+}
@@ -0,0 +1,234 @@
+use std::{
+    pin::pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use futures_util::{StreamExt, stream};
+use moka::future::Cache;
+use serde::{Deserialize, Serialize};
+use tokio::time::{Interval, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::{ApiError, AppState, cache::CacheMode, fetch_detail, markers, party, torrentio};
+
+/// Títulos aquecidos ao mesmo tempo.
+const WARM_CONCURRENCY: usize = 4;
+/// Teto de ids por pedido.
+const MAX_WARM_IDS: usize = 5_000;
+
+/// Tarefas de aquecimento, consultáveis por uma hora depois de criadas.
+#[derive(Clone)]
+pub struct WarmTasks {
+    tasks: Cache<String, Arc<WarmTask>>,
+    /// Ritmo das chamadas ao OMDb, comum a todas as tarefas.
+    omdb_pacer: Arc<tokio::sync::Mutex<Interval>>,
+}
+
+struct WarmTask {
+    cancel: CancellationToken,
+    report: Mutex<WarmReport>,
+}
+
+#[derive(Clone, Serialize)]
+struct WarmReport {
+    task_id: String,
+    status: &'static str,
+    total: usize,
+    done: usize,
+    failed: usize,
+    results: Vec<WarmResult>,
+}
+
+#[derive(Clone, Serialize)]
+struct WarmResult {
+    imdb_id: String,
+    ok: bool,
+    /// `fetched` (foi ao upstream), `cached` (já estava) ou `failed`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    streams: Option<&'static str>,
+    error: Option<String>,
+}
+
+impl WarmTasks {
+    pub fn new(omdb_per_min: u32) -> Self {
+        let period = Duration::from_secs(60) / omdb_per_min.max(1);
+        let mut pacer = tokio::time::interval(period);
+        pacer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        WarmTasks {
+            tasks: Cache::builder()
+                .max_capacity(100)
+                .time_to_live(Duration::from_secs(3600))
+                .build(),
+            omdb_pacer: Arc::new(tokio::sync::Mutex::new(pacer)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct WarmRequest {
+    ids: Vec<String>,
+    #[serde(default = "default_include")]
+    include: Vec<String>,
+}
+
+fn default_include() -> Vec<String> {
+    vec!["detail".into(), "streams".into()]
+}
+
+/// `POST /admin/cache/warm`: preenche em segundo plano as mesmas entradas
+/// de cache de `/movie/:id` e `/torrentio/movie/:id`. Responde `202` com o
+/// `task_id`; o progresso sai em `GET /admin/cache/warm/:id`.
+pub async fn start_warm(
+    State(state): State<AppState>,
+    Json(req): Json<WarmRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut detail = false;
+    let mut streams = false;
+    for item in &req.include {
+        match item.as_str() {
+            "detail" => detail = true,
+            "streams" => streams = true,
+            other => return Err(ApiError::BadRequest(format!("include desconhecido: {other}"))),
+        }
+    }
+    if !detail && !streams {
+        return Err(ApiError::BadRequest("include vazio".into()));
+    }
+    if req.ids.is_empty() || req.ids.len() > MAX_WARM_IDS {
+        return Err(ApiError::BadRequest(format!("informe de 1 a {MAX_WARM_IDS} ids")));
+    }
+    let mut ids = Vec::with_capacity(req.ids.len());
+    for id in req.ids {
+        let id = id.trim().to_string();
+        markers::check_imdb_id(&id)?;
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+
+    let task_id = party::new_id();
+    let task = Arc::new(WarmTask {
+        cancel: CancellationToken::new(),
+        report: Mutex::new(WarmReport {
+            task_id: task_id.clone(),
+            status: "running",
+            total: ids.len(),
+            done: 0,
+            failed: 0,
+            results: Vec::new(),
+        }),
+    });
+    state.warm.tasks.insert(task_id.clone(), task.clone()).await;
+    info!(task_id, total = ids.len(), "aquecimento do cache iniciado");
+
+    tokio::spawn(run(state, task, ids, detail, streams));
+    Ok((StatusCode::ACCEPTED, Json(serde_json::json!({ "task_id": task_id }))))
+}
+
+async fn run(state: AppState, task: Arc<WarmTask>, ids: Vec<String>, detail: bool, streams: bool) {
+    let mut results = pin!(
+        stream::iter(ids)
+            .take_until(task.cancel.clone().cancelled_owned())
+            .map(|id| async {
+                // cancelar também interrompe quem está esperando o ritmo do OMDb
+                tokio::select! {
+                    result = warm_one(&state, id, detail, streams) => Some(result),
+                    _ = task.cancel.cancelled() => None,
+                }
+            })
+            .buffer_unordered(WARM_CONCURRENCY)
+            .filter_map(|result| async { result })
+    );
+    while let Some(result) = results.next().await {
+        let mut report = task.report.lock().unwrap();
+        report.done += 1;
+        if !result.ok {
+            report.failed += 1;
+        }
+        report.results.push(result);
+    }
+
+    let mut report = task.report.lock().unwrap();
+    report.status = if task.cancel.is_cancelled() { "cancelled" } else { "done" };
+    info!(task_id = report.task_id, done = report.done, failed = report.failed, "aquecimento do cache {}", report.status);
+}
+
+async fn warm_one(state: &AppState, imdb_id: String, detail: bool, streams: bool) -> WarmResult {
+    let mut result = WarmResult {
+        imdb_id,
+        ok: true,
+        detail: None,
+        streams: None,
+        error: None,
+    };
+    if detail {
+        let key = format!("detail:{}", result.imdb_id);
+        result.detail = Some(if state.cache.contains_key(&key).await {
+            "cached"
+        } else {
+            // só as idas ao OMDb consomem o ritmo
+            state.warm.omdb_pacer.lock().await.tick().await;
+            match fetch_detail(state, &result.imdb_id, CacheMode::Normal).await {
+                Ok(_) => "fetched",
+                Err(e) => {
+                    result.error.get_or_insert(format!("detail: {e}"));
+                    "failed"
+                }
+            }
+        });
+    }
+    if streams {
+        result.streams = Some(if state.cache.contains_key(&torrentio::movie_key(&result.imdb_id)).await {
+            "cached"
+        } else {
+            match torrentio::movie_streams(state, &result.imdb_id, CacheMode::Normal).await {
+                Ok(_) => "fetched",
+                Err(e) => {
+                    result.error.get_or_insert(format!("streams: {e}"));
+                    "failed"
+                }
+            }
+        });
+    }
+    result.ok = result.error.is_none();
+    result
+}
+
+/// `GET /admin/cache/warm/:id`: progresso e resultado por id.
+pub async fn warm_status(
+    State(state): State<AppState>,
+    Path(task_id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let task = find(&state, &task_id).await?;
+    let report = task.report.lock().unwrap().clone();
+    Ok(Json(report))
+}
+
+/// `POST /admin/cache/warm/:id/cancel`: interrompe a tarefa; o que já foi
+/// para o cache continua lá.
+pub async fn cancel_warm(
+    State(state): State<AppState>,
+    Path(task_id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    find(&state, &task_id).await?.cancel.cancel();
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn find(state: &AppState, task_id: &str) -> Result<Arc<WarmTask>, ApiError> {
+    state
+        .warm
+        .tasks
+        .get(task_id)
+        .await
+        .ok_or_else(|| ApiError::NotFound(format!("tarefa {task_id} não encontrada")))
+}
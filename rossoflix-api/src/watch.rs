@@ -0,0 +1,204 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use tokio::fs;
+use tracing::{info, warn};
+
+use crate::{
+    AppState,
+    downloads::{self, Source},
+    magnet::Magnet,
+    release_name,
+    slug,
+    stream_title::StreamTitle,
+    torrent::{self, TorrentFile},
+};
+
+/// Subpastas para onde vão os arquivos já lidos.
+const PROCESSED_DIR: &str = "processed";
+const FAILED_DIR: &str = "failed";
+
+/// O que uma passada deixa para a seguinte.
+#[derive(Default)]
+struct Seen {
+    /// Tamanho e modificação de cada arquivo, para saber se ainda está
+    /// sendo gravado.
+    files: HashMap<PathBuf, (u64, SystemTime)>,
+    /// Já processados que não puderam sair da pasta: não são lidos de novo.
+    stuck: HashSet<PathBuf>,
+}
+
+/// Vigia `WATCH_DIR` em segundo plano: cada `.torrent` ou `.magnet` vira um
+/// download, e o arquivo vai para `processed/` (ou para `failed/`, com o
+/// motivo em `<nome>.error.txt`). Um arquivo só é lido depois de passar uma
+/// passada inteira sem mudar de tamanho, para não pegar cópias pela metade.
+/// Não faz nada sem `WATCH_DIR`.
+pub fn spawn_watcher(state: AppState) {
+    let Some(dir) = state.config().watch_dir.clone() else {
+        return;
+    };
+    info!(dir = %dir.display(), "vigiando a pasta de torrents");
+    tokio::spawn(async move {
+        let mut seen = Seen::default();
+        loop {
+            tokio::time::sleep(Duration::from_secs(state.config().watch_interval_secs.max(1))).await;
+            // uma passada que entre em pânico não derruba o vigia
+            let pass = tokio::spawn(scan(state.clone(), dir.clone(), seen));
+            seen = match pass.await {
+                Ok(next) => next,
+                Err(e) => {
+                    warn!(dir = %dir.display(), "passada na pasta vigiada falhou: {e}");
+                    Seen::default()
+                }
+            };
+        }
+    });
+}
+
+/// Uma passada: processa os arquivos estáveis desde a anterior e devolve o
+/// que ainda está sendo gravado.
+async fn scan(state: AppState, dir: PathBuf, seen: Seen) -> Seen {
+    let mut next = Seen::default();
+    let mut entries = match fs::read_dir(&dir).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!(dir = %dir.display(), "não foi possível ler a pasta vigiada: {e}");
+            return next;
+        }
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let watched = matches!(path.extension().and_then(|e| e.to_str()), Some("torrent" | "magnet"));
+        if name.starts_with('.') || !watched {
+            continue;
+        }
+        let Ok(meta) = entry.metadata().await else { continue };
+        if !meta.is_file() {
+            continue;
+        }
+        if seen.stuck.contains(&path) {
+            next.stuck.insert(path);
+            continue;
+        }
+        let current = (meta.len(), meta.modified().unwrap_or(SystemTime::UNIX_EPOCH));
+        if current.0 == 0 || seen.files.get(&path) != Some(&current) {
+            next.files.insert(path, current);
+            continue;
+        }
+        let outcome = enqueue(&state, &path).await;
+        let sub = if outcome.is_ok() { PROCESSED_DIR } else { FAILED_DIR };
+        if !settle(&dir, &path, sub, outcome.as_ref().err()).await {
+            next.stuck.insert(path.clone());
+        }
+        match outcome {
+            Ok(label) => {
+                info!(file = name, "download da pasta vigiada iniciado: {label}");
+                if state.telegram.is_some() {
+                    state.outbox.enqueue("telegram", format!("Download iniciado pela pasta vigiada: {label}"));
+                }
+            }
+            Err(e) => {
+                warn!(file = name, "arquivo da pasta vigiada recusado: {e}");
+                if state.telegram.is_some() {
+                    state.outbox.enqueue("telegram", format!("Arquivo recusado na pasta vigiada: {name} ({e})"));
+                }
+            }
+        }
+    }
+    next
+}
+
+/// Lê o arquivo e dispara o download em segundo plano; devolve o nome do
+/// arquivo baixado.
+async fn enqueue(state: &AppState, path: &Path) -> Result<String, String> {
+    let raw = fs::read(path).await.map_err(|e| format!("falha ao ler: {e}"))?;
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let title = title_from_name(&stem);
+
+    if path.extension().is_some_and(|e| e == "torrent") {
+        if raw.len() > torrent::MAX_TORRENT_BYTES {
+            return Err(".torrent grande demais".into());
+        }
+        let torrent = TorrentFile::parse(raw)?;
+        let filename = torrent.largest_file().name.clone();
+        let size = torrent.file_index(&filename).map(|i| torrent.files[i].size_bytes);
+        mark_title(state, &torrent.info_hash, title.as_ref()).await;
+        let (state, label) = (state.clone(), filename.clone());
+        tokio::spawn(async move {
+            if let Err(e) = downloads::run(&state, Source::Torrent(&torrent), &filename, size).await {
+                warn!(id = %torrent.info_hash, filename, "download da pasta vigiada falhou: {e}");
+            }
+        });
+        return Ok(label);
+    }
+
+    let text = String::from_utf8(raw).map_err(|_| ".magnet não é texto".to_string())?;
+    let line = text
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with('#'))
+        .ok_or_else(|| ".magnet vazio".to_string())?;
+    let magnet = Magnet::parse(line).ok_or_else(|| format!("magnet inválido: {line}"))?;
+    let filename = slug::from_magnet(&magnet).unwrap_or_else(|| slug::filename(&format!("{stem}.mkv")));
+    let dir = downloads::job_dir(&state.config().downloads_dir, &magnet.info_hash);
+    fs::create_dir_all(&dir).await.map_err(|e| format!("não foi possível criar {}: {e}", dir.display()))?;
+    mark_title(state, &magnet.info_hash, title.as_ref()).await;
+    let (state, label) = (state.clone(), filename.clone());
+    tokio::spawn(async move {
+        if let Err(e) = downloads::run(&state, Source::Magnet(&magnet), &filename, None).await {
+            warn!(id = %magnet.info_hash, filename, "download da pasta vigiada falhou: {e}");
+        }
+    });
+    Ok(label)
+}
+
+/// Título pelo nome do arquivo: um `tt1234567` e, se houver, o episódio
+/// (`S01E02`).
+fn title_from_name(name: &str) -> Option<StreamTitle> {
+    let imdb_id = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .find(|t| t.len() >= 9 && t.starts_with("tt") && t[2..].bytes().all(|b| b.is_ascii_digit()))?;
+    let parsed = release_name::parse(name);
+    let episode = parsed.season.and(parsed.episode);
+    StreamTitle::from_hints(Some(imdb_id), parsed.season.filter(|_| episode.is_some()), episode).ok().flatten()
+}
+
+async fn mark_title(state: &AppState, info_hash: &str, title: Option<&StreamTitle>) {
+    if let Some(title) = title
+        && let Err(e) = state.downloads.set_title(info_hash, title).await
+    {
+        warn!(id = info_hash, "falha ao gravar o título do download: {e}");
+    }
+}
+
+/// Move o arquivo para `dir/<sub>/`, com o motivo ao lado quando falhou.
+/// Um nome já usado lá ganha o horário na frente. `false` se não conseguiu
+/// mover.
+async fn settle(dir: &Path, path: &Path, sub: &str, error: Option<&String>) -> bool {
+    let target_dir = dir.join(sub);
+    let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let mut target = target_dir.join(&name);
+    if fs::try_exists(&target).await.unwrap_or(false) {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        target = target_dir.join(format!("{now}-{name}"));
+    }
+    let moved = async {
+        fs::create_dir_all(&target_dir).await?;
+        fs::rename(path, &target).await
+    };
+    if let Err(e) = moved.await {
+        warn!(path = %path.display(), "falha ao mover para {sub}/; o arquivo fica, mas não é lido de novo: {e}");
+        return false;
+    }
+    if let Some(error) = error {
+        let sidecar = target.with_file_name(format!("{}.error.txt", target.file_name().unwrap_or_default().to_string_lossy()));
+        if let Err(e) = fs::write(&sidecar, format!("{error}\n")).await {
+            warn!(path = %sidecar.display(), "falha ao gravar o motivo: {e}");
+        }
+    }
+    true
+}
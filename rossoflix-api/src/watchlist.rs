@@ -0,0 +1,107 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+use crate::{ApiError, AppState, auth::Identity, markers::check_imdb_id};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ProfileParams {
+    /// Perfil do usuário; ausente é a lista padrão da casa (ou a do
+    /// usuário do proxy, veja `Identity::profile`).
+    #[serde(default)]
+    pub profile: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WatchlistItem {
+    pub imdb_id: String,
+    /// Unix timestamp (s).
+    pub added_at: i64,
+}
+
+/// Títulos da lista do perfil, do mais recente ao mais antigo.
+pub async fn items(state: &AppState, profile: &str) -> Result<Vec<WatchlistItem>, ApiError> {
+    let profile = profile.to_string();
+    state
+        .db
+        .call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT imdb_id, added_at FROM watchlist WHERE profile = ?1 ORDER BY added_at DESC, imdb_id",
+            )?;
+            stmt.query_map(params![profile], |row| {
+                Ok(WatchlistItem {
+                    imdb_id: row.get(0)?,
+                    added_at: row.get(1)?,
+                })
+            })?
+            .collect()
+        })
+        .await
+}
+
+/// `GET /watchlist?profile=`
+pub async fn get_watchlist(
+    State(state): State<AppState>,
+    identity: Identity,
+    Query(params): Query<ProfileParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let profile = identity.profile(&params.profile)?;
+    let items = items(&state, &profile).await?;
+    Ok(Json(serde_json::json!({ "profile": profile, "items": items })))
+}
+
+/// `PUT /watchlist/:imdb_id?profile=` — idempotente.
+pub async fn add_to_watchlist(
+    State(state): State<AppState>,
+    Path(imdb_id): Path<String>,
+    identity: Identity,
+    Query(params): Query<ProfileParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let profile = identity.profile(&params.profile)?;
+    check_imdb_id(&imdb_id)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    state
+        .db
+        .call(move |conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO watchlist (profile, imdb_id, added_at) VALUES (?1, ?2, ?3)",
+                params![profile, imdb_id, now],
+            )
+        })
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `DELETE /watchlist/:imdb_id?profile=`
+pub async fn remove_from_watchlist(
+    State(state): State<AppState>,
+    Path(imdb_id): Path<String>,
+    identity: Identity,
+    Query(params): Query<ProfileParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let profile = identity.profile(&params.profile)?;
+    check_imdb_id(&imdb_id)?;
+    let removed = state
+        .db
+        .call(move |conn| {
+            conn.execute(
+                "DELETE FROM watchlist WHERE profile = ?1 AND imdb_id = ?2",
+                params![profile, imdb_id],
+            )
+        })
+        .await?;
+    if removed == 0 {
+        return Err(ApiError::NotFound("título não está na lista".into()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
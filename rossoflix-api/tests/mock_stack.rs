@@ -0,0 +1,2322 @@
+//! A API de verdade contra os upstreams falsos de [`support`]: cada teste
+//! sobe a sua pilha, com diretório de trabalho e fixtures próprios.
+//!
+//! ```bash
+//! cargo test --test mock_stack   # todas as verificações
+//! cargo mock-stack               # sobe uma pilha e espera o Ctrl-C (desenvolvimento do frontend)
+//! ```
+
+mod support;
+
+use std::{collections::HashMap, sync::{Arc, atomic::{AtomicUsize, Ordering}}, time::Duration};
+
+use axum::{Router, extract::Query, http::HeaderMap, http::StatusCode, http::header::CONTENT_TYPE, response::IntoResponse, routing::get};
+use reqwest::{Method, header};
+use serde_json::{Value, json};
+use support::*;
+
+/// Sobe a pilha e fica no ar até o Ctrl-C; é o `cargo mock-stack`.
+#[tokio::test]
+#[ignore = "fica no ar até o Ctrl-C; rode com `cargo mock-stack`"]
+async fn interactive() -> Result<(), String> {
+    let mut stack = Stack::start().await?;
+    println!("API:       {}", stack.api);
+    println!("OMDb:      {}", stack.upstreams.omdb);
+    println!("TMDB:      {}", stack.upstreams.tmdb);
+    println!("torrentio: {}", stack.upstreams.torrentio);
+    println!("admin:     Authorization: Bearer {ADMIN_TOKEN}");
+    println!("stream:    {}/stream?magnet={SAMPLE_HASH}&filename={SAMPLE_FILE}", stack.api);
+    println!("Ctrl-C para sair");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => Ok(()),
+        status = stack.exited() => Err(format!("a API saiu: {status}")),
+    }
+}
+
+#[tokio::test]
+async fn search() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let body = get_json(http, &format!("{api}/search?q=matrix")).await?;
+    expect(body.to_string().contains("tt0234215"), || format!("sem tt0234215 em {body}"))
+}
+
+// a mesma busca de novo sai do cache: o OMDb vê uma chamada só
+#[tokio::test]
+async fn search_is_cached() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    for _ in 0..2 {
+        get_json(http, &format!("{api}/search?q=matrix")).await?;
+    }
+    let usage = http
+        .get(format!("{api}/admin/upstream-usage"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?
+        .json::<Value>()
+        .await
+        .map_err(|e| e.to_string())?;
+    let calls = &usage["today"]["endpoints"]["omdb:search"];
+    expect(calls == 1, || format!("omdb:search = {calls}, esperado 1"))
+}
+
+#[tokio::test]
+async fn movie_detail() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let body = get_json(http, &format!("{api}/movie/tt0133093")).await?;
+    expect(body.to_string().contains("The Matrix"), || format!("sem o título em {body}"))
+}
+
+#[tokio::test]
+async fn trending() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let body = get_json(http, &format!("{api}/movies/trending")).await?;
+    expect(body.to_string().contains("tt0133093"), || format!("sem tt0133093 em {body}"))
+}
+
+// trending primeiro, depois o que só está em cartaz; rank de 1 em diante
+#[tokio::test]
+async fn trending_order_and_rank() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let body = get_json(http, &format!("{api}/movies/trending")).await?;
+    let got: Vec<(String, u64)> = items(&body)
+        .map(|item| (item["imdbID"].as_str().unwrap_or_default().to_string(), item["rank"].as_u64().unwrap_or_default()))
+        .collect();
+    let want: Vec<(String, u64)> =
+        ["tt0133093", "tt0234215", "tt0242653"].iter().zip(1..).map(|(id, rank)| (id.to_string(), rank)).collect();
+    expect(got == want, || format!("ordem {got:?}, esperado {want:?}"))
+}
+
+// duas páginas lidas em seguida são fatias da mesma lista
+#[tokio::test]
+async fn trending_pages() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let full = get_json(http, &format!("{api}/movies/trending")).await?;
+    let first = get_json(http, &format!("{api}/movies/trending?page=1&page_size=2")).await?;
+    let second = get_json(http, &format!("{api}/movies/trending?page=2&page_size=2")).await?;
+    let joined: Vec<&Value> = items(&first).chain(items(&second)).collect();
+    expect(joined == items(&full).collect::<Vec<_>>(), || "páginas 1 e 2 não somam a lista".into())?;
+    expect(first["generation"] == second["generation"], || "generation mudou entre as páginas".into())?;
+    expect(second["total_pages"] == 2, || format!("total_pages = {}", second["total_pages"]))
+}
+
+#[tokio::test]
+async fn trending_refresh() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let before = get_json(http, &format!("{api}/movies/trending")).await?["generation"].as_u64();
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    let after = http
+        .get(format!("{api}/movies/trending?refresh=1"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?
+        .json::<Value>()
+        .await
+        .map_err(|e| e.to_string())?["generation"]
+        .as_u64();
+    expect(after > before, || format!("generation {before:?} → {after:?} no refresh"))
+}
+
+#[tokio::test]
+async fn attribution_sources() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let body = get_json(http, &format!("{api}/attribution")).await?;
+    let count = body["sources"].as_array().map_or(0, Vec::len);
+    expect(count == 3, || format!("{count} fontes em /attribution"))
+}
+
+#[tokio::test]
+async fn torrentio_streams() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let body = get_json(http, &format!("{api}/torrentio/movie/tt0133093")).await?;
+    expect(body.to_string().contains(SAMPLE_HASH), || format!("sem o infoHash em {body}"))
+}
+
+// páginas de 20 sobre as de 100 do addon, com o detalhe já em cache por
+// cima do nome do addon; sem catálogo de séries, o popular do TMDB
+#[tokio::test]
+async fn popular_catalog() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    get_json(http, &format!("{api}/movie/{}", MOVIES[0].0)).await?;
+    let url = |query: &str| format!("{api}/catalog/torrents/popular?{query}");
+    let first = get_json(http, &url("type=movie&page=1")).await?;
+    let ids: Vec<&str> = items(&first).filter_map(|item| item["imdbID"].as_str()).collect();
+    expect(first["source"] == "torrentio" && first["has_more"] == true && ids.len() == 19, || format!("{first}"))?;
+    expect(items(&first).next().is_some_and(|item| item["Title"] == "The Matrix"), || format!("sem o detalhe em cache: {first}"))?;
+    let sixth = get_json(http, &url("type=movie&page=6")).await?;
+    expect(items(&sixth).next().is_some_and(|item| item["Title"] == "Popular 100") && items(&sixth).count() == 20, || format!("{sixth}"))?;
+    let last = get_json(http, &url("type=movie&page=7")).await?;
+    expect(items(&last).count() == 5 && last["has_more"] == false, || format!("{last}"))?;
+    let series = get_json(http, &url("type=series")).await?;
+    expect(
+        series["source"] == "tmdb_popular"
+            && series["fallback_reason"] == "no_catalog"
+            && series["has_more"] == true
+            && items(&series).next().is_some_and(|item| item["Title"] == "Game of Thrones" && item["tmdb_id"] == 1399),
+        || format!("{series}"),
+    )
+}
+
+// idiomas dos releases brasileiros e o filtro por áudio
+#[tokio::test]
+async fn release_languages() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let body = get_json(http, &format!("{api}/torrentio/movie/{RELEASES_IMDB_ID}")).await?;
+    let streams = body["streams"].as_array().cloned().unwrap_or_default();
+    expect(streams.len() == RELEASES.len(), || format!("{} streams", streams.len()))?;
+    for (stream, (release, _, want, dubbed)) in streams.iter().zip(RELEASES) {
+        let mut got: Vec<&str> =
+            stream["parsed"]["languages"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+        let mut want = want.to_vec();
+        got.sort();
+        want.sort();
+        expect(got == want && stream["parsed"]["dubbed"] == dubbed, || {
+            format!("{release}: {got:?} dublado={}, esperado {want:?} dublado={dubbed}", stream["parsed"]["dubbed"])
+        })?;
+    }
+    let filtered = get_json(http, &format!("{api}/torrentio/movie/{RELEASES_IMDB_ID}?audio_lang=pt-br")).await?;
+    let want = RELEASES.iter().filter(|(.., langs, _)| langs.iter().any(|l| l.starts_with("pt"))).count();
+    expect(filtered["total"] == want, || format!("audio_lang=pt-br: {} de {want}", filtered["total"]))
+}
+
+// o filtro é aplicado sobre o corpo cru em cache: variar o filtro (e a
+// caixa do id) não busca de novo no torrentio
+#[tokio::test]
+async fn torrentio_filters_reuse_cache() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let calls = || async {
+        let usage = admin_json(http, &format!("{api}/admin/upstream-usage")).await?;
+        Ok::<_, String>(usage["today"]["endpoints"]["torrentio:/stream/movie/:imdb_id"].as_u64().unwrap_or(0))
+    };
+    let before = calls().await?;
+    for query in ["", "?capabilities=chromecast", "?audio_lang=pt-BR", "?limit=5", "?capabilities=h264,hdr10&limit=3", ""] {
+        get_json(http, &format!("{api}/torrentio/movie/{RELEASES_IMDB_ID}{query}")).await?;
+    }
+    get_json(http, &format!("{api}/torrentio/movie/{}", RELEASES_IMDB_ID.to_uppercase())).await?;
+    let fresh = "tt0468569";
+    for query in ["", "?limit=1", "?audio_lang=en"] {
+        get_json(http, &format!("{api}/torrentio/movie/{fresh}{query}")).await?;
+    }
+    let after = calls().await?;
+    expect(after - before == 2, || format!("{} chamadas ao torrentio, esperadas 2 (uma por título)", after - before))
+}
+
+// abrir o detalhe pré-carrega os streams: o `/torrentio` seguinte é hit,
+// sem uma segunda chamada ao upstream
+#[tokio::test]
+async fn prefetch_warms_torrentio() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let calls = || async {
+        let usage = admin_json(http, &format!("{api}/admin/upstream-usage")).await?;
+        Ok::<_, String>(usage["today"]["endpoints"]["torrentio:/stream/movie/:imdb_id"].as_u64().unwrap_or(0))
+    };
+    let imdb_id = MOVIES[2].0;
+    get_json(http, &format!("{api}/movie/{imdb_id}")).await?;
+    let mut prefetched = 0;
+    for _ in 0..50 {
+        prefetched = calls().await?;
+        if prefetched > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    expect(prefetched == 1, || format!("{prefetched} chamadas ao torrentio depois do detalhe, esperada 1"))?;
+    // a chamada conta antes da gravação no cache; o usuário ainda lê a sinopse
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let resp = http.get(format!("{api}/torrentio/movie/{imdb_id}")).send().await.map_err(|e| e.to_string())?;
+    let cache = resp.headers().get("x-cache").and_then(|v| v.to_str().ok()).unwrap_or_default().to_string();
+    let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+    expect(cache == "HIT" && body.to_string().contains(SAMPLE_HASH), || format!("X-Cache {cache}: {body}"))?;
+    let after = calls().await?;
+    expect(after == 1, || format!("{after} chamadas ao torrentio, esperada só a do prefetch"))
+}
+
+// o parse do servidor exposto ao cliente (a tabela de nomes fica nos
+// testes de `release_name`)
+#[tokio::test]
+async fn parse_release() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let name = "Breaking.Bad.S01E02.720p.HDTV.x264-CTU";
+    let url = reqwest::Url::parse_with_params(&format!("{api}/parse/release"), [("name", name)]).map_err(|e| e.to_string())?;
+    let body = get_json(http, url.as_str()).await?;
+    let parsed = &body["parsed"];
+    expect(
+        body["name"] == name && parsed["title"] == "Breaking Bad" && parsed["season"] == 1 && parsed["episode"] == 2 && parsed["group"] == "CTU",
+        || format!("{body}"),
+    )?;
+    let resp = http.get(format!("{api}/parse/release?name=%20")).send().await.map_err(|e| e.to_string())?;
+    expect(resp.status() == StatusCode::BAD_REQUEST, || format!("name vazio: {}", resp.status()))
+}
+
+// busca de episódios com itens incompletos: a página sai, o item sem id
+// fica de fora e o ano vem do detalhe da série já em cache
+#[tokio::test]
+async fn episode_search_with_incomplete_items() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    for id in [SERIES_ID, EPISODE_ID] {
+        get_json(http, &format!("{api}/movie/{id}")).await?;
+    }
+    let body = get_json(http, &format!("{api}/search?q=ozymandias&type=episode")).await?;
+    let results = body["results"].as_array().ok_or_else(|| format!("sem results: {body}"))?;
+    let by_id = |id: &str| results.iter().find(|r| r["imdbID"] == id).cloned().unwrap_or_default();
+    let (ozymandias, felina) = (by_id(EPISODE_ID), by_id("tt2301455"));
+    expect(
+        results.len() == 2
+            && body["skipped"] == 1
+            && ozymandias["Year"] == "2008"
+            && ozymandias.get("Poster").is_some()
+            && felina["Year"] == "2013"
+            && felina["Type"] == "episode",
+        || format!("{body}"),
+    )
+}
+
+// campos calculados da v2: duração no idioma pedido, data ISO e anos
+#[tokio::test]
+async fn v2_computed_fields() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let v2 = |path: String| async move {
+        let resp = http
+            .get(format!("{api}{path}"))
+            .header(header::ACCEPT, "application/vnd.rossoflix.v2+json")
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        resp.json::<Value>().await.map_err(|e| e.to_string())
+    };
+    let (id, _, year, _) = MOVIES[0];
+    let year: i64 = year.parse().unwrap();
+    let pt = v2(format!("/movie/{id}?locale=pt-BR")).await?;
+    let en = v2(format!("/movie/{id}")).await?;
+    let (pt, en) = (&pt["data"], &en["data"]);
+    expect(
+        pt["runtimeMinutes"] == 136
+            && pt["runtimeFormatted"] == "2 horas e 16 minutos"
+            && en["runtimeFormatted"] == "2 hours 16 minutes"
+            && pt["releasedIso"] == format!("{year}-03-31")
+            && pt["yearStart"] == year
+            && pt["yearEnd"] == year,
+        || format!("pt-BR: {pt}; en: {en}"),
+    )?;
+    let series = v2(format!("/movie/{SERIES_ID}")).await?;
+    let series = &series["data"];
+    expect(
+        series["yearStart"] == 2008 && series["yearEnd"] == 2013 && series["runtimeMinutes"].is_null(),
+        || format!("série: {series}"),
+    )?;
+    let search = v2("/search?q=matrix".to_string()).await?;
+    expect(search["data"][0]["yearStart"].is_number(), || format!("busca: {search}"))
+}
+
+// teste de velocidade: bytes incompressíveis, sem compressão nem cache,
+// um por vez; a velocidade informada limita a resolução do `/play`
+#[tokio::test]
+async fn speedtest() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let resp = http
+        .get(format!("{api}/speedtest?bytes=100000"))
+        .header(header::ACCEPT_ENCODING, "gzip, br")
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let headers = resp.headers().clone();
+    let body = resp.bytes().await.map_err(|e| e.to_string())?;
+    let distinct = body.iter().collect::<std::collections::HashSet<_>>().len();
+    expect(
+        body.len() == 100_000
+            && headers.get(header::CONTENT_ENCODING).is_none()
+            && headers.get(header::CACHE_CONTROL).is_some_and(|v| v == "no-store")
+            && distinct > 200,
+        || format!("{} bytes, {distinct} valores distintos, {headers:?}", body.len()),
+    )?;
+
+    let held = http.get(format!("{api}/speedtest?bytes=50000000")).send().await.map_err(|e| e.to_string())?;
+    let second = http.get(format!("{api}/speedtest")).send().await.map_err(|e| e.to_string())?;
+    expect(held.status() == StatusCode::OK && second.status() == StatusCode::CONFLICT, || {
+        format!("com um teste em andamento: {}", second.status())
+    })?;
+    drop(held);
+
+    let report = http
+        .post(format!("{api}/speedtest/report"))
+        .json(&json!({ "mbps": 6.0 }))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let report: Value = report.json().await.map_err(|e| e.to_string())?;
+    expect(report["max_height"] == 720, || format!("report: {report}"))?;
+    let play = get_json(http, &format!("{api}/play/{RELEASES_IMDB_ID}?device=browser")).await?;
+    expect(
+        play["bandwidth"]["source"] == "speedtest"
+            && play["filename"].as_str().is_some_and(|f| f.contains("720p")),
+        || format!("play com 6 Mbps: {play}"),
+    )?;
+    let play = get_json(http, &format!("{api}/play/{RELEASES_IMDB_ID}?device=browser&bandwidth_mbps=50")).await?;
+    expect(
+        play["bandwidth"]["source"] == "param" && play["filename"].as_str().is_some_and(|f| !f.contains("720p")),
+        || format!("play com 50 Mbps: {play}"),
+    )
+}
+
+// `?debug=1` só vale com o token de admin
+#[tokio::test]
+async fn trending_debug_needs_admin() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let anonymous = get_json(http, &format!("{api}/movies/trending?debug=1")).await?;
+    expect(anonymous.get("debug").is_none(), || format!("debug sem admin: {anonymous}"))?;
+    let admin = admin_json(http, &format!("{api}/movies/trending?debug=1")).await?;
+    let stages: Vec<&str> = admin["debug"]["stages"].as_array().into_iter().flatten().filter_map(|s| s["stage"].as_str()).collect();
+    expect(stages == ["lists", "resolve", "enrich", "assemble"], || format!("{admin}"))
+}
+
+// `db migrate --dry-run` lista sem criar o banco; banco mais novo que o
+// binário é recusado, sem tocar nele
+#[tokio::test]
+async fn db_migrate_dry_run() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { work, .. } = &stack;
+    let fresh = work.join("migrate-dry-run.db");
+    let (ok, out) = db_command(work, &fresh, &["migrate", "--dry-run"]).await?;
+    expect(ok && out.contains("versão: 0") && out.contains("pendentes:") && out.contains("--dry-run"), || out.clone())?;
+    expect(!fresh.exists(), || "--dry-run criou o banco".into())?;
+
+    let (ok, out) = db_command(work, &fresh, &["migrate"]).await?;
+    expect(ok && out.contains("aplicadas"), || out.clone())?;
+    let (ok, out) = db_command(work, &fresh, &["migrate", "--dry-run"]).await?;
+    expect(ok && out.contains("nenhuma migração pendente"), || out.clone())?;
+
+    let newer = work.join("migrate-newer.db");
+    rusqlite::Connection::open(&newer)
+        .and_then(|conn| conn.pragma_update(None, "user_version", 999))
+        .map_err(|e| e.to_string())?;
+    for args in [&["migrate", "--dry-run"][..], &["migrate"][..]] {
+        let (ok, out) = db_command(work, &newer, args).await?;
+        expect(!ok && out.contains("mais nova"), || format!("{args:?}: {out}"))?;
+    }
+    let version: i64 = rusqlite::Connection::open(&newer)
+        .and_then(|conn| conn.pragma_query_value(None, "user_version", |row| row.get(0)))
+        .map_err(|e| e.to_string())?;
+    expect(version == 999, || format!("user_version mudou para {version}"))
+}
+
+#[tokio::test]
+async fn stream_range() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, sample, .. } = &stack;
+    let resp = http
+        .get(format!("{api}/stream?magnet={SAMPLE_HASH}&filename={SAMPLE_FILE}"))
+        .header(header::RANGE, "bytes=1000-1999")
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = resp.status();
+    expect(status == StatusCode::PARTIAL_CONTENT, || format!("status {status}, esperado 206"))?;
+    let bytes = resp.bytes().await.map_err(|e| e.to_string())?;
+    expect(bytes[..] == sample[1000..2000], || format!("{} bytes diferentes do arquivo", bytes.len()))
+}
+
+// `/stream?url=`: o `Range` vai ao upstream e o 206/200/416 volta como veio,
+// inclusive de um upstream que ignora o `Range`
+#[tokio::test]
+async fn stream_proxy_ranges() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, sample, .. } = &stack;
+    let file = sample.clone();
+    let ranged = move |headers: HeaderMap| {
+        let file = file.clone();
+        async move {
+            let total = file.len();
+            let range = headers.get(header::RANGE).and_then(|v| v.to_str().ok()).and_then(|r| r.strip_prefix("bytes="));
+            let Some((start, end)) = range.and_then(|r| r.split_once('-')) else {
+                return (StatusCode::OK, [(header::ACCEPT_RANGES, "bytes".to_string())], file).into_response();
+            };
+            let start: usize = start.parse().unwrap_or(0);
+            let end = end.parse::<usize>().map_or(total - 1, |e| e.min(total - 1));
+            if start >= total {
+                return (StatusCode::RANGE_NOT_SATISFIABLE, [(header::CONTENT_RANGE, format!("bytes */{total}"))]).into_response();
+            }
+            let content_range = format!("bytes {start}-{end}/{total}");
+            (StatusCode::PARTIAL_CONTENT, [(header::CONTENT_RANGE, content_range)], file[start..=end].to_vec()).into_response()
+        }
+    };
+    let file = sample.clone();
+    let upstream = serve(Router::new().route("/ranged", get(ranged)).route("/ignores", get(move || async move { file }))).await;
+
+    tokio::fs::write(work.join(".env"), "STREAM_PROXY_HOSTS=127.0.0.1\n").await.map_err(|e| e.to_string())?;
+    let resp = http.post(format!("{api}/admin/config/reload")).bearer_auth(ADMIN_TOKEN).send().await;
+    expect(resp.is_ok_and(|r| r.status().is_success()), || "recarga da configuração falhou".into())?;
+
+    let total = sample.len();
+    let last = total - 1;
+    let cases = [
+        ("ranged", Some("bytes=100-199"), StatusCode::PARTIAL_CONTENT, Some(format!("bytes 100-199/{total}")), &sample[100..200]),
+        ("ranged", Some("bytes=1000-"), StatusCode::PARTIAL_CONTENT, Some(format!("bytes 1000-{last}/{total}")), &sample[1000..]),
+        ("ranged", None, StatusCode::OK, None, &sample[..]),
+        ("ranged", Some("bytes=99999999-"), StatusCode::RANGE_NOT_SATISFIABLE, Some(format!("bytes */{total}")), &[][..]),
+        ("ignores", Some("bytes=100-199"), StatusCode::OK, None, &sample[..]),
+    ];
+    for (path, range, want, want_range, want_body) in cases {
+        let url = reqwest::Url::parse_with_params(&format!("{api}/stream"), [("url", format!("{upstream}/{path}"))])
+            .map_err(|e| e.to_string())?;
+        let mut req = http.get(url);
+        if let Some(range) = range {
+            req = req.header(header::RANGE, range);
+        }
+        let resp = req.send().await.map_err(|e| e.to_string())?;
+        let status = resp.status();
+        let content_range = resp.headers().get(header::CONTENT_RANGE).and_then(|v| v.to_str().ok()).map(String::from);
+        let body = resp.bytes().await.map_err(|e| e.to_string())?;
+        expect(status == want && content_range == want_range && body[..] == *want_body, || {
+            format!("{path} {range:?}: {status} {content_range:?} com {} bytes", body.len())
+        })?;
+    }
+
+    // fora da lista de hosts
+    let url = reqwest::Url::parse_with_params(&format!("{api}/stream"), [("url", upstream.replace("127.0.0.1", "localhost") + "/ranged")])
+        .map_err(|e| e.to_string())?;
+    let status = http.get(url).send().await.map_err(|e| e.to_string())?.status();
+    expect(status == StatusCode::BAD_REQUEST, || format!("host fora da lista: {status}"))
+}
+
+// `filename` com caminho é recusado antes de tocar no disco, no `/stream`
+// e no upload de `.torrent`
+#[tokio::test]
+async fn rejects_paths_and_internal_torrent_hosts() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, .. } = &stack;
+    let escape = work.join("fora");
+    let name = format!("../../fora/{SAMPLE_FILE}");
+    let url = reqwest::Url::parse_with_params(&format!("{api}/stream"), [("magnet", SAMPLE_HASH), ("filename", &name)])
+        .map_err(|e| e.to_string())?;
+    let stream = http.get(url).send().await.map_err(|e| e.to_string())?.status();
+    let torrent = b"d4:infod6:lengthi13e4:name9:Fora.mkv12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+    let boundary = "mock-boundary";
+    let body = [
+        format!("--{boundary}\r\nContent-Disposition: form-data; name=\"filename\"\r\n\r\n{name}\r\n").as_bytes(),
+        format!("--{boundary}\r\nContent-Disposition: form-data; name=\"torrent\"; filename=\"a.torrent\"\r\n\r\n").as_bytes(),
+        torrent,
+        format!("\r\n--{boundary}--\r\n").as_bytes(),
+    ]
+    .concat();
+    let upload = http
+        .post(format!("{api}/downloads/torrent"))
+        .header(CONTENT_TYPE, format!("multipart/form-data; boundary={boundary}"))
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?
+        .status();
+    // `.torrent` de um host interno (as fixtures estão no loopback)
+    let url = reqwest::Url::parse_with_params(&format!("{api}/stream"), [("magnet", format!("{api}/health")), ("filename", SAMPLE_FILE.into())])
+        .map_err(|e| e.to_string())?;
+    let internal = http.get(url).send().await.map_err(|e| e.to_string())?;
+    let internal = (internal.status(), internal.json::<Value>().await.unwrap_or_default());
+    expect(
+        internal.0 == StatusCode::BAD_REQUEST && internal.1["error"]["message"].as_str().is_some_and(|m| m.contains("não é público")),
+        || format!(".torrent interno: {internal:?}"),
+    )?;
+    expect(
+        stream == StatusCode::BAD_REQUEST && upload == StatusCode::BAD_REQUEST && !escape.exists(),
+        || format!("/stream {stream}, upload {upload}, {} existe: {}", escape.display(), escape.exists()),
+    )
+}
+
+// sem ADMIN_BIND_ADDR as rotas de admin dividem o listener público e
+// exigem o token
+#[tokio::test]
+async fn admin_on_public_listener_needs_token() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let anonymous = http.get(format!("{api}/admin/stats")).send().await.map_err(|e| e.to_string())?.status();
+    let wrong = http.get(format!("{api}/admin/stats")).bearer_auth("errado").send().await.map_err(|e| e.to_string())?.status();
+    admin_json(http, &format!("{api}/admin/stats")).await?;
+    expect(anonymous == StatusCode::UNAUTHORIZED && wrong == StatusCode::UNAUTHORIZED, || format!("sem token {anonymous}, errado {wrong}"))
+}
+
+// o trecho seguinte a um Range já foi lido adiante
+#[tokio::test]
+async fn stream_readahead() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    for range in ["bytes=1000-1999", "bytes=2000-2999"] {
+        let resp = http
+            .get(format!("{api}/stream?magnet={SAMPLE_HASH}&filename={SAMPLE_FILE}"))
+            .header(header::RANGE, range)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        resp.bytes().await.map_err(|e| e.to_string())?;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    let stats = http
+        .get(format!("{api}/admin/stats"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?
+        .json::<Value>()
+        .await
+        .map_err(|e| e.to_string())?;
+    let counters = &stats["readahead"];
+    expect(counters["warmed"].as_u64() >= Some(1) && counters["hits"] == 1, || format!("readahead = {counters}"))
+}
+
+// pedidos simultâneos no mesmo arquivo saem do mesmo descritor em
+// cache, cada um com os seus bytes
+#[tokio::test]
+async fn stream_shares_file_handles() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, sample, .. } = &stack;
+    let hits = || async {
+        let stats = http
+            .get(format!("{api}/admin/stats"))
+            .bearer_auth(ADMIN_TOKEN)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .json::<Value>()
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok::<_, String>(stats["caches"]["file_handles"]["hits"].as_u64().unwrap_or_default())
+    };
+    let fetch = |start: usize| async move {
+        let resp = http
+            .get(format!("{api}/stream?magnet={SAMPLE_HASH}&filename={SAMPLE_FILE}"))
+            .header(header::RANGE, format!("bytes={start}-{}", start + 9_999))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let etag = resp.headers().get(header::ETAG).and_then(|v| v.to_str().ok()).map(str::to_string);
+        let bytes = resp.bytes().await.map_err(|e| e.to_string())?;
+        expect(bytes[..] == sample[start..start + 10_000], || format!("bytes errados a partir de {start}"))?;
+        Ok::<_, String>(etag)
+    };
+    // o primeiro pedido abre o arquivo; os seguintes já o acham em cache
+    fetch(40_000).await?;
+    let before = hits().await?;
+    let etags = futures_util::future::try_join_all([0, 10_000, 20_000, 30_000].map(fetch)).await?;
+    expect(etags[0].is_some() && etags.iter().all(|e| *e == etags[0]), || format!("ETags: {etags:?}"))?;
+    let after = hits().await?;
+    expect(after >= before + 4, || format!("hits {before} -> {after}"))
+}
+
+// assistido pelo lado do servidor: pular pelo arquivo não conta; os
+// trechos encavalados que cobrem tudo (com o fim) contam uma vez
+#[tokio::test]
+async fn playback_completed_from_served_ranges() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let (imdb_id, ..) = MOVIES[0];
+    let fetch = |range: &'static str| async move {
+        let resp = http
+            .get(format!("{api}/stream?magnet={SAMPLE_HASH}&filename={SAMPLE_FILE}&imdb_id={imdb_id}&profile=watcher"))
+            .header(header::RANGE, range)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        resp.bytes().await.map_err(|e| e.to_string()).map(drop)
+    };
+    let watched = || async { get_json(http, &format!("{api}/watched?profile=watcher")).await };
+    for range in ["bytes=0-4095", "bytes=20000-24095", "bytes=60000-"] {
+        fetch(range).await?;
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let before = watched().await?;
+    expect(before["items"].as_array().is_some_and(Vec::is_empty), || format!("só com saltos: {before}"))?;
+    for range in ["bytes=0-40000", "bytes=30000-50000", "bytes=45000-"] {
+        fetch(range).await?;
+    }
+    for _ in 0..20 {
+        let after = watched().await?;
+        if after["items"][0]["imdb_id"] == imdb_id {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    Err(format!("não marcado como assistido: {}", watched().await?))
+}
+
+// dicas de título no /stream vão para o índice dos downloads
+#[tokio::test]
+async fn downloads_by_imdb_id() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    http.get(format!("{api}/stream?magnet={SAMPLE_HASH}&filename={SAMPLE_FILE}&imdb_id=tt0133093"))
+        .header(header::RANGE, "bytes=0-99")
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let listed = admin_json(http, &format!("{api}/downloads?imdb_id=tt0133093")).await?;
+    let ids: Vec<&str> = listed["downloads"].as_array().into_iter().flatten().filter_map(|d| d["id"].as_str()).collect();
+    expect(ids == [SAMPLE_HASH], || format!("downloads de tt0133093: {ids:?}"))?;
+    let status = admin_json(http, &format!("{api}/downloads/{SAMPLE_HASH}")).await?;
+    expect(status["title"]["imdb_id"] == "tt0133093", || format!("título: {}", status["title"]))
+}
+
+// crash antes do rename: o `.partial` ganha o nome final; depois dele: o
+// arquivo entra no índice; com `.aria2`, o `.partial` segue incompleto
+#[tokio::test]
+async fn recovers_downloads_after_crash() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, downloads, .. } = &stack;
+    let report = admin_json(http, &format!("{api}/admin/recovery")).await?;
+    let finalized: Vec<&str> = report["finalized"].as_array().into_iter().flatten().filter_map(|p| p.as_str()).collect();
+    for hash in [CRASH_BEFORE_RENAME_HASH, CRASH_AFTER_RENAME_HASH] {
+        let path = downloads.join(hash).join(CRASH_FILE);
+        expect(finalized.contains(&path.to_string_lossy().as_ref()), || format!("{hash} fora de finalized: {finalized:?}"))?;
+        let status = admin_json(http, &format!("{api}/downloads/{hash}")).await?;
+        expect(status["is_complete"] == true, || format!("{hash} incompleto: {status}"))?;
+    }
+    expect(!downloads.join(CRASH_BEFORE_RENAME_HASH).join(format!("{CRASH_FILE}.partial")).exists(), || {
+        ".partial não foi renomeado".into()
+    })?;
+    let interrupted = &report["interrupted"];
+    expect(interrupted == &json!([{ "id": INTERRUPTED_HASH, "filename": CRASH_FILE }]), || {
+        format!("interrompidos: {interrupted}")
+    })?;
+    let status = admin_json(http, &format!("{api}/downloads/{INTERRUPTED_HASH}")).await?;
+    expect(status["is_complete"] == false, || format!("{INTERRUPTED_HASH} completo: {status}"))?;
+    let export = admin_json(http, &format!("{api}/admin/export")).await?;
+    let indexed: Vec<&str> = export["downloads"].as_array().into_iter().flatten().filter_map(|d| d["path"].as_str()).collect();
+    for hash in [CRASH_BEFORE_RENAME_HASH, CRASH_AFTER_RENAME_HASH] {
+        let path = format!("{hash}/{CRASH_FILE}");
+        expect(indexed.contains(&path.as_str()), || format!("{path} fora do índice: {indexed:?}"))?;
+    }
+    // um `.partial` nunca é servido como arquivo baixado
+    let partial = format!("{CRASH_FILE}.partial");
+    let resp = http
+        .get(format!("{api}/media/thumbnail?filename={partial}&at=5"))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    expect(resp.status() == StatusCode::NOT_FOUND, || format!("miniatura de {partial}: {}", resp.status()))
+}
+
+// miniaturas iguais ao mesmo tempo dividem um só ffmpeg na fila de mídia:
+// os pedidos pegam carona no job em andamento (não no cache em disco), chaves
+// diferentes rodam cada uma o seu, e depois a miniatura sai do cache
+#[tokio::test]
+async fn thumbnails_share_one_ffmpeg() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, .. } = &stack;
+    let burst = |times: &[u32]| {
+        let mut tasks = tokio::task::JoinSet::new();
+        for at in times {
+            let (http, url) = (http.clone(), format!("{api}/media/thumbnail?filename={SAMPLE_FILE}&at={at}"));
+            tasks.spawn(async move {
+                let resp = http.get(url).send().await.map_err(|e| e.to_string())?;
+                let status = resp.status();
+                let kind = resp.headers().get(header::CONTENT_TYPE).cloned();
+                let body = resp.bytes().await.map_err(|e| e.to_string())?;
+                expect(status == StatusCode::OK && kind.is_some_and(|k| k == "image/jpeg"), || format!("{status}"))?;
+                Ok::<_, String>(body)
+            });
+        }
+        async move {
+            let mut bodies = Vec::new();
+            while let Some(done) = tasks.join_next().await {
+                bodies.push(done.map_err(|e| e.to_string())??);
+            }
+            expect(bodies.iter().all(|b| b.starts_with(b"\xff\xd8\xffmock-jpeg")), || "corpo diferente do ffmpeg".into())
+        }
+    };
+    let runs = || async {
+        let log = tokio::fs::read_to_string(work.join(FAKE_FFMPEG_LOG)).await.map_err(|e| e.to_string())?;
+        Ok::<_, String>(log.lines().count())
+    };
+    let jobs = || async { Ok::<_, String>(admin_json(http, &format!("{api}/admin/stats")).await?["media_jobs"].clone()) };
+
+    burst(&[5; THUMBNAIL_REQUESTS]).await?;
+    let (ran, stats) = (runs().await?, jobs().await?);
+    expect(
+        ran == 1 && stats["submitted"] == 1 && stats["completed"] == 1 && stats["coalesced"] == THUMBNAIL_REQUESTS - 1,
+        || format!("ffmpeg rodou {ran} vezes; media_jobs: {stats}"),
+    )?;
+
+    // metade num quadro, metade noutro: dois jobs, cada um com as suas caronas
+    let half = THUMBNAIL_REQUESTS / 2;
+    let mixed: Vec<u32> = (0..THUMBNAIL_REQUESTS).map(|i| if i < half { 6 } else { 7 }).collect();
+    burst(&mixed).await?;
+    let (ran, stats) = (runs().await?, jobs().await?);
+    expect(
+        ran == 3 && stats["submitted"] == 3 && stats["coalesced"] == 2 * (THUMBNAIL_REQUESTS - 1) - 1,
+        || format!("ffmpeg rodou {ran} vezes; media_jobs: {stats}"),
+    )?;
+
+    // já gerada: nem job novo nem ffmpeg
+    burst(&[5, 6, 7]).await?;
+    let (ran, stats) = (runs().await?, jobs().await?);
+    expect(ran == 3 && stats["submitted"] == 3, || format!("ffmpeg rodou {ran} vezes; media_jobs: {stats}"))
+}
+
+// sobrecarga: com SHED_MAX_IN_FLIGHT baixo, miniaturas lentas seguram
+// pedidos em andamento; a busca é recusada, o torrentio e o /stream não
+#[tokio::test]
+async fn load_shedding_by_priority() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, .. } = &stack;
+    let reload = |limit: usize| async move {
+        tokio::fs::write(work.join(".env"), format!("SHED_MAX_IN_FLIGHT={limit}\n")).await.map_err(|e| e.to_string())?;
+        let resp = http.post(format!("{api}/admin/config/reload")).bearer_auth(ADMIN_TOKEN).send().await;
+        expect(resp.is_ok_and(|r| r.status().is_success()), || "recarga da configuração falhou".into())
+    };
+    reload(SHED_IN_FLIGHT).await?;
+    let mut slow = tokio::task::JoinSet::new();
+    for at in 1..=SHED_IN_FLIGHT {
+        let (http, url) = (http.clone(), format!("{api}/media/thumbnail?filename={SAMPLE_FILE}&at={}", 100 + at));
+        slow.spawn(async move { http.get(url).send().await.map(|r| r.status()) });
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let status = |path: String| async move {
+        let resp = http.get(format!("{api}{path}")).header(header::RANGE, "bytes=0-99").send().await;
+        resp.map(|r| (r.status(), r.headers().contains_key(header::RETRY_AFTER))).map_err(|e| e.to_string())
+    };
+    let (search, retry_after) = status("/search?q=matrix".into()).await?;
+    expect(search == StatusCode::SERVICE_UNAVAILABLE && retry_after, || format!("/search: {search}, esperado 503 com Retry-After"))?;
+    let (torrentio, _) = status("/torrentio/movie/tt0133093".into()).await?;
+    expect(torrentio == StatusCode::OK, || format!("/torrentio: {torrentio}, esperado 200"))?;
+    let (stream, _) = status(format!("/stream?magnet={SAMPLE_HASH}&filename={SAMPLE_FILE}")).await?;
+    expect(stream == StatusCode::PARTIAL_CONTENT, || format!("/stream: {stream}, esperado 206"))?;
+    let (ready, _) = status("/health/ready".into()).await?;
+    expect(ready == StatusCode::SERVICE_UNAVAILABLE, || format!("/health/ready: {ready}, esperado 503"))?;
+
+    while let Some(done) = slow.join_next().await {
+        let status = done.map_err(|e| e.to_string())?.map_err(|e| e.to_string())?;
+        expect(status == StatusCode::OK, || format!("miniatura: {status}"))?;
+    }
+    let stats = admin_json(http, &format!("{api}/admin/stats")).await?;
+    expect(stats["load"]["shed_total"]["low"] == 1 && stats["load"]["shed_total"]["normal"] == 0, || {
+        format!("recusados: {}", stats["load"]["shed_total"])
+    })?;
+    reload(SHED_DEFAULT_IN_FLIGHT).await?;
+    let (ready, _) = status("/health/ready".into()).await?;
+    expect(ready == StatusCode::OK, || format!("/health/ready depois da carga: {ready}"))
+}
+
+// import duplicado e DELETE intercalados: nenhum registro perdido ou repetido
+#[tokio::test]
+async fn download_index_under_concurrency() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, downloads, .. } = &stack;
+    let mut tasks = tokio::task::JoinSet::new();
+    for i in 0..STRESS_DOWNLOADS {
+        let hash = format!("{i:040x}");
+        tokio::fs::create_dir_all(downloads.join(&hash)).await.map_err(|e| e.to_string())?;
+        tokio::fs::write(downloads.join(&hash).join("f.mkv"), b"x").await.map_err(|e| e.to_string())?;
+        let (http, api) = (http.clone(), api.to_string());
+        tasks.spawn(async move {
+            let entry = json!({ "info_hash": hash, "file_index": null, "path": format!("{hash}/f.mkv"), "size_bytes": 1, "stored_at": 0 });
+            let doc = json!({ "schema_version": 1, "downloads": [entry] });
+            for _ in 0..2 {
+                let resp = http.post(format!("{api}/admin/import")).bearer_auth(ADMIN_TOKEN).json(&doc).send().await;
+                expect(resp.is_ok_and(|r| r.status().is_success()), || format!("import de {hash} falhou"))?;
+            }
+            if i % 2 == 0 {
+                let resp = http.delete(format!("{api}/downloads/{hash}")).bearer_auth(ADMIN_TOKEN).send().await;
+                expect(resp.is_ok_and(|r| r.status().is_success()), || format!("DELETE de {hash} falhou"))?;
+            }
+            Ok::<_, String>(())
+        });
+    }
+    while let Some(done) = tasks.join_next().await {
+        done.map_err(|e| e.to_string())??;
+    }
+    let export = admin_json(http, &format!("{api}/admin/export")).await?;
+    let mut got: Vec<&str> = export["downloads"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|d| d["info_hash"].as_str())
+        .filter(|h| ![SAMPLE_HASH, CRASH_BEFORE_RENAME_HASH, CRASH_AFTER_RENAME_HASH].contains(h))
+        .collect();
+    got.sort();
+    let want: Vec<String> = (0..STRESS_DOWNLOADS).filter(|i| i % 2 == 1).map(|i| format!("{i:040x}")).collect();
+    expect(got == want, || format!("{} registros no índice, esperados {}", got.len(), want.len()))
+}
+
+// pasta vigiada: o magnet vira download, o .torrent quebrado vai para failed/
+#[tokio::test]
+async fn watch_dir() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { work, .. } = &stack;
+    let dir = work.join(WATCH_DIR);
+    tokio::fs::create_dir_all(&dir).await.map_err(|e| e.to_string())?;
+    let magnet = format!("# comentário\nmagnet:?xt=urn:btih:{WATCH_HASH}&dn=Inception.2010.1080p\n");
+    tokio::fs::write(dir.join("Inception tt1375666.magnet"), magnet).await.map_err(|e| e.to_string())?;
+    tokio::fs::write(dir.join("quebrado.torrent"), b"d4:infoi1e").await.map_err(|e| e.to_string())?;
+    let processed = dir.join("processed").join("Inception tt1375666.magnet");
+    let failed = dir.join("failed").join("quebrado.torrent.error.txt");
+    let give_up = tokio::time::Instant::now() + Duration::from_secs(10);
+    while !(processed.exists() && failed.exists()) {
+        expect(tokio::time::Instant::now() < give_up, || "arquivos não saíram da pasta vigiada".into())?;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    expect(!dir.join("quebrado.torrent").exists(), || ".torrent quebrado ficou na pasta".into())?;
+    let reason = tokio::fs::read_to_string(&failed).await.map_err(|e| e.to_string())?;
+    expect(!reason.trim().is_empty(), || "motivo vazio em failed/".into())
+}
+
+// pedidos simultâneos do mesmo torrent em download: um aria2c só, e cada
+// pedido responde conforme `wait` (o padrão continua esperando)
+#[tokio::test]
+async fn simultaneous_streams_share_download() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, .. } = &stack;
+    let stream = format!("{api}/stream?magnet={SLOW_DOWNLOAD_HASH}&filename=slow.mkv");
+    let first = tokio::spawn(http.get(&stream).send());
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let second = tokio::spawn(http.get(&stream).send());
+
+    let progress = http.get(format!("{stream}&wait=progress")).send().await.map_err(|e| e.to_string())?;
+    expect(progress.status() == StatusCode::ACCEPTED, || format!("wait=progress: {}", progress.status()))?;
+    let body: Value = progress.json().await.map_err(|e| e.to_string())?;
+    let status_url = format!("/downloads/{SLOW_DOWNLOAD_HASH}");
+    expect(body["joined"] == true && body["id"] == SLOW_DOWNLOAD_HASH && body["status_url"] == status_url.as_str(), || {
+        format!("wait=progress: {body}")
+    })?;
+
+    let no_redirects = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none()).build().map_err(|e| e.to_string())?;
+    let redirect = no_redirects.get(format!("{stream}&wait=redirect")).send().await.map_err(|e| e.to_string())?;
+    let location = redirect.headers().get("location").and_then(|v| v.to_str().ok()).unwrap_or_default().to_string();
+    expect(redirect.status() == StatusCode::SEE_OTHER && location == status_url, || {
+        format!("wait=redirect: {} {location}", redirect.status())
+    })?;
+    let downloading = get_json(http, &format!("{api}{location}")).await?;
+    expect(downloading["state"] == "downloading", || format!("{location}: {downloading}"))?;
+
+    for (name, request) in [("primeiro", first), ("segundo", second)] {
+        let resp = request.await.map_err(|e| e.to_string())?.map_err(|e| e.to_string())?;
+        let status = resp.status();
+        let body = resp.text().await.map_err(|e| e.to_string())?;
+        expect(status == StatusCode::OK && body == SLOW_DOWNLOAD_BODY, || format!("{name} pedido: {status} {body}"))?;
+    }
+    let log = tokio::fs::read_to_string(work.join(FAKE_ARIA2C_LOG)).await.map_err(|e| e.to_string())?;
+    let runs = log.lines().filter(|line| line.contains(SLOW_DOWNLOAD_HASH)).count();
+    expect(runs == 1, || format!("{runs} execuções do aria2c para o mesmo torrent"))
+}
+
+// progressive=1 num torrent ainda não baixado já começa o download, com
+// as peças em ordem, e serve o arquivo assim que ele existe
+#[tokio::test]
+async fn progressive_stream_starts_download() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, .. } = &stack;
+    let hash = FRESH_DOWNLOAD_HASHES[0];
+    let resp = http
+        .get(format!("{api}/stream?magnet={hash}&filename=progressive.mkv&progressive=1"))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = resp.status();
+    let body = resp.text().await.map_err(|e| e.to_string())?;
+    expect(status.is_success() && body == SLOW_DOWNLOAD_BODY, || format!("{status} {body}"))?;
+    let log = tokio::fs::read_to_string(work.join(FAKE_ARIA2C_LOG)).await.map_err(|e| e.to_string())?;
+    let run = log.lines().rfind(|line| line.contains(hash)).unwrap_or_default();
+    expect(run.contains("--stream-piece-selector=inorder"), || format!("aria2c sem ordem sequencial: {run}"))
+}
+
+// progresso de um download ativo, com os peers do resumo do aria2c, e
+// `complete` depois do fim
+#[tokio::test]
+async fn download_progress() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let hash = FRESH_DOWNLOAD_HASHES[1];
+    let stream = format!("{api}/stream?magnet={hash}&filename=progress.mkv&wait=progress");
+    let started = http.get(&stream).send().await.map_err(|e| e.to_string())?;
+    expect(started.status() == StatusCode::ACCEPTED, || format!("wait=progress: {}", started.status()))?;
+    let url = format!("{api}/downloads/{hash}/progress");
+    let mut last = Value::Null;
+    for _ in 0..15 {
+        last = get_json(http, &url).await?;
+        if last["peers"] == 3 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    expect(last["state"] == "downloading" && last["peers"] == 3 && last["seeders"] == 2, || format!("durante: {last}"))?;
+    for _ in 0..40 {
+        last = get_json(http, &url).await?;
+        if last["state"] == "complete" {
+            return expect(last["percent"] == 100.0, || format!("depois: {last}"));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    Err(format!("não terminou: {last}"))
+}
+
+// POST /downloads responde na hora com o job; o estado segue em
+// /downloads/:id até complete (ou failed, com o motivo)
+#[tokio::test]
+async fn download_jobs() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let create = |body: Value| async move {
+        let resp = http.post(format!("{api}/downloads")).json(&body).send().await.map_err(|e| e.to_string())?;
+        let status = resp.status();
+        let location = resp.headers().get(header::LOCATION).and_then(|v| v.to_str().ok()).unwrap_or_default().to_string();
+        let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+        expect(status == StatusCode::ACCEPTED && body["status_url"] == location.as_str(), || format!("{status} {body}"))?;
+        Ok::<_, String>(location)
+    };
+    let settle = |location: String| async move {
+        for _ in 0..50 {
+            let status = get_json(http, &format!("{api}{location}")).await?;
+            if status["state"] != "queued" && status["state"] != "downloading" {
+                return Ok(status);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        Err::<Value, _>(format!("{location} não terminou"))
+    };
+
+    let hash = FRESH_DOWNLOAD_HASHES[2];
+    let location = create(json!({ "magnet": format!("magnet:?xt=urn:btih:{hash}&dn=Job.2024.mkv") })).await?;
+    let started = get_json(http, &format!("{api}{location}")).await?;
+    expect(started["state"] == "queued" || started["state"] == "downloading", || format!("logo depois: {started}"))?;
+    let done = settle(location).await?;
+    let names: Vec<&str> = done["files"].as_array().into_iter().flatten().filter_map(|f| f["name"].as_str()).collect();
+    expect(done["state"] == "complete" && names == ["Job.2024.mkv"], || format!("ok: {done}"))?;
+
+    let location = create(json!({ "magnet": "d".repeat(40), "filename": "nada.mkv" })).await?;
+    let failed = settle(location).await?;
+    expect(failed["state"] == "failed" && failed["error"]["reason"] == "timeout", || format!("falha: {failed}"))?;
+
+    let resp = http.post(format!("{api}/downloads")).json(&json!({ "magnet": "d".repeat(40) })).send().await.map_err(|e| e.to_string())?;
+    expect(resp.status() == StatusCode::BAD_REQUEST, || format!("sem filename nem dn: {}", resp.status()))
+}
+
+// /ws/downloads: snapshot ao conectar e os eventos de um download novo
+// até o fim
+#[tokio::test]
+async fn downloads_websocket() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    use futures_util::StreamExt;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let url = format!("{}/ws/downloads", api.replacen("http", "ws", 1));
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await.map_err(|e| e.to_string())?;
+    let mut next = async || -> Result<Value, String> {
+        loop {
+            let frame = tokio::time::timeout(Duration::from_secs(10), socket.next())
+                .await
+                .map_err(|_| "sem eventos".to_string())?
+                .ok_or("WebSocket fechado")?
+                .map_err(|e| e.to_string())?;
+            if let Message::Text(text) = frame {
+                return serde_json::from_str(&text).map_err(|e| e.to_string());
+            }
+        }
+    };
+    let snapshot = next().await?;
+    expect(snapshot["type"] == "snapshot" && snapshot["downloads"].is_array(), || format!("ao conectar: {snapshot}"))?;
+
+    let hash = FRESH_DOWNLOAD_HASHES[3];
+    let body = json!({ "magnet": format!("magnet:?xt=urn:btih:{hash}&dn=Ws.2024.mkv") });
+    let resp = http.post(format!("{api}/downloads")).json(&body).send().await.map_err(|e| e.to_string())?;
+    expect(resp.status() == StatusCode::ACCEPTED, || format!("POST /downloads: {}", resp.status()))?;
+    let mut seen = Vec::new();
+    while seen.last().is_none_or(|t| t != "completed" && t != "failed") {
+        let event = next().await?;
+        if event["id"] == hash {
+            expect(event["type"] != "progress" || event["progress"]["bytes_done"].is_u64(), || format!("progresso: {event}"))?;
+            seen.push(event["type"].as_str().unwrap_or_default().to_string());
+        }
+    }
+    seen.dedup();
+    seen.retain(|t| t != "progress");
+    expect(seen == ["queued", "started", "completed"], || format!("eventos: {seen:?}"))
+}
+
+// escolha do episódio dentro do pack: numeração absoluta (com as
+// temporadas do TMDB), intervalos e especiais
+#[tokio::test]
+async fn episode_hint_in_packs() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let [kaiju, absolute, multi, specials] = EPISODE_PACKS.map(|(hash, _)| hash);
+    let cases: [(&str, &str, bool, Option<&str>); 7] = [
+        (kaiju, "S01E05", false, Some("[Grp] Kaiju - 05 [1080p].mkv")),
+        (kaiju, "E04", false, Some("[Grp] Kaiju - 04 [1080p].mkv")),
+        (absolute, "S02E05", true, Some("Saga.E17.1080p.mkv")),
+        // sem a série não há como saber onde começa a segunda temporada
+        (absolute, "S02E05", false, None),
+        (multi, "S01E02", false, Some("Saga.S01E01-E03.1080p.mkv")),
+        (specials, "S00E01", true, Some("Saga.S00E01.Special.mkv")),
+        (specials, "S01E01", true, Some("Saga - 01.mkv")),
+    ];
+    for (hash, hint, with_series, want) in cases {
+        let mut url = format!("{api}/stream?magnet={hash}&filename=pack.mkv&episode_hint={hint}");
+        if with_series {
+            url.push_str(&format!("&imdb_id={SERIES_IMDB_ID}"));
+        }
+        let resp = http.get(&url).send().await.map_err(|e| e.to_string())?;
+        let status = resp.status();
+        let offset = resp.headers().get("x-start-offset-hint-seconds").and_then(|v| v.to_str().ok()).map(str::to_string);
+        let body = resp.text().await.map_err(|e| e.to_string())?;
+        match want {
+            Some(file) => expect(status == StatusCode::OK && body == file, || format!("{hint}: {status} {body}"))?,
+            // nada no pack casa: vai ao aria2c (o falso não conhece o torrent)
+            None => expect(status == StatusCode::BAD_GATEWAY, || format!("{hint} sem a série: {status} {body}"))?,
+        }
+        // S01E02 é o segundo de três: começa em um terço do arquivo
+        let want_offset = (hash == multi).then(|| (MULTI_EPISODE_SECS / 3).to_string());
+        expect(offset == want_offset, || format!("{hint}: início {offset:?}, esperado {want_offset:?}"))?;
+    }
+    Ok(())
+}
+
+// o código de saída do aria2c vira um motivo legível
+#[tokio::test]
+async fn aria2c_failure_reason() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let hash = "9".repeat(40);
+    let resp = http
+        .get(format!("{api}/stream?magnet={hash}&filename=nada.mkv"))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = resp.status();
+    let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+    let error = &body["error"];
+    expect(
+        status == StatusCode::BAD_GATEWAY && error["exit_code"] == 2 && error["reason"] == "timeout",
+        || format!("{status} {body}"),
+    )
+}
+
+// corpo de erro padrão do `/stream`: episódio que não está no download e
+// download que falhou (o panic fica no teste de `middleware::catch_panic`)
+#[tokio::test]
+async fn stream_error_bodies() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    let failed = "9".repeat(40);
+    for (query, want, code) in [
+        (format!("magnet={SLOW_DOWNLOAD_HASH}&filename=Saga.S01E01.mkv&episode_hint=S03E07"), StatusCode::NOT_FOUND, "not_found"),
+        (format!("magnet={failed}&filename=nada.mkv"), StatusCode::BAD_GATEWAY, "download_failed"),
+    ] {
+        let resp = http.get(format!("{api}/stream?{query}")).send().await.map_err(|e| e.to_string())?;
+        let status = resp.status();
+        let request_id = resp.headers().get("x-request-id").and_then(|v| v.to_str().ok()).map(String::from);
+        let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+        let error = &body["error"];
+        expect(
+            status == want
+                && error["code"] == code
+                && error["message"].is_string()
+                && error["retryable"].is_boolean()
+                && error["request_id"].as_str() == request_id.as_deref(),
+            || format!("{query}: {status} {body}"),
+        )?;
+        let extra = match code {
+            "not_found" => error["available_files"][0]["filename"] == "Saga.S01E01.mkv",
+            _ => error["exit_code"] == 2 && error["stderr_excerpt"] == "torrent desconhecido: sem peers",
+        };
+        expect(extra, || format!("{query}: {body}"))?;
+    }
+    Ok(())
+}
+
+// HLS transcodificado: o ffprobe falso não conhece o arquivo, então
+// tudo é reencodado; a playlist fechada não roda o ffmpeg de novo
+#[tokio::test]
+async fn hls_transcoding() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, .. } = &stack;
+    let master_url = format!("{api}/stream/hls/{SAMPLE_HASH}/master.m3u8");
+    let mut variant = String::new();
+    for _ in 0..2 {
+        let resp = http.get(&master_url).send().await.map_err(|e| e.to_string())?;
+        let status = resp.status();
+        let master = resp.text().await.map_err(|e| e.to_string())?;
+        expect(status == StatusCode::OK && master.contains("#EXT-X-STREAM-INF"), || format!("{status} {master}"))?;
+        variant = master.lines().last().unwrap_or_default().to_string();
+    }
+    let base = format!("{api}/stream/hls/{SAMPLE_HASH}/{}", variant.trim_end_matches("index.m3u8"));
+    let resp = http.get(format!("{base}index.m3u8")).send().await.map_err(|e| e.to_string())?;
+    let status = resp.status();
+    let playlist = resp.text().await.map_err(|e| e.to_string())?;
+    expect(status == StatusCode::OK && playlist.contains("seg00000.ts"), || format!("{variant}: {status} {playlist}"))?;
+    let resp = http.get(format!("{base}seg00000.ts")).send().await.map_err(|e| e.to_string())?;
+    let kind = resp.headers().get(header::CONTENT_TYPE).cloned();
+    let body = resp.bytes().await.map_err(|e| e.to_string())?;
+    expect(kind.is_some_and(|k| k == "video/mp2t") && body == FAKE_SEGMENT.as_bytes(), || format!("segmento: {body:?}"))?;
+    let resp = http.get(format!("{base}seg00001.ts")).send().await.map_err(|e| e.to_string())?;
+    expect(resp.status() == StatusCode::NOT_FOUND, || format!("segmento inexistente: {}", resp.status()))?;
+    let resp = http.get(format!("{master_url}?filename=..")).send().await.map_err(|e| e.to_string())?;
+    expect(resp.status() == StatusCode::BAD_REQUEST, || format!("filename=..: {}", resp.status()))?;
+    let log = tokio::fs::read_to_string(work.join(FAKE_HLS_LOG)).await.map_err(|e| e.to_string())?;
+    expect(log.lines().count() == 1 && log.contains("libx264"), || format!("ffmpeg do HLS: {log}"))
+}
+
+// Content-Type pelos bytes iniciais, depois pela extensão; o contêiner
+// detectado aparece em /media/:id/info mesmo sem o ffprobe
+#[tokio::test]
+async fn content_type_and_media_info() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    for (name, expected) in MIME_FILES {
+        for range in [None, Some("bytes=0-99")] {
+            let mut req = http.get(format!("{api}/stream?magnet={MIME_HASH}&filename={name}"));
+            if let Some(range) = range {
+                req = req.header(header::RANGE, range);
+            }
+            let resp = req.send().await.map_err(|e| e.to_string())?;
+            let kind = resp.headers().get(header::CONTENT_TYPE).cloned();
+            expect(kind.as_ref().is_some_and(|k| k == expected), || format!("{name} ({range:?}): {kind:?}"))?;
+        }
+    }
+    let info = get_json(http, &format!("{api}/media/{MIME_HASH}/info")).await?;
+    expect(
+        info["filename"] == MIME_FILES[0].0
+            && info["content_type"] == "video/x-matroska"
+            && info["detected_by"] == "magic"
+            && info["container"] == "mkv"
+            && info["probe_error"].is_string(),
+        || format!("{info}"),
+    )?;
+    let info = get_json(http, &format!("{api}/media/{MIME_HASH}/info?filename={}", MIME_FILES[2].0)).await?;
+    expect(info["detected_by"] == "extension" && info["container"].is_null(), || format!("{info}"))
+}
+
+// legenda mais baixada no idioma, em WebVTT; a segunda vem do cache
+#[tokio::test]
+async fn subtitles_webvtt() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, subtitle_downloads, .. } = &stack;
+    let url = format!("{api}/subtitles/{}?lang=pt-BR", MOVIES[0].0);
+    for expected in ["MISS", "HIT"] {
+        let resp = http.get(&url).send().await.map_err(|e| e.to_string())?;
+        let status = resp.status();
+        let kind = resp.headers().get(header::CONTENT_TYPE).cloned();
+        let cache = resp.headers().get("x-cache").cloned();
+        let vtt = resp.text().await.map_err(|e| e.to_string())?;
+        expect(
+            status == StatusCode::OK
+                && kind.is_some_and(|k| k == "text/vtt; charset=utf-8")
+                && cache.is_some_and(|c| c == expected)
+                && vtt.starts_with("WEBVTT\n")
+                && vtt.contains("00:00:01.000 --> 00:00:02.500\nOlá mundo\n"),
+            || format!("{expected}: {status} {vtt:?}"),
+        )?;
+    }
+    let downloads = subtitle_downloads.load(Ordering::SeqCst);
+    expect(downloads == 1, || format!("{downloads} downloads no OpenSubtitles"))?;
+    let resp = http.get(format!("{api}/subtitles/{NO_SUBTITLES_IMDB_ID}?lang=pt-BR")).send().await.map_err(|e| e.to_string())?;
+    expect(resp.status() == StatusCode::NOT_FOUND, || format!("sem legendas: {}", resp.status()))?;
+    let resp = http.get(format!("{api}/subtitles/{}?lang=português", MOVIES[0].0)).send().await.map_err(|e| e.to_string())?;
+    expect(resp.status() == StatusCode::BAD_REQUEST, || format!("lang inválido: {}", resp.status()))
+}
+
+// AUTH_MODE=proxy_headers: o usuário do proxy (a própria máquina, que
+// está em TRUSTED_PROXIES) ganha perfil, e o grupo decide o admin
+#[tokio::test]
+async fn auth_mode_proxy_headers() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, .. } = &stack;
+    let mode = |mode: &'static str| async move {
+        tokio::fs::write(work.join(".env"), format!("AUTH_MODE={mode}\n")).await.map_err(|e| e.to_string())?;
+        let resp = http.post(format!("{api}/admin/config/reload")).bearer_auth(ADMIN_TOKEN).send().await;
+        expect(resp.is_ok_and(|r| r.status().is_success()), || "recarga da configuração falhou".into())
+    };
+    let as_user = |method: reqwest::Method, path: &str, groups: &str| {
+        http.request(method, format!("{api}{path}")).header("Remote-User", PROXY_USER).header("Remote-Groups", groups)
+    };
+    mode("proxy_headers").await?;
+    let added = as_user(reqwest::Method::PUT, "/watchlist/tt0133093", "users").send().await.map_err(|e| e.to_string())?;
+    expect(added.status() == StatusCode::NO_CONTENT, || format!("PUT /watchlist: {}", added.status()))?;
+    let mine: Value = as_user(reqwest::Method::GET, "/watchlist", "users")
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+    expect(mine["profile"] == PROXY_USER && mine["items"][0]["imdb_id"] == "tt0133093", || format!("lista: {mine}"))?;
+    let other = as_user(reqwest::Method::GET, "/watchlist?profile=outro", "users").send().await.map_err(|e| e.to_string())?;
+    expect(other.status() == StatusCode::FORBIDDEN, || format!("perfil alheio: {}", other.status()))?;
+
+    let denied = as_user(reqwest::Method::GET, "/admin/stats", "users").send().await.map_err(|e| e.to_string())?;
+    expect(denied.status() == StatusCode::FORBIDDEN, || format!("/admin sem o grupo: {}", denied.status()))?;
+    let allowed = as_user(reqwest::Method::GET, "/admin/stats", "users, admins").send().await.map_err(|e| e.to_string())?;
+    expect(allowed.status() == StatusCode::OK, || format!("/admin com o grupo: {}", allowed.status()))?;
+
+    let profiles = admin_json(http, &format!("{api}/admin/profiles")).await?;
+    expect(profiles["profiles"][0]["name"] == PROXY_USER, || format!("perfis: {profiles}"))?;
+    let audit = admin_json(http, &format!("{api}/admin/audit?limit=10")).await?;
+    let denied_entry = audit["entries"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|e| e["path"] == "/admin/stats" && e["status"] == 403);
+    expect(denied_entry.is_some_and(|e| e["user"] == PROXY_USER && e["admin"] == false), || {
+        format!("auditoria sem o pedido negado: {audit}")
+    })?;
+    mode("token").await?;
+    // de volta ao modo token, o cabeçalho não diz mais nada
+    let plain = as_user(reqwest::Method::GET, "/watchlist", "admins").send().await.map_err(|e| e.to_string())?;
+    let plain: Value = plain.json().await.map_err(|e| e.to_string())?;
+    expect(plain["profile"] == "", || format!("lista no modo token: {plain}"))
+}
+
+// estatísticas locais: desligadas por padrão; ligadas na recarga, cada
+// busca, reprodução, falha e resposta do cache entra em /admin/insights
+#[tokio::test]
+async fn insights() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, .. } = &stack;
+    let reload = |env: &'static str| async move {
+        tokio::fs::write(work.join(".env"), env).await.map_err(|e| e.to_string())?;
+        let resp = http.post(format!("{api}/admin/config/reload")).bearer_auth(ADMIN_TOKEN).send().await;
+        expect(resp.is_ok_and(|r| r.status().is_success()), || "recarga da configuração falhou".into())
+    };
+    let before = admin_json(http, &format!("{api}/admin/insights")).await?;
+    expect(before["enabled"] == false && before["searches"] == 0, || format!("desligado: {before}"))?;
+
+    reload("STATS_ENABLED=on\n").await?;
+    get_json(http, &format!("{api}/search?q=revolutions&page=3")).await?;
+    get_json(http, &format!("{api}/search?q=revolutions&page=3")).await?;
+    let stream = format!("{api}/stream?magnet={SAMPLE_HASH}&filename={SAMPLE_FILE}&imdb_id={RELEASES_IMDB_ID}");
+    http.get(&stream).header(header::RANGE, "bytes=0-99").send().await.map_err(|e| e.to_string())?;
+    http.get(&stream).header(header::RANGE, "bytes=100-199").send().await.map_err(|e| e.to_string())?;
+    http.get(format!("{api}/stream?magnet=nada")).send().await.map_err(|e| e.to_string())?;
+
+    // a gravação é em segundo plano: espera os eventos chegarem ao banco
+    let give_up = tokio::time::Instant::now() + Duration::from_secs(5);
+    let body = loop {
+        let body = admin_json(http, &format!("{api}/admin/insights?days=1")).await?;
+        if body["plays"] == 1 && body["failures"]["total"] == 1 && body["searches"] == 2 {
+            break body;
+        }
+        expect(tokio::time::Instant::now() < give_up, || format!("eventos não chegaram: {body}"))?;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    };
+    expect(body["top_titles"][0]["imdb_id"] == RELEASES_IMDB_ID && body["top_titles"][0]["plays"] == 1, || format!("top: {body}"))?;
+    expect(body["plays_by_hour_utc"].as_array().is_some_and(|h| h.len() == 24), || format!("por hora: {body}"))?;
+    expect(body["failures"]["by_cause"][0]["cause"] == "bad_request" && body["failures"]["rate"] == 0.5, || {
+        format!("falhas: {}", body["failures"])
+    })?;
+    expect(body["cache"]["hits"].as_u64() >= Some(1) && body["cache"]["misses"].as_u64() >= Some(1), || format!("cache: {}", body["cache"]))?;
+    let bad = http.get(format!("{api}/admin/insights?days=0")).bearer_auth(ADMIN_TOKEN).send().await.map_err(|e| e.to_string())?;
+    expect(bad.status() == StatusCode::BAD_REQUEST, || format!("days=0: {}", bad.status()))?;
+    reload("").await
+}
+
+// limpeza do armazenamento: primeiro o ocioso; depois, com o teto logo
+// abaixo do uso sem a lixeira, toda a lixeira (um download apagado antes) e
+// o download de acesso mais antigo
+#[tokio::test]
+async fn storage_cleanup() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, downloads, .. } = &stack;
+    let reload = |env: String| async move {
+        tokio::fs::write(work.join(".env"), env).await.map_err(|e| e.to_string())?;
+        let resp = http.post(format!("{api}/admin/config/reload")).bearer_auth(ADMIN_TOKEN).send().await;
+        expect(resp.is_ok_and(|r| r.status().is_success()), || "recarga da configuração falhou".into())
+    };
+    let cleanup = || async {
+        let resp = http.post(format!("{api}/admin/storage/cleanup")).bearer_auth(ADMIN_TOKEN).send().await.map_err(|e| e.to_string())?;
+        resp.json::<Value>().await.map_err(|e| e.to_string())
+    };
+    let removed = |report: &Value| -> Vec<(String, String)> {
+        let pairs = report["removed"].as_array().into_iter().flatten();
+        pairs.map(|r| (r["reason"].as_str().unwrap_or_default().into(), r["id"].as_str().unwrap_or_default().into())).collect()
+    };
+    let resp = http.delete(format!("{api}/downloads/{CRASH_AFTER_RENAME_HASH}")).bearer_auth(ADMIN_TOKEN).send().await;
+    expect(resp.is_ok_and(|r| r.status().is_success()), || "DELETE para a lixeira falhou".into())?;
+
+    let report = cleanup().await?;
+    expect(report["removed"] == json!([]), || format!("sem limites: {report}"))?;
+
+    reload("DOWNLOADS_MAX_IDLE_DAYS=7\n".into()).await?;
+    let report = cleanup().await?;
+    expect(removed(&report) == [("idle".into(), IDLE_HASH.into())], || format!("ociosos: {report}"))?;
+    expect(!downloads.join(IDLE_HASH).exists() && downloads.join(QUOTA_HASH).exists(), || "pastas erradas".into())?;
+
+    let status = admin_json(http, &format!("{api}/admin/storage")).await?;
+    let (used, trash) = (status["used_bytes"].as_u64().unwrap_or_default(), status["trash_bytes"].as_u64().unwrap_or_default());
+    expect(trash > 0 && status["metrics"]["reclaimed_bytes"]["idle"] == STORAGE_FILE_BYTES, || format!("status: {status}"))?;
+    reload(format!("DOWNLOADS_MAX_IDLE_DAYS=7\nDOWNLOADS_MAX_BYTES={}\n", used - trash - 1)).await?;
+    let report = cleanup().await?;
+    let removed = removed(&report);
+    let quota: Vec<_> = removed.iter().filter(|(reason, _)| reason != "trash").collect();
+    expect(quota == [&("quota".to_string(), QUOTA_HASH.to_string())] && report["over_quota"] == false, || format!("teto: {report}"))?;
+    let status = admin_json(http, &format!("{api}/admin/storage")).await?;
+    expect(status["trash_bytes"] == 0 && status["metrics"]["runs"] == 3, || format!("depois: {status}"))?;
+    reload(String::new()).await
+}
+
+// TTL por tipo de entrada: com `CACHE_TTL_SEARCH=0` a busca não fica em
+// cache, e o detalhe continua com o seu
+#[tokio::test]
+async fn cache_ttl_per_kind() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, .. } = &stack;
+    let reload = |env: &'static str| async move {
+        tokio::fs::write(work.join(".env"), env).await.map_err(|e| e.to_string())?;
+        let resp = http.post(format!("{api}/admin/config/reload")).bearer_auth(ADMIN_TOKEN).send().await;
+        expect(resp.is_ok_and(|r| r.status().is_success()), || "recarga da configuração falhou".into())
+    };
+    let x_cache = |url: String| async move {
+        let resp = http.get(url).send().await.map_err(|e| e.to_string())?;
+        Ok::<_, String>(resp.headers().get("x-cache").and_then(|v| v.to_str().ok()).unwrap_or_default().to_string())
+    };
+    reload("CACHE_TTL_SEARCH=0\n").await?;
+    let config = admin_json(http, &format!("{api}/admin/config")).await?;
+    expect(config["config"]["cache"]["ttl_secs"]["search"] == 0, || format!("{}", config["config"]["cache"]))?;
+    x_cache(format!("{api}/movie/{}", MOVIES[1].0)).await?;
+    let mut seen = Vec::new();
+    for url in [format!("{api}/search?q=revolutions"), format!("{api}/search?q=revolutions"), format!("{api}/movie/{}", MOVIES[1].0)] {
+        seen.push(x_cache(url).await?);
+    }
+    expect(seen == ["MISS", "MISS", "HIT"], || format!("{seen:?}"))?;
+    reload("").await
+}
+
+// `X-Cache`/`Age` ao longo da vida de uma entrada de TTL curto: miss que
+// grava, hits envelhecendo e miss de novo depois do vencimento
+#[tokio::test]
+async fn cache_headers_through_expiry() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, .. } = &stack;
+    tokio::fs::write(work.join(".env"), "CACHE_TTL_SEARCH=3\n").await.map_err(|e| e.to_string())?;
+    let resp = http.post(format!("{api}/admin/config/reload")).bearer_auth(ADMIN_TOKEN).send().await;
+    expect(resp.is_ok_and(|r| r.status().is_success()), || "recarga da configuração falhou".into())?;
+    let headers = || async {
+        let resp = http.get(format!("{api}/search?q=reloaded")).send().await.map_err(|e| e.to_string())?;
+        let header = |name: &str| resp.headers().get(name).and_then(|v| v.to_str().ok()).unwrap_or_default().to_string();
+        Ok::<_, String>((header("x-cache"), header("age")))
+    };
+    let mut seen = vec![headers().await?, headers().await?];
+    // com a variação de ±15%, a entrada vive de 2,55 s a 3,45 s (e pode ser
+    // renovada antes nos últimos 10%)
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    seen.push(headers().await?);
+    tokio::time::sleep(Duration::from_millis(2400)).await;
+    seen.push(headers().await?);
+    seen.push(headers().await?);
+    let want = [("MISS", "0"), ("HIT", "0"), ("HIT", "1"), ("MISS", "0"), ("HIT", "0")];
+    expect(seen.iter().map(|(c, a)| (c.as_str(), a.as_str())).eq(want), || format!("{seen:?}, esperado {want:?}"))
+}
+
+// `Cache-Control: no-cache` e `?refresh=1`: ignorados para anônimos,
+// buscam de novo e regravam para o admin ou com `ALLOW_CACHE_BYPASS`
+#[tokio::test]
+async fn cache_bypass() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, .. } = &stack;
+    let calls = || async {
+        let usage = admin_json(http, &format!("{api}/admin/upstream-usage")).await?;
+        Ok::<_, String>(usage["today"]["endpoints"]["omdb:search"].as_u64().unwrap_or(0))
+    };
+    let search = |query: &'static str, no_cache: bool, admin: bool| async move {
+        let mut req = http.get(format!("{api}/search?q=revolutions{query}"));
+        if no_cache {
+            req = req.header(header::CACHE_CONTROL, "no-cache");
+        }
+        if admin {
+            req = req.bearer_auth(ADMIN_TOKEN);
+        }
+        let resp = req.send().await.map_err(|e| e.to_string())?;
+        expect(resp.status().is_success(), || format!("busca: {}", resp.status()))?;
+        Ok::<_, String>(resp.headers().get("x-cache").and_then(|v| v.to_str().ok()).unwrap_or_default().to_string())
+    };
+
+    let mut seen = vec![search("", false, false).await?];
+    // anônimo: o pedido de bypass é ignorado
+    seen.push(search("", true, false).await?);
+    seen.push(search("&refresh=1", false, false).await?);
+    expect(seen == ["MISS", "HIT", "HIT"] && calls().await? == 1, || format!("anônimo: {seen:?}"))?;
+
+    // admin: busca de novo e grava o resultado novo
+    seen = vec![search("", true, true).await?, search("&refresh=true", false, true).await?, search("", false, false).await?];
+    expect(seen == ["MISS", "MISS", "HIT"] && calls().await? == 3, || format!("admin: {seen:?}"))?;
+
+    // com ALLOW_CACHE_BYPASS, qualquer cliente
+    tokio::fs::write(work.join(".env"), "ALLOW_CACHE_BYPASS=true\n").await.map_err(|e| e.to_string())?;
+    let resp = http.post(format!("{api}/admin/config/reload")).bearer_auth(ADMIN_TOKEN).send().await;
+    expect(resp.is_ok_and(|r| r.status().is_success()), || "recarga da configuração falhou".into())?;
+    seen = vec![search("&refresh=1", false, false).await?, search("", false, false).await?];
+    expect(seen == ["MISS", "HIT"] && calls().await? == 4, || format!("ALLOW_CACHE_BYPASS: {seen:?}"))
+}
+
+// limite por cliente: um download novo por hora e rajada de 4 pedidos
+// (quase sem reposição); o admin e o /health passam
+#[tokio::test]
+async fn rate_limits() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, .. } = &stack;
+    let reload = |env: &'static str| async move {
+        tokio::fs::write(work.join(".env"), env).await.map_err(|e| e.to_string())?;
+        let resp = http.post(format!("{api}/admin/config/reload")).bearer_auth(ADMIN_TOKEN).send().await;
+        expect(resp.is_ok_and(|r| r.status().is_success()), || "recarga da configuração falhou".into())
+    };
+    reload("RATE_LIMIT_PER_MINUTE=1\nRATE_LIMIT_BURST=4\nRATE_LIMIT_DOWNLOADS_PER_HOUR=1\n").await?;
+    let result = async {
+        let mut statuses = Vec::new();
+        for hash in [RATE_LIMIT_HASHES[0], RATE_LIMIT_HASHES[1]] {
+            let resp = http.post(format!("{api}/downloads")).json(&json!({ "magnet": hash, "filename": "limite.mkv" })).send().await;
+            statuses.push(resp.map_err(|e| e.to_string())?.status().as_u16());
+        }
+        expect(statuses == [202, 429], || format!("downloads: {statuses:?}"))?;
+        for _ in 0..2 {
+            statuses.push(http.get(format!("{api}/attribution")).send().await.map_err(|e| e.to_string())?.status().as_u16());
+        }
+        let resp = http.get(format!("{api}/attribution")).send().await.map_err(|e| e.to_string())?;
+        let retry_after = resp.headers().get(header::RETRY_AFTER).and_then(|v| v.to_str().ok()?.parse::<u64>().ok());
+        let status = resp.status();
+        let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+        expect(
+            statuses == [202, 429, 200, 200]
+                && status == StatusCode::TOO_MANY_REQUESTS
+                && body["error"]["code"] == "rate_limited"
+                && retry_after.is_some_and(|s| s > 1),
+            || format!("{statuses:?} {status} {retry_after:?} {body}"),
+        )?;
+        let admin = http.get(format!("{api}/attribution")).bearer_auth(ADMIN_TOKEN).send().await.map_err(|e| e.to_string())?;
+        let health = http.get(format!("{api}/health")).send().await.map_err(|e| e.to_string())?;
+        expect(admin.status().is_success() && health.status().is_success(), || format!("admin {} health {}", admin.status(), health.status()))?;
+        let stats = admin_json(http, &format!("{api}/admin/stats")).await?;
+        let limit = &stats["rate_limit"];
+        expect(limit["rejected_requests"] == 1 && limit["rejected_downloads"] == 1, || format!("{limit}"))
+    }
+    .await;
+    reload("RATE_LIMIT_PER_MINUTE=0\nRATE_LIMIT_DOWNLOADS_PER_HOUR=0\n").await?;
+    result
+}
+
+// com API_KEYS, sem chave (ou com uma errada) é 401; a chave só vale no
+// cabeçalho, um `sig` solto não abre nada, e o /health e o admin
+// continuam abertos
+#[tokio::test]
+async fn api_keys() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, .. } = &stack;
+    let reload = |env: &'static str| async move {
+        tokio::fs::write(work.join(".env"), env).await.map_err(|e| e.to_string())?;
+        let resp = http.post(format!("{api}/admin/config/reload")).bearer_auth(ADMIN_TOKEN).send().await;
+        expect(resp.is_ok_and(|r| r.status().is_success()), || "recarga da configuração falhou".into())
+    };
+    reload(MOCK_API_KEY_ENV).await?;
+    let result = async {
+        let status = |req: reqwest::RequestBuilder| async move {
+            let resp = req.send().await.map_err(|e| e.to_string())?;
+            let status = resp.status().as_u16();
+            let body: Value = resp.json().await.unwrap_or_default();
+            Ok::<_, String>((status, body["error"]["message"].as_str().unwrap_or_default().to_string()))
+        };
+        let attribution = format!("{api}/attribution");
+        let missing = status(http.get(&attribution)).await?;
+        let wrong = status(http.get(&attribution).header("X-Api-Key", "chave-errada-mas-comprida")).await?;
+        let header = status(http.get(&attribution).header("X-Api-Key", MOCK_API_KEY)).await?;
+        let query = status(http.get(format!("{attribution}?api_key={MOCK_API_KEY}"))).await?;
+        let unsigned = status(http.get(format!("{api}/search?q=matrix&sig=1&exp=1"))).await?;
+        let health = status(http.get(format!("{api}/health"))).await?;
+        let admin = status(http.get(&attribution).bearer_auth(ADMIN_TOKEN)).await?;
+        let sign = http
+            .post(format!("{api}/stream/sign"))
+            .bearer_auth(ADMIN_TOKEN)
+            .json(&json!({ "magnet": SAMPLE_HASH, "filename": SAMPLE_FILE }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let url = sign.json::<Value>().await.map_err(|e| e.to_string())?["url"].as_str().unwrap_or_default().to_string();
+        let signed = http.get(format!("{api}{url}")).send().await.map_err(|e| e.to_string())?.status().as_u16();
+        let tampered = http.get(format!("{api}{}", url.replace(SAMPLE_FILE, "outro.mp4"))).send().await.map_err(|e| e.to_string())?.status().as_u16();
+        expect(signed == 200 && tampered == 401, || format!("/stream assinado {signed}, adulterado {tampered}"))?;
+        expect(
+            [missing.0, query.0, unsigned.0] == [401; 3]
+                && wrong == (401, "chave de API inválida".to_string())
+                && [header.0, health.0, admin.0] == [200; 3],
+            || format!("sem {missing:?} errada {wrong:?} cabeçalho {header:?} query {query:?} sig {unsigned:?} health {health:?} admin {admin:?}"),
+        )?;
+        // com `none` todo pedido seria admin e passaria sem chave
+        tokio::fs::write(work.join(".env"), format!("{MOCK_API_KEY_ENV}AUTH_MODE=none\n")).await.map_err(|e| e.to_string())?;
+        let resp = http.post(format!("{api}/admin/config/reload")).bearer_auth(ADMIN_TOKEN).send().await.map_err(|e| e.to_string())?;
+        let none = status(http.get(&attribution)).await?;
+        expect(resp.status() == StatusCode::BAD_REQUEST && none.0 == 401, || format!("AUTH_MODE=none com API_KEYS: recarga {}, {none:?}", resp.status()))?;
+        let config = admin_json(http, &format!("{api}/admin/config")).await?;
+        let names = &config["config"]["auth"]["api_keys"];
+        expect(*names == json!(["mock-frontend"]) && !config.to_string().contains(MOCK_API_KEY), || format!("{names}"))
+    }
+    .await;
+    reload("API_KEYS=\nSTREAM_SIGNING_KEY=\nAUTH_MODE=token\n").await?;
+    result
+}
+
+// com API_KEYS, o link de convidado abre sem chave (o id é a credencial)
+// e a `stream_url` dele também; revogar continua exigindo o admin, e o
+// link revogado responde 410, não 401
+#[tokio::test]
+async fn share_links_with_api_keys() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, .. } = &stack;
+    tokio::fs::write(work.join(".env"), MOCK_API_KEY_ENV).await.map_err(|e| e.to_string())?;
+    let resp = http.post(format!("{api}/admin/config/reload")).bearer_auth(ADMIN_TOKEN).send().await;
+    expect(resp.is_ok_and(|r| r.status().is_success()), || "recarga da configuração falhou".into())?;
+
+    let resp = http
+        .post(format!("{api}/share"))
+        .bearer_auth(ADMIN_TOKEN)
+        .json(&json!({ "magnet": SAMPLE_HASH, "filename": SAMPLE_FILE }))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let id = resp.json::<Value>().await.map_err(|e| e.to_string())?["id"].as_str().unwrap_or_default().to_string();
+    expect(!id.is_empty(), || "link de convidado sem id".into())?;
+
+    let resp = http.get(format!("{api}/share/{id}")).send().await.map_err(|e| e.to_string())?;
+    let opened = resp.status();
+    let body: Value = resp.json().await.unwrap_or_default();
+    let stream_url = body["stream_url"].as_str().unwrap_or_default().to_string();
+    let streamed = http.get(format!("{api}{stream_url}")).send().await.map_err(|e| e.to_string())?.status();
+    let revoke = http.delete(format!("{api}/share/{id}")).send().await.map_err(|e| e.to_string())?.status();
+    let nested = http.get(format!("{api}/share/{id}/extra")).send().await.map_err(|e| e.to_string())?.status();
+    expect(
+        opened == StatusCode::OK && streamed == StatusCode::OK && revoke == StatusCode::UNAUTHORIZED && nested == StatusCode::UNAUTHORIZED,
+        || format!("aberto {opened} ({body}), stream {streamed}, revogar sem chave {revoke}, subcaminho {nested}"),
+    )?;
+
+    let revoked = http.delete(format!("{api}/share/{id}")).bearer_auth(ADMIN_TOKEN).send().await.map_err(|e| e.to_string())?.status();
+    let reopened = http.get(format!("{api}/share/{id}")).send().await.map_err(|e| e.to_string())?.status();
+    let unknown = http.get(format!("{api}/share/nao-existe")).send().await.map_err(|e| e.to_string())?.status();
+    expect(
+        revoked.is_success() && reopened == StatusCode::GONE && unknown != StatusCode::UNAUTHORIZED,
+        || format!("revogado {revoked}, reaberto {reopened}, desconhecido {unknown}"),
+    )
+}
+
+// a assinatura de `/stream` cobre também perfil, título e espera:
+// acrescentar `profile=` (mesmo vazio) ou `wait=` a uma URL assinada dá 403,
+// e o que o admin assinou passa
+#[tokio::test]
+async fn signed_stream_scope() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, .. } = &stack;
+    tokio::fs::write(work.join(".env"), "STREAM_SIGNING_KEY=mock-signing-key\n").await.map_err(|e| e.to_string())?;
+    let resp = http.post(format!("{api}/admin/config/reload")).bearer_auth(ADMIN_TOKEN).send().await;
+    expect(resp.is_ok_and(|r| r.status().is_success()), || "recarga da configuração falhou".into())?;
+    let sign = |body: Value| async move {
+        let resp = http.post(format!("{api}/stream/sign")).bearer_auth(ADMIN_TOKEN).json(&body).send().await.map_err(|e| e.to_string())?;
+        let url = resp.json::<Value>().await.map_err(|e| e.to_string())?["url"].as_str().unwrap_or_default().to_string();
+        Ok::<_, String>(url)
+    };
+    let status = |url: String| async move { Ok::<_, String>(http.get(format!("{api}{url}")).send().await.map_err(|e| e.to_string())?.status().as_u16()) };
+
+    let url = sign(json!({ "magnet": SAMPLE_HASH, "filename": SAMPLE_FILE })).await?;
+    let plain = status(url.clone()).await?;
+    let mut tampered = Vec::new();
+    for extra in ["profile=", "profile=outro", "imdb_id=tt0133093", "season=1", "progressive=1", "wait=progress"] {
+        tampered.push((extra, status(format!("{url}&{extra}")).await?));
+    }
+    expect(plain == 200 && tampered.iter().all(|(_, s)| *s == 403), || format!("assinada {plain}, adulteradas {tampered:?}"))?;
+
+    let url = sign(json!({ "magnet": SAMPLE_HASH, "filename": SAMPLE_FILE, "profile": "outro", "wait": "block" })).await?;
+    let scoped = status(url.clone()).await?;
+    let swapped = status(url.replace("profile=outro", "profile=terceiro")).await?;
+    expect(
+        url.contains("profile=outro") && url.contains("wait=block") && scoped == 200 && swapped == 403,
+        || format!("{url}: {scoped}, trocado {swapped}"),
+    )
+}
+
+// atribuição pelo que cada resposta usou: o detalhe do OMDb não cita o
+// TMDB, e no combinado o TMDB falso (sem detalhe de filme) não ganha campo
+#[tokio::test]
+async fn attribution_per_response() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    for (path, want) in [
+        ("/movie/tt0133093", vec!["omdb"]),
+        ("/movies/trending", vec!["tmdb", "omdb"]),
+        ("/title/tt0133093", vec!["omdb"]),
+    ] {
+        let body = get_json(http, &format!("{api}{path}")).await?;
+        let got: Vec<&str> = body["attribution"].as_array().into_iter().flatten().filter_map(|a| a["source"].as_str()).collect();
+        expect(got == want, || format!("{path}: attribution {got:?}, esperado {want:?}"))?;
+    }
+    Ok(())
+}
+
+// corpos fora do formato: 502 com o tipo da falha, e a amostra guardada em
+// /admin/upstream-failures, da mais nova para a mais velha
+#[tokio::test]
+async fn broken_upstream_bodies() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    for (name, path, kind) in [
+        ("HTML", format!("/movie/{BROKEN_HTML}"), "html"),
+        ("JSON cortado", format!("/movie/{BROKEN_TRUNCATED}"), "truncated"),
+        ("formato inesperado", format!("/search?q={BROKEN_SCHEMA}"), "schema"),
+    ] {
+        let resp = http.get(format!("{api}{path}")).send().await.map_err(|e| e.to_string())?;
+        let status = resp.status();
+        let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+        let error = &body["error"];
+        expect(status == StatusCode::BAD_GATEWAY && error["code"] == "upstream_error" && error["kind"] == kind, || {
+            format!("{name}: {status} {body}")
+        })?;
+    }
+    let body = admin_json(http, &format!("{api}/admin/upstream-failures")).await?;
+    let kinds: Vec<&str> = body["failures"]["127.0.0.1"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|f| f["kind"].as_str())
+        .collect();
+    let html_sampled = body.to_string().contains("Just a moment");
+    expect(kinds == ["schema", "truncated", "html"] && html_sampled, || format!("falhas: {body}"))
+}
+
+// cada tipo de erro com seu status e código estável, com o id do pedido
+#[tokio::test]
+async fn error_codes() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, .. } = &stack;
+    for (method, path, accept, want, code) in [
+        (Method::GET, "/search".to_string(), None, StatusCode::BAD_REQUEST, "bad_request"),
+        (Method::GET, "/nao-existe".to_string(), None, StatusCode::NOT_FOUND, "not_found"),
+        (Method::POST, "/health".to_string(), None, StatusCode::METHOD_NOT_ALLOWED, "bad_request"),
+        (Method::GET, "/media/thumbnail?filename=nada.mp4".to_string(), None, StatusCode::NOT_FOUND, "not_found"),
+        (Method::GET, format!("/media/thumbnail?filename={SAMPLE_FILE}&width=1"), None, StatusCode::BAD_REQUEST, "bad_request"),
+        (Method::GET, "/health".to_string(), Some("application/vnd.rossoflix.v9+json"), StatusCode::NOT_ACCEPTABLE, "not_acceptable"),
+    ] {
+        let mut req = http.request(method.clone(), format!("{api}{path}"));
+        if let Some(accept) = accept {
+            req = req.header(header::ACCEPT, accept);
+        }
+        let resp = req.send().await.map_err(|e| e.to_string())?;
+        let status = resp.status();
+        let request_id = resp.headers().get("x-request-id").and_then(|v| v.to_str().ok()).map(String::from);
+        let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+        let error = &body["error"];
+        expect(
+            status == want
+                && error["code"] == code
+                && error["message"].is_string()
+                && error["retryable"].is_boolean()
+                && error["request_id"].as_str() == request_id.as_deref(),
+            || format!("{method} {path}: {status} {body}"),
+        )?;
+    }
+    Ok(())
+}
+
+// playlist HLS em cache com envelope: só a versão atual é servida; as
+// outras e as ilegíveis viram miss (o sample não é MP4 fragmentado: 409)
+#[tokio::test]
+async fn cached_hls_playlist_versions() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, downloads, .. } = &stack;
+    let cached_playlist = downloads.join(SAMPLE_HASH).join(format!(".{SAMPLE_FILE}.m3u8"));
+    for (name, entry, want) in [
+        ("versão atual", json!({ "version": 1, "inserted_at": 0, "payload": "#EXTM3U\n" }).to_string(), StatusCode::OK),
+        ("texto puro, versão 0", "#EXTM3U\n".to_string(), StatusCode::CONFLICT),
+        ("versão desconhecida", json!({ "version": 99, "inserted_at": 0, "payload": ["?"] }).to_string(), StatusCode::CONFLICT),
+        ("corrompida", r#"{"version":1,"inserted_at":"#.to_string(), StatusCode::CONFLICT),
+    ] {
+        tokio::fs::write(&cached_playlist, &entry).await.map_err(|e| e.to_string())?;
+        let resp = http
+            .get(format!("{api}/hls/file/{SAMPLE_FILE}/playlist.m3u8"))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = resp.status();
+        let body = resp.text().await.map_err(|e| e.to_string())?;
+        expect(status == want && (want != StatusCode::OK || body == "#EXTM3U\n"), || format!("{name}: {status} {body}"))?;
+    }
+    Ok(())
+}
+
+// trending de SLOW_TITLES títulos sem cache, contra upstreams com latência
+// de verdade, dentro de SLOW_REBUILD_BUDGET; o título travado fica de fora
+#[tokio::test]
+async fn slow_trending_rebuild() -> Result<(), String> {
+    let _turn = turn().await;
+    let omdb = serve(Router::new().route("/", get(slow_omdb))).await;
+    let tmdb = serve(slow_tmdb()).await;
+    let upstreams = Upstreams { omdb: omdb.clone(), tmdb, torrentio: omdb.clone(), opensubtitles: omdb };
+    let work = work_dir();
+    let result = async {
+        let downloads = work.join("downloads");
+        tokio::fs::create_dir_all(&downloads).await.map_err(|e| e.to_string())?;
+        let env = format!("TRENDING_TITLE_TIMEOUT_MS={STUCK_TITLE_TIMEOUT_MS}\n");
+        tokio::fs::write(work.join(".env"), env).await.map_err(|e| e.to_string())?;
+        let http = reqwest::Client::new();
+        let (api, _server) = spawn_api(&http, &work, &downloads, &upstreams).await?;
+        let started = std::time::Instant::now();
+        let body = admin_json(&http, &format!("{api}/movies/trending?debug=1")).await?;
+        let took = started.elapsed();
+        let count = items(&body).count() as u64;
+        expect(count == SLOW_TITLES, || format!("{count} títulos em vez de {SLOW_TITLES}"))?;
+        expect(took < SLOW_REBUILD_BUDGET, || format!("reconstrução em {took:?}: {}", body["debug"]))?;
+        expect(body["debug"]["timed_out"]["resolve"] == 1, || format!("sem o título travado: {}", body["debug"]))?;
+        expect(body["debug"]["cache"] == "miss", || format!("{}", body["debug"]))
+    }
+    .await;
+    let _ = std::fs::remove_dir_all(&work);
+    result
+}
+
+// torrentio que responde 200 com um corpo enorme: o pedido é abortado logo
+// depois do teto de `MAX_UPSTREAM_BODY_BYTES` (ou já pelo Content-Length),
+// sem ler o resto
+#[tokio::test]
+async fn oversized_upstream_body() -> Result<(), String> {
+    const LIMIT: usize = 1024 * 1024;
+    const CHUNK: usize = 64 * 1024;
+    const TOTAL: usize = 512 * 1024 * 1024;
+    let _turn = turn().await;
+    let sent = Arc::new(AtomicUsize::new(0));
+    let counted = sent.clone();
+    let huge = serve(Router::new().route(
+        "/stream/movie/:file",
+        get(move |axum::extract::Path(file): axum::extract::Path<String>| {
+            let counted = counted.clone();
+            async move {
+                // gerado sob demanda: conta só o que o cliente de fato puxou
+                let chunks = futures_util::stream::iter((0..TOTAL / CHUNK).map(move |_| {
+                    counted.fetch_add(CHUNK, Ordering::SeqCst);
+                    Ok::<_, std::io::Error>(vec![b' '; CHUNK])
+                }));
+                let body = axum::body::Body::from_stream(chunks);
+                match file.as_str() {
+                    "tt9000002.json" => ([(header::CONTENT_LENGTH, TOTAL.to_string())], body).into_response(),
+                    _ => body.into_response(),
+                }
+            }
+        }),
+    ))
+    .await;
+    let mut upstreams = Upstreams::start(Arc::new(AtomicUsize::new(0))).await;
+    upstreams.torrentio = huge;
+    let work = work_dir();
+    let result = async {
+        let downloads = work.join("downloads");
+        tokio::fs::create_dir_all(&downloads).await.map_err(|e| e.to_string())?;
+        tokio::fs::write(work.join(".env"), format!("MAX_UPSTREAM_BODY_BYTES={LIMIT}\n")).await.map_err(|e| e.to_string())?;
+        let http = reqwest::Client::new();
+        let (api, _server) = spawn_api(&http, &work, &downloads, &upstreams).await?;
+        for (imdb_id, case) in [("tt9000001", "sem Content-Length"), ("tt9000002", "com Content-Length")] {
+            sent.store(0, Ordering::SeqCst);
+            let started = std::time::Instant::now();
+            let resp = http.get(format!("{api}/torrentio/movie/{imdb_id}")).send().await.map_err(|e| e.to_string())?;
+            let status = resp.status();
+            let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+            let took = started.elapsed();
+            expect(status == StatusCode::BAD_GATEWAY && body["error"]["message"].as_str().is_some_and(|m| m.contains("too large")), || {
+                format!("{case}: {status} {body}")
+            })?;
+            // o que sobra no caminho são os buffers dos sockets, não o corpo
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            let read = sent.load(Ordering::SeqCst);
+            expect(read < LIMIT + 16 * 1024 * 1024 && took < Duration::from_secs(5), || {
+                format!("{case}: {read} bytes enviados de {TOTAL} em {took:?}")
+            })?;
+        }
+        Ok(())
+    }
+    .await;
+    let _ = std::fs::remove_dir_all(&work);
+    result
+}
+
+// cada upstream recebe o User-Agent com a versão, `Accept: application/json`
+// e o id do pedido; os timeouts são por serviço: o OMDb lento estoura com
+// `OMDB_TIMEOUT_SECS=1` enquanto o torrentio, igualmente lento, cabe nos 5 s dele
+#[tokio::test]
+async fn upstream_headers_and_timeouts() -> Result<(), String> {
+    type Seen = Arc<std::sync::Mutex<Vec<(&'static str, HeaderMap)>>>;
+    let _turn = turn().await;
+    let seen: Seen = Default::default();
+    let record = |service: &'static str, router: Router| {
+        let seen = seen.clone();
+        router.layer(axum::middleware::from_fn(move |req: axum::extract::Request, next: axum::middleware::Next| {
+            seen.lock().unwrap().push((service, req.headers().clone()));
+            next.run(req)
+        }))
+    };
+    let slow = Duration::from_secs(2);
+    let omdb = serve(record(
+        "omdb",
+        Router::new().route(
+            "/",
+            get(move |query: Query<HashMap<String, String>>| async move {
+                if query.get("s").is_some_and(|s| s == "lenta") {
+                    tokio::time::sleep(slow).await;
+                }
+                support::omdb(query).await
+            }),
+        ),
+    ))
+    .await;
+    let tmdb = serve(record(
+        "tmdb",
+        Router::new()
+            .route("/configuration", get(|| async { axum::Json(json!({ "images": {} })) }))
+            .route("/trending/movie/week", get(|q| tmdb_list(q, &TRENDING)))
+            .route("/movie/now_playing", get(|q| tmdb_list(q, &NOW_PLAYING)))
+            .route("/find/:imdb_id", get(tmdb_find))
+            .route("/movie/:id/external_ids", get(tmdb_external_ids)),
+    ))
+    .await;
+    let torrentio = serve(record(
+        "torrentio",
+        Router::new().route(
+            "/stream/movie/:file",
+            get(move |file: axum::extract::Path<String>| async move {
+                if file.0 == "tt7000001.json" {
+                    tokio::time::sleep(slow).await;
+                }
+                torrentio_movie(file).await
+            }),
+        ),
+    ))
+    .await;
+    let upstreams = Upstreams { omdb: omdb.clone(), tmdb, torrentio, opensubtitles: omdb };
+    let work = work_dir();
+    let result = async {
+        let downloads = work.join("downloads");
+        tokio::fs::create_dir_all(&downloads).await.map_err(|e| e.to_string())?;
+        tokio::fs::write(work.join(".env"), "OMDB_TIMEOUT_SECS=1\nTORRENTIO_TIMEOUT_SECS=5\n").await.map_err(|e| e.to_string())?;
+        let http = reqwest::Client::new();
+        let (api, _server) = spawn_api(&http, &work, &downloads, &upstreams).await?;
+
+        for (path, services) in [
+            ("/search?q=matrix", &["omdb"][..]),
+            ("/movies/trending", &["tmdb", "omdb"]),
+            (&format!("/torrentio/movie/{}", MOVIES[0].0), &["torrentio"]),
+        ] {
+            seen.lock().unwrap().clear();
+            let resp = http.get(format!("{api}{path}")).send().await.map_err(|e| e.to_string())?;
+            let request_id = resp.headers().get("x-request-id").and_then(|v| v.to_str().ok()).unwrap_or_default().to_string();
+            expect(resp.status().is_success(), || format!("{path}: {}", resp.status()))?;
+            let seen = seen.lock().unwrap();
+            for service in services {
+                expect(seen.iter().any(|(s, _)| s == service), || format!("{path}: nenhuma chamada ao {service}"))?;
+            }
+            for (service, headers) in seen.iter() {
+                let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or_default();
+                expect(
+                    header("user-agent") == concat!("rossoflix-api/", env!("CARGO_PKG_VERSION"))
+                        && header("accept") == "application/json"
+                        && header("x-request-id") == request_id,
+                    || format!("{path} → {service}: {headers:?}, id do pedido {request_id}"),
+                )?;
+            }
+        }
+
+        let started = std::time::Instant::now();
+        let resp = http.get(format!("{api}/search?q=lenta")).send().await.map_err(|e| e.to_string())?;
+        let (status, took) = (resp.status(), started.elapsed());
+        let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+        expect(status == StatusCode::GATEWAY_TIMEOUT && body["error"]["code"] == "upstream_timeout" && took < slow, || {
+            format!("OMDb lento: {status} em {took:?} {body}")
+        })?;
+        let started = std::time::Instant::now();
+        let resp = http.get(format!("{api}/torrentio/movie/tt7000001")).send().await.map_err(|e| e.to_string())?;
+        let (status, took) = (resp.status(), started.elapsed());
+        expect(status == StatusCode::OK && took >= slow, || format!("torrentio lento: {status} em {took:?}"))
+    }
+    .await;
+    let _ = std::fs::remove_dir_all(&work);
+    result
+}
+
+// duas APIs, uma depois da outra, com `CACHE_BACKEND=redis` no mesmo Redis
+// falso: a busca gravada pela primeira sai do cache na segunda, sem outra
+// ida ao OMDb
+#[tokio::test]
+async fn shared_redis_cache() -> Result<(), String> {
+    let _turn = turn().await;
+    let searches = Arc::new(AtomicUsize::new(0));
+    let counted = searches.clone();
+    let omdb = serve(Router::new().route(
+        "/",
+        get(move |query: Query<HashMap<String, String>>| async move {
+            if query.contains_key("s") {
+                counted.fetch_add(1, Ordering::SeqCst);
+            }
+            support::omdb(query).await
+        }),
+    ))
+    .await;
+    let tmdb = serve(Router::new()).await;
+    let upstreams = Upstreams { omdb: omdb.clone(), tmdb, torrentio: omdb.clone(), opensubtitles: omdb };
+    let (redis, keys) = fake_redis().await;
+    let work = work_dir();
+    let result = async {
+        let downloads = work.join("downloads");
+        tokio::fs::create_dir_all(&downloads).await.map_err(|e| e.to_string())?;
+        let env = format!("CACHE_BACKEND=redis\nREDIS_URL=redis://:{API_KEY}@{redis}/2\nMETADATA_PRIORITY=omdb\n");
+        tokio::fs::write(work.join(".env"), env).await.map_err(|e| e.to_string())?;
+        let http = reqwest::Client::new();
+        for expected in ["MISS", "HIT"] {
+            let (api, _server) = spawn_api(&http, &work, &downloads, &upstreams).await?;
+            let resp = http.get(format!("{api}/search?q=matrix")).send().await.map_err(|e| e.to_string())?;
+            let cache = resp.headers().get("x-cache").cloned();
+            expect(resp.status().is_success() && cache.as_ref().is_some_and(|c| c == expected), || {
+                format!("{expected}: {} {cache:?}", resp.status())
+            })?;
+            let stats = admin_json(&http, &format!("{api}/admin/stats")).await?;
+            let responses = &stats["caches"]["responses"];
+            expect(responses["backend"] == "redis" && responses["backend_errors"] == 0, || format!("{responses}"))?;
+        }
+        let searches = searches.load(Ordering::SeqCst);
+        expect(searches == 1, || format!("{searches} buscas no OMDb"))?;
+        let keys = keys.lock().unwrap();
+        expect(keys.iter().any(|k| k.starts_with("2 rossoflix:responses:")), || format!("chaves: {keys:?}"))
+    }
+    .await;
+    let _ = std::fs::remove_dir_all(&work);
+    result
+}
+
+// OMDb em gzip e torrentio em brotli: os corpos chegam decodificados e o
+// `/admin/upstream` conta menos bytes no fio do que depois de descomprimir
+#[tokio::test]
+async fn compressed_upstreams() -> Result<(), String> {
+    use tower_http::compression::CompressionLayer;
+    let _turn = turn().await;
+    let encodings: Arc<std::sync::Mutex<Vec<String>>> = Default::default();
+    let seen = encodings.clone();
+    let omdb = serve(
+        Router::new()
+            .route("/", get(support::omdb))
+            .layer(CompressionLayer::new().no_br())
+            .layer(axum::middleware::from_fn(move |req: axum::extract::Request, next: axum::middleware::Next| {
+                let accept = req.headers().get(header::ACCEPT_ENCODING).and_then(|v| v.to_str().ok()).unwrap_or_default();
+                seen.lock().unwrap().push(accept.to_string());
+                next.run(req)
+            })),
+    )
+    .await;
+    let torrentio = serve(Router::new().route("/stream/movie/:file", get(torrentio_movie)).layer(CompressionLayer::new().no_gzip()))
+        .await;
+    let mut upstreams = Upstreams::start(Arc::new(AtomicUsize::new(0))).await;
+    upstreams.omdb = omdb;
+    upstreams.torrentio = torrentio;
+    let work = work_dir();
+    let result = async {
+        let downloads = work.join("downloads");
+        tokio::fs::create_dir_all(&downloads).await.map_err(|e| e.to_string())?;
+        let http = reqwest::Client::new();
+        let (api, _server) = spawn_api(&http, &work, &downloads, &upstreams).await?;
+
+        let found = get_json(&http, &format!("{api}/search?q=matrix")).await?;
+        expect(items(&found).next().is_some(), || "busca em gzip sem resultados".to_string())?;
+        let streams = get_json(&http, &format!("{api}/torrentio/movie/{}", MOVIES[0].0)).await?;
+        expect(streams["streams"].as_array().is_some_and(|s| !s.is_empty()), || format!("torrentio em brotli: {streams}"))?;
+        let accepted = encodings.lock().unwrap().clone();
+        expect(!accepted.is_empty() && accepted.iter().all(|a| a.contains("gzip") && a.contains("br")), || {
+            format!("Accept-Encoding enviado ao OMDb: {accepted:?}")
+        })?;
+
+        let status = admin_json(&http, &format!("{api}/admin/upstream")).await?;
+        let traffic = &status["bandwidth"]["127.0.0.1"];
+        let (wire, decoded) = (traffic["wire_bytes"].as_u64().unwrap_or(0), traffic["decoded_bytes"].as_u64().unwrap_or(0));
+        expect(traffic["compressed_responses"].as_u64() >= Some(2) && 0 < wire && wire < decoded, || {
+            format!("tráfego: {traffic}")
+        })
+    }
+    .await;
+    let _ = std::fs::remove_dir_all(&work);
+    result
+}
+
+/// `.aria2` (versão 1, big-endian) de um arquivo de `pieces` peças de
+/// `piece` bytes, com as peças `done` já gravadas.
+fn aria2_control(piece: u32, pieces: u64, done: &[u64]) -> Vec<u8> {
+    let mut bitfield = vec![0u8; pieces.div_ceil(8) as usize];
+    for &i in done {
+        bitfield[(i / 8) as usize] |= 0x80 >> (i % 8);
+    }
+    let mut data = vec![0, 1, 0, 0, 0, 0];
+    data.extend(20u32.to_be_bytes());
+    data.extend([0xa5; 20]);
+    data.extend(piece.to_be_bytes());
+    data.extend((piece as u64 * pieces).to_be_bytes());
+    data.extend(0u64.to_be_bytes());
+    data.extend((bitfield.len() as u32).to_be_bytes());
+    data.extend(bitfield);
+    data
+}
+
+// arquivo pré-alocado pelo aria2c (tamanho final, zeros onde falta) com o
+// `.aria2` ao lado: `409` com o progresso, ou com `progressive=1` só os
+// bytes já gravados, esperando a peça pedida; arquivo vazio nunca vira `200`
+#[tokio::test]
+async fn preallocated_and_empty_files() -> Result<(), String> {
+    const HASH: &str = "a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5";
+    const PIECE: usize = 16 * 1024;
+    let stack = Stack::start().await?;
+    let Stack { http, api, downloads, .. } = &stack;
+    let dir = downloads.join(HASH);
+    let (file, control) = (dir.join("alocado.mkv"), dir.join("alocado.mkv.aria2"));
+    let io = |e: std::io::Error| e.to_string();
+    // peça `i` gravada = bytes `i + 1`; as que faltam seguem zeradas
+    let contents = |done: &[u64]| -> Vec<u8> {
+        (0..4u64).flat_map(|i| vec![if done.contains(&i) { i as u8 + 1 } else { 0 }; PIECE]).collect()
+    };
+    let write = |done: &'static [u64]| {
+        let (file, control) = (file.clone(), control.clone());
+        async move {
+            tokio::fs::write(&file, contents(done)).await?;
+            tokio::fs::write(&control, aria2_control(PIECE as u32, 4, done)).await
+        }
+    };
+    tokio::fs::create_dir_all(&dir).await.map_err(io)?;
+    write(&[0]).await.map_err(io)?;
+    let stream = |name: &str, progressive: bool, range: Option<&str>| {
+        let mut req = http.get(format!("{api}/stream?magnet={HASH}&filename={name}&progressive={}", progressive as u8));
+        if let Some(range) = range {
+            req = req.header(header::RANGE, range);
+        }
+        req.send()
+    };
+
+    // sem `progressive`: nada dos zeros, só o progresso
+    let resp = stream("alocado.mkv", false, None).await.map_err(|e| e.to_string())?;
+    let (status, retry) = (resp.status(), resp.headers().get(header::RETRY_AFTER).cloned());
+    let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+    let error = &body["error"];
+    expect(
+        status == StatusCode::CONFLICT
+            && retry.is_some_and(|r| r == "5")
+            && error["code"] == "download_in_progress"
+            && error["retryable"] == true
+            && error["bytes_done"] == PIECE
+            && error["total_bytes"] == 4 * PIECE
+            && error["percent"] == 25.0,
+        || format!("alocado: {status} {body}"),
+    )?;
+
+    // `progressive=1`: só a peça contígua já gravada
+    let resp = stream("alocado.mkv", true, Some("bytes=0-")).await.map_err(|e| e.to_string())?;
+    let (status, range) = (resp.status(), resp.headers().get(header::CONTENT_RANGE).cloned());
+    let bytes = resp.bytes().await.map_err(|e| e.to_string())?;
+    expect(
+        status == StatusCode::PARTIAL_CONTENT
+            && range.is_some_and(|r| r == format!("bytes 0-{}/{}", PIECE - 1, 4 * PIECE).as_str())
+            && bytes.len() == PIECE
+            && bytes.iter().all(|b| *b == 1),
+        || format!("progressivo do começo: {status}, {} bytes", bytes.len()),
+    )?;
+
+    // peça 2 ainda não gravada: espera o aria2c chegar nela
+    let pending = tokio::spawn(stream("alocado.mkv", true, Some(&format!("bytes={}-", 2 * PIECE))));
+    tokio::time::sleep(Duration::from_millis(800)).await;
+    expect(!pending.is_finished(), || "respondeu antes da peça 2 existir".to_string())?;
+    write(&[0, 1, 2]).await.map_err(io)?;
+    let resp = pending.await.map_err(|e| e.to_string())?.map_err(|e| e.to_string())?;
+    let (status, range) = (resp.status(), resp.headers().get(header::CONTENT_RANGE).cloned());
+    let bytes = resp.bytes().await.map_err(|e| e.to_string())?;
+    expect(
+        status == StatusCode::PARTIAL_CONTENT
+            && range.is_some_and(|r| r == format!("bytes {}-{}/{}", 2 * PIECE, 3 * PIECE - 1, 4 * PIECE).as_str())
+            && bytes.iter().all(|b| *b == 3),
+        || format!("progressivo depois da espera: {status}, {} bytes", bytes.len()),
+    )?;
+
+    // terminou: sem o `.aria2`, o arquivo inteiro
+    tokio::fs::write(&file, contents(&[0, 1, 2, 3])).await.map_err(io)?;
+    tokio::fs::remove_file(&control).await.map_err(io)?;
+    let resp = stream("alocado.mkv", false, None).await.map_err(|e| e.to_string())?;
+    let status = resp.status();
+    let bytes = resp.bytes().await.map_err(|e| e.to_string())?;
+    expect(status == StatusCode::OK && bytes[..] == contents(&[0, 1, 2, 3])[..], || format!("completo: {status}"))?;
+
+    // arquivo vazio, com o `.aria2` ainda ilegível (recém-criado) e sem ele
+    tokio::fs::write(dir.join("vazio.mkv"), b"").await.map_err(io)?;
+    tokio::fs::write(dir.join("vazio.mkv.aria2"), b"\0\x01").await.map_err(io)?;
+    let resp = stream("vazio.mkv", false, None).await.map_err(|e| e.to_string())?;
+    let status = resp.status();
+    let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+    expect(
+        status == StatusCode::CONFLICT && body["error"]["code"] == "download_in_progress" && body["error"]["bytes_done"].is_null(),
+        || format!("vazio com controle: {status} {body}"),
+    )?;
+    tokio::fs::remove_file(dir.join("vazio.mkv.aria2")).await.map_err(io)?;
+    for range in [None, Some("bytes=0-")] {
+        let resp = stream("vazio.mkv", false, range).await.map_err(|e| e.to_string())?;
+        let status = resp.status();
+        let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+        expect(status == StatusCode::CONFLICT && body["error"]["code"] == "conflict", || {
+            format!("vazio ({range:?}): {status} {body}")
+        })?;
+    }
+    Ok(())
+}
+
+// scrape UDP (BEP 15) num tracker falso: contagens de quem responde,
+// `timeout` de quem fica calado; os trackers do magnet não chegam a
+// endereços locais e a lista é cortada em 20
+#[tokio::test]
+async fn torrent_health_over_udp() -> Result<(), String> {
+    const HASH: &str = "0123456789abcdef0123456789abcdef01234567";
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, .. } = &stack;
+    let mut hash = [0u8; 20];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&HASH[2 * i..2 * i + 2], 16).map_err(|e| e.to_string())?;
+    }
+    let (tracker, _) = fake_udp_tracker(hash, (12, 340, 5)).await;
+    let (local, local_packets) = fake_udp_tracker(hash, (999, 999, 999)).await;
+    let silent = tokio::net::UdpSocket::bind("127.0.0.1:0").await.map_err(|e| e.to_string())?;
+    let silent_addr = silent.local_addr().map_err(|e| e.to_string())?;
+
+    let env = format!("BT_TRACKERS=udp://{tracker}/announce,udp://{silent_addr}\nBT_TRACKERS_FALLBACK=\n");
+    tokio::fs::write(work.join(".env"), env).await.map_err(|e| e.to_string())?;
+    let resp = http.post(format!("{api}/admin/config/reload")).bearer_auth(ADMIN_TOKEN).send().await;
+    expect(resp.is_ok_and(|r| r.status().is_success()), || "recarga da configuração falhou".into())?;
+
+    let mut magnet = format!("magnet:?xt=urn:btih:{HASH}&tr=udp://{local}/announce");
+    for i in 0..30 {
+        magnet.push_str(&format!("&tr=http://tracker{i}.invalid/announce"));
+    }
+    let resp = http.get(format!("{api}/torrent/health")).query(&[("magnet", &magnet)]).send().await.map_err(|e| e.to_string())?;
+    let status = resp.status();
+    let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+    let reports = body["trackers"].as_array().cloned().unwrap_or_default();
+    expect(status.is_success() && reports.len() == 20, || format!("{status}: {} trackers", reports.len()))?;
+    let report = |i: usize| reports[i].clone();
+    expect(
+        report(0)["tracker"] == format!("udp://{tracker}/announce")
+            && report(0)["status"] == "ok"
+            && (&report(0)["seeders"], &report(0)["completed"], &report(0)["leechers"]) == (&json!(12), &json!(340), &json!(5)),
+        || format!("tracker que responde: {}", report(0)),
+    )?;
+    expect(report(1)["status"] == "timeout", || format!("tracker calado: {}", report(1)))?;
+    expect(
+        report(2)["status"] == "error" && report(2)["message"] == "host sem endereço público",
+        || format!("tracker local do magnet: {}", report(2)),
+    )?;
+    expect(local_packets.load(Ordering::SeqCst) == 0, || "o tracker local do magnet recebeu pacotes".into())?;
+    expect(reports[3..].iter().all(|r| r["status"] == "unsupported"), || format!("{reports:?}"))?;
+    expect(body["verdict"] == "healthy" && body["seeders"] == 12 && body["leechers"] == 5, || format!("{body}"))
+}
+
+// DOWNLOAD_ENGINE=embedded: sem aria2c no PATH, um magnet sai de um seeder
+// falso achado por um tracker HTTP falso, direto para o /stream; o health
+// profundo não cobra o aria2c
+#[tokio::test]
+async fn embedded_engine_without_aria2c() -> Result<(), String> {
+    const FILE: &str = "embutido.mkv";
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, downloads, .. } = &stack;
+    let data: Vec<u8> = (0..5 * SEEDER_PIECE_LEN as u32 + 123).map(|i| (i % 251) as u8).collect();
+    let (hash, seeder) = fake_seeder(FILE, data.clone()).await;
+    let tracker = fake_announce_tracker(seeder).await;
+
+    tokio::fs::remove_file(work.join("bin/aria2c")).await.map_err(|e| e.to_string())?;
+    let env = format!("DOWNLOAD_ENGINE=embedded\nBT_TRACKERS={tracker}\nBT_TRACKERS_FALLBACK=\n");
+    tokio::fs::write(work.join(".env"), env).await.map_err(|e| e.to_string())?;
+    let resp = http.post(format!("{api}/admin/config/reload")).bearer_auth(ADMIN_TOKEN).send().await;
+    expect(resp.is_ok_and(|r| r.status().is_success()), || "recarga da configuração falhou".into())?;
+
+    let resp = http.get(format!("{api}/health/deep")).bearer_auth(ADMIN_TOKEN).send().await.map_err(|e| e.to_string())?;
+    let status = resp.status();
+    let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+    expect(
+        status == StatusCode::OK && body["checks"]["aria2c"] == false && body["checks"]["download_engine"] == "embedded",
+        || format!("health profundo: {status} {body}"),
+    )?;
+
+    let resp = http
+        .get(format!("{api}/stream?magnet={hash}&filename={FILE}"))
+        .timeout(Duration::from_secs(60))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = resp.status();
+    let body = resp.bytes().await.map_err(|e| e.to_string())?;
+    expect(status == StatusCode::OK && body[..] == data[..], || {
+        format!("{status}: {} bytes, {:?}", body.len(), String::from_utf8_lossy(&body[..body.len().min(300)]))
+    })?;
+
+    let job = downloads.join(&hash);
+    let names: Vec<String> = std::fs::read_dir(&job)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .collect();
+    expect(names == [FILE], || format!("sobras na pasta do job: {names:?}"))?;
+    let log = tokio::fs::read_to_string(downloads.join(format!("{hash}.log"))).await.map_err(|e| e.to_string())?;
+    expect(log.contains("=== motor embutido") && log.ends_with("--- exit code 0 ---\n"), || log.clone())?;
+    expect(!work.join(FAKE_ARIA2C_LOG).exists(), || "o aria2c rodou".into())
+}
+
+// `/stream?url=`: um host permitido redirecionando para fora da lista
+// (`localhost` não está em `STREAM_PROXY_HOSTS=127.0.0.1`) não tem o corpo
+// repassado, nem o pedido chega lá; redirecionamento dentro da lista segue
+#[tokio::test]
+async fn stream_proxy_redirects() -> Result<(), String> {
+    let stack = Stack::start().await?;
+    let Stack { http, api, work, sample, .. } = &stack;
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counted = hits.clone();
+    let internal = serve(Router::new().route(
+        "/secret",
+        get(move || async move {
+            counted.fetch_add(1, Ordering::SeqCst);
+            "segredo interno"
+        }),
+    ))
+    .await;
+    let internal_port = internal.rsplit(':').next().unwrap_or_default().to_string();
+    let file = sample.clone();
+    let upstream = serve(
+        Router::new()
+            .route("/video", get(move || async move { file }))
+            .route(
+                "/out",
+                get(move || async move { axum::response::Redirect::temporary(&format!("http://localhost:{internal_port}/secret")) }),
+            ),
+    )
+    .await;
+    let inside = format!("{upstream}/video");
+    let upstream_router = Router::new().route("/in", get(move || async move { axum::response::Redirect::temporary(&inside) }));
+    let hop = serve(upstream_router).await;
+
+    tokio::fs::write(work.join(".env"), "STREAM_PROXY_HOSTS=127.0.0.1\n").await.map_err(|e| e.to_string())?;
+    let resp = http.post(format!("{api}/admin/config/reload")).bearer_auth(ADMIN_TOKEN).send().await;
+    expect(resp.is_ok_and(|r| r.status().is_success()), || "recarga da configuração falhou".into())?;
+
+    let resp = http.get(format!("{api}/stream")).query(&[("url", format!("{upstream}/out"))]).send().await.map_err(|e| e.to_string())?;
+    let status = resp.status();
+    let body = resp.text().await.map_err(|e| e.to_string())?;
+    expect(
+        status == StatusCode::BAD_GATEWAY && body.contains("fora de STREAM_PROXY_HOSTS") && !body.contains("segredo"),
+        || format!("redirecionamento para fora: {status} {body}"),
+    )?;
+    expect(hits.load(Ordering::SeqCst) == 0, || "o pedido chegou ao host fora da lista".into())?;
+
+    let resp = http.get(format!("{api}/stream")).query(&[("url", format!("{hop}/in"))]).send().await.map_err(|e| e.to_string())?;
+    let status = resp.status();
+    let bytes = resp.bytes().await.map_err(|e| e.to_string())?;
+    expect(status == StatusCode::OK && bytes[..] == sample[..], || format!("redirecionamento dentro da lista: {status}"))
+}